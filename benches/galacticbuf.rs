@@ -0,0 +1,179 @@
+//! Serialize and deserialize throughput of galacticbuf, next to serde_json
+//! and bincode writing the same values
+//!
+//! Run with `cargo bench --features benchmarking`, and `simd` for the SIMD
+//! accelerated UTF-8 validation of strings. Each workload measures the
+//! untyped message alone (`galacticbuf`), the serde data model on top of it
+//! (`galacticbuf-serde`), and the two other formats. Throughput is in bytes of
+//! each format's own encoding.
+
+use std::hint::black_box;
+
+use criterion::{
+    BenchmarkGroup, Criterion, Throughput, criterion_group, criterion_main, measurement::WallTime,
+};
+use galactic_exchange::bench::{self, Message};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+#[derive(Serialize, Deserialize)]
+enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Order {
+    id: u64,
+    instrument: String,
+    side: Side,
+    price: f64,
+    quantity: u64,
+    timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Level {
+    price: f64,
+    quantity: u64,
+    orders: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    instrument: String,
+    sequence: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Node {
+    depth: u32,
+    child: Option<Box<Node>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Notes {
+    author: String,
+    memo: String,
+    tags: Vec<String>,
+}
+
+fn order() -> Order {
+    Order {
+        id: 48_213,
+        instrument: String::from("XGAL-USD"),
+        side: Side::Buy,
+        price: 101.25,
+        quantity: 300,
+        timestamp: 1_718_000_000_000,
+    }
+}
+
+/// Order book of 1,000 price levels a side
+fn snapshot() -> Snapshot {
+    let levels = |start: f64, step: f64| {
+        (0..1_000)
+            .map(|i| Level {
+                price: start + step * i as f64,
+                quantity: 100 + i * 7 % 900,
+                orders: 1 + i as u32 % 12,
+            })
+            .collect()
+    };
+    Snapshot {
+        instrument: String::from("XGAL-USD"),
+        sequence: 9_031_337,
+        bids: levels(101.0, -0.01),
+        asks: levels(101.01, 0.01),
+    }
+}
+
+/// Objects 48 deep, within the default depth limit
+fn nested() -> Node {
+    (0..48).fold(
+        Node {
+            depth: 48,
+            child: None,
+        },
+        |child, depth| Node {
+            depth,
+            child: Some(Box::new(child)),
+        },
+    )
+}
+
+/// A 4 KB memo and 200 tags
+fn notes() -> Notes {
+    Notes {
+        author: String::from("desk-7@galactic.exchange"),
+        memo: "Crossed at the open, resting remainder pegged to the mid. ".repeat(70),
+        tags: (0..200)
+            .map(|i| format!("strategy/momentum/{:03}", i))
+            .collect(),
+    }
+}
+
+fn formats<T: Serialize + DeserializeOwned>(c: &mut Criterion, name: &str, value: T) {
+    let mut group = c.benchmark_group(name);
+
+    let message = Message::from_value(&value);
+    let bytes = message.serialize();
+    measure(
+        &mut group,
+        "galacticbuf",
+        &bytes,
+        || message.serialize(),
+        Message::deserialize,
+    );
+    measure(
+        &mut group,
+        "galacticbuf-serde",
+        &bench::to_vec(&value),
+        || bench::to_vec(&value),
+        |bytes| bench::from_slice::<T>(bytes),
+    );
+    measure(
+        &mut group,
+        "serde_json",
+        &serde_json::to_vec(&value).unwrap(),
+        || serde_json::to_vec(&value).unwrap(),
+        |bytes| serde_json::from_slice::<T>(bytes).unwrap(),
+    );
+    measure(
+        &mut group,
+        "bincode",
+        &bincode::serialize(&value).unwrap(),
+        || bincode::serialize(&value).unwrap(),
+        |bytes| bincode::deserialize::<T>(bytes).unwrap(),
+    );
+
+    group.finish();
+}
+
+/// `{format}/serialize` and `{format}/deserialize` of one encoding, `bytes`
+fn measure<V>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    format: &str,
+    bytes: &[u8],
+    mut serialize: impl FnMut() -> Vec<u8>,
+    mut deserialize: impl FnMut(&[u8]) -> V,
+) {
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function(format!("{}/serialize", format), |b| {
+        b.iter(|| black_box(serialize()))
+    });
+    group.bench_function(format!("{}/deserialize", format), |b| {
+        b.iter(|| black_box(deserialize(black_box(bytes))))
+    });
+}
+
+fn benchmarks(c: &mut Criterion) {
+    formats(c, "small_order", order());
+    formats(c, "large_snapshot", snapshot());
+    formats(c, "deep_nesting", nested());
+    formats(c, "string_heavy", notes());
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
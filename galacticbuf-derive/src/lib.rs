@@ -0,0 +1,194 @@
+//! Derives mapping Rust structs to galacticbuf objects
+//!
+//! `#[derive(GalacticSerialize, GalacticDeserialize)]` on a struct with named
+//! fields writes every field as an object field of the same name, converted with
+//! `From<T> for FieldValue` and read back with `TryFrom<FieldValue>`. Fields
+//! take the attributes:
+//!
+//! - `#[galactic(rename = "name")]` uses another name on the wire
+//! - `#[galactic(skip)]` leaves the field out, it reads as `Default::default()`
+//! - `#[galactic(default)]` reads a missing field as `Default::default()`
+//!
+//! The generated code refers to `crate::galacticbuf`, so the derives are for
+//! the exchange crate itself.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, Ident, LitStr, Type, parse_macro_input, spanned::Spanned};
+
+/// Struct field with its attributes
+struct Field {
+    ident: Ident,
+    ty: Type,
+    /// Field name on the wire
+    name: String,
+    skip: bool,
+    default: bool,
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(input.span(), "expected a struct"));
+    };
+    let Fields::Named(named) = &data.fields else {
+        return Err(syn::Error::new(
+            data.fields.span(),
+            "expected a struct with named fields",
+        ));
+    };
+    named
+        .named
+        .iter()
+        .map(|field| {
+            let ident = field.ident.clone().expect("named fields have names");
+            let mut parsed = Field {
+                name: ident.to_string(),
+                ident,
+                ty: field.ty.clone(),
+                skip: false,
+                default: false,
+            };
+            for attr in field.attrs.iter().filter(|a| a.path().is_ident("galactic")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        parsed.name = meta.value()?.parse::<LitStr>()?.value();
+                    } else if meta.path.is_ident("skip") {
+                        parsed.skip = true;
+                    } else if meta.path.is_ident("default") {
+                        parsed.default = true;
+                    } else {
+                        return Err(meta.error("expected `rename`, `skip` or `default`"));
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(parsed)
+        })
+        .collect()
+}
+
+/// Implements `GalacticSerialize` and `From<Self> for FieldValue`
+#[proc_macro_derive(GalacticSerialize, attributes(galactic))]
+pub fn derive_serialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_serialize(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `GalacticDeserialize` and `TryFrom<FieldValue> for Self`
+#[proc_macro_derive(GalacticDeserialize, attributes(galactic))]
+pub fn derive_deserialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_deserialize(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_serialize(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let inserts = fields(input)?.into_iter().filter(|field| !field.skip).map(
+        |Field { ident, name, .. }| {
+            quote! {
+                fields.insert(
+                    crate::galacticbuf::FieldName::from(#name),
+                    crate::galacticbuf::FieldValue::from(::std::clone::Clone::clone(&self.#ident)),
+                );
+            }
+        },
+    );
+    Ok(quote! {
+        impl #impl_generics crate::galacticbuf::GalacticSerialize for #ident #ty_generics
+            #where_clause
+        {
+            fn to_object(&self) -> crate::galacticbuf::Object {
+                let mut fields = crate::galacticbuf::Fields::new();
+                #(#inserts)*
+                crate::galacticbuf::Object(fields)
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#ident #ty_generics>
+            for crate::galacticbuf::FieldValue #where_clause
+        {
+            fn from(value: #ident #ty_generics) -> Self {
+                crate::galacticbuf::FieldValue::Object(
+                    crate::galacticbuf::GalacticSerialize::to_object(&value),
+                )
+            }
+        }
+    })
+}
+
+fn expand_deserialize(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let inits = fields(input)?.into_iter().map(|field| {
+        let Field {
+            ident,
+            ty,
+            name,
+            skip,
+            default,
+        } = field;
+        let type_name = quote!(#ty).to_string().replace(' ', "");
+        let convert = quote! {
+            <#ty as ::std::convert::TryFrom<crate::galacticbuf::FieldValue>>::try_from(
+                ::std::clone::Clone::clone(value),
+            )
+            .map_err(|value| crate::galacticbuf::FieldError::wrong_type(#name, #type_name, &value))?
+        };
+        if skip {
+            quote!(#ident: ::std::default::Default::default())
+        } else if default {
+            quote! {
+                #ident: match crate::galacticbuf::FieldAccess::fields(object)
+                    .get(&crate::galacticbuf::FieldName::from(#name))
+                {
+                    ::std::option::Option::Some(value) => #convert,
+                    ::std::option::Option::None => ::std::default::Default::default(),
+                }
+            }
+        } else {
+            quote! {
+                #ident: {
+                    let value = crate::galacticbuf::FieldAccess::field(object, #name)?;
+                    #convert
+                }
+            }
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics crate::galacticbuf::GalacticDeserialize for #ident #ty_generics
+            #where_clause
+        {
+            fn from_object(
+                object: &crate::galacticbuf::Object,
+            ) -> ::std::result::Result<Self, crate::galacticbuf::FieldError> {
+                ::std::result::Result::Ok(#ident {
+                    #(#inits),*
+                })
+            }
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<crate::galacticbuf::FieldValue>
+            for #ident #ty_generics #where_clause
+        {
+            type Error = crate::galacticbuf::FieldValue;
+
+            fn try_from(
+                value: crate::galacticbuf::FieldValue,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                let crate::galacticbuf::FieldValue::Object(object) = &value else {
+                    return ::std::result::Result::Err(value);
+                };
+                match crate::galacticbuf::GalacticDeserialize::from_object(object) {
+                    ::std::result::Result::Ok(parsed) => ::std::result::Result::Ok(parsed),
+                    ::std::result::Result::Err(_) => ::std::result::Result::Err(value),
+                }
+            }
+        }
+    })
+}
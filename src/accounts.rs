@@ -0,0 +1,307 @@
+//! Accounts, the identities orders are placed for, and their API keys
+//!
+//! An account is registered with a first API key, and may be issued more,
+//! e.g. one per trading bot, and revoke them. A key is shown once, when it is
+//! issued, as `gx_{key_id}_{secret}`. Only the SHA-256 digest of the secret
+//! is kept, which is enough for 256 random bits, and the key ID to find it.
+//! Revoked keys are kept too, so listing them tells when they were revoked.
+//!
+//! Accounts are written to a file in the data directory as they change.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    fs, io,
+    path::PathBuf,
+    sync::RwLock,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// ID of an account, counting from 1
+pub(crate) type AccountId = u64;
+
+/// Prefix of every API key, so they are told apart from other secrets
+const KEY_PREFIX: &str = "gx_";
+
+/// Longest name of an account or label of a key
+const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Account {
+    pub(crate) id: AccountId,
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub(crate) created_at: u64,
+    pub(crate) keys: Vec<ApiKey>,
+}
+
+/// API key of an account, without its secret
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ApiKey {
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) label: Option<String>,
+    /// SHA-256 of the secret, in hex
+    digest: String,
+    /// Milliseconds since the Unix epoch
+    pub(crate) created_at: u64,
+    #[serde(default)]
+    pub(crate) revoked_at: Option<u64>,
+}
+
+/// Key as it is issued, the only time its secret is known
+#[derive(Debug)]
+pub(crate) struct IssuedKey {
+    pub(crate) key: ApiKey,
+    /// Whole key, as clients send it
+    pub(crate) secret: String,
+}
+
+/// Name or label which can't be taken, with the field at fault
+#[derive(Debug, PartialEq)]
+pub(crate) struct AccountError {
+    pub(crate) field: &'static str,
+    pub(crate) reason: String,
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+/// Checks a name or label is 1 to 64 characters without control characters
+pub(crate) fn validate_name(field: &'static str, name: &str) -> Result<(), AccountError> {
+    if name.is_empty()
+        || name.chars().count() > MAX_NAME_LENGTH
+        || name.chars().any(char::is_control)
+    {
+        let reason = format!(
+            "must be 1 to {} characters without control characters",
+            MAX_NAME_LENGTH
+        );
+        return Err(AccountError { field, reason });
+    }
+    Ok(())
+}
+
+/// Every account, in memory and on disk
+#[derive(Debug)]
+pub(crate) struct AccountStore {
+    path: PathBuf,
+    /// Held while the file is written, so the file sees changes in the order
+    /// memory does
+    state: RwLock<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    accounts: BTreeMap<AccountId, Account>,
+    /// Account of each key ID
+    key_accounts: HashMap<String, AccountId>,
+}
+
+impl AccountStore {
+    /// Accounts of the file, none when there is no file yet
+    pub(crate) fn open(path: impl Into<PathBuf>) -> io::Result<AccountStore> {
+        let path = path.into();
+        let accounts: Vec<Account> = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                let message = format!("{}: {}", path.display(), e);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut state = State::default();
+        for account in accounts {
+            for key in &account.keys {
+                state.key_accounts.insert(key.id.clone(), account.id);
+            }
+            state.accounts.insert(account.id, account);
+        }
+        Ok(AccountStore {
+            path,
+            state: RwLock::new(state),
+        })
+    }
+
+    /// Registers an account, returns it with its first key
+    pub(crate) fn create(&self, name: Option<String>) -> io::Result<(Account, IssuedKey)> {
+        let mut state = self.state.write().unwrap();
+        let id = state.accounts.keys().next_back().map_or(1, |last| last + 1);
+        let issued = issue();
+        let account = Account {
+            id,
+            name,
+            created_at: issued.key.created_at,
+            keys: vec![issued.key.clone()],
+        };
+        let mut accounts = state.accounts.clone();
+        accounts.insert(id, account.clone());
+        self.write(&accounts)?;
+        state.accounts = accounts;
+        state.key_accounts.insert(issued.key.id.clone(), id);
+        Ok((account, issued))
+    }
+
+    pub(crate) fn account(&self, id: AccountId) -> Option<Account> {
+        self.state.read().unwrap().accounts.get(&id).cloned()
+    }
+
+    /// Issues the account another key, `None` for an unknown account
+    pub(crate) fn issue_key(
+        &self,
+        id: AccountId,
+        label: Option<String>,
+    ) -> io::Result<Option<IssuedKey>> {
+        let mut issued = issue();
+        issued.key.label = label;
+        let key = issued.key.clone();
+        let updated = self.update(id, |account| account.keys.push(key))?;
+        Ok(updated.map(|_| issued))
+    }
+
+    /// Revokes a key of the account, returns it as revoked, `None` when the
+    /// account has no such key, a key revoked before is returned as it was
+    pub(crate) fn revoke_key(&self, id: AccountId, key_id: &str) -> io::Result<Option<ApiKey>> {
+        let account = self.update(id, |account| {
+            if let Some(key) = account.keys.iter_mut().find(|key| key.id == key_id) {
+                key.revoked_at
+                    .get_or_insert_with(|| millis(SystemTime::now()));
+            }
+        })?;
+        Ok(account.and_then(|account| account.keys.into_iter().find(|key| key.id == key_id)))
+    }
+
+    /// Account of an API key, `None` unless it is one of an account and not
+    /// revoked
+    pub(crate) fn authenticate(&self, secret: &str) -> Option<AccountId> {
+        let (key_id, secret) = secret.strip_prefix(KEY_PREFIX)?.split_once('_')?;
+        let state = self.state.read().unwrap();
+        let account = state.key_accounts.get(key_id)?;
+        let key = state.accounts[account]
+            .keys
+            .iter()
+            .find(|key| key.id == key_id)?;
+        (key.revoked_at.is_none() && key.digest == digest(secret)).then_some(*account)
+    }
+
+    /// Changes the account and writes it, returns it as changed, `None` for
+    /// an unknown account
+    fn update(
+        &self,
+        id: AccountId,
+        change: impl FnOnce(&mut Account),
+    ) -> io::Result<Option<Account>> {
+        let mut state = self.state.write().unwrap();
+        let Some(account) = state.accounts.get(&id) else {
+            return Ok(None);
+        };
+        let mut account = account.clone();
+        change(&mut account);
+        let mut accounts = state.accounts.clone();
+        accounts.insert(id, account.clone());
+        self.write(&accounts)?;
+        state.accounts = accounts;
+        for key in &account.keys {
+            state.key_accounts.insert(key.id.clone(), id);
+        }
+        Ok(Some(account))
+    }
+
+    fn write(&self, accounts: &BTreeMap<AccountId, Account>) -> io::Result<()> {
+        let accounts: Vec<_> = accounts.values().collect();
+        let partial = self.path.with_extension("partial");
+        fs::write(&partial, serde_json::to_vec_pretty(&accounts)?)?;
+        fs::rename(&partial, &self.path)
+    }
+}
+
+/// New key with a random ID and secret
+fn issue() -> IssuedKey {
+    let mut random = [0; 40];
+    getrandom::fill(&mut random).expect("the OS has random bytes");
+    let (id, secret) = (hex(&random[..8]), hex(&random[8..]));
+    IssuedKey {
+        secret: format!("{}{}_{}", KEY_PREFIX, id, secret),
+        key: ApiKey {
+            id,
+            label: None,
+            digest: digest(&secret),
+            created_at: millis(SystemTime::now()),
+            revoked_at: None,
+        },
+    }
+}
+
+fn digest(secret: &str) -> String {
+    hex(&Sha256::digest(secret))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let path = std::env::temp_dir().join(format!("accounts-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = AccountStore::open(&path).unwrap();
+        let (account, first) = store.create(Some(String::from("Orbital"))).unwrap();
+        assert_eq!(account.id, 1);
+        assert!(first.secret.starts_with("gx_"));
+        assert_eq!(store.authenticate(&first.secret), Some(1));
+        assert_eq!(store.authenticate("gx_nokey_secret"), None);
+        let forged = format!("gx_{}_{}", first.key.id, "0".repeat(64));
+        assert_eq!(store.authenticate(&forged), None);
+
+        let (other, _) = store.create(None).unwrap();
+        assert_eq!(other.id, 2);
+        let second = store
+            .issue_key(1, Some(String::from("bot")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(store.authenticate(&second.secret), Some(1));
+        assert!(store.issue_key(3, None).unwrap().is_none());
+
+        let revoked = store.revoke_key(1, &first.key.id).unwrap().unwrap();
+        assert!(revoked.revoked_at.is_some());
+        assert_eq!(store.authenticate(&first.secret), None);
+        assert_eq!(store.revoke_key(2, &second.key.id).unwrap(), None);
+
+        // Read back from the file, secrets aren't in it
+        let store = AccountStore::open(&path).unwrap();
+        assert_eq!(store.authenticate(&second.secret), Some(1));
+        assert_eq!(store.authenticate(&first.secret), None);
+        assert_eq!(store.account(1).unwrap().keys.len(), 2);
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(!file.contains(&second.secret[20..]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(validate_name("name", "Orbital Traders"), Ok(()));
+        assert_eq!(validate_name("label", "").unwrap_err().field, "label");
+        assert!(validate_name("name", "tab\there").is_err());
+        assert!(validate_name("name", &"x".repeat(65)).is_err());
+    }
+}
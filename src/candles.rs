@@ -0,0 +1,248 @@
+//! Candles of each market, the open, high, low and close prices and the volume
+//! of its trades in each minute, five minutes, hour and day
+//!
+//! The [`CandleStore`] listens to the engine, see
+//! [`TradeListener`](crate::engine::TradeListener), and folds each trade into
+//! the candle of each interval it falls in as it happens. Candles start on
+//! whole multiples of their interval since the Unix epoch, days at midnight
+//! UTC. An interval without trades has no candle. The last 10,000 candles of
+//! each interval are kept, the trade history fills them in again at start.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    decimal::Decimal,
+    engine::{MAX_DECIMAL_PLACES, Trade, TradeListener},
+};
+
+/// Candles of each interval of a market kept
+const MAX_CANDLES: usize = 10_000;
+
+/// Span of a candle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Interval {
+    Minute,
+    FiveMinutes,
+    Hour,
+    Day,
+}
+
+impl Interval {
+    const ALL: [Interval; 4] = [
+        Interval::Minute,
+        Interval::FiveMinutes,
+        Interval::Hour,
+        Interval::Day,
+    ];
+
+    /// Interval of a name the API takes, e.g. `5m`
+    pub(crate) fn parse(name: &str) -> Option<Interval> {
+        Interval::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == name)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Interval::Minute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::Hour => "1h",
+            Interval::Day => "1d",
+        }
+    }
+
+    fn duration(self) -> Duration {
+        Duration::from_secs(match self {
+            Interval::Minute => 60,
+            Interval::FiveMinutes => 5 * 60,
+            Interval::Hour => 60 * 60,
+            Interval::Day => 24 * 60 * 60,
+        })
+    }
+
+    /// Start of the candle of the instant, in seconds since the Unix epoch
+    fn start(self, time: SystemTime) -> u64 {
+        let seconds = seconds(time);
+        seconds - seconds % self.duration().as_secs()
+    }
+}
+
+/// Candles of every market, see the module docs
+#[derive(Debug, Default)]
+pub(crate) struct CandleStore {
+    /// Candles of each interval, in the order of [`Interval::ALL`], by start
+    markets: RwLock<HashMap<String, [BTreeMap<u64, Candle>; 4]>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Candle {
+    pub(crate) start: SystemTime,
+    pub(crate) open: Decimal,
+    pub(crate) high: Decimal,
+    pub(crate) low: Decimal,
+    pub(crate) close: Decimal,
+    /// Quantity traded, in units of 10^-8, see [`Candle::volume`]
+    units: i128,
+}
+
+impl Candle {
+    /// Quantity traded
+    pub(crate) fn volume(&self) -> Decimal {
+        Decimal::from_units(self.units, MAX_DECIMAL_PLACES)
+    }
+}
+
+impl CandleStore {
+    /// Candles of the market starting at or after `start` and before `end`,
+    /// oldest first, the last `limit` of them when there are more
+    pub(crate) fn candles(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        start: SystemTime,
+        end: SystemTime,
+        limit: usize,
+    ) -> Vec<Candle> {
+        let markets = self.markets.read().unwrap();
+        let Some(candles) = markets.get(symbol) else {
+            return vec![];
+        };
+        let (start, end) = (
+            seconds(start + Duration::from_nanos(999_999_999)),
+            seconds(end),
+        );
+        if start >= end {
+            return vec![];
+        }
+        let mut page: Vec<_> = candles[interval as usize]
+            .range(start..end)
+            .rev()
+            .take(limit)
+            .map(|(_, candle)| candle.clone())
+            .collect();
+        page.reverse();
+        page
+    }
+}
+
+impl TradeListener for CandleStore {
+    fn trade(&self, trade: &Trade) {
+        let units = trade.quantity.to_units(MAX_DECIMAL_PLACES).unwrap_or(0);
+        let mut markets = self.markets.write().unwrap();
+        let candles = markets.entry(trade.symbol.clone()).or_default();
+        for interval in Interval::ALL {
+            let candles = &mut candles[interval as usize];
+            let start = interval.start(trade.time);
+            let candle = candles.entry(start).or_insert_with(|| Candle {
+                start: SystemTime::UNIX_EPOCH + Duration::from_secs(start),
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                units: 0,
+            });
+            candle.high = candle.high.max(trade.price);
+            candle.low = candle.low.min(trade.price);
+            candle.close = trade.price;
+            candle.units = candle.units.saturating_add(units);
+            if candles.len() > MAX_CANDLES {
+                candles.pop_first();
+            }
+        }
+    }
+}
+
+/// Whole seconds since the Unix epoch, 0 before it
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Side;
+
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_006_400 + seconds)
+    }
+
+    fn trade(price: &str, quantity: &str, seconds: u64) -> Trade {
+        Trade {
+            id: seconds,
+            symbol: String::from("XGAL-USD"),
+            price: price.parse().unwrap(),
+            quantity: quantity.parse().unwrap(),
+            maker_order_id: 1,
+            taker_order_id: 2,
+            taker_side: Side::Buy,
+            time: at(seconds),
+        }
+    }
+
+    fn summary(candles: &[Candle]) -> Vec<(SystemTime, String, String, String, String, String)> {
+        candles
+            .iter()
+            .map(|candle| {
+                (
+                    candle.start,
+                    candle.open.to_string(),
+                    candle.high.to_string(),
+                    candle.low.to_string(),
+                    candle.close.to_string(),
+                    candle.volume().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(Interval::parse("5m"), Some(Interval::FiveMinutes));
+        assert_eq!(Interval::parse("1w"), None);
+        // 1_700_006_400 is midnight UTC
+        assert_eq!(Interval::Day.start(at(86_399)), seconds(at(0)));
+        assert_eq!(Interval::FiveMinutes.start(at(599)), seconds(at(300)));
+    }
+
+    #[test]
+    fn folding() {
+        let store = CandleStore::default();
+        store.trade(&trade("10", "1", 5));
+        store.trade(&trade("12", "0.5", 30));
+        store.trade(&trade("9.5", "2", 59));
+        store.trade(&trade("11", "1", 130));
+        let s = String::from;
+
+        let minutes = store.candles("XGAL-USD", Interval::Minute, at(0), at(3600), 10);
+        assert_eq!(
+            summary(&minutes),
+            [
+                (at(0), s("10"), s("12"), s("9.5"), s("9.5"), s("3.5")),
+                (at(120), s("11"), s("11"), s("11"), s("11"), s("1")),
+            ]
+        );
+        let hours = store.candles("XGAL-USD", Interval::Hour, at(0), at(3600), 10);
+        assert_eq!(
+            summary(&hours),
+            [(at(0), s("10"), s("12"), s("9.5"), s("11"), s("4.5"))]
+        );
+
+        // The last candles of the range, which starts at a candle start or
+        // after it and ends before one
+        let last = store.candles("XGAL-USD", Interval::Minute, at(0), at(3600), 1);
+        assert_eq!(last[0].start, at(120));
+        let later = store.candles("XGAL-USD", Interval::Minute, at(1), at(120), 10);
+        assert!(later.is_empty());
+        assert!(
+            store
+                .candles("XORB-USD", Interval::Day, at(0), at(86_400), 10)
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,514 @@
+//! Settings of the server binary, from a TOML file and the environment
+//!
+//! Every setting has a default, the file overrides the defaults and
+//! environment variables override the file. Each key has a variable of its
+//! path in capitals with a `GALACTIC_` prefix, e.g. `limits.max_body_size`
+//! is `GALACTIC_LIMITS_MAX_BODY_SIZE`.
+//!
+//! ```toml
+//! listen = "0.0.0.0:8080"
+//! log_level = "info"
+//! log_format = "pretty"
+//! workers = 16
+//! data_dir = "data"
+//! idempotency_ttl_secs = 86400
+//!
+//! [limits]
+//! max_body_size = 1048576
+//! max_message_size = 1048576
+//! max_depth = 64
+//! max_fields = 10000
+//! max_string_bytes = 1048576
+//! ```
+//!
+//! `admin_token` has no default, the admin API answers requests only once it
+//! is set, see [`markets`](crate::markets). The markets orders are taken for
+//! are listed in `[[markets]]` tables, which only the file can set:
+//!
+//! ```toml
+//! [[markets]]
+//! symbol = "XGAL-USD"
+//! base = "XGAL"
+//! quote = "USD"
+//! tick_size = "0.01"
+//! lot_size = "0.001"
+//! min_notional = "10"
+//! status = "trading"
+//! ```
+
+use std::{
+    collections::HashSet,
+    env,
+    fmt::{self, Display},
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+};
+
+use crate::{galacticbuf::DeserializeLimits, markets::Market};
+
+/// Prefix of the environment variables overriding the settings
+const ENV_PREFIX: &str = "GALACTIC_";
+
+/// Settings of the server, see the module docs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Address the server listens on
+    pub listen: SocketAddr,
+    /// Least severe messages logged, unless `RUST_LOG` says otherwise, see
+    /// [`logging`](crate::logging)
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    /// Threads handling requests
+    pub workers: usize,
+    pub limits: Limits,
+    /// Directory the server keeps its state in, e.g. the registered schemas
+    pub data_dir: PathBuf,
+    /// Seconds the answer to a request with an `Idempotency-Key` is replayed
+    /// to retries of it
+    pub idempotency_ttl_secs: u64,
+    /// Bearer token of the admin API, which is disabled without one
+    pub admin_token: Option<String>,
+    /// Markets listed at start, see [`markets`](crate::markets)
+    pub markets: Vec<Market>,
+}
+
+/// Bounds on what clients send
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Bytes of a request body
+    pub max_body_size: u64,
+    /// Bytes of a message, and of its body once decompressed
+    pub max_message_size: usize,
+    /// Objects, lists, maps and enum payloads nested in each other
+    pub max_depth: usize,
+    /// Fields of a message and all of its objects together
+    pub max_fields: usize,
+    /// Bytes of a single string or field name
+    pub max_string_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// How log lines are written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// For people, over several lines with colors
+    Pretty,
+    /// One JSON object a line, for log collectors
+    Json,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Pretty,
+            // As many as rouille starts by default
+            workers: 8 * thread::available_parallelism().map_or(1, |n| n.get()),
+            limits: Limits::default(),
+            data_dir: PathBuf::from("data"),
+            idempotency_ttl_secs: 24 * 60 * 60,
+            admin_token: None,
+            markets: vec![],
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        let limits = DeserializeLimits::default();
+        Limits {
+            max_body_size: 1 << 20,
+            max_message_size: limits.max_message_size,
+            max_depth: limits.max_depth,
+            max_fields: limits.max_fields,
+            max_string_bytes: limits.max_string_bytes,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits of the messages the server reads
+    pub(crate) fn message_limits(&self) -> DeserializeLimits {
+        DeserializeLimits {
+            max_depth: self.max_depth,
+            max_fields: self.max_fields,
+            max_string_bytes: self.max_string_bytes,
+            max_message_size: self.max_message_size,
+            ..DeserializeLimits::default()
+        }
+    }
+}
+
+/// Value of a setting, as the file or the environment has it
+enum Setting<'a> {
+    Toml(&'a toml::Value),
+    Env(&'a str),
+}
+
+impl Config {
+    /// Settings of the file if there is one, overridden by the environment of
+    /// the process
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let config = match path {
+            Some(path) => {
+                let source = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+                Config::from_toml(&source)?
+            }
+            None => Config::default(),
+        };
+        config.with_env(env::vars())
+    }
+
+    /// Settings of the TOML source, the defaults for the keys it leaves out
+    pub fn from_toml(source: &str) -> Result<Config, ConfigError> {
+        let table: toml::Table = source
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError::Parse(e.message().to_string()))?;
+        let mut config = Config::default();
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("limits", toml::Value::Table(limits)) => {
+                    for (key, value) in limits {
+                        let key = format!("limits.{}", key);
+                        config.set(&key, &key, Setting::Toml(value))?;
+                    }
+                }
+                ("markets", toml::Value::Array(markets)) => {
+                    for (i, market) in markets.iter().enumerate() {
+                        let market = market.clone().try_into().map_err(|e: toml::de::Error| {
+                            ConfigError::Invalid {
+                                key: format!("markets[{}]", i),
+                                reason: e.message().to_string(),
+                            }
+                        })?;
+                        config.markets.push(market);
+                    }
+                }
+                (key, value) => config.set(key, key, Setting::Toml(value))?,
+            }
+        }
+        config.validate()
+    }
+
+    /// Settings overridden by the `GALACTIC_` variables among `vars`, other
+    /// variables are ignored
+    pub fn with_env(
+        mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Config, ConfigError> {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_lowercase();
+            let key = match key.strip_prefix("limits_") {
+                Some(limit) => format!("limits.{}", limit),
+                None => key,
+            };
+            self.set(&key, &name, Setting::Env(&value))?;
+        }
+        self.validate()
+    }
+
+    /// Sets the setting of `key`, errors name it as `name`
+    fn set(&mut self, key: &str, name: &str, setting: Setting) -> Result<(), ConfigError> {
+        match key {
+            "listen" => self.listen = parse(name, setting)?,
+            "log_level" => self.log_level = parse(name, setting)?,
+            "log_format" => self.log_format = parse(name, setting)?,
+            "workers" => self.workers = parse(name, setting)?,
+            "data_dir" => self.data_dir = parse(name, setting)?,
+            "idempotency_ttl_secs" => self.idempotency_ttl_secs = parse(name, setting)?,
+            "admin_token" => self.admin_token = Some(parse(name, setting)?),
+            "markets" => {
+                return Err(ConfigError::Invalid {
+                    key: name.to_string(),
+                    reason: String::from("must be `[[markets]]` tables of the file"),
+                });
+            }
+            "limits.max_body_size" => self.limits.max_body_size = parse(name, setting)?,
+            "limits.max_message_size" => self.limits.max_message_size = parse(name, setting)?,
+            "limits.max_depth" => self.limits.max_depth = parse(name, setting)?,
+            "limits.max_fields" => self.limits.max_fields = parse(name, setting)?,
+            "limits.max_string_bytes" => self.limits.max_string_bytes = parse(name, setting)?,
+            _ => return Err(ConfigError::UnknownKey(name.to_string())),
+        }
+        Ok(())
+    }
+
+    fn validate(self) -> Result<Config, ConfigError> {
+        let invalid = |key: &str, reason: &str| ConfigError::Invalid {
+            key: key.to_string(),
+            reason: reason.to_string(),
+        };
+        if self.workers == 0 {
+            return Err(invalid("workers", "must be at least 1"));
+        }
+        if self.data_dir.as_os_str().is_empty() {
+            return Err(invalid("data_dir", "must not be empty"));
+        }
+        if self.idempotency_ttl_secs == 0 {
+            return Err(invalid("idempotency_ttl_secs", "must be at least 1"));
+        }
+        if self
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.is_empty())
+        {
+            return Err(invalid("admin_token", "must not be empty"));
+        }
+        let mut symbols = HashSet::new();
+        for (i, market) in self.markets.iter().enumerate() {
+            if let Err(e) = market.validate() {
+                return Err(invalid(&format!("markets[{}].{}", i, e.field), &e.reason));
+            }
+            if !symbols.insert(&market.symbol) {
+                return Err(invalid(
+                    &format!("markets[{}].symbol", i),
+                    "is listed twice",
+                ));
+            }
+        }
+        let limits = [
+            ("limits.max_body_size", self.limits.max_body_size as usize),
+            ("limits.max_message_size", self.limits.max_message_size),
+            ("limits.max_depth", self.limits.max_depth),
+            ("limits.max_fields", self.limits.max_fields),
+            ("limits.max_string_bytes", self.limits.max_string_bytes),
+        ];
+        if let Some((key, _)) = limits.iter().find(|(_, limit)| *limit == 0) {
+            return Err(invalid(key, "must be at least 1"));
+        }
+        Ok(self)
+    }
+}
+
+/// Value of the setting, TOML strings and environment variables are parsed
+/// as their text, TOML integers must be positive
+fn parse<T: FromStr>(name: &str, setting: Setting) -> Result<T, ConfigError>
+where
+    T::Err: Display,
+{
+    let text = match setting {
+        Setting::Env(text) => text.to_string(),
+        Setting::Toml(toml::Value::String(text)) => text.clone(),
+        Setting::Toml(toml::Value::Integer(integer)) if *integer >= 0 => integer.to_string(),
+        Setting::Toml(value) => {
+            return Err(ConfigError::Invalid {
+                key: name.to_string(),
+                reason: format!("unexpected {}", value.type_str()),
+            });
+        }
+    };
+    text.parse().map_err(|e: T::Err| ConfigError::Invalid {
+        key: name.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+impl LogLevel {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "unknown level `{}`, expected error, warn, info, debug or trace",
+                level
+            )),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unknown format `{}`, expected pretty or json",
+                format
+            )),
+        }
+    }
+}
+
+/// Settings which can't be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// File which isn't TOML
+    Parse(String),
+    /// Key of no setting, as the file or the environment names it
+    UnknownKey(String),
+    /// Value out of the range of the setting
+    Invalid {
+        key: String,
+        reason: String,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Parse(e) => write!(f, "invalid TOML: {}", e),
+            ConfigError::UnknownKey(key) => write!(f, "unknown setting `{}`", key),
+            ConfigError::Invalid { key, reason } => write!(f, "`{}`: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn file_and_environment() {
+        let config = Config::from_toml(
+            r#"
+            listen = "127.0.0.1:9000"
+            log_level = "debug"
+            log_format = "json"
+            workers = 4
+            idempotency_ttl_secs = 600
+
+            [limits]
+            max_body_size = 4096
+
+            [[markets]]
+            symbol = "XGAL-USD"
+            base = "XGAL"
+            quote = "USD"
+            tick_size = "0.01"
+            lot_size = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.listen, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.workers, 4);
+        assert_eq!(config.idempotency_ttl_secs, 600);
+        assert_eq!(config.limits.max_body_size, 4096);
+        assert_eq!(config.limits.max_depth, Limits::default().max_depth);
+        assert_eq!(config.data_dir, PathBuf::from("data"));
+        assert_eq!(config.markets.len(), 1);
+        assert_eq!(config.markets[0].tick_size.to_string(), "0.01");
+        assert_eq!(config.admin_token, None);
+
+        let config = config
+            .with_env(vars(&[
+                ("GALACTIC_WORKERS", "2"),
+                ("GALACTIC_LIMITS_MAX_DEPTH", "8"),
+                ("GALACTIC_DATA_DIR", "/var/lib/galactic"),
+                ("GALACTIC_ADMIN_TOKEN", "s3cret"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+        assert_eq!(config.workers, 2);
+        assert_eq!(config.limits.max_depth, 8);
+        assert_eq!(config.limits.max_body_size, 4096);
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/galactic"));
+        assert_eq!(config.admin_token.as_deref(), Some("s3cret"));
+        assert_eq!(config.limits.message_limits().max_depth, 8);
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let error = |result: Result<Config, ConfigError>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(Config::from_toml("listen = \"localhost\"")),
+            "`listen`: invalid socket address syntax"
+        );
+        assert_eq!(
+            error(Config::from_toml("[limits]\nmax_depth = -1")),
+            "`limits.max_depth`: unexpected integer"
+        );
+        assert_eq!(
+            error(Config::from_toml("[limits]\nmax_fields = 0")),
+            "`limits.max_fields`: must be at least 1"
+        );
+        assert_eq!(
+            error(Config::from_toml("idempotency_ttl_secs = 0")),
+            "`idempotency_ttl_secs`: must be at least 1"
+        );
+        let market = "[[markets]]\nsymbol = \"XGAL-USD\"\nbase = \"XGAL\"\nquote = \"USD\"\n";
+        assert_eq!(
+            error(Config::from_toml(&format!(
+                "{}tick_size = 1\nlot_size = 0",
+                market
+            ))),
+            "`markets[0].lot_size`: must be positive"
+        );
+        assert!(
+            error(Config::from_toml(&format!("{}tick_size = 1", market)))
+                .starts_with("`markets[0]`: missing field `lot_size`")
+        );
+        assert_eq!(
+            error(Config::default().with_env(vars(&[("GALACTIC_MARKETS", "XGAL-USD")]))),
+            "`GALACTIC_MARKETS`: must be `[[markets]]` tables of the file"
+        );
+        assert_eq!(
+            error(Config::from_toml("port = 8080")),
+            "unknown setting `port`"
+        );
+        assert_eq!(
+            error(Config::default().with_env(vars(&[("GALACTIC_LOG_LEVEL", "loud")]))),
+            "`GALACTIC_LOG_LEVEL`: unknown level `loud`, expected error, warn, info, debug or trace"
+        );
+        assert_eq!(
+            error(Config::from_toml("log_format = \"xml\"")),
+            "`log_format`: unknown format `xml`, expected pretty or json"
+        );
+        assert_eq!(
+            error(Config::default().with_env(vars(&[("GALACTIC_WORKERS", "many")]))),
+            "`GALACTIC_WORKERS`: invalid digit found in string"
+        );
+        assert!(error(Config::from_toml("workers = ")).starts_with("invalid TOML: "));
+    }
+}
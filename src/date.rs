@@ -0,0 +1,340 @@
+//! Calendar dates, the representation of settlement and expiry dates
+//!
+//! A [`Date`] counts days since 1970-01-01 in the proleptic Gregorian
+//! calendar, negative before it, so dates order and subtract like the
+//! integers they are. Calendar conversions follow Howard Hinnant's
+//! `days_from_civil` and `civil_from_days`.
+
+#![allow(dead_code)]
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Date(i32);
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParseDateError(pub(crate) String);
+
+const SECONDS_PER_DAY: u64 = 86_400;
+/// Days from 0000-03-01, where the computations start, to 1970-01-01
+const EPOCH_OFFSET: i64 = 719_468;
+/// Days of a 400 year cycle of the Gregorian calendar
+const DAYS_PER_ERA: i64 = 146_097;
+
+impl Date {
+    pub(crate) const EPOCH: Date = Date(0);
+
+    pub(crate) const fn from_days(days: i32) -> Self {
+        Date(days)
+    }
+
+    /// Days since 1970-01-01
+    pub(crate) fn days(self) -> i32 {
+        self.0
+    }
+
+    /// `None` for a day the month doesn't have, e.g. February 30, or a year
+    /// too far out for the day count
+    pub(crate) fn from_ymd(year: i32, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        // Years start in March so the leap day ends them
+        let (month, day) = (month as i64, day as i64);
+        let year = year as i64 - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_PER_ERA + day_of_era - EPOCH_OFFSET;
+        i32::try_from(days).ok().map(Date)
+    }
+
+    /// Year, month and day of the month, both counted from 1
+    pub(crate) fn to_ymd(self) -> (i32, u32, u32) {
+        let days = self.0 as i64 + EPOCH_OFFSET;
+        let era = days.div_euclid(DAYS_PER_ERA);
+        let day_of_era = days.rem_euclid(DAYS_PER_ERA);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+        (year as i32, month as u32, day as u32)
+    }
+
+    pub(crate) fn year(self) -> i32 {
+        self.to_ymd().0
+    }
+
+    pub(crate) fn month(self) -> u32 {
+        self.to_ymd().1
+    }
+
+    pub(crate) fn day(self) -> u32 {
+        self.to_ymd().2
+    }
+
+    /// ISO 8601 day of the week, 1 for Monday through 7 for Sunday
+    pub(crate) fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.0 as i64 + 3).rem_euclid(7) as u32 + 1
+    }
+
+    pub(crate) fn is_weekend(self) -> bool {
+        self.weekday() >= 6
+    }
+
+    pub(crate) fn checked_add_days(self, days: i32) -> Option<Date> {
+        self.0.checked_add(days).map(Date)
+    }
+
+    /// Days from the date to `other`, negative when `other` is earlier
+    pub(crate) fn days_until(self, other: Date) -> i64 {
+        other.0 as i64 - self.0 as i64
+    }
+
+    /// Next date at least `days` business days out, skipping weekends, e.g.
+    /// the settlement date of a T+2 trade
+    pub(crate) fn add_business_days(self, days: u32) -> Option<Date> {
+        let mut date = self;
+        for _ in 0..days {
+            date = date.checked_add_days(1)?;
+            while date.is_weekend() {
+                date = date.checked_add_days(1)?;
+            }
+        }
+        Some(date)
+    }
+
+    /// Date of the instant in UTC
+    pub(crate) fn from_system_time(time: SystemTime) -> Date {
+        let days = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs() / SECONDS_PER_DAY) as i64,
+            Err(e) => -(e.duration().as_secs().div_ceil(SECONDS_PER_DAY) as i64),
+        };
+        Date(days.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+
+    /// Midnight UTC at the start of the date
+    pub(crate) fn to_system_time(self) -> SystemTime {
+        let since = Duration::from_secs(self.0.unsigned_abs() as u64 * SECONDS_PER_DAY);
+        if self.0 >= 0 {
+            UNIX_EPOCH + since
+        } else {
+            UNIX_EPOCH - since
+        }
+    }
+}
+
+/// UTC time of the instant to the millisecond in RFC 3339, e.g.
+/// `2026-10-17T09:30:00.250Z`, as the API writes timestamps
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let millis = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as u64,
+        Err(_) => 0,
+    };
+    let seconds = millis / 1000 % SECONDS_PER_DAY;
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        Date::from_system_time(time),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis % 1000
+    )
+}
+
+/// Instant of an RFC 3339 UTC timestamp, e.g. `2026-10-17T09:30:00Z`, with
+/// up to nine digits of fractional seconds as [`format_timestamp`] writes
+/// three, `None` for anything else
+pub(crate) fn parse_timestamp(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.split_once('T')?;
+    let date: Date = date.parse().ok()?;
+    let time = time.strip_suffix('Z')?;
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let digits = |part: &str, lengths: std::ops::RangeInclusive<usize>| {
+        lengths.contains(&part.len()) && part.bytes().all(|byte| byte.is_ascii_digit())
+    };
+    let mut parts = time.split(':');
+    let mut part = |below: u64| {
+        parts
+            .next()
+            .filter(|part| digits(part, 2..=2))?
+            .parse::<u64>()
+            .ok()
+            .filter(|&value| value < below)
+    };
+    let seconds = part(24)? * 3600 + part(60)? * 60 + part(60)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let nanos = match fraction {
+        None => 0,
+        Some(fraction) if digits(fraction, 1..=9) => {
+            fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32)
+        }
+        Some(_) => return None,
+    };
+    Some(date.to_system_time() + Duration::new(seconds, nanos))
+}
+
+pub(crate) fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub(crate) fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// ISO 8601 calendar date, e.g. `2024-03-01`, years before 0 with a `-`
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.to_ymd();
+        let sign = if year < 0 { "-" } else { "" };
+        write!(
+            f,
+            "{}{:04}-{:02}-{:02}",
+            sign,
+            year.unsigned_abs(),
+            month,
+            day
+        )
+    }
+}
+
+/// Parses the format [`Display`] writes
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseDateError(format!("invalid date: `{}`", s));
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let mut parts = unsigned.splitn(3, '-');
+        let mut part = |digits: std::ops::RangeInclusive<usize>| {
+            parts
+                .next()
+                .filter(|part| digits.contains(&part.len()))
+                .filter(|part| part.chars().all(|c| c.is_ascii_digit()))
+                .ok_or_else(error)
+        };
+        let year = part(4..=7)?.parse::<i32>().map_err(|_| error())?;
+        let month = part(2..=2)?.parse().map_err(|_| error())?;
+        let day = part(2..=2)?.parse().map_err(|_| error())?;
+        let year = if negative { -year } else { year };
+        Date::from_ymd(year, month, day).ok_or_else(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(Date::from_ymd(1970, 1, 1), Some(Date::EPOCH));
+        assert_eq!(d("2000-03-01").days(), 11_017);
+        assert_eq!(d("1969-12-31").days(), -1);
+        assert_eq!(Date::from_ymd(2024, 2, 29).unwrap().to_ymd(), (2024, 2, 29));
+        assert_eq!(Date::from_ymd(2023, 2, 29), None);
+        assert_eq!(Date::from_ymd(2024, 13, 1), None);
+        for days in [i32::MIN, -719_529, -1, 0, 59, 11_016, 19_783, i32::MAX] {
+            let (year, month, day) = Date(days).to_ymd();
+            assert_eq!(
+                Date::from_ymd(year, month, day),
+                Some(Date(days)),
+                "{}",
+                days
+            );
+        }
+
+        // Friday, settling two business days later on Tuesday
+        let trade = d("2024-03-01");
+        assert_eq!(trade.weekday(), 5);
+        assert_eq!(trade.add_business_days(2), Some(d("2024-03-05")));
+        assert_eq!(trade.days_until(d("2024-03-05")), 4);
+
+        let time = UNIX_EPOCH + Duration::from_secs(19_783 * SECONDS_PER_DAY + 3600);
+        assert_eq!(Date::from_system_time(time), Date(19_783));
+        assert_eq!(
+            Date(-2).to_system_time(),
+            UNIX_EPOCH - Duration::from_secs(2 * 86_400)
+        );
+        assert_eq!(
+            Date::from_system_time(UNIX_EPOCH - Duration::from_secs(1)),
+            Date(-1)
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        let time = d("2026-10-17").to_system_time() + Duration::from_millis(34_200_250);
+        assert_eq!(format_timestamp(time), "2026-10-17T09:30:00.250Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+
+        assert_eq!(parse_timestamp("2026-10-17T09:30:00.250Z"), Some(time));
+        assert_eq!(
+            parse_timestamp("2026-10-17T09:30:00Z"),
+            Some(time - Duration::from_millis(250))
+        );
+        assert_eq!(
+            parse_timestamp("1970-01-01T00:00:00.000000001Z"),
+            Some(UNIX_EPOCH + Duration::from_nanos(1))
+        );
+        for s in [
+            "2026-10-17",
+            "2026-10-17T09:30:00",
+            "2026-10-17T09:30:00+02:00",
+            "2026-10-17T24:00:00Z",
+            "2026-10-17T09:30Z",
+            "2026-10-17T09:30:00.Z",
+            "2026-10-17T9:30:00Z",
+        ] {
+            assert_eq!(parse_timestamp(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for s in [
+            "1970-01-01",
+            "2024-02-29",
+            "0001-12-31",
+            "-0044-03-15",
+            "12345-06-07",
+        ] {
+            assert_eq!(d(s).to_string(), s);
+        }
+        for s in [
+            "",
+            "2024-2-01",
+            "2024-02-30",
+            "24-02-01",
+            "2024-02-01x",
+            "+2024-02-01",
+        ] {
+            assert!(s.parse::<Date>().is_err(), "{}", s);
+        }
+    }
+}
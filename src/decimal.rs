@@ -0,0 +1,480 @@
+//! Fixed-point decimal numbers, the representation of all prices and quantities
+//!
+//! A [`Decimal`] is `mantissa * 10^exponent`, so `12.50` is `(1250, -2)`.
+//! Arithmetic keeps the scale of its operands like SQL decimals do, equality and
+//! ordering compare the numeric value, so `12.5 == 12.50`.
+
+#![allow(dead_code)]
+
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+    ops::{Add, Mul, Neg, Sub},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Decimal {
+    mantissa: i64,
+    exponent: i8,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParseDecimalError(pub(crate) String);
+
+impl Decimal {
+    pub(crate) const ZERO: Decimal = Decimal::new(0, 0);
+
+    pub(crate) const fn new(mantissa: i64, exponent: i8) -> Self {
+        Decimal { mantissa, exponent }
+    }
+
+    pub(crate) fn mantissa(&self) -> i64 {
+        self.mantissa
+    }
+
+    pub(crate) fn exponent(&self) -> i8 {
+        self.exponent
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub(crate) fn is_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    /// Same value with trailing zeros of the mantissa removed, `12.50` becomes `12.5`
+    pub(crate) fn normalize(&self) -> Decimal {
+        if self.mantissa == 0 {
+            return Decimal::ZERO;
+        }
+        let mut normalized = *self;
+        while normalized.mantissa % 10 == 0 && normalized.exponent < i8::MAX {
+            normalized.mantissa /= 10;
+            normalized.exponent += 1;
+        }
+        normalized
+    }
+
+    /// Same value expressed with the given exponent, `None` when it would lose
+    /// digits or overflow the mantissa
+    pub(crate) fn rescale(&self, exponent: i8) -> Option<Decimal> {
+        match exponent.cmp(&self.exponent) {
+            Ordering::Equal => Some(*self),
+            Ordering::Less => {
+                let factor = 10i64.checked_pow((self.exponent as i32 - exponent as i32) as u32)?;
+                let mantissa = self.mantissa.checked_mul(factor)?;
+                Some(Decimal { mantissa, exponent })
+            }
+            Ordering::Greater => {
+                let factor = 10i64.checked_pow((exponent as i32 - self.exponent as i32) as u32);
+                match factor {
+                    Some(factor) if self.mantissa % factor == 0 => Some(Decimal {
+                        mantissa: self.mantissa / factor,
+                        exponent,
+                    }),
+                    None if self.mantissa == 0 => Some(Decimal {
+                        mantissa: 0,
+                        exponent,
+                    }),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Whether the value is a whole number of `step`s, `false` for a step
+    /// which isn't positive or when their scales are too far apart to tell
+    pub(crate) fn is_multiple_of(&self, step: Decimal) -> bool {
+        match self.steps(step) {
+            Some((value, step, _)) => value % step == 0,
+            None => false,
+        }
+    }
+
+    /// Greatest multiple of `step` at or below the value, in the scale of
+    /// `step` when it fits, `None` for a step which isn't positive or when out
+    /// of range
+    pub(crate) fn floor_to(&self, step: Decimal) -> Option<Decimal> {
+        let (value, step_units, exponent) = self.steps(step)?;
+        Decimal::of_steps(value.div_euclid(step_units) * step_units, exponent, step)
+    }
+
+    /// Least multiple of `step` at or above the value, like [`Self::floor_to`]
+    pub(crate) fn ceil_to(&self, step: Decimal) -> Option<Decimal> {
+        let (value, step_units, exponent) = self.steps(step)?;
+        let steps = value.div_euclid(step_units) + (value.rem_euclid(step_units) != 0) as i128;
+        Decimal::of_steps(steps.checked_mul(step_units)?, exponent, step)
+    }
+
+    /// Whole units of 10^-`places` in the value, for sums which would overflow
+    /// the mantissa, `None` when it has more places or is out of range
+    pub(crate) fn to_units(self, places: i8) -> Option<i128> {
+        let shift = self.exponent as i32 + places as i32;
+        let factor = 10i128.checked_pow(shift.unsigned_abs())?;
+        match shift >= 0 {
+            true => (self.mantissa as i128).checked_mul(factor),
+            false => (self.mantissa as i128 % factor == 0).then(|| self.mantissa as i128 / factor),
+        }
+    }
+
+    /// Value of `units` of 10^-`places`, with its last places dropped when
+    /// the mantissa can't hold them, saturating beyond that
+    pub(crate) fn from_units(mut units: i128, mut places: i8) -> Decimal {
+        while places > 0 && (units % 10 == 0 || i64::try_from(units).is_err()) {
+            units /= 10;
+            places -= 1;
+        }
+        let mantissa = i64::try_from(units).unwrap_or(match units < 0 {
+            true => i64::MIN,
+            false => i64::MAX,
+        });
+        Decimal::new(mantissa, -places)
+    }
+
+    /// Value and positive step as integers of their common exponent
+    fn steps(&self, step: Decimal) -> Option<(i128, i128, i8)> {
+        let (value, step) = (self.normalize(), step.normalize());
+        let exponent = value.exponent.min(step.exponent);
+        let scaled = |d: Decimal| {
+            10i128
+                .checked_pow((d.exponent as i32 - exponent as i32) as u32)?
+                .checked_mul(d.mantissa as i128)
+        };
+        let step = scaled(step).filter(|&step| step > 0)?;
+        Some((scaled(value)?, step, exponent))
+    }
+
+    fn of_steps(units: i128, exponent: i8, step: Decimal) -> Option<Decimal> {
+        let value = Decimal::new(i64::try_from(units).ok()?, exponent);
+        Some(value.rescale(step.exponent).unwrap_or(value))
+    }
+
+    pub(crate) fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let exponent = self.exponent.min(other.exponent);
+        let (a, b) = (self.rescale(exponent)?, other.rescale(exponent)?);
+        Some(Decimal {
+            mantissa: a.mantissa.checked_add(b.mantissa)?,
+            exponent,
+        })
+    }
+
+    pub(crate) fn checked_sub(self, other: Decimal) -> Option<Decimal> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    pub(crate) fn checked_mul(self, other: Decimal) -> Option<Decimal> {
+        Some(Decimal {
+            mantissa: self.mantissa.checked_mul(other.mantissa)?,
+            exponent: self.exponent.checked_add(other.exponent)?,
+        })
+    }
+
+    pub(crate) fn checked_neg(self) -> Option<Decimal> {
+        Some(Decimal {
+            mantissa: self.mantissa.checked_neg()?,
+            exponent: self.exponent,
+        })
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        self.checked_add(other).expect("decimal addition overflow")
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        self.checked_sub(other)
+            .expect("decimal subtraction overflow")
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: Decimal) -> Decimal {
+        self.checked_mul(other)
+            .expect("decimal multiplication overflow")
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        self.checked_neg().expect("decimal negation overflow")
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.normalize(), other.normalize());
+        let sign = a.mantissa.signum().cmp(&b.mantissa.signum());
+        if sign != Ordering::Equal || a.mantissa == 0 {
+            return sign;
+        }
+        // Scale the mantissa with the larger exponent down to the smaller one,
+        // when that overflows i128 its magnitude is the larger one
+        let (larger, smaller, swapped) = if a.exponent >= b.exponent {
+            (a, b, false)
+        } else {
+            (b, a, true)
+        };
+        let scaled = 10i128
+            .checked_pow((larger.exponent as i32 - smaller.exponent as i32) as u32)
+            .and_then(|factor| (larger.mantissa as i128).checked_mul(factor));
+        let ordering = match scaled {
+            Some(scaled) => scaled.cmp(&(smaller.mantissa as i128)),
+            None if larger.mantissa > 0 => Ordering::Greater,
+            None => Ordering::Less,
+        };
+        if swapped {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa.hash(state);
+        normalized.exponent.hash(state);
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Decimal::new(value, 0)
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        if self.exponent >= 0 {
+            let zeros = "0".repeat(self.exponent as usize);
+            return write!(f, "{}{}{}", sign, digits, zeros);
+        }
+        let scale = self.exponent.unsigned_abs() as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Parses plain decimal notation, e.g. `42`, `-0.015` or `12.50`
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseDecimalError(format!("invalid decimal: `{}`", s));
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if integer.is_empty()
+            || !integer.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+            || unsigned.ends_with('.')
+        {
+            return Err(error());
+        }
+        let sign = if negative { "-" } else { "" };
+        let mut digits = format!("{}{}{}", sign, integer, fraction);
+        let mut exponent = -(fraction.len() as i64);
+        // Trailing zeros of a mantissa too large for i64 go into the exponent,
+        // which is how Display writes positive exponents
+        let mantissa = loop {
+            match digits.parse::<i64>() {
+                Ok(mantissa) => break mantissa,
+                Err(_) if digits.ends_with('0') => {
+                    digits.pop();
+                    exponent += 1;
+                }
+                Err(_) => return Err(error()),
+            }
+        };
+        let exponent = i8::try_from(exponent).map_err(|_| error())?;
+        Ok(Decimal { mantissa, exponent })
+    }
+}
+
+/// Written as a string, so JSON readers parsing numbers as floats can't
+/// round it
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Read from a string like [`FromStr`] or from a number
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a decimal number")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+                v.parse().map_err(|e: ParseDecimalError| E::custom(e.0))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+                Ok(Decimal::from(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+                i64::try_from(v)
+                    .map(Decimal::from)
+                    .map_err(|_| E::custom(format!("invalid decimal: `{}`", v)))
+            }
+
+            // Display of a float is the shortest decimal reading back as it
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Decimal, E> {
+                self.visit_str(&v.to_string())
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for s in ["0", "42", "-7", "12.50", "0.015", "-0.5", "1000"] {
+            assert_eq!(d(s).to_string(), s);
+        }
+        assert_eq!(d("12.50"), Decimal::new(1250, -2));
+        assert_eq!(Decimal::new(15, 2).to_string(), "1500");
+        for decimal in [
+            Decimal::new(i64::MAX, 20),
+            Decimal::new(i64::MIN, 0),
+            Decimal::new(-3, i8::MIN),
+        ] {
+            assert_eq!(d(&decimal.to_string()), decimal);
+        }
+        for s in [
+            "",
+            "-",
+            ".5",
+            "5.",
+            "1.2.3",
+            "1e5",
+            "+1",
+            "99999999999999999999",
+        ] {
+            assert!(s.parse::<Decimal>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!((d("1.50") + d("2.25")).to_string(), "3.75");
+        assert_eq!((d("1") - d("0.001")).to_string(), "0.999");
+        assert_eq!((d("1.5") * d("2.5")).to_string(), "3.75");
+        assert_eq!((-d("1.5")).to_string(), "-1.5");
+        assert_eq!(Decimal::new(i64::MAX, 0).checked_add(d("1")), None);
+        assert_eq!(d("1").checked_add(Decimal::new(1, -127)), None);
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(d("12.5"), d("12.500"));
+        assert_eq!(Decimal::new(0, 5), Decimal::ZERO);
+        assert!(d("0.1") < d("0.11"));
+        assert!(d("-2") < d("-1.99"));
+        assert!(d("-1") < d("0"));
+        assert!(Decimal::new(1, 100) > Decimal::new(i64::MAX, -100));
+        assert!(Decimal::new(-1, 100) < Decimal::new(i64::MIN, 0));
+    }
+
+    #[test]
+    fn rescale() {
+        assert_eq!(d("1.5").rescale(-3), Some(Decimal::new(1500, -3)));
+        assert_eq!(d("1.50").rescale(-1), Some(Decimal::new(15, -1)));
+        assert_eq!(d("1.55").rescale(-1), None);
+    }
+
+    #[test]
+    fn multiples() {
+        assert!(d("10.25").is_multiple_of(d("0.05")));
+        assert!(d("300").is_multiple_of(d("100.0")));
+        assert!(d("0").is_multiple_of(d("0.01")));
+        assert!(!d("10.26").is_multiple_of(d("0.05")));
+        assert!(!d("250").is_multiple_of(d("100")));
+        assert!(!d("1").is_multiple_of(Decimal::ZERO));
+        assert!(!Decimal::new(1, 100).is_multiple_of(Decimal::new(1, -100)));
+
+        assert_eq!(d("10.37").floor_to(d("0.5")).unwrap().to_string(), "10.0");
+        assert_eq!(d("10.37").ceil_to(d("0.5")).unwrap().to_string(), "10.5");
+        assert_eq!(d("10.5").ceil_to(d("0.5")).unwrap().to_string(), "10.5");
+        assert_eq!(d("-0.3").floor_to(d("0.5")).unwrap().to_string(), "-0.5");
+        assert_eq!(d("1234").floor_to(d("100")).unwrap().to_string(), "1200");
+        assert_eq!(d("1").floor_to(Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(d("1.5").to_units(8), Some(150_000_000));
+        assert_eq!(d("1200").to_units(-2), Some(12));
+        assert_eq!(d("0.001").to_units(2), None);
+        assert_eq!(Decimal::from_units(150_000_000, 8).to_string(), "1.5");
+        assert_eq!(Decimal::from_units(0, 16).to_string(), "0");
+        let large = Decimal::from_units(123_456_789_012_345_678_900_000_000, 16);
+        assert_eq!(large.to_string(), "12345678901.23456789");
+        assert_eq!(Decimal::from_units(i128::MAX, 0), Decimal::new(i64::MAX, 0));
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(serde_json::to_string(&d("12.50")).unwrap(), r#""12.50""#);
+        let read = |json| serde_json::from_str::<Decimal>(json);
+        for (json, value) in [(r#""12.50""#, "12.5"), ("12.5", "12.5"), ("-3", "-3")] {
+            assert_eq!(read(json).unwrap(), d(value), "{}", json);
+        }
+        for json in [r#""1e5""#, "true", "18446744073709551615"] {
+            assert!(read(json).is_err(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,1491 @@
+//! Matching engine, which matches the orders of a symbol by price, then time
+//!
+//! A buy order trades with the cheapest sell orders at or below its limit
+//! price, a sell order with the dearest buy orders at or above it, orders at
+//! the same price in the order they arrived. Trades happen at the price of the
+//! order resting in the book. What a limit order doesn't fill at once rests in
+//! the book when it is good till cancelled, immediate or cancel and market
+//! orders expire instead. A fill or kill order expires whole unless it can
+//! fill at once. Orders are only taken for the markets listed, and must fit
+//! their steps, see [`markets`](crate::markets).
+//!
+//! An order in the book can be cancelled or amended until it fills. An amend
+//! which lowers the quantity keeps the order's place in the queue at its
+//! price, one which raises it or moves the price sends the order to the back
+//! of the queue at its new price, matching it again when the price crosses.
+//!
+//! Each order is placed for an account, see [`accounts`](crate::accounts),
+//! and is only found, cancelled or listed for it. Clients may name an order
+//! with a client order ID of their own, which no other order of the account
+//! in the book may have, and cancel, amend or look it up by it. Once the
+//! order leaves the book the ID can be given to a new order, looking it up
+//! then finds the newest order with it.
+//!
+//! Orders in the books are kept in a pool of slots which new orders take again
+//! once theirs leave, the last 100,000 orders out of the books in a history,
+//! where they can still be looked up and listed. Older ones are forgotten.
+//!
+//! The [`Engine`] runs on a thread of its own behind an [`EngineHandle`], so
+//! orders are matched one at a time in the order they arrive however many
+//! requests are served at once. Each [`TradeListener`] sees the trades on
+//! that thread as they happen, before the request making them is answered.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    io,
+    sync::{Arc, mpsc},
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::AccountId,
+    decimal::Decimal,
+    markets::{Market, MarketStatus},
+};
+
+mod book;
+mod pool;
+
+use self::{
+    book::Book,
+    pool::{OrderPool, Slot},
+};
+
+/// Most decimal places of a price or quantity
+pub(crate) const MAX_DECIMAL_PLACES: i8 = 8;
+
+/// Prices and quantities are below this, so sums of them can't overflow
+const MAX_VALUE: Decimal = Decimal::new(10_000_000_000, 0);
+
+/// Longest client order ID
+const MAX_CLIENT_ORDER_ID_LENGTH: usize = 64;
+
+/// Orders out of the books kept in the history
+const ORDER_HISTORY: usize = 100_000;
+
+/// ID the engine gives an order, counting from 1
+pub(crate) type OrderId = u64;
+
+/// Order as a client names it, by the ID the engine gave it or its own
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OrderKey {
+    Id(OrderId),
+    Client(String),
+}
+
+impl Display for OrderKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderKey::Id(id) => write!(f, "order {}", id),
+            OrderKey::Client(client_id) => write!(f, "order with client ID `{}`", client_id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// Side of the orders an order of this side trades with
+    pub(crate) fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OrderType {
+    /// Trades at its price or better
+    Limit,
+    /// Trades at any price
+    Market,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum TimeInForce {
+    #[serde(rename = "gtc")]
+    GoodTillCancelled,
+    #[serde(rename = "ioc")]
+    ImmediateOrCancel,
+    #[serde(rename = "fok")]
+    FillOrKill,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OrderStatus {
+    /// In the book, nothing filled yet
+    Open,
+    /// In the book with part of it filled
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    /// Left the book without filling whole, as its time in force asks
+    Expired,
+}
+
+impl OrderStatus {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Open => "open",
+            OrderStatus::PartiallyFilled => "partially_filled",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Expired => "expired",
+        }
+    }
+
+    /// Whether the order is in the book, so can still trade
+    pub(crate) fn is_open(self) -> bool {
+        matches!(self, OrderStatus::Open | OrderStatus::PartiallyFilled)
+    }
+}
+
+/// Order as a client submits it, see [`Engine::submit`]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NewOrder {
+    pub(crate) symbol: String,
+    pub(crate) side: Side,
+    #[serde(rename = "type")]
+    pub(crate) order_type: OrderType,
+    /// Limit price, none for market orders
+    #[serde(default)]
+    pub(crate) price: Option<Decimal>,
+    pub(crate) quantity: Decimal,
+    /// Good till cancelled for limit orders and immediate or cancel for
+    /// market orders when not given
+    #[serde(default)]
+    pub(crate) time_in_force: Option<TimeInForce>,
+    #[serde(default)]
+    pub(crate) client_order_id: Option<String>,
+    /// Account placing the order, which the server sets from its API key
+    #[serde(skip)]
+    pub(crate) account_id: AccountId,
+}
+
+/// New price or quantity of an order in the book, see [`Engine::amend`]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Amend {
+    #[serde(default)]
+    pub(crate) price: Option<Decimal>,
+    /// Quantity of the whole order, what is filled included
+    #[serde(default)]
+    pub(crate) quantity: Option<Decimal>,
+}
+
+/// Which orders [`Engine::orders`] lists
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OrderFilter {
+    pub(crate) account_id: AccountId,
+    pub(crate) symbol: Option<String>,
+    /// In the book when true, out of it when false
+    pub(crate) open: Option<bool>,
+    /// Orders after this one only, the cursor of the previous page
+    pub(crate) after: Option<OrderId>,
+    pub(crate) limit: usize,
+}
+
+/// Price levels of a book, best first, with the quantity left at each, see
+/// [`Engine::depth`]
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Depth {
+    /// Changes the book went through, the same sequence is the same book
+    pub(crate) sequence: u64,
+    pub(crate) bids: Vec<(Decimal, Decimal)>,
+    pub(crate) asks: Vec<(Decimal, Decimal)>,
+}
+
+/// Orders matching a filter, oldest first, with the last of them when there
+/// are more to list after it
+#[derive(Debug, PartialEq)]
+pub(crate) struct OrderPage {
+    pub(crate) orders: Vec<Order>,
+    pub(crate) next: Option<OrderId>,
+}
+
+/// Order accepted by the engine
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Order {
+    pub(crate) id: OrderId,
+    pub(crate) symbol: String,
+    pub(crate) side: Side,
+    pub(crate) order_type: OrderType,
+    pub(crate) price: Option<Decimal>,
+    pub(crate) quantity: Decimal,
+    pub(crate) filled: Decimal,
+    pub(crate) time_in_force: TimeInForce,
+    pub(crate) status: OrderStatus,
+    pub(crate) client_order_id: Option<String>,
+    pub(crate) account_id: AccountId,
+    pub(crate) created_at: SystemTime,
+    pub(crate) updated_at: SystemTime,
+}
+
+/// Trade of two orders, at the price of the one which rested in the book
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Trade {
+    pub(crate) id: u64,
+    pub(crate) symbol: String,
+    pub(crate) price: Decimal,
+    pub(crate) quantity: Decimal,
+    /// Order which was in the book
+    pub(crate) maker_order_id: OrderId,
+    /// Order which traded with it on arrival
+    pub(crate) taker_order_id: OrderId,
+    pub(crate) taker_side: Side,
+    pub(crate) time: SystemTime,
+}
+
+/// Sees every trade of the engine, see the module docs
+pub(crate) trait TradeListener: fmt::Debug + Send + Sync {
+    fn trade(&self, trade: &Trade);
+}
+
+/// Order the engine doesn't accept, with the field at fault
+#[derive(Debug, PartialEq)]
+pub(crate) struct OrderError {
+    pub(crate) field: &'static str,
+    pub(crate) reason: String,
+}
+
+impl Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+/// Order the engine doesn't accept, see [`Engine::submit`]
+#[derive(Debug, PartialEq)]
+pub(crate) enum SubmitError {
+    Invalid(OrderError),
+    /// Client order ID of an order in the book
+    DuplicateClientOrderId {
+        client_order_id: String,
+        order_id: OrderId,
+    },
+}
+
+impl Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::Invalid(e) => write!(f, "{}", e),
+            SubmitError::DuplicateClientOrderId {
+                client_order_id,
+                order_id,
+            } => write!(
+                f,
+                "client order ID `{}` is taken by open order {}",
+                client_order_id, order_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+impl From<OrderError> for SubmitError {
+    fn from(e: OrderError) -> Self {
+        SubmitError::Invalid(e)
+    }
+}
+
+/// Order which can't be cancelled or amended
+#[derive(Debug, PartialEq)]
+pub(crate) enum UpdateError {
+    NotFound(OrderKey),
+    /// Order which left the book before the update arrived, as it is
+    Closed(Box<Order>),
+    Invalid(OrderError),
+}
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::NotFound(key) => write!(f, "{} not found", key),
+            UpdateError::Closed(order) => {
+                write!(f, "order {} is {}", order.id, order.status.as_str())
+            }
+            UpdateError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+impl From<OrderError> for UpdateError {
+    fn from(e: OrderError) -> Self {
+        UpdateError::Invalid(e)
+    }
+}
+
+impl OrderError {
+    fn new(field: &'static str, reason: impl Into<String>) -> OrderError {
+        OrderError {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl NewOrder {
+    /// Time in force of the order when it is valid
+    fn validate(&self) -> Result<TimeInForce, OrderError> {
+        let client_id_chars = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if let Some(client_id) = &self.client_order_id
+            && (client_id.is_empty()
+                || client_id.len() > MAX_CLIENT_ORDER_ID_LENGTH
+                || !client_id.chars().all(client_id_chars))
+        {
+            let reason = format!(
+                "must be 1 to {} letters, digits, `-` or `_`",
+                MAX_CLIENT_ORDER_ID_LENGTH
+            );
+            return Err(OrderError::new("client_order_id", reason));
+        }
+        validate_amount("quantity", self.quantity)?;
+        match (self.order_type, self.price) {
+            (OrderType::Limit, Some(price)) => validate_amount("price", price)?,
+            (OrderType::Limit, None) => {
+                return Err(OrderError::new("price", "limit orders need a price"));
+            }
+            (OrderType::Market, Some(_)) => {
+                return Err(OrderError::new("price", "market orders have no price"));
+            }
+            (OrderType::Market, None) => {}
+        }
+        match (self.order_type, self.time_in_force) {
+            (OrderType::Limit, None) => Ok(TimeInForce::GoodTillCancelled),
+            (OrderType::Market, None) => Ok(TimeInForce::ImmediateOrCancel),
+            (OrderType::Market, Some(TimeInForce::GoodTillCancelled)) => Err(OrderError::new(
+                "time_in_force",
+                "market orders can't rest in the book",
+            )),
+            (_, Some(time_in_force)) => Ok(time_in_force),
+        }
+    }
+}
+
+/// Checks a price or quantity is positive and within the engine's range
+pub(crate) fn validate_amount(field: &'static str, amount: Decimal) -> Result<(), OrderError> {
+    if amount <= Decimal::ZERO {
+        return Err(OrderError::new(field, "must be positive"));
+    }
+    if amount >= MAX_VALUE {
+        return Err(OrderError::new(
+            field,
+            format!("must be less than {}", MAX_VALUE),
+        ));
+    }
+    if amount.normalize().exponent() < -MAX_DECIMAL_PLACES {
+        let reason = format!("must have at most {} decimal places", MAX_DECIMAL_PLACES);
+        return Err(OrderError::new(field, reason));
+    }
+    Ok(())
+}
+
+impl Order {
+    pub(crate) fn remaining(&self) -> Decimal {
+        self.quantity - self.filled
+    }
+
+    /// Whether the order trades with one resting at `price`
+    fn crosses(&self, price: Decimal) -> bool {
+        match (self.price, self.side) {
+            (None, _) => true,
+            (Some(limit), Side::Buy) => price <= limit,
+            (Some(limit), Side::Sell) => price >= limit,
+        }
+    }
+
+    fn fill(&mut self, quantity: Decimal, time: SystemTime) {
+        self.filled = self.filled + quantity;
+        self.status = match self.remaining().is_zero() {
+            true => OrderStatus::Filled,
+            false => OrderStatus::PartiallyFilled,
+        };
+        self.updated_at = time;
+    }
+}
+
+/// Books of every symbol and the orders in them, see the module docs
+#[derive(Debug)]
+pub(crate) struct Engine {
+    books: HashMap<String, Book>,
+    /// Orders in the books
+    pool: OrderPool,
+    /// Orders out of the books, the newest `history_capacity` of them
+    history: BTreeMap<OrderId, Order>,
+    history_capacity: usize,
+    markets: HashMap<String, Market>,
+    /// Newest order of each account with each client order ID
+    client_ids: HashMap<(AccountId, String), OrderId>,
+    listeners: Vec<Arc<dyn TradeListener>>,
+    last_order_id: OrderId,
+    last_trade_id: u64,
+}
+
+impl Engine {
+    /// Engine taking orders for the markets
+    pub(crate) fn new(markets: impl IntoIterator<Item = Market>) -> Engine {
+        let mut engine = Engine {
+            books: HashMap::new(),
+            pool: OrderPool::default(),
+            history: BTreeMap::new(),
+            history_capacity: ORDER_HISTORY,
+            markets: HashMap::new(),
+            client_ids: HashMap::new(),
+            listeners: vec![],
+            last_order_id: 0,
+            last_trade_id: 0,
+        };
+        for market in markets {
+            engine.list(market);
+        }
+        engine
+    }
+
+    /// Lets the listener see the trades from now on
+    pub(crate) fn subscribe(&mut self, listener: Arc<dyn TradeListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Numbers the trades from now on after `last`, the last trade of an
+    /// earlier run
+    pub(crate) fn continue_trades_after(&mut self, last: u64) {
+        self.last_trade_id = self.last_trade_id.max(last);
+    }
+
+    /// Takes orders for the market from now on, or changes its steps or
+    /// status, the orders in its book stay as they are
+    pub(crate) fn list(&mut self, market: Market) {
+        self.markets.insert(market.symbol.clone(), market);
+    }
+
+    /// Matches the order against the book of its symbol, returns it as it is
+    /// after matching with the trades it made
+    pub(crate) fn submit(&mut self, new: NewOrder) -> Result<(Order, Vec<Trade>), SubmitError> {
+        let time_in_force = new.validate()?;
+        self.check_market(&new.symbol, new.price, new.quantity)?;
+        if let Some(client_order_id) = &new.client_order_id
+            && let Some(&order_id) = self
+                .client_ids
+                .get(&(new.account_id, client_order_id.clone()))
+            && self.pool.slot(order_id).is_some()
+        {
+            return Err(SubmitError::DuplicateClientOrderId {
+                client_order_id: client_order_id.clone(),
+                order_id,
+            });
+        }
+        let now = SystemTime::now();
+        self.last_order_id += 1;
+        let mut order = Order {
+            id: self.last_order_id,
+            symbol: new.symbol,
+            side: new.side,
+            order_type: new.order_type,
+            price: new.price,
+            quantity: new.quantity,
+            filled: Decimal::ZERO,
+            time_in_force,
+            status: OrderStatus::Open,
+            client_order_id: new.client_order_id,
+            account_id: new.account_id,
+            created_at: now,
+            updated_at: now,
+        };
+        if time_in_force == TimeInForce::FillOrKill {
+            let book = self.books.entry(order.symbol.clone()).or_default();
+            let mut available = Decimal::ZERO;
+            for (&price, slots) in book.levels(order.side.opposite()) {
+                if !order.crosses(price) || available >= order.quantity {
+                    break;
+                }
+                for &slot in slots {
+                    available = available + self.pool[slot].remaining();
+                }
+            }
+            if available < order.quantity {
+                order.status = OrderStatus::Expired;
+                self.accept(order.clone());
+                return Ok((order, vec![]));
+            }
+        }
+        let trades = self.execute(&mut order, now);
+        self.accept(order.clone());
+        Ok((order, trades))
+    }
+
+    /// Keeps a new order
+    fn accept(&mut self, order: Order) {
+        if let Some(client_order_id) = &order.client_order_id {
+            let key = (order.account_id, client_order_id.clone());
+            self.client_ids.insert(key, order.id);
+        }
+        self.keep(order);
+    }
+
+    /// Rests an order in the book of its symbol while it is open, keeps it in
+    /// the history otherwise
+    fn keep(&mut self, order: Order) {
+        if !order.status.is_open() {
+            self.archive(order);
+            return;
+        }
+        let (side, price) = (order.side, order.price.expect("open orders have a price"));
+        let book = self.books.entry(order.symbol.clone()).or_default();
+        book.insert(side, price, self.pool.insert(order));
+    }
+
+    /// Keeps an order out of the book in the history, forgets the oldest one
+    /// when it is full
+    fn archive(&mut self, order: Order) {
+        self.history.insert(order.id, order);
+        if self.history.len() > self.history_capacity
+            && let Some((id, forgotten)) = self.history.pop_first()
+            && let Some(client_order_id) = forgotten.client_order_id
+        {
+            let key = (forgotten.account_id, client_order_id);
+            if self.client_ids.get(&key) == Some(&id) {
+                self.client_ids.remove(&key);
+            }
+        }
+    }
+
+    /// Order of the ID, in a book or in the history
+    pub(crate) fn get(&self, id: OrderId) -> Option<&Order> {
+        match self.pool.slot(id) {
+            Some(slot) => Some(&self.pool[slot]),
+            None => self.history.get(&id),
+        }
+    }
+
+    /// ID of the order of the account the key names, that of the account's
+    /// newest order with a client order ID
+    pub(crate) fn find(
+        &self,
+        account_id: AccountId,
+        key: &OrderKey,
+    ) -> Result<OrderId, UpdateError> {
+        let id = match key {
+            OrderKey::Id(id) => Some(*id).filter(|&id| {
+                self.get(id)
+                    .is_some_and(|order| order.account_id == account_id)
+            }),
+            OrderKey::Client(client_id) => self
+                .client_ids
+                .get(&(account_id, client_id.clone()))
+                .copied(),
+        };
+        id.ok_or_else(|| UpdateError::NotFound(key.clone()))
+    }
+
+    /// Cancels an order in the book, returns it as cancelled
+    pub(crate) fn cancel(&mut self, id: OrderId) -> Result<Order, UpdateError> {
+        let slot = self.open_slot(id)?;
+        let mut order = self.pool.remove(slot);
+        self.unbook(&order, slot);
+        order.status = OrderStatus::Cancelled;
+        order.updated_at = SystemTime::now();
+        self.archive(order.clone());
+        Ok(order)
+    }
+
+    /// Cancels every order of the account in the book, of the symbol and side
+    /// when given, returns the IDs of the orders cancelled
+    ///
+    /// Only the account's orders in the book are gone over, see
+    /// [`OrderPool`], not the orders of other accounts or out of the books.
+    pub(crate) fn cancel_all(
+        &mut self,
+        account_id: AccountId,
+        symbol: Option<&str>,
+        side: Option<Side>,
+    ) -> Vec<OrderId> {
+        let ids: Vec<_> = self
+            .pool
+            .orders(account_id, 0)
+            .filter(|order| {
+                symbol.is_none_or(|symbol| symbol == order.symbol)
+                    && side.is_none_or(|side| side == order.side)
+            })
+            .map(|order| order.id)
+            .collect();
+        for &id in &ids {
+            self.cancel(id)
+                .expect("orders in the book can be cancelled");
+        }
+        ids
+    }
+
+    /// Changes the price or quantity of an order in the book, see the module
+    /// docs, returns it as amended with the trades it made when moved
+    pub(crate) fn amend(
+        &mut self,
+        id: OrderId,
+        amend: Amend,
+    ) -> Result<(Order, Vec<Trade>), UpdateError> {
+        let slot = self.open_slot(id)?;
+        let mut order = self.pool[slot].clone();
+        let price = amend
+            .price
+            .or(order.price)
+            .expect("orders in the book have a price");
+        let quantity = amend.quantity.unwrap_or(order.quantity);
+        validate_amount("price", price)?;
+        validate_amount("quantity", quantity)?;
+        if quantity <= order.filled {
+            let reason = format!("must be above the filled quantity {}", order.filled);
+            return Err(OrderError::new("quantity", reason).into());
+        }
+        self.check_market(&order.symbol, Some(price), quantity)?;
+        let now = SystemTime::now();
+        let keeps_priority = Some(price) == order.price && quantity <= order.quantity;
+        if keeps_priority {
+            self.books
+                .get_mut(&order.symbol)
+                .expect("orders in the book have one")
+                .changed();
+            order.quantity = quantity;
+            order.updated_at = now;
+            self.pool[slot] = order.clone();
+            return Ok((order, vec![]));
+        }
+        self.unbook(&order, slot);
+        self.pool.remove(slot);
+        order.price = Some(price);
+        order.quantity = quantity;
+        order.updated_at = now;
+        let trades = self.execute(&mut order, now);
+        self.keep(order.clone());
+        Ok((order, trades))
+    }
+
+    pub(crate) fn order(
+        &self,
+        account_id: AccountId,
+        key: &OrderKey,
+    ) -> Result<&Order, UpdateError> {
+        let id = self.find(account_id, key)?;
+        Ok(self.get(id).expect("orders found are kept"))
+    }
+
+    /// Page of the orders matching the filter, see [`OrderPage`]
+    pub(crate) fn orders(&self, filter: &OrderFilter) -> OrderPage {
+        let after = filter.after.map_or(0, |after| after.saturating_add(1));
+        let mut open = self.pool.orders(filter.account_id, after).peekable();
+        let mut closed = self
+            .history
+            .range(after..)
+            .map(|(_, order)| order)
+            .peekable();
+        // Both by ID, oldest first
+        let orders = std::iter::from_fn(|| match (open.peek(), closed.peek()) {
+            (Some(order), Some(other)) if order.id > other.id => closed.next(),
+            (Some(_), _) => open.next(),
+            (None, _) => closed.next(),
+        });
+        let mut matching = orders.filter(|order| {
+            order.account_id == filter.account_id
+                && filter
+                    .symbol
+                    .as_ref()
+                    .is_none_or(|symbol| *symbol == order.symbol)
+                && filter
+                    .open
+                    .is_none_or(|open| open == order.status.is_open())
+        });
+        let orders: Vec<_> = matching.by_ref().take(filter.limit).cloned().collect();
+        let next = match matching.next() {
+            Some(_) => orders.last().map(|order| order.id),
+            None => None,
+        };
+        OrderPage { orders, next }
+    }
+
+    /// First `limit` price levels of each side of the book of the symbol,
+    /// those of each multiple of `aggregation` when given, the bids at or
+    /// above it and the asks at or below it, so they don't cross
+    pub(crate) fn depth(&self, symbol: &str, limit: usize, aggregation: Option<Decimal>) -> Depth {
+        let Some(book) = self.books.get(symbol) else {
+            return Depth::default();
+        };
+        let levels = |side: Side| {
+            let mut levels: Vec<(Decimal, Decimal)> = vec![];
+            for (&price, slots) in book.levels(side) {
+                let price = match (aggregation, side) {
+                    (None, _) => price,
+                    (Some(step), Side::Buy) => price.floor_to(step).expect("prices are in range"),
+                    (Some(step), Side::Sell) => price.ceil_to(step).expect("prices are in range"),
+                };
+                let quantity = slots.iter().fold(Decimal::ZERO, |sum, &slot| {
+                    sum + self.pool[slot].remaining()
+                });
+                if let Some((last, sum)) = levels.last_mut()
+                    && *last == price
+                {
+                    *sum = *sum + quantity;
+                } else if levels.len() == limit {
+                    break;
+                } else {
+                    levels.push((price, quantity));
+                }
+            }
+            levels
+        };
+        Depth {
+            sequence: book.sequence(),
+            bids: levels(Side::Buy),
+            asks: levels(Side::Sell),
+        }
+    }
+
+    /// Checks an order fits the market of its symbol, see
+    /// [`markets`](crate::markets)
+    fn check_market(
+        &self,
+        symbol: &str,
+        price: Option<Decimal>,
+        quantity: Decimal,
+    ) -> Result<(), OrderError> {
+        let market = self
+            .markets
+            .get(symbol)
+            .ok_or_else(|| OrderError::new("symbol", format!("no market `{}`", symbol)))?;
+        if market.status != MarketStatus::Trading {
+            let reason = format!("market `{}` is {}", symbol, market.status.as_str());
+            return Err(OrderError::new("symbol", reason));
+        }
+        if let Some(price) = price
+            && !price.is_multiple_of(market.tick_size)
+        {
+            let reason = format!("must be a multiple of the tick size {}", market.tick_size);
+            return Err(OrderError::new("price", reason));
+        }
+        if !quantity.is_multiple_of(market.lot_size) {
+            let reason = format!("must be a multiple of the lot size {}", market.lot_size);
+            return Err(OrderError::new("quantity", reason));
+        }
+        if let Some(price) = price
+            && !notional_reaches(price, quantity, market.min_notional)
+        {
+            let reason = format!(
+                "price times quantity must be at least {}",
+                market.min_notional
+            );
+            return Err(OrderError::new("quantity", reason));
+        }
+        Ok(())
+    }
+
+    /// Slot of the order of the ID when it is in the book
+    fn open_slot(&self, id: OrderId) -> Result<Slot, UpdateError> {
+        if let Some(slot) = self.pool.slot(id) {
+            return Ok(slot);
+        }
+        match self.history.get(&id) {
+            Some(order) => Err(UpdateError::Closed(Box::new(order.clone()))),
+            None => Err(UpdateError::NotFound(OrderKey::Id(id))),
+        }
+    }
+
+    /// Takes an order in the book out of it
+    fn unbook(&mut self, order: &Order, slot: Slot) {
+        let price = order.price.expect("orders in the book have a price");
+        self.books
+            .get_mut(&order.symbol)
+            .expect("orders in the book have one")
+            .remove(order.side, price, slot);
+    }
+
+    /// Matches the order against the book of its symbol and expires what is
+    /// left of it unless it is good till cancelled, see [`Engine::keep`]
+    fn execute(&mut self, order: &mut Order, now: SystemTime) -> Vec<Trade> {
+        let book = self.books.entry(order.symbol.clone()).or_default();
+        let opposite = order.side.opposite();
+        let mut trades = vec![];
+        let mut filled = vec![];
+        while !order.remaining().is_zero() {
+            let Some(mut level) = book.best_mut(opposite) else {
+                break;
+            };
+            let price = *level.key();
+            if !order.crosses(price) {
+                break;
+            }
+            let slot = *level.get().front().expect("price levels are never empty");
+            let maker = &mut self.pool[slot];
+            let maker_id = maker.id;
+            let quantity = order.remaining().min(maker.remaining());
+            maker.fill(quantity, now);
+            order.fill(quantity, now);
+            if maker.remaining().is_zero() {
+                level.get_mut().pop_front();
+                if level.get().is_empty() {
+                    level.remove();
+                }
+                filled.push(slot);
+            }
+            book.changed();
+            self.last_trade_id += 1;
+            let trade = Trade {
+                id: self.last_trade_id,
+                symbol: order.symbol.clone(),
+                price,
+                quantity,
+                maker_order_id: maker_id,
+                taker_order_id: order.id,
+                taker_side: order.side,
+                time: now,
+            };
+            for listener in &self.listeners {
+                listener.trade(&trade);
+            }
+            trades.push(trade);
+        }
+
+        for slot in filled {
+            let maker = self.pool.remove(slot);
+            self.archive(maker);
+        }
+        let rests = order.time_in_force == TimeInForce::GoodTillCancelled && order.price.is_some();
+        if !order.remaining().is_zero() && !rests {
+            order.status = OrderStatus::Expired;
+        }
+        trades
+    }
+}
+
+/// Whether `price * quantity >= min`, exactly, for amounts which passed
+/// [`validate_amount`]
+fn notional_reaches(price: Decimal, quantity: Decimal, min: Decimal) -> bool {
+    // Whole units of 10^-8, below 10^18 each
+    let units = |amount: Decimal| {
+        amount
+            .rescale(-MAX_DECIMAL_PLACES)
+            .map_or(i128::MAX, |amount| amount.mantissa() as i128)
+    };
+    let scale = 10i128.pow(MAX_DECIMAL_PLACES as u32);
+    units(price).saturating_mul(units(quantity)) >= units(min).saturating_mul(scale)
+}
+
+/// Work for the engine thread
+type Command = Box<dyn FnOnce(&mut Engine) + Send>;
+
+/// Engine running on a thread of its own, see the module docs
+#[derive(Debug)]
+pub(crate) struct EngineHandle {
+    commands: mpsc::Sender<Command>,
+    thread: JoinHandle<()>,
+}
+
+impl EngineHandle {
+    /// Starts a thread running the engine until the handle is dropped
+    pub(crate) fn spawn(mut engine: Engine) -> io::Result<EngineHandle> {
+        let (commands, received) = mpsc::channel::<Command>();
+        let thread = thread::Builder::new()
+            .name(String::from("matching-engine"))
+            .spawn(move || {
+                for command in received {
+                    command(&mut engine);
+                }
+            })?;
+        Ok(EngineHandle { commands, thread })
+    }
+
+    /// Result of `f` run on the engine after everything sent before it,
+    /// `None` when the engine thread has stopped
+    pub(crate) fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Engine) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (reply, result) = mpsc::sync_channel(1);
+        let command: Command = Box::new(move |engine| {
+            let _ = reply.send(f(engine));
+        });
+        self.commands.send(command).ok()?;
+        result.recv().ok()
+    }
+
+    /// Whether the engine thread is still running, it stops when a command
+    /// panics
+    pub(crate) fn check(&self) -> Result<(), String> {
+        match self.thread.is_finished() {
+            true => Err(String::from("matching engine thread has stopped")),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: Side, price: &str, quantity: &str) -> NewOrder {
+        NewOrder {
+            symbol: String::from("XGAL-USD"),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price.parse().unwrap()),
+            quantity: quantity.parse().unwrap(),
+            time_in_force: None,
+            client_order_id: None,
+            account_id: 1,
+        }
+    }
+
+    fn market(side: Side, quantity: &str) -> NewOrder {
+        NewOrder {
+            order_type: OrderType::Market,
+            price: None,
+            ..order(side, "1", quantity)
+        }
+    }
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn listed(symbol: &str) -> Market {
+        let (base, quote) = symbol.split_once('-').unwrap();
+        Market {
+            symbol: symbol.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            tick_size: Decimal::new(1, -MAX_DECIMAL_PLACES),
+            lot_size: Decimal::new(1, -MAX_DECIMAL_PLACES),
+            min_notional: Decimal::ZERO,
+            status: MarketStatus::Trading,
+        }
+    }
+
+    /// Engine with the markets of the tests, taking any amount it can
+    fn engine() -> Engine {
+        Engine::new([listed("XGAL-USD"), listed("XORB-USD")])
+    }
+
+    #[test]
+    fn price_time_priority() {
+        let mut engine = engine();
+        let (first, _) = engine.submit(order(Side::Sell, "101", "5")).unwrap();
+        let (second, _) = engine.submit(order(Side::Sell, "100.5", "3")).unwrap();
+        let (third, _) = engine.submit(order(Side::Sell, "100.5", "4")).unwrap();
+        assert_eq!((first.id, first.status), (1, OrderStatus::Open));
+
+        let (buy, trades) = engine.submit(order(Side::Buy, "101", "10")).unwrap();
+        let matched: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_order_id, trade.price, trade.quantity))
+            .collect();
+        assert_eq!(
+            matched,
+            [
+                (second.id, d("100.5"), d("3")),
+                (third.id, d("100.5"), d("4")),
+                (first.id, d("101"), d("3")),
+            ]
+        );
+        assert!(trades.iter().all(|trade| trade.taker_order_id == buy.id));
+        assert_eq!((buy.status, buy.filled), (OrderStatus::Filled, d("10")));
+        let first = engine.get(first.id).unwrap();
+        assert_eq!(first.status, OrderStatus::PartiallyFilled);
+        assert_eq!(first.remaining(), d("2"));
+        assert_eq!(engine.get(third.id).unwrap().status, OrderStatus::Filled);
+
+        // Nothing left at or below 100
+        let (bid, trades) = engine.submit(order(Side::Buy, "100", "1")).unwrap();
+        assert!(trades.is_empty());
+        assert_eq!(bid.status, OrderStatus::Open);
+        let (_, trades) = engine.submit(order(Side::Sell, "99", "2")).unwrap();
+        assert_eq!((trades[0].price, trades[0].quantity), (d("100"), d("1")));
+    }
+
+    #[test]
+    fn time_in_force() {
+        let mut engine = engine();
+        engine.submit(order(Side::Sell, "10", "2")).unwrap();
+        engine.submit(order(Side::Sell, "11", "2")).unwrap();
+
+        let fok = NewOrder {
+            time_in_force: Some(TimeInForce::FillOrKill),
+            ..order(Side::Buy, "10", "3")
+        };
+        let (killed, trades) = engine.submit(fok.clone()).unwrap();
+        assert_eq!((killed.status, trades.len()), (OrderStatus::Expired, 0));
+
+        let ioc = NewOrder {
+            time_in_force: Some(TimeInForce::ImmediateOrCancel),
+            ..fok
+        };
+        let (expired, trades) = engine.submit(ioc).unwrap();
+        assert_eq!(
+            (expired.status, expired.filled),
+            (OrderStatus::Expired, d("2"))
+        );
+        assert_eq!(trades.len(), 1);
+
+        let (bought, trades) = engine.submit(market(Side::Buy, "5")).unwrap();
+        assert_eq!(
+            (bought.status, bought.filled),
+            (OrderStatus::Expired, d("2"))
+        );
+        assert_eq!(trades[0].price, d("11"));
+        let (unfilled, _) = engine.submit(market(Side::Sell, "1")).unwrap();
+        assert_eq!(unfilled.status, OrderStatus::Expired);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut engine = engine();
+        let (resting, _) = engine.submit(order(Side::Sell, "10", "2")).unwrap();
+        let cancelled = engine.cancel(resting.id).unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        let (buy, trades) = engine.submit(order(Side::Buy, "10", "1")).unwrap();
+        assert!(trades.is_empty());
+
+        // Too late once it left the book
+        assert_eq!(
+            engine.cancel(resting.id),
+            Err(UpdateError::Closed(Box::new(cancelled)))
+        );
+        engine.submit(order(Side::Sell, "10", "1")).unwrap();
+        let error = engine.cancel(buy.id).unwrap_err();
+        assert_eq!(error.to_string(), format!("order {} is filled", buy.id));
+        assert_eq!(
+            engine.cancel(99),
+            Err(UpdateError::NotFound(OrderKey::Id(99)))
+        );
+    }
+
+    #[test]
+    fn cancel_all() {
+        let mut engine = engine();
+        for side in [Side::Buy, Side::Sell, Side::Buy] {
+            let price = if side == Side::Buy { "9" } else { "10" };
+            engine.submit(order(side, price, "1")).unwrap();
+        }
+        let other = NewOrder {
+            symbol: String::from("XORB-USD"),
+            ..order(Side::Buy, "9", "1")
+        };
+        engine.submit(other).unwrap();
+        let of_other_account = NewOrder {
+            account_id: 2,
+            ..order(Side::Buy, "8", "1")
+        };
+        engine.submit(of_other_account).unwrap();
+
+        assert_eq!(
+            engine.cancel_all(1, Some("XGAL-USD"), Some(Side::Buy)),
+            [1, 3]
+        );
+        assert_eq!(engine.cancel_all(1, Some("XGAL-USD"), None), [2]);
+        assert_eq!(engine.cancel_all(1, None, None), [4]);
+        assert_eq!(engine.cancel_all(1, None, None), Vec::<OrderId>::new());
+        assert_eq!(engine.pool.orders(2, 0).count(), 1);
+        assert_eq!(engine.cancel_all(2, None, None), [5]);
+        assert_eq!(engine.pool.orders(2, 0).count(), 0);
+        let (_, trades) = engine.submit(market(Side::Sell, "1")).unwrap();
+        assert!(trades.is_empty());
+    }
+
+    #[test]
+    fn amend() {
+        let mut engine = engine();
+        let (first, _) = engine.submit(order(Side::Sell, "10", "5")).unwrap();
+        let (second, _) = engine.submit(order(Side::Sell, "10", "5")).unwrap();
+        let quantity = |quantity: &str| Amend {
+            quantity: Some(d(quantity)),
+            ..Amend::default()
+        };
+
+        // Smaller, still first in the queue
+        let (amended, _) = engine.amend(first.id, quantity("4")).unwrap();
+        assert_eq!(amended.quantity, d("4"));
+        let (_, trades) = engine.submit(order(Side::Buy, "10", "1")).unwrap();
+        assert_eq!(trades[0].maker_order_id, first.id);
+
+        // Larger, behind the second now
+        engine.amend(first.id, quantity("6")).unwrap();
+        let (_, trades) = engine.submit(order(Side::Buy, "10", "6")).unwrap();
+        let makers: Vec<_> = trades.iter().map(|trade| trade.maker_order_id).collect();
+        assert_eq!(makers, [second.id, first.id]);
+        let first_now = engine.get(first.id).unwrap();
+        assert_eq!((first_now.filled, first_now.remaining()), (d("2"), d("4")));
+
+        // Not below what is filled already
+        let error = engine.amend(first.id, quantity("2")).unwrap_err();
+        assert!(matches!(
+            error,
+            UpdateError::Invalid(OrderError {
+                field: "quantity",
+                ..
+            })
+        ));
+
+        // Moved across the book, it trades
+        let (bid, _) = engine.submit(order(Side::Buy, "9", "1")).unwrap();
+        let price = Amend {
+            price: Some(d("9")),
+            ..Amend::default()
+        };
+        let (moved, trades) = engine.amend(first.id, price).unwrap();
+        assert_eq!(trades[0].maker_order_id, bid.id);
+        assert_eq!(
+            (trades[0].taker_order_id, trades[0].price),
+            (first.id, d("9"))
+        );
+        assert_eq!(
+            (moved.status, moved.remaining()),
+            (OrderStatus::PartiallyFilled, d("3"))
+        );
+        assert_eq!(
+            engine.cancel(first.id).unwrap().status,
+            OrderStatus::Cancelled
+        );
+        assert!(matches!(
+            engine.amend(first.id, quantity("9")),
+            Err(UpdateError::Closed(_))
+        ));
+    }
+
+    #[test]
+    fn client_order_ids() {
+        let mut engine = engine();
+        let named = |client_id: &str, side, price| NewOrder {
+            client_order_id: Some(client_id.to_string()),
+            ..order(side, price, "1")
+        };
+        let key = |client_id: &str| OrderKey::Client(client_id.to_string());
+        let (first, _) = engine.submit(named("a", Side::Sell, "10")).unwrap();
+        assert_eq!(first.client_order_id.as_deref(), Some("a"));
+        assert_eq!(engine.find(1, &key("a")), Ok(first.id));
+        assert_eq!(
+            engine.find(1, &key("b")),
+            Err(UpdateError::NotFound(key("b")))
+        );
+
+        // Unique among the orders in the book
+        assert_eq!(
+            engine.submit(named("a", Side::Sell, "11")),
+            Err(SubmitError::DuplicateClientOrderId {
+                client_order_id: String::from("a"),
+                order_id: first.id,
+            })
+        );
+        engine.submit(named("b", Side::Buy, "10")).unwrap();
+        let (second, _) = engine.submit(named("a", Side::Sell, "12")).unwrap();
+        assert_eq!(engine.find(1, &key("a")), Ok(second.id));
+        engine.cancel(second.id).unwrap();
+        let (third, _) = engine.submit(named("a", Side::Buy, "1")).unwrap();
+        assert_eq!(engine.find(1, &key("a")), Ok(third.id));
+        assert_eq!(
+            engine.find(1, &OrderKey::Id(99)),
+            Err(UpdateError::NotFound(OrderKey::Id(99)))
+        );
+
+        // Each account names its own orders
+        let of_other_account = NewOrder {
+            account_id: 2,
+            ..named("a", Side::Buy, "2")
+        };
+        let (fourth, _) = engine.submit(of_other_account).unwrap();
+        assert_eq!(engine.find(2, &key("a")), Ok(fourth.id));
+        assert_eq!(engine.find(1, &key("a")), Ok(third.id));
+        assert_eq!(
+            engine.find(2, &OrderKey::Id(third.id)),
+            Err(UpdateError::NotFound(OrderKey::Id(third.id)))
+        );
+    }
+
+    #[test]
+    fn pool_and_history() {
+        let mut engine = Engine {
+            history_capacity: 2,
+            ..engine()
+        };
+        // A slot is taken again once its order leaves the book
+        let (first, _) = engine.submit(order(Side::Sell, "10", "1")).unwrap();
+        let slot = engine.pool.slot(first.id).unwrap();
+        engine.cancel(first.id).unwrap();
+        assert_eq!(engine.pool.slot(first.id), None);
+        let (second, _) = engine.submit(order(Side::Sell, "11", "1")).unwrap();
+        assert_eq!(engine.pool.slot(second.id), Some(slot));
+        let (_, trades) = engine.submit(order(Side::Buy, "11", "1")).unwrap();
+        assert_eq!(trades[0].maker_order_id, second.id);
+
+        // The oldest orders out of the book are forgotten, their client order
+        // IDs with them
+        assert_eq!(engine.get(first.id), None);
+        assert_eq!(engine.get(second.id).unwrap().status, OrderStatus::Filled);
+        let named = NewOrder {
+            client_order_id: Some(String::from("a")),
+            ..market(Side::Buy, "1")
+        };
+        let (expired, _) = engine.submit(named.clone()).unwrap();
+        engine.submit(market(Side::Buy, "1")).unwrap();
+        let key = OrderKey::Client(String::from("a"));
+        assert_eq!(engine.order(1, &key).unwrap().id, expired.id);
+        engine.submit(market(Side::Buy, "1")).unwrap();
+        assert_eq!(
+            engine.order(1, &key),
+            Err(UpdateError::NotFound(key.clone()))
+        );
+        assert_eq!(
+            engine.cancel(expired.id),
+            Err(UpdateError::NotFound(OrderKey::Id(expired.id)))
+        );
+        let all = OrderFilter {
+            account_id: 1,
+            limit: 10,
+            ..OrderFilter::default()
+        };
+        assert_eq!(engine.orders(&all).orders.len(), 2);
+    }
+
+    #[test]
+    fn markets() {
+        let mut engine = engine();
+        engine.list(Market {
+            tick_size: d("0.05"),
+            lot_size: d("0.1"),
+            min_notional: d("10"),
+            ..listed("XGAL-USD")
+        });
+        let error = |engine: &mut Engine, order| match engine.submit(order) {
+            Err(SubmitError::Invalid(e)) => e.to_string(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            error(&mut engine, order(Side::Buy, "10.01", "1")),
+            "`price`: must be a multiple of the tick size 0.05"
+        );
+        assert_eq!(
+            error(&mut engine, order(Side::Buy, "10", "1.05")),
+            "`quantity`: must be a multiple of the lot size 0.1"
+        );
+        assert_eq!(
+            error(&mut engine, order(Side::Buy, "10.05", "0.9")),
+            "`quantity`: price times quantity must be at least 10"
+        );
+        let (resting, _) = engine.submit(order(Side::Buy, "10", "1")).unwrap();
+        engine.submit(market(Side::Sell, "0.1")).unwrap();
+        let unlisted = NewOrder {
+            symbol: String::from("XNEB-USD"),
+            ..order(Side::Buy, "10", "1")
+        };
+        assert_eq!(
+            error(&mut engine, unlisted),
+            "`symbol`: no market `XNEB-USD`"
+        );
+
+        engine.list(Market {
+            status: MarketStatus::Halted,
+            ..listed("XGAL-USD")
+        });
+        assert_eq!(
+            error(&mut engine, order(Side::Buy, "10", "1")),
+            "`symbol`: market `XGAL-USD` is halted"
+        );
+        let amend = Amend {
+            quantity: Some(d("2")),
+            ..Amend::default()
+        };
+        assert!(matches!(
+            engine.amend(resting.id, amend),
+            Err(UpdateError::Invalid(_))
+        ));
+        assert_eq!(
+            engine.cancel(resting.id).unwrap().status,
+            OrderStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let mut engine = engine();
+        for (side, price, quantity) in [
+            (Side::Buy, "9.9", "1"),
+            (Side::Buy, "9.7", "2"),
+            (Side::Buy, "9.2", "3"),
+            (Side::Sell, "10.1", "1"),
+            (Side::Sell, "10.1", "1.5"),
+            (Side::Sell, "10.6", "4"),
+        ] {
+            engine.submit(order(side, price, quantity)).unwrap();
+        }
+        let levels = |levels: &[(Decimal, Decimal)]| {
+            levels
+                .iter()
+                .map(|(price, quantity)| format!("{}x{}", price, quantity))
+                .collect::<Vec<_>>()
+        };
+        let depth = engine.depth("XGAL-USD", 2, None);
+        assert_eq!(levels(&depth.bids), ["9.9x1", "9.7x2"]);
+        assert_eq!(levels(&depth.asks), ["10.1x2.5", "10.6x4"]);
+        let aggregated = engine.depth("XGAL-USD", 10, Some(d("0.5")));
+        assert_eq!(levels(&aggregated.bids), ["9.5x3", "9.0x3"]);
+        assert_eq!(levels(&aggregated.asks), ["10.5x2.5", "11.0x4"]);
+        assert_eq!(aggregated.sequence, depth.sequence);
+
+        // Fills move the sequence like orders resting and leaving do
+        engine.submit(market(Side::Buy, "1")).unwrap();
+        let filled = engine.depth("XGAL-USD", 1, None);
+        assert_eq!(levels(&filled.asks), ["10.1x1.5"]);
+        assert!(filled.sequence > depth.sequence);
+        assert_eq!(engine.depth("XORB-USD", 1, None), Depth::default());
+    }
+
+    #[test]
+    fn list_orders() {
+        let mut engine = engine();
+        for _ in 0..3 {
+            engine.submit(order(Side::Sell, "10", "1")).unwrap();
+        }
+        let other = NewOrder {
+            symbol: String::from("XORB-USD"),
+            ..order(Side::Sell, "10", "1")
+        };
+        engine.submit(other).unwrap();
+        engine.submit(market(Side::Buy, "1")).unwrap();
+
+        let ids = |page: &OrderPage| page.orders.iter().map(|order| order.id).collect::<Vec<_>>();
+        let mut filter = OrderFilter {
+            account_id: 1,
+            symbol: Some(String::from("XGAL-USD")),
+            open: Some(true),
+            after: None,
+            limit: 1,
+        };
+        let page = engine.orders(&filter);
+        assert_eq!((ids(&page), page.next), (vec![2], Some(2)));
+        filter.after = page.next;
+        let page = engine.orders(&filter);
+        assert_eq!((ids(&page), page.next), (vec![3], None));
+
+        let closed = OrderFilter {
+            account_id: 1,
+            open: Some(false),
+            limit: 10,
+            ..OrderFilter::default()
+        };
+        assert_eq!(ids(&engine.orders(&closed)), [1, 5]);
+        let all = OrderFilter {
+            account_id: 1,
+            limit: 10,
+            ..OrderFilter::default()
+        };
+        assert_eq!(ids(&engine.orders(&all)), [1, 2, 3, 4, 5]);
+        assert_eq!(
+            engine.order(1, &OrderKey::Id(4)).unwrap().symbol,
+            "XORB-USD"
+        );
+        let of_other_account = OrderFilter {
+            account_id: 2,
+            ..all
+        };
+        assert!(engine.orders(&of_other_account).orders.is_empty());
+    }
+
+    #[test]
+    fn invalid_orders() {
+        let mut engine = engine();
+        let field = |engine: &mut Engine, order| match engine.submit(order) {
+            Err(SubmitError::Invalid(e)) => e.field,
+            other => panic!("{:?}", other),
+        };
+        let lowercase = NewOrder {
+            symbol: String::from("xgal"),
+            ..order(Side::Buy, "1", "1")
+        };
+        assert_eq!(field(&mut engine, lowercase), "symbol");
+        assert_eq!(field(&mut engine, order(Side::Buy, "1", "0")), "quantity");
+        assert_eq!(field(&mut engine, order(Side::Buy, "-1", "1")), "price");
+        assert_eq!(
+            field(&mut engine, order(Side::Buy, "0.000000001", "1")),
+            "price"
+        );
+        assert_eq!(
+            field(&mut engine, order(Side::Buy, "1", "10000000000")),
+            "quantity"
+        );
+        let no_price = NewOrder {
+            price: None,
+            ..order(Side::Buy, "1", "1")
+        };
+        assert_eq!(field(&mut engine, no_price), "price");
+        let spaced_client_id = NewOrder {
+            client_order_id: Some(String::from("my order")),
+            ..order(Side::Buy, "1", "1")
+        };
+        assert_eq!(field(&mut engine, spaced_client_id), "client_order_id");
+        let resting_market = NewOrder {
+            time_in_force: Some(TimeInForce::GoodTillCancelled),
+            ..market(Side::Buy, "1")
+        };
+        let error = engine.submit(resting_market).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "`time_in_force`: market orders can't rest in the book"
+        );
+        // Rejected orders take no ID
+        assert_eq!(engine.submit(order(Side::Buy, "1", "1")).unwrap().0.id, 1);
+    }
+
+    #[test]
+    fn listeners() {
+        #[derive(Debug, Default)]
+        struct Recorder(std::sync::Mutex<Vec<u64>>);
+
+        impl TradeListener for Recorder {
+            fn trade(&self, trade: &Trade) {
+                self.0.lock().unwrap().push(trade.id);
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut engine = engine();
+        engine.subscribe(recorder.clone());
+        engine.continue_trades_after(41);
+        engine.submit(order(Side::Sell, "10", "1")).unwrap();
+        engine.submit(order(Side::Sell, "11", "1")).unwrap();
+        let (_, trades) = engine.submit(market(Side::Buy, "2")).unwrap();
+        assert_eq!(*recorder.0.lock().unwrap(), [42, 43]);
+        assert_eq!(trades.len(), 2);
+    }
+
+    #[test]
+    fn handle() {
+        let handle = EngineHandle::spawn(engine()).unwrap();
+        let (order, _) = handle
+            .call(|engine| engine.submit(order(Side::Buy, "1", "1")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            handle.call(move |engine| engine.get(order.id).cloned()),
+            Some(Some(order))
+        );
+        assert_eq!(handle.check(), Ok(()));
+
+        let _ = handle.call(|_| panic!("engine bug"));
+        assert_eq!(handle.call(|engine| engine.last_order_id), None);
+        while handle.check().is_ok() {
+            thread::yield_now();
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Order book of a symbol, the orders resting at each price in the order they
+//! arrived
+
+use std::collections::{BTreeMap, VecDeque, btree_map::OccupiedEntry};
+
+use super::{Side, pool::Slot};
+use crate::decimal::Decimal;
+
+/// Resting orders by side and price, the orders themselves are kept in the
+/// [`OrderPool`](super::pool::OrderPool) by the [`Engine`](super::Engine)
+#[derive(Debug, Default)]
+pub(crate) struct Book {
+    bids: BTreeMap<Decimal, VecDeque<Slot>>,
+    asks: BTreeMap<Decimal, VecDeque<Slot>>,
+    /// Changes to the book so far, orders resting, leaving or filling in it
+    sequence: u64,
+}
+
+impl Book {
+    pub(crate) fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Counts a change the book doesn't see itself, an order in it filling or
+    /// shrinking
+    pub(crate) fn changed(&mut self) {
+        self.sequence += 1;
+    }
+
+    /// Rests the order behind the others at its price
+    pub(crate) fn insert(&mut self, side: Side, price: Decimal, slot: Slot) {
+        self.side_mut(side)
+            .entry(price)
+            .or_default()
+            .push_back(slot);
+        self.changed();
+    }
+
+    /// Takes the order out of its price level, dropping the level when it was
+    /// the last order in it
+    pub(crate) fn remove(&mut self, side: Side, price: Decimal, slot: Slot) {
+        let levels = self.side_mut(side);
+        if let Some(level) = levels.get_mut(&price) {
+            level.retain(|&queued| queued != slot);
+            if level.is_empty() {
+                levels.remove(&price);
+            }
+        }
+        self.changed();
+    }
+
+    /// Price levels of the side, best first, the highest bid or lowest ask
+    pub(crate) fn levels(
+        &self,
+        side: Side,
+    ) -> Box<dyn Iterator<Item = (&Decimal, &VecDeque<Slot>)> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bids.iter().rev()),
+            Side::Sell => Box::new(self.asks.iter()),
+        }
+    }
+
+    /// Best price level of the side, `None` when the side is empty
+    ///
+    /// Levels are never empty, whoever takes the last order of one removes it.
+    pub(crate) fn best_mut(
+        &mut self,
+        side: Side,
+    ) -> Option<OccupiedEntry<'_, Decimal, VecDeque<Slot>>> {
+        match side {
+            Side::Buy => self.bids.last_entry(),
+            Side::Sell => self.asks.first_entry(),
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Decimal, VecDeque<Slot>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
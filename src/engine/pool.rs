@@ -0,0 +1,96 @@
+//! Orders resting in the books, in slots which new orders take again once
+//! theirs leave, so the pool only grows with the most orders resting at once
+//!
+//! The orders of each account are indexed, so what an account has in the
+//! books is found without going over the orders of every other.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::{Index, IndexMut},
+};
+
+use super::{Order, OrderId};
+use crate::accounts::AccountId;
+
+/// Place of an order in the [`OrderPool`], which price levels refer to it by
+pub(crate) type Slot = usize;
+
+#[derive(Debug, Default)]
+pub(crate) struct OrderPool {
+    slots: Vec<Option<Order>>,
+    /// Slots without an order, the last freed first
+    free: Vec<Slot>,
+    /// Slot of each order, oldest first
+    ids: BTreeMap<OrderId, Slot>,
+    /// Orders of each account, oldest first
+    accounts: HashMap<AccountId, BTreeSet<OrderId>>,
+}
+
+impl OrderPool {
+    /// Keeps the order in a free slot, in a new one when there is none
+    pub(crate) fn insert(&mut self, order: Order) -> Slot {
+        let (id, account_id) = (order.id, order.account_id);
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(order);
+                slot
+            }
+            None => {
+                self.slots.push(Some(order));
+                self.slots.len() - 1
+            }
+        };
+        self.ids.insert(id, slot);
+        self.accounts.entry(account_id).or_default().insert(id);
+        slot
+    }
+
+    /// Takes the order out of its slot, which the next order may take
+    pub(crate) fn remove(&mut self, slot: Slot) -> Order {
+        let order = self.slots[slot]
+            .take()
+            .expect("slots removed from hold an order");
+        self.ids.remove(&order.id);
+        if let Some(ids) = self.accounts.get_mut(&order.account_id) {
+            ids.remove(&order.id);
+            if ids.is_empty() {
+                self.accounts.remove(&order.account_id);
+            }
+        }
+        self.free.push(slot);
+        order
+    }
+
+    pub(crate) fn slot(&self, id: OrderId) -> Option<Slot> {
+        self.ids.get(&id).copied()
+    }
+
+    /// Orders of the account with the ID `from` or a later one, oldest first
+    pub(crate) fn orders(
+        &self,
+        account_id: AccountId,
+        from: OrderId,
+    ) -> impl Iterator<Item = &Order> {
+        let ids = self.accounts.get(&account_id).into_iter();
+        ids.flat_map(move |ids| ids.range(from..))
+            .map(|id| &self[self.ids[id]])
+    }
+}
+
+impl Index<Slot> for OrderPool {
+    type Output = Order;
+
+    fn index(&self, slot: Slot) -> &Order {
+        self.slots[slot]
+            .as_ref()
+            .expect("slots in use hold an order")
+    }
+}
+
+impl IndexMut<Slot> for OrderPool {
+    fn index_mut(&mut self, slot: Slot) -> &mut Order {
+        self.slots[slot]
+            .as_mut()
+            .expect("slots in use hold an order")
+    }
+}
@@ -1,608 +1,6894 @@
 #![allow(dead_code)]
 
-use std::{collections::HashMap, fmt::Debug, hash::Hash};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::{Debug, Display},
+    hash::Hash,
+    io::{Read, Write},
+    sync::Arc,
+    time::Duration,
+};
 
-const VERSION1: u8 = 0x01;
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit,
+    aead::{Aead, Payload},
+};
+use hmac::{Hmac, Mac};
+use indexmap::{IndexMap, IndexSet};
+use num_bigint::{BigInt, Sign};
+use sha2::Sha256;
+
+use crate::{date::Date, decimal::Decimal};
+
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "benchmarking")]
+pub mod bench;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod bridge;
+mod capture;
+#[cfg(feature = "tokio")]
+mod codec;
+pub(crate) mod codegen;
+mod csv;
+mod dictionary;
+mod diff;
+mod explain;
+mod frame;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+#[cfg(any(feature = "proptest", feature = "arbitrary"))]
+mod generate;
+mod json;
+mod lazy;
+mod merge;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+mod rpc;
+pub(crate) mod schema;
+mod serde;
+mod text;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+
+#[cfg(feature = "arena")]
+#[allow(unused_imports)]
+pub(crate) use self::arena::{ArenaField, ArenaList, ArenaMessage, ArenaValue};
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+#[allow(unused_imports)]
+pub(crate) use self::bridge::BridgeError;
+#[cfg(feature = "tokio")]
+#[allow(unused_imports)]
+pub(crate) use self::codec::MessageCodec;
+#[cfg(any(feature = "proptest", feature = "arbitrary"))]
+#[allow(unused_imports)]
+pub(crate) use self::generate::Bounds;
+#[allow(unused_imports)]
+pub(crate) use self::{
+    capture::{CaptureError, CaptureReader, CaptureWriter},
+    csv::{CsvError, NestedValues, records_to_csv},
+    dictionary::Dictionary,
+    diff::{FieldDelta, Patch, PatchError, PatchOp},
+    explain::explain,
+    frame::FrameCodec,
+    json::JsonError,
+    lazy::{FieldValueRef, LazyFields},
+    merge::{ListMerge, MergeStrategy},
+    reader::{Decoder, MessageReader, ReadError},
+    rpc::{PendingCalls, RpcRequest, RpcResponse, RpcStatus},
+    serde::{from_slice, to_vec},
+    text::TextError,
+};
+
+pub(crate) const VERSION1: u8 = 0x01;
+/// Integers are encoded as zig-zag LEB128 varints, field counts take 2 bytes and
+/// the header has a message type, a sequence number and a 4 byte length
+pub(crate) const VERSION2: u8 = 0x02;
+/// Like version 2, but values are prefixed by their length, so readers can skip
+/// values of types they don't know yet
+pub(crate) const VERSION3: u8 = 0x03;
+/// Protocol versions this crate reads and writes
+pub(crate) const VERSIONS: [u8; 3] = [VERSION1, VERSION2, VERSION3];
 const INTEGER_T: u8 = 0x01;
 const STRING_T: u8 = 0x02;
 const LIST_T: u8 = 0x03;
 const OBJECT_T: u8 = 0x04;
+const FLOAT_T: u8 = 0x05;
+const BOOL_T: u8 = 0x06;
+const NULL_T: u8 = 0x07;
+const UUID_T: u8 = 0x08;
+const DECIMAL_T: u8 = 0x09;
+const COMPACT_INTEGER_T: u8 = 0x0A;
+/// List element type only, every element carries its own type
+const MIXED_T: u8 = 0x0B;
+const MAP_T: u8 = 0x0C;
+const MAP_KEY_TYPES: [u8; 4] = [INTEGER_T, STRING_T, UUID_T, DECIMAL_T];
+const ENUM_T: u8 = 0x0D;
+/// [Days since 1970-01-01 - 4 bytes]
+const DATE_T: u8 = 0x0E;
+/// [Nanoseconds - 8 bytes, unsigned]
+const DURATION_T: u8 = 0x0F;
+/// [Integer - 16 bytes]
+const INT128_T: u8 = 0x10;
+/// [Negative (1 byte)][Length (2 bytes)][Magnitude, big-endian]
+const BIGINT_T: u8 = 0x11;
+/// [Element Type (1 byte)][Element Count (2 bytes)][Element 1]...[Element N]
+const ARRAY_T: u8 = 0x12;
+/// Types a value can have, a reader keeps values of any other type as
+/// [`FieldValue::Unknown`] since version 3
+const VALUE_TYPES: [u8; 17] = [
+    INTEGER_T,
+    STRING_T,
+    LIST_T,
+    OBJECT_T,
+    FLOAT_T,
+    BOOL_T,
+    NULL_T,
+    UUID_T,
+    DECIMAL_T,
+    COMPACT_INTEGER_T,
+    MAP_T,
+    ENUM_T,
+    DATE_T,
+    DURATION_T,
+    INT128_T,
+    BIGINT_T,
+    ARRAY_T,
+];
+/// Header flag, the body is followed by a CRC32C of the header and body
+pub(crate) const CHECKSUM_FLAG: u8 = 0x01;
+/// Size of the CRC32C trailer
+const CHECKSUM_SIZE: usize = 4;
+/// Header flag, the body is zstd compressed
+pub(crate) const COMPRESSED_FLAG: u8 = 0x02;
+/// Header flag, the body is followed by an HMAC-SHA256 of the header and body
+pub(crate) const SIGNED_FLAG: u8 = 0x04;
+/// Size of the HMAC-SHA256 trailer
+const SIGNATURE_SIZE: usize = 32;
+/// Header flag, the body is sealed with ChaCha20-Poly1305 and prefixed with its nonce
+pub(crate) const ENCRYPTED_FLAG: u8 = 0x08;
+/// Size of the ChaCha20-Poly1305 nonce before a sealed body
+const NONCE_SIZE: usize = 12;
+/// Header flag, field names are replaced by the field IDs of a schema
+pub(crate) const FIELD_IDS_FLAG: u8 = 0x10;
+/// Header flag, the body starts with a table of its field names and fields
+/// refer to them by index
+pub(crate) const NAME_TABLE_FLAG: u8 = 0x20;
+/// Header flag, the header ends with the fingerprint of the schema the message
+/// was written with
+pub(crate) const FINGERPRINT_FLAG: u8 = 0x40;
+/// Size of a schema fingerprint, see [`Schema::fingerprint`](schema::Schema::fingerprint)
+pub(crate) const FINGERPRINT_SIZE: usize = 8;
+/// Header flag, the body is compressed with a zstd dictionary and the header
+/// ends with the ID of the dictionary
+pub(crate) const DICTIONARY_FLAG: u8 = 0x80;
+/// Size of a dictionary ID, see [`Dictionary::id`]
+pub(crate) const DICTIONARY_ID_SIZE: usize = 4;
 
-#[derive(Debug, PartialEq)]
-struct Header {
-    version: u8,
-    field_count: u8,
-    length: u16,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Header {
+    pub(crate) version: u8,
+    /// Features of the message, e.g. [`CHECKSUM_FLAG`], always 0 in version 1
+    pub(crate) flags: u8,
+    pub(crate) message_type: MessageType,
+    /// Position of the message in its stream, 0 when it is not sequenced
+    pub(crate) sequence: u64,
+    pub(crate) field_count: u16,
+    pub(crate) length: u32,
+    /// Fingerprint of the schema the message was written with, see
+    /// [`FINGERPRINT_FLAG`]
+    pub(crate) fingerprint: Option<[u8; FINGERPRINT_SIZE]>,
+    /// ID of the dictionary the body is compressed with, see [`DICTIONARY_FLAG`]
+    pub(crate) dictionary: Option<u32>,
 }
 
 #[derive(Debug, PartialEq)]
-struct Message {
-    header: Header,
-    body: HashMap<FieldName, FieldValue>,
+pub(crate) struct Message {
+    pub(crate) header: Header,
+    pub(crate) body: Fields,
 }
 
+/// Kind of a message, e.g. an order, a trade or a heartbeat
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct MessageType(pub(crate) u16);
+
 #[derive(Clone, Debug, PartialEq)]
-struct StringValue(String);
+pub(crate) struct StringValue(pub(crate) String);
 #[derive(Clone, Debug, PartialEq)]
-enum List {
+pub(crate) enum List {
     Integers(Vec<i64>),
     Strings(Vec<StringValue>),
     Objects(Vec<Object>),
+    Floats(Vec<f64>),
+    Bools(Vec<bool>),
+    Lists(Vec<List>),
+    Mixed(Vec<FieldValue>),
 }
 #[derive(Clone, Debug, PartialEq)]
-struct Object(HashMap<FieldName, FieldValue>);
+pub(crate) struct Object(pub(crate) Fields);
 
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
-struct FieldName(String);
+/// Entries keep their wire order, keys are all of one scalar type
+/// (Integer/String/Uuid/Decimal) and values are all of one type
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Map(pub(crate) Vec<(FieldValue, FieldValue)>);
 
+/// Fixed number of values of one type of a fixed size, e.g. the `[x, y, z]` of
+/// a coordinate, elements have no type indicator or length of their own
 #[derive(Clone, Debug, PartialEq)]
-enum FieldValue {
-    Integer(i64),
-    String(StringValue),
-    List(List),
-    Object(Object),
-}
+pub(crate) struct Array(pub(crate) Vec<FieldValue>);
 
-trait Serializable {
-    fn serialize(&self) -> Vec<u8>;
+/// Variant of an enum, the wire only carries the discriminant, its name
+/// comes from the [`EnumType`] the application registered
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EnumValue {
+    pub(crate) discriminant: u16,
+    pub(crate) payload: Option<Box<FieldValue>>,
 }
 
-#[derive(Debug)]
-struct DeserializeError(String);
+/// Name of a field, shared rather than copied so the names repeated by every
+/// object of a long list cost one allocation between them
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub(crate) struct FieldName(pub(crate) Arc<str>);
 
-impl From<String> for DeserializeError {
-    fn from(value: String) -> Self {
-        Self(value)
+impl FieldName {
+    /// Name standing for a field ID, which is how fields read without their
+    /// schema are named, e.g. `#7`
+    pub(crate) fn from_id(id: u16) -> FieldName {
+        FieldName::from(format!("#{}", id))
+    }
+
+    /// Name shared with the other fields of that name read on this thread, see
+    /// [`FIELD_NAMES`]
+    pub(crate) fn interned(name: &str) -> FieldName {
+        FIELD_NAMES.with_borrow_mut(|names| {
+            if let Some(interned) = names.get(name) {
+                return FieldName(interned.clone());
+            }
+            let interned = Arc::<str>::from(name);
+            if names.len() < MAX_INTERNED_NAMES {
+                names.insert(interned.clone());
+            }
+            FieldName(interned)
+        })
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Field ID the name stands for, see [`FieldName::from_id`]
+    pub(crate) fn id(&self) -> Option<u16> {
+        let digits = self.0.strip_prefix('#')?;
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
     }
 }
 
-trait Deserializable: Sized {
-    fn deserialize(bytes: &[u8], count: Option<usize>) -> Result<(Self, &[u8]), DeserializeError>;
+impl From<&str> for FieldName {
+    fn from(name: &str) -> Self {
+        FieldName(Arc::from(name))
+    }
 }
 
-/// [Integer - 8 bytes]
-impl Serializable for i64 {
-    fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+impl From<String> for FieldName {
+    fn from(name: String) -> Self {
+        FieldName(Arc::from(name))
     }
 }
 
-/// [Integer - 8 bytes]
-impl Deserializable for i64 {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let Some(integer) = bytes
-            .get(..std::mem::size_of::<i64>())
-            .and_then(|b| b.try_into().ok())
-            .map(i64::from_be_bytes)
-        else {
-            return Err(DeserializeError(format!("expected i64, end of buffer!")));
+/// Most field names [`FIELD_NAMES`] keeps, names past it are read into
+/// allocations of their own so a peer sending ever new names can't grow the
+/// table without bound
+const MAX_INTERNED_NAMES: usize = 4096;
+
+thread_local! {
+    /// Field names read on this thread, each field read with one of them
+    /// shares it instead of allocating its own copy
+    static FIELD_NAMES: RefCell<HashSet<Arc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// Fields of a message or object in wire order, so a message read and written
+/// again comes out byte for byte the same
+pub(crate) type Fields = IndexMap<FieldName, FieldValue>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FieldValue {
+    Integer(i64),
+    String(StringValue),
+    List(List),
+    Object(Object),
+    Float(f64),
+    Bool(bool),
+    /// Field is present, but has no value
+    Null,
+    Uuid([u8; 16]),
+    Decimal(Decimal),
+    Map(Map),
+    Enum(EnumValue),
+    Date(Date),
+    /// Written in nanoseconds, so at most 584 years
+    Duration(Duration),
+    /// Integer beyond `i64`, e.g. a cumulative volume
+    Int128(i128),
+    /// Integer of any size, e.g. an asset amount in its smallest unit
+    BigInt(BigInt),
+    Array(Array),
+    /// Value of a type this reader doesn't know, with its type indicator and
+    /// bytes, written back as it was read
+    Unknown(u8, Vec<u8>),
+}
+
+impl Message {
+    /// Untyped message of the given fields, with its header computed like
+    /// [`MessageBuilder::build`] does
+    pub(crate) fn new<'a>(
+        fields: impl IntoIterator<Item = (&'a str, FieldValue)>,
+    ) -> Result<Message, SerializeError> {
+        fields
+            .into_iter()
+            .fold(MessageBuilder::new(), |builder, (name, value)| {
+                builder.field(name, value)
+            })
+            .build()
+    }
+
+    /// `None` when the field is missing, `Some(FieldValue::Null)` when it is explicitly null
+    pub(crate) fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.body.get(&FieldName::from(name))
+    }
+
+    /// Type to dispatch the message on, version 1 messages are always untyped
+    pub(crate) fn message_type(&self) -> MessageType {
+        self.header.message_type
+    }
+
+    /// Serializes the message so that equal messages always come out as the same
+    /// bytes, e.g. for hashing or deduplication
+    ///
+    /// Fields are sorted by name and map entries by key, decimals are normalized
+    /// and floats lose the sign of zero. The message is written uncompressed in
+    /// the lowest version that holds it.
+    pub(crate) fn serialize_canonical(&self) -> Result<Vec<u8>, SerializeError> {
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                ..self.header
+            },
+            body: canonical_fields(&self.body),
         };
+        message.serialize_frame(Encoding::default(), Protection::default())
+    }
 
-        let bytes = match bytes.get(std::mem::size_of::<i64>()..) {
-            Some(slice) => slice,
-            None => &[],
+    /// Serializes the message signed with an HMAC-SHA256 of the given key
+    pub(crate) fn serialize_signed(&self, key: &[u8]) -> Result<Vec<u8>, SerializeError> {
+        let protection = Protection {
+            signing_key: Some(key),
+            ..Protection::default()
         };
-        Ok((integer, bytes))
+        self.serialize_frame(Encoding::default(), protection)
     }
-}
 
-/// [UTF-8 Data]
-impl Serializable for String {
-    fn serialize(&self) -> Vec<u8> {
-        self.as_bytes().to_vec()
+    /// Deserializes a signed message, fails unless it was signed with the given key
+    pub(crate) fn deserialize_verified<'a>(
+        bytes: &'a [u8],
+        key: &[u8],
+    ) -> Result<(Self, &'a [u8]), DeserializeError> {
+        let protection = Protection {
+            signing_key: Some(key),
+            ..Protection::default()
+        };
+        Self::deserialize_frame(bytes, Encoding::default(), protection)
     }
-}
 
-/// [UTF-8 Data]
-impl Deserializable for String {
-    fn deserialize(bytes: &[u8], count: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let count = count.unwrap_or(0);
+    /// Serializes the message with its body sealed by the session key, the nonce
+    /// must never repeat for the same key
+    pub(crate) fn serialize_encrypted(
+        &self,
+        key: &[u8; 32],
+        nonce: [u8; NONCE_SIZE],
+    ) -> Result<Vec<u8>, SerializeError> {
+        let protection = Protection {
+            sealing_key: Some(key),
+            nonce,
+            ..Protection::default()
+        };
+        self.serialize_frame(Encoding::default(), protection)
+    }
 
-        if count == 0 {
-            return Ok((String::new(), bytes));
-        }
+    /// Deserializes a message sealed by the session key
+    pub(crate) fn deserialize_decrypted<'a>(
+        bytes: &'a [u8],
+        key: &[u8; 32],
+    ) -> Result<(Self, &'a [u8]), DeserializeError> {
+        let protection = Protection {
+            sealing_key: Some(key),
+            ..Protection::default()
+        };
+        Self::deserialize_frame(bytes, Encoding::default(), protection)
+    }
 
-        let name = bytes.get(..count).ok_or(format!(
-            "expected string of length {}, end of buffer!",
-            count
-        ))?;
-        let name = std::str::from_utf8(name).map_err(|e| format!("invalid utf-8 string: {}", e))?;
-        let bytes = match bytes.get(count..) {
-            Some(slice) => slice,
-            None => &[],
+    /// Serializes the message with its body compressed by the dictionary,
+    /// however small it is
+    pub(crate) fn serialize_compressed(
+        &self,
+        dictionary: &Dictionary,
+    ) -> Result<Vec<u8>, SerializeError> {
+        let encoding = Encoding {
+            compression_threshold: Some(0),
+            ..Encoding::default()
+        };
+        let protection = Protection {
+            dictionary: Some(dictionary),
+            ..Protection::default()
         };
-        Ok((String::from(name), bytes))
+        self.serialize_frame(encoding, protection)
     }
-}
 
-/// [Element 1][Element 2]...[Element N]
-impl<T: Serializable> Serializable for Vec<T> {
-    fn serialize(&self) -> Vec<u8> {
-        self.iter().map(|el| el.serialize()).flatten().collect()
+    /// Deserializes a message, fails when its body is compressed with
+    /// another dictionary than the given one
+    pub(crate) fn deserialize_with_dictionary<'a>(
+        bytes: &'a [u8],
+        dictionary: &Dictionary,
+    ) -> Result<(Self, &'a [u8]), DeserializeError> {
+        let protection = Protection {
+            dictionary: Some(dictionary),
+            ..Protection::default()
+        };
+        Self::deserialize_frame(bytes, Encoding::default(), protection)
     }
 }
 
-/// [Element 1][Element 2]...[Element N]
-impl<T: Deserializable> Deserializable for Vec<T> {
-    fn deserialize(
-        mut bytes: &[u8],
-        count: Option<usize>,
-    ) -> Result<(Self, &[u8]), DeserializeError> {
-        let count = count.unwrap_or(0);
-
-        let mut list = vec![];
-        for i in 0..count {
-            let (element, next_bytes) = T::deserialize(bytes, None)
-                .map_err(|DeserializeError(e)| format!("at [{}]: {}", i, e))?;
-            list.push(element);
-            bytes = next_bytes;
+impl List {
+    /// Same list with its elements in canonical form
+    fn canonical(&self) -> List {
+        match self {
+            List::Floats(floats) => {
+                List::Floats(floats.iter().copied().map(canonical_float).collect())
+            }
+            List::Objects(objects) => List::Objects(
+                objects
+                    .iter()
+                    .map(|Object(fields)| Object(canonical_fields(fields)))
+                    .collect(),
+            ),
+            List::Lists(lists) => List::Lists(lists.iter().map(List::canonical).collect()),
+            List::Mixed(values) => List::Mixed(values.iter().map(FieldValue::canonical).collect()),
+            list => list.clone(),
         }
-
-        Ok((list, bytes))
     }
 }
 
-/// [Value U][Value V]
-impl<U: Serializable, V: Serializable> Serializable for (U, V) {
-    fn serialize(&self) -> Vec<u8> {
-        [self.0.serialize(), self.1.serialize()].concat()
+/// Zero without its sign and a single NaN
+fn canonical_float(float: f64) -> f64 {
+    if float == 0.0 {
+        0.0
+    } else if float.is_nan() {
+        f64::NAN
+    } else {
+        float
     }
 }
 
-/// [Value U][Value V]
-impl<U: Deserializable + Debug, V: Deserializable> Deserializable for (U, V) {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let (u, bytes) = U::deserialize(bytes, None)
-            .map_err(|DeserializeError(e)| format!("at (u, _): {}", e))?;
-        let (v, bytes) = V::deserialize(bytes, None)
-            .map_err(|DeserializeError(e)| format!("at `{:?}`: {}", u, e))?;
-        Ok(((u, v), bytes))
-    }
+/// Fields sorted by name with their values in canonical form
+fn canonical_fields(fields: &Fields) -> Fields {
+    let mut fields: Fields = fields
+        .iter()
+        .map(|(name, value)| (name.clone(), value.canonical()))
+        .collect();
+    fields.sort_keys();
+    fields
 }
 
-/// [Key 1][Value 1][Key 2][Value 2]...[Key N][Value N]
-impl<K: Serializable + Clone, V: Serializable + Clone> Serializable for HashMap<K, V> {
-    fn serialize(&self) -> Vec<u8> {
-        self.clone()
-            .into_iter()
-            .collect::<Vec<(K, V)>>()
-            .serialize()
-    }
+/// Keys protecting a message beyond its checksum, and the dictionary its body
+/// is compressed with, which both ends agree on ahead of time like the keys
+#[derive(Clone, Copy, Default)]
+struct Protection<'a> {
+    /// Signs and verifies the message with HMAC-SHA256
+    signing_key: Option<&'a [u8]>,
+    /// Seals and opens the body with ChaCha20-Poly1305
+    sealing_key: Option<&'a [u8; 32]>,
+    /// Nonce to seal the body with, opening reads it from the message
+    nonce: [u8; NONCE_SIZE],
+    /// Compresses and decompresses the body, see [`Dictionary`]
+    dictionary: Option<&'a Dictionary>,
 }
 
-/// [Key 1][Value 1][Key 2][Value 2]...[Key N][Value N]
-impl<K: Deserializable + Eq + Hash + Debug, V: Deserializable> Deserializable for HashMap<K, V> {
-    fn deserialize(bytes: &[u8], count: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let (list, bytes) = Vec::<(K, V)>::deserialize(bytes, count)?;
-        let map = list.into_iter().collect();
-        Ok((map, bytes))
-    }
+fn signature(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length")
 }
 
-/// [Element Type (1 byte)][Element Count (2 bytes)][Elements...]
-/// Element is one of Integer/String/Object
-impl Serializable for List {
-    fn serialize(&self) -> Vec<u8> {
-        let (element_type, count, elements) = match self {
-            List::Integers(integers) => (INTEGER_T, integers.len(), integers.serialize()),
-            List::Strings(strings) => (STRING_T, strings.len(), strings.serialize()),
-            List::Objects(objects) => (OBJECT_T, objects.len(), objects.serialize()),
-        };
-        assert!(
-            count <= u16::MAX as usize,
-            "Maximum list elements: 65,535 is supported"
-        );
-        [
-            vec![element_type],
-            (count as u16).to_be_bytes().to_vec(),
-            elements,
-        ]
-        .concat()
-    }
+impl MessageType {
+    /// Version 1 messages and messages without a registered type
+    pub(crate) const UNTYPED: MessageType = MessageType(0);
 }
 
-/// [Element Type (1 byte)][Element Count (2 bytes)][Elements...]
-/// Element is one of Integer/String/Object
-impl Deserializable for List {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let element_type = *bytes
-            .get(0)
-            .ok_or(format!("expected u8 (element type), end of buffer!"))?;
-        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
+/// Builds a message, its header is computed from the fields
+#[derive(Debug)]
+pub(crate) struct MessageBuilder {
+    version: u8,
+    flags: u8,
+    message_type: MessageType,
+    sequence: u64,
+    fingerprint: Option<[u8; FINGERPRINT_SIZE]>,
+    body: Fields,
+}
 
-        let count = bytes
-            .get(..std::mem::size_of::<u16>())
-            .and_then(|b| b.try_into().ok())
-            .map(u16::from_be_bytes)
-            .ok_or(format!("expected u16 (count), end of buffer!"))? as usize;
+impl MessageBuilder {
+    pub(crate) fn new() -> Self {
+        MessageBuilder {
+            version: VERSION1,
+            flags: 0,
+            message_type: MessageType::UNTYPED,
+            sequence: 0,
+            fingerprint: None,
+            body: Fields::new(),
+        }
+    }
 
-        let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        let (elements, bytes) = match element_type {
-            INTEGER_T => {
-                let (integers, bytes) = Vec::<i64>::deserialize(bytes, Some(count))?;
-                (List::Integers(integers), bytes)
-            }
-            STRING_T => {
-                let (strings, bytes) = Vec::<StringValue>::deserialize(bytes, Some(count))?;
-                (List::Strings(strings), bytes)
-            }
-            OBJECT_T => {
-                let (objects, bytes) = Vec::<Object>::deserialize(bytes, Some(count))?;
-                (List::Objects(objects), bytes)
-            }
-            t => {
-                return Err(DeserializeError(format!(
-                    "Unsupported type {}, expected one of {} = Integer, {} = String, {} = Object",
-                    t, INTEGER_T, STRING_T, OBJECT_T
-                )));
-            }
-        };
-        Ok((elements, bytes))
+    /// Lowest version to write the message in, typed, sequenced, checksummed
+    /// or large messages are written as version 2 regardless
+    pub(crate) fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
     }
-}
 
-/// [Length (2 byte)][UTF-8 Data]
-impl Serializable for StringValue {
-    fn serialize(&self) -> Vec<u8> {
-        assert!(
-            self.0.len() <= u16::MAX as usize,
-            "Maximum string value length: 65,535 bytes is supported"
-        );
-        let length = (self.0.len() as u16).to_be_bytes();
-        let string = self.0.as_bytes();
-        [&length, string].concat()
+    pub(crate) fn with_type(mut self, message_type: MessageType) -> Self {
+        self.message_type = message_type;
+        self
     }
-}
 
-/// [Length (2 byte)][UTF-8 Data]
-impl Deserializable for StringValue {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let length = bytes
-            .get(..std::mem::size_of::<u16>())
-            .and_then(|b| b.try_into().ok())
-            .map(u16::from_be_bytes)
-            .ok_or(format!("expected u16 (length), end of buffer!"))? as usize;
+    pub(crate) fn with_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = sequence;
+        self
+    }
 
-        let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        let (string, bytes) = String::deserialize(bytes, Some(length))?;
-        Ok((StringValue(string), bytes))
+    /// Appends a CRC32C checksum, verified on deserialization
+    pub(crate) fn with_checksum(mut self) -> Self {
+        self.flags |= CHECKSUM_FLAG;
+        self
     }
-}
 
-/// [Length (1 byte)][UTF-8 Data]
-impl Serializable for FieldName {
-    fn serialize(&self) -> Vec<u8> {
-        assert!(
-            self.0.len() <= u8::MAX as usize,
-            "Maximum field name length: 255 bytes is supported"
-        );
-        let length = [self.0.len() as u8];
-        let string = self.0.as_bytes();
-        [&length, string].concat()
+    /// Marks the message as written with the schema of the fingerprint, which
+    /// makes it a version 2 message
+    pub(crate) fn with_fingerprint(mut self, fingerprint: [u8; FINGERPRINT_SIZE]) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
     }
-}
 
-/// [Length (1 byte)][UTF-8 Data]
-impl Deserializable for FieldName {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let length = *bytes
-            .get(0)
-            .ok_or(format!("expected u8 (element type), end of buffer!"))?
-            as usize;
-        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        let (string, bytes) = String::deserialize(bytes, Some(length))?;
-        Ok((FieldName(string), bytes))
+    pub(crate) fn field(mut self, name: &str, value: FieldValue) -> Self {
+        self.body.insert(FieldName::from(name), value);
+        self
     }
-}
 
-/// [Type (1 byte)][Integer/String/List/Object]
-impl Serializable for FieldValue {
-    fn serialize(&self) -> Vec<u8> {
-        let (type_indicator, value) = match self {
-            Self::Integer(i) => (INTEGER_T, i.serialize()),
-            Self::String(s) => (STRING_T, s.serialize()),
-            Self::List(l) => (LIST_T, l.serialize()),
-            Self::Object(o) => (OBJECT_T, o.serialize()),
+    /// Message with the header it is serialized with
+    pub(crate) fn build(self) -> Result<Message, SerializeError> {
+        let mut message = Message {
+            header: Header {
+                version: self.version,
+                flags: self.flags,
+                message_type: self.message_type,
+                sequence: self.sequence,
+                field_count: 0,
+                length: 0,
+                fingerprint: self.fingerprint,
+                dictionary: None,
+            },
+            body: self.body,
         };
-        [vec![type_indicator], value].concat()
+        let bytes = message.serialize()?;
+        let (header, _) =
+            Header::deserialize(&bytes, None).expect("serialized header is deserializable");
+        message.header = header;
+        Ok(message)
     }
 }
 
-/// [Type (1 byte)][Integer/String/List/Object]
-impl Deserializable for FieldValue {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let type_indicator = *bytes
-            .get(0)
-            .ok_or(format!("expected u8 (type indicator), end of buffer!"))?;
-        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        let (value, bytes) = match type_indicator {
-            INTEGER_T => {
-                let (integer, bytes) = i64::deserialize(bytes, None)?;
-                (FieldValue::Integer(integer), bytes)
-            }
-            STRING_T => {
-                let (string, bytes) = StringValue::deserialize(bytes, None)?;
-                (FieldValue::String(string), bytes)
-            }
-            LIST_T => {
-                let (list, bytes) = List::deserialize(bytes, None)?;
-                (FieldValue::List(list), bytes)
-            }
-            OBJECT_T => {
-                let (object, bytes) = Object::deserialize(bytes, None)?;
-                (FieldValue::Object(object), bytes)
-            }
-            t => {
-                return Err(DeserializeError(format!(
-                    "Unsupported type {}, expected one of {} = Integer, {} = String, {} = List, {} = Object",
-                    t, INTEGER_T, STRING_T, LIST_T, OBJECT_T
-                )));
-            }
-        };
-        Ok((value, bytes))
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-/// [Field Count (1 byte)][Field 1][Field 2]...[Field N]
-impl Serializable for Object {
-    fn serialize(&self) -> Vec<u8> {
-        assert!(
-            self.0.len() <= u8::MAX as usize,
-            "Maximum fields per object: 255 is supported"
-        );
-        let count = self.0.len() as u8;
-        let fields = self.0.serialize();
-        [vec![count], fields].concat()
+impl Map {
+    pub(crate) fn get(&self, key: &FieldValue) -> Option<&FieldValue> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
     }
 }
 
-/// [Field Count (1 byte)][Field 1][Field 2]...[Field N]
-impl Deserializable for Object {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let count = *bytes
-            .get(0)
-            .ok_or(format!("expected u8 (count), end of buffer!"))? as usize;
-        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        let (object, bytes) = HashMap::<FieldName, FieldValue>::deserialize(bytes, Some(count))?;
-        Ok((Object(object), bytes))
+impl List {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            List::Integers(integers) => integers.len(),
+            List::Strings(strings) => strings.len(),
+            List::Objects(objects) => objects.len(),
+            List::Floats(floats) => floats.len(),
+            List::Bools(bools) => bools.len(),
+            List::Lists(lists) => lists.len(),
+            List::Mixed(values) => values.len(),
+        }
     }
-}
 
-/// Byte 0: Protocol Version (0x01)
-/// Byte 1: Field Count (0-255)
-/// Bytes 2-3: Total Message Length (big-endian, includes header)
-impl Serializable for Header {
-    fn serialize(&self) -> Vec<u8> {
-        let length = self.length.to_be_bytes();
-        vec![self.version, self.field_count, length[0], length[1]]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
     }
-}
 
-/// Byte 0: Protocol Version (0x01)
-/// Byte 1: Field Count (0-255)
-/// Bytes 2-3: Total Message Length (big-endian, includes header)
-impl Deserializable for Header {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        bytes
-            .get(..4)
-            .ok_or(format!("expected 4 byte header, end of buffer!"))?;
-        let header = Header {
-            version: bytes[0],
-            field_count: bytes[1],
-            length: u16::from_be_bytes([bytes[2], bytes[3]]),
-        };
-        let bytes = match bytes.get(4..) {
-            Some(slice) => slice,
-            None => &[],
-        };
-        Ok((header, bytes))
+    /// List of the values, typed when they all share a type
+    pub(crate) fn from_values(values: Vec<FieldValue>) -> List {
+        macro_rules! typed {
+            ($variant:ident, $list:ident) => {
+                if !values.is_empty()
+                    && values
+                        .iter()
+                        .all(|value| matches!(value, FieldValue::$variant(_)))
+                {
+                    return List::$list(
+                        values
+                            .into_iter()
+                            .map(|value| match value {
+                                FieldValue::$variant(value) => value,
+                                _ => unreachable!(),
+                            })
+                            .collect(),
+                    );
+                }
+            };
+        }
+        typed!(Integer, Integers);
+        typed!(String, Strings);
+        typed!(Object, Objects);
+        typed!(Float, Floats);
+        typed!(Bool, Bools);
+        typed!(List, Lists);
+        List::Mixed(values)
     }
-}
 
-/// [Header][Field 1][Field 2]...[Field N]
-impl Serializable for Message {
-    fn serialize(&self) -> Vec<u8> {
-        let header = self.header.serialize();
-        let body = self.body.serialize();
-        [header, body].concat()
+    /// Elements as values, whatever the type of the list
+    pub(crate) fn into_values(self) -> Vec<FieldValue> {
+        match self {
+            List::Integers(integers) => integers.into_iter().map(FieldValue::Integer).collect(),
+            List::Strings(strings) => strings.into_iter().map(FieldValue::String).collect(),
+            List::Objects(objects) => objects.into_iter().map(FieldValue::Object).collect(),
+            List::Floats(floats) => floats.into_iter().map(FieldValue::Float).collect(),
+            List::Bools(bools) => bools.into_iter().map(FieldValue::Bool).collect(),
+            List::Lists(lists) => lists.into_iter().map(FieldValue::List).collect(),
+            List::Mixed(values) => values,
+        }
     }
 }
 
-/// [Header][Field 1][Field 2]...[Field N]
-impl Deserializable for Message {
-    fn deserialize(bytes: &[u8], _: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
-        let old_bytes = bytes;
-        let (header, bytes) = Header::deserialize(bytes, None)?;
+impl Object {
+    /// `None` when the field is missing, `Some(FieldValue::Null)` when it is explicitly null
+    pub(crate) fn get(&self, name: &str) -> Option<&FieldValue> {
+        self.0.get(&FieldName::from(name))
+    }
+}
 
-        if header.version != VERSION1 {
-            return Err(DeserializeError(format!(
-                "expected version: {}, found: {}",
-                VERSION1, header.version
-            )));
+/// `From<T> for FieldValue` and `TryFrom<FieldValue> for T`, the conversion
+/// back fails with the value itself when it holds another variant
+macro_rules! field_value_conversions {
+    ($($type:ty => $variant:ident),* $(,)?) => {$(
+        impl From<$type> for FieldValue {
+            fn from(value: $type) -> Self {
+                FieldValue::$variant(value)
+            }
         }
 
-        if header.length as usize > bytes.len() + 4 {
-            return Err(DeserializeError(format!(
-                "buffer: {} is shorter than the message length: {}!",
-                bytes.len() + 4,
-                header.length
-            )));
+        impl TryFrom<FieldValue> for $type {
+            type Error = FieldValue;
+
+            fn try_from(value: FieldValue) -> Result<Self, Self::Error> {
+                match value {
+                    FieldValue::$variant(value) => Ok(value),
+                    value => Err(value),
+                }
+            }
         }
+    )*};
+}
 
-        let (body, bytes) = HashMap::<FieldName, FieldValue>::deserialize(
-            bytes,
-            Some(header.field_count as usize),
-        )?;
+field_value_conversions! {
+    i64 => Integer,
+    f64 => Float,
+    bool => Bool,
+    [u8; 16] => Uuid,
+    Decimal => Decimal,
+    Object => Object,
+    List => List,
+    Map => Map,
+    Array => Array,
+    EnumValue => Enum,
+    Date => Date,
+    Duration => Duration,
+    BigInt => BigInt,
+}
 
-        let message_length = old_bytes.len() - bytes.len();
-        if message_length != header.length as usize {
-            return Err(DeserializeError(format!(
-                "message length: {} does not match the length in header: {}",
-                message_length, header.length
-            )));
-        }
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::String(StringValue(value))
+    }
+}
 
-        Ok((Message { header, body }, bytes))
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::String(StringValue(String::from(value)))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl TryFrom<FieldValue> for String {
+    type Error = FieldValue;
 
-    #[test]
-    fn simple_message() {
-        // Message: `user_id=1001, name="Alice", scores=[100, 200, 300]`
+    fn try_from(value: FieldValue) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::String(StringValue(string)) => Ok(string),
+            value => Err(value),
+        }
+    }
+}
+
+/// `FieldValue::Null` for `None`
+impl<T: Into<FieldValue>> From<Option<T>> for FieldValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(FieldValue::Null, Into::into)
+    }
+}
+
+/// `From<Vec<T>> for FieldValue` and `TryFrom<FieldValue> for Vec<T>` through
+/// the list variant holding `T`
+macro_rules! list_conversions {
+    ($($type:ty => $variant:ident),* $(,)?) => {$(
+        impl From<Vec<$type>> for FieldValue {
+            fn from(values: Vec<$type>) -> Self {
+                FieldValue::List(List::$variant(values))
+            }
+        }
+
+        impl TryFrom<FieldValue> for Vec<$type> {
+            type Error = FieldValue;
+
+            fn try_from(value: FieldValue) -> Result<Self, Self::Error> {
+                match value {
+                    FieldValue::List(List::$variant(values)) => Ok(values),
+                    value => Err(value),
+                }
+            }
+        }
+    )*};
+}
+
+list_conversions! {
+    i64 => Integers,
+    f64 => Floats,
+    bool => Bools,
+    Object => Objects,
+    List => Lists,
+    FieldValue => Mixed,
+}
+
+impl From<Vec<String>> for FieldValue {
+    fn from(values: Vec<String>) -> Self {
+        FieldValue::List(List::Strings(values.into_iter().map(StringValue).collect()))
+    }
+}
+
+impl From<Vec<&str>> for FieldValue {
+    fn from(values: Vec<&str>) -> Self {
+        FieldValue::List(List::Strings(
+            values
+                .into_iter()
+                .map(|value| StringValue(String::from(value)))
+                .collect(),
+        ))
+    }
+}
+
+impl TryFrom<FieldValue> for Vec<String> {
+    type Error = FieldValue;
+
+    fn try_from(value: FieldValue) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::List(List::Strings(strings)) => Ok(strings
+                .into_iter()
+                .map(|StringValue(string)| string)
+                .collect()),
+            value => Err(value),
+        }
+    }
+}
+
+/// Field missing or of another type than the one asked for
+#[derive(Debug, PartialEq)]
+pub(crate) enum FieldError {
+    Missing {
+        name: String,
+    },
+    WrongType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// List index beyond the end of the list
+    OutOfBounds {
+        name: String,
+        index: usize,
+        length: usize,
+    },
+    /// Array of another length than the one asked for
+    WrongLength {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Field path not of the form `trades[2].price`
+    InvalidPath {
+        path: String,
+    },
+    /// Field of a damaged message which didn't read, see
+    /// [`Message::deserialize_lossy`]
+    Unreadable {
+        /// Position of the field in the message
+        index: usize,
+        /// `None` when the name itself didn't read
+        name: Option<String>,
+        error: DeserializeError,
+    },
+}
+
+impl FieldError {
+    pub(crate) fn wrong_type(name: &str, expected: &'static str, value: &FieldValue) -> Self {
+        FieldError::WrongType {
+            name: String::from(name),
+            expected,
+            found: value.type_name(),
+        }
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldError::Missing { name } => write!(f, "missing field `{}`", name),
+            FieldError::WrongType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "field `{}`: expected {}, found {}",
+                name, expected, found
+            ),
+            FieldError::WrongLength {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "field `{}`: expected {} elements, found {}",
+                name, expected, found
+            ),
+            FieldError::OutOfBounds {
+                name,
+                index,
+                length,
+            } => write!(
+                f,
+                "field `{}`: index {} beyond the end of the list of {}",
+                name, index, length
+            ),
+            FieldError::InvalidPath { path } => write!(f, "invalid field path `{}`", path),
+            FieldError::Unreadable {
+                name: Some(name),
+                error,
+                ..
+            } => write!(f, "field `{}`: {}", name, error),
+            FieldError::Unreadable {
+                index,
+                name: None,
+                error,
+            } => write!(f, "field {}: {}", index, error),
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Fields of a well-formed message the reader can't use
+impl From<FieldError> for DeserializeError {
+    fn from(error: FieldError) -> Self {
+        match error {
+            FieldError::Missing { name } => DeserializeError::invalid("missing field").within(name),
+            FieldError::WrongType {
+                name,
+                expected,
+                found,
+            } => DeserializeError::invalid(format!("expected {}, found {}", expected, found))
+                .within(name),
+            error => DeserializeError::invalid(error.to_string()),
+        }
+    }
+}
+
+/// Typed access to the fields of a message or an object
+pub(crate) trait FieldAccess {
+    fn fields(&self) -> &Fields;
+
+    /// Value of the field, [`FieldValue::Null`] when it is explicitly null
+    fn field(&self, name: &str) -> Result<&FieldValue, FieldError> {
+        self.fields()
+            .get(&FieldName::from(name))
+            .ok_or_else(|| FieldError::Missing {
+                name: String::from(name),
+            })
+    }
+
+    fn get_i64(&self, name: &str) -> Result<i64, FieldError> {
+        match self.field(name)? {
+            FieldValue::Integer(integer) => Ok(*integer),
+            value => Err(FieldError::wrong_type(name, "integer", value)),
+        }
+    }
+
+    fn get_f64(&self, name: &str) -> Result<f64, FieldError> {
+        match self.field(name)? {
+            FieldValue::Float(float) => Ok(*float),
+            value => Err(FieldError::wrong_type(name, "float", value)),
+        }
+    }
+
+    fn get_bool(&self, name: &str) -> Result<bool, FieldError> {
+        match self.field(name)? {
+            FieldValue::Bool(boolean) => Ok(*boolean),
+            value => Err(FieldError::wrong_type(name, "bool", value)),
+        }
+    }
+
+    fn get_str(&self, name: &str) -> Result<&str, FieldError> {
+        match self.field(name)? {
+            FieldValue::String(StringValue(string)) => Ok(string),
+            value => Err(FieldError::wrong_type(name, "string", value)),
+        }
+    }
+
+    fn get_uuid(&self, name: &str) -> Result<&[u8; 16], FieldError> {
+        match self.field(name)? {
+            FieldValue::Uuid(uuid) => Ok(uuid),
+            value => Err(FieldError::wrong_type(name, "uuid", value)),
+        }
+    }
+
+    fn get_decimal(&self, name: &str) -> Result<Decimal, FieldError> {
+        match self.field(name)? {
+            FieldValue::Decimal(decimal) => Ok(*decimal),
+            value => Err(FieldError::wrong_type(name, "decimal", value)),
+        }
+    }
+
+    fn get_date(&self, name: &str) -> Result<Date, FieldError> {
+        match self.field(name)? {
+            FieldValue::Date(date) => Ok(*date),
+            value => Err(FieldError::wrong_type(name, "date", value)),
+        }
+    }
+
+    fn get_duration(&self, name: &str) -> Result<Duration, FieldError> {
+        match self.field(name)? {
+            FieldValue::Duration(duration) => Ok(*duration),
+            value => Err(FieldError::wrong_type(name, "duration", value)),
+        }
+    }
+
+    fn get_int128(&self, name: &str) -> Result<i128, FieldError> {
+        match self.field(name)? {
+            FieldValue::Int128(integer) => Ok(*integer),
+            value => Err(FieldError::wrong_type(name, "int128", value)),
+        }
+    }
+
+    fn get_bigint(&self, name: &str) -> Result<&BigInt, FieldError> {
+        match self.field(name)? {
+            FieldValue::BigInt(integer) => Ok(integer),
+            value => Err(FieldError::wrong_type(name, "bigint", value)),
+        }
+    }
+
+    fn get_list(&self, name: &str) -> Result<&List, FieldError> {
+        match self.field(name)? {
+            FieldValue::List(list) => Ok(list),
+            value => Err(FieldError::wrong_type(name, "list", value)),
+        }
+    }
+
+    fn get_object(&self, name: &str) -> Result<&Object, FieldError> {
+        match self.field(name)? {
+            FieldValue::Object(object) => Ok(object),
+            value => Err(FieldError::wrong_type(name, "object", value)),
+        }
+    }
+
+    /// Value at a path of field names and list indices, e.g. `trades[2].price`
+    ///
+    /// Elements of lists of scalars or objects are not stored as a `FieldValue`,
+    /// those are returned as an owned copy.
+    fn get_path(&self, path: &str) -> Result<Cow<'_, FieldValue>, FieldError> {
+        let invalid = || FieldError::InvalidPath {
+            path: String::from(path),
+        };
+        let mut cursor = PathCursor::Fields(self.fields());
+        let mut prefix = String::new();
+        for segment in path.split('.') {
+            let (name, mut indices) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+            if name.is_empty() {
+                return Err(invalid());
+            }
+            cursor = cursor.field(&prefix, name)?;
+            prefix = join_path(&prefix, name);
+            while !indices.is_empty() {
+                let (index, rest) = indices
+                    .strip_prefix('[')
+                    .and_then(|indices| indices.split_once(']'))
+                    .ok_or_else(invalid)?;
+                let index = index.parse().map_err(|_| invalid())?;
+                cursor = cursor.element(&prefix, index)?;
+                prefix = format!("{}[{}]", prefix, index);
+                indices = rest;
+            }
+        }
+        Ok(cursor.into_value())
+    }
+}
+
+/// Rust value written as an object, derived with `#[derive(GalacticSerialize)]`
+pub(crate) trait GalacticSerialize {
+    fn to_object(&self) -> Object;
+}
+
+/// Rust value read from an object, derived with `#[derive(GalacticDeserialize)]`
+pub(crate) trait GalacticDeserialize: Sized {
+    fn from_object(object: &Object) -> Result<Self, FieldError>;
+}
+
+/// Position of [`FieldAccess::get_path`] while it walks the path
+enum PathCursor<'a> {
+    Fields(&'a Fields),
+    Value(&'a FieldValue),
+    List(&'a List),
+    /// Element of a list of scalars, which holds no `FieldValue` to borrow
+    Element(FieldValue),
+}
+
+impl<'a> PathCursor<'a> {
+    fn field(self, prefix: &str, name: &str) -> Result<PathCursor<'a>, FieldError> {
+        let path = join_path(prefix, name);
+        let fields = match self {
+            PathCursor::Fields(fields) => fields,
+            PathCursor::Value(FieldValue::Object(Object(fields))) => fields,
+            cursor => {
+                let found = cursor.into_value().type_name();
+                return Err(FieldError::WrongType {
+                    name: String::from(prefix),
+                    expected: "object",
+                    found,
+                });
+            }
+        };
+        fields
+            .get(&FieldName::from(name))
+            .map(PathCursor::Value)
+            .ok_or(FieldError::Missing { name: path })
+    }
+
+    fn element(self, prefix: &str, index: usize) -> Result<PathCursor<'a>, FieldError> {
+        let list = match self {
+            PathCursor::List(list) | PathCursor::Value(FieldValue::List(list)) => list,
+            cursor => {
+                let found = cursor.into_value().type_name();
+                return Err(FieldError::WrongType {
+                    name: String::from(prefix),
+                    expected: "list",
+                    found,
+                });
+            }
+        };
+        let length = list.len();
+        if index >= length {
+            return Err(FieldError::OutOfBounds {
+                name: String::from(prefix),
+                index,
+                length,
+            });
+        }
+        let cursor = match list {
+            List::Integers(integers) => PathCursor::Element(FieldValue::Integer(integers[index])),
+            List::Strings(strings) => {
+                PathCursor::Element(FieldValue::String(strings[index].clone()))
+            }
+            List::Floats(floats) => PathCursor::Element(FieldValue::Float(floats[index])),
+            List::Bools(bools) => PathCursor::Element(FieldValue::Bool(bools[index])),
+            List::Objects(objects) => PathCursor::Fields(&objects[index].0),
+            List::Lists(lists) => PathCursor::List(&lists[index]),
+            List::Mixed(values) => PathCursor::Value(&values[index]),
+        };
+        Ok(cursor)
+    }
+
+    fn into_value(self) -> Cow<'a, FieldValue> {
+        match self {
+            PathCursor::Fields(fields) => Cow::Owned(FieldValue::Object(Object(fields.clone()))),
+            PathCursor::Value(value) => Cow::Borrowed(value),
+            PathCursor::List(list) => Cow::Owned(FieldValue::List(list.clone())),
+            PathCursor::Element(value) => Cow::Owned(value),
+        }
+    }
+}
+
+fn join_path(prefix: &str, name: &str) -> String {
+    match prefix {
+        "" => String::from(name),
+        _ => format!("{}.{}", prefix, name),
+    }
+}
+
+impl FieldAccess for Message {
+    fn fields(&self) -> &Fields {
+        &self.body
+    }
+}
+
+impl FieldAccess for Object {
+    fn fields(&self) -> &Fields {
+        &self.0
+    }
+}
+
+/// Formats a schema fingerprint as its 16 hex digits, e.g. `00010203fcfdfeff`
+pub(crate) fn format_fingerprint(fingerprint: &[u8; FINGERPRINT_SIZE]) -> String {
+    format!("{:016x}", u64::from_be_bytes(*fingerprint))
+}
+
+/// Parses the 16 hex digits of a schema fingerprint, of either case
+pub(crate) fn parse_fingerprint(hex: &str) -> Option<[u8; FINGERPRINT_SIZE]> {
+    if hex.len() != 2 * FINGERPRINT_SIZE || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok().map(u64::to_be_bytes)
+}
+
+/// Formats a UUID in the canonical hyphenated form, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`
+pub(crate) fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Parses a UUID in the canonical hyphenated form, hex digits may be of either case
+pub(crate) fn parse_uuid(uuid: &str) -> Option<[u8; 16]> {
+    let groups: Vec<&str> = uuid.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    if lengths != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    let hex = groups.concat();
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let digits = hex.get(2 * i..2 * i + 2)?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Choices a writer can make about the wire representation, readers
+/// recognize them on their own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Encoding {
+    /// Protocol version from the message header
+    pub(crate) version: u8,
+    /// Integer fields and lists of integers use the smallest width that fits,
+    /// has no effect since version 2 where all integers are varints
+    pub(crate) compact_integers: bool,
+    /// Message bodies of at least this many bytes are zstd compressed, `None`
+    /// never compresses
+    pub(crate) compression_threshold: Option<usize>,
+    /// Bounds on what a reader accepts, writers ignore them
+    pub(crate) limits: DeserializeLimits,
+    /// What a reader does with a field name repeated within an object
+    pub(crate) duplicate_fields: DuplicateFields,
+    /// Fields are identified by 2 byte IDs rather than names, set from
+    /// [`FIELD_IDS_FLAG`] when reading
+    pub(crate) field_ids: bool,
+    /// Each distinct field name is written once in a table ahead of the body,
+    /// see [`NAME_TABLE_FLAG`], has no effect with field IDs
+    pub(crate) name_table: bool,
+    /// Input validated as UTF-8 in one go, strings within it skip their own
+    /// validation, set by the reader of the body only
+    valid_utf8: Utf8Region,
+}
+
+/// Addresses of bytes known to be valid UTF-8
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Utf8Region {
+    start: usize,
+    end: usize,
+}
+
+impl Utf8Region {
+    /// The longest prefix of `bytes` which is valid UTF-8, validated with a
+    /// single pass rather than one per string
+    fn of(bytes: &[u8]) -> Utf8Region {
+        let valid = match from_utf8(bytes) {
+            Ok(_) => bytes.len(),
+            Err(valid_up_to) => valid_up_to,
+        };
+        let start = bytes.as_ptr() as usize;
+        Utf8Region {
+            start,
+            end: start + valid,
+        }
+    }
+
+    /// Whether the first `count` bytes of `bytes` are valid UTF-8 as part of
+    /// the region, which they are when they start and end on character
+    /// boundaries within it
+    fn holds(self, bytes: &[u8], count: usize) -> bool {
+        let start = bytes.as_ptr() as usize;
+        let end = start + count;
+        let boundary = |byte: Option<&u8>| byte.is_none_or(|&byte| (byte as i8) >= -0x40);
+        self.start <= start
+            && end <= self.end
+            && boundary(bytes.first())
+            && (end == self.end || bytes.len() > count && boundary(bytes.get(count)))
+    }
+}
+
+/// UTF-8 validation, SIMD accelerated with the `simd` feature, fails with the
+/// length of the longest valid prefix
+fn from_utf8(bytes: &[u8]) -> Result<&str, usize> {
+    #[cfg(feature = "simd")]
+    let string = simdutf8::compat::from_utf8(bytes);
+    #[cfg(not(feature = "simd"))]
+    let string = std::str::from_utf8(bytes);
+    string.map_err(|e| e.valid_up_to())
+}
+
+/// Policy for a field name repeated within the message or an object, which
+/// writers never produce and readers could otherwise disagree on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum DuplicateFields {
+    /// Reject the message
+    #[default]
+    Error,
+    /// Keep the value of the first occurrence
+    FirstWins,
+    /// Keep the value of the last occurrence
+    LastWins,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding {
+            version: VERSION1,
+            compact_integers: false,
+            compression_threshold: None,
+            limits: DeserializeLimits::default(),
+            duplicate_fields: DuplicateFields::default(),
+            field_ids: false,
+            name_table: false,
+            valid_utf8: Utf8Region::default(),
+        }
+    }
+}
+
+impl Encoding {
+    /// Encoding of the values nested one level deeper, fails with
+    /// [`DeserializeErrorKind::DepthExceeded`] at the start of `bytes` when the
+    /// depth limit is used up
+    fn nested(self, bytes: &[u8]) -> Result<Encoding, DeserializeError> {
+        let Some(max_depth) = self.limits.max_depth.checked_sub(1) else {
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::DepthExceeded,
+                bytes,
+            ));
+        };
+        Ok(Encoding {
+            limits: DeserializeLimits {
+                max_depth,
+                ..self.limits
+            },
+            ..self
+        })
+    }
+
+    /// Encoding of the elements of a collection which reserves `count` of
+    /// `size` bytes up front, fails at the start of `bytes` when they can't
+    /// hold that many elements or the reservation exceeds what is left of
+    /// [`DeserializeLimits::max_alloc_bytes`]
+    fn reserve(
+        self,
+        count: usize,
+        size: usize,
+        bytes: &[u8],
+    ) -> Result<Encoding, DeserializeError> {
+        check_count(count, bytes)?;
+        let reserved = count.saturating_mul(size);
+        let max = self.limits.max_alloc_bytes;
+        check_limit("allocation bytes", reserved, max, bytes)?;
+        Ok(Encoding {
+            limits: DeserializeLimits {
+                max_alloc_bytes: max - reserved,
+                ..self.limits
+            },
+            ..self
+        })
+    }
+}
+
+/// Bounds on untrusted input, checked before the bytes are trusted with an
+/// allocation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DeserializeLimits {
+    /// Objects, lists, maps and enum payloads nested in each other, counts down
+    /// while the reader descends
+    pub(crate) max_depth: usize,
+    /// Fields of the message and all of its objects together
+    pub(crate) max_fields: usize,
+    /// Length of a single string or field name
+    pub(crate) max_string_bytes: usize,
+    /// Length of the message, and of its body once decompressed
+    pub(crate) max_message_size: usize,
+    /// Bytes reserved for the elements of the collections being read, which
+    /// their counts claim before the elements are there, counts down while
+    /// the reader descends so nested counts can't multiply
+    pub(crate) max_alloc_bytes: usize,
+}
+
+impl DeserializeLimits {
+    /// No bounds beyond what the wire format can express, for trusted input only
+    pub(crate) fn unlimited() -> Self {
+        DeserializeLimits {
+            max_depth: usize::MAX,
+            max_fields: usize::MAX,
+            max_string_bytes: usize::MAX,
+            max_message_size: usize::MAX,
+            max_alloc_bytes: usize::MAX,
+        }
+    }
+}
+
+impl Default for DeserializeLimits {
+    fn default() -> Self {
+        DeserializeLimits {
+            max_depth: 64,
+            max_fields: 65_536,
+            max_string_bytes: 1 << 20,
+            max_message_size: 16 << 20,
+            max_alloc_bytes: 64 << 20,
+        }
+    }
+}
+
+/// Named variants of an enum, e.g. `OrderSide` with `Buy = 0` and `Sell = 1`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EnumType {
+    name: String,
+    variants: Vec<(u16, String)>,
+}
+
+impl EnumType {
+    pub(crate) fn new(name: &str) -> Self {
+        EnumType {
+            name: String::from(name),
+            variants: vec![],
+        }
+    }
+
+    pub(crate) fn variant(mut self, discriminant: u16, name: &str) -> Self {
+        assert!(
+            self.variant_name(discriminant).is_none() && self.discriminant(name).is_none(),
+            "Variant {} = {} of {} is already registered",
+            name,
+            discriminant,
+            self.name
+        );
+        self.variants.push((discriminant, String::from(name)));
+        self
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn variant_name(&self, discriminant: u16) -> Option<&str> {
+        self.variants
+            .iter()
+            .find(|(d, _)| *d == discriminant)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub(crate) fn discriminant(&self, name: &str) -> Option<u16> {
+        self.variants
+            .iter()
+            .find(|(_, n)| n == name)
+            .map(|(discriminant, _)| *discriminant)
+    }
+
+    /// Variant without payload by its name
+    pub(crate) fn value(&self, name: &str) -> Option<EnumValue> {
+        Some(EnumValue {
+            discriminant: self.discriminant(name)?,
+            payload: None,
+        })
+    }
+}
+
+/// Message types known to the application, e.g. `order = 1` and `trade = 2`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MessageTypeRegistry(Vec<(MessageType, String)>);
+
+impl MessageTypeRegistry {
+    pub(crate) fn register(&mut self, message_type: MessageType, name: &str) {
+        assert!(
+            message_type != MessageType::UNTYPED,
+            "Message type {} is reserved for untyped messages",
+            MessageType::UNTYPED.0
+        );
+        assert!(
+            self.name(message_type).is_none() && self.message_type(name).is_none(),
+            "Message type {} = {} is already registered",
+            name,
+            message_type.0
+        );
+        self.0.push((message_type, String::from(name)));
+    }
+
+    pub(crate) fn name(&self, message_type: MessageType) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(t, _)| *t == message_type)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub(crate) fn message_type(&self, name: &str) -> Option<MessageType> {
+        self.0
+            .iter()
+            .find(|(_, n)| n == name)
+            .map(|(message_type, _)| *message_type)
+    }
+}
+
+/// Enum types known to the application, looked up by name
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EnumRegistry(HashMap<String, EnumType>);
+
+impl EnumRegistry {
+    pub(crate) fn register(&mut self, enum_type: EnumType) {
+        assert!(
+            !self.0.contains_key(enum_type.name()),
+            "Enum {} is already registered",
+            enum_type.name()
+        );
+        self.0.insert(String::from(enum_type.name()), enum_type);
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&EnumType> {
+        self.0.get(name)
+    }
+
+    /// Name of the variant of the value, as a registered enum of the given type
+    pub(crate) fn variant_name(&self, enum_name: &str, value: &EnumValue) -> Option<&str> {
+        self.get(enum_name)?.variant_name(value.discriminant)
+    }
+}
+
+pub(crate) trait Serializable {
+    fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        self.serialize_with(Encoding::default())
+    }
+
+    fn serialize_with(&self, encoding: Encoding) -> Result<Vec<u8>, SerializeError> {
+        let mut bytes = vec![];
+        self.serialize_into_with(&mut bytes, encoding)?;
+        Ok(bytes)
+    }
+
+    /// Appends the value to `bytes`, values nested in it included, so a single
+    /// buffer can be cleared and reused from one message to the next
+    ///
+    /// On an error what was appended up to it is left in the buffer.
+    fn serialize_into(&self, bytes: &mut Vec<u8>) -> Result<(), SerializeError> {
+        self.serialize_into_with(bytes, Encoding::default())
+    }
+
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError>;
+
+    /// Bytes [`Serializable::serialize`] writes, to reserve room for them up
+    /// front, meaningless for values which fail to serialize
+    fn serialized_len(&self) -> usize {
+        self.serialized_len_with(Encoding::default())
+    }
+
+    /// Counted without serializing the value, unless the type can't tell
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        self.serialize_with(encoding).map_or(0, |bytes| bytes.len())
+    }
+
+    /// Writes the value to `writer` out of a single buffer, values nested in it
+    /// are appended to that rather than each serialized on their own
+    fn serialize_to<W: Write>(&self, writer: &mut W) -> Result<(), WriteError> {
+        self.serialize_to_with(writer, Encoding::default())
+    }
+
+    fn serialize_to_with<W: Write>(
+        &self,
+        writer: &mut W,
+        encoding: Encoding,
+    ) -> Result<(), WriteError> {
+        writer.write_all(&self.serialize_with(encoding)?)?;
+        Ok(())
+    }
+}
+
+/// Writer failing, or a value the wire format can't represent
+#[derive(Debug)]
+pub(crate) enum WriteError {
+    Io(std::io::Error),
+    Serialize(SerializeError),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::Io(error) => write!(f, "{}", error),
+            WriteError::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<std::io::Error> for WriteError {
+    fn from(error: std::io::Error) -> Self {
+        WriteError::Io(error)
+    }
+}
+
+impl From<SerializeError> for WriteError {
+    fn from(error: SerializeError) -> Self {
+        WriteError::Serialize(error)
+    }
+}
+
+/// Value the wire format can't represent
+#[derive(Debug, PartialEq)]
+pub(crate) enum SerializeError {
+    /// Length or count beyond what its field on the wire holds
+    TooLong {
+        what: &'static str,
+        length: usize,
+        max: usize,
+    },
+    /// Map key of a type other than Integer/String/Uuid/Decimal
+    UnsupportedMapKey { type_indicator: u8 },
+    /// Map keys or values of more than one type
+    MixedMapTypes,
+    /// Array element of a type without a fixed size
+    UnsupportedArrayElement { type_indicator: u8 },
+    /// Array elements of more than one type
+    MixedArrayTypes,
+    /// Header field the protocol version has no room for
+    UnsupportedInVersion { version: u8, field: &'static str },
+    /// [`FieldValue::Unknown`] other than a length prefixed value
+    UnknownType { type_indicator: u8 },
+    /// Field name other than a field ID, written with field IDs
+    MissingFieldId { name: String },
+    /// Duration of more nanoseconds than 8 bytes hold
+    DurationTooLong { duration: Duration },
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::TooLong { what, length, max } => {
+                write!(f, "{}: {} exceeds the maximum of {}", what, length, max)
+            }
+            SerializeError::UnsupportedMapKey { type_indicator } => write!(
+                f,
+                "map key of type {}, expected one of {} = Integer, {} = String, {} = Uuid, {} = Decimal",
+                type_indicator, INTEGER_T, STRING_T, UUID_T, DECIMAL_T
+            ),
+            SerializeError::MixedMapTypes => {
+                write!(f, "map keys and values must each be of a single type")
+            }
+            SerializeError::UnsupportedArrayElement { type_indicator } => {
+                write!(
+                    f,
+                    "array element of type {} has no fixed size",
+                    type_indicator
+                )
+            }
+            SerializeError::MixedArrayTypes => {
+                write!(f, "array elements must be of a single type")
+            }
+            SerializeError::UnsupportedInVersion { version, field } => {
+                write!(f, "version {} messages have no {}", version, field)
+            }
+            SerializeError::UnknownType { type_indicator } => write!(
+                f,
+                "value of unknown type {} needs a length prefix, only version {} values have one",
+                type_indicator, VERSION3
+            ),
+            SerializeError::MissingFieldId { name } => {
+                write!(f, "field {} has no field ID", name)
+            }
+            SerializeError::DurationTooLong { duration } => write!(
+                f,
+                "duration of {:?} exceeds the maximum of {:?}",
+                duration,
+                Duration::from_nanos(u64::MAX)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+/// Fails with [`SerializeError::TooLong`] when `length` exceeds `max`
+fn check_length(what: &'static str, length: usize, max: usize) -> Result<(), SerializeError> {
+    if length > max {
+        return Err(SerializeError::TooLong { what, length, max });
+    }
+    Ok(())
+}
+
+/// Bytes which don't form a valid message, with where they were found
+#[derive(Debug, PartialEq)]
+pub(crate) struct DeserializeError {
+    pub(crate) kind: DeserializeErrorKind,
+    /// Offset from the start of the buffer, inside a compressed or encrypted
+    /// body it counts the decoded bytes following the header
+    pub(crate) offset: usize,
+    /// Field path to the value, e.g. `trades[1].price`, empty for the message itself
+    pub(crate) path: String,
+    /// Bytes left from the failure to the end of the buffer, until `offset` is resolved
+    remaining: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum DeserializeErrorKind {
+    /// Buffer ends before the expected value
+    UnexpectedEof {
+        expected: String,
+    },
+    InvalidUtf8,
+    /// Bool other than 0x00 or 0x01
+    InvalidBool {
+        found: u8,
+    },
+    /// Varint beyond the range of i64
+    VarintOverflow,
+    /// Compact integer width other than 1, 2, 4 or 8
+    InvalidIntegerWidth {
+        found: u8,
+    },
+    /// Type indicator of no type allowed where it was found
+    UnknownType {
+        type_indicator: u8,
+    },
+    /// Map key of a type other than Integer/String/Uuid/Decimal
+    UnsupportedMapKey {
+        type_indicator: u8,
+    },
+    /// Array element of a type without a fixed size
+    UnsupportedArrayElement {
+        type_indicator: u8,
+    },
+    VersionMismatch {
+        found: u8,
+    },
+    /// Message length in the header disagreeing with the bytes it frames
+    LengthMismatch {
+        length: usize,
+        actual: usize,
+    },
+    /// Length prefix of a value disagreeing with the bytes of the value
+    ValueLengthMismatch {
+        length: usize,
+        actual: usize,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    SignatureMismatch,
+    /// Message written with another schema than the one reading it
+    SchemaMismatch {
+        expected: [u8; FINGERPRINT_SIZE],
+        found: [u8; FINGERPRINT_SIZE],
+    },
+    /// Message written with a schema missing from the registry reading it
+    UnknownSchema {
+        fingerprint: [u8; FINGERPRINT_SIZE],
+    },
+    /// Body compressed with a dictionary the reader wasn't given
+    UnknownDictionary {
+        id: u32,
+    },
+    /// Signature expected, but the message carries none
+    NotSigned,
+    /// Plain message expected, but the body is encrypted
+    Encrypted,
+    /// Encrypted message expected, but the body is plain
+    NotEncrypted,
+    DecryptionFailed,
+    InvalidCompressedBody(String),
+    /// Objects, lists, maps or enum payloads nested deeper than
+    /// [`DeserializeLimits::max_depth`]
+    DepthExceeded,
+    /// Field name repeated within an object, see [`DuplicateFields`]
+    DuplicateField,
+    /// Input beyond one of the [`DeserializeLimits`]
+    LimitExceeded {
+        limit: &'static str,
+        value: usize,
+        max: usize,
+    },
+    /// Well-formed message the reader rejects, e.g. for a missing field
+    Invalid(String),
+}
+
+impl DeserializeError {
+    /// Error at the start of `bytes`
+    fn new(kind: DeserializeErrorKind, bytes: &[u8]) -> Self {
+        DeserializeError {
+            kind,
+            offset: 0,
+            path: String::new(),
+            remaining: Some(bytes.len()),
+        }
+    }
+
+    /// Error at a known offset of the buffer
+    fn at(kind: DeserializeErrorKind, offset: usize) -> Self {
+        DeserializeError {
+            kind,
+            offset,
+            path: String::new(),
+            remaining: None,
+        }
+    }
+
+    /// Error about a message which was read fine, but doesn't make sense
+    pub(crate) fn invalid(reason: impl Into<String>) -> Self {
+        DeserializeError {
+            kind: DeserializeErrorKind::Invalid(reason.into()),
+            offset: 0,
+            path: String::new(),
+            remaining: None,
+        }
+    }
+
+    /// Same error inside the field or element `segment`, e.g. `price` or `[1]`
+    pub(crate) fn within(mut self, segment: impl Display) -> Self {
+        self.path = nest_path(segment, &self.path);
+        self
+    }
+
+    /// Same error in a slice of the buffer which is followed by `bytes`
+    fn followed_by(mut self, bytes: &[u8]) -> Self {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining += bytes.len();
+        }
+        self
+    }
+
+    /// Offset of the failure in a buffer of `length` bytes starting at `base`
+    fn resolve(mut self, length: usize, base: usize) -> Self {
+        if let Some(remaining) = self.remaining.take() {
+            self.offset = base + length.saturating_sub(remaining);
+        }
+        self
+    }
+}
+
+/// Fails with [`DeserializeErrorKind::LimitExceeded`] at the start of `bytes`
+/// when `value` exceeds `max`
+fn check_limit(
+    limit: &'static str,
+    value: usize,
+    max: usize,
+    bytes: &[u8],
+) -> Result<(), DeserializeError> {
+    if value > max {
+        let kind = DeserializeErrorKind::LimitExceeded { limit, value, max };
+        return Err(DeserializeError::new(kind, bytes));
+    }
+    Ok(())
+}
+
+/// Fails unless `bytes` can hold `count` values of at least one byte each,
+/// so a made up count can't drive the allocation
+fn check_count(count: usize, bytes: &[u8]) -> Result<(), DeserializeError> {
+    if count > bytes.len() {
+        return Err(eof(format!("{} values", count), bytes));
+    }
+    Ok(())
+}
+
+/// [`DeserializeErrorKind::UnexpectedEof`] at the start of `bytes`
+fn eof(expected: impl Into<String>, bytes: &[u8]) -> DeserializeError {
+    let expected = expected.into();
+    DeserializeError::new(DeserializeErrorKind::UnexpectedEof { expected }, bytes)
+}
+
+impl Display for DeserializeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeErrorKind::UnexpectedEof { expected } => {
+                write!(f, "expected {}, end of buffer!", expected)
+            }
+            DeserializeErrorKind::InvalidUtf8 => write!(f, "invalid utf-8 string"),
+            DeserializeErrorKind::InvalidBool { found } => {
+                write!(f, "expected bool (0x00 or 0x01), found: {:#04x}", found)
+            }
+            DeserializeErrorKind::VarintOverflow => write!(f, "varint does not fit into i64!"),
+            DeserializeErrorKind::InvalidIntegerWidth { found } => {
+                write!(f, "expected integer width 1, 2, 4 or 8, found: {}", found)
+            }
+            DeserializeErrorKind::UnknownType { type_indicator } => {
+                write!(f, "unsupported type {}", type_indicator)
+            }
+            DeserializeErrorKind::UnsupportedMapKey { type_indicator } => write!(
+                f,
+                "unsupported key type {}, expected one of {} = Integer, {} = String, {} = Uuid, {} = Decimal",
+                type_indicator, INTEGER_T, STRING_T, UUID_T, DECIMAL_T
+            ),
+            DeserializeErrorKind::UnsupportedArrayElement { type_indicator } => {
+                write!(f, "unsupported array element type {}", type_indicator)
+            }
+            DeserializeErrorKind::VersionMismatch { found } => write!(
+                f,
+                "expected version: {}, {} or {}, found: {}",
+                VERSION1, VERSION2, VERSION3, found
+            ),
+            DeserializeErrorKind::LengthMismatch { length, actual } => write!(
+                f,
+                "message length: {} does not match the {} bytes of the message",
+                length, actual
+            ),
+            DeserializeErrorKind::ValueLengthMismatch { length, actual } => write!(
+                f,
+                "value length: {} does not match the {} bytes of the value",
+                length, actual
+            ),
+            DeserializeErrorKind::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {:#010x}, found {:#010x}",
+                expected, found
+            ),
+            DeserializeErrorKind::SignatureMismatch => write!(f, "signature mismatch"),
+            DeserializeErrorKind::SchemaMismatch { expected, found } => write!(
+                f,
+                "schema mismatch: expected fingerprint {}, found {}",
+                format_fingerprint(expected),
+                format_fingerprint(found)
+            ),
+            DeserializeErrorKind::UnknownSchema { fingerprint } => write!(
+                f,
+                "unknown schema of fingerprint {}",
+                format_fingerprint(fingerprint)
+            ),
+            DeserializeErrorKind::UnknownDictionary { id } => {
+                write!(f, "unknown compression dictionary {:#010x}", id)
+            }
+            DeserializeErrorKind::NotSigned => write!(f, "message is not signed"),
+            DeserializeErrorKind::Encrypted => write!(f, "message is encrypted"),
+            DeserializeErrorKind::NotEncrypted => write!(f, "message is not encrypted"),
+            DeserializeErrorKind::DecryptionFailed => write!(f, "decryption failed"),
+            DeserializeErrorKind::InvalidCompressedBody(e) => {
+                write!(f, "invalid compressed body: {}", e)
+            }
+            DeserializeErrorKind::DepthExceeded => write!(f, "values nested too deeply"),
+            DeserializeErrorKind::DuplicateField => write!(f, "duplicate field"),
+            DeserializeErrorKind::LimitExceeded { limit, value, max } => {
+                write!(f, "{}: {} exceeds the limit of {}", limit, value, max)
+            }
+            DeserializeErrorKind::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{} at byte {}", self.kind, self.offset)
+        } else {
+            write!(f, "{}: {} at byte {}", self.path, self.kind, self.offset)
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// `path` inside the field or element `segment`, e.g. `fills` and `[2].id`
+/// make `fills[2].id`
+fn nest_path(segment: impl Display, path: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else if path.starts_with(['[', '(']) {
+        format!("{}{}", segment, path)
+    } else {
+        format!("{}.{}", segment, path)
+    }
+}
+
+/// Value of another format, e.g. JSON, without a counterpart in a message
+#[derive(Debug, PartialEq)]
+pub(crate) enum ConvertError {
+    /// Value the mapping doesn't cover, with the path to it, e.g. `fills[2].id`
+    Unsupported { path: String, reason: String },
+    /// Fields which don't fit into a message, e.g. a name of over 255 bytes
+    Serialize(SerializeError),
+}
+
+impl ConvertError {
+    pub(crate) fn unsupported(reason: impl Into<String>) -> Self {
+        ConvertError::Unsupported {
+            path: String::new(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Same error inside the field or element `segment`, e.g. `price` or `[1]`
+    pub(crate) fn within(self, segment: impl Display) -> Self {
+        match self {
+            ConvertError::Unsupported { path, reason } => ConvertError::Unsupported {
+                path: nest_path(segment, &path),
+                reason,
+            },
+            error => error,
+        }
+    }
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Unsupported { path, reason } if path.is_empty() => {
+                write!(f, "{}", reason)
+            }
+            ConvertError::Unsupported { path, reason } => write!(f, "{}: {}", path, reason),
+            ConvertError::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<SerializeError> for ConvertError {
+    fn from(error: SerializeError) -> Self {
+        ConvertError::Serialize(error)
+    }
+}
+
+pub(crate) trait Deserializable: Sized {
+    fn deserialize(bytes: &[u8], count: Option<usize>) -> Result<(Self, &[u8]), DeserializeError> {
+        Self::deserialize_with(bytes, count, Encoding::default())
+            .map_err(|e| e.resolve(bytes.len(), 0))
+    }
+
+    fn deserialize_with(
+        bytes: &[u8],
+        count: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError>;
+}
+
+/// [Integer - 8 bytes] or since version 2 [Integer - zig-zag LEB128, 1-10 bytes]
+impl Serializable for i64 {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        if encoding.version >= VERSION2 {
+            serialize_varint(*self, bytes);
+            return Ok(());
+        }
+        bytes.extend(self.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        if encoding.version >= VERSION2 {
+            return varint_len(*self);
+        }
+        std::mem::size_of::<i64>()
+    }
+}
+
+/// [Integer - 8 bytes] or since version 2 [Integer - zig-zag LEB128, 1-10 bytes]
+impl Deserializable for i64 {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        if encoding.version >= VERSION2 {
+            return deserialize_varint(bytes);
+        }
+        let Some(integer) = bytes
+            .get(..std::mem::size_of::<i64>())
+            .and_then(|b| b.try_into().ok())
+            .map(i64::from_be_bytes)
+        else {
+            return Err(eof("i64", bytes));
+        };
+
+        let bytes = match bytes.get(std::mem::size_of::<i64>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((integer, bytes))
+    }
+}
+
+/// [Integer - zig-zag LEB128, 1-10 bytes]
+fn serialize_varint(integer: i64, bytes: &mut Vec<u8>) {
+    let mut zigzag = ((integer << 1) ^ (integer >> 63)) as u64;
+    loop {
+        let byte = (zigzag & 0x7F) as u8;
+        zigzag >>= 7;
+        if zigzag == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Bytes of the integer as a varint
+fn varint_len(integer: i64) -> usize {
+    let zigzag = ((integer << 1) ^ (integer >> 63)) as u64;
+    let bits = u64::BITS - zigzag.leading_zeros();
+    bits.max(1).div_ceil(7) as usize
+}
+
+/// [Integer - zig-zag LEB128, 1-10 bytes]
+fn deserialize_varint(bytes: &[u8]) -> Result<(i64, &[u8]), DeserializeError> {
+    let mut zigzag = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+        let overflow = i == 9 && *byte > 0x01;
+        if i > 9 || overflow {
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::VarintOverflow,
+                &bytes[i..],
+            ));
+        }
+        zigzag |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            let integer = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+            return Ok((integer, &bytes[i + 1..]));
+        }
+    }
+    Err(eof("varint", bytes))
+}
+
+/// Smallest width in bytes (1, 2, 4 or 8) which holds the integer
+fn integer_width(integer: i64) -> u8 {
+    if i8::try_from(integer).is_ok() {
+        1
+    } else if i16::try_from(integer).is_ok() {
+        2
+    } else if i32::try_from(integer).is_ok() {
+        4
+    } else {
+        8
+    }
+}
+
+/// [Integer - width bytes]
+fn serialize_compact_integer(integer: i64, width: u8, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&integer.to_be_bytes()[8 - width as usize..]);
+}
+
+/// [Width (1 byte)]
+fn deserialize_width(bytes: &[u8]) -> Result<(u8, &[u8]), DeserializeError> {
+    let width = *bytes
+        .first()
+        .ok_or_else(|| eof("u8 (integer width)", bytes))?;
+    if ![1, 2, 4, 8].contains(&width) {
+        return Err(DeserializeError::new(
+            DeserializeErrorKind::InvalidIntegerWidth { found: width },
+            bytes,
+        ));
+    }
+    let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    Ok((width, bytes))
+}
+
+/// [Integer - width bytes], sign extended to 8 bytes
+fn deserialize_compact_integer(bytes: &[u8], width: u8) -> Result<(i64, &[u8]), DeserializeError> {
+    let width = width as usize;
+    let value = bytes
+        .get(..width)
+        .ok_or_else(|| eof(format!("{} byte integer", width), bytes))?;
+    let mut integer = if value[0] & 0x80 == 0 {
+        [0x00; 8]
+    } else {
+        [0xFF; 8]
+    };
+    integer[8 - width..].copy_from_slice(value);
+    let bytes = match bytes.get(width..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    Ok((i64::from_be_bytes(integer), bytes))
+}
+
+/// [IEEE-754 Double - 8 bytes]
+impl Serializable for f64 {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.extend(self.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        std::mem::size_of::<f64>()
+    }
+}
+
+/// [IEEE-754 Double - 8 bytes]
+impl Deserializable for f64 {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let Some(float) = bytes
+            .get(..std::mem::size_of::<f64>())
+            .and_then(|b| b.try_into().ok())
+            .map(f64::from_be_bytes)
+        else {
+            return Err(eof("f64", bytes));
+        };
+
+        let bytes = match bytes.get(std::mem::size_of::<f64>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((float, bytes))
+    }
+}
+
+/// [Boolean - 1 byte, 0x00 = false, 0x01 = true]
+impl Serializable for bool {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.push(*self as u8);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        std::mem::size_of::<u8>()
+    }
+}
+
+/// [Boolean - 1 byte, 0x00 = false, 0x01 = true]
+impl Deserializable for bool {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let boolean = match bytes.first() {
+            Some(0x00) => false,
+            Some(0x01) => true,
+            Some(b) => {
+                return Err(DeserializeError::new(
+                    DeserializeErrorKind::InvalidBool { found: *b },
+                    bytes,
+                ));
+            }
+            None => {
+                return Err(eof("bool", bytes));
+            }
+        };
+        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((boolean, bytes))
+    }
+}
+
+/// [UUID - 16 bytes]
+impl Serializable for [u8; 16] {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.extend_from_slice(self);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        16
+    }
+}
+
+/// [UUID - 16 bytes]
+impl Deserializable for [u8; 16] {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let uuid: [u8; 16] = bytes
+            .get(..16)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| eof("16 byte uuid", bytes))?;
+        let bytes = match bytes.get(16..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((uuid, bytes))
+    }
+}
+
+/// [Mantissa - 8 bytes][Exponent - 1 byte]
+impl Serializable for Decimal {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        self.mantissa().serialize_into_with(bytes, encoding)?;
+        bytes.extend(self.exponent().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        self.mantissa().serialized_len_with(encoding) + std::mem::size_of::<i8>()
+    }
+}
+
+/// [Mantissa - 8 bytes][Exponent - 1 byte]
+impl Deserializable for Decimal {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (mantissa, bytes) =
+            i64::deserialize_with(bytes, None, encoding).map_err(|e| e.within("mantissa"))?;
+        let exponent = *bytes.first().ok_or_else(|| eof("i8 (exponent)", bytes))? as i8;
+        let bytes = match bytes.get(std::mem::size_of::<i8>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((Decimal::new(mantissa, exponent), bytes))
+    }
+}
+
+/// [Days since 1970-01-01 - 4 bytes]
+impl Serializable for Date {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.extend(self.days().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        std::mem::size_of::<i32>()
+    }
+}
+
+/// [Days since 1970-01-01 - 4 bytes]
+impl Deserializable for Date {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let Some(days) = bytes
+            .get(..std::mem::size_of::<i32>())
+            .and_then(|b| b.try_into().ok())
+            .map(i32::from_be_bytes)
+        else {
+            return Err(eof("i32 (days)", bytes));
+        };
+        let bytes = match bytes.get(std::mem::size_of::<i32>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((Date::from_days(days), bytes))
+    }
+}
+
+/// [Nanoseconds - 8 bytes, unsigned]
+impl Serializable for Duration {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        let nanos = u64::try_from(self.as_nanos())
+            .map_err(|_| SerializeError::DurationTooLong { duration: *self })?;
+        bytes.extend(nanos.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        std::mem::size_of::<u64>()
+    }
+}
+
+/// [Nanoseconds - 8 bytes, unsigned]
+impl Deserializable for Duration {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let Some(nanos) = bytes
+            .get(..std::mem::size_of::<u64>())
+            .and_then(|b| b.try_into().ok())
+            .map(u64::from_be_bytes)
+        else {
+            return Err(eof("u64 (nanoseconds)", bytes));
+        };
+        let bytes = match bytes.get(std::mem::size_of::<u64>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((Duration::from_nanos(nanos), bytes))
+    }
+}
+
+/// [Integer - 16 bytes]
+impl Serializable for i128 {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.extend(self.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        std::mem::size_of::<i128>()
+    }
+}
+
+/// [Integer - 16 bytes]
+impl Deserializable for i128 {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let Some(integer) = bytes
+            .get(..std::mem::size_of::<i128>())
+            .and_then(|b| b.try_into().ok())
+            .map(i128::from_be_bytes)
+        else {
+            return Err(eof("i128", bytes));
+        };
+        let bytes = match bytes.get(std::mem::size_of::<i128>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        Ok((integer, bytes))
+    }
+}
+
+/// [Negative (1 byte)][Length (2 bytes)][Magnitude, big-endian]
+/// or [Negative (1 byte)][0xFFFF][Length (4 bytes)][Magnitude, big-endian]
+///
+/// Zero has no magnitude bytes, others no leading zero bytes.
+impl Serializable for BigInt {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let (sign, magnitude) = self.to_bytes_be();
+        let magnitude = match sign {
+            Sign::NoSign => &[][..],
+            _ => &magnitude[..],
+        };
+        (sign == Sign::Minus).serialize_into_with(bytes, encoding)?;
+        serialize_length(magnitude.len(), "bigint length", bytes)?;
+        bytes.extend_from_slice(magnitude);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        let magnitude = self.magnitude().bits().div_ceil(8) as usize;
+        std::mem::size_of::<bool>() + length_len(magnitude) + magnitude
+    }
+}
+
+/// [Negative (1 byte)][Length (2 bytes)][Magnitude, big-endian]
+/// or [Negative (1 byte)][0xFFFF][Length (4 bytes)][Magnitude, big-endian]
+impl Deserializable for BigInt {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (negative, bytes) = bool::deserialize_with(bytes, None, encoding)?;
+        let (length, bytes) = deserialize_length(bytes, "bigint length")?;
+        let magnitude = bytes
+            .get(..length)
+            .ok_or_else(|| eof(format!("bigint of length {}", length), bytes))?;
+        let sign = if negative { Sign::Minus } else { Sign::Plus };
+        Ok((BigInt::from_bytes_be(sign, magnitude), &bytes[length..]))
+    }
+}
+
+/// [UTF-8 Data]
+impl Serializable for String {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        bytes.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        self.len()
+    }
+}
+
+/// [UTF-8 Data]
+impl Deserializable for String {
+    fn deserialize_with(
+        bytes: &[u8],
+        count: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (string, bytes) = deserialize_str(bytes, count.unwrap_or(0), encoding)?;
+        Ok((String::from(string), bytes))
+    }
+}
+
+/// UTF-8 string of `count` bytes, borrowed from the input
+fn deserialize_str(
+    bytes: &[u8],
+    count: usize,
+    encoding: Encoding,
+) -> Result<(&str, &[u8]), DeserializeError> {
+    if count == 0 {
+        return Ok(("", bytes));
+    }
+    let max = encoding.limits.max_string_bytes;
+    check_limit("string bytes", count, max, bytes)?;
+
+    let string = bytes
+        .get(..count)
+        .ok_or_else(|| eof(format!("string of length {}", count), bytes))?;
+    let string = if encoding.valid_utf8.holds(bytes, count) {
+        // SAFETY: the region is valid UTF-8 and the string starts and ends on
+        // character boundaries within it
+        unsafe { std::str::from_utf8_unchecked(string) }
+    } else {
+        from_utf8(string)
+            .map_err(|_| DeserializeError::new(DeserializeErrorKind::InvalidUtf8, bytes))?
+    };
+    let bytes = match bytes.get(count..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    Ok((string, bytes))
+}
+
+/// [Element 1][Element 2]...[Element N]
+impl<T: Serializable> Serializable for Vec<T> {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        for element in self {
+            element.serialize_into_with(bytes, encoding)?;
+        }
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        self.iter()
+            .map(|element| element.serialized_len_with(encoding))
+            .sum()
+    }
+}
+
+/// [Element 1][Element 2]...[Element N]
+impl<T: Deserializable> Deserializable for Vec<T> {
+    fn deserialize_with(
+        mut bytes: &[u8],
+        count: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let count = count.unwrap_or(0);
+        let encoding = encoding.reserve(count, std::mem::size_of::<T>(), bytes)?;
+
+        let mut list = Vec::with_capacity(count);
+        for i in 0..count {
+            let (element, next_bytes) = T::deserialize_with(bytes, None, encoding)
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            list.push(element);
+            bytes = next_bytes;
+        }
+
+        Ok((list, bytes))
+    }
+}
+
+/// [Value U][Value V]
+impl<U: Serializable, V: Serializable> Serializable for (U, V) {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        self.0.serialize_into_with(bytes, encoding)?;
+        self.1.serialize_into_with(bytes, encoding)
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        self.0.serialized_len_with(encoding) + self.1.serialized_len_with(encoding)
+    }
+}
+
+/// [Value U][Value V]
+impl<U: Deserializable + Display, V: Deserializable> Deserializable for (U, V) {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (u, bytes) = U::deserialize_with(bytes, None, encoding)?;
+        let (v, bytes) = V::deserialize_with(bytes, None, encoding).map_err(|e| e.within(&u))?;
+        Ok(((u, v), bytes))
+    }
+}
+
+/// [Key 1][Value 1][Key 2][Value 2]...[Key N][Value N]
+impl<K: Serializable, V: Serializable> Serializable for IndexMap<K, V> {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        for (key, value) in self {
+            key.serialize_into_with(bytes, encoding)?;
+            value.serialize_into_with(bytes, encoding)?;
+        }
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        self.iter()
+            .map(|(key, value)| {
+                key.serialized_len_with(encoding) + value.serialized_len_with(encoding)
+            })
+            .sum()
+    }
+}
+
+/// [Key 1][Value 1][Key 2][Value 2]...[Key N][Value N]
+impl<K: Deserializable + Eq + Hash + Display, V: Deserializable> Deserializable for IndexMap<K, V> {
+    fn deserialize_with(
+        mut bytes: &[u8],
+        count: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let count = count.unwrap_or(0);
+        // Entries and their slots in the index
+        let size = std::mem::size_of::<(K, V)>() + 2 * std::mem::size_of::<usize>();
+        let encoding = encoding.reserve(count, size, bytes)?;
+
+        let mut map = IndexMap::with_capacity(count);
+        for i in 0..count {
+            let (key, next_bytes) = K::deserialize_with(bytes, None, encoding)
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            let (value, next_bytes) =
+                V::deserialize_with(next_bytes, None, encoding).map_err(|e| e.within(&key))?;
+            match (map.contains_key(&key), encoding.duplicate_fields) {
+                (true, DuplicateFields::Error) => {
+                    let error = DeserializeError::new(DeserializeErrorKind::DuplicateField, bytes);
+                    return Err(error.within(&key));
+                }
+                (true, DuplicateFields::FirstWins) => {}
+                _ => {
+                    map.insert(key, value);
+                }
+            }
+            bytes = next_bytes;
+        }
+        Ok((map, bytes))
+    }
+}
+
+/// [Element Type (1 byte)][Element Count (2 bytes)][Elements...]
+/// Element is one of Integer/String/Object/Float/Bool/List/Mixed ([Type (1 byte)][Value])
+/// or [Compact Integer (1 byte)][Width (1 byte)][Element Count (2 bytes)][Elements...]
+/// Element Count is [0xFFFF][Count (4 bytes)] for 65,535 elements and more
+impl Serializable for List {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        if let Some(width) = self.compact_width(encoding)
+            && let List::Integers(integers) = self
+        {
+            bytes.extend([COMPACT_INTEGER_T, width]);
+            serialize_length(self.len(), "list elements", bytes)?;
+            for integer in integers {
+                serialize_compact_integer(*integer, width, bytes);
+            }
+            return Ok(());
+        }
+        let element_type = match self {
+            List::Integers(_) => INTEGER_T,
+            List::Strings(_) => STRING_T,
+            List::Objects(_) => OBJECT_T,
+            List::Floats(_) => FLOAT_T,
+            List::Bools(_) => BOOL_T,
+            List::Lists(_) => LIST_T,
+            List::Mixed(_) => MIXED_T,
+        };
+        bytes.push(element_type);
+        serialize_length(self.len(), "list elements", bytes)?;
+        match self {
+            List::Integers(integers) => integers.serialize_into_with(bytes, encoding),
+            List::Strings(strings) => strings.serialize_into_with(bytes, encoding),
+            List::Objects(objects) => objects.serialize_into_with(bytes, encoding),
+            List::Floats(floats) => floats.serialize_into_with(bytes, encoding),
+            List::Bools(bools) => bools.serialize_into_with(bytes, encoding),
+            List::Lists(lists) => lists.serialize_into_with(bytes, encoding),
+            List::Mixed(values) => values.serialize_into_with(bytes, encoding),
+        }
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let count = length_len(self.len());
+        if let Some(width) = self.compact_width(encoding) {
+            return 2 + count + self.len() * width as usize;
+        }
+        let elements = match self {
+            List::Integers(integers) => integers.serialized_len_with(encoding),
+            List::Strings(strings) => strings.serialized_len_with(encoding),
+            List::Objects(objects) => objects.serialized_len_with(encoding),
+            List::Floats(floats) => floats.serialized_len_with(encoding),
+            List::Bools(bools) => bools.serialized_len_with(encoding),
+            List::Lists(lists) => lists.serialized_len_with(encoding),
+            List::Mixed(values) => values.serialized_len_with(encoding),
+        };
+        1 + count + elements
+    }
+}
+
+/// [Element Type (1 byte)][Element Count (2 bytes)][Elements...]
+/// Element is one of Integer/String/Object/Float/Bool/List/Mixed ([Type (1 byte)][Value])
+/// or [Compact Integer (1 byte)][Width (1 byte)][Element Count (2 bytes)][Elements...]
+/// Element Count is [0xFFFF][Count (4 bytes)] for 65,535 elements and more
+impl Deserializable for List {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let element_type = *bytes
+            .first()
+            .ok_or_else(|| eof("u8 (element type)", bytes))?;
+        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        let (width, bytes) = match element_type {
+            COMPACT_INTEGER_T => deserialize_width(bytes)?,
+            _ => (0, bytes),
+        };
+
+        let (count, bytes) = deserialize_length(bytes, "count")?;
+        let (elements, bytes) = match element_type {
+            INTEGER_T => {
+                let (integers, bytes) = Vec::<i64>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Integers(integers), bytes)
+            }
+            STRING_T => {
+                let (strings, bytes) =
+                    Vec::<StringValue>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Strings(strings), bytes)
+            }
+            OBJECT_T => {
+                #[cfg(feature = "parallel")]
+                if parallel::splits(count, encoding) {
+                    let (objects, bytes) = parallel::deserialize_objects(bytes, count, encoding)?;
+                    return Ok((List::Objects(objects), bytes));
+                }
+                let (objects, bytes) =
+                    Vec::<Object>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Objects(objects), bytes)
+            }
+            FLOAT_T => {
+                let (floats, bytes) = Vec::<f64>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Floats(floats), bytes)
+            }
+            BOOL_T => {
+                let (bools, bytes) = Vec::<bool>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Bools(bools), bytes)
+            }
+            LIST_T => {
+                let (lists, bytes) = Vec::<List>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Lists(lists), bytes)
+            }
+            MIXED_T => {
+                let (values, bytes) =
+                    Vec::<FieldValue>::deserialize_with(bytes, Some(count), encoding)?;
+                (List::Mixed(values), bytes)
+            }
+            COMPACT_INTEGER_T => {
+                let mut integers = vec![];
+                let mut bytes = bytes;
+                for i in 0..count {
+                    let (integer, next_bytes) = deserialize_compact_integer(bytes, width)
+                        .map_err(|e| e.within(format!("[{}]", i)))?;
+                    integers.push(integer);
+                    bytes = next_bytes;
+                }
+                (List::Integers(integers), bytes)
+            }
+            t => {
+                return Err(DeserializeError::new(
+                    DeserializeErrorKind::UnknownType { type_indicator: t },
+                    bytes,
+                ));
+            }
+        };
+        Ok((elements, bytes))
+    }
+}
+
+/// Length of a string value or list escaping to the long form with a 4 byte length
+const LONG_LENGTH_ESCAPE: u16 = u16::MAX;
+
+impl List {
+    /// Width of the elements of an integer list written as compact integers,
+    /// `None` when it is written as a plain list
+    fn compact_width(&self, encoding: Encoding) -> Option<u8> {
+        let List::Integers(integers) = self else {
+            return None;
+        };
+        if !encoding.compact_integers || encoding.version >= VERSION2 {
+            return None;
+        }
+        let width = integers
+            .iter()
+            .copied()
+            .map(integer_width)
+            .max()
+            .unwrap_or(1);
+        (width < 8).then_some(width)
+    }
+}
+
+/// Bytes of the length as written by [`serialize_length`]
+fn length_len(length: usize) -> usize {
+    match length < LONG_LENGTH_ESCAPE as usize {
+        true => std::mem::size_of::<u16>(),
+        false => std::mem::size_of::<u16>() + std::mem::size_of::<u32>(),
+    }
+}
+
+/// [Length (2 bytes)] or [0xFFFF][Length (4 bytes)] for lengths of 65,535 and more
+///
+/// The long form never fits into a version 1 message, `Message` switches to
+/// version 2 for those.
+fn serialize_length(
+    length: usize,
+    what: &'static str,
+    bytes: &mut Vec<u8>,
+) -> Result<(), SerializeError> {
+    if length < LONG_LENGTH_ESCAPE as usize {
+        bytes.extend((length as u16).to_be_bytes());
+        return Ok(());
+    }
+    check_length(what, length, u32::MAX as usize)?;
+    bytes.extend(LONG_LENGTH_ESCAPE.to_be_bytes());
+    bytes.extend((length as u32).to_be_bytes());
+    Ok(())
+}
+
+/// [Length (2 bytes)] or [0xFFFF][Length (4 bytes)] for lengths of 65,535 and more
+fn deserialize_length<'a>(
+    bytes: &'a [u8],
+    what: &str,
+) -> Result<(usize, &'a [u8]), DeserializeError> {
+    let length = bytes
+        .get(..std::mem::size_of::<u16>())
+        .and_then(|b| b.try_into().ok())
+        .map(u16::from_be_bytes)
+        .ok_or_else(|| eof(format!("u16 ({})", what), bytes))?;
+    let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    if length != LONG_LENGTH_ESCAPE {
+        return Ok((length as usize, bytes));
+    }
+
+    let length = bytes
+        .get(..std::mem::size_of::<u32>())
+        .and_then(|b| b.try_into().ok())
+        .map(u32::from_be_bytes)
+        .ok_or_else(|| eof(format!("u32 (long {})", what), bytes))?;
+    let bytes = match bytes.get(std::mem::size_of::<u32>()..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    Ok((length as usize, bytes))
+}
+
+/// [Length (2 bytes)][UTF-8 Data]
+/// or [0xFFFF][Length (4 bytes)][UTF-8 Data] for strings of 65,535 bytes and more
+impl Serializable for StringValue {
+    fn serialize_into_with(&self, bytes: &mut Vec<u8>, _: Encoding) -> Result<(), SerializeError> {
+        let string = self.0.as_bytes();
+        serialize_length(string.len(), "string value length", bytes)?;
+        bytes.extend_from_slice(string);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        length_len(self.0.len()) + self.0.len()
+    }
+}
+
+/// [Length (2 bytes)][UTF-8 Data]
+/// or [0xFFFF][Length (4 bytes)][UTF-8 Data] for strings of 65,535 bytes and more
+impl Deserializable for StringValue {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (length, bytes) = deserialize_length(bytes, "length")?;
+        let (string, bytes) = String::deserialize_with(bytes, Some(length), encoding)?;
+        Ok((StringValue(string), bytes))
+    }
+}
+
+impl Display for FieldName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// [Length (1 byte)][UTF-8 Data]
+/// or [Field ID (2 bytes)] with field IDs
+impl Serializable for FieldName {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        if encoding.field_ids {
+            let id = self.id().ok_or_else(|| SerializeError::MissingFieldId {
+                name: String::from(self.as_str()),
+            })?;
+            bytes.extend(id.to_be_bytes());
+            return Ok(());
+        }
+        check_length("field name length", self.0.len(), u8::MAX as usize)?;
+        bytes.push(self.0.len() as u8);
+        bytes.extend_from_slice(self.0.as_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        if encoding.field_ids {
+            return std::mem::size_of::<u16>();
+        }
+        std::mem::size_of::<u8>() + self.0.len()
+    }
+}
+
+/// [Length (1 byte)][UTF-8 Data]
+/// or [Field ID (2 bytes)] with field IDs
+impl Deserializable for FieldName {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        if encoding.field_ids {
+            let id = bytes
+                .get(..std::mem::size_of::<u16>())
+                .and_then(|b| b.try_into().ok())
+                .map(u16::from_be_bytes)
+                .ok_or_else(|| eof("u16 (field ID)", bytes))?;
+            let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
+                Some(slice) => slice,
+                None => &[],
+            };
+            return Ok((FieldName::from_id(id), bytes));
+        }
+        let length = *bytes
+            .first()
+            .ok_or_else(|| eof("u8 (element type)", bytes))? as usize;
+        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        let (name, bytes) = deserialize_str(bytes, length, encoding)?;
+        Ok((FieldName::interned(name), bytes))
+    }
+}
+
+impl FieldValue {
+    /// Objects within the value at any depth, the value itself included
+    ///
+    /// Walks an explicit stack rather than recursing, so a deeply nested value
+    /// can't overflow the call stack.
+    fn objects(&self) -> Vec<&Object> {
+        enum Node<'a> {
+            Value(&'a FieldValue),
+            List(&'a List),
+        }
+
+        let mut objects = vec![];
+        let mut stack = vec![Node::Value(self)];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Value(Self::Object(object)) => {
+                    objects.push(object);
+                    stack.extend(object.0.values().map(Node::Value));
+                }
+                Node::Value(Self::List(list)) => stack.push(Node::List(list)),
+                Node::Value(Self::Map(Map(entries))) => {
+                    stack.extend(entries.iter().map(|(_, value)| Node::Value(value)));
+                }
+                Node::Value(Self::Enum(EnumValue {
+                    payload: Some(payload),
+                    ..
+                })) => stack.push(Node::Value(payload)),
+                Node::Value(_) => {}
+                Node::List(List::Objects(list)) => {
+                    for object in list {
+                        objects.push(object);
+                        stack.extend(object.0.values().map(Node::Value));
+                    }
+                }
+                Node::List(List::Lists(lists)) => stack.extend(lists.iter().map(Node::List)),
+                Node::List(List::Mixed(values)) => stack.extend(values.iter().map(Node::Value)),
+                Node::List(_) => {}
+            }
+        }
+        objects
+    }
+
+    /// Name of the type of the value, as used in error messages
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => "integer",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Object(_) => "object",
+            Self::Float(_) => "float",
+            Self::Bool(_) => "bool",
+            Self::Null => "null",
+            Self::Uuid(_) => "uuid",
+            Self::Decimal(_) => "decimal",
+            Self::Map(_) => "map",
+            Self::Enum(_) => "enum",
+            Self::Date(_) => "date",
+            Self::Duration(_) => "duration",
+            Self::Int128(_) => "int128",
+            Self::BigInt(_) => "bigint",
+            Self::Array(_) => "array",
+            Self::Unknown(..) => "unknown",
+        }
+    }
+
+    /// Same value with one representation for values which compare equal, see
+    /// [`Message::serialize_canonical`]
+    fn canonical(&self) -> FieldValue {
+        match self {
+            Self::Float(float) => Self::Float(canonical_float(*float)),
+            Self::Decimal(decimal) => Self::Decimal(decimal.normalize()),
+            Self::Object(Object(fields)) => Self::Object(Object(canonical_fields(fields))),
+            Self::List(list) => Self::List(list.canonical()),
+            Self::Map(Map(entries)) => {
+                let mut entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| (key.canonical(), value.canonical()))
+                    .collect();
+                entries.sort_by_cached_key(|(key, _)| key.serialize().unwrap_or_default());
+                Self::Map(Map(entries))
+            }
+            Self::Enum(EnumValue {
+                discriminant,
+                payload,
+            }) => Self::Enum(EnumValue {
+                discriminant: *discriminant,
+                payload: payload
+                    .as_ref()
+                    .map(|payload| Box::new(payload.canonical())),
+            }),
+            value => value.clone(),
+        }
+    }
+
+    /// Whether the value holds an object with more fields than version 1 can count
+    fn needs_version2(&self) -> bool {
+        self.objects()
+            .iter()
+            .any(|Object(fields)| fields.len() > u8::MAX as usize)
+    }
+
+    /// Fields of the objects within the value, at any depth
+    fn nested_fields(&self) -> usize {
+        self.objects()
+            .iter()
+            .map(|Object(fields)| fields.len())
+            .sum()
+    }
+
+    /// Appends the value without its type indicator, returns the indicator
+    fn serialize_value_into(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<u8, SerializeError> {
+        let type_indicator = match self {
+            Self::Integer(i)
+                if encoding.compact_integers
+                    && encoding.version < VERSION2
+                    && integer_width(*i) < 8 =>
+            {
+                let width = integer_width(*i);
+                bytes.push(width);
+                serialize_compact_integer(*i, width, bytes);
+                COMPACT_INTEGER_T
+            }
+            Self::Integer(i) => {
+                i.serialize_into_with(bytes, encoding)?;
+                INTEGER_T
+            }
+            Self::String(s) => {
+                s.serialize_into_with(bytes, encoding)?;
+                STRING_T
+            }
+            Self::List(l) => {
+                l.serialize_into_with(bytes, encoding)?;
+                LIST_T
+            }
+            Self::Object(o) => {
+                o.serialize_into_with(bytes, encoding)?;
+                OBJECT_T
+            }
+            Self::Float(f) => {
+                f.serialize_into_with(bytes, encoding)?;
+                FLOAT_T
+            }
+            Self::Bool(b) => {
+                b.serialize_into_with(bytes, encoding)?;
+                BOOL_T
+            }
+            Self::Null => NULL_T,
+            Self::Uuid(u) => {
+                u.serialize_into_with(bytes, encoding)?;
+                UUID_T
+            }
+            Self::Decimal(d) => {
+                d.serialize_into_with(bytes, encoding)?;
+                DECIMAL_T
+            }
+            Self::Map(m) => {
+                m.serialize_into_with(bytes, encoding)?;
+                MAP_T
+            }
+            Self::Enum(e) => {
+                e.serialize_into_with(bytes, encoding)?;
+                ENUM_T
+            }
+            Self::Date(d) => {
+                d.serialize_into_with(bytes, encoding)?;
+                DATE_T
+            }
+            Self::Duration(d) => {
+                d.serialize_into_with(bytes, encoding)?;
+                DURATION_T
+            }
+            Self::Int128(i) => {
+                i.serialize_into_with(bytes, encoding)?;
+                INT128_T
+            }
+            Self::BigInt(i) => {
+                i.serialize_into_with(bytes, encoding)?;
+                BIGINT_T
+            }
+            Self::Array(a) => {
+                a.serialize_into_with(bytes, encoding)?;
+                ARRAY_T
+            }
+            Self::Unknown(type_indicator, _) => {
+                return Err(SerializeError::UnknownType {
+                    type_indicator: *type_indicator,
+                });
+            }
+        };
+        Ok(type_indicator)
+    }
+
+    /// Bytes of the value without its type indicator
+    fn value_len(&self, encoding: Encoding) -> usize {
+        match self {
+            Self::Integer(i)
+                if encoding.compact_integers
+                    && encoding.version < VERSION2
+                    && integer_width(*i) < 8 =>
+            {
+                1 + integer_width(*i) as usize
+            }
+            Self::Integer(i) => i.serialized_len_with(encoding),
+            Self::String(s) => s.serialized_len_with(encoding),
+            Self::List(l) => l.serialized_len_with(encoding),
+            Self::Object(o) => o.serialized_len_with(encoding),
+            Self::Float(f) => f.serialized_len_with(encoding),
+            Self::Bool(b) => b.serialized_len_with(encoding),
+            Self::Null => 0,
+            Self::Uuid(u) => u.serialized_len_with(encoding),
+            Self::Decimal(d) => d.serialized_len_with(encoding),
+            Self::Map(m) => m.serialized_len_with(encoding),
+            Self::Enum(e) => e.serialized_len_with(encoding),
+            Self::Date(d) => d.serialized_len_with(encoding),
+            Self::Duration(d) => d.serialized_len_with(encoding),
+            Self::Int128(i) => i.serialized_len_with(encoding),
+            Self::BigInt(i) => i.serialized_len_with(encoding),
+            Self::Array(a) => a.serialized_len_with(encoding),
+            Self::Unknown(_, value) => value.len(),
+        }
+    }
+
+    /// Value of the given type, the type indicator has already been read
+    fn deserialize_value(
+        type_indicator: u8,
+        bytes: &[u8],
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (value, bytes) = match type_indicator {
+            INTEGER_T => {
+                let (integer, bytes) = i64::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Integer(integer), bytes)
+            }
+            STRING_T => {
+                let (string, bytes) = StringValue::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::String(string), bytes)
+            }
+            LIST_T => {
+                let (list, bytes) = List::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::List(list), bytes)
+            }
+            OBJECT_T => {
+                let (object, bytes) = Object::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Object(object), bytes)
+            }
+            FLOAT_T => {
+                let (float, bytes) = f64::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Float(float), bytes)
+            }
+            BOOL_T => {
+                let (boolean, bytes) = bool::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Bool(boolean), bytes)
+            }
+            NULL_T => (FieldValue::Null, bytes),
+            UUID_T => {
+                let (uuid, bytes) = <[u8; 16]>::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Uuid(uuid), bytes)
+            }
+            DECIMAL_T => {
+                let (decimal, bytes) = Decimal::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Decimal(decimal), bytes)
+            }
+            MAP_T => {
+                let (map, bytes) = Map::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Map(map), bytes)
+            }
+            ENUM_T => {
+                let (value, bytes) = EnumValue::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Enum(value), bytes)
+            }
+            DATE_T => {
+                let (date, bytes) = Date::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Date(date), bytes)
+            }
+            DURATION_T => {
+                let (duration, bytes) = Duration::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Duration(duration), bytes)
+            }
+            INT128_T => {
+                let (integer, bytes) = i128::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Int128(integer), bytes)
+            }
+            BIGINT_T => {
+                let (integer, bytes) = BigInt::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::BigInt(integer), bytes)
+            }
+            ARRAY_T => {
+                let (array, bytes) = Array::deserialize_with(bytes, None, encoding)?;
+                (FieldValue::Array(array), bytes)
+            }
+            COMPACT_INTEGER_T => {
+                let (width, bytes) = deserialize_width(bytes)?;
+                let (integer, bytes) = deserialize_compact_integer(bytes, width)?;
+                (FieldValue::Integer(integer), bytes)
+            }
+            t => {
+                return Err(DeserializeError::new(
+                    DeserializeErrorKind::UnknownType { type_indicator: t },
+                    bytes,
+                ));
+            }
+        };
+        Ok((value, bytes))
+    }
+}
+
+/// [Type (1 byte)][Integer/String/List/Object/Float/Bool/Null (no value)/Uuid/Decimal/Map/Enum/Date/Duration/Int128/BigInt/Array/Compact Integer]
+/// or since version 3 [Type (1 byte)][Length (2 bytes)][Value]
+/// Length is [0xFFFF][Length (4 bytes)] for values of 65,535 bytes and more
+impl Serializable for FieldValue {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let start = bytes.len();
+        bytes.push(0);
+        if encoding.version < VERSION3 {
+            bytes[start] = self.serialize_value_into(bytes, encoding)?;
+            return Ok(());
+        }
+
+        // The value goes after room for a short length, which is filled in
+        // once the value is known
+        bytes.extend([0; 2]);
+        bytes[start] = match self {
+            Self::Unknown(type_indicator, value) => {
+                bytes.extend_from_slice(value);
+                *type_indicator
+            }
+            value => value.serialize_value_into(bytes, encoding)?,
+        };
+        let length = bytes.len() - start - 3;
+        if length < LONG_LENGTH_ESCAPE as usize {
+            bytes[start + 1..start + 3].copy_from_slice(&(length as u16).to_be_bytes());
+            return Ok(());
+        }
+        let mut long_length = vec![];
+        serialize_length(length, "value length", &mut long_length)?;
+        bytes.splice(start + 1..start + 3, long_length);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let value = self.value_len(encoding);
+        if encoding.version >= VERSION3 {
+            return 1 + length_len(value) + value;
+        }
+        1 + value
+    }
+}
+
+/// [Type (1 byte)][Integer/String/List/Object/Float/Bool/Null (no value)/Uuid/Decimal/Map/Enum/Date/Duration/Int128/BigInt/Array/Compact Integer]
+/// or since version 3 [Type (1 byte)][Length (2 bytes)][Value]
+/// Length is [0xFFFF][Length (4 bytes)] for values of 65,535 bytes and more
+///
+/// Since version 3 values of unknown types are skipped and kept as [`FieldValue::Unknown`].
+impl Deserializable for FieldValue {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let type_indicator = *bytes
+            .first()
+            .ok_or_else(|| eof("u8 (type indicator)", bytes))?;
+        let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        if encoding.version < VERSION3 {
+            return FieldValue::deserialize_value(type_indicator, bytes, encoding);
+        }
+
+        let (length, value_bytes) = deserialize_length(bytes, "value length")?;
+        if length > value_bytes.len() {
+            return Err(eof(format!("value of {} bytes", length), value_bytes));
+        }
+        let (value_bytes, bytes) = value_bytes.split_at(length);
+        if !VALUE_TYPES.contains(&type_indicator) {
+            return Ok((
+                FieldValue::Unknown(type_indicator, value_bytes.to_vec()),
+                bytes,
+            ));
+        }
+        let (value, left) = FieldValue::deserialize_value(type_indicator, value_bytes, encoding)
+            .map_err(|e| e.followed_by(bytes))?;
+        if !left.is_empty() {
+            let kind = DeserializeErrorKind::ValueLengthMismatch {
+                length,
+                actual: length - left.len(),
+            };
+            return Err(DeserializeError::new(kind, value_bytes).followed_by(bytes));
+        }
+        Ok((value, bytes))
+    }
+}
+
+/// [Discriminant (2 bytes)][Payload Type (1 byte)][Payload], Null payload type when there is none
+impl Serializable for EnumValue {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        bytes.extend(self.discriminant.to_be_bytes());
+        match &self.payload {
+            Some(payload) => payload.serialize_into_with(bytes, encoding),
+            None => FieldValue::Null.serialize_into_with(bytes, encoding),
+        }
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let payload = match &self.payload {
+            Some(payload) => payload.serialized_len_with(encoding),
+            None => FieldValue::Null.serialized_len_with(encoding),
+        };
+        std::mem::size_of::<u16>() + payload
+    }
+}
+
+/// [Discriminant (2 bytes)][Payload Type (1 byte)][Payload], Null payload type when there is none
+impl Deserializable for EnumValue {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let discriminant = bytes
+            .get(..std::mem::size_of::<u16>())
+            .and_then(|b| b.try_into().ok())
+            .map(u16::from_be_bytes)
+            .ok_or_else(|| eof("u16 (discriminant)", bytes))?;
+        let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        let (payload, bytes) = FieldValue::deserialize_with(bytes, None, encoding)
+            .map_err(|e| e.within(format!("({})", discriminant)))?;
+        let payload = match payload {
+            FieldValue::Null => None,
+            payload => Some(Box::new(payload)),
+        };
+        Ok((
+            EnumValue {
+                discriminant,
+                payload,
+            },
+            bytes,
+        ))
+    }
+}
+
+/// [Key Type (1 byte)][Value Type (1 byte)][Entry Count (2 bytes)][Key 1][Value 1]...[Key N][Value N]
+/// Keys and values are written without their type indicators
+impl Serializable for Map {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        // Compact integers would mix two type indicators among the values
+        let encoding = Encoding {
+            compact_integers: false,
+            ..encoding
+        };
+        check_length("map entries", self.0.len(), u16::MAX as usize)?;
+        // Types are filled in from the first entry, [Integer, Null] when empty
+        let start = bytes.len();
+        bytes.extend([INTEGER_T, NULL_T]);
+        bytes.extend((self.0.len() as u16).to_be_bytes());
+        let (mut key_type, mut value_type) = (INTEGER_T, NULL_T);
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            let key_t = key.serialize_value_into(bytes, encoding)?;
+            let value_t = value.serialize_value_into(bytes, encoding)?;
+            if i == 0 {
+                if !MAP_KEY_TYPES.contains(&key_t) {
+                    return Err(SerializeError::UnsupportedMapKey {
+                        type_indicator: key_t,
+                    });
+                }
+                (key_type, value_type) = (key_t, value_t);
+            }
+            if key_t != key_type || value_t != value_type {
+                return Err(SerializeError::MixedMapTypes);
+            }
+        }
+        bytes[start..start + 2].copy_from_slice(&[key_type, value_type]);
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let encoding = Encoding {
+            compact_integers: false,
+            ..encoding
+        };
+        let entries: usize = self
+            .0
+            .iter()
+            .map(|(key, value)| key.value_len(encoding) + value.value_len(encoding))
+            .sum();
+        2 + std::mem::size_of::<u16>() + entries
+    }
+}
+
+/// [Key Type (1 byte)][Value Type (1 byte)][Entry Count (2 bytes)][Key 1][Value 1]...[Key N][Value N]
+/// Keys and values are written without their type indicators
+impl Deserializable for Map {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let (key_type, value_type) = match bytes.get(..2) {
+            Some(types) => (types[0], types[1]),
+            None => {
+                return Err(eof("u8 (key type) and u8 (value type)", bytes));
+            }
+        };
+        if !MAP_KEY_TYPES.contains(&key_type) {
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::UnsupportedMapKey {
+                    type_indicator: key_type,
+                },
+                bytes,
+            ));
+        }
+        let count = bytes
+            .get(2..4)
+            .and_then(|b| b.try_into().ok())
+            .map(u16::from_be_bytes)
+            .ok_or_else(|| eof("u16 (count)", bytes))? as usize;
+
+        let mut bytes = match bytes.get(4..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        let mut entries = vec![];
+        for i in 0..count {
+            let (key, next_bytes) = FieldValue::deserialize_value(key_type, bytes, encoding)
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            let (value, next_bytes) =
+                FieldValue::deserialize_value(value_type, next_bytes, encoding)
+                    .map_err(|e| e.within(format!("[{}]", map_key(&key))))?;
+            entries.push((key, value));
+            bytes = next_bytes;
+        }
+        Ok((Map(entries), bytes))
+    }
+}
+
+/// Bytes of an array element of the type, `None` for types without a fixed size
+fn fixed_size(type_indicator: u8) -> Option<usize> {
+    match type_indicator {
+        BOOL_T => Some(1),
+        DATE_T => Some(4),
+        INTEGER_T | FLOAT_T | DURATION_T => Some(8),
+        DECIMAL_T => Some(9),
+        UUID_T | INT128_T => Some(16),
+        _ => None,
+    }
+}
+
+/// Elements are written as in version 1, where integers take 8 bytes
+fn fixed_encoding(encoding: Encoding) -> Encoding {
+    Encoding {
+        version: VERSION1,
+        compact_integers: false,
+        ..encoding
+    }
+}
+
+/// [Element Type (1 byte)][Element Count (2 bytes)][Element 1]...[Element N]
+/// Elements are written without their type indicators, each in the fixed size
+/// of the element type
+impl Serializable for Array {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let encoding = fixed_encoding(encoding);
+        check_length("array elements", self.0.len(), u16::MAX as usize)?;
+        // Type is filled in from the first element, Integer when empty
+        let start = bytes.len();
+        bytes.push(INTEGER_T);
+        bytes.extend((self.0.len() as u16).to_be_bytes());
+        let mut element_type = INTEGER_T;
+        for (i, element) in self.0.iter().enumerate() {
+            let type_indicator = element.serialize_value_into(bytes, encoding)?;
+            if i == 0 {
+                if fixed_size(type_indicator).is_none() {
+                    return Err(SerializeError::UnsupportedArrayElement { type_indicator });
+                }
+                element_type = type_indicator;
+            }
+            if type_indicator != element_type {
+                return Err(SerializeError::MixedArrayTypes);
+            }
+        }
+        bytes[start] = element_type;
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let encoding = fixed_encoding(encoding);
+        let elements: usize = self.0.iter().map(|e| e.value_len(encoding)).sum();
+        1 + std::mem::size_of::<u16>() + elements
+    }
+}
+
+/// [Element Type (1 byte)][Element Count (2 bytes)][Element 1]...[Element N]
+/// Elements are written without their type indicators, each in the fixed size
+/// of the element type
+impl Deserializable for Array {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let encoding = fixed_encoding(encoding);
+        let element_type = *bytes
+            .first()
+            .ok_or_else(|| eof("u8 (element type)", bytes))?;
+        let Some(size) = fixed_size(element_type) else {
+            let kind = DeserializeErrorKind::UnsupportedArrayElement {
+                type_indicator: element_type,
+            };
+            return Err(DeserializeError::new(kind, bytes));
+        };
+        let count = bytes
+            .get(1..3)
+            .and_then(|b| b.try_into().ok())
+            .map(u16::from_be_bytes)
+            .ok_or_else(|| eof("u16 (count)", bytes))? as usize;
+
+        let mut bytes = match bytes.get(3..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        // The count fixes the length, checked before anything is allocated
+        if bytes.len() < count * size {
+            return Err(eof(format!("{} elements of {} bytes", count, size), bytes));
+        }
+        let encoding = encoding.reserve(count, std::mem::size_of::<FieldValue>(), bytes)?;
+        let mut elements = Vec::with_capacity(count);
+        for i in 0..count {
+            let (element, next_bytes) =
+                FieldValue::deserialize_value(element_type, bytes, encoding)
+                    .map_err(|e| e.within(format!("[{}]", i)))?;
+            elements.push(element);
+            bytes = next_bytes;
+        }
+        Ok((Array(elements), bytes))
+    }
+}
+
+/// Map key as it appears in a field path, e.g. `42` or `"BTC"`
+fn map_key(key: &FieldValue) -> String {
+    match key {
+        FieldValue::Integer(integer) => integer.to_string(),
+        FieldValue::String(StringValue(string)) => format!("{:?}", string),
+        FieldValue::Uuid(uuid) => format_uuid(uuid),
+        FieldValue::Decimal(decimal) => decimal.to_string(),
+        key => format!("{:?}", key),
+    }
+}
+
+/// [Field Count (1 byte)], [Field Count (2 bytes)] from version 2 on
+fn serialize_field_count(
+    count: usize,
+    what: &'static str,
+    encoding: Encoding,
+    bytes: &mut Vec<u8>,
+) -> Result<(), SerializeError> {
+    if encoding.version >= VERSION2 {
+        check_length(what, count, u16::MAX as usize)?;
+        bytes.extend((count as u16).to_be_bytes());
+        return Ok(());
+    }
+    check_length(what, count, u8::MAX as usize)?;
+    bytes.push(count as u8);
+    Ok(())
+}
+
+/// [Field Count (1 byte)], [Field Count (2 bytes)] from version 2 on
+fn deserialize_field_count(
+    bytes: &[u8],
+    encoding: Encoding,
+) -> Result<(usize, &[u8]), DeserializeError> {
+    if encoding.version >= VERSION2 {
+        let count = bytes
+            .get(..std::mem::size_of::<u16>())
+            .and_then(|b| b.try_into().ok())
+            .map(u16::from_be_bytes)
+            .ok_or_else(|| eof("u16 (count)", bytes))?;
+        let bytes = match bytes.get(std::mem::size_of::<u16>()..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        return Ok((count as usize, bytes));
+    }
+    let count = *bytes.first().ok_or_else(|| eof("u8 (count)", bytes))?;
+    let bytes = match bytes.get(std::mem::size_of::<u8>()..) {
+        Some(slice) => slice,
+        None => &[],
+    };
+    Ok((count as usize, bytes))
+}
+
+/// [Field Count (1 byte, 2 bytes from version 2 on)][Field 1][Field 2]...[Field N]
+impl Serializable for Object {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        serialize_field_count(self.0.len(), "fields per object", encoding, bytes)?;
+        self.0.serialize_into_with(bytes, encoding)
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let count = match encoding.version >= VERSION2 {
+            true => std::mem::size_of::<u16>(),
+            false => std::mem::size_of::<u8>(),
+        };
+        count + self.0.serialized_len_with(encoding)
+    }
+}
+
+/// [Field Count (1 byte, 2 bytes from version 2 on)][Field 1][Field 2]...[Field N]
+impl Deserializable for Object {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let (count, bytes) = deserialize_field_count(bytes, encoding)?;
+        let (object, bytes) = Fields::deserialize_with(bytes, Some(count), encoding)?;
+        Ok((Object(object), bytes))
+    }
+}
+
+impl Header {
+    /// Size of the header of the given protocol version
+    pub(crate) fn size(version: u8) -> usize {
+        match version {
+            VERSION1 => 4,
+            _ => 18,
+        }
+    }
+
+    /// Size of a header with the given flags, its fingerprint and dictionary
+    /// ID included
+    pub(crate) fn size_with_flags(version: u8, flags: u8) -> usize {
+        let fingerprint = match flags & FINGERPRINT_FLAG {
+            0 => 0,
+            _ => FINGERPRINT_SIZE,
+        };
+        let dictionary = match flags & DICTIONARY_FLAG {
+            0 => 0,
+            _ => DICTIONARY_ID_SIZE,
+        };
+        Header::size(version) + fingerprint + dictionary
+    }
+}
+
+/// Version 1:
+/// Byte 0: Protocol Version (0x01)
+/// Byte 1: Field Count (0-255)
+/// Bytes 2-3: Total Message Length (big-endian, includes header)
+///
+/// Version 2 and 3:
+/// Byte 0: Protocol Version (0x02 or 0x03)
+/// Byte 1: Flags
+/// Bytes 2-3: Message Type (big-endian)
+/// Bytes 4-11: Sequence Number (big-endian)
+/// Bytes 12-13: Field Count (0-65,535, big-endian)
+/// Bytes 14-17: Total Message Length (big-endian, includes header and trailer)
+/// Bytes 18-25: Schema Fingerprint with FINGERPRINT_FLAG
+/// Bytes 18-21, or 26-29 after a fingerprint: Dictionary ID with DICTIONARY_FLAG (big-endian)
+impl Serializable for Header {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let encoding = Encoding {
+            version: self.version,
+            ..encoding
+        };
+        let field_count = self.field_count as usize;
+        if self.version != VERSION1 {
+            // The flags follow the fingerprint and dictionary, so they can't
+            // disagree
+            let mut flags = self.flags & !(FINGERPRINT_FLAG | DICTIONARY_FLAG);
+            if self.fingerprint.is_some() {
+                flags |= FINGERPRINT_FLAG;
+            }
+            if self.dictionary.is_some() {
+                flags |= DICTIONARY_FLAG;
+            }
+            bytes.push(self.version);
+            bytes.push(flags);
+            bytes.extend(self.message_type.0.to_be_bytes());
+            bytes.extend(self.sequence.to_be_bytes());
+            serialize_field_count(field_count, "fields per message", encoding, bytes)?;
+            bytes.extend(self.length.to_be_bytes());
+            if let Some(fingerprint) = self.fingerprint {
+                bytes.extend(fingerprint);
+            }
+            if let Some(dictionary) = self.dictionary {
+                bytes.extend(dictionary.to_be_bytes());
+            }
+            return Ok(());
+        }
+
+        let unsupported = |field| SerializeError::UnsupportedInVersion {
+            version: VERSION1,
+            field,
+        };
+        if self.fingerprint.is_some() {
+            return Err(unsupported("schema fingerprint"));
+        }
+        if self.dictionary.is_some() {
+            return Err(unsupported("compression dictionary"));
+        }
+        if self.message_type != MessageType::UNTYPED {
+            return Err(unsupported("message type"));
+        }
+        if self.sequence != 0 {
+            return Err(unsupported("sequence number"));
+        }
+        if self.flags != 0 {
+            return Err(unsupported("flags"));
+        }
+        check_length("message length", self.length as usize, u16::MAX as usize)?;
+        bytes.push(self.version);
+        serialize_field_count(field_count, "fields per message", encoding, bytes)?;
+        bytes.extend((self.length as u16).to_be_bytes());
+        Ok(())
+    }
+
+    fn serialized_len_with(&self, _: Encoding) -> usize {
+        if self.version == VERSION1 {
+            return Header::size(self.version);
+        }
+        let fingerprint = self.fingerprint.map_or(0, |_| FINGERPRINT_SIZE);
+        let dictionary = self.dictionary.map_or(0, |_| DICTIONARY_ID_SIZE);
+        Header::size(self.version) + fingerprint + dictionary
+    }
+}
+
+/// Version 1:
+/// Byte 0: Protocol Version (0x01)
+/// Byte 1: Field Count (0-255)
+/// Bytes 2-3: Total Message Length (big-endian, includes header)
+///
+/// Version 2 and 3:
+/// Byte 0: Protocol Version (0x02 or 0x03)
+/// Byte 1: Flags
+/// Bytes 2-3: Message Type (big-endian)
+/// Bytes 4-11: Sequence Number (big-endian)
+/// Bytes 12-13: Field Count (0-65,535, big-endian)
+/// Bytes 14-17: Total Message Length (big-endian, includes header and trailer)
+/// Bytes 18-25: Schema Fingerprint with FINGERPRINT_FLAG
+/// Bytes 18-21, or 26-29 after a fingerprint: Dictionary ID with DICTIONARY_FLAG (big-endian)
+impl Deserializable for Header {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        _: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let version = *bytes.first().ok_or_else(|| eof("u8 (version)", bytes))?;
+        if !VERSIONS.contains(&version) {
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::VersionMismatch { found: version },
+                bytes,
+            ));
+        }
+        let size = Header::size(version);
+        let header = bytes
+            .get(..size)
+            .ok_or_else(|| eof(format!("{} byte header", size), bytes))?;
+        let (flags, message_type, sequence, field_count, length) = match version {
+            VERSION1 => (
+                0,
+                MessageType::UNTYPED,
+                0,
+                header[1] as u16,
+                u16::from_be_bytes([header[2], header[3]]) as u32,
+            ),
+            _ => (
+                header[1],
+                MessageType(u16::from_be_bytes([header[2], header[3]])),
+                u64::from_be_bytes(header[4..12].try_into().unwrap()),
+                u16::from_be_bytes([header[12], header[13]]),
+                u32::from_be_bytes(header[14..18].try_into().unwrap()),
+            ),
+        };
+        let bytes = match bytes.get(size..) {
+            Some(slice) => slice,
+            None => &[],
+        };
+        let (fingerprint, bytes) = match flags & FINGERPRINT_FLAG {
+            0 => (None, bytes),
+            _ => {
+                let fingerprint = bytes
+                    .first_chunk::<FINGERPRINT_SIZE>()
+                    .ok_or_else(|| eof("8 byte schema fingerprint", bytes))?;
+                (Some(*fingerprint), &bytes[FINGERPRINT_SIZE..])
+            }
+        };
+        let (dictionary, bytes) = match flags & DICTIONARY_FLAG {
+            0 => (None, bytes),
+            _ => {
+                let dictionary = bytes
+                    .first_chunk::<DICTIONARY_ID_SIZE>()
+                    .ok_or_else(|| eof("4 byte dictionary ID", bytes))?;
+                (
+                    Some(u32::from_be_bytes(*dictionary)),
+                    &bytes[DICTIONARY_ID_SIZE..],
+                )
+            }
+        };
+        let header = Header {
+            version,
+            flags,
+            message_type,
+            sequence,
+            field_count,
+            length,
+            fingerprint,
+            dictionary,
+        };
+        Ok((header, bytes))
+    }
+}
+
+/// [Header][Field 1][Field 2]...[Field N]
+/// [HMAC-SHA256 (32 bytes) with SIGNED_FLAG][CRC32C (4 bytes) with CHECKSUM_FLAG]
+/// Fields are a zstd frame with COMPRESSED_FLAG, compressed with the dictionary
+/// of the header's ID with DICTIONARY_FLAG
+/// Fields are [Nonce (12 bytes)][ChaCha20-Poly1305 sealed fields (+16 bytes)] with ENCRYPTED_FLAG
+/// Field names are [Field ID (2 bytes)] with FIELD_IDS_FLAG
+/// Fields are [Name Table][Field 1]...[Field N] with names as [Name Index (2 bytes)]
+/// with NAME_TABLE_FLAG
+///
+/// Field count and length in the header are computed from the body, a version 1
+/// message with a type, sequence number or flags, or one with more fields or
+/// bytes than it can count is written as version 2. Whether the body is
+/// compressed depends on the encoding only, whether it is signed on the method.
+impl Serializable for Message {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        self.serialize_frame_into(bytes, encoding, Protection::default())
+    }
+
+    fn serialized_len_with(&self, encoding: Encoding) -> usize {
+        let (version, flags, body_length) = self.frame_layout(encoding, Protection::default());
+        if flags & COMPRESSED_FLAG != 0 {
+            // How far the body compresses is only known by compressing it
+            return self.serialize_with(encoding).map_or(0, |bytes| bytes.len());
+        }
+        Header::size_with_flags(version, flags) + body_length + trailer_size(flags)
+    }
+}
+
+impl Message {
+    fn serialize_frame(
+        &self,
+        encoding: Encoding,
+        protection: Protection,
+    ) -> Result<Vec<u8>, SerializeError> {
+        let mut bytes = vec![];
+        self.serialize_frame_into(&mut bytes, encoding, protection)?;
+        Ok(bytes)
+    }
+
+    /// Version and flags of the message as written and the length of its body
+    /// before it is compressed or sealed
+    fn frame_layout(&self, encoding: Encoding, protection: Protection) -> (u8, u8, usize) {
+        let mut version = self.header.version;
+        let mut flags = self.header.flags
+            & !(COMPRESSED_FLAG
+                | SIGNED_FLAG
+                | ENCRYPTED_FLAG
+                | FIELD_IDS_FLAG
+                | NAME_TABLE_FLAG
+                | FINGERPRINT_FLAG
+                | DICTIONARY_FLAG);
+        if self.header.fingerprint.is_some() {
+            flags |= FINGERPRINT_FLAG;
+        }
+        let mut table_length = 0;
+        if encoding.field_ids {
+            flags |= FIELD_IDS_FLAG;
+        } else if encoding.name_table {
+            flags |= NAME_TABLE_FLAG;
+            table_length = name_table_len(&self.field_names());
+        }
+        if protection.signing_key.is_some() {
+            flags |= SIGNED_FLAG;
+        }
+        if protection.sealing_key.is_some() {
+            flags |= ENCRYPTED_FLAG;
+        }
+        if version == VERSION1
+            && (self.header.message_type != MessageType::UNTYPED
+                || self.header.sequence != 0
+                || flags != 0
+                || self.body.len() > u8::MAX as usize
+                || self.body.values().any(FieldValue::needs_version2))
+        {
+            version = VERSION2;
+        }
+        let body_length = |version| {
+            let encoding = Encoding {
+                version,
+                field_ids: flags & (FIELD_IDS_FLAG | NAME_TABLE_FLAG) != 0,
+                ..encoding
+            };
+            table_length + self.body.serialized_len_with(encoding)
+        };
+        let mut length = body_length(version);
+        if version == VERSION1 && Header::size(version) + length > u16::MAX as usize {
+            version = VERSION2;
+            length = body_length(version);
+        }
+        if encoding
+            .compression_threshold
+            .is_some_and(|threshold| length >= threshold)
+        {
+            if version == VERSION1 {
+                version = VERSION2;
+                length = body_length(version);
+            }
+            flags |= COMPRESSED_FLAG;
+            if protection.dictionary.is_some() {
+                flags |= DICTIONARY_FLAG;
+            }
+        }
+        (version, flags, length)
+    }
+
+    /// Appends the message, the body goes right after the header unless it is
+    /// compressed or sealed
+    fn serialize_frame_into(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+        protection: Protection,
+    ) -> Result<(), SerializeError> {
+        let (version, flags, mut body_length) = self.frame_layout(encoding, protection);
+        let body_encoding = Encoding {
+            version,
+            field_ids: flags & (FIELD_IDS_FLAG | NAME_TABLE_FLAG) != 0,
+            ..encoding
+        };
+        let names = match flags & NAME_TABLE_FLAG {
+            0 => None,
+            _ => Some(self.field_names()),
+        };
+        let renamed;
+        let fields = match &names {
+            Some(names) => {
+                let mut fields = self.body.clone();
+                rename_fields(&mut fields, &mut |name| {
+                    let index = names.get_index_of(name).expect("names cover the message");
+                    Ok::<_, SerializeError>(FieldName::from_id(index as u16))
+                })?;
+                renamed = fields;
+                &renamed
+            }
+            None => &self.body,
+        };
+        let write_body = |bytes: &mut Vec<u8>| {
+            if let Some(names) = &names {
+                serialize_name_table(names, bytes)?;
+            }
+            fields.serialize_into_with(bytes, body_encoding)
+        };
+        let buffered_body = || {
+            let mut body = vec![];
+            write_body(&mut body)?;
+            Ok::<_, SerializeError>(body)
+        };
+        let mut body = None;
+        if flags & COMPRESSED_FLAG != 0 {
+            let plain = buffered_body()?;
+            let compressed = compress(&plain, protection.dictionary);
+            body_length = compressed.len();
+            body = Some(compressed);
+        }
+        let sealing = match flags & ENCRYPTED_FLAG {
+            0 => 0,
+            _ => NONCE_SIZE + SEAL_TAG_SIZE,
+        };
+        let trailer = trailer_size(flags);
+        let length = Header::size_with_flags(version, flags) + sealing + body_length + trailer;
+        check_length("message length", length, u32::MAX as usize)?;
+        let header = Header {
+            version,
+            flags,
+            message_type: self.header.message_type,
+            sequence: self.header.sequence,
+            field_count: self.body.len() as u16,
+            length: length as u32,
+            fingerprint: self.header.fingerprint,
+            dictionary: protection
+                .dictionary
+                .filter(|_| flags & DICTIONARY_FLAG != 0)
+                .map(Dictionary::id),
+        };
+        bytes.reserve(length);
+        let start = bytes.len();
+        header.serialize_into_with(bytes, encoding)?;
+        match (protection.sealing_key, body) {
+            (Some(key), body) => {
+                let body = match body {
+                    Some(body) => body,
+                    None => buffered_body()?,
+                };
+                let payload = Payload {
+                    msg: &body,
+                    aad: &bytes[start..],
+                };
+                let sealed = ChaCha20Poly1305::new(key.into())
+                    .encrypt(&protection.nonce.into(), payload)
+                    .expect("sealing into memory does not fail");
+                bytes.extend(protection.nonce);
+                bytes.extend(sealed);
+            }
+            (None, Some(body)) => bytes.extend(body),
+            (None, None) => write_body(bytes)?,
+        }
+        if let Some(key) = protection.signing_key {
+            let mut mac = signature(key);
+            mac.update(&bytes[start..]);
+            bytes.extend(mac.finalize().into_bytes());
+        }
+        if flags & CHECKSUM_FLAG != 0 {
+            let checksum = crc32c::crc32c(&bytes[start..]).to_be_bytes();
+            bytes.extend(checksum);
+        }
+        Ok(())
+    }
+}
+
+/// Size of the Poly1305 tag at the end of a sealed body
+const SEAL_TAG_SIZE: usize = 16;
+
+/// Size of the signature and checksum following the body
+fn trailer_size(flags: u8) -> usize {
+    let signature = match flags & SIGNED_FLAG {
+        0 => 0,
+        _ => SIGNATURE_SIZE,
+    };
+    let checksum = match flags & CHECKSUM_FLAG {
+        0 => 0,
+        _ => CHECKSUM_SIZE,
+    };
+    signature + checksum
+}
+
+impl Message {
+    /// Distinct field names of the message and of its objects at any depth,
+    /// which make up its name table
+    fn field_names(&self) -> IndexSet<&FieldName> {
+        let mut names = IndexSet::new();
+        for (name, value) in &self.body {
+            names.insert(name);
+            for Object(fields) in value.objects() {
+                names.extend(fields.keys());
+            }
+        }
+        names
+    }
+}
+
+fn name_table_len(names: &IndexSet<&FieldName>) -> usize {
+    std::mem::size_of::<u16>() + names.iter().map(|name| 1 + name.0.len()).sum::<usize>()
+}
+
+/// [Name Count (2 bytes)][Name 1][Name 2]...[Name N]
+///
+/// Fields of the body refer to the names by their index in the table.
+fn serialize_name_table(
+    names: &IndexSet<&FieldName>,
+    bytes: &mut Vec<u8>,
+) -> Result<(), SerializeError> {
+    check_length("name table length", names.len(), u16::MAX as usize)?;
+    bytes.extend((names.len() as u16).to_be_bytes());
+    for name in names {
+        name.serialize_into_with(bytes, Encoding::default())?;
+    }
+    Ok(())
+}
+
+/// Bytes following the value at the start of `bytes`, found by the length
+/// prefix of version 3, `None` before it or when the length doesn't read
+fn skip_value(bytes: &[u8], encoding: Encoding) -> Option<&[u8]> {
+    if encoding.version < VERSION3 {
+        return None;
+    }
+    let (_, bytes) = bytes.split_first()?;
+    let (length, bytes) = deserialize_length(bytes, "value length").ok()?;
+    bytes.get(length..)
+}
+
+/// [Name Count (2 bytes)][Name 1][Name 2]...[Name N], no name twice
+fn deserialize_name_table(
+    bytes: &[u8],
+    encoding: Encoding,
+) -> Result<(IndexSet<FieldName>, &[u8]), DeserializeError> {
+    let (count, mut bytes) = deserialize_field_count(bytes, encoding)?;
+    let size = std::mem::size_of::<FieldName>() + 2 * std::mem::size_of::<usize>();
+    let encoding = encoding.reserve(count, size, bytes)?;
+    let encoding = Encoding {
+        field_ids: false,
+        ..encoding
+    };
+    let mut names = IndexSet::with_capacity(count);
+    for _ in 0..count {
+        let (name, rest) = FieldName::deserialize_with(bytes, None, encoding)?;
+        if names.contains(&name) {
+            let kind = DeserializeErrorKind::Invalid(format!(
+                "name table repeats field name {}",
+                name.as_str()
+            ));
+            return Err(DeserializeError::new(kind, bytes));
+        }
+        names.insert(name);
+        bytes = rest;
+    }
+    Ok((names, bytes))
+}
+
+/// Name of the table the field name read for an index stands for
+fn table_name(
+    names: &IndexSet<FieldName>,
+    name: &FieldName,
+) -> Result<FieldName, DeserializeErrorKind> {
+    name.id()
+        .and_then(|index| names.get_index(index as usize))
+        .cloned()
+        .ok_or_else(|| name_index_error(name, names.len()))
+}
+
+fn name_index_error(name: &FieldName, table_length: usize) -> DeserializeErrorKind {
+    DeserializeErrorKind::Invalid(format!(
+        "name index {} is out of a table of {} names",
+        name.as_str().trim_start_matches('#'),
+        table_length
+    ))
+}
+
+/// Renames the fields and those of the objects within them at any depth
+fn rename_fields<E>(
+    fields: &mut Fields,
+    rename: &mut impl FnMut(&FieldName) -> Result<FieldName, E>,
+) -> Result<(), E> {
+    rename_keys(fields, rename)?;
+    for value in fields.values_mut() {
+        rename_value(value, rename)?;
+    }
+    Ok(())
+}
+
+fn rename_keys<E>(
+    fields: &mut Fields,
+    rename: &mut impl FnMut(&FieldName) -> Result<FieldName, E>,
+) -> Result<(), E> {
+    *fields = std::mem::take(fields)
+        .into_iter()
+        .map(|(name, value)| Ok((rename(&name)?, value)))
+        .collect::<Result<_, E>>()?;
+    Ok(())
+}
+
+/// Renames the fields of the objects within the value at any depth, the value
+/// itself included, walking an explicit stack like [`FieldValue::objects`]
+fn rename_value<E>(
+    value: &mut FieldValue,
+    rename: &mut impl FnMut(&FieldName) -> Result<FieldName, E>,
+) -> Result<(), E> {
+    enum Node<'a> {
+        Value(&'a mut FieldValue),
+        List(&'a mut List),
+    }
+
+    let mut stack = vec![Node::Value(value)];
+    while let Some(node) = stack.pop() {
+        match node {
+            Node::Value(FieldValue::Object(Object(fields))) => {
+                rename_keys(fields, rename)?;
+                stack.extend(fields.values_mut().map(Node::Value));
+            }
+            Node::Value(FieldValue::List(list)) => stack.push(Node::List(list)),
+            Node::Value(FieldValue::Map(Map(entries))) => {
+                stack.extend(entries.iter_mut().map(|(_, value)| Node::Value(value)));
+            }
+            Node::Value(FieldValue::Enum(EnumValue {
+                payload: Some(payload),
+                ..
+            })) => stack.push(Node::Value(payload)),
+            Node::Value(_) => {}
+            Node::List(List::Objects(list)) => {
+                for Object(fields) in list {
+                    rename_keys(fields, rename)?;
+                    stack.extend(fields.values_mut().map(Node::Value));
+                }
+            }
+            Node::List(List::Lists(lists)) => stack.extend(lists.iter_mut().map(Node::List)),
+            Node::List(List::Mixed(values)) => stack.extend(values.iter_mut().map(Node::Value)),
+            Node::List(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// [Header][Field 1][Field 2]...[Field N]
+/// [HMAC-SHA256 (32 bytes) with SIGNED_FLAG][CRC32C (4 bytes) with CHECKSUM_FLAG]
+/// Fields are a zstd frame with COMPRESSED_FLAG, compressed with the dictionary
+/// of the header's ID with DICTIONARY_FLAG
+/// Fields are [Nonce (12 bytes)][ChaCha20-Poly1305 sealed fields (+16 bytes)] with ENCRYPTED_FLAG
+/// Field names are [Field ID (2 bytes)] with FIELD_IDS_FLAG
+/// Fields are [Name Table][Field 1]...[Field N] with names as [Name Index (2 bytes)]
+/// with NAME_TABLE_FLAG
+///
+/// The signature of signed messages is only verified by `deserialize_verified`.
+impl Deserializable for Message {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        Self::deserialize_frame(bytes, encoding, Protection::default())
+    }
+}
+
+/// Message split at its header with the trailer cut off
+struct Frame<'a> {
+    header: Header,
+    /// Encoding of the body, with the version and field IDs of the header
+    encoding: Encoding,
+    header_bytes: &'a [u8],
+    /// Body as written, possibly sealed or compressed
+    body: &'a [u8],
+    /// Bytes following the message
+    rest: &'a [u8],
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compress(plain: &[u8], dictionary: Option<&Dictionary>) -> Vec<u8> {
+    let dictionary = dictionary.map_or(&[][..], Dictionary::as_bytes);
+    zstd::bulk::Compressor::with_dictionary(zstd::DEFAULT_COMPRESSION_LEVEL, dictionary)
+        .and_then(|mut compressor| compressor.compress(plain))
+        .expect("compressing into memory does not fail")
+}
+
+/// Compresses with ruzstd, whose frames any zstd decoder reads
+///
+/// ruzstd can't compress with a dictionary, the frame is written without one,
+/// which decoders given the dictionary read all the same.
+#[cfg(target_arch = "wasm32")]
+fn compress(plain: &[u8], _: Option<&Dictionary>) -> Vec<u8> {
+    ruzstd::encoding::compress_to_vec(plain, ruzstd::encoding::CompressionLevel::Fastest)
+}
+
+/// Decompresses a zstd frame, stopping one byte past `max` so a small frame
+/// can't inflate into an unbounded allocation
+#[cfg(not(target_arch = "wasm32"))]
+fn decompress(
+    body: &[u8],
+    max: usize,
+    dictionary: Option<&Dictionary>,
+) -> std::io::Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    let dictionary = dictionary.map_or(&[][..], Dictionary::as_bytes);
+    zstd::Decoder::with_dictionary(body, dictionary)?
+        .take((max as u64).saturating_add(1))
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decompress(
+    body: &[u8],
+    max: usize,
+    dictionary: Option<&Dictionary>,
+) -> std::io::Result<Vec<u8>> {
+    let mut decoder = ruzstd::decoding::FrameDecoder::new();
+    if let Some(dictionary) = dictionary {
+        let dictionary = ruzstd::decoding::Dictionary::decode_dict(dictionary.as_bytes())
+            .map_err(std::io::Error::other)?;
+        decoder
+            .add_dict(dictionary)
+            .map_err(std::io::Error::other)?;
+    }
+    let mut decompressed = vec![];
+    ruzstd::decoding::StreamingDecoder::new_with_decoder(body, decoder)
+        .map_err(std::io::Error::other)?
+        .take((max as u64).saturating_add(1))
+        .read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Dictionary to decompress the body with, the given one if the header names
+/// it, fails when the header names another one
+fn body_dictionary<'a>(
+    header: &Header,
+    given: Option<&'a Dictionary>,
+) -> Result<Option<&'a Dictionary>, DeserializeError> {
+    match (header.dictionary, given) {
+        (None, _) => Ok(None),
+        (Some(id), Some(dictionary)) if dictionary.id() == id => Ok(Some(dictionary)),
+        (Some(id), _) => {
+            let kind = DeserializeErrorKind::UnknownDictionary { id };
+            Err(DeserializeError::at(kind, 0))
+        }
+    }
+}
+
+impl Message {
+    /// Splits the message off the bytes, its checksum and signature verified
+    /// and cut off, the body still as written
+    fn split_frame<'a>(
+        bytes: &'a [u8],
+        encoding: Encoding,
+        protection: Protection,
+    ) -> Result<Frame<'a>, DeserializeError> {
+        let old_bytes = bytes;
+        let (header, bytes) = Header::deserialize_with(bytes, None, encoding)
+            .map_err(|e| e.resolve(old_bytes.len(), 0))?;
+        let encoding = Encoding {
+            version: header.version,
+            field_ids: header.flags & (FIELD_IDS_FLAG | NAME_TABLE_FLAG) != 0,
+            ..encoding
+        };
+
+        let header_size = old_bytes.len() - bytes.len();
+        let length = header.length as usize;
+        let limits = encoding.limits;
+        if length > limits.max_message_size {
+            let kind = DeserializeErrorKind::LimitExceeded {
+                limit: "message size",
+                value: length,
+                max: limits.max_message_size,
+            };
+            return Err(DeserializeError::at(kind, 0));
+        }
+        if length > old_bytes.len() {
+            let expected = format!("message of {} bytes", length);
+            return Err(DeserializeError::at(
+                DeserializeErrorKind::UnexpectedEof { expected },
+                0,
+            ));
+        }
+        let trailer = trailer_size(header.flags);
+        if length < header_size + trailer {
+            let actual = header_size + trailer;
+            return Err(DeserializeError::at(
+                DeserializeErrorKind::LengthMismatch { length, actual },
+                0,
+            ));
+        }
+
+        let (mut frame, rest) = old_bytes.split_at(length);
+        if header.flags & CHECKSUM_FLAG != 0 {
+            let (checked, checksum) = frame.split_at(frame.len() - CHECKSUM_SIZE);
+            let expected = crc32c::crc32c(checked);
+            let found = u32::from_be_bytes(checksum.try_into().unwrap());
+            if expected != found {
+                return Err(DeserializeError::at(
+                    DeserializeErrorKind::ChecksumMismatch { expected, found },
+                    checked.len(),
+                ));
+            }
+            frame = checked;
+        }
+        if header.flags & SIGNED_FLAG != 0 {
+            let (signed, found) = frame.split_at(frame.len() - SIGNATURE_SIZE);
+            if let Some(key) = protection.signing_key {
+                let mut mac = signature(key);
+                mac.update(signed);
+                mac.verify_slice(found).map_err(|_| {
+                    DeserializeError::at(DeserializeErrorKind::SignatureMismatch, signed.len())
+                })?;
+            }
+            frame = signed;
+        } else if protection.signing_key.is_some() {
+            return Err(DeserializeError::at(DeserializeErrorKind::NotSigned, 0));
+        }
+
+        let (header_bytes, body) = frame.split_at(header_size);
+        Ok(Frame {
+            header,
+            encoding,
+            header_bytes,
+            body,
+            rest,
+        })
+    }
+
+    fn deserialize_frame<'a>(
+        bytes: &'a [u8],
+        encoding: Encoding,
+        protection: Protection,
+    ) -> Result<(Self, &'a [u8]), DeserializeError> {
+        let Frame {
+            header,
+            encoding,
+            header_bytes,
+            body,
+            rest,
+        } = Message::split_frame(bytes, encoding, protection)?;
+        let header_size = header_bytes.len();
+        let length = header.length as usize;
+        let limits = encoding.limits;
+
+        let opened;
+        let body = match (header.flags & ENCRYPTED_FLAG, protection.sealing_key) {
+            (0, None) => body,
+            (0, Some(_)) => {
+                let kind = DeserializeErrorKind::NotEncrypted;
+                return Err(DeserializeError::at(kind, 0));
+            }
+            (_, None) => return Err(DeserializeError::at(DeserializeErrorKind::Encrypted, 0)),
+            (_, Some(key)) => {
+                if body.len() < NONCE_SIZE + SEAL_TAG_SIZE {
+                    let expected = String::from("nonce and sealed body");
+                    return Err(DeserializeError::at(
+                        DeserializeErrorKind::UnexpectedEof { expected },
+                        header_size,
+                    ));
+                }
+                let (nonce, sealed) = body.split_at(NONCE_SIZE);
+                let payload = Payload {
+                    msg: sealed,
+                    aad: header_bytes,
+                };
+                opened = ChaCha20Poly1305::new(key.into())
+                    .decrypt(nonce.into(), payload)
+                    .map_err(|_| {
+                        DeserializeError::at(DeserializeErrorKind::DecryptionFailed, header_size)
+                    })?;
+                &opened[..]
+            }
+        };
+
+        let decompressed;
+        let body = match header.flags & COMPRESSED_FLAG {
+            0 => body,
+            _ => {
+                let dictionary = body_dictionary(&header, protection.dictionary)?;
+                decompressed =
+                    decompress(body, limits.max_message_size, dictionary).map_err(|e| {
+                        let kind = DeserializeErrorKind::InvalidCompressedBody(e.to_string());
+                        DeserializeError::at(kind, header_size)
+                    })?;
+                if decompressed.len() > limits.max_message_size {
+                    let kind = DeserializeErrorKind::LimitExceeded {
+                        limit: "decompressed body size",
+                        value: decompressed.len(),
+                        max: limits.max_message_size,
+                    };
+                    return Err(DeserializeError::at(kind, header_size));
+                }
+                &decompressed[..]
+            }
+        };
+        let (names, fields_bytes) = match header.flags & NAME_TABLE_FLAG {
+            0 => (None, body),
+            _ => {
+                let (names, bytes) = deserialize_name_table(body, encoding)
+                    .map_err(|e| e.resolve(body.len(), header_size))?;
+                (Some(names), bytes)
+            }
+        };
+        let encoding = Encoding {
+            valid_utf8: Utf8Region::of(fields_bytes),
+            ..encoding
+        };
+        let (mut fields, bytes) =
+            Fields::deserialize_with(fields_bytes, Some(header.field_count as usize), encoding)
+                .map_err(|e| e.resolve(body.len(), header_size))?;
+
+        let total_fields = fields.len()
+            + fields
+                .values()
+                .map(FieldValue::nested_fields)
+                .sum::<usize>();
+        if total_fields > limits.max_fields {
+            let kind = DeserializeErrorKind::LimitExceeded {
+                limit: "fields",
+                value: total_fields,
+                max: limits.max_fields,
+            };
+            return Err(DeserializeError::at(kind, header_size));
+        }
+
+        if !bytes.is_empty() {
+            let actual = length - bytes.len();
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::LengthMismatch { length, actual },
+                bytes,
+            )
+            .resolve(body.len(), header_size));
+        }
+        if let Some(names) = names {
+            rename_fields(&mut fields, &mut |name| table_name(&names, name))
+                .map_err(|kind| DeserializeError::at(kind, header_size))?;
+        }
+
+        Ok((
+            Message {
+                header,
+                body: fields,
+            },
+            rest,
+        ))
+    }
+
+    /// Reads as much of a damaged message as it can, e.g. out of a corrupt
+    /// capture file, returns the fields read with an error for each field that
+    /// wasn't
+    ///
+    /// A value which doesn't read is skipped to the next field by the length
+    /// prefix version 3 writes. Without one, or when a field name or length
+    /// doesn't read, reading stops at that field. A message cut short is read
+    /// as far as it goes, its checksum and signature aren't checked. Fails
+    /// only when the header or the name table doesn't read or the body can't
+    /// be opened, e.g. because it is encrypted.
+    pub(crate) fn deserialize_lossy(
+        bytes: &[u8],
+    ) -> Result<(Message, Vec<FieldError>), DeserializeError> {
+        let (header, rest) =
+            Header::deserialize(bytes, None).map_err(|e| e.resolve(bytes.len(), 0))?;
+        let header_size = bytes.len() - rest.len();
+        let encoding = Encoding {
+            version: header.version,
+            field_ids: header.flags & (FIELD_IDS_FLAG | NAME_TABLE_FLAG) != 0,
+            ..Encoding::default()
+        };
+        let body_length = (header.length as usize)
+            .saturating_sub(header_size + trailer_size(header.flags))
+            .min(rest.len());
+        let body = &rest[..body_length];
+        if header.flags & ENCRYPTED_FLAG != 0 {
+            return Err(DeserializeError::at(DeserializeErrorKind::Encrypted, 0));
+        }
+        let decompressed;
+        let body = match header.flags & COMPRESSED_FLAG {
+            0 => body,
+            _ => {
+                let max = encoding.limits.max_message_size;
+                decompressed =
+                    decompress(body, max, body_dictionary(&header, None)?).map_err(|e| {
+                        let kind = DeserializeErrorKind::InvalidCompressedBody(e.to_string());
+                        DeserializeError::at(kind, header_size)
+                    })?;
+                &decompressed[..]
+            }
+        };
+        let (names, mut bytes) = match header.flags & NAME_TABLE_FLAG {
+            0 => (None, body),
+            _ => {
+                let (names, bytes) = deserialize_name_table(body, encoding)
+                    .map_err(|e| e.resolve(body.len(), header_size))?;
+                (Some(names), bytes)
+            }
+        };
+
+        let mut fields = Fields::new();
+        let mut errors = vec![];
+        for index in 0..header.field_count as usize {
+            let mut unreadable = |name: Option<&FieldName>, error: DeserializeError| {
+                errors.push(FieldError::Unreadable {
+                    index,
+                    name: name.map(|name| String::from(name.as_str())),
+                    error: error.resolve(body.len(), header_size),
+                });
+            };
+            let (mut name, value_bytes) = match FieldName::deserialize_with(bytes, None, encoding) {
+                Ok(read) => read,
+                Err(e) => {
+                    unreadable(None, e);
+                    break;
+                }
+            };
+            let (value, next_bytes) =
+                match FieldValue::deserialize_with(value_bytes, None, encoding) {
+                    Ok(read) => read,
+                    Err(e) => {
+                        unreadable(Some(&name), e);
+                        match skip_value(value_bytes, encoding) {
+                            Some(next_bytes) => {
+                                bytes = next_bytes;
+                                continue;
+                            }
+                            None => break,
+                        }
+                    }
+                };
+            bytes = next_bytes;
+            let mut value = value;
+            if let Some(names) = &names {
+                let renamed = table_name(names, &name).and_then(|renamed| {
+                    rename_value(&mut value, &mut |name| table_name(names, name))?;
+                    Ok(renamed)
+                });
+                match renamed {
+                    Ok(renamed) => name = renamed,
+                    Err(kind) => {
+                        unreadable(Some(&name), DeserializeError::new(kind, value_bytes));
+                        continue;
+                    }
+                }
+            }
+            if fields.contains_key(&name) {
+                let kind = DeserializeErrorKind::DuplicateField;
+                unreadable(Some(&name), DeserializeError::new(kind, value_bytes));
+                continue;
+            }
+            fields.insert(name, value);
+        }
+        Ok((
+            Message {
+                header,
+                body: fields,
+            },
+            errors,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use galacticbuf_derive::{GalacticDeserialize, GalacticSerialize};
+
+    #[test]
+    fn simple_message() {
+        // Message: `user_id=1001, name="Alice", scores=[100, 200, 300]`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 3,
+                length: 69,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [
+                (FieldName::from("user_id"), FieldValue::Integer(1001)),
+                (
+                    FieldName::from("name"),
+                    FieldValue::String(StringValue(String::from("Alice"))),
+                ),
+                (
+                    FieldName::from("scores"),
+                    FieldValue::List(List::Integers(vec![100, 200, 300])),
+                ),
+            ]
+            .into(),
+        };
+        let binary_message: [u8; 69] = [
+            // Header (4 bytes):
+            0x01, //      - Protocol version
+            0x03, //      - 3 fields
+            0x00, 0x45, //  - Total length: 69 bytes
+            // Field 1 - user_id (integer):
+            0x07, //          - Name length: 7
+            0x75, 0x73, 0x65, 0x72, 0x5F, 0x69, 0x64, // - "user_id" in UTF-8
+            0x01, //          - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xE9, // - Value: 1001 (64-bit)
+            // Field 2 - name (string):
+            0x04, //                  - Name length: 4
+            0x6E, 0x61, 0x6D, 0x65, //  - "name" in UTF-8
+            0x02, //                  - Type: String
+            0x00, 0x05, //              - String length: 5
+            0x41, 0x6C, 0x69, 0x63, 0x65, // - "Alice" in UTF-8
+            //Field 3 - scores (list of integers):
+            0x06, //              - Name length: 6
+            0x73, 0x63, 0x6F, 0x72, 0x65, 0x73, // - "scores" in UTF-8
+            0x03, //              - Type: List
+            0x01, //              - Element type: Integer
+            0x00, 0x03, //          - Element count: 3
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //      - 10x00
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC8, //    - 20x00
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x2C, //    -30x00,
+        ];
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn list_of_objects() {
+        // ### Message with List of Objects: `timestamp=1698765432, trades=[{id:1, price:100}, {id:2, price:200}]`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 2,
+                length: 90,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [
+                (
+                    FieldName::from("timestamp"),
+                    FieldValue::Integer(1698765432),
+                ),
+                (
+                    FieldName::from("trades"),
+                    FieldValue::List(List::Objects(vec![
+                        Object(
+                            [
+                                (FieldName::from("id"), FieldValue::Integer(1)),
+                                (FieldName::from("price"), FieldValue::Integer(100)),
+                            ]
+                            .into(),
+                        ),
+                        Object(
+                            [
+                                (FieldName::from("id"), FieldValue::Integer(2)),
+                                (FieldName::from("price"), FieldValue::Integer(200)),
+                            ]
+                            .into(),
+                        ),
+                    ])),
+                ),
+            ]
+            .into(),
+        };
+        let binary_message: [u8; 90] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x02, //        - 2 fields
+            0x00, 0x5a, //  - Total length: 90 bytes
+            // Field 1 - timestamp (integer):
+            0x09, //        - Name length: 9
+            0x74, 0x69, 0x6D, 0x65, 0x73, 0x74, 0x61, 0x6D, 0x70, //    - "timestamp" in UTF-8
+            0x01, //        - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x65, 0x41, 0x1A, 0x78, //  - Value: 1698765432
+            // Field 2 - trades (list of objects):
+            0x06, //        - Name length: 6
+            0x74, 0x72, 0x61, 0x64, 0x65, 0x73, //  - "trades" in UTF-8
+            0x03, //        - Type: List
+            0x04, //        - Element type: Object
+            0x00, 0x02, //  - Element count: 2
+            // Object 1:
+            0x02, //        - Field count: 2
+            // Field: id
+            0x02, //        - Name length: 2
+            0x69, 0x64, //  - "id" in UTF-8
+            0x01, //        - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, //  - Value: 1
+            // Field: price
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x01, //       - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //  - Value: 100
+            // Object 2:
+            0x02, //        - Field count: 2
+            // Field: id
+            0x02, //        - Name length: 2
+            0x69, 0x64, //  - "id" in UTF-8
+            0x01, //        - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, //  - Value: 2
+            // Field: price
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x01, //        - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC8, //  - Value: 200
+        ];
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn float_field() {
+        // Message: `price=1.5`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 19,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("price"), FieldValue::Float(1.5))].into(),
+        };
+        let binary_message: [u8; 19] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x13, //  - Total length: 19 bytes
+            // Field 1 - price (float):
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x05, //        - Type: Float
+            0x3F, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //  - Value: 1.5
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn list_of_floats() {
+        // Message: `rates=[0.25, -2.0, f64::MAX]`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 38,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("rates"),
+                FieldValue::List(List::Floats(vec![0.25, -2.0, f64::MAX])),
+            )]
+            .into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 38);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn bool_fields() {
+        // Message: `fill={is_maker:true}, flags=[false, true]`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 2,
+                length: 34,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [
+                (
+                    FieldName::from("fill"),
+                    FieldValue::Object(Object(
+                        [(FieldName::from("is_maker"), FieldValue::Bool(true))].into(),
+                    )),
+                ),
+                (
+                    FieldName::from("flags"),
+                    FieldValue::List(List::Bools(vec![false, true])),
+                ),
+            ]
+            .into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 34);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn invalid_bool() {
+        let error = bool::deserialize(&[0x02], None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::InvalidBool { found: 0x02 }
+        );
+        assert_eq!(
+            error.to_string(),
+            "expected bool (0x00 or 0x01), found: 0x02 at byte 0"
+        );
+    }
+
+    #[test]
+    fn null_field() {
+        // Message: `price=null`
+        let binary_message: [u8; 11] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x0B, //  - Total length: 11 bytes
+            // Field 1 - price (null):
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x07, //        - Type: Null
+        ];
+        let (message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.get("price"), Some(&FieldValue::Null));
+        assert_eq!(message.get("quantity"), None);
+        assert_eq!(message.serialize().unwrap(), binary_message);
+    }
+
+    #[test]
+    fn uuid_field() {
+        let uuid = parse_uuid("67E55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(format_uuid(&uuid), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+
+        // Message: `order_id=67e55044-10b1-426f-9247-bb680e5fe0c8`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 30,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("order_id"), FieldValue::Uuid(uuid))].into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 30);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn invalid_uuid() {
+        assert_eq!(parse_uuid(""), None);
+        assert_eq!(parse_uuid("67e5504410b1426f9247bb680e5fe0c8"), None);
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0cg"), None);
+        assert_eq!(parse_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8-"), None);
+        assert_eq!(parse_uuid("+7e55044-10b1-426f-9247-bb680e5fe0c8"), None);
+    }
+
+    #[test]
+    fn decimal_field() {
+        // Message: `price=12.50`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 20,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("price"),
+                FieldValue::Decimal(Decimal::new(1250, -2)),
+            )]
+            .into(),
+        };
+        let binary_message: [u8; 20] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x14, //  - Total length: 20 bytes
+            // Field 1 - price (decimal):
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x09, //        - Type: Decimal
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xE2, //  - Mantissa: 1250
+            0xFE, //        - Exponent: -2
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn compact_integers() {
+        let encoding = Encoding {
+            compact_integers: true,
+            ..Encoding::default()
+        };
+        // Message: `count=5`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 13,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("count"), FieldValue::Integer(5))].into(),
+        };
+        let binary_message: [u8; 13] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x0D, //  - Total length: 13 bytes
+            // Field 1 - count (compact integer):
+            0x05, //        - Name length: 5
+            0x63, 0x6F, 0x75, 0x6E, 0x74, //    - "count" in UTF-8
+            0x0A, //        - Type: Compact Integer
+            0x01, //        - Width: 1 byte
+            0x05, //        - Value: 5
+        ];
+        assert_eq!(message.serialize_with(encoding).unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        let wide = FieldValue::Integer(i64::MAX);
+        assert_eq!(
+            wide.serialize_with(encoding).unwrap(),
+            wide.serialize().unwrap()
+        );
+
+        for (integers, length) in [
+            (vec![-1, 300, -70000], 16),
+            (vec![i64::MIN, 1], 19),
+            (vec![], 4),
+        ] {
+            let list = List::Integers(integers);
+            let binary_list = list.serialize_with(encoding).unwrap();
+            assert_eq!(binary_list.len(), length);
+            let (deserialized_list, bytes) = List::deserialize(&binary_list, None).unwrap();
+            assert_eq!(bytes.len(), 0);
+            assert_eq!(list, deserialized_list);
+        }
+    }
+
+    #[test]
+    fn version2_varint_integers() {
+        // Message: `user_id=1001`
+        let message = Message {
+            header: Header {
+                version: VERSION2,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 29,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("user_id"), FieldValue::Integer(1001))].into(),
+        };
+        let binary_message: [u8; 29] = [
+            // Header (18 bytes):
+            0x02, //        - Protocol version
+            0x00, //        - No flags
+            0x00, 0x00, //  - Untyped
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // - Not sequenced
+            0x00, 0x01, //  - 1 field
+            0x00, 0x00, 0x00, 0x1D, //  - Total length: 29 bytes
+            // Field 1 - user_id (integer):
+            0x07, //        - Name length: 7
+            0x75, 0x73, 0x65, 0x72, 0x5F, 0x69, 0x64, // - "user_id" in UTF-8
+            0x01, //        - Type: Integer
+            0xD2, 0x0F, //  - Value: 1001 (zig-zag 2002)
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        let encoding = Encoding {
+            version: VERSION2,
+            ..Encoding::default()
+        };
+        for (integer, length) in [
+            (0, 1),
+            (-1, 1),
+            (63, 1),
+            (-65, 2),
+            (i64::MIN, 10),
+            (i64::MAX, 10),
+        ] {
+            let binary_integer = integer.serialize_with(encoding).unwrap();
+            assert_eq!(binary_integer.len(), length, "{}", integer);
+            let (deserialized_integer, bytes) =
+                i64::deserialize_with(&binary_integer, None, encoding).unwrap();
+            assert_eq!(bytes.len(), 0);
+            assert_eq!(integer, deserialized_integer);
+        }
+    }
+
+    #[test]
+    fn invalid_varint() {
+        let error = deserialize_varint(&[0x80, 0x80]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::UnexpectedEof {
+                expected: String::from("varint")
+            }
+        );
+        let error = deserialize_varint(&[0xFF; 10]).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::VarintOverflow);
+        assert_eq!(error.resolve(10, 0).offset, 9);
+    }
+
+    #[test]
+    fn list_of_lists() {
+        // Message: `levels=[[100, 5], [101]]`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 45,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("levels"),
+                FieldValue::List(List::Lists(vec![
+                    List::Integers(vec![100, 5]),
+                    List::Integers(vec![101]),
+                ])),
+            )]
+            .into(),
+        };
+        let binary_message: [u8; 45] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x2D, //  - Total length: 45 bytes
+            // Field 1 - levels (list of lists):
+            0x06, //        - Name length: 6
+            0x6C, 0x65, 0x76, 0x65, 0x6C, 0x73, //  - "levels" in UTF-8
+            0x03, //        - Type: List
+            0x03, //        - Element type: List
+            0x00, 0x02, //  - Element count: 2
+            // List 1:
+            0x01, //        - Element type: Integer
+            0x00, 0x02, //  - Element count: 2
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //  - 100
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, //  - 5
+            // List 2:
+            0x01, //        - Element type: Integer
+            0x00, 0x01, //  - Element count: 1
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x65, //  - 101
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn mixed_list() {
+        // Message: `order=[7, "GAL", {}]`
         let message = Message {
             header: Header {
                 version: VERSION1,
-                field_count: 3,
-                length: 69,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 31,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("order"),
+                FieldValue::List(List::Mixed(vec![
+                    FieldValue::Integer(7),
+                    FieldValue::String(StringValue(String::from("GAL"))),
+                    FieldValue::Object(Object(Fields::new())),
+                ])),
+            )]
+            .into(),
+        };
+        let binary_message: [u8; 31] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x1F, //  - Total length: 31 bytes
+            // Field 1 - order (mixed list):
+            0x05, //        - Name length: 5
+            0x6F, 0x72, 0x64, 0x65, 0x72, //    - "order" in UTF-8
+            0x03, //        - Type: List
+            0x0B, //        - Element type: Mixed
+            0x00, 0x03, //  - Element count: 3
+            0x01, //        - Type: Integer
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, //  - 7
+            0x02, //        - Type: String
+            0x00, 0x03, //  - String length: 3
+            0x47, 0x41, 0x4C, //    - "GAL" in UTF-8
+            0x04, //        - Type: Object
+            0x00, //        - Field count: 0
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message)
+    }
+
+    #[test]
+    fn map_field() {
+        // Message: `depth={100: 5, 101: 7}`
+        let depth = Map(vec![
+            (FieldValue::Integer(100), FieldValue::Integer(5)),
+            (FieldValue::Integer(101), FieldValue::Integer(7)),
+        ]);
+        assert_eq!(
+            depth.get(&FieldValue::Integer(101)),
+            Some(&FieldValue::Integer(7))
+        );
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 47,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("depth"), FieldValue::Map(depth))].into(),
+        };
+        let binary_message: [u8; 47] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x2F, //  - Total length: 47 bytes
+            // Field 1 - depth (map):
+            0x05, //        - Name length: 5
+            0x64, 0x65, 0x70, 0x74, 0x68, //    - "depth" in UTF-8
+            0x0C, //        - Type: Map
+            0x01, //        - Key type: Integer
+            0x01, //        - Value type: Integer
+            0x00, 0x02, //  - Entry count: 2
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //  - Key: 100
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, //  - Value: 5
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x65, //  - Key: 101
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, //  - Value: 7
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        let error = Map::deserialize(&[0x04, 0x01, 0x00, 0x00], None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::UnsupportedMapKey { type_indicator: 4 }
+        );
+    }
+
+    #[test]
+    fn array_field() {
+        // Message: `position=[1.5, -2.0, 0.25]`
+        let position = Array(vec![
+            FieldValue::Float(1.5),
+            FieldValue::Float(-2.0),
+            FieldValue::Float(0.25),
+        ]);
+        let message = MessageBuilder::new()
+            .with_version(VERSION2)
+            .field("position", FieldValue::Array(position))
+            .build()
+            .unwrap();
+        let bytes = message.serialize().unwrap();
+        let body = [
+            // Field 1 - position (array):
+            0x08, //        - Name length: 8
+            0x70, 0x6F, 0x73, 0x69, 0x74, 0x69, 0x6F, 0x6E, //  - "position" in UTF-8
+            0x12, //        - Type: Array
+            0x05, //        - Element type: Float
+            0x00, 0x03, //  - Element count: 3
+            0x3F, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //  - 1.5
+            0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //  - -2.0
+            0x3F, 0xD0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //  - 0.25
+        ];
+        assert!(bytes.ends_with(&body));
+        assert_eq!(Message::deserialize(&bytes, None).unwrap().0, message);
+
+        // Integers keep their 8 bytes where version 2 writes varints
+        let ids = Array(vec![FieldValue::Integer(1), FieldValue::Integer(-1)]);
+        let encoding = Encoding {
+            version: VERSION2,
+            ..Encoding::default()
+        };
+        let bytes = ids.serialize_with(encoding).unwrap();
+        assert_eq!(bytes.len(), 3 + 2 * 8);
+        assert_eq!(ids.serialized_len_with(encoding), bytes.len());
+        assert_eq!(
+            Array::deserialize_with(&bytes, None, encoding).unwrap().0,
+            ids
+        );
+
+        // A count the bytes can't hold fails before reading any element
+        let error = Array::deserialize(&[0x05, 0xFF, 0xFE, 0x00], None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected 65534 elements of 8 bytes, end of buffer! at byte 3"
+        );
+        let error = Array::deserialize(&[0x02, 0x00, 0x00], None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::UnsupportedArrayElement { type_indicator: 2 }
+        );
+        let strings = Array(vec![FieldValue::from("x")]);
+        assert_eq!(
+            strings.serialize(),
+            Err(SerializeError::UnsupportedArrayElement { type_indicator: 2 })
+        );
+        let mixed = Array(vec![FieldValue::Integer(1), FieldValue::Float(1.0)]);
+        assert_eq!(mixed.serialize(), Err(SerializeError::MixedArrayTypes));
+    }
+
+    #[test]
+    fn enum_field() {
+        let mut registry = EnumRegistry::default();
+        registry.register(
+            EnumType::new("OrderSide")
+                .variant(0, "Buy")
+                .variant(1, "Sell"),
+        );
+        registry.register(
+            EnumType::new("TimeInForce")
+                .variant(0, "GTC")
+                .variant(1, "IOC")
+                .variant(2, "FOK"),
+        );
+        let side = registry.get("OrderSide").unwrap().value("Sell").unwrap();
+        let expiry = EnumValue {
+            discriminant: registry
+                .get("TimeInForce")
+                .unwrap()
+                .discriminant("GTC")
+                .unwrap(),
+            payload: Some(Box::new(FieldValue::Integer(1698765432))),
+        };
+
+        // Message: `side=Sell`
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 13,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("side"), FieldValue::Enum(side))].into(),
+        };
+        let binary_message: [u8; 13] = [
+            // Header (4 bytes):
+            0x01, //        - Protocol version
+            0x01, //        - 1 field
+            0x00, 0x0D, //  - Total length: 13 bytes
+            // Field 1 - side (enum):
+            0x04, //        - Name length: 4
+            0x73, 0x69, 0x64, 0x65, //  - "side" in UTF-8
+            0x0D, //        - Type: Enum
+            0x00, 0x01, //  - Discriminant: 1
+            0x07, //        - Payload type: Null
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+        let Some(FieldValue::Enum(side)) = deserialized_message.get("side") else {
+            panic!("expected enum");
+        };
+        assert_eq!(registry.variant_name("OrderSide", side), Some("Sell"));
+
+        let value = FieldValue::Enum(expiry);
+        let binary_value = value.serialize().unwrap();
+        let (deserialized_value, bytes) = FieldValue::deserialize(&binary_value, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(value, deserialized_value);
+    }
+
+    #[test]
+    fn long_message_uses_version2_header() {
+        // Message: `note="...", memo="..."` with 70,000 bytes of text
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 2,
+                length: 0,
+                fingerprint: None,
+                dictionary: None,
             },
             body: [
                 (
-                    FieldName(String::from("user_id")),
-                    FieldValue::Integer(1001),
-                ),
-                (
-                    FieldName(String::from("name")),
-                    FieldValue::String(StringValue(String::from("Alice"))),
+                    FieldName::from("note"),
+                    FieldValue::String(StringValue("x".repeat(60000))),
                 ),
                 (
-                    FieldName(String::from("scores")),
-                    FieldValue::List(List::Integers(vec![100, 200, 300])),
+                    FieldName::from("memo"),
+                    FieldValue::String(StringValue("y".repeat(10000))),
                 ),
             ]
             .into(),
         };
-        let binary_message: [u8; 69] = [
-            // Header (4 bytes):
-            0x01, //      - Protocol version
-            0x03, //      - 3 fields
-            0x00, 0x45, //  - Total length: 69 bytes
-            // Field 1 - user_id (integer):
-            0x07, //          - Name length: 7
-            0x75, 0x73, 0x65, 0x72, 0x5F, 0x69, 0x64, // - "user_id" in UTF-8
-            0x01, //          - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xE9, // - Value: 1001 (64-bit)
-            // Field 2 - name (string):
-            0x04, //                  - Name length: 4
-            0x6E, 0x61, 0x6D, 0x65, //  - "name" in UTF-8
-            0x02, //                  - Type: String
-            0x00, 0x05, //              - String length: 5
-            0x41, 0x6C, 0x69, 0x63, 0x65, // - "Alice" in UTF-8
-            //Field 3 - scores (list of integers):
-            0x06, //              - Name length: 6
-            0x73, 0x63, 0x6F, 0x72, 0x65, 0x73, // - "scores" in UTF-8
-            0x03, //              - Type: List
-            0x01, //              - Element type: Integer
-            0x00, 0x03, //          - Element count: 3
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //      - 10x00
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC8, //    - 20x00
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x2C, //    -30x00,
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 70034);
+        assert_eq!(
+            binary_message[..18],
+            [
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+                0x00, 0x01, 0x11, 0x92
+            ]
+        );
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(deserialized_message.header.version, VERSION2);
+        assert_eq!(deserialized_message.header.length, 70034);
+        assert_eq!(message.body, deserialized_message.body);
+    }
+
+    #[test]
+    fn long_string() {
+        // Message: `memo="..."` with 70,000 bytes of text
+        let memo = "z".repeat(70000);
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 0,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("memo"),
+                FieldValue::String(StringValue(memo.clone())),
+            )]
+            .into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 70030);
+        assert_eq!(
+            binary_message[..30],
+            [
+                0x02, 0x00, 0x00, 0x00, // Version, no flags, untyped
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Not sequenced
+                0x00, 0x01, 0x00, 0x01, 0x11, 0x8E, // 1 field, length
+                0x04, b'm', b'e', b'm', b'o', // Field name
+                0x02, // Type: String
+                0xFF, 0xFF, // Long string escape
+                0x00, 0x01, 0x11, 0x70, // Length: 70,000
+            ]
+        );
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.body, deserialized_message.body);
+
+        let error = StringValue::deserialize(&binary_message[24..32], None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected string of length 70000, end of buffer! at byte 6"
+        );
+    }
+
+    #[test]
+    fn long_list() {
+        // Message: `fills=[true, false, ...]` with 70,000 elements
+        let fills: Vec<bool> = (0..70000).map(|i| i % 2 == 0).collect();
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 0,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("fills"),
+                FieldValue::List(List::Bools(fills)),
+            )]
+            .into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 70032);
+        assert_eq!(
+            binary_message[..32],
+            [
+                0x02, 0x00, 0x00, 0x00, // Version, no flags, untyped
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Not sequenced
+                0x00, 0x01, 0x00, 0x01, 0x11, 0x90, // 1 field, length
+                0x05, b'f', b'i', b'l', b'l', b's', // Field name
+                0x03, // Type: List
+                0x06, // Element type: Bool
+                0xFF, 0xFF, // Long count escape
+                0x00, 0x01, 0x11, 0x70, // Count: 70,000
+            ]
+        );
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.body, deserialized_message.body);
+    }
+
+    #[test]
+    fn wide_object() {
+        // Message: `telemetry={sensor_0=0, sensor_1=1, ...}` with 300 fields
+        let telemetry: Fields = (0..300)
+            .map(|i| {
+                (
+                    FieldName::from(format!("sensor_{}", i)),
+                    FieldValue::Integer(i),
+                )
+            })
+            .collect();
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 0,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(
+                FieldName::from("telemetry"),
+                FieldValue::Object(Object(telemetry)),
+            )]
+            .into(),
+        };
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(
+            binary_message[..31],
+            [
+                0x02, 0x00, 0x00, 0x00, // Version, no flags, untyped
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Not sequenced
+                0x00, 0x01, 0x00, 0x00, 0x0F, 0xD9, // 1 field, length
+                0x09, b't', b'e', b'l', b'e', b'm', b'e', b't', b'r', b'y', // Field name
+                0x04, // Type: Object
+                0x01, 0x2C, // 300 fields
+            ]
+        );
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.body, deserialized_message.body);
+    }
+
+    #[test]
+    fn message_type() {
+        let mut registry = MessageTypeRegistry::default();
+        registry.register(MessageType(1), "order");
+        registry.register(MessageType(2), "trade");
+
+        // Message: `trade_id=7` of type trade
+        let message = Message {
+            header: Header {
+                version: VERSION1,
+                flags: 0,
+                message_type: registry.message_type("trade").unwrap(),
+                sequence: 0,
+                field_count: 1,
+                length: 0,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from("trade_id"), FieldValue::Integer(7))].into(),
+        };
+        let binary_message: [u8; 29] = [
+            // Header (18 bytes):
+            0x02, //        - Protocol version
+            0x00, //        - No flags
+            0x00, 0x02, //  - Message type: 2
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // - Not sequenced
+            0x00, 0x01, //  - 1 field
+            0x00, 0x00, 0x00, 0x1D, //  - Total length: 29 bytes
+            // Field 1 - trade_id (integer):
+            0x08, //        - Name length: 8
+            0x74, 0x72, 0x61, 0x64, 0x65, 0x5F, 0x69, 0x64, //  - "trade_id" in UTF-8
+            0x01, //        - Type: Integer
+            0x0E, //        - Value: 7
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(deserialized_message.message_type(), MessageType(2));
+        assert_eq!(
+            registry.name(deserialized_message.message_type()),
+            Some("trade")
+        );
+        assert_eq!(registry.name(MessageType::UNTYPED), None);
+    }
+
+    #[test]
+    fn sequence_number() {
+        let message = MessageBuilder::new()
+            .with_sequence(258)
+            .field("halted", FieldValue::Bool(true))
+            .build()
+            .unwrap();
+        assert_eq!(
+            message.header,
+            Header {
+                version: VERSION2,
+                flags: 0,
+                message_type: MessageType::UNTYPED,
+                sequence: 258,
+                field_count: 1,
+                length: 27,
+                fingerprint: None,
+                dictionary: None,
+            }
+        );
+        let binary_message: [u8; 27] = [
+            // Header (18 bytes):
+            0x02, //        - Protocol version
+            0x00, //        - No flags
+            0x00, 0x00, //  - Untyped
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, // - Sequence: 258
+            0x00, 0x01, //  - 1 field
+            0x00, 0x00, 0x00, 0x1B, //  - Total length: 27 bytes
+            // Field 1 - halted (bool):
+            0x06, //        - Name length: 6
+            0x68, 0x61, 0x6C, 0x74, 0x65, 0x64, // - "halted" in UTF-8
+            0x06, //        - Type: Bool
+            0x01, //        - Value: true
+        ];
+        assert_eq!(message.serialize().unwrap(), binary_message);
+
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        let unsequenced = MessageBuilder::new()
+            .field("halted", FieldValue::Bool(true))
+            .build()
+            .unwrap();
+        assert_eq!(unsequenced.header.version, VERSION1);
+        assert_eq!(unsequenced.header.length, 13);
+    }
+
+    #[test]
+    fn checksum() {
+        let message = MessageBuilder::new()
+            .with_checksum()
+            .field("halted", FieldValue::Bool(true))
+            .build()
+            .unwrap();
+        assert_eq!(message.header.flags, CHECKSUM_FLAG);
+        assert_eq!(message.header.length, 31);
+
+        let mut binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 31);
+        assert_eq!(
+            binary_message[27..],
+            crc32c::crc32c(&binary_message[..27]).to_be_bytes()
+        );
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        // Flip the bool value
+        binary_message[26] = 0x00;
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            DeserializeErrorKind::ChecksumMismatch { .. }
+        ));
+        assert_eq!(error.offset, 27);
+    }
+
+    #[test]
+    fn compression() {
+        let message = MessageBuilder::new()
+            .field("memo", FieldValue::String(StringValue("spam ".repeat(200))))
+            .build()
+            .unwrap();
+        let encoding = Encoding {
+            compression_threshold: Some(512),
+            ..Encoding::default()
+        };
+
+        let binary_message = message.serialize_with(encoding).unwrap();
+        assert!(binary_message.len() < 100, "{}", binary_message.len());
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(deserialized_message.header.version, VERSION2);
+        assert_eq!(deserialized_message.header.flags, COMPRESSED_FLAG);
+        assert_eq!(message.body, deserialized_message.body);
+
+        // Below the threshold
+        let small_message = MessageBuilder::new()
+            .field("memo", FieldValue::String(StringValue("spam".repeat(10))))
+            .build()
+            .unwrap();
+        let binary_message = small_message.serialize_with(encoding).unwrap();
+        assert_eq!(binary_message, small_message.serialize().unwrap());
+    }
+
+    #[test]
+    fn signed_message() {
+        let key = b"session key";
+        let message = MessageBuilder::new()
+            .with_checksum()
+            .field("quantity", FieldValue::Integer(100))
+            .build()
+            .unwrap();
+
+        let mut binary_message = message.serialize_signed(key).unwrap();
+        assert_eq!(
+            binary_message.len(),
+            message.serialize().unwrap().len() + SIGNATURE_SIZE
+        );
+        let (verified_message, bytes) =
+            Message::deserialize_verified(&binary_message, key).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(verified_message.header.flags, CHECKSUM_FLAG | SIGNED_FLAG);
+        assert_eq!(message.body, verified_message.body);
+
+        let error = Message::deserialize_verified(&binary_message, b"other key").unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::SignatureMismatch);
+        let error = Message::deserialize_verified(&message.serialize().unwrap(), key).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::NotSigned);
+
+        // Tamper with the quantity and fix up the checksum
+        let checked = binary_message.len() - CHECKSUM_SIZE;
+        binary_message[checked - SIGNATURE_SIZE - 1] += 1;
+        let checksum = crc32c::crc32c(&binary_message[..checked]).to_be_bytes();
+        binary_message[checked..].copy_from_slice(&checksum);
+        let error = Message::deserialize_verified(&binary_message, key).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::SignatureMismatch);
+        assert!(Message::deserialize(&binary_message, None).is_ok());
+    }
+
+    #[test]
+    fn encrypted_message() {
+        let key = [0x42; 32];
+        let nonce = [0x07; NONCE_SIZE];
+        let message = MessageBuilder::new()
+            .with_sequence(9)
+            .field(
+                "account",
+                FieldValue::String(StringValue(String::from("ACME-01"))),
+            )
+            .build()
+            .unwrap();
+
+        let mut binary_message = message.serialize_encrypted(&key, nonce).unwrap();
+        assert_eq!(
+            binary_message.len(),
+            message.serialize().unwrap().len() + NONCE_SIZE + SEAL_TAG_SIZE
+        );
+        assert!(!binary_message.windows(7).any(|w| w == b"ACME-01"));
+        let (decrypted_message, bytes) =
+            Message::deserialize_decrypted(&binary_message, &key).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(decrypted_message.header.flags, ENCRYPTED_FLAG);
+        assert_eq!(message.body, decrypted_message.body);
+
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::Encrypted);
+        let error = Message::deserialize_decrypted(&binary_message, &[0x43; 32]).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::DecryptionFailed);
+
+        // The header is authenticated too, bump the sequence number
+        binary_message[11] += 1;
+        let error = Message::deserialize_decrypted(&binary_message, &key).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::DecryptionFailed);
+    }
+
+    #[test]
+    fn field_ids() {
+        // Message: `#3=1001` written with field IDs
+        let message = Message {
+            header: Header {
+                version: VERSION2,
+                flags: FIELD_IDS_FLAG,
+                message_type: MessageType::UNTYPED,
+                sequence: 0,
+                field_count: 1,
+                length: 23,
+                fingerprint: None,
+                dictionary: None,
+            },
+            body: [(FieldName::from_id(3), FieldValue::Integer(1001))].into(),
+        };
+        let binary_message: [u8; 23] = [
+            // Header (18 bytes):
+            0x02, //        - Protocol version
+            0x10, //        - Field IDs
+            0x00, 0x00, //  - Untyped
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // - Not sequenced
+            0x00, 0x01, //  - 1 field
+            0x00, 0x00, 0x00, 0x17, //  - Total length: 23 bytes
+            // Field 1 - #3 (integer):
+            0x00, 0x03, //  - Field ID: 3
+            0x01, //        - Type: Integer
+            0xD2, 0x0F, //  - Value: 1001 (zig-zag 2002)
+        ];
+        let encoding = Encoding {
+            field_ids: true,
+            ..Encoding::default()
+        };
+        assert_eq!(message.serialize_with(encoding).unwrap(), binary_message);
+        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message, deserialized_message);
+
+        // Without field IDs the same message is written by name
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message[1], 0x00);
+        assert_eq!(&binary_message[18..21], [0x02, b'#', b'3']);
+
+        assert_eq!(FieldName::from("#65535").id(), Some(65535));
+        for name in ["#65536", "#", "#+1", "3", "price"] {
+            assert_eq!(FieldName::from(name).id(), None);
+        }
+        let message = Message::new([("price", FieldValue::Integer(1))]).unwrap();
+        assert_eq!(
+            message.serialize_with(encoding).unwrap_err(),
+            SerializeError::MissingFieldId {
+                name: String::from("price")
+            }
+        );
+    }
+
+    #[test]
+    fn version3_unknown_values() {
+        // Message: `price=5, rating=<type 0x20>` from a newer writer
+        let binary_message: [u8; 41] = [
+            // Header (18 bytes):
+            0x03, //        - Protocol version
+            0x00, //        - No flags
+            0x00, 0x00, //  - Untyped
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // - Not sequenced
+            0x00, 0x02, //  - 2 fields
+            0x00, 0x00, 0x00, 0x29, //  - Total length: 41 bytes
+            // Field 1 - price (integer):
+            0x05, //        - Name length: 5
+            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
+            0x01, //        - Type: Integer
+            0x00, 0x01, //  - Value length: 1
+            0x0A, //        - Value: 5 (zig-zag 10)
+            // Field 2 - rating (unknown type):
+            0x06, //        - Name length: 6
+            0x72, 0x61, 0x74, 0x69, 0x6E, 0x67, // - "rating" in UTF-8
+            0x20, //        - Type: Unknown
+            0x00, 0x03, //  - Value length: 3
+            0x01, 0x02, 0x03, // - Value
+        ];
+        let (message, bytes) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.get("price"), Some(&FieldValue::Integer(5)));
+        assert_eq!(
+            message.get("rating"),
+            Some(&FieldValue::Unknown(0x20, vec![0x01, 0x02, 0x03]))
+        );
+        assert_eq!(message.serialize().unwrap(), binary_message);
+
+        // Unknown values nest in mixed lists too
+        let list = FieldValue::List(List::Mixed(vec![
+            FieldValue::Unknown(0x20, vec![0xFF]),
+            FieldValue::Bool(true),
+        ]));
+        let encoding = Encoding {
+            version: VERSION3,
+            ..Encoding::default()
+        };
+        let binary_list = list.serialize_with(encoding).unwrap();
+        let (deserialized_list, bytes) =
+            FieldValue::deserialize_with(&binary_list, None, encoding).unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(list, deserialized_list);
+
+        // Earlier versions have no room for them
+        let older = MessageBuilder::new()
+            .with_version(VERSION2)
+            .field("rating", FieldValue::Unknown(0x20, vec![]));
+        assert_eq!(
+            older.build().unwrap_err(),
+            SerializeError::UnknownType {
+                type_indicator: 0x20
+            }
+        );
+
+        // The value length has to match the known value it prefixes
+        let mut binary_message = binary_message;
+        binary_message[26] = 0x02;
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::ValueLengthMismatch {
+                length: 2,
+                actual: 1
+            }
+        );
+        assert_eq!(error.path, "price");
+    }
+
+    #[test]
+    fn serialize_to_writer() {
+        let fill = Object(
+            [
+                (FieldName::from("quantity"), FieldValue::Integer(3)),
+                (FieldName::from("maker"), FieldValue::Bool(false)),
+            ]
+            .into(),
+        );
+        let message = MessageBuilder::new()
+            .with_type(MessageType(4))
+            .field("fills", FieldValue::List(List::Objects(vec![fill.clone()])))
+            .field("last", FieldValue::Object(fill))
+            .field(
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 1,
+                    payload: Some(Box::new(FieldValue::List(List::Mixed(vec![
+                        FieldValue::Null,
+                        FieldValue::Integer(-1),
+                    ])))),
+                }),
+            )
+            .build()
+            .unwrap();
+        for encoding in [
+            Encoding::default(),
+            Encoding {
+                compact_integers: true,
+                ..Encoding::default()
+            },
+            Encoding {
+                version: VERSION3,
+                ..Encoding::default()
+            },
+        ] {
+            for value in message.body.values() {
+                let mut written = vec![];
+                value.serialize_to_with(&mut written, encoding).unwrap();
+                assert_eq!(written, value.serialize_with(encoding).unwrap());
+            }
+        }
+        let mut written = vec![];
+        message.serialize_to(&mut written).unwrap();
+        assert_eq!(written, message.serialize().unwrap());
+
+        /// Writer failing once it has taken as many bytes as it holds
+        struct Full(usize);
+
+        impl Write for Full {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(std::io::ErrorKind::WriteZero.into());
+                }
+                let written = buf.len().min(self.0);
+                self.0 -= written;
+                Ok(written)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let list = FieldValue::List(List::Integers(vec![1, 2, 3]));
+        let error = list.serialize_to(&mut Full(5)).unwrap_err();
+        assert!(matches!(error, WriteError::Io(e) if e.kind() == std::io::ErrorKind::WriteZero));
+        let object = Object([(FieldName::from("x".repeat(256)), FieldValue::Null)].into());
+        let error = object.serialize_to(&mut Full(usize::MAX)).unwrap_err();
+        assert!(matches!(
+            error,
+            WriteError::Serialize(SerializeError::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn serialize_into_buffer() {
+        let messages = [
+            Message::new([("price", FieldValue::Integer(5))]).unwrap(),
+            MessageBuilder::new()
+                .with_type(MessageType(2))
+                .field(
+                    "prices",
+                    FieldValue::Map(Map(vec![(
+                        FieldValue::String(StringValue(String::from("ask"))),
+                        FieldValue::Integer(6),
+                    )])),
+                )
+                .build()
+                .unwrap(),
         ];
-        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
-        assert_eq!(bytes.len(), 0);
-        assert_eq!(message, deserialized_message)
+        let mut bytes = vec![0xAA];
+        messages[0].serialize_into(&mut bytes).unwrap();
+        assert_eq!(bytes[0], 0xAA);
+        assert_eq!(bytes[1..], messages[0].serialize().unwrap());
+
+        let capacity = bytes.capacity();
+        for message in &messages {
+            bytes.clear();
+            message.serialize_into(&mut bytes).unwrap();
+            assert_eq!(bytes, message.serialize().unwrap());
+        }
+        assert!(bytes.capacity() >= capacity);
+
+        // Since version 3 a value of 65,535 bytes and more moves along for the
+        // long form of its length
+        let encoding = Encoding {
+            version: VERSION3,
+            ..Encoding::default()
+        };
+        let string = FieldValue::String(StringValue("x".repeat(u16::MAX as usize)));
+        let mut bytes = vec![];
+        string.serialize_into_with(&mut bytes, encoding).unwrap();
+        assert_eq!(
+            bytes[..9],
+            [STRING_T, 0xFF, 0xFF, 0x00, 0x01, 0x00, 0x05, 0xFF, 0xFF]
+        );
+        let (value, rest) = FieldValue::deserialize_with(&bytes, None, encoding).unwrap();
+        assert_eq!(value, string);
+        assert!(rest.is_empty());
     }
 
     #[test]
-    fn list_of_objects() {
-        // ### Message with List of Objects: `timestamp=1698765432, trades=[{id:1, price:100}, {id:2, price:200}]`
-        let message = Message {
-            header: Header {
+    fn serialized_len() {
+        let values = [
+            FieldValue::Integer(-3),
+            FieldValue::Integer(i64::MAX),
+            FieldValue::String(StringValue("x".repeat(u16::MAX as usize))),
+            FieldValue::List(List::Integers(vec![1, -300, 70_000])),
+            FieldValue::List(List::Mixed(vec![FieldValue::Null, FieldValue::Bool(true)])),
+            FieldValue::Object(Object(
+                [(FieldName::from("price"), FieldValue::Float(1.5))].into(),
+            )),
+            FieldValue::Uuid([7; 16]),
+            FieldValue::Decimal(Decimal::new(-12_345, -2)),
+            FieldValue::Map(Map(vec![(FieldValue::Integer(1), FieldValue::Integer(2))])),
+            FieldValue::Map(Map(vec![])),
+            FieldValue::Enum(EnumValue {
+                discriminant: 2,
+                payload: None,
+            }),
+        ];
+        for encoding in [
+            Encoding {
                 version: VERSION1,
-                field_count: 2,
-                length: 90,
+                compact_integers: true,
+                ..Encoding::default()
             },
-            body: [
-                (
-                    FieldName(String::from("timestamp")),
-                    FieldValue::Integer(1698765432),
-                ),
-                (
-                    FieldName(String::from("trades")),
-                    FieldValue::List(List::Objects(vec![
-                        Object(
-                            [
-                                (FieldName(String::from("id")), FieldValue::Integer(1)),
-                                (FieldName(String::from("price")), FieldValue::Integer(100)),
-                            ]
-                            .into(),
-                        ),
-                        Object(
-                            [
-                                (FieldName(String::from("id")), FieldValue::Integer(2)),
-                                (FieldName(String::from("price")), FieldValue::Integer(200)),
-                            ]
-                            .into(),
-                        ),
-                    ])),
-                ),
-            ]
-            .into(),
+            Encoding::default(),
+            Encoding {
+                version: VERSION3,
+                ..Encoding::default()
+            },
+        ] {
+            for value in &values {
+                let bytes = value.serialize_with(encoding).unwrap();
+                assert_eq!(
+                    value.serialized_len_with(encoding),
+                    bytes.len(),
+                    "{:?}",
+                    value
+                );
+            }
+        }
+
+        let fields = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (format!("f{}", i), value.clone()));
+        let mut builder = MessageBuilder::new().with_version(VERSION1);
+        for (name, value) in fields {
+            builder = builder.field(&name, value);
+        }
+        let message = builder.build().unwrap();
+        assert_eq!(message.serialized_len(), message.serialize().unwrap().len());
+        let compressed = Encoding {
+            compression_threshold: Some(16),
+            ..Encoding::default()
         };
-        let binary_message: [u8; 90] = [
-            // Header (4 bytes):
-            0x01, //        - Protocol version
-            0x02, //        - 2 fields
-            0x00, 0x5a, //  - Total length: 90 bytes
-            // Field 1 - timestamp (integer):
-            0x09, //        - Name length: 9
-            0x74, 0x69, 0x6D, 0x65, 0x73, 0x74, 0x61, 0x6D, 0x70, //    - "timestamp" in UTF-8
-            0x01, //        - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x65, 0x41, 0x1A, 0x78, //  - Value: 1698765432
-            // Field 2 - trades (list of objects):
-            0x06, //        - Name length: 6
-            0x74, 0x72, 0x61, 0x64, 0x65, 0x73, //  - "trades" in UTF-8
-            0x03, //        - Type: List
-            0x04, //        - Element type: Object
-            0x00, 0x02, //  - Element count: 2
-            // Object 1:
-            0x02, //        - Field count: 2
-            // Field: id
-            0x02, //        - Name length: 2
-            0x69, 0x64, //  - "id" in UTF-8
-            0x01, //        - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, //  - Value: 1
-            // Field: price
-            0x05, //        - Name length: 5
-            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
-            0x01, //       - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, //  - Value: 100
-            // Object 2:
-            0x02, //        - Field count: 2
-            // Field: id
-            0x02, //        - Name length: 2
-            0x69, 0x64, //  - "id" in UTF-8
-            0x01, //        - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, //  - Value: 2
-            // Field: price
-            0x05, //        - Name length: 5
-            0x70, 0x72, 0x69, 0x63, 0x65, //    - "price" in UTF-8
-            0x01, //        - Type: Integer
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC8, //  - Value: 200
+        assert_eq!(
+            message.serialized_len_with(compressed),
+            message.serialize_with(compressed).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn name_table() {
+        let trade = |id| {
+            Object(
+                [
+                    (FieldName::from("price"), FieldValue::Integer(5)),
+                    (FieldName::from("id"), FieldValue::Integer(id)),
+                ]
+                .into(),
+            )
+        };
+        let message = MessageBuilder::new()
+            .field("id", FieldValue::Integer(9))
+            .field(
+                "trades",
+                FieldValue::List(List::Objects((0..10).map(trade).collect())),
+            )
+            .build()
+            .unwrap();
+        let encoding = Encoding {
+            name_table: true,
+            ..Encoding::default()
+        };
+        let mut bytes = message.serialize_with(encoding).unwrap();
+        assert_eq!(bytes[1], NAME_TABLE_FLAG);
+        // "id", "trades" and "price" once each
+        assert_eq!(bytes[18..30], *b"\x00\x03\x02id\x06trades");
+        assert_eq!(message.serialized_len_with(encoding), bytes.len());
+        assert!(bytes.len() < message.serialize().unwrap().len());
+        let (read, _) = Message::deserialize(&bytes, None).unwrap();
+        assert_eq!(read.body, message.body);
+
+        // Names read for every object of a list share one allocation
+        let (read, _) = Message::deserialize(&message.serialize().unwrap(), None).unwrap();
+        let FieldValue::List(List::Objects(trades)) = &read.body[&FieldName::from("trades")] else {
+            panic!("expected the trades");
+        };
+        let price = |trade: &Object| trade.0.get_index(0).unwrap().0.0.clone();
+        assert!(Arc::ptr_eq(&price(&trades[0]), &price(&trades[9])));
+
+        // The first field "id" refers to a name past the end of the table
+        bytes[37] = 0x07;
+        assert_eq!(
+            Message::deserialize(&bytes, None).unwrap_err().kind,
+            DeserializeErrorKind::Invalid(String::from(
+                "name index 7 is out of a table of 3 names"
+            ))
+        );
+
+        let message = Message::new([("ab", FieldValue::Null), ("cd", FieldValue::Null)]).unwrap();
+        let mut bytes = message.serialize_with(encoding).unwrap();
+        let at = bytes.windows(2).position(|w| w == b"cd").unwrap();
+        bytes[at..at + 2].copy_from_slice(b"ab");
+        let error = Message::deserialize(&bytes, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("name table repeats field name ab at byte {}", at - 1)
+        );
+    }
+
+    #[test]
+    fn serialize_errors() {
+        let name = FieldName::from("x".repeat(256));
+        assert_eq!(
+            name.serialize(),
+            Err(SerializeError::TooLong {
+                what: "field name length",
+                length: 256,
+                max: 255,
+            })
+        );
+
+        let map = Map(vec![(FieldValue::Bool(true), FieldValue::Integer(1))]);
+        let error = map.serialize().unwrap_err();
+        assert_eq!(
+            error,
+            SerializeError::UnsupportedMapKey {
+                type_indicator: BOOL_T
+            }
+        );
+        assert!(error.to_string().starts_with("map key of type 6"));
+
+        let map = Map(vec![
+            (FieldValue::Integer(1), FieldValue::Integer(1)),
+            (FieldValue::Integer(2), FieldValue::Float(2.0)),
+        ]);
+        assert_eq!(map.serialize(), Err(SerializeError::MixedMapTypes));
+
+        let header = Header {
+            version: VERSION1,
+            flags: 0,
+            message_type: MessageType::UNTYPED,
+            sequence: 7,
+            field_count: 0,
+            length: 4,
+            fingerprint: None,
+            dictionary: None,
+        };
+        assert_eq!(
+            header.serialize().unwrap_err().to_string(),
+            "version 1 messages have no sequence number"
+        );
+    }
+
+    #[test]
+    fn utf8_validation() {
+        let region = Utf8Region::of("aë€".as_bytes());
+        let bytes = "aë€".as_bytes();
+        assert!(region.holds(bytes, 3));
+        assert!(region.holds(&bytes[1..], 5));
+        assert!(!region.holds(bytes, 2));
+        assert!(!region.holds(&bytes[2..], 1));
+        assert!(!Utf8Region::of(&[b'a', 0xFF]).holds(b"a", 1));
+
+        // Strings before and after a float which isn't UTF-8
+        let message = MessageBuilder::new()
+            .field("name", "Zoë".into())
+            .field("price", FieldValue::Float(-1.5))
+            .field("memo", "café €".into())
+            .build()
+            .unwrap();
+        let mut binary_message = message.serialize().unwrap();
+        let (deserialized_message, _) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(message, deserialized_message);
+
+        // Still a valid body, but the name now ends within its last character
+        let name = binary_message
+            .windows(4)
+            .position(|w| w == "Zoë".as_bytes())
+            .unwrap();
+        binary_message[name - 1] -= 1;
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::InvalidUtf8);
+        assert_eq!(error.path, "name");
+    }
+
+    #[test]
+    fn lossy_deserialization() {
+        let message = |version| {
+            MessageBuilder::new()
+                .with_version(version)
+                .field("id", FieldValue::Integer(7))
+                .field("venue", "XGAL".into())
+                .field("quantity", FieldValue::Integer(300))
+                .build()
+                .unwrap()
+        };
+        let fields = |message: &Message| -> Vec<String> {
+            message
+                .body
+                .keys()
+                .map(|name| String::from(name.as_str()))
+                .collect()
+        };
+        let errors = |errors: Vec<FieldError>| -> Vec<String> {
+            errors.iter().map(FieldError::to_string).collect()
+        };
+        let damaged = |message: &Message| {
+            let mut bytes = message.serialize().unwrap();
+            let venue = bytes.windows(4).position(|w| w == b"XGAL").unwrap();
+            bytes[venue] = 0xFF;
+            bytes
+        };
+
+        // The length prefix of version 3 leads past the damaged value
+        let bytes = damaged(&message(VERSION3));
+        assert!(Message::deserialize(&bytes, None).is_err());
+        let (read, read_errors) = Message::deserialize_lossy(&bytes).unwrap();
+        assert_eq!(fields(&read), ["id", "quantity"]);
+        assert_eq!(read.get("quantity"), Some(&FieldValue::Integer(300)));
+        assert_eq!(
+            errors(read_errors),
+            ["field `venue`: invalid utf-8 string at byte 36"]
+        );
+
+        // Before version 3 reading stops at it
+        let bytes = damaged(&message(VERSION2));
+        let (read, read_errors) = Message::deserialize_lossy(&bytes).unwrap();
+        assert_eq!(fields(&read), ["id"]);
+        assert_eq!(read_errors.len(), 1);
+
+        let bytes = message(VERSION3).serialize().unwrap();
+        let (read, read_errors) = Message::deserialize_lossy(&bytes[..bytes.len() - 2]).unwrap();
+        assert_eq!(fields(&read), ["id", "venue"]);
+        assert_eq!(
+            errors(read_errors),
+            ["field `quantity`: expected value of 2 bytes, end of buffer! at byte 52"]
+        );
+        assert!(Message::deserialize_lossy(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn error_offset_and_path() {
+        let trade = |price| Object([(FieldName::from("price"), FieldValue::Bool(price))].into());
+        let message = MessageBuilder::new()
+            .field(
+                "trades",
+                FieldValue::List(List::Objects(vec![trade(true), trade(false)])),
+            )
+            .build()
+            .unwrap();
+        let mut binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message.len(), 33);
+
+        // Second trade's price is neither true nor false
+        binary_message[32] = 0x02;
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::InvalidBool { found: 0x02 }
+        );
+        assert_eq!(error.offset, 32);
+        assert_eq!(error.path, "trades[1].price");
+        assert_eq!(
+            error.to_string(),
+            "trades[1].price: expected bool (0x00 or 0x01), found: 0x02 at byte 32"
+        );
+
+        let error = Message::deserialize(&binary_message[..30], None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected message of 33 bytes, end of buffer! at byte 0"
+        );
+    }
+
+    #[test]
+    fn deserialize_limits() {
+        let message = MessageBuilder::new()
+            .field("memo", FieldValue::String(StringValue("x".repeat(100))))
+            .field(
+                "fill",
+                FieldValue::Object(Object(
+                    [(FieldName::from("price"), FieldValue::Integer(7))].into(),
+                )),
+            )
+            .build()
+            .unwrap();
+        let binary_message = message.serialize().unwrap();
+        assert!(Message::deserialize(&binary_message, None).is_ok());
+
+        let with_limits = |limits| Encoding {
+            limits,
+            ..Encoding::default()
+        };
+        let limits = DeserializeLimits {
+            max_string_bytes: 99,
+            ..DeserializeLimits::default()
+        };
+        let error =
+            Message::deserialize_with(&binary_message, None, with_limits(limits)).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::LimitExceeded {
+                limit: "string bytes",
+                value: 100,
+                max: 99
+            }
+        );
+        let limits = DeserializeLimits {
+            max_fields: 2,
+            ..DeserializeLimits::default()
+        };
+        let error =
+            Message::deserialize_with(&binary_message, None, with_limits(limits)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "fields: 3 exceeds the limit of 2 at byte 4"
+        );
+        let limits = DeserializeLimits {
+            max_message_size: 100,
+            ..DeserializeLimits::default()
+        };
+        assert!(Message::deserialize_with(&binary_message, None, with_limits(limits)).is_err());
+        let limits = DeserializeLimits::unlimited();
+        assert!(Message::deserialize_with(&binary_message, None, with_limits(limits)).is_ok());
+
+        // A list claiming 65,534 objects in a buffer of 2 bytes
+        let error = List::deserialize(&[0x04, 0xFF, 0xFE, 0x00, 0x00], None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected 65534 values, end of buffer! at byte 3"
+        );
+
+        // Compressed body inflating beyond the message size
+        let encoding = Encoding {
+            compression_threshold: Some(0),
+            ..Encoding::default()
+        };
+        let message = MessageBuilder::new()
+            .field("memo", FieldValue::String(StringValue("x".repeat(60_000))))
+            .build()
+            .unwrap();
+        let binary_message = message.serialize_with(encoding).unwrap();
+        let limits = DeserializeLimits {
+            max_message_size: 1_000,
+            ..DeserializeLimits::default()
+        };
+        let error =
+            Message::deserialize_with(&binary_message, None, with_limits(limits)).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            DeserializeErrorKind::LimitExceeded {
+                limit: "decompressed body size",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn allocation_limit() {
+        // Message: `a=[{a=[{a=[...]}, ...]}, ...]`, 30 lists deep, each list
+        // claiming 65,535 objects and each object 65,535 fields, 64 KB of zeros
+        // making the claims look plausible
+        let mut body = vec![];
+        for _ in 0..30 {
+            body.extend([
+                0x01, b'a', LIST_T, OBJECT_T, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF,
+            ]);
+            body.extend([0xFF, 0xFF]);
+        }
+        body.resize(body.len() + (64 << 10), 0x00);
+        let header = Header {
+            version: VERSION2,
+            flags: 0,
+            message_type: MessageType(0),
+            sequence: 0,
+            field_count: 1,
+            length: (Header::size(VERSION2) + body.len()) as u32,
+            fingerprint: None,
+            dictionary: None,
+        };
+        let mut binary_message = header.serialize().unwrap();
+        binary_message.extend(&body);
+
+        // Without the budget the claims would reserve gigabytes on the way down
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            DeserializeErrorKind::LimitExceeded {
+                limit: "allocation bytes",
+                ..
+            }
+        ));
+        assert!(error.path.starts_with("a[0].a[0]"), "{}", error.path);
+
+        let encoding = Encoding {
+            limits: DeserializeLimits {
+                max_alloc_bytes: 1 << 20,
+                ..DeserializeLimits::default()
+            },
+            ..Encoding::default()
+        };
+        let error = Message::deserialize_with(&binary_message, None, encoding).unwrap_err();
+        assert_eq!(error.path, "a");
+
+        // Counts backed by their elements stay within it
+        let objects = vec![Object(Fields::new()); 65_535];
+        let message = MessageBuilder::new()
+            .with_version(VERSION2)
+            .field("a", FieldValue::List(List::Objects(objects)))
+            .build()
+            .unwrap();
+        let binary_message = message.serialize().unwrap();
+        let (deserialized_message, _) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(message, deserialized_message);
+    }
+
+    #[test]
+    fn depth_limit() {
+        // Message: `a={a={a=...{a=null}}}`, 100 objects deep
+        let mut value = FieldValue::Null;
+        for _ in 0..100 {
+            value = FieldValue::Object(Object([(FieldName::from("a"), value)].into()));
+        }
+        let message = MessageBuilder::new().field("a", value).build().unwrap();
+        let binary_message = message.serialize().unwrap();
+
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::DepthExceeded);
+        assert_eq!(error.path, vec!["a"; 65].join("."));
+        // Header, then 64 times [Field name (2 bytes)][Type (1 byte)][Field count (1 byte)]
+        assert_eq!(error.offset, 4 + 64 * 4 + 3);
+
+        let encoding = Encoding {
+            limits: DeserializeLimits {
+                max_depth: 100,
+                ..DeserializeLimits::default()
+            },
+            ..Encoding::default()
+        };
+        let (deserialized_message, _) =
+            Message::deserialize_with(&binary_message, None, encoding).unwrap();
+        assert_eq!(message, deserialized_message);
+    }
+
+    #[test]
+    fn duplicate_fields() {
+        // Message: `halted=true, halted=false`
+        let binary_message = [
+            0x01, // Version: 1
+            0x02, // Field count: 2
+            0x00, 0x0C, // Message length: 12
+            0x01, b'h', // Field name
+            0x06, // Type: Bool
+            0x01, // true
+            0x01, b'h', // Field name
+            0x06, // Type: Bool
+            0x00, // false
         ];
-        let (deserialized_message, bytes) = Message::deserialize(&binary_message, None).unwrap();
-        assert_eq!(bytes.len(), 0);
-        assert_eq!(message, deserialized_message)
+        let error = Message::deserialize(&binary_message, None).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::DuplicateField);
+        assert_eq!(error.path, "h");
+        assert_eq!(error.offset, 8);
+
+        for (duplicate_fields, halted) in [
+            (DuplicateFields::FirstWins, true),
+            (DuplicateFields::LastWins, false),
+        ] {
+            let encoding = Encoding {
+                duplicate_fields,
+                ..Encoding::default()
+            };
+            let (message, _) = Message::deserialize_with(&binary_message, None, encoding).unwrap();
+            assert_eq!(message.get("h"), Some(&FieldValue::Bool(halted)));
+        }
+    }
+
+    #[test]
+    fn field_order_round_trip() {
+        // Message: `z=1, a={y=true, b=null}, m=[2, 3]`
+        let message = MessageBuilder::new()
+            .field("z", FieldValue::Integer(1))
+            .field(
+                "a",
+                FieldValue::Object(Object(
+                    [
+                        (FieldName::from("y"), FieldValue::Bool(true)),
+                        (FieldName::from("b"), FieldValue::Null),
+                    ]
+                    .into(),
+                )),
+            )
+            .field("m", FieldValue::List(List::Integers(vec![2, 3])))
+            .build()
+            .unwrap();
+        let binary_message = message.serialize().unwrap();
+        assert_eq!(binary_message[4..6], [0x01, b'z']);
+
+        let (deserialized_message, _) = Message::deserialize(&binary_message, None).unwrap();
+        let names: Vec<_> = deserialized_message
+            .body
+            .keys()
+            .map(FieldName::as_str)
+            .collect();
+        assert_eq!(names, ["z", "a", "m"]);
+        assert_eq!(deserialized_message.serialize().unwrap(), binary_message);
+    }
+
+    #[test]
+    fn canonical_serialization() {
+        let prices = |entries: Vec<(i64, Decimal)>| {
+            FieldValue::Map(Map(entries
+                .into_iter()
+                .map(|(key, price)| (FieldValue::Integer(key), FieldValue::Decimal(price)))
+                .collect()))
+        };
+        let message = MessageBuilder::new()
+            .field("price", FieldValue::Decimal(Decimal::new(1250, -2)))
+            .field("delta", FieldValue::Float(-0.0))
+            .field(
+                "levels",
+                prices(vec![(2, Decimal::new(3, 0)), (1, Decimal::new(40, -1))]),
+            )
+            .build()
+            .unwrap();
+        let equal_message = MessageBuilder::new()
+            .with_version(VERSION2)
+            .field(
+                "levels",
+                prices(vec![(1, Decimal::new(4, 0)), (2, Decimal::new(300, -2))]),
+            )
+            .field("delta", FieldValue::Float(0.0))
+            .field("price", FieldValue::Decimal(Decimal::new(125, -1)))
+            .build()
+            .unwrap();
+        assert_ne!(
+            message.serialize().unwrap(),
+            equal_message.serialize().unwrap()
+        );
+
+        let canonical = message.serialize_canonical().unwrap();
+        assert_eq!(canonical, equal_message.serialize_canonical().unwrap());
+        assert_eq!(canonical[0], VERSION1);
+        assert_eq!(canonical[4..10], [0x05, b'd', b'e', b'l', b't', b'a']);
+        let (deserialized_message, _) = Message::deserialize(&canonical, None).unwrap();
+        assert_eq!(deserialized_message.serialize().unwrap(), canonical);
+    }
+
+    #[test]
+    fn message_new() {
+        let message = Message::new([
+            ("user_id", FieldValue::Integer(1001)),
+            (
+                "name",
+                FieldValue::String(StringValue(String::from("Alice"))),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(message.header.field_count, 2);
+        assert_eq!(
+            message.header.length as usize,
+            message.serialize().unwrap().len()
+        );
+        assert_eq!(message.get("user_id"), Some(&FieldValue::Integer(1001)));
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let meta = Object(
+            [(
+                FieldName::from("venue"),
+                FieldValue::String(StringValue(String::from("GX"))),
+            )]
+            .into(),
+        );
+        let message = Message::new([
+            ("price", FieldValue::Integer(100)),
+            (
+                "symbol",
+                FieldValue::String(StringValue(String::from("BTC"))),
+            ),
+            ("fills", FieldValue::List(List::Integers(vec![1, 2]))),
+            ("meta", FieldValue::Object(meta)),
+        ])
+        .unwrap();
+
+        assert_eq!(message.get_i64("price"), Ok(100));
+        assert_eq!(message.get_str("symbol"), Ok("BTC"));
+        assert_eq!(message.get_list("fills"), Ok(&List::Integers(vec![1, 2])));
+        assert_eq!(
+            message.get_object("meta").unwrap().get_str("venue"),
+            Ok("GX")
+        );
+
+        assert_eq!(
+            message.get_i64("quantity"),
+            Err(FieldError::Missing {
+                name: String::from("quantity")
+            })
+        );
+        let error = message.get_i64("symbol").unwrap_err();
+        assert_eq!(
+            error,
+            FieldError::WrongType {
+                name: String::from("symbol"),
+                expected: "integer",
+                found: "string"
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "field `symbol`: expected integer, found string"
+        );
+    }
+
+    #[test]
+    fn path_access() {
+        let trade = |price| {
+            Object(
+                [
+                    (FieldName::from("price"), FieldValue::Integer(price)),
+                    (
+                        FieldName::from("fills"),
+                        FieldValue::List(List::Integers(vec![price, price + 1])),
+                    ),
+                ]
+                .into(),
+            )
+        };
+        let message = Message::new([
+            (
+                "trades",
+                FieldValue::List(List::Objects(vec![trade(10), trade(20), trade(30)])),
+            ),
+            (
+                "meta",
+                FieldValue::Object(Object(
+                    [(FieldName::from("venue"), FieldValue::Null)].into(),
+                )),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            message.get_path("trades[2].price").unwrap().as_ref(),
+            &FieldValue::Integer(30)
+        );
+        assert_eq!(
+            message.get_path("trades[1].fills[1]").unwrap().as_ref(),
+            &FieldValue::Integer(21)
+        );
+        assert_eq!(
+            message.get_path("trades[0]").unwrap().into_owned(),
+            FieldValue::Object(trade(10))
+        );
+        assert!(matches!(
+            message.get_path("meta.venue"),
+            Ok(Cow::Borrowed(FieldValue::Null))
+        ));
+
+        assert_eq!(
+            message.get_path("trades[3].price"),
+            Err(FieldError::OutOfBounds {
+                name: String::from("trades"),
+                index: 3,
+                length: 3
+            })
+        );
+        assert_eq!(
+            message.get_path("trades[0].quantity"),
+            Err(FieldError::Missing {
+                name: String::from("trades[0].quantity")
+            })
+        );
+        assert_eq!(
+            message.get_path("meta[0]").unwrap_err().to_string(),
+            "field `meta`: expected list, found object"
+        );
+        for path in ["", "trades[", "trades[x]", "trades..price", "[0]"] {
+            assert_eq!(
+                message.get_path(path),
+                Err(FieldError::InvalidPath {
+                    path: String::from(path)
+                }),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let message = Message::new([
+            ("price", 100.into()),
+            ("symbol", "BTC".into()),
+            ("fills", vec![1, 2].into()),
+            ("venues", vec!["GX", "LX"].into()),
+            ("fee", Decimal::new(15, -1).into()),
+            ("limit", None::<i64>.into()),
+        ])
+        .unwrap();
+        assert_eq!(message.get("price"), Some(&FieldValue::Integer(100)));
+        assert_eq!(
+            message.get("symbol"),
+            Some(&FieldValue::String(StringValue(String::from("BTC"))))
+        );
+        assert_eq!(message.get("limit"), Some(&FieldValue::Null));
+
+        let fills: Vec<i64> = message.get("fills").unwrap().clone().try_into().unwrap();
+        assert_eq!(fills, [1, 2]);
+        let venues: Vec<String> = message.get("venues").unwrap().clone().try_into().unwrap();
+        assert_eq!(venues, ["GX", "LX"]);
+        assert_eq!(
+            Decimal::try_from(message.get("fee").unwrap().clone()),
+            Ok(Decimal::new(15, -1))
+        );
+        assert_eq!(i64::try_from(FieldValue::Null), Err(FieldValue::Null));
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, GalacticSerialize, GalacticDeserialize)]
+    struct Venue {
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, GalacticSerialize, GalacticDeserialize)]
+    struct Order {
+        #[galactic(rename = "order_id")]
+        id: i64,
+        price: Decimal,
+        fills: Vec<i64>,
+        venue: Venue,
+        #[galactic(default)]
+        note: String,
+        #[galactic(skip)]
+        cached: bool,
+    }
+
+    #[test]
+    fn derive() {
+        let order = Order {
+            id: 7,
+            price: Decimal::new(1250, -2),
+            fills: vec![1, 2],
+            venue: Venue {
+                name: String::from("GX"),
+            },
+            note: String::from("rush"),
+            cached: true,
+        };
+        let object = order.to_object();
+        let names: Vec<_> = object.0.keys().map(FieldName::as_str).collect();
+        assert_eq!(names, ["order_id", "price", "fills", "venue", "note"]);
+        assert_eq!(
+            object.get_path("venue.name").unwrap().as_ref(),
+            &"GX".into()
+        );
+
+        let mut read = Order::from_object(&object).unwrap();
+        assert!(!read.cached);
+        read.cached = true;
+        assert_eq!(read, order);
+
+        let mut object = object;
+        object.0.shift_remove(&FieldName::from("note"));
+        assert_eq!(Order::from_object(&object).unwrap().note, "");
+        object.0.insert(FieldName::from("fills"), "none".into());
+        assert_eq!(
+            Order::from_object(&object),
+            Err(FieldError::WrongType {
+                name: String::from("fills"),
+                expected: "Vec<i64>",
+                found: "string"
+            })
+        );
+        object.0.shift_remove(&FieldName::from("order_id"));
+        assert_eq!(
+            Order::from_object(&object),
+            Err(FieldError::Missing {
+                name: String::from("order_id")
+            })
+        );
     }
 }
@@ -0,0 +1,701 @@
+//! Messages deserialized into an arena, for feeds decoding more messages than
+//! the allocator keeps up with
+//!
+//! The lists, objects and maps of a message are allocated in a [`Bump`] and
+//! its strings borrow from the bytes it was read from, so a message costs no
+//! allocation of its own and the arena frees a whole batch of them at once.
+//! Fields are kept as written, the [`DuplicateFields`] policy doesn't apply
+//! and [`ArenaMessage::get`] finds the first of repeated names.
+//!
+//! [`DuplicateFields`]: super::DuplicateFields
+
+use std::time::Duration;
+
+use bumpalo::{Bump, collections::Vec as BumpVec};
+use num_bigint::{BigInt, Sign};
+
+use super::{
+    ARRAY_T, Array, BIGINT_T, BOOL_T, COMPACT_INTEGER_T, COMPRESSED_FLAG, DATE_T, DECIMAL_T,
+    DURATION_T, Deserializable, DeserializeError, DeserializeErrorKind, ENCRYPTED_FLAG, ENUM_T,
+    Encoding, EnumValue, FLOAT_T, FieldName, FieldValue, Fields, Frame, Header, INT128_T,
+    INTEGER_T, LIST_T, List, MAP_KEY_TYPES, MAP_T, MIXED_T, Map, Message, NAME_TABLE_FLAG, NULL_T,
+    OBJECT_T, Object, Protection, STRING_T, StringValue, UUID_T, Utf8Region, VALUE_TYPES, VERSION3,
+    body_dictionary, decompress, deserialize_compact_integer, deserialize_field_count,
+    deserialize_length, deserialize_name_table, deserialize_str, deserialize_width, eof,
+    fixed_encoding, fixed_size, map_key, name_index_error,
+};
+use crate::{date::Date, decimal::Decimal};
+
+/// Field name and value of a message or object in an arena
+pub(crate) type ArenaField<'a> = (&'a str, ArenaValue<'a>);
+
+/// Message whose fields live in an arena
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ArenaMessage<'a> {
+    pub(crate) header: Header,
+    pub(crate) body: &'a [ArenaField<'a>],
+}
+
+/// [`FieldValue`] in an arena
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ArenaValue<'a> {
+    Integer(i64),
+    String(&'a str),
+    List(ArenaList<'a>),
+    Object(&'a [ArenaField<'a>]),
+    Float(f64),
+    Bool(bool),
+    Null,
+    Uuid([u8; 16]),
+    Decimal(Decimal),
+    Map(&'a [(ArenaValue<'a>, ArenaValue<'a>)]),
+    Array(&'a [ArenaValue<'a>]),
+    Enum(u16, Option<&'a ArenaValue<'a>>),
+    Date(Date),
+    Duration(Duration),
+    Int128(i128),
+    /// Whether it is negative and its magnitude, big-endian
+    BigInt(bool, &'a [u8]),
+    Unknown(u8, &'a [u8]),
+}
+
+/// [`List`] in an arena
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ArenaList<'a> {
+    Integers(&'a [i64]),
+    Strings(&'a [&'a str]),
+    Objects(&'a [&'a [ArenaField<'a>]]),
+    Floats(&'a [f64]),
+    Bools(&'a [bool]),
+    Lists(&'a [ArenaList<'a>]),
+    Mixed(&'a [ArenaValue<'a>]),
+}
+
+impl Message {
+    /// Deserializes the message into `arena`, strings are borrowed from `bytes`
+    /// unless the body is compressed
+    pub(crate) fn deserialize_in<'a>(
+        bytes: &'a [u8],
+        arena: &'a Bump,
+    ) -> Result<(ArenaMessage<'a>, &'a [u8]), DeserializeError> {
+        Message::deserialize_in_with(bytes, arena, Encoding::default())
+    }
+
+    pub(crate) fn deserialize_in_with<'a>(
+        bytes: &'a [u8],
+        arena: &'a Bump,
+        encoding: Encoding,
+    ) -> Result<(ArenaMessage<'a>, &'a [u8]), DeserializeError> {
+        let Frame {
+            header,
+            encoding,
+            header_bytes,
+            body,
+            rest,
+        } = Message::split_frame(bytes, encoding, Protection::default())?;
+        let header_size = header_bytes.len();
+        let limits = encoding.limits;
+        if header.flags & ENCRYPTED_FLAG != 0 {
+            return Err(DeserializeError::at(DeserializeErrorKind::Encrypted, 0));
+        }
+        let body: &[u8] = match header.flags & COMPRESSED_FLAG {
+            0 => body,
+            _ => {
+                let dictionary = body_dictionary(&header, None)?;
+                let decompressed =
+                    decompress(body, limits.max_message_size, dictionary).map_err(|e| {
+                        let kind = DeserializeErrorKind::InvalidCompressedBody(e.to_string());
+                        DeserializeError::at(kind, header_size)
+                    })?;
+                if decompressed.len() > limits.max_message_size {
+                    let kind = DeserializeErrorKind::LimitExceeded {
+                        limit: "decompressed body size",
+                        value: decompressed.len(),
+                        max: limits.max_message_size,
+                    };
+                    return Err(DeserializeError::at(kind, header_size));
+                }
+                arena.alloc_slice_copy(&decompressed)
+            }
+        };
+
+        let (names, fields_bytes) = match header.flags & NAME_TABLE_FLAG {
+            0 => (None, body),
+            _ => {
+                let (names, bytes) = deserialize_name_table(body, encoding)
+                    .map_err(|e| e.resolve(body.len(), header_size))?;
+                let names = arena.alloc_slice_fill_iter(
+                    names.iter().map(|name| &*arena.alloc_str(name.as_str())),
+                );
+                (Some(&*names), bytes)
+            }
+        };
+        let mut reader = ArenaReader {
+            arena,
+            fields: 0,
+            names,
+        };
+        let encoding = Encoding {
+            valid_utf8: Utf8Region::of(fields_bytes),
+            ..encoding
+        };
+        let (fields, left) = reader
+            .fields(fields_bytes, header.field_count as usize, encoding)
+            .map_err(|e| e.resolve(body.len(), header_size))?;
+        if reader.fields > limits.max_fields {
+            let kind = DeserializeErrorKind::LimitExceeded {
+                limit: "fields",
+                value: reader.fields,
+                max: limits.max_fields,
+            };
+            return Err(DeserializeError::at(kind, header_size));
+        }
+        if !left.is_empty() {
+            let length = header.length as usize;
+            let actual = length - left.len();
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::LengthMismatch { length, actual },
+                left,
+            )
+            .resolve(body.len(), header_size));
+        }
+        let message = ArenaMessage {
+            header,
+            body: fields,
+        };
+        Ok((message, rest))
+    }
+}
+
+impl<'a> ArenaMessage<'a> {
+    /// Value of the first field of that name
+    pub(crate) fn get(&self, name: &str) -> Option<&ArenaValue<'a>> {
+        self.body
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Copies the message out of the arena
+    pub(crate) fn to_message(self) -> Message {
+        Message {
+            header: self.header,
+            body: to_fields(self.body),
+        }
+    }
+}
+
+impl ArenaValue<'_> {
+    /// Copies the value out of the arena
+    pub(crate) fn to_field_value(self) -> FieldValue {
+        match self {
+            ArenaValue::Integer(integer) => FieldValue::Integer(integer),
+            ArenaValue::String(string) => FieldValue::String(StringValue(String::from(string))),
+            ArenaValue::List(list) => FieldValue::List(list.to_list()),
+            ArenaValue::Object(fields) => FieldValue::Object(Object(to_fields(fields))),
+            ArenaValue::Float(float) => FieldValue::Float(float),
+            ArenaValue::Bool(boolean) => FieldValue::Bool(boolean),
+            ArenaValue::Null => FieldValue::Null,
+            ArenaValue::Uuid(uuid) => FieldValue::Uuid(uuid),
+            ArenaValue::Decimal(decimal) => FieldValue::Decimal(decimal),
+            ArenaValue::Map(entries) => FieldValue::Map(Map(entries
+                .iter()
+                .map(|(key, value)| (key.to_field_value(), value.to_field_value()))
+                .collect())),
+            ArenaValue::Array(elements) => {
+                FieldValue::Array(Array(elements.iter().map(|e| e.to_field_value()).collect()))
+            }
+            ArenaValue::Enum(discriminant, payload) => FieldValue::Enum(EnumValue {
+                discriminant,
+                payload: payload.map(|payload| Box::new(payload.to_field_value())),
+            }),
+            ArenaValue::Date(date) => FieldValue::Date(date),
+            ArenaValue::Duration(duration) => FieldValue::Duration(duration),
+            ArenaValue::Int128(integer) => FieldValue::Int128(integer),
+            ArenaValue::BigInt(negative, magnitude) => {
+                let sign = if negative { Sign::Minus } else { Sign::Plus };
+                FieldValue::BigInt(BigInt::from_bytes_be(sign, magnitude))
+            }
+            ArenaValue::Unknown(type_indicator, bytes) => {
+                FieldValue::Unknown(type_indicator, bytes.to_vec())
+            }
+        }
+    }
+}
+
+impl ArenaList<'_> {
+    /// Copies the list out of the arena
+    pub(crate) fn to_list(self) -> List {
+        match self {
+            ArenaList::Integers(integers) => List::Integers(integers.to_vec()),
+            ArenaList::Strings(strings) => List::Strings(
+                strings
+                    .iter()
+                    .map(|string| StringValue(String::from(*string)))
+                    .collect(),
+            ),
+            ArenaList::Objects(objects) => List::Objects(
+                objects
+                    .iter()
+                    .map(|fields| Object(to_fields(fields)))
+                    .collect(),
+            ),
+            ArenaList::Floats(floats) => List::Floats(floats.to_vec()),
+            ArenaList::Bools(bools) => List::Bools(bools.to_vec()),
+            ArenaList::Lists(lists) => {
+                List::Lists(lists.iter().map(|list| list.to_list()).collect())
+            }
+            ArenaList::Mixed(values) => {
+                List::Mixed(values.iter().map(|value| value.to_field_value()).collect())
+            }
+        }
+    }
+}
+
+fn to_fields(fields: &[ArenaField<'_>]) -> Fields {
+    fields
+        .iter()
+        .map(|(name, value)| (FieldName::from(*name), value.to_field_value()))
+        .collect()
+}
+
+/// Reads values into the arena, counting the fields of all objects on the way
+struct ArenaReader<'a> {
+    arena: &'a Bump,
+    fields: usize,
+    /// Name table of the body, fields are named by their index in it
+    names: Option<&'a [&'a str]>,
+}
+
+impl<'a> ArenaReader<'a> {
+    /// [Field 1][Field 2]...[Field N]
+    fn fields(
+        &mut self,
+        mut bytes: &'a [u8],
+        count: usize,
+        encoding: Encoding,
+    ) -> Result<(&'a [ArenaField<'a>], &'a [u8]), DeserializeError> {
+        let encoding = encoding.reserve(count, std::mem::size_of::<ArenaField>(), bytes)?;
+        self.fields += count;
+        let mut fields = BumpVec::with_capacity_in(count, self.arena);
+        for _ in 0..count {
+            let (name, next_bytes) = self.name(bytes, encoding)?;
+            let (value, next_bytes) = self
+                .field_value(next_bytes, encoding)
+                .map_err(|e| e.within(name))?;
+            fields.push((name, value));
+            bytes = next_bytes;
+        }
+        Ok((fields.into_bump_slice(), bytes))
+    }
+
+    /// [Length (1 byte)][UTF-8 Data] or [Field ID (2 bytes)] with field IDs
+    /// or [Name Index (2 bytes)] with a name table
+    fn name(
+        &self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(&'a str, &'a [u8]), DeserializeError> {
+        if encoding.field_ids {
+            let (name, rest) = FieldName::deserialize_with(bytes, None, encoding)?;
+            let Some(names) = self.names else {
+                return Ok((self.arena.alloc_str(name.as_str()), rest));
+            };
+            return match name.id().and_then(|index| names.get(index as usize)) {
+                Some(name) => Ok((name, rest)),
+                None => {
+                    let kind = name_index_error(&name, names.len());
+                    Err(DeserializeError::new(kind, bytes))
+                }
+            };
+        }
+        let (&length, bytes) = bytes
+            .split_first()
+            .ok_or_else(|| eof("u8 (field name length)", bytes))?;
+        deserialize_str(bytes, length as usize, encoding)
+    }
+
+    /// [Type (1 byte)][Value] or since version 3 [Type (1 byte)][Length][Value]
+    fn field_value(
+        &mut self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(ArenaValue<'a>, &'a [u8]), DeserializeError> {
+        let (&type_indicator, bytes) = bytes
+            .split_first()
+            .ok_or_else(|| eof("u8 (type indicator)", bytes))?;
+        if encoding.version < VERSION3 {
+            return self.value(type_indicator, bytes, encoding);
+        }
+
+        let (length, value_bytes) = deserialize_length(bytes, "value length")?;
+        if length > value_bytes.len() {
+            return Err(eof(format!("value of {} bytes", length), value_bytes));
+        }
+        let (value_bytes, bytes) = value_bytes.split_at(length);
+        if !VALUE_TYPES.contains(&type_indicator) {
+            return Ok((ArenaValue::Unknown(type_indicator, value_bytes), bytes));
+        }
+        let (value, left) = self
+            .value(type_indicator, value_bytes, encoding)
+            .map_err(|e| e.followed_by(bytes))?;
+        if !left.is_empty() {
+            let kind = DeserializeErrorKind::ValueLengthMismatch {
+                length,
+                actual: length - left.len(),
+            };
+            return Err(DeserializeError::new(kind, value_bytes).followed_by(bytes));
+        }
+        Ok((value, bytes))
+    }
+
+    /// Value of the given type, the type indicator has already been read
+    fn value(
+        &mut self,
+        type_indicator: u8,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(ArenaValue<'a>, &'a [u8]), DeserializeError> {
+        let (value, bytes) = match type_indicator {
+            INTEGER_T => {
+                let (integer, bytes) = i64::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Integer(integer), bytes)
+            }
+            STRING_T => {
+                let (length, bytes) = deserialize_length(bytes, "length")?;
+                let (string, bytes) = deserialize_str(bytes, length, encoding)?;
+                (ArenaValue::String(string), bytes)
+            }
+            LIST_T => {
+                let (list, bytes) = self.list(bytes, encoding)?;
+                (ArenaValue::List(list), bytes)
+            }
+            OBJECT_T => {
+                let encoding = encoding.nested(bytes)?;
+                let (count, bytes) = deserialize_field_count(bytes, encoding)?;
+                let (fields, bytes) = self.fields(bytes, count, encoding)?;
+                (ArenaValue::Object(fields), bytes)
+            }
+            FLOAT_T => {
+                let (float, bytes) = f64::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Float(float), bytes)
+            }
+            BOOL_T => {
+                let (boolean, bytes) = bool::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Bool(boolean), bytes)
+            }
+            NULL_T => (ArenaValue::Null, bytes),
+            UUID_T => {
+                let (uuid, bytes) = <[u8; 16]>::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Uuid(uuid), bytes)
+            }
+            DECIMAL_T => {
+                let (decimal, bytes) = Decimal::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Decimal(decimal), bytes)
+            }
+            DATE_T => {
+                let (date, bytes) = Date::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Date(date), bytes)
+            }
+            DURATION_T => {
+                let (duration, bytes) = Duration::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Duration(duration), bytes)
+            }
+            INT128_T => {
+                let (integer, bytes) = i128::deserialize_with(bytes, None, encoding)?;
+                (ArenaValue::Int128(integer), bytes)
+            }
+            BIGINT_T => {
+                let (negative, bytes) = bool::deserialize_with(bytes, None, encoding)?;
+                let (length, bytes) = deserialize_length(bytes, "bigint length")?;
+                let magnitude = bytes
+                    .get(..length)
+                    .ok_or_else(|| eof(format!("bigint of length {}", length), bytes))?;
+                (ArenaValue::BigInt(negative, magnitude), &bytes[length..])
+            }
+            MAP_T => self.map(bytes, encoding)?,
+            ARRAY_T => self.array(bytes, encoding)?,
+            ENUM_T => {
+                let encoding = encoding.nested(bytes)?;
+                let discriminant = bytes
+                    .get(..std::mem::size_of::<u16>())
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                    .ok_or_else(|| eof("u16 (discriminant)", bytes))?;
+                let (payload, bytes) = self
+                    .field_value(&bytes[std::mem::size_of::<u16>()..], encoding)
+                    .map_err(|e| e.within(format!("({})", discriminant)))?;
+                let payload = match payload {
+                    ArenaValue::Null => None,
+                    payload => Some(&*self.arena.alloc(payload)),
+                };
+                (ArenaValue::Enum(discriminant, payload), bytes)
+            }
+            COMPACT_INTEGER_T => {
+                let (width, bytes) = deserialize_width(bytes)?;
+                let (integer, bytes) = deserialize_compact_integer(bytes, width)?;
+                (ArenaValue::Integer(integer), bytes)
+            }
+            t => {
+                return Err(DeserializeError::new(
+                    DeserializeErrorKind::UnknownType { type_indicator: t },
+                    bytes,
+                ));
+            }
+        };
+        Ok((value, bytes))
+    }
+
+    /// [Element Type (1 byte)][Element Count (2 bytes)][Elements...]
+    fn list(
+        &mut self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(ArenaList<'a>, &'a [u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let (&element_type, bytes) = bytes
+            .split_first()
+            .ok_or_else(|| eof("u8 (element type)", bytes))?;
+        let (width, bytes) = match element_type {
+            COMPACT_INTEGER_T => deserialize_width(bytes)?,
+            _ => (0, bytes),
+        };
+        let (count, mut bytes) = deserialize_length(bytes, "count")?;
+        let size = match element_type {
+            OBJECT_T => std::mem::size_of::<&[ArenaField]>(),
+            LIST_T => std::mem::size_of::<ArenaList>(),
+            MIXED_T => std::mem::size_of::<ArenaValue>(),
+            _ => std::mem::size_of::<i64>(),
+        };
+        let encoding = encoding.reserve(count, size, bytes)?;
+
+        /// Reads `count` elements with `element` into a slice of the arena
+        fn elements<'a, T>(
+            arena: &'a Bump,
+            count: usize,
+            bytes: &mut &'a [u8],
+            mut element: impl FnMut(&'a [u8]) -> Result<(T, &'a [u8]), DeserializeError>,
+        ) -> Result<&'a [T], DeserializeError> {
+            let mut elements = BumpVec::with_capacity_in(count, arena);
+            for i in 0..count {
+                let (value, next_bytes) =
+                    element(bytes).map_err(|e| e.within(format!("[{}]", i)))?;
+                elements.push(value);
+                *bytes = next_bytes;
+            }
+            Ok(elements.into_bump_slice())
+        }
+
+        let arena = self.arena;
+        let list = match element_type {
+            INTEGER_T => ArenaList::Integers(elements(arena, count, &mut bytes, |bytes| {
+                i64::deserialize_with(bytes, None, encoding)
+            })?),
+            STRING_T => ArenaList::Strings(elements(arena, count, &mut bytes, |bytes| {
+                let (length, bytes) = deserialize_length(bytes, "length")?;
+                deserialize_str(bytes, length, encoding)
+            })?),
+            OBJECT_T => ArenaList::Objects(elements(arena, count, &mut bytes, |bytes| {
+                let encoding = encoding.nested(bytes)?;
+                let (count, bytes) = deserialize_field_count(bytes, encoding)?;
+                self.fields(bytes, count, encoding)
+            })?),
+            FLOAT_T => ArenaList::Floats(elements(arena, count, &mut bytes, |bytes| {
+                f64::deserialize_with(bytes, None, encoding)
+            })?),
+            BOOL_T => ArenaList::Bools(elements(arena, count, &mut bytes, |bytes| {
+                bool::deserialize_with(bytes, None, encoding)
+            })?),
+            LIST_T => ArenaList::Lists(elements(arena, count, &mut bytes, |bytes| {
+                self.list(bytes, encoding)
+            })?),
+            MIXED_T => ArenaList::Mixed(elements(arena, count, &mut bytes, |bytes| {
+                self.field_value(bytes, encoding)
+            })?),
+            COMPACT_INTEGER_T => {
+                ArenaList::Integers(elements(arena, count, &mut bytes, |bytes| {
+                    deserialize_compact_integer(bytes, width)
+                })?)
+            }
+            t => {
+                return Err(DeserializeError::new(
+                    DeserializeErrorKind::UnknownType { type_indicator: t },
+                    bytes,
+                ));
+            }
+        };
+        Ok((list, bytes))
+    }
+
+    /// [Key Type (1 byte)][Value Type (1 byte)][Entry Count (2 bytes)][Key 1][Value 1]...
+    fn map(
+        &mut self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(ArenaValue<'a>, &'a [u8]), DeserializeError> {
+        let encoding = encoding.nested(bytes)?;
+        let Some(&[key_type, value_type]) = bytes.get(..2) else {
+            return Err(eof("u8 (key type) and u8 (value type)", bytes));
+        };
+        if !MAP_KEY_TYPES.contains(&key_type) {
+            return Err(DeserializeError::new(
+                DeserializeErrorKind::UnsupportedMapKey {
+                    type_indicator: key_type,
+                },
+                bytes,
+            ));
+        }
+        let count = bytes
+            .get(2..4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| eof("u16 (count)", bytes))? as usize;
+        let mut bytes = &bytes[4..];
+        let size = std::mem::size_of::<(ArenaValue, ArenaValue)>();
+        let encoding = encoding.reserve(count, size, bytes)?;
+
+        let mut entries = BumpVec::with_capacity_in(count, self.arena);
+        for i in 0..count {
+            let (key, next_bytes) = self
+                .value(key_type, bytes, encoding)
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            let (value, next_bytes) = self
+                .value(value_type, next_bytes, encoding)
+                .map_err(|e| e.within(format!("[{}]", map_key(&key.to_field_value()))))?;
+            entries.push((key, value));
+            bytes = next_bytes;
+        }
+        Ok((ArenaValue::Map(entries.into_bump_slice()), bytes))
+    }
+
+    fn array(
+        &mut self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(ArenaValue<'a>, &'a [u8]), DeserializeError> {
+        let encoding = fixed_encoding(encoding);
+        let element_type = *bytes
+            .first()
+            .ok_or_else(|| eof("u8 (element type)", bytes))?;
+        let Some(size) = fixed_size(element_type) else {
+            let kind = DeserializeErrorKind::UnsupportedArrayElement {
+                type_indicator: element_type,
+            };
+            return Err(DeserializeError::new(kind, bytes));
+        };
+        let count = bytes
+            .get(1..3)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| eof("u16 (count)", bytes))? as usize;
+        let mut bytes = &bytes[3..];
+        if bytes.len() < count * size {
+            return Err(eof(format!("{} elements of {} bytes", count, size), bytes));
+        }
+        let encoding = encoding.reserve(count, std::mem::size_of::<ArenaValue>(), bytes)?;
+
+        let mut elements = BumpVec::with_capacity_in(count, self.arena);
+        for i in 0..count {
+            let (element, next_bytes) = self
+                .value(element_type, bytes, encoding)
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            elements.push(element);
+            bytes = next_bytes;
+        }
+        Ok((ArenaValue::Array(elements.into_bump_slice()), bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{MessageBuilder, MessageType, Serializable};
+
+    fn message() -> Message {
+        let fill = Object(
+            [
+                (FieldName::from("quantity"), FieldValue::Integer(3)),
+                (FieldName::from("maker"), FieldValue::Bool(false)),
+            ]
+            .into(),
+        );
+        MessageBuilder::new()
+            .with_type(MessageType(3))
+            .field(
+                "symbol",
+                FieldValue::String(StringValue(String::from("XAU"))),
+            )
+            .field("fills", FieldValue::List(List::Objects(vec![fill])))
+            .field(
+                "prices",
+                FieldValue::Map(Map(vec![(
+                    FieldValue::String(StringValue(String::from("ask"))),
+                    FieldValue::Decimal(Decimal::new(1250, -2)),
+                )])),
+            )
+            .field(
+                "tags",
+                FieldValue::List(List::Strings(vec![StringValue(String::from("otc"))])),
+            )
+            .field(
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 1,
+                    payload: Some(Box::new(FieldValue::List(List::Mixed(vec![
+                        FieldValue::Null,
+                        FieldValue::Float(0.5),
+                    ])))),
+                }),
+            )
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn arena_matches_owned() {
+        let arena = Bump::new();
+        for encoding in [
+            Encoding::default(),
+            Encoding {
+                version: VERSION3,
+                ..Encoding::default()
+            },
+            Encoding {
+                compression_threshold: Some(0),
+                ..Encoding::default()
+            },
+            Encoding {
+                name_table: true,
+                ..Encoding::default()
+            },
+        ] {
+            let bytes = message().serialize_with(encoding).unwrap();
+            let (owned, _) = Message::deserialize(&bytes, None).unwrap();
+            let (in_arena, rest) = Message::deserialize_in(&bytes, &arena).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(in_arena.to_message(), owned);
+        }
+
+        let bytes = message().serialize().unwrap();
+        let (in_arena, _) = Message::deserialize_in(&bytes, &arena).unwrap();
+        let Some(ArenaValue::String(symbol)) = in_arena.get("symbol") else {
+            panic!("expected the symbol");
+        };
+        // Strings point into the message rather than the arena
+        assert!(bytes.as_ptr_range().contains(&symbol.as_ptr()));
+    }
+
+    #[test]
+    fn arena_errors() {
+        let arena = Bump::new();
+        let mut bytes = message().serialize().unwrap();
+        let owned = Message::deserialize(&bytes[..bytes.len() - 1], None).unwrap_err();
+        let error = Message::deserialize_in(&bytes[..bytes.len() - 1], &arena).unwrap_err();
+        assert_eq!(error, owned);
+
+        // Invalid UTF-8 in "XAU", path and offset as without the arena
+        let at = bytes.windows(3).position(|w| w == b"XAU").unwrap();
+        bytes[at] = 0xFF;
+        let owned = Message::deserialize(&bytes, None).unwrap_err();
+        let error = Message::deserialize_in(&bytes, &arena).unwrap_err();
+        assert_eq!(error.kind, DeserializeErrorKind::InvalidUtf8);
+        assert_eq!(error, owned);
+    }
+}
@@ -0,0 +1,41 @@
+//! Entry points of the benchmarks in `benches/`, public behind the
+//! `benchmarking` feature since benchmarks only see the public API
+//!
+//! Values cross over as serde types, so the benchmarks can write the same
+//! value with serde_json and bincode for comparison. [`Message`] keeps the
+//! conversion out of the measurement of the wire format itself.
+
+use ::serde::{Serialize, de::DeserializeOwned};
+
+use super::{Deserializable, Serializable, serde};
+
+/// Untyped message of the fields of a serde value
+pub struct Message(super::Message);
+
+impl Message {
+    /// Message of the fields `value` serializes to, which has to serialize as
+    /// a struct or a map with string keys
+    pub fn from_value<T: Serialize>(value: &T) -> Message {
+        let bytes = to_vec(value);
+        Message(super::Message::deserialize(&bytes, None).unwrap().0)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.0.serialize().unwrap()
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Message {
+        Message(super::Message::deserialize(bytes, None).unwrap().0)
+    }
+}
+
+/// Serializes the value as a message, through the serde data model
+pub fn to_vec<T: Serialize>(value: &T) -> Vec<u8> {
+    serde::to_vec(value).unwrap()
+}
+
+/// Deserializes the value from the fields of a message, through the serde
+/// data model
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> T {
+    serde::from_slice(bytes).unwrap()
+}
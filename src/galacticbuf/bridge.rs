@@ -0,0 +1,551 @@
+//! Messages as MessagePack or CBOR, for systems which ingest exchange messages
+//! with a decoder for one of those rather than a galacticbuf one
+//!
+//! Both carry the fields of a message as a map with string keys, the header is
+//! left out. Scalars, lists and objects map onto the native types of the
+//! format, arrays become arrays, maps keep keys of any type and enums become a
+//! map of their `discriminant` and `payload`, which come back as an object. Beyond that:
+//!
+//! | galacticbuf    | MessagePack (`msgpack`)          | CBOR (`cbor`)                      |
+//! |----------------|----------------------------------|------------------------------------|
+//! | UUID           | 16 byte binary                   | tag 37 on 16 bytes                 |
+//! | decimal        | string, e.g. `"12.50"`           | tag 4 on `[exponent, mantissa]`    |
+//! | date           | string, e.g. `"2024-03-01"`      | tag 100 on days since 1970-01-01   |
+//! | duration       | integer nanoseconds              | integer nanoseconds                |
+//! | int128, bigint | integer, beyond 64 bits a string | integer, beyond 64 bits tag 2 or 3 |
+//! | unknown type   | ext of the type indicator        | null                               |
+//!
+//! Reading, integers beyond `i64` and byte strings which are no UUID are
+//! rejected, maps whose keys are all strings become objects and CBOR tags
+//! other than the five above are dropped in favour of the value they tag.
+//! CBOR bignums come back as bigints, durations and other large integers as
+//! integers or strings, MessagePack dates as strings. An ext reads as a value
+//! of an unknown type, which no message can be built of.
+
+use std::fmt::{self, Display};
+
+use num_bigint::BigInt;
+#[cfg(feature = "cbor")]
+use num_bigint::Sign;
+
+use super::{
+    Array, ConvertError, EnumValue, FieldName, FieldValue, Fields, List, Map, Message,
+    MessageBuilder, Object, StringValue,
+};
+#[cfg(feature = "cbor")]
+use crate::{date::Date, decimal::Decimal};
+
+/// Bytes of another format which don't make a message
+#[derive(Debug, PartialEq)]
+pub(crate) enum BridgeError {
+    /// Bytes which aren't valid in their format
+    Decode(String),
+    /// Value without a counterpart in a message
+    Convert(ConvertError),
+}
+
+impl Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BridgeError::Decode(reason) => write!(f, "{}", reason),
+            BridgeError::Convert(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {}
+
+impl From<ConvertError> for BridgeError {
+    fn from(error: ConvertError) -> Self {
+        BridgeError::Convert(error)
+    }
+}
+
+/// Untyped message of the entries of a map, which all need string keys
+fn message<V>(
+    entries: Vec<(V, V)>,
+    convert: impl Fn(V) -> Result<FieldValue, ConvertError>,
+) -> Result<Message, ConvertError> {
+    let mut builder = MessageBuilder::new();
+    for (key, value) in entries {
+        let FieldValue::String(StringValue(name)) = convert(key)? else {
+            return Err(ConvertError::unsupported(
+                "message must be a map with string keys",
+            ));
+        };
+        let value = convert(value).map_err(|e| e.within(&name))?;
+        builder = builder.field(&name, value);
+    }
+    Ok(builder.build()?)
+}
+
+/// List of the values, see [`List::from_values`]
+fn list<V>(
+    values: Vec<V>,
+    convert: impl Fn(V) -> Result<FieldValue, ConvertError>,
+) -> Result<FieldValue, ConvertError> {
+    let values = values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| convert(value).map_err(|e| e.within(format!("[{}]", i))))
+        .collect::<Result<_, _>>()?;
+    Ok(FieldValue::List(List::from_values(values)))
+}
+
+/// Object when all keys are strings, a map otherwise
+fn map<V>(
+    entries: Vec<(V, V)>,
+    convert: impl Fn(V) -> Result<FieldValue, ConvertError>,
+) -> Result<FieldValue, ConvertError> {
+    let mut converted = Vec::with_capacity(entries.len());
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        let key = convert(key).map_err(|e| e.within(format!("[{}]", i)))?;
+        let value = match &key {
+            FieldValue::String(StringValue(name)) => convert(value).map_err(|e| e.within(name)),
+            _ => convert(value).map_err(|e| e.within(format!("[{}]", i))),
+        }?;
+        converted.push((key, value));
+    }
+    if !converted
+        .iter()
+        .all(|(key, _)| matches!(key, FieldValue::String(_)))
+    {
+        return Ok(FieldValue::Map(Map(converted)));
+    }
+    let fields: Fields = converted
+        .into_iter()
+        .map(|(key, value)| match key {
+            FieldValue::String(StringValue(name)) => (FieldName::from(name), value),
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok(FieldValue::Object(Object(fields)))
+}
+
+#[cfg(feature = "msgpack")]
+impl Message {
+    /// Fields of the message as a MessagePack map, see the module docs
+    pub(crate) fn to_msgpack(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        rmpv::encode::write_value(&mut bytes, &msgpack_fields(&self.body))
+            .expect("writing into memory does not fail");
+        bytes
+    }
+
+    /// Untyped message of a MessagePack map, see the module docs
+    pub(crate) fn from_msgpack(bytes: &[u8]) -> Result<Message, BridgeError> {
+        let value = rmpv::decode::read_value(&mut &bytes[..])
+            .map_err(|e| BridgeError::Decode(e.to_string()))?;
+        let rmpv::Value::Map(entries) = value else {
+            let reason = "message must be a map with string keys";
+            return Err(ConvertError::unsupported(reason).into());
+        };
+        Ok(message(entries, from_msgpack)?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+fn msgpack_fields(fields: &Fields) -> rmpv::Value {
+    rmpv::Value::Map(
+        fields
+            .iter()
+            .map(|(name, value)| (rmpv::Value::from(name.as_str()), to_msgpack(value)))
+            .collect(),
+    )
+}
+
+#[cfg(feature = "msgpack")]
+fn to_msgpack(value: &FieldValue) -> rmpv::Value {
+    use rmpv::Value;
+
+    match value {
+        FieldValue::Integer(integer) => Value::from(*integer),
+        FieldValue::Float(float) => Value::F64(*float),
+        FieldValue::String(StringValue(string)) => Value::from(string.as_str()),
+        FieldValue::Bool(boolean) => Value::Boolean(*boolean),
+        FieldValue::Null => Value::Nil,
+        FieldValue::List(list) => {
+            Value::Array(list.clone().into_values().iter().map(to_msgpack).collect())
+        }
+        FieldValue::Array(Array(elements)) => {
+            Value::Array(elements.iter().map(to_msgpack).collect())
+        }
+        FieldValue::Object(Object(fields)) => msgpack_fields(fields),
+        FieldValue::Uuid(uuid) => Value::Binary(uuid.to_vec()),
+        FieldValue::Decimal(decimal) => Value::from(decimal.to_string()),
+        FieldValue::Date(date) => Value::from(date.to_string()),
+        FieldValue::Duration(duration) => Value::from(duration_nanos(duration)),
+        FieldValue::Int128(integer) => msgpack_integer(&BigInt::from(*integer)),
+        FieldValue::BigInt(integer) => msgpack_integer(integer),
+        FieldValue::Map(Map(entries)) => Value::Map(
+            entries
+                .iter()
+                .map(|(key, value)| (to_msgpack(key), to_msgpack(value)))
+                .collect(),
+        ),
+        FieldValue::Enum(EnumValue {
+            discriminant,
+            payload,
+        }) => Value::Map(vec![
+            (Value::from("discriminant"), Value::from(*discriminant)),
+            (
+                Value::from("payload"),
+                payload.as_deref().map_or(Value::Nil, to_msgpack),
+            ),
+        ]),
+        FieldValue::Unknown(type_indicator, bytes) => {
+            Value::Ext(*type_indicator as i8, bytes.clone())
+        }
+    }
+}
+
+/// Integer where it fits 64 bits, its digits otherwise
+#[cfg(feature = "msgpack")]
+fn msgpack_integer(integer: &BigInt) -> rmpv::Value {
+    use rmpv::Value;
+
+    if let Ok(integer) = i64::try_from(integer) {
+        Value::from(integer)
+    } else if let Ok(integer) = u64::try_from(integer) {
+        Value::from(integer)
+    } else {
+        Value::from(integer.to_string())
+    }
+}
+
+#[cfg(feature = "msgpack")]
+fn from_msgpack(value: rmpv::Value) -> Result<FieldValue, ConvertError> {
+    use rmpv::Value;
+
+    let value = match value {
+        Value::Nil => FieldValue::Null,
+        Value::Boolean(boolean) => FieldValue::Bool(boolean),
+        Value::Integer(integer) => match integer.as_i64() {
+            Some(integer) => FieldValue::Integer(integer),
+            None => {
+                let reason = format!("integer {} beyond i64", integer);
+                return Err(ConvertError::unsupported(reason));
+            }
+        },
+        Value::F32(float) => FieldValue::Float(float.into()),
+        Value::F64(float) => FieldValue::Float(float),
+        Value::String(string) => match string.into_str() {
+            Some(string) => FieldValue::String(StringValue(string)),
+            None => return Err(ConvertError::unsupported("invalid utf-8 string")),
+        },
+        Value::Binary(bytes) => match bytes.try_into() {
+            Ok(uuid) => FieldValue::Uuid(uuid),
+            Err(bytes) => {
+                let reason = format!("binary of {} bytes, only UUIDs are binary", bytes.len());
+                return Err(ConvertError::unsupported(reason));
+            }
+        },
+        Value::Array(values) => list(values, from_msgpack)?,
+        Value::Map(entries) => map(entries, from_msgpack)?,
+        Value::Ext(type_indicator, bytes) => FieldValue::Unknown(type_indicator as u8, bytes),
+    };
+    Ok(value)
+}
+
+/// Tag of a UUID in CBOR, a byte string of 16 bytes
+#[cfg(feature = "cbor")]
+const CBOR_UUID_TAG: u64 = 37;
+/// Tag of a decimal fraction in CBOR, an array of exponent and mantissa
+#[cfg(feature = "cbor")]
+const CBOR_DECIMAL_TAG: u64 = 4;
+/// Tag of a date in CBOR, an integer of days since 1970-01-01 (RFC 8943)
+#[cfg(feature = "cbor")]
+const CBOR_DATE_TAG: u64 = 100;
+/// Tags of bignums in CBOR, byte strings of the magnitude `n` of `n` and of
+/// `-1 - n` (RFC 8949)
+#[cfg(feature = "cbor")]
+const CBOR_POSITIVE_BIGNUM_TAG: u64 = 2;
+#[cfg(feature = "cbor")]
+const CBOR_NEGATIVE_BIGNUM_TAG: u64 = 3;
+
+#[cfg(feature = "cbor")]
+impl Message {
+    /// Fields of the message as a CBOR map, see the module docs
+    pub(crate) fn to_cbor(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        ciborium::into_writer(&cbor_fields(&self.body), &mut bytes)
+            .expect("writing into memory does not fail");
+        bytes
+    }
+
+    /// Untyped message of a CBOR map, see the module docs
+    pub(crate) fn from_cbor(bytes: &[u8]) -> Result<Message, BridgeError> {
+        let value: ciborium::Value =
+            ciborium::from_reader(bytes).map_err(|e| BridgeError::Decode(e.to_string()))?;
+        let ciborium::Value::Map(entries) = value else {
+            let reason = "message must be a map with string keys";
+            return Err(ConvertError::unsupported(reason).into());
+        };
+        Ok(message(entries, from_cbor)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+fn cbor_fields(fields: &Fields) -> ciborium::Value {
+    ciborium::Value::Map(
+        fields
+            .iter()
+            .map(|(name, value)| (ciborium::Value::from(name.as_str()), to_cbor(value)))
+            .collect(),
+    )
+}
+
+#[cfg(feature = "cbor")]
+fn to_cbor(value: &FieldValue) -> ciborium::Value {
+    use ciborium::Value;
+
+    match value {
+        FieldValue::Integer(integer) => Value::from(*integer),
+        FieldValue::Float(float) => Value::Float(*float),
+        FieldValue::String(StringValue(string)) => Value::from(string.as_str()),
+        FieldValue::Bool(boolean) => Value::Bool(*boolean),
+        FieldValue::Null | FieldValue::Unknown(..) => Value::Null,
+        FieldValue::List(list) => {
+            Value::Array(list.clone().into_values().iter().map(to_cbor).collect())
+        }
+        FieldValue::Array(Array(elements)) => Value::Array(elements.iter().map(to_cbor).collect()),
+        FieldValue::Object(Object(fields)) => cbor_fields(fields),
+        FieldValue::Uuid(uuid) => Value::Tag(CBOR_UUID_TAG, Box::new(Value::Bytes(uuid.to_vec()))),
+        FieldValue::Decimal(decimal) => Value::Tag(
+            CBOR_DECIMAL_TAG,
+            Box::new(Value::Array(vec![
+                Value::from(decimal.exponent()),
+                Value::from(decimal.mantissa()),
+            ])),
+        ),
+        FieldValue::Date(date) => Value::Tag(CBOR_DATE_TAG, Box::new(Value::from(date.days()))),
+        FieldValue::Duration(duration) => Value::from(duration_nanos(duration)),
+        FieldValue::Int128(integer) => cbor_integer(&BigInt::from(*integer)),
+        FieldValue::BigInt(integer) => cbor_integer(integer),
+        FieldValue::Map(Map(entries)) => Value::Map(
+            entries
+                .iter()
+                .map(|(key, value)| (to_cbor(key), to_cbor(value)))
+                .collect(),
+        ),
+        FieldValue::Enum(EnumValue {
+            discriminant,
+            payload,
+        }) => Value::Map(vec![
+            (Value::from("discriminant"), Value::from(*discriminant)),
+            (
+                Value::from("payload"),
+                payload.as_deref().map_or(Value::Null, to_cbor),
+            ),
+        ]),
+    }
+}
+
+#[cfg(feature = "cbor")]
+fn from_cbor(value: ciborium::Value) -> Result<FieldValue, ConvertError> {
+    use ciborium::Value;
+
+    let value = match value {
+        Value::Null => FieldValue::Null,
+        Value::Bool(boolean) => FieldValue::Bool(boolean),
+        Value::Integer(integer) => match i64::try_from(integer) {
+            Ok(integer) => FieldValue::Integer(integer),
+            Err(_) => {
+                let reason = format!("integer {} beyond i64", i128::from(integer));
+                return Err(ConvertError::unsupported(reason));
+            }
+        },
+        Value::Float(float) => FieldValue::Float(float),
+        Value::Text(string) => FieldValue::String(StringValue(string)),
+        Value::Tag(CBOR_UUID_TAG, value) => match *value {
+            Value::Bytes(bytes) if bytes.len() == 16 => {
+                FieldValue::Uuid(bytes.try_into().expect("length is checked"))
+            }
+            _ => {
+                return Err(ConvertError::unsupported(
+                    "UUID must be a string of 16 bytes",
+                ));
+            }
+        },
+        Value::Tag(CBOR_DECIMAL_TAG, value) => FieldValue::Decimal(cbor_decimal(*value)?),
+        Value::Tag(CBOR_DATE_TAG, value) => match *value {
+            Value::Integer(days) => match i32::try_from(days) {
+                Ok(days) => FieldValue::Date(Date::from_days(days)),
+                Err(_) => return Err(ConvertError::unsupported("date beyond 32 bit days")),
+            },
+            _ => return Err(ConvertError::unsupported("date must be an integer of days")),
+        },
+        Value::Tag(tag @ (CBOR_POSITIVE_BIGNUM_TAG | CBOR_NEGATIVE_BIGNUM_TAG), value) => {
+            let Value::Bytes(magnitude) = *value else {
+                return Err(ConvertError::unsupported("bignum must be a byte string"));
+            };
+            let integer = BigInt::from_bytes_be(Sign::Plus, &magnitude);
+            match tag {
+                CBOR_POSITIVE_BIGNUM_TAG => FieldValue::BigInt(integer),
+                _ => FieldValue::BigInt(-1 - integer),
+            }
+        }
+        Value::Tag(_, value) => from_cbor(*value)?,
+        Value::Bytes(bytes) => {
+            let reason = format!("{} bytes, only UUIDs are byte strings", bytes.len());
+            return Err(ConvertError::unsupported(reason));
+        }
+        Value::Array(values) => list(values, from_cbor)?,
+        Value::Map(entries) => map(entries, from_cbor)?,
+        _ => return Err(ConvertError::unsupported("CBOR value of an unknown kind")),
+    };
+    Ok(value)
+}
+
+/// Nanoseconds of the duration, saturated like a message can't hold more
+fn duration_nanos(duration: &std::time::Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Integer where CBOR has one, a bignum beyond
+#[cfg(feature = "cbor")]
+fn cbor_integer(integer: &BigInt) -> ciborium::Value {
+    use ciborium::Value;
+
+    let native = i128::try_from(integer)
+        .ok()
+        .and_then(|integer| ciborium::value::Integer::try_from(integer).ok());
+    if let Some(integer) = native {
+        return Value::Integer(integer);
+    }
+    let (tag, magnitude) = match integer.sign() {
+        Sign::Minus => (CBOR_NEGATIVE_BIGNUM_TAG, -1 - integer),
+        _ => (CBOR_POSITIVE_BIGNUM_TAG, integer.clone()),
+    };
+    Value::Tag(tag, Box::new(Value::Bytes(magnitude.to_bytes_be().1)))
+}
+
+/// [exponent, mantissa] of a decimal fraction, within the range of [`Decimal`]
+#[cfg(feature = "cbor")]
+fn cbor_decimal(value: ciborium::Value) -> Result<Decimal, ConvertError> {
+    use ciborium::Value;
+
+    let invalid = || ConvertError::unsupported("decimal must be [exponent, mantissa] within range");
+    let Value::Array(parts) = value else {
+        return Err(invalid());
+    };
+    let [Value::Integer(exponent), Value::Integer(mantissa)] = parts[..] else {
+        return Err(invalid());
+    };
+    let exponent = i8::try_from(exponent).map_err(|_| invalid())?;
+    let mantissa = i64::try_from(mantissa).map_err(|_| invalid())?;
+    Ok(Decimal::new(mantissa, exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decimal::Decimal;
+
+    fn message() -> Message {
+        let fill = Object([(FieldName::from("quantity"), FieldValue::Integer(3))].into());
+        Message::new([
+            ("id", FieldValue::Uuid([0xAB; 16])),
+            ("price", FieldValue::Decimal(Decimal::new(1250, -2))),
+            ("ratio", FieldValue::Float(0.5)),
+            (
+                "symbol",
+                FieldValue::String(StringValue(String::from("XAU"))),
+            ),
+            ("open", FieldValue::Bool(true)),
+            ("expires", FieldValue::Null),
+            ("fills", FieldValue::List(List::Objects(vec![fill]))),
+            (
+                "limits",
+                FieldValue::Map(Map(vec![(FieldValue::Integer(1), FieldValue::Integer(-5))])),
+            ),
+        ])
+        .unwrap()
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        let message = message();
+        let read = Message::from_msgpack(&message.to_msgpack()).unwrap();
+        for (name, value) in &message.body {
+            match value {
+                FieldValue::Decimal(decimal) => assert_eq!(
+                    read.body[name],
+                    FieldValue::String(StringValue(decimal.to_string()))
+                ),
+                value => assert_eq!(&read.body[name], value, "{}", name.as_str()),
+            }
+        }
+
+        // Values of unknown types are written as an ext of the same type, which
+        // comes back unknown and doesn't fit into a message of this version
+        let value = to_msgpack(&FieldValue::Unknown(0x42, vec![1, 2]));
+        assert_eq!(value, rmpv::Value::Ext(0x42, vec![1, 2]));
+        let error = Message::from_msgpack(&[0x81, 0xA1, b'x', 0xD5, 0x42, 1, 2]).unwrap_err();
+        assert!(matches!(
+            error,
+            BridgeError::Convert(ConvertError::Serialize(_))
+        ));
+
+        let error = Message::from_msgpack(&[0x92, 0x01, 0x02]).unwrap_err();
+        assert_eq!(error.to_string(), "message must be a map with string keys");
+        assert!(matches!(
+            Message::from_msgpack(&[0x81]),
+            Err(BridgeError::Decode(_))
+        ));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_round_trip() {
+        let message = message();
+        let bytes = message.to_cbor();
+        // Tag 37 on the 16 bytes of the UUID
+        assert!(bytes.windows(3).any(|w| w == [0xD8, 0x25, 0x50]));
+        let read = Message::from_cbor(&bytes).unwrap();
+        assert_eq!(read.body, message.body);
+
+        let side = FieldValue::Enum(EnumValue {
+            discriminant: 2,
+            payload: Some(Box::new(FieldValue::Integer(7))),
+        });
+        let message = Message::new([("side", side)]).unwrap();
+        let read = Message::from_cbor(&message.to_cbor()).unwrap();
+        assert_eq!(
+            read.get("side"),
+            Some(&FieldValue::Object(Object(
+                [
+                    (FieldName::from("discriminant"), FieldValue::Integer(2)),
+                    (FieldName::from("payload"), FieldValue::Integer(7)),
+                ]
+                .into()
+            )))
+        );
+
+        // Bignums beyond CBOR's integers, -2^64 - 1 being tag 3 on 2^64
+        let supply = -BigInt::from(u64::MAX) - 2u8;
+        let message = Message::new([
+            ("supply", FieldValue::BigInt(supply.clone())),
+            ("volume", FieldValue::Int128(-5)),
+        ])
+        .unwrap();
+        let bytes = message.to_cbor();
+        assert!(bytes.windows(3).any(|w| w == [0xC3, 0x49, 0x01]));
+        let read = Message::from_cbor(&bytes).unwrap();
+        assert_eq!(read.get("supply"), Some(&FieldValue::BigInt(supply)));
+        assert_eq!(read.get("volume"), Some(&FieldValue::Integer(-5)));
+
+        // {"fills": [{"id": 2^64 - 1}]}
+        let mut bytes = vec![0xA1, 0x65];
+        bytes.extend(b"fills");
+        bytes.extend([0x81, 0xA1, 0x62]);
+        bytes.extend(b"id");
+        bytes.extend([0x1B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let error = Message::from_cbor(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "fills[0].id: integer 18446744073709551615 beyond i64"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Capture files (`.gbc`), durable recordings of a message stream for replay
+//! and compliance
+//!
+//! [Magic "GBCAP" (5 bytes)][Capture Version (1 byte)][Metadata Length (4 bytes)][Metadata]
+//! [Record 1][Record 2]...[Record N]
+//!
+//! The metadata is a message of its own, e.g. with the source of the feed and
+//! the start of the recording. Each record is
+//! [Timestamp (8 bytes)][Length (4 bytes)][Message], the timestamp in
+//! nanoseconds since the Unix epoch. The message is kept as the bytes that
+//! arrived, so a recording holds what was on the wire even where that doesn't
+//! deserialize. Timestamps never decrease from one record to the next, which
+//! lets a reader seek to a point in time by skipping from record to record.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    Deserializable, DeserializeError, DeserializeErrorKind, Encoding, Message, Serializable,
+    SerializeError, reader::read_start,
+};
+
+const MAGIC: &[u8; 5] = b"GBCAP";
+const CAPTURE_VERSION: u8 = 0x01;
+/// [Timestamp (8 bytes)][Length (4 bytes)]
+const RECORD_HEADER_SIZE: usize = 12;
+
+/// Writes a capture, appending one record per message
+pub(crate) struct CaptureWriter<W> {
+    writer: W,
+    /// Timestamp of the last record, the next may not be earlier
+    last: u64,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Starts a capture with its metadata
+    pub(crate) fn new(mut writer: W, metadata: &Message) -> Result<Self, CaptureError> {
+        let metadata = metadata.serialize()?;
+        let mut head = Vec::with_capacity(MAGIC.len() + 5 + metadata.len());
+        head.extend(MAGIC);
+        head.push(CAPTURE_VERSION);
+        head.extend(record_length(metadata.len())?.to_be_bytes());
+        head.extend(metadata);
+        writer.write_all(&head)?;
+        Ok(CaptureWriter { writer, last: 0 })
+    }
+
+    /// Appends the message as it was received at `time`
+    pub(crate) fn append(
+        &mut self,
+        time: SystemTime,
+        message: &Message,
+    ) -> Result<(), CaptureError> {
+        let frame = message.serialize()?;
+        self.append_frame(time, &frame)
+    }
+
+    /// Appends the bytes of a message as they were received at `time`, which
+    /// may not be earlier than the time of the last record
+    ///
+    /// A record is written in one piece, an error leaves no part of it behind
+    /// unless the writer itself failed.
+    pub(crate) fn append_frame(
+        &mut self,
+        time: SystemTime,
+        frame: &[u8],
+    ) -> Result<(), CaptureError> {
+        let timestamp = nanoseconds(time)?;
+        if timestamp < self.last {
+            return Err(CaptureError::OutOfOrder {
+                last: self.last,
+                timestamp,
+            });
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + frame.len());
+        record.extend(timestamp.to_be_bytes());
+        record.extend(record_length(frame.len())?.to_be_bytes());
+        record.extend(frame);
+        self.writer.write_all(&record)?;
+        self.last = timestamp;
+        Ok(())
+    }
+
+    /// Flushes the records written so far, durable once the writer's own
+    /// storage is, e.g. after [`std::fs::File::sync_data`]
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a capture, record by record or as an iterator over its messages which
+/// ends after the first error
+pub(crate) struct CaptureReader<R> {
+    reader: R,
+    encoding: Encoding,
+    metadata: Message,
+    /// Position of the first record
+    start: u64,
+    /// Bytes of the current record's message, kept to read the next one into
+    frame: Vec<u8>,
+    failed: bool,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads the capture's metadata, which has to follow the magic of a
+    /// capture of a version this reader knows
+    pub(crate) fn new(reader: R) -> Result<Self, CaptureError> {
+        CaptureReader::with_encoding(reader, Encoding::default())
+    }
+
+    /// Reader applying the limits and policies of `encoding` to every message
+    pub(crate) fn with_encoding(mut reader: R, encoding: Encoding) -> Result<Self, CaptureError> {
+        let mut magic = [0; MAGIC.len() + 1];
+        reader.read_exact(&mut magic)?;
+        if magic[..MAGIC.len()] != MAGIC[..] {
+            return Err(CaptureError::NotACapture);
+        }
+        let version = magic[MAGIC.len()];
+        if version != CAPTURE_VERSION {
+            return Err(CaptureError::UnsupportedVersion(version));
+        }
+
+        let mut length = [0; 4];
+        reader.read_exact(&mut length)?;
+        let length = checked_length(u32::from_be_bytes(length), encoding)?;
+        let mut metadata = vec![0; length];
+        reader.read_exact(&mut metadata)?;
+        let (metadata, _) = Message::deserialize_with(&metadata, None, encoding)
+            .map_err(|e| e.resolve(metadata.len(), 0))?;
+        Ok(CaptureReader {
+            reader,
+            encoding,
+            metadata,
+            start: (magic.len() + 4 + length) as u64,
+            frame: vec![],
+            failed: false,
+        })
+    }
+
+    pub(crate) fn metadata(&self) -> &Message {
+        &self.metadata
+    }
+
+    /// Time and bytes of the next record, `None` at the end of the capture
+    pub(crate) fn read_frame(&mut self) -> Result<Option<(SystemTime, &[u8])>, CaptureError> {
+        let Some((timestamp, length)) = read_record_header(&mut self.reader)? else {
+            return Ok(None);
+        };
+        let length = checked_length(length, self.encoding)?;
+        self.frame.resize(length, 0);
+        self.reader.read_exact(&mut self.frame)?;
+        Ok(Some((time(timestamp), &self.frame)))
+    }
+
+    /// Time and message of the next record, `None` at the end of the capture
+    pub(crate) fn read_message(&mut self) -> Result<Option<(SystemTime, Message)>, CaptureError> {
+        let encoding = self.encoding;
+        let Some((time, frame)) = self.read_frame()? else {
+            return Ok(None);
+        };
+        let (message, _) = Message::deserialize_with(frame, None, encoding)
+            .map_err(|e| e.resolve(frame.len(), 0))?;
+        Ok(Some((time, message)))
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    /// Moves to the first record at or after `time`, or to the end of the
+    /// capture when there is none, reading only the record headers on the way
+    pub(crate) fn seek(&mut self, time: SystemTime) -> Result<(), CaptureError> {
+        let target = nanoseconds(time)?;
+        self.failed = false;
+        self.reader.seek(SeekFrom::Start(self.start))?;
+        while let Some((timestamp, length)) = read_record_header(&mut self.reader)? {
+            if timestamp >= target {
+                self.reader
+                    .seek(SeekFrom::Current(-(RECORD_HEADER_SIZE as i64)))?;
+                break;
+            }
+            self.reader.seek(SeekFrom::Current(length as i64))?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<(SystemTime, Message), CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let message = self.read_message();
+        self.failed = message.is_err();
+        message.transpose()
+    }
+}
+
+/// [Timestamp (8 bytes)][Length (4 bytes)], `None` at the end of the capture
+fn read_record_header<R: Read>(reader: &mut R) -> io::Result<Option<(u64, u32)>> {
+    let mut header = [0; RECORD_HEADER_SIZE];
+    if !read_start(reader, &mut header)? {
+        return Ok(None);
+    }
+    let (timestamp, length) = header.split_at(8);
+    Ok(Some((
+        u64::from_be_bytes(timestamp.try_into().unwrap()),
+        u32::from_be_bytes(length.try_into().unwrap()),
+    )))
+}
+
+/// Length of a record, checked against the size limit before it is read
+fn checked_length(length: u32, encoding: Encoding) -> Result<usize, DeserializeError> {
+    let length = length as usize;
+    let max = encoding.limits.max_message_size;
+    if length > max {
+        let kind = DeserializeErrorKind::LimitExceeded {
+            limit: "message size",
+            value: length,
+            max,
+        };
+        return Err(DeserializeError::at(kind, 0));
+    }
+    Ok(length)
+}
+
+fn record_length(length: usize) -> Result<u32, CaptureError> {
+    u32::try_from(length).map_err(|_| {
+        let message = format!("record of {} bytes", length);
+        CaptureError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+    })
+}
+
+/// Nanoseconds since the Unix epoch, times before it can't be recorded
+fn nanoseconds(time: SystemTime) -> Result<u64, CaptureError> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|since| u64::try_from(since.as_nanos()).ok())
+        .ok_or(CaptureError::InvalidTime(time))
+}
+
+fn time(nanoseconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanoseconds)
+}
+
+/// Capture which can't be written or read
+#[derive(Debug)]
+pub(crate) enum CaptureError {
+    Io(io::Error),
+    Serialize(SerializeError),
+    /// Message or metadata which doesn't deserialize, with offsets from its
+    /// start
+    Deserialize(DeserializeError),
+    /// Input without the magic of a capture
+    NotACapture,
+    UnsupportedVersion(u8),
+    /// Record earlier than the one before it, in nanoseconds since the epoch
+    OutOfOrder {
+        last: u64,
+        timestamp: u64,
+    },
+    /// Time before the Unix epoch or too far after it for 8 bytes of
+    /// nanoseconds
+    InvalidTime(SystemTime),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(error) => write!(f, "{}", error),
+            CaptureError::Serialize(error) => write!(f, "{}", error),
+            CaptureError::Deserialize(error) => write!(f, "{}", error),
+            CaptureError::NotACapture => write!(f, "not a galacticbuf capture"),
+            CaptureError::UnsupportedVersion(version) => {
+                write!(f, "unsupported capture version {}", version)
+            }
+            CaptureError::OutOfOrder { last, timestamp } => write!(
+                f,
+                "record at {}ns is earlier than the record before it at {}ns",
+                timestamp, last
+            ),
+            CaptureError::InvalidTime(time) => write!(f, "time {:?} can't be recorded", time),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        CaptureError::Io(error)
+    }
+}
+
+impl From<SerializeError> for CaptureError {
+    fn from(error: SerializeError) -> Self {
+        CaptureError::Serialize(error)
+    }
+}
+
+impl From<DeserializeError> for CaptureError {
+    fn from(error: DeserializeError) -> Self {
+        CaptureError::Deserialize(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::galacticbuf::{FieldValue, MessageBuilder, MessageType};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn trade(price: i64) -> Message {
+        MessageBuilder::new()
+            .with_type(MessageType(3))
+            .field("price", FieldValue::Integer(price))
+            .build()
+            .unwrap()
+    }
+
+    fn capture() -> Vec<u8> {
+        let metadata = Message::new([("source", "XGAL feed A".into())]).unwrap();
+        let mut writer = CaptureWriter::new(vec![], &metadata).unwrap();
+        for (seconds, price) in [(10, 100), (20, 101), (20, 99), (30, 102)] {
+            writer.append(at(seconds), &trade(price)).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn records_read_back_in_order() {
+        let capture = capture();
+        assert_eq!(&capture[..6], b"GBCAP\x01");
+        let reader = CaptureReader::new(&capture[..]).unwrap();
+        assert_eq!(reader.metadata().get("source"), Some(&"XGAL feed A".into()));
+        let records: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            records,
+            [
+                (at(10), trade(100)),
+                (at(20), trade(101)),
+                (at(20), trade(99)),
+                (at(30), trade(102)),
+            ]
+        );
+
+        // Bytes which aren't a message are recorded as they are
+        let mut writer = CaptureWriter::new(vec![], &Message::new([]).unwrap()).unwrap();
+        writer.append_frame(at(1), &[0x07, 0x00]).unwrap();
+        let capture = writer.into_inner();
+        let mut reader = CaptureReader::new(&capture[..]).unwrap();
+        assert_eq!(
+            reader.read_frame().unwrap(),
+            Some((at(1), &[0x07, 0x00][..]))
+        );
+        let mut reader = CaptureReader::new(&capture[..]).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(CaptureError::Deserialize(_)))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn seeks_by_time() {
+        let mut reader = CaptureReader::new(Cursor::new(capture())).unwrap();
+        reader.seek(at(20)).unwrap();
+        let prices: Vec<_> = reader
+            .by_ref()
+            .map(|record| record.unwrap().1.get("price").cloned().unwrap())
+            .collect();
+        assert_eq!(
+            prices,
+            [
+                FieldValue::Integer(101),
+                FieldValue::Integer(99),
+                FieldValue::Integer(102)
+            ]
+        );
+
+        reader.seek(at(15)).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().0, at(20));
+        reader.seek(at(0)).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().0, at(10));
+        reader.seek(at(31)).unwrap();
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let mut writer = CaptureWriter::new(vec![], &Message::new([]).unwrap()).unwrap();
+        writer.append(at(20), &trade(1)).unwrap();
+        let error = writer.append(at(10), &trade(2)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "record at 10000000000ns is earlier than the record before it at 20000000000ns"
+        );
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert!(matches!(
+            writer.append(before_epoch, &trade(3)),
+            Err(CaptureError::InvalidTime(_))
+        ));
+
+        assert!(matches!(
+            CaptureReader::new(&b"GBMSG\x01"[..]),
+            Err(CaptureError::NotACapture)
+        ));
+        assert!(matches!(
+            CaptureReader::new(&b"GBCAP\x02"[..]),
+            Err(CaptureError::UnsupportedVersion(2))
+        ));
+
+        // A record cut short, e.g. by a crash while it was written
+        let mut capture = capture();
+        capture.truncate(capture.len() - 1);
+        let mut reader = CaptureReader::new(&capture[..]).unwrap();
+        assert_eq!(reader.by_ref().take(3).filter(Result::is_ok).count(), 3);
+        let Some(Err(CaptureError::Io(error))) = reader.next() else {
+            panic!("expected the truncated record to fail");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,114 @@
+//! `tokio_util` codec for async transports, so servers and clients can wrap a
+//! socket in `Framed` and send and receive messages directly
+//!
+//! Messages frame themselves with the length in their header, the codec adds
+//! no framing of its own.
+
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{
+    Deserializable, Encoding, Header, Message, Serializable, WriteError,
+    reader::{ReadError, frame_length},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MessageCodec {
+    encoding: Encoding,
+}
+
+impl MessageCodec {
+    pub(crate) fn new() -> Self {
+        MessageCodec::default()
+    }
+
+    /// Codec writing messages with `encoding`, and reading them with its limits
+    /// and policies
+    pub(crate) fn with_encoding(encoding: Encoding) -> Self {
+        MessageCodec { encoding }
+    }
+}
+
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = ReadError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, ReadError> {
+        let Some(version) = src.first() else {
+            return Ok(None);
+        };
+        let header_size = Header::size(*version);
+        if src.len() < header_size {
+            src.reserve(header_size - src.len());
+            return Ok(None);
+        }
+        let length = frame_length(&src[..header_size], self.encoding)?;
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        let frame = src.split_to(length);
+        let (message, _) = Message::deserialize_with(&frame, None, self.encoding)
+            .map_err(|e| e.resolve(frame.len(), 0))?;
+        Ok(Some(message))
+    }
+}
+
+impl Encoder<&Message> for MessageCodec {
+    type Error = WriteError;
+
+    fn encode(&mut self, message: &Message, dst: &mut BytesMut) -> Result<(), WriteError> {
+        dst.extend_from_slice(&message.serialize_with(self.encoding)?);
+        Ok(())
+    }
+}
+
+impl Encoder<Message> for MessageCodec {
+    type Error = WriteError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), WriteError> {
+        self.encode(&message, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{DeserializeErrorKind, FieldValue, MessageBuilder, MessageType};
+
+    #[test]
+    fn codec_round_trip() {
+        let messages = [
+            Message::new([("price", FieldValue::Integer(5))]).unwrap(),
+            MessageBuilder::new()
+                .with_type(MessageType(3))
+                .field("side", FieldValue::Bool(false))
+                .build()
+                .unwrap(),
+        ];
+        let mut codec = MessageCodec::new();
+        let mut encoded = BytesMut::new();
+        for message in &messages {
+            codec.encode(message, &mut encoded).unwrap();
+        }
+
+        // Bytes arrive one at a time
+        let mut src = BytesMut::new();
+        let mut decoded = vec![];
+        for byte in encoded {
+            src.extend_from_slice(&[byte]);
+            decoded.extend(codec.decode(&mut src).unwrap());
+        }
+        assert_eq!(decoded, messages);
+        assert!(src.is_empty());
+
+        let mut src = BytesMut::from(&[0x09; 18][..]);
+        let Err(ReadError::Deserialize(error)) = codec.decode(&mut src) else {
+            panic!("expected an unknown version to fail");
+        };
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::VersionMismatch { found: 0x09 }
+        );
+    }
+}
@@ -0,0 +1,626 @@
+//! Rust code generated from schemas
+//!
+//! [`generate`] turns every enum of a schema into a Rust enum and every object
+//! and message into a struct with typed getters, converted with
+//! [`GalacticSerialize`](super::GalacticSerialize) and
+//! [`GalacticDeserialize`](super::GalacticDeserialize). Messages with a message
+//! type also implement [`Serializable`](super::Serializable) and
+//! [`Deserializable`](super::Deserializable). The output is a module of this
+//! crate, written by a build script or checked in and kept current by a test
+//! like the one below.
+
+use std::{hash::Hash, time::Duration};
+
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+
+use super::{
+    Array, EnumType, FieldAccess, FieldError, FieldValue, List, Map, Object, StringValue,
+    schema::{FieldSchema, FieldType, ObjectSchema, Schema},
+};
+use crate::{date::Date, decimal::Decimal};
+
+/// Conversion of the Rust type of a field, used by generated code
+pub(crate) trait SchemaValue: Sized {
+    fn to_value(&self) -> FieldValue;
+
+    /// `name` is the field the value belongs to, for errors
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError>;
+}
+
+/// Required field, converted to its Rust type
+pub(crate) fn field<T: SchemaValue>(object: &Object, name: &str) -> Result<T, FieldError> {
+    T::from_value(name, object.field(name)?)
+}
+
+/// Optional field, `None` when it is missing or null
+pub(crate) fn optional_field<T: SchemaValue>(
+    object: &Object,
+    name: &str,
+) -> Result<Option<T>, FieldError> {
+    match object.get(name) {
+        None | Some(FieldValue::Null) => Ok(None),
+        Some(value) => T::from_value(name, value).map(Some),
+    }
+}
+
+macro_rules! schema_values {
+    ($($type:ty => $variant:ident as $name:literal),* $(,)?) => {$(
+        impl SchemaValue for $type {
+            fn to_value(&self) -> FieldValue {
+                FieldValue::$variant(*self)
+            }
+
+            fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+                match value {
+                    FieldValue::$variant(value) => Ok(*value),
+                    value => Err(FieldError::wrong_type(name, $name, value)),
+                }
+            }
+        }
+    )*};
+}
+
+schema_values! {
+    i64 => Integer as "integer",
+    f64 => Float as "float",
+    bool => Bool as "bool",
+    [u8; 16] => Uuid as "uuid",
+    Decimal => Decimal as "decimal",
+    Date => Date as "date",
+    Duration => Duration as "duration",
+    i128 => Int128 as "int128",
+}
+
+impl SchemaValue for String {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::String(StringValue(self.clone()))
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::String(StringValue(string)) => Ok(string.clone()),
+            value => Err(FieldError::wrong_type(name, "string", value)),
+        }
+    }
+}
+
+impl SchemaValue for BigInt {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::BigInt(self.clone())
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::BigInt(integer) => Ok(integer.clone()),
+            value => Err(FieldError::wrong_type(name, "bigint", value)),
+        }
+    }
+}
+
+/// `any`, taken as it is
+impl SchemaValue for FieldValue {
+    fn to_value(&self) -> FieldValue {
+        self.clone()
+    }
+
+    fn from_value(_: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        Ok(value.clone())
+    }
+}
+
+/// Written as the most specific list, read from any list
+impl<T: SchemaValue> SchemaValue for Vec<T> {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::List(List::from_values(self.iter().map(T::to_value).collect()))
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::List(list) => list
+                .clone()
+                .into_values()
+                .iter()
+                .map(|element| T::from_value(name, element))
+                .collect(),
+            value => Err(FieldError::wrong_type(name, "list", value)),
+        }
+    }
+}
+
+impl<T: SchemaValue, const N: usize> SchemaValue for [T; N] {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::Array(Array(self.iter().map(T::to_value).collect()))
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        let FieldValue::Array(Array(elements)) = value else {
+            return Err(FieldError::wrong_type(name, "array", value));
+        };
+        let elements = elements
+            .iter()
+            .map(|element| T::from_value(name, element))
+            .collect::<Result<Vec<T>, _>>()?;
+        elements
+            .try_into()
+            .map_err(|elements: Vec<T>| FieldError::WrongLength {
+                name: String::from(name),
+                expected: N,
+                found: elements.len(),
+            })
+    }
+}
+
+impl<K: SchemaValue + Eq + Hash, V: SchemaValue> SchemaValue for IndexMap<K, V> {
+    fn to_value(&self) -> FieldValue {
+        let entries = self
+            .iter()
+            .map(|(key, value)| (key.to_value(), value.to_value()))
+            .collect();
+        FieldValue::Map(Map(entries))
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Map(Map(entries)) => entries
+                .iter()
+                .map(|(key, value)| Ok((K::from_value(name, key)?, V::from_value(name, value)?)))
+                .collect(),
+            value => Err(FieldError::wrong_type(name, "map", value)),
+        }
+    }
+}
+
+const HEADER: &str = "\
+// Generated from a galacticbuf schema, do not edit
+
+#[allow(unused_imports)]
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use indexmap::IndexMap;
+#[allow(unused_imports)]
+use num_bigint::BigInt;
+
+#[allow(unused_imports)]
+use crate::{
+    date::Date,
+    decimal::Decimal,
+    galacticbuf::{
+        Deserializable, DeserializeError, Encoding, EnumValue, FieldError, FieldName, FieldValue,
+        Fields, GalacticDeserialize, GalacticSerialize, Message, MessageBuilder, MessageType,
+        Object, Serializable, SerializeError,
+        codegen::{SchemaValue, field, optional_field},
+    },
+};
+";
+
+const KEYWORDS: [&str; 35] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "yield",
+];
+
+/// Rust source of the types of the schema
+pub(crate) fn generate(schema: &Schema) -> String {
+    let mut out = String::from(HEADER);
+    for enum_type in &schema.enums {
+        generate_enum(&mut out, enum_type);
+    }
+    for object in &schema.objects {
+        generate_object(&mut out, object);
+    }
+    out
+}
+
+/// `order_id` for `orderId`, raw when it is a keyword
+fn field_ident(name: &str) -> String {
+    let mut ident = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_ascii_uppercase() && i > 0 && !ident.ends_with('_') {
+            ident.push('_');
+        }
+        ident.push(c.to_ascii_lowercase());
+    }
+    match ident.as_str() {
+        "self" | "super" | "crate" => format!("{}_", ident),
+        keyword if KEYWORDS.contains(&keyword) => format!("r#{}", ident),
+        _ => ident,
+    }
+}
+
+/// `PartialFill` for `partial_fill`
+fn type_ident(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().expect("parts are not empty");
+            format!("{}{}", first.to_ascii_uppercase(), chars.as_str())
+        })
+        .collect()
+}
+
+fn rust_type(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Integer => String::from("i64"),
+        FieldType::String => String::from("String"),
+        FieldType::Float => String::from("f64"),
+        FieldType::Bool => String::from("bool"),
+        FieldType::Uuid => String::from("[u8; 16]"),
+        FieldType::Decimal => String::from("Decimal"),
+        FieldType::Date => String::from("Date"),
+        FieldType::Duration => String::from("Duration"),
+        FieldType::Int128 => String::from("i128"),
+        FieldType::BigInt => String::from("BigInt"),
+        FieldType::Any => String::from("FieldValue"),
+        FieldType::List(element) => format!("Vec<{}>", rust_type(element)),
+        FieldType::Array(element, length) => format!("[{}; {}]", rust_type(element), length),
+        FieldType::Map(key, value) => {
+            format!("IndexMap<{}, {}>", rust_type(key), rust_type(value))
+        }
+        FieldType::Object(name) | FieldType::Enum(name) => type_ident(name),
+    }
+}
+
+fn field_rust_type(field: &FieldSchema) -> String {
+    match field.required {
+        true => rust_type(&field.field_type),
+        false => format!("Option<{}>", rust_type(&field.field_type)),
+    }
+}
+
+fn generate_enum(out: &mut String, enum_type: &EnumType) {
+    let name = type_ident(enum_type.name());
+    let variants: Vec<(u16, String)> = enum_type
+        .variants
+        .iter()
+        .map(|(discriminant, variant)| (*discriminant, type_ident(variant)))
+        .collect();
+
+    out.push_str("\n#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]\n");
+    out.push_str(&format!("pub(crate) enum {} {{\n", name));
+    for (_, variant) in &variants {
+        out.push_str(&format!("    {},\n", variant));
+    }
+    out.push_str("}\n");
+
+    out.push_str(&format!("\nimpl {} {{\n", name));
+    out.push_str("    pub(crate) fn discriminant(self) -> u16 {\n        match self {\n");
+    for (discriminant, variant) in &variants {
+        out.push_str(&format!(
+            "            {}::{} => {},\n",
+            name, variant, discriminant
+        ));
+    }
+    out.push_str("        }\n    }\n\n");
+    out.push_str("    pub(crate) fn from_discriminant(discriminant: u16) -> Option<Self> {\n");
+    out.push_str("        match discriminant {\n");
+    for (discriminant, variant) in &variants {
+        out.push_str(&format!(
+            "            {} => Some({}::{}),\n",
+            discriminant, name, variant
+        ));
+    }
+    out.push_str("            _ => None,\n        }\n    }\n}\n");
+
+    out.push_str(&format!(
+        "
+impl SchemaValue for {0} {{
+    fn to_value(&self) -> FieldValue {{
+        FieldValue::Enum(EnumValue {{
+            discriminant: self.discriminant(),
+            payload: None,
+        }})
+    }}
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {{
+        let variant = match value {{
+            FieldValue::Enum(EnumValue {{ discriminant, .. }}) => {{
+                {0}::from_discriminant(*discriminant)
+            }}
+            _ => None,
+        }};
+        variant.ok_or_else(|| FieldError::wrong_type(name, {1:?}, value))
+    }}
+}}
+",
+        name,
+        enum_type.name()
+    ));
+}
+
+fn generate_object(out: &mut String, object: &ObjectSchema) {
+    let name = type_ident(&object.name);
+
+    out.push_str("\n#[derive(Clone, Debug, PartialEq)]\n");
+    out.push_str(&format!("pub(crate) struct {} {{", name));
+    for field in &object.fields {
+        out.push_str(&format!(
+            "\n    pub(crate) {}: {},",
+            field_ident(&field.name),
+            field_rust_type(field)
+        ));
+    }
+    out.push_str(&end_fields(object, ""));
+    out.push('\n');
+
+    if !object.fields.is_empty() {
+        out.push_str(&format!("\nimpl {} {{\n", name));
+        for (i, field) in object.fields.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            generate_getter(out, field);
+        }
+        out.push_str("}\n");
+    }
+
+    out.push_str(&format!("\nimpl GalacticSerialize for {} {{\n", name));
+    out.push_str("    fn to_object(&self) -> Object {\n");
+    if object.fields.is_empty() {
+        out.push_str("        Object(Fields::new())\n    }\n}\n");
+    } else {
+        out.push_str("        let mut fields = Fields::new();\n");
+    }
+    for field in &object.fields {
+        let ident = field_ident(&field.name);
+        let insert = |value: &str| {
+            format!(
+                "fields.insert(FieldName::from({:?}), {}.to_value());",
+                field.name, value
+            )
+        };
+        if field.required {
+            let value = format!("self.{}", ident);
+            out.push_str(&format!("        {}\n", insert(&value)));
+        } else {
+            out.push_str(&format!(
+                "        if let Some({0}) = &self.{0} {{\n            {1}\n        }}\n",
+                ident,
+                insert(&ident)
+            ));
+        }
+    }
+    if !object.fields.is_empty() {
+        out.push_str("        Object(fields)\n    }\n}\n");
+    }
+
+    out.push_str(&format!("\nimpl GalacticDeserialize for {} {{\n", name));
+    let object_param = match object.fields.is_empty() {
+        true => "_",
+        false => "object",
+    };
+    out.push_str(&format!(
+        "    fn from_object({}: &Object) -> Result<Self, FieldError> {{\n",
+        object_param
+    ));
+    out.push_str(&format!("        Ok({} {{", name));
+    for field in &object.fields {
+        let read = match field.required {
+            true => "field",
+            false => "optional_field",
+        };
+        out.push_str(&format!(
+            "\n            {}: {}(object, {:?})?,",
+            field_ident(&field.name),
+            read,
+            field.name
+        ));
+    }
+    out.push_str(&end_fields(object, "        "));
+    out.push_str(")\n    }\n}\n");
+
+    out.push_str(&format!(
+        "
+impl SchemaValue for {0} {{
+    fn to_value(&self) -> FieldValue {{
+        FieldValue::Object(self.to_object())
+    }}
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {{
+        match value {{
+            FieldValue::Object(object) => {0}::from_object(object),
+            value => Err(FieldError::wrong_type(name, {1:?}, value)),
+        }}
+    }}
+}}
+",
+        name, object.name
+    ));
+
+    if let Some(message_type) = object.message_type {
+        generate_message(out, &name, message_type.0);
+    }
+}
+
+/// Closing brace of a struct definition or literal, on the line of the opening
+/// one when there are no fields
+fn end_fields(object: &ObjectSchema, indent: &str) -> String {
+    match object.fields.is_empty() {
+        true => String::from("}"),
+        false => format!("\n{}}}", indent),
+    }
+}
+
+fn generate_getter(out: &mut String, field: &FieldSchema) {
+    let ident = field_ident(&field.name);
+    let copy = matches!(
+        field.field_type,
+        FieldType::Integer
+            | FieldType::Float
+            | FieldType::Bool
+            | FieldType::Uuid
+            | FieldType::Decimal
+            | FieldType::Date
+            | FieldType::Duration
+            | FieldType::Int128
+            | FieldType::Array(..)
+            | FieldType::Enum(_)
+    );
+    let borrowed = match &field.field_type {
+        FieldType::String => String::from("str"),
+        FieldType::List(element) => format!("[{}]", rust_type(element)),
+        field_type => rust_type(field_type),
+    };
+    let (return_type, body) = match (copy, field.required, &field.field_type) {
+        (true, _, _) => (field_rust_type(field), format!("self.{}", ident)),
+        (false, true, _) => (format!("&{}", borrowed), format!("&self.{}", ident)),
+        (false, false, FieldType::String | FieldType::List(_)) => (
+            format!("Option<&{}>", borrowed),
+            format!("self.{}.as_deref()", ident),
+        ),
+        (false, false, _) => (
+            format!("Option<&{}>", borrowed),
+            format!("self.{}.as_ref()", ident),
+        ),
+    };
+    out.push_str(&format!(
+        "    pub(crate) fn {}(&self) -> {} {{\n        {}\n    }}\n",
+        ident, return_type, body
+    ));
+}
+
+fn generate_message(out: &mut String, name: &str, message_type: u16) {
+    out.push_str(&format!(
+        "
+/// [Message of type {1}]
+impl Serializable for {0} {{
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {{
+        let Object(fields) = self.to_object();
+        let mut builder = MessageBuilder::new().with_type(MessageType({1}));
+        for (FieldName(name), value) in fields {{
+            builder = builder.field(&name, value);
+        }}
+        builder.build()?.serialize_into_with(bytes, encoding)
+    }}
+}}
+
+/// [Message of type {1}]
+impl Deserializable for {0} {{
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {{
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        if message.header.message_type != MessageType({1}) {{
+            return Err(DeserializeError::invalid(format!(
+                \"expected message type {1}, found {{}}\",
+                message.header.message_type.0
+            )));
+        }}
+        let value = {0}::from_object(&Object(message.body))?;
+        Ok((value, bytes))
+    }}
+}}
+",
+        name, message_type
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{
+        Deserializable, GalacticDeserialize, GalacticSerialize, Message, MessageType, Serializable,
+    };
+
+    mod generated {
+        include!("testdata/orders.rs");
+    }
+
+    const SCHEMA: &str = include_str!("testdata/orders.gbs");
+
+    /// Fails when the checked in code is stale, `GALACTICBUF_BLESS=1` rewrites it
+    #[test]
+    fn generated_code_is_current() {
+        let generated = generate(&Schema::parse(SCHEMA).unwrap());
+        if std::env::var_os("GALACTICBUF_BLESS").is_some() {
+            let path = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/galacticbuf/testdata/orders.rs"
+            );
+            std::fs::write(path, &generated).unwrap();
+        }
+        assert!(
+            generated == include_str!("testdata/orders.rs"),
+            "generated code is stale, rerun with GALACTICBUF_BLESS=1"
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(field_ident("orderId"), "order_id");
+        assert_eq!(field_ident("type"), "r#type");
+        assert_eq!(field_ident("self"), "self_");
+        assert_eq!(type_ident("partial_fill"), "PartialFill");
+        assert_eq!(type_ident("Order"), "Order");
+    }
+
+    #[test]
+    fn generated_round_trip() {
+        use generated::{Fill, Order, Side};
+
+        let order = Order {
+            id: [3; 16],
+            side: Side::Sell,
+            price: Some(Decimal::new(1250, -2)),
+            quantity: 4,
+            fills: vec![Fill {
+                quantity: 1,
+                maker: true,
+                r#type: Some(vec![Side::Buy]),
+            }],
+            tags: Some([(String::from("desk"), String::from("fx"))].into()),
+            matrix: vec![vec![1.5], vec![]],
+            client_order_id: None,
+            settles: Date::from_ymd(2024, 3, 5).unwrap(),
+            expires_in: Some(Duration::from_secs(30)),
+            volume: i128::from(i64::MAX) * 3,
+            notional: Some(BigInt::from(10).pow(30)),
+            brackets: Some([Decimal::new(1200, -2), Decimal::new(1300, -2)]),
+            extra: FieldValue::Null,
+        };
+        assert_eq!(order.side(), Side::Sell);
+        assert!(order.fills()[0].maker());
+        assert_eq!(order.client_order_id(), None);
+        assert_eq!(order.settles().weekday(), 2);
+        assert_eq!(order.brackets().unwrap()[1], Decimal::new(13, 0));
+
+        let bytes = order.serialize().unwrap();
+        let (message, _) = Message::deserialize(&bytes, None).unwrap();
+        assert_eq!(message.header.message_type, MessageType(1));
+        let schema = Schema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.validate(&message), Ok(()));
+        assert_eq!(Order::deserialize(&bytes, None).unwrap().0, order);
+
+        let mut object = order.to_object();
+        assert_eq!(Order::from_object(&object).as_ref(), Ok(&order));
+        object.0.insert(
+            crate::galacticbuf::FieldName::from("side"),
+            FieldValue::Integer(1),
+        );
+        assert_eq!(
+            Order::from_object(&object),
+            Err(FieldError::wrong_type(
+                "side",
+                "Side",
+                &FieldValue::Integer(1)
+            ))
+        );
+
+        let fill = Message::new([("quantity", FieldValue::Integer(1))]).unwrap();
+        assert_eq!(
+            Order::deserialize(&fill.serialize().unwrap(), None)
+                .unwrap_err()
+                .to_string(),
+            "expected message type 1, found 0 at byte 0"
+        );
+    }
+}
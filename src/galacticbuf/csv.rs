@@ -0,0 +1,277 @@
+//! Messages as CSV rows, so trade records can go straight into spreadsheets
+//! and databases
+//!
+//! The columns are the fields of the message's definition in a schema, in
+//! their order, fields the schema doesn't know are left out. Decimals, dates
+//! and UUIDs are written as in JSON, big integers by all of their digits,
+//! durations in nanoseconds, bools as `true` or `false` and enum variants
+//! without a payload by their name. Null and missing fields leave their cell
+//! empty. Lists, objects, maps and the like either fail or are
+//! written as JSON, see [`NestedValues`]. Cells are quoted where RFC 4180
+//! asks for it, rows end with `\n`.
+
+use std::fmt::{self, Display};
+
+use super::{
+    EnumValue, FieldValue, Message, MessageType, StringValue, format_uuid,
+    schema::{FieldType, ObjectSchema, Schema},
+};
+
+/// What a CSV row does with a value which isn't a scalar
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum NestedValues {
+    /// Fail the row
+    #[default]
+    Error,
+    /// Write the value as JSON, see [`FieldValue::to_json`]
+    Json,
+}
+
+impl Message {
+    /// Fields of the message as a CSV row, without the line ending, see the
+    /// module docs
+    pub(crate) fn to_csv_row(
+        &self,
+        schema: &Schema,
+        nested: NestedValues,
+    ) -> Result<String, CsvError> {
+        let definition = definition(schema, self.header.message_type)?;
+        let mut row = String::new();
+        for (i, field) in definition.fields.iter().enumerate() {
+            if i > 0 {
+                row.push(',');
+            }
+            let Some(value) = self.get(&field.name) else {
+                continue;
+            };
+            let cell =
+                cell(value, &field.field_type, schema, nested).ok_or_else(|| CsvError::Nested {
+                    field: field.name.clone(),
+                })?;
+            push_quoted(&mut row, &cell);
+        }
+        Ok(row)
+    }
+}
+
+/// Messages of one type as CSV, a row with the names of the fields first
+pub(crate) fn records_to_csv<'a>(
+    schema: &Schema,
+    messages: impl IntoIterator<Item = &'a Message>,
+    nested: NestedValues,
+) -> Result<String, CsvError> {
+    let mut csv = String::new();
+    let mut message_type = None;
+    for message in messages {
+        let found = message.header.message_type;
+        match message_type {
+            None => {
+                let definition = definition(schema, found)?;
+                for (i, field) in definition.fields.iter().enumerate() {
+                    if i > 0 {
+                        csv.push(',');
+                    }
+                    push_quoted(&mut csv, &field.name);
+                }
+                csv.push('\n');
+                message_type = Some(found);
+            }
+            Some(expected) if expected != found => {
+                return Err(CsvError::MixedMessages { expected, found });
+            }
+            Some(_) => {}
+        }
+        csv.push_str(&message.to_csv_row(schema, nested)?);
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+fn definition(schema: &Schema, message_type: MessageType) -> Result<&ObjectSchema, CsvError> {
+    schema
+        .message(message_type)
+        .ok_or(CsvError::UnknownMessage(message_type))
+}
+
+/// Text of a value, `None` for a value which isn't a scalar when nested values
+/// are an error
+fn cell(
+    value: &FieldValue,
+    field_type: &FieldType,
+    schema: &Schema,
+    nested: NestedValues,
+) -> Option<String> {
+    let cell = match value {
+        FieldValue::Integer(integer) => integer.to_string(),
+        FieldValue::Float(float) => float.to_string(),
+        FieldValue::String(StringValue(string)) => string.clone(),
+        FieldValue::Bool(boolean) => boolean.to_string(),
+        FieldValue::Null => String::new(),
+        FieldValue::Uuid(uuid) => format_uuid(uuid),
+        FieldValue::Decimal(decimal) => decimal.to_string(),
+        FieldValue::Date(date) => date.to_string(),
+        FieldValue::Duration(duration) => duration.as_nanos().to_string(),
+        FieldValue::Int128(integer) => integer.to_string(),
+        FieldValue::BigInt(integer) => integer.to_string(),
+        FieldValue::Enum(EnumValue {
+            discriminant,
+            payload: None,
+        }) => {
+            let name = match field_type {
+                FieldType::Enum(name) => schema
+                    .enum_type(name)
+                    .and_then(|enum_type| enum_type.variant_name(*discriminant)),
+                _ => None,
+            };
+            name.map_or_else(|| discriminant.to_string(), String::from)
+        }
+        value => match nested {
+            NestedValues::Error => return None,
+            NestedValues::Json => value.to_json().to_string(),
+        },
+    };
+    Some(cell)
+}
+
+/// Appends the cell, in quotes when it holds a separator, a quote or a line
+/// break, or starts or ends with a space
+fn push_quoted(row: &mut String, cell: &str) {
+    let quoted =
+        cell.contains([',', '"', '\n', '\r']) || cell.starts_with(' ') || cell.ends_with(' ');
+    if !quoted {
+        row.push_str(cell);
+        return;
+    }
+    row.push('"');
+    row.push_str(&cell.replace('"', "\"\""));
+    row.push('"');
+}
+
+/// Message which can't be written as a CSV row
+#[derive(Debug, PartialEq)]
+pub(crate) enum CsvError {
+    /// Message of a type the schema doesn't define
+    UnknownMessage(MessageType),
+    /// Messages of another type than the first, whose fields are the columns
+    MixedMessages {
+        expected: MessageType,
+        found: MessageType,
+    },
+    /// Value which isn't a scalar, see [`NestedValues`]
+    Nested { field: String },
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::UnknownMessage(MessageType(message_type)) => {
+                write!(f, "message type {} is not in the schema", message_type)
+            }
+            CsvError::MixedMessages {
+                expected: MessageType(expected),
+                found: MessageType(found),
+            } => write!(
+                f,
+                "message of type {} among messages of type {}",
+                found, expected
+            ),
+            CsvError::Nested { field } => write!(f, "field `{}` is not a scalar", field),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decimal::Decimal,
+        galacticbuf::{List, MessageBuilder},
+    };
+
+    const SCHEMA: &str = "
+        enum Side {
+            buy = 0
+            sell = 1
+        }
+
+        message Trade = 3 {
+            id: integer
+            price: decimal
+            side: Side
+            venue: string?
+            fills: list<integer>?
+        }
+    ";
+
+    fn trade(id: i64, venue: &str) -> Message {
+        MessageBuilder::new()
+            .with_type(MessageType(3))
+            .field("id", FieldValue::Integer(id))
+            .field("price", FieldValue::Decimal(Decimal::new(10125, -2)))
+            .field(
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 1,
+                    payload: None,
+                }),
+            )
+            .field("venue", venue.into())
+            .field("note", "left out".into())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn rows() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let trades = [trade(1, "XGAL"), trade(2, "Galactic, \"Main\"")];
+        assert_eq!(
+            records_to_csv(&schema, &trades, NestedValues::Error).unwrap(),
+            "id,price,side,venue,fills\n\
+             1,101.25,sell,XGAL,\n\
+             2,101.25,sell,\"Galactic, \"\"Main\"\"\",\n"
+        );
+        assert_eq!(
+            records_to_csv(&schema, &[], NestedValues::Error).unwrap(),
+            ""
+        );
+
+        let mut filled = trade(3, "XGAL");
+        filled
+            .body
+            .insert("fills".into(), FieldValue::List(List::Integers(vec![5, 7])));
+        assert_eq!(
+            filled.to_csv_row(&schema, NestedValues::Json).unwrap(),
+            "3,101.25,sell,XGAL,\"[5,7]\""
+        );
+        assert_eq!(
+            filled
+                .to_csv_row(&schema, NestedValues::Error)
+                .unwrap_err()
+                .to_string(),
+            "field `fills` is not a scalar"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let heartbeat = MessageBuilder::new()
+            .with_type(MessageType(9))
+            .build()
+            .unwrap();
+        assert_eq!(
+            heartbeat.to_csv_row(&schema, NestedValues::Error),
+            Err(CsvError::UnknownMessage(MessageType(9)))
+        );
+        let messages = [trade(1, "XGAL"), heartbeat];
+        assert_eq!(
+            records_to_csv(&schema, &messages, NestedValues::Error)
+                .unwrap_err()
+                .to_string(),
+            "message of type 9 among messages of type 3"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! zstd dictionaries, which compress small messages far better than zstd alone
+//!
+//! A message of a few hundred bytes is too short for zstd to learn anything
+//! from, so the field names and values it repeats across messages are learnt
+//! ahead of time from samples and shared by both ends of a connection. A body
+//! compressed with a dictionary sets
+//! [`DICTIONARY_FLAG`](super::DICTIONARY_FLAG) and the header carries the ID
+//! of the dictionary, so a reader can tell whether it holds the one the body
+//! needs.
+//!
+//! [Magic (4 bytes, 0xEC30A437 little-endian)][Dictionary ID (4 bytes, little-endian)][Entropy Tables][Content]
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::{Deserializable, Header, Message, Serializable, VERSION2, trailer_size};
+
+/// First bytes of a zstd dictionary
+const MAGIC: u32 = 0xEC30A437;
+
+/// zstd dictionary and its ID, see the module docs
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Dictionary {
+    id: u32,
+    bytes: Vec<u8>,
+}
+
+impl Dictionary {
+    /// Trains a dictionary of at most `max_size` bytes on the bodies of the
+    /// samples, fails when zstd can't, e.g. because there are too few samples
+    ///
+    /// The samples should be like the messages the dictionary compresses,
+    /// a few hundred to a few thousand of them for a dictionary of 16 KB.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn train(samples: &[Message], max_size: usize) -> std::io::Result<Dictionary> {
+        let bodies = samples
+            .iter()
+            .map(|sample| {
+                // Compressed bodies are never written as version 1
+                let message = Message {
+                    header: Header {
+                        version: sample.header.version.max(VERSION2),
+                        ..sample.header
+                    },
+                    body: sample.body.clone(),
+                };
+                let bytes = message.serialize().map_err(std::io::Error::other)?;
+                let (header, _) =
+                    Header::deserialize(&bytes, None).expect("serialized header is deserializable");
+                let start = Header::size_with_flags(header.version, header.flags);
+                Ok(bytes[start..bytes.len() - trailer_size(header.flags)].to_vec())
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let bytes = zstd::dict::from_samples(&bodies, max_size)?;
+        Dictionary::from_bytes(bytes)
+            .ok_or_else(|| std::io::Error::other("zstd trained no dictionary"))
+    }
+
+    /// Dictionary as [`Dictionary::as_bytes`] wrote it, `None` unless the
+    /// bytes start like a zstd dictionary
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> Option<Dictionary> {
+        let magic = u32::from_le_bytes(*bytes.first_chunk()?);
+        let id = u32::from_le_bytes(bytes.get(4..8)?.try_into().unwrap());
+        (magic == MAGIC && id != 0).then_some(Dictionary { id, bytes })
+    }
+
+    /// ID of the dictionary in the headers of the messages it compresses,
+    /// picked by zstd at random when training
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Bytes of the dictionary, to store or send it to the other end
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{
+        COMPRESSED_FLAG, DICTIONARY_FLAG, DeserializeErrorKind, Encoding, FieldValue,
+        MessageBuilder, MessageType, explain,
+    };
+
+    fn order(id: i64) -> Message {
+        let venues = ["XGAL", "XORB", "XNEB"];
+        MessageBuilder::new()
+            .with_type(MessageType(1))
+            .with_sequence(id as u64)
+            .field("order_id", FieldValue::Integer(10_000 + id))
+            .field("instrument", "XGAL-USD".into())
+            .field("side", if id % 2 == 0 { "buy" } else { "sell" }.into())
+            .field("quantity", FieldValue::Integer(100 * (id % 7 + 1)))
+            .field("venue", venues[id as usize % venues.len()].into())
+            .field("account", format!("desk-{}", id % 13).as_str().into())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn compresses_small_messages() {
+        let samples: Vec<_> = (0..1_000).map(order).collect();
+        let dictionary = Dictionary::train(&samples, 4096).unwrap();
+        assert!(dictionary.as_bytes().len() <= 4096);
+        assert_eq!(
+            Dictionary::from_bytes(dictionary.as_bytes().to_vec()),
+            Some(dictionary.clone())
+        );
+        assert_eq!(Dictionary::from_bytes(b"not a dictionary".to_vec()), None);
+
+        let message = order(1_234);
+        let bytes = message.serialize_compressed(&dictionary).unwrap();
+        let without = message
+            .serialize_with(Encoding {
+                compression_threshold: Some(0),
+                ..Encoding::default()
+            })
+            .unwrap();
+        assert!(
+            bytes.len() < without.len() && bytes.len() < message.serialize().unwrap().len(),
+            "{} bytes, {} without the dictionary",
+            bytes.len(),
+            without.len()
+        );
+        let (read, rest) = Message::deserialize_with_dictionary(&bytes, &dictionary).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(read.body, message.body);
+        assert_eq!(read.header.flags, COMPRESSED_FLAG | DICTIONARY_FLAG);
+        assert_eq!(read.header.dictionary, Some(dictionary.id()));
+        assert!(explain(&bytes).contains(&format!("dictionary={:#010x}", dictionary.id())));
+
+        // A message compressed without a dictionary reads with one
+        let (read, _) = Message::deserialize_with_dictionary(&without, &dictionary).unwrap();
+        assert_eq!(read.body, message.body);
+    }
+
+    #[test]
+    fn unknown_dictionary() {
+        let samples: Vec<_> = (0..1_000).map(order).collect();
+        let dictionary = Dictionary::train(&samples, 4096).unwrap();
+        let bytes = order(7).serialize_compressed(&dictionary).unwrap();
+        let unknown = DeserializeErrorKind::UnknownDictionary {
+            id: dictionary.id(),
+        };
+
+        let error = Message::deserialize(&bytes, None).unwrap_err();
+        assert_eq!(error.kind, unknown);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "unknown compression dictionary {:#010x} at byte 0",
+                dictionary.id()
+            )
+        );
+
+        let mut other = dictionary.as_bytes().to_vec();
+        other[4..8].copy_from_slice(&(dictionary.id() ^ 1).to_le_bytes());
+        let other = Dictionary::from_bytes(other).unwrap();
+        let error = Message::deserialize_with_dictionary(&bytes, &other).unwrap_err();
+        assert_eq!(error.kind, unknown);
+    }
+}
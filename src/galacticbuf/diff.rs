@@ -0,0 +1,435 @@
+//! Differences between two messages and patches which turn one into the other,
+//! so a subscriber holding a snapshot can be sent what changed rather than the
+//! next snapshot
+//!
+//! Fields are compared by name, objects on both sides field by field and all
+//! other values as a whole, so an element changed in a list replaces the list.
+//! The header isn't compared, a patched message keeps its own.
+
+use std::fmt::{self, Display};
+
+use super::{
+    CHECKSUM_FLAG, Deserializable, DeserializeError, Encoding, FieldAccess, FieldName, FieldValue,
+    Fields, List, Message, MessageBuilder, Object, Serializable, SerializeError, StringValue,
+};
+
+/// Field which differs between two messages, with the path of names leading
+/// to it through the objects it is nested in
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FieldDelta {
+    Added {
+        path: Vec<FieldName>,
+        value: FieldValue,
+    },
+    Removed {
+        path: Vec<FieldName>,
+        value: FieldValue,
+    },
+    Changed {
+        path: Vec<FieldName>,
+        old: FieldValue,
+        new: FieldValue,
+    },
+}
+
+impl FieldDelta {
+    pub(crate) fn path(&self) -> &[FieldName] {
+        match self {
+            FieldDelta::Added { path, .. }
+            | FieldDelta::Removed { path, .. }
+            | FieldDelta::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl Message {
+    /// Fields added, removed or changed from this message to `other`, in the
+    /// order of this message's fields followed by the fields only `other` has
+    pub(crate) fn diff(&self, other: &Message) -> Vec<FieldDelta> {
+        let mut deltas = vec![];
+        diff_fields(&self.body, &other.body, &mut vec![], &mut deltas);
+        deltas
+    }
+}
+
+fn diff_fields(
+    old: &Fields,
+    new: &Fields,
+    path: &mut Vec<FieldName>,
+    deltas: &mut Vec<FieldDelta>,
+) {
+    for (name, old_value) in old {
+        path.push(name.clone());
+        match (old_value, new.get(name)) {
+            (_, None) => deltas.push(FieldDelta::Removed {
+                path: path.clone(),
+                value: old_value.clone(),
+            }),
+            (FieldValue::Object(Object(old)), Some(FieldValue::Object(Object(new)))) => {
+                diff_fields(old, new, path, deltas)
+            }
+            (old_value, Some(new_value)) if old_value != new_value => {
+                deltas.push(FieldDelta::Changed {
+                    path: path.clone(),
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                })
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+    for (name, new_value) in new {
+        if !old.contains_key(name) {
+            let mut path = path.clone();
+            path.push(name.clone());
+            deltas.push(FieldDelta::Added {
+                path,
+                value: new_value.clone(),
+            });
+        }
+    }
+}
+
+/// Change a [`Patch`] makes to a message
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PatchOp {
+    /// Adds a field which isn't there yet
+    Add {
+        path: Vec<FieldName>,
+        value: FieldValue,
+    },
+    /// Removes a field which is there
+    Remove { path: Vec<FieldName> },
+    /// Replaces the value of a field which is there
+    Replace {
+        path: Vec<FieldName>,
+        value: FieldValue,
+    },
+}
+
+/// Changes to apply to a message, in order
+///
+/// Operations expect the message they are applied to to be the one the patch
+/// was made from, adding a field which is there or removing or replacing one
+/// which isn't fails, so a subscriber who missed a patch finds out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Patch(pub(crate) Vec<PatchOp>);
+
+impl Patch {
+    /// Patch turning `old` into `new`
+    pub(crate) fn between(old: &Message, new: &Message) -> Patch {
+        Patch::from(old.diff(new))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Applies the operations to the message, which is left as it was when
+    /// one of them fails
+    pub(crate) fn apply(&self, message: &mut Message) -> Result<(), PatchError> {
+        let mut body = message.body.clone();
+        for op in &self.0 {
+            apply(&mut body, op)?;
+        }
+        let header = message.header;
+        let mut builder = body.into_iter().fold(
+            MessageBuilder::new()
+                .with_version(header.version)
+                .with_type(header.message_type)
+                .with_sequence(header.sequence),
+            |builder, (name, value)| builder.field(name.as_str(), value),
+        );
+        if header.flags & CHECKSUM_FLAG != 0 {
+            builder = builder.with_checksum();
+        }
+        if let Some(fingerprint) = header.fingerprint {
+            builder = builder.with_fingerprint(fingerprint);
+        }
+        *message = builder.build()?;
+        Ok(())
+    }
+}
+
+impl From<Vec<FieldDelta>> for Patch {
+    fn from(deltas: Vec<FieldDelta>) -> Self {
+        Patch(
+            deltas
+                .into_iter()
+                .map(|delta| match delta {
+                    FieldDelta::Added { path, value } => PatchOp::Add { path, value },
+                    FieldDelta::Removed { path, .. } => PatchOp::Remove { path },
+                    FieldDelta::Changed { path, new, .. } => PatchOp::Replace { path, value: new },
+                })
+                .collect(),
+        )
+    }
+}
+
+fn apply(body: &mut Fields, op: &PatchOp) -> Result<(), PatchError> {
+    let (PatchOp::Add { path, .. } | PatchOp::Remove { path } | PatchOp::Replace { path, .. }) = op;
+    let Some((name, parents)) = path.split_last() else {
+        return Err(PatchError::path(path, "empty path"));
+    };
+    let mut fields = body;
+    for (i, parent) in parents.iter().enumerate() {
+        fields = match fields.get_mut(parent) {
+            Some(FieldValue::Object(Object(fields))) => fields,
+            Some(value) => {
+                let reason = format!("expected object, found {}", value.type_name());
+                return Err(PatchError::path(&path[..=i], reason));
+            }
+            None => return Err(PatchError::path(&path[..=i], "missing field")),
+        };
+    }
+    match op {
+        PatchOp::Add { value, .. } => {
+            if fields.contains_key(name) {
+                return Err(PatchError::path(path, "field is already there"));
+            }
+            fields.insert(name.clone(), value.clone());
+        }
+        PatchOp::Remove { .. } => {
+            fields
+                .shift_remove(name)
+                .ok_or_else(|| PatchError::path(path, "missing field"))?;
+        }
+        PatchOp::Replace { value, .. } => {
+            let field = fields
+                .get_mut(name)
+                .ok_or_else(|| PatchError::path(path, "missing field"))?;
+            *field = value.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Patch which doesn't fit the message it is applied to
+#[derive(Debug, PartialEq)]
+pub(crate) enum PatchError {
+    /// Operation on a field which isn't as the patch expects, e.g. `book.bids`
+    Path { path: String, reason: String },
+    /// Patched fields which don't fit into a message
+    Serialize(SerializeError),
+}
+
+impl PatchError {
+    fn path(path: &[FieldName], reason: impl Into<String>) -> Self {
+        PatchError::Path {
+            path: join(path),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Path { path, reason } => write!(f, "{}: {}", path, reason),
+            PatchError::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<SerializeError> for PatchError {
+    fn from(error: SerializeError) -> Self {
+        PatchError::Serialize(error)
+    }
+}
+
+/// Path of names as written in errors, e.g. `book.bids`
+fn join(path: &[FieldName]) -> String {
+    path.iter()
+        .map(FieldName::as_str)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl TryFrom<&Patch> for Message {
+    type Error = SerializeError;
+
+    fn try_from(patch: &Patch) -> Result<Self, Self::Error> {
+        let ops = patch
+            .0
+            .iter()
+            .map(|op| {
+                let (name, path, value) = match op {
+                    PatchOp::Add { path, value } => ("add", path, Some(value)),
+                    PatchOp::Remove { path } => ("remove", path, None),
+                    PatchOp::Replace { path, value } => ("replace", path, Some(value)),
+                };
+                let path = path
+                    .iter()
+                    .map(|name| StringValue(String::from(name.as_str())))
+                    .collect();
+                let mut fields = Fields::new();
+                fields.insert(FieldName::from("op"), FieldValue::from(name));
+                fields.insert(
+                    FieldName::from("path"),
+                    FieldValue::List(List::Strings(path)),
+                );
+                if let Some(value) = value {
+                    fields.insert(FieldName::from("value"), value.clone());
+                }
+                Object(fields)
+            })
+            .collect();
+        MessageBuilder::new()
+            .field("ops", FieldValue::List(List::Objects(ops)))
+            .build()
+    }
+}
+
+impl TryFrom<&Message> for Patch {
+    type Error = DeserializeError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let ops = match message.get_list("ops")? {
+            List::Objects(ops) => ops.as_slice(),
+            list if list.is_empty() => &[],
+            _ => {
+                let error = DeserializeError::invalid("expected a list of objects");
+                return Err(error.within("ops"));
+            }
+        };
+        let ops = ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| patch_op(op).map_err(|e| e.within(format!("[{}]", i)).within("ops")))
+            .collect::<Result<_, _>>()?;
+        Ok(Patch(ops))
+    }
+}
+
+fn patch_op(op: &Object) -> Result<PatchOp, DeserializeError> {
+    let path: Vec<FieldName> = Vec::<String>::try_from(op.field("path")?.clone())
+        .map_err(|_| DeserializeError::invalid("expected a list of strings").within("path"))?
+        .into_iter()
+        .map(FieldName::from)
+        .collect();
+    if path.is_empty() {
+        return Err(DeserializeError::invalid("empty path").within("path"));
+    }
+    let value = || op.field("value").cloned();
+    let op = match op.get_str("op")? {
+        "add" => PatchOp::Add {
+            path,
+            value: value()?,
+        },
+        "remove" => PatchOp::Remove { path },
+        "replace" => PatchOp::Replace {
+            path,
+            value: value()?,
+        },
+        name => {
+            let error = DeserializeError::invalid(format!("unknown operation `{}`", name));
+            return Err(error.within("op"));
+        }
+    };
+    Ok(op)
+}
+
+/// [Message with an `ops` list of `op`, `path` and `value` objects]
+impl Serializable for Patch {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        Message::try_from(self)?.serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message with an `ops` list of `op`, `path` and `value` objects]
+impl Deserializable for Patch {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        Ok((Patch::try_from(&message)?, bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(bids: Vec<i64>, asks: Option<Vec<i64>>, venue: &str) -> Message {
+        let mut sides = Fields::new();
+        sides.insert(FieldName::from("bids"), FieldValue::from(bids));
+        if let Some(asks) = asks {
+            sides.insert(FieldName::from("asks"), FieldValue::from(asks));
+        }
+        Message::new([
+            ("symbol", FieldValue::from("XAU")),
+            ("book", FieldValue::Object(Object(sides))),
+            ("venue", FieldValue::from(venue)),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn diffs_and_patches() {
+        let old = book(vec![100, 99], Some(vec![101]), "main");
+        let mut new = book(vec![100, 98], None, "main");
+        new.body.shift_remove(&FieldName::from("venue"));
+        new.body
+            .insert(FieldName::from("halted"), FieldValue::Bool(true));
+
+        let deltas = old.diff(&new);
+        let paths: Vec<_> = deltas.iter().map(|delta| join(delta.path())).collect();
+        assert_eq!(paths, ["book.bids", "book.asks", "venue", "halted"]);
+        assert!(matches!(
+            &deltas[0],
+            FieldDelta::Changed { old, .. } if *old == FieldValue::from(vec![100, 99])
+        ));
+        assert!(matches!(deltas[1], FieldDelta::Removed { .. }));
+        assert!(matches!(deltas[3], FieldDelta::Added { .. }));
+        assert!(old.diff(&old).is_empty());
+
+        let patch = Patch::between(&old, &new);
+        let bytes = patch.serialize().unwrap();
+        let (read, _) = Patch::deserialize(&bytes, None).unwrap();
+        assert_eq!(read, patch);
+
+        let mut patched = book(vec![100, 99], Some(vec![101]), "main");
+        read.apply(&mut patched).unwrap();
+        assert_eq!(patched.body, new.body);
+        assert_eq!(patched.header.field_count, new.header.field_count);
+        assert!(Patch::between(&patched, &new).is_empty());
+    }
+
+    #[test]
+    fn patches_need_their_message() {
+        let old = book(vec![100], None, "main");
+        let new = book(vec![101], None, "main");
+        let patch = Patch::between(&old, &new);
+
+        // A patch made from another snapshot fails and leaves the message be
+        let mut other = book(vec![100], None, "main");
+        other.body.shift_remove(&FieldName::from("book"));
+        let before = other.body.clone();
+        let error = patch.apply(&mut other).unwrap_err();
+        assert_eq!(error.to_string(), "book: missing field");
+        assert_eq!(other.body, before);
+
+        let add = Patch(vec![PatchOp::Add {
+            path: vec![FieldName::from("symbol")],
+            value: FieldValue::Integer(1),
+        }]);
+        let mut message = book(vec![100], None, "main");
+        let error = add.apply(&mut message).unwrap_err();
+        assert_eq!(error.to_string(), "symbol: field is already there");
+        let error = Patch(vec![PatchOp::Remove {
+            path: vec![FieldName::from("symbol"), FieldName::from("x")],
+        }])
+        .apply(&mut message)
+        .unwrap_err();
+        assert_eq!(error.to_string(), "symbol: expected object, found string");
+    }
+}
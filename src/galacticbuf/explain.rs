@@ -0,0 +1,621 @@
+//! Hexdump of a buffer with what each span of it holds, for tracking down
+//! where another implementation's bytes part ways with ours
+//!
+//! ```text
+//! 0000  01                                               byte 0: version=0x01
+//! 0001  01                                               byte 1: field count=1
+//! 0002  00 15                                            bytes 2–3: length=21
+//! 0004  07 75 73 65 72 5f 69 64                          bytes 4–11: field name 'user_id'
+//! 000c  01                                               byte 12: type=0x01 (integer)
+//! 000d  00 00 00 00 00 00 00 07                          bytes 13–20: integer = 7
+//! ```
+//!
+//! Reading stops at the first error, which is explained below the dump along
+//! with the bytes which couldn't be read. Compressed and encrypted bodies are
+//! shown as a whole.
+
+use std::fmt::{Display, Write as _};
+
+use super::{
+    CHECKSUM_FLAG, CHECKSUM_SIZE, COMPRESSED_FLAG, DICTIONARY_FLAG, DICTIONARY_ID_SIZE,
+    Deserializable, DeserializeError, DeserializeErrorKind, ENCRYPTED_FLAG, Encoding,
+    FIELD_IDS_FLAG, FINGERPRINT_FLAG, FINGERPRINT_SIZE, FieldName, FieldValue, Header,
+    NAME_TABLE_FLAG, NONCE_SIZE, OBJECT_T, SIGNATURE_SIZE, SIGNED_FLAG, VERSION1, VERSION3,
+    VERSIONS, format_fingerprint, text, trailer_size,
+};
+
+/// Bytes shown on each row of the dump
+const ROW: usize = 16;
+
+/// Objects nested deeper than this are shown as a whole rather than field by
+/// field
+const MAX_DEPTH: usize = 16;
+
+/// Annotated hexdump of the message at the start of `bytes`, see the module docs
+pub(crate) fn explain(bytes: &[u8]) -> String {
+    let mut explainer = Explainer {
+        bytes,
+        spans: vec![],
+        errors: vec![],
+    };
+    let end = explainer.message();
+    let read = explainer.spans.last().map_or(0, |span| span.end);
+    if read < bytes.len() {
+        let what = match explainer.errors.is_empty() && read == end {
+            true => "following the message",
+            false => "not read",
+        };
+        explainer.span(read, bytes.len(), what);
+    }
+    explainer.render()
+}
+
+/// Bytes `start..end` of the buffer and what they hold
+struct Span {
+    start: usize,
+    end: usize,
+    what: String,
+}
+
+struct Explainer<'a> {
+    bytes: &'a [u8],
+    spans: Vec<Span>,
+    errors: Vec<String>,
+}
+
+impl Explainer<'_> {
+    fn span(&mut self, start: usize, end: usize, what: impl Into<String>) {
+        self.spans.push(Span {
+            start,
+            end,
+            what: what.into(),
+        });
+    }
+
+    /// Records the error of reading the bytes from `start` to `end`
+    fn error(&mut self, error: DeserializeError, start: usize, end: usize) {
+        self.errors
+            .push(error.resolve(end - start, start).to_string());
+    }
+
+    /// Explains the header, body and trailer, returns the end of the message
+    fn message(&mut self) -> usize {
+        let bytes = self.bytes;
+        let Some(&version) = bytes.first() else {
+            self.errors.push(String::from("empty buffer"));
+            return 0;
+        };
+        if !VERSIONS.contains(&version) {
+            self.span(0, 1, format!("version=0x{:02x}", version));
+            let kind = DeserializeErrorKind::VersionMismatch { found: version };
+            self.errors.push(DeserializeError::at(kind, 0).to_string());
+            return 0;
+        }
+
+        let mut size = Header::size(version);
+        let header_spans: &[(usize, &str)] = match version {
+            VERSION1 => &[(1, "version"), (1, "field count"), (2, "length")],
+            _ => &[
+                (1, "version"),
+                (1, "flags"),
+                (2, "type"),
+                (8, "sequence"),
+                (2, "field count"),
+                (4, "length"),
+            ],
+        };
+        let mut start = 0;
+        for &(width, name) in header_spans {
+            let Some(field) = bytes.get(start..start + width) else {
+                let error = DeserializeError::at(
+                    DeserializeErrorKind::UnexpectedEof {
+                        expected: format!("{} byte header", size),
+                    },
+                    start,
+                );
+                self.errors.push(error.to_string());
+                return start;
+            };
+            let value = be(field);
+            let what = match name {
+                "version" => format!("version=0x{:02x}", value),
+                "flags" => format!("flags=0x{:02x}{}", value, flag_names(value as u8)),
+                name => format!("{}={}", name, value),
+            };
+            self.span(start, start + width, what);
+            start += width;
+        }
+        if version != VERSION1 && bytes[1] & FINGERPRINT_FLAG != 0 {
+            let Some(fingerprint) = bytes.get(start..start + FINGERPRINT_SIZE) else {
+                let error = DeserializeError::at(
+                    DeserializeErrorKind::UnexpectedEof {
+                        expected: String::from("8 byte schema fingerprint"),
+                    },
+                    start,
+                );
+                self.errors.push(error.to_string());
+                return start;
+            };
+            let fingerprint = fingerprint.try_into().expect("fingerprints are 8 bytes");
+            let what = format!("fingerprint={}", format_fingerprint(fingerprint));
+            self.span(start, start + FINGERPRINT_SIZE, what);
+            start += FINGERPRINT_SIZE;
+            size += FINGERPRINT_SIZE;
+        }
+        if version != VERSION1 && bytes[1] & DICTIONARY_FLAG != 0 {
+            let Some(id) = bytes.get(start..start + DICTIONARY_ID_SIZE) else {
+                let error = DeserializeError::at(
+                    DeserializeErrorKind::UnexpectedEof {
+                        expected: String::from("4 byte dictionary ID"),
+                    },
+                    start,
+                );
+                self.errors.push(error.to_string());
+                return start;
+            };
+            let id = u32::from_be_bytes(id.try_into().expect("dictionary IDs are 4 bytes"));
+            let what = format!("dictionary={:#010x}", id);
+            self.span(start, start + DICTIONARY_ID_SIZE, what);
+            size += DICTIONARY_ID_SIZE;
+        }
+        let (header, _) = Header::deserialize(bytes, None).expect("header bytes were checked");
+
+        let length = header.length as usize;
+        let trailer = trailer_size(header.flags);
+        let end = match length {
+            length if length > bytes.len() => {
+                self.errors.push(format!(
+                    "length is {} bytes, the buffer ends at {}",
+                    length,
+                    bytes.len()
+                ));
+                bytes.len()
+            }
+            length if length < size + trailer => {
+                let kind = DeserializeErrorKind::LengthMismatch {
+                    length,
+                    actual: size + trailer,
+                };
+                self.errors.push(DeserializeError::at(kind, 0).to_string());
+                return size;
+            }
+            length => length,
+        };
+        // The trailer is only where the length says when the buffer holds it
+        let body_end = match length <= bytes.len() {
+            true => end - trailer,
+            false => end,
+        };
+
+        if self.body(&header, size, body_end) && length <= bytes.len() {
+            if header.flags & SIGNED_FLAG != 0 {
+                self.span(body_end, body_end + SIGNATURE_SIZE, "HMAC-SHA256 signature");
+            }
+            if header.flags & CHECKSUM_FLAG != 0 {
+                let checksum_start = end - CHECKSUM_SIZE;
+                let found = u32::from_be_bytes(bytes[checksum_start..end].try_into().unwrap());
+                let expected = crc32c::crc32c(&bytes[..checksum_start]);
+                let what = match found == expected {
+                    true => format!("CRC32C=0x{:08x}", found),
+                    false => format!("CRC32C=0x{:08x}, expected 0x{:08x}", found, expected),
+                };
+                self.span(checksum_start, end, what);
+            }
+        }
+        end
+    }
+
+    /// Explains the body from `start` to `end`, whether all of it was read
+    fn body(&mut self, header: &Header, start: usize, end: usize) -> bool {
+        if header.flags & ENCRYPTED_FLAG != 0 {
+            let nonce_end = (start + NONCE_SIZE).min(end);
+            self.span(start, nonce_end, "nonce");
+            if nonce_end < end {
+                self.span(nonce_end, end, "sealed body");
+            }
+            return true;
+        }
+        if header.flags & COMPRESSED_FLAG != 0 {
+            if start < end {
+                self.span(start, end, "zstd compressed body");
+            }
+            return true;
+        }
+
+        let encoding = Encoding {
+            version: header.version,
+            ..Encoding::default()
+        };
+        let mut names = None;
+        let mut position = start;
+        if header.flags & NAME_TABLE_FLAG != 0 {
+            let Some(table) = self.name_table(&mut position, end, encoding) else {
+                return false;
+            };
+            names = Some(table);
+        }
+        let encoding = Encoding {
+            field_ids: header.flags & (FIELD_IDS_FLAG | NAME_TABLE_FLAG) != 0,
+            ..encoding
+        };
+        let fields = Fields {
+            encoding,
+            names: names.as_deref(),
+            end,
+        };
+        if !self.fields(&fields, &mut position, header.field_count as usize, "", 0) {
+            return false;
+        }
+        if position < end {
+            let kind = DeserializeErrorKind::LengthMismatch {
+                length: header.length as usize,
+                actual: header.length as usize - (end - position),
+            };
+            self.errors
+                .push(DeserializeError::at(kind, position).to_string());
+            return false;
+        }
+        true
+    }
+
+    /// Names of the name table at `position`
+    fn name_table(
+        &mut self,
+        position: &mut usize,
+        end: usize,
+        encoding: Encoding,
+    ) -> Option<Vec<FieldName>> {
+        let count = self.count(position, end, encoding, "name count")?;
+        let mut names = Vec::with_capacity(count.min(end - *position));
+        for i in 0..count {
+            let name = self.read::<FieldName>(position, end, encoding, |name| {
+                format!("name {} '{}'", i, name)
+            })?;
+            names.push(name);
+        }
+        Some(names)
+    }
+
+    /// Explains `count` fields at `position`, whether all of them were read
+    fn fields(
+        &mut self,
+        fields: &Fields,
+        position: &mut usize,
+        count: usize,
+        path: &str,
+        depth: usize,
+    ) -> bool {
+        for _ in 0..count {
+            let Some(name) =
+                self.read::<FieldName>(position, fields.end, fields.encoding, |_| String::new())
+            else {
+                return false;
+            };
+            let (name, what) = match (fields.names, name.id()) {
+                (Some(names), Some(index)) => match names.get(index as usize) {
+                    Some(table_name) => (
+                        table_name.to_string(),
+                        format!("name index {} ('{}')", index, join(path, table_name)),
+                    ),
+                    None => (
+                        name.to_string(),
+                        format!("name index {}, out of the table", index),
+                    ),
+                },
+                (None, Some(id)) if fields.encoding.field_ids => (
+                    name.to_string(),
+                    format!("field ID {} ('{}')", id, join(path, &name)),
+                ),
+                _ => (
+                    name.to_string(),
+                    format!("field name '{}'", join(path, &name)),
+                ),
+            };
+            self.spans.last_mut().expect("name was read").what = what;
+            let path = join(path, &name);
+            if !self.value(fields, position, &path, depth) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Explains the value at `position`, whether it was read
+    fn value(&mut self, fields: &Fields, position: &mut usize, path: &str, depth: usize) -> bool {
+        let encoding = fields.encoding;
+        let Some(&type_indicator) = self.bytes[..fields.end].get(*position) else {
+            let error = DeserializeError::at(
+                DeserializeErrorKind::UnexpectedEof {
+                    expected: String::from("u8 (type indicator)"),
+                },
+                *position,
+            );
+            self.errors.push(error.within(path).to_string());
+            return false;
+        };
+        if type_indicator != OBJECT_T || depth >= MAX_DEPTH {
+            let start = *position;
+            let Some(value) =
+                self.read::<FieldValue>(position, fields.end, encoding, |_| String::new())
+            else {
+                let error = self.errors.pop().unwrap_or_default();
+                self.errors.push(format!("{}: {}", path, error));
+                return false;
+            };
+            // Type indicator and length, then the value
+            self.spans.pop();
+            let header_end = start + 1 + length_size(&self.bytes[start..*position], encoding);
+            self.span(
+                start,
+                start + 1,
+                format!("type=0x{:02x} ({})", type_indicator, value.type_name()),
+            );
+            if header_end > start + 1 {
+                let length = &self.bytes[start + 1..header_end];
+                self.span(
+                    start + 1,
+                    header_end,
+                    format!("value length={}", be(length)),
+                );
+            }
+            if header_end < *position {
+                self.span(header_end, *position, describe(&value));
+            }
+            return true;
+        }
+
+        let start = *position;
+        self.span(
+            start,
+            start + 1,
+            format!("type=0x{:02x} (object)", type_indicator),
+        );
+        *position += 1;
+        let length = length_size(&self.bytes[start..fields.end], encoding);
+        if length > 0 {
+            if start + 1 + length > fields.end {
+                let error = DeserializeError::at(
+                    DeserializeErrorKind::UnexpectedEof {
+                        expected: String::from("value length"),
+                    },
+                    *position,
+                );
+                self.errors.push(error.within(path).to_string());
+                return false;
+            }
+            let value_length = &self.bytes[*position..*position + length];
+            self.span(
+                *position,
+                *position + length,
+                format!("value length={}", be(value_length)),
+            );
+            *position += length;
+        }
+        let Some(count) = self.count(position, fields.end, encoding, "field count") else {
+            let error = self.errors.pop().unwrap_or_default();
+            self.errors.push(format!("{}: {}", path, error));
+            return false;
+        };
+        self.fields(fields, position, count, path, depth + 1)
+    }
+
+    /// Count of 1 byte in version 1 and 2 bytes from version 2 on
+    fn count(
+        &mut self,
+        position: &mut usize,
+        end: usize,
+        encoding: Encoding,
+        what: &str,
+    ) -> Option<usize> {
+        let width = match encoding.version {
+            VERSION1 => 1,
+            _ => 2,
+        };
+        let Some(count) = self.bytes[..end].get(*position..*position + width) else {
+            let error = DeserializeError::at(
+                DeserializeErrorKind::UnexpectedEof {
+                    expected: String::from(what),
+                },
+                *position,
+            );
+            self.errors.push(error.to_string());
+            return None;
+        };
+        let count = be(count) as usize;
+        self.span(*position, *position + width, format!("{}={}", what, count));
+        *position += width;
+        Some(count)
+    }
+
+    /// Reads a `T` at `position`, explained by `what`
+    fn read<T: Deserializable>(
+        &mut self,
+        position: &mut usize,
+        end: usize,
+        encoding: Encoding,
+        what: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        let start = *position;
+        let bytes = &self.bytes[start..end];
+        match T::deserialize_with(bytes, None, encoding) {
+            Ok((value, rest)) => {
+                *position = end - rest.len();
+                let what = what(&value);
+                self.span(start, *position, what);
+                Some(value)
+            }
+            Err(error) => {
+                self.error(error, start, end);
+                None
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for span in &self.spans {
+            let what = match span.end - span.start {
+                1 => format!("byte {}: {}", span.start, span.what),
+                _ => format!("bytes {}–{}: {}", span.start, span.end - 1, span.what),
+            };
+            let bytes = &self.bytes[span.start..span.end];
+            for (i, row) in bytes.chunks(ROW).enumerate() {
+                let hex: Vec<_> = row.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(
+                    text,
+                    "{:04x}  {:<width$}",
+                    span.start + i * ROW,
+                    hex.join(" "),
+                    width = ROW * 3 - 1
+                )
+                .unwrap();
+                match i {
+                    0 => writeln!(text, "  {}", what).unwrap(),
+                    _ => text.push('\n'),
+                }
+            }
+        }
+        for error in &self.errors {
+            writeln!(text, "error: {}", error).unwrap();
+        }
+        text
+    }
+}
+
+/// Where and how the fields of the body are read
+struct Fields<'a> {
+    encoding: Encoding,
+    /// Names of the name table, which field names are indexes into
+    names: Option<&'a [FieldName]>,
+    /// End of the body
+    end: usize,
+}
+
+/// Big-endian integer of the bytes, a long length's escape skipped
+fn be(bytes: &[u8]) -> u64 {
+    let bytes = match bytes {
+        [0xFF, 0xFF, rest @ ..] if rest.len() == 4 => rest,
+        bytes => bytes,
+    };
+    bytes.iter().fold(0, |value, &b| value << 8 | b as u64)
+}
+
+/// Path of the field `name` within `path`, e.g. `order.quantity`
+fn join(path: &str, name: impl Display) -> String {
+    match path.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", path, name),
+    }
+}
+
+/// Bytes of the length ahead of a value, see [`FieldValue`]'s wire format
+fn length_size(value: &[u8], encoding: Encoding) -> usize {
+    if encoding.version < VERSION3 {
+        return 0;
+    }
+    match value.get(1..3) {
+        Some([0xFF, 0xFF]) => 6,
+        _ => 2,
+    }
+}
+
+fn flag_names(flags: u8) -> String {
+    let names: Vec<_> = [
+        (CHECKSUM_FLAG, "checksum"),
+        (COMPRESSED_FLAG, "compressed"),
+        (SIGNED_FLAG, "signed"),
+        (ENCRYPTED_FLAG, "encrypted"),
+        (FIELD_IDS_FLAG, "field IDs"),
+        (NAME_TABLE_FLAG, "name table"),
+        (FINGERPRINT_FLAG, "fingerprint"),
+        (DICTIONARY_FLAG, "dictionary"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| flags & flag != 0)
+    .map(|(_, name)| name)
+    .collect();
+    match names.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", names.join(", ")),
+    }
+}
+
+/// Value as written by the text format, with the number of elements of a
+/// container
+fn describe(value: &FieldValue) -> String {
+    let label = text::type_label(value);
+    if let Some(scalar) = text::scalar(value) {
+        return format!("{} = {}", label, scalar);
+    }
+    match value {
+        FieldValue::List(list) => format!("{} of {}", label, list.len()),
+        FieldValue::Object(object) => format!("object of {} fields", object.0.len()),
+        FieldValue::Map(map) => format!("map of {} entries", map.0.len()),
+        _ => label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{
+        List, Message, MessageBuilder, MessageType, Object, Serializable, StringValue,
+    };
+
+    #[test]
+    fn explains_messages() {
+        let message = Message::new([("user_id", FieldValue::Integer(7))]).unwrap();
+        let bytes = message.serialize().unwrap();
+        assert_eq!(
+            explain(&bytes),
+            "0000  01                                               byte 0: version=0x01\n\
+             0001  01                                               byte 1: field count=1\n\
+             0002  00 15                                            bytes 2–3: length=21\n\
+             0004  07 75 73 65 72 5f 69 64                          bytes 4–11: field name 'user_id'\n\
+             000c  01                                               byte 12: type=0x01 (integer)\n\
+             000d  00 00 00 00 00 00 00 07                          bytes 13–20: integer = 7\n"
+        );
+
+        let fill = Object([(FieldName::from("quantity"), FieldValue::Integer(3))].into());
+        let message = MessageBuilder::new()
+            .with_version(VERSION3)
+            .with_type(MessageType(4))
+            .with_checksum()
+            .field("order", FieldValue::Object(fill))
+            .field("sizes", FieldValue::List(List::Integers(vec![1, 2])))
+            .build()
+            .unwrap();
+        let bytes = message.serialize().unwrap();
+        let explained = explain(&bytes);
+        assert!(explained.contains("byte 1: flags=0x01 (checksum)"));
+        assert!(explained.contains("field name 'order.quantity'"));
+        assert!(explained.contains(": list<integer> of 2\n"));
+        assert!(explained.contains(": CRC32C=0x"));
+        assert!(!explained.contains("error"));
+    }
+
+    #[test]
+    fn explains_up_to_the_error() {
+        let message = Message::new([
+            (
+                "symbol",
+                FieldValue::String(StringValue(String::from("XAU"))),
+            ),
+            ("price", FieldValue::Float(101.5)),
+        ])
+        .unwrap();
+        let bytes = message.serialize().unwrap();
+        let explained = explain(&bytes[..bytes.len() - 3]);
+        assert!(explained.contains("field name 'symbol'"));
+        assert!(explained.contains("string = \"XAU\""));
+        assert!(explained.contains("field name 'price'"));
+        assert!(explained.contains(": not read\n"));
+        assert!(explained.contains("error: length is 32 bytes, the buffer ends at 29\n"));
+        assert!(explained.contains("error: price: "));
+
+        let explained = explain(&[0x09, 0x00]);
+        assert!(explained.starts_with("0000  09"));
+        assert!(explained.contains("error: expected version: 1, 2 or 3, found: 9 at byte 0"));
+        assert_eq!(explain(&[]), "error: empty buffer\n");
+    }
+}
@@ -0,0 +1,201 @@
+//! Messages framed by a length prefix, for transports which carry a plain
+//! byte stream, e.g. raw TCP or binary WebSocket frames joined together
+//!
+//! Each frame is [Frame Length (4 bytes, big-endian)][Message], the length
+//! counts the message bytes only. A reader learns the size of a frame before
+//! it knows anything about the message in it, so the size is bounded on both
+//! ends.
+
+use std::io::{Read, Write};
+
+use super::{
+    Deserializable, DeserializeError, DeserializeErrorKind, DeserializeLimits, Encoding, Message,
+    Serializable, SerializeError, WriteError,
+    reader::{ReadError, read_start},
+};
+
+/// Size of the length prefix of a frame
+const FRAME_LENGTH_SIZE: usize = 4;
+
+/// Writes and reads length prefixed frames over any `Read` or `Write`
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FrameCodec {
+    max_frame_size: usize,
+    encoding: Encoding,
+}
+
+impl FrameCodec {
+    pub(crate) fn new() -> Self {
+        FrameCodec {
+            max_frame_size: DeserializeLimits::default().max_message_size,
+            encoding: Encoding::default(),
+        }
+    }
+
+    /// Largest frame written or read, the length prefix excluded
+    pub(crate) fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Encoding messages are written with, and read with its limits and policies
+    pub(crate) fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub(crate) fn write_frame<W: Write>(
+        &self,
+        writer: &mut W,
+        bytes: &[u8],
+    ) -> Result<(), WriteError> {
+        let max = self.max_frame_size.min(u32::MAX as usize);
+        if bytes.len() > max {
+            return Err(SerializeError::TooLong {
+                what: "frame length",
+                length: bytes.len(),
+                max,
+            }
+            .into());
+        }
+        writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn write_message<W: Write>(
+        &self,
+        writer: &mut W,
+        message: &Message,
+    ) -> Result<(), WriteError> {
+        self.write_frame(writer, &message.serialize_with(self.encoding)?)
+    }
+
+    /// Reads the next frame into `frame`, `false` at the end of the stream
+    /// between two frames
+    pub(crate) fn read_frame<R: Read>(
+        &self,
+        reader: &mut R,
+        frame: &mut Vec<u8>,
+    ) -> Result<bool, ReadError> {
+        let mut length = [0u8; FRAME_LENGTH_SIZE];
+        if !read_start(reader, &mut length)? {
+            return Ok(false);
+        }
+        let length = u32::from_be_bytes(length) as usize;
+        if length > self.max_frame_size {
+            let kind = DeserializeErrorKind::LimitExceeded {
+                limit: "frame size",
+                value: length,
+                max: self.max_frame_size,
+            };
+            return Err(DeserializeError::at(kind, 0).into());
+        }
+        frame.clear();
+        frame.resize(length, 0);
+        reader.read_exact(frame)?;
+        Ok(true)
+    }
+
+    /// Reads the message of the next frame, which has to fill it exactly,
+    /// `None` at the end of the stream between two frames
+    pub(crate) fn read_message<R: Read>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<Message>, ReadError> {
+        let mut frame = vec![];
+        if !self.read_frame(reader, &mut frame)? {
+            return Ok(None);
+        }
+        let (message, rest) = Message::deserialize_with(&frame, None, self.encoding)
+            .map_err(|e| e.resolve(frame.len(), FRAME_LENGTH_SIZE))?;
+        if !rest.is_empty() {
+            let reason = format!(
+                "frame of {} bytes holds a message of {}",
+                frame.len(),
+                frame.len() - rest.len()
+            );
+            return Err(DeserializeError::invalid(reason).into());
+        }
+        Ok(Some(message))
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        FrameCodec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::FieldValue;
+
+    #[test]
+    fn frames_round_trip() {
+        let codec = FrameCodec::new();
+        let messages = [
+            Message::new([("price", FieldValue::Integer(5))]).unwrap(),
+            Message::new([]).unwrap(),
+        ];
+        let mut stream = vec![];
+        for message in &messages {
+            codec.write_message(&mut stream, message).unwrap();
+        }
+        assert_eq!(stream[..4], [0x00, 0x00, 0x00, 0x13]);
+
+        let mut reader = &stream[..];
+        for message in &messages {
+            assert_eq!(
+                codec.read_message(&mut reader).unwrap().as_ref(),
+                Some(message)
+            );
+        }
+        assert!(codec.read_message(&mut reader).unwrap().is_none());
+
+        // A frame cut short fails rather than looking like the end
+        let mut reader = &stream[..2];
+        assert!(matches!(
+            codec.read_message(&mut reader),
+            Err(ReadError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn frame_size_limit() {
+        let codec = FrameCodec::new().with_max_frame_size(8);
+        let error = codec.write_frame(&mut vec![], &[0; 9]).unwrap_err();
+        assert!(matches!(
+            error,
+            WriteError::Serialize(SerializeError::TooLong { length: 9, .. })
+        ));
+
+        let mut reader = &[0x00, 0x00, 0x10, 0x00, 0x01][..];
+        let Err(ReadError::Deserialize(error)) = codec.read_message(&mut reader) else {
+            panic!("expected the frame to exceed the limit");
+        };
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::LimitExceeded {
+                limit: "frame size",
+                value: 4096,
+                max: 8
+            }
+        );
+
+        // Bytes after the message within its frame
+        let mut stream = vec![];
+        let message = Message::new([]).unwrap().serialize().unwrap();
+        FrameCodec::new()
+            .write_frame(&mut stream, &[&message[..], &[0x00]].concat())
+            .unwrap();
+        let error = FrameCodec::new()
+            .read_message(&mut &stream[..])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "frame of 5 bytes holds a message of 4 at byte 0"
+        );
+    }
+}
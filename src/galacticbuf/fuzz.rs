@@ -0,0 +1,168 @@
+//! Entry points of the fuzz targets in `fuzz/`, public behind the `fuzzing`
+//! feature since the targets are a crate of their own
+//!
+//! Each hands arbitrary bytes to one deserializer under [`LIMITS`], reading
+//! them must return an error rather than panic or allocate without bound. A
+//! value which reads and serializes again must read back from its own bytes.
+
+use super::{Deserializable, DeserializeLimits, Encoding, FieldValue, List, Message, Serializable};
+
+/// Tighter than [`DeserializeLimits::default`] so the fuzzer spends its time
+/// on the parser rather than on large allocations
+const LIMITS: DeserializeLimits = DeserializeLimits {
+    max_depth: 32,
+    max_fields: 4096,
+    max_string_bytes: 1 << 16,
+    max_message_size: 1 << 20,
+    max_alloc_bytes: 16 << 20,
+};
+
+/// Encoding of a value out of the first input byte, messages carry theirs in
+/// the header
+fn encoding(data: &[u8]) -> (Encoding, &[u8]) {
+    let (selector, data) = data.split_first().unwrap_or((&0, &[]));
+    let encoding = Encoding {
+        version: selector % 3 + 1,
+        compact_integers: selector & 0x04 != 0,
+        field_ids: selector & 0x08 != 0,
+        limits: LIMITS,
+        ..Encoding::default()
+    };
+    (encoding, data)
+}
+
+/// Reads the value, then checks one which serializes reads back
+fn round_trip<T: Deserializable + Serializable>(data: &[u8], encoding: Encoding) {
+    let value = match T::deserialize_with(data, None, encoding) {
+        Ok((value, _)) => value,
+        Err(e) => {
+            let _ = e.resolve(data.len(), 0).to_string();
+            return;
+        }
+    };
+    // Unknown types and the like are read but can't be written
+    let Ok(bytes) = value.serialize_with(encoding) else {
+        return;
+    };
+    if let Err(e) = T::deserialize_with(&bytes, None, encoding) {
+        panic!("{:?} doesn't read back: {}", bytes, e);
+    }
+}
+
+/// Target `message`: a whole message, header, trailer and all
+pub fn message(data: &[u8]) {
+    let encoding = Encoding {
+        limits: LIMITS,
+        ..Encoding::default()
+    };
+    let message = match Message::deserialize_with(data, None, encoding) {
+        Ok((message, _)) => message,
+        Err(e) => {
+            let _ = e.to_string();
+            return;
+        }
+    };
+    let Ok(bytes) = message.serialize() else {
+        return;
+    };
+    if let Err(e) = Message::deserialize_with(&bytes, None, encoding) {
+        panic!("{:?} doesn't read back: {}", bytes, e);
+    }
+}
+
+/// Target `list`: a list with its element type, in the encoding the first
+/// byte selects
+pub fn list(data: &[u8]) {
+    let (encoding, data) = encoding(data);
+    round_trip::<List>(data, encoding);
+}
+
+/// Target `field_value`: a value with its type indicator, in the encoding the
+/// first byte selects
+pub fn field_value(data: &[u8]) {
+    let (encoding, data) = encoding(data);
+    round_trip::<FieldValue>(data, encoding);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decimal::Decimal,
+        galacticbuf::{EnumValue, Map, MessageBuilder, MessageType, Object, StringValue},
+    };
+
+    /// Valid input with a few bytes flipped, in place of a fuzzer's corpus
+    fn mutations(seed: Vec<u8>, count: usize) -> impl Iterator<Item = Vec<u8>> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        (0..count).map(move |_| {
+            let mut bytes = seed.clone();
+            for _ in 0..next() % 4 + 1 {
+                let index = next() % bytes.len();
+                bytes[index] = next() as u8;
+            }
+            bytes.truncate(next() % (bytes.len() + 1) + bytes.len() / 2);
+            bytes
+        })
+    }
+
+    fn values() -> List {
+        let order = Object(
+            [
+                ("price", FieldValue::Decimal(Decimal::new(1250, -2))),
+                (
+                    "side",
+                    FieldValue::Enum(EnumValue {
+                        discriminant: 1,
+                        payload: Some(Box::new(FieldValue::Bool(true))),
+                    }),
+                ),
+                (
+                    "tags",
+                    FieldValue::List(List::Strings(vec![StringValue("ioc".into())])),
+                ),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.into(), value))
+            .collect(),
+        );
+        List::Mixed(vec![
+            FieldValue::Object(order),
+            FieldValue::Map(Map(vec![(FieldValue::Integer(7), FieldValue::Float(0.5))])),
+            FieldValue::Uuid([9; 16]),
+            FieldValue::Null,
+        ])
+    }
+
+    #[test]
+    fn targets_survive_mutated_input() {
+        for version in 1..=3 {
+            let message = MessageBuilder::new()
+                .with_version(version)
+                .with_type(MessageType(4))
+                .with_sequence(17)
+                .with_checksum()
+                .field("orders", FieldValue::List(values()))
+                .build()
+                .unwrap();
+            mutations(message.serialize().unwrap(), 5000).for_each(|bytes| self::message(&bytes));
+
+            let selector = version - 1;
+            let encoding = encoding(&[selector]).0;
+            let mut value = vec![selector];
+            FieldValue::List(values())
+                .serialize_into_with(&mut value, encoding)
+                .unwrap();
+            mutations(value, 5000).for_each(|bytes| field_value(&bytes));
+            let mut value = vec![selector];
+            values().serialize_into_with(&mut value, encoding).unwrap();
+            mutations(value, 5000).for_each(|bytes| list(&bytes));
+        }
+    }
+}
@@ -0,0 +1,557 @@
+//! Random values and messages for property tests and fuzzing, with `proptest`
+//! strategies behind the `proptest` feature and [`arbitrary::Arbitrary`]
+//! implementations behind the `arbitrary` feature
+//!
+//! Generated messages serialize and read back equal to themselves: floats are
+//! never NaN, maps keep to one key and one value type, enum payloads are never
+//! null and no value is of an unknown type. [`Bounds`] keeps them small enough
+//! to shrink and serialize quickly.
+
+use std::time::Duration;
+
+#[cfg(feature = "arbitrary")]
+use arbitrary::{Arbitrary, Unstructured};
+use num_bigint::{BigInt, Sign};
+#[cfg(feature = "proptest")]
+use proptest::{
+    arbitrary::Arbitrary as PropArbitrary,
+    collection::vec,
+    option,
+    prelude::{BoxedStrategy, Just, Strategy, any, prop_oneof},
+    sample::select,
+};
+
+use super::{
+    Array, EnumValue, FINGERPRINT_SIZE, FieldName, FieldValue, List, Map, Message, MessageBuilder,
+    MessageType, Object, StringValue,
+};
+use crate::{date::Date, decimal::Decimal};
+
+/// Limits on the size of generated values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Bounds {
+    /// Containers nested in containers, 0 generates scalars only
+    pub(crate) depth: u32,
+    /// Most fields of an object or message and elements of a list or map
+    pub(crate) width: usize,
+    /// Most characters of a string
+    pub(crate) string_len: usize,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            depth: 3,
+            width: 4,
+            string_len: 16,
+        }
+    }
+}
+
+fn bigint(negative: bool, magnitude: &[u8]) -> BigInt {
+    let sign = if negative { Sign::Minus } else { Sign::Plus };
+    BigInt::from_bytes_be(sign, magnitude)
+}
+
+/// Kinds of scalars a map has keys or values of, the first four make keys
+const MAP_KINDS: usize = 10;
+const MAP_KEY_KINDS: usize = 4;
+/// Kinds of scalars of a fixed size, which arrays have elements of
+const ARRAY_KINDS: [usize; 8] = [0, 2, 3, 4, 5, 6, 7, 8];
+
+#[cfg(feature = "proptest")]
+fn string(bounds: Bounds) -> BoxedStrategy<String> {
+    vec(any::<char>(), 0..=bounds.string_len)
+        .prop_map(String::from_iter)
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn name() -> BoxedStrategy<FieldName> {
+    "[a-z_][a-z0-9_]{0,11}".prop_map(FieldName::from).boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn float() -> BoxedStrategy<f64> {
+    any::<f64>()
+        .prop_filter("NaN", |float| !float.is_nan())
+        .boxed()
+}
+
+/// Scalar of one of the [`MAP_KINDS`]: integer, string, UUID, decimal, float,
+/// bool, date, duration, int128 or bigint
+#[cfg(feature = "proptest")]
+fn typed_scalar(kind: usize, bounds: Bounds) -> BoxedStrategy<FieldValue> {
+    match kind {
+        0 => any::<i64>().prop_map(FieldValue::Integer).boxed(),
+        1 => string(bounds)
+            .prop_map(|string| FieldValue::String(StringValue(string)))
+            .boxed(),
+        2 => any::<[u8; 16]>().prop_map(FieldValue::Uuid).boxed(),
+        3 => (any::<i64>(), any::<i8>())
+            .prop_map(|(mantissa, exponent)| FieldValue::Decimal(Decimal::new(mantissa, exponent)))
+            .boxed(),
+        4 => float().prop_map(FieldValue::Float).boxed(),
+        5 => any::<bool>().prop_map(FieldValue::Bool).boxed(),
+        6 => any::<i32>()
+            .prop_map(|days| FieldValue::Date(Date::from_days(days)))
+            .boxed(),
+        7 => any::<u64>()
+            .prop_map(|nanos| FieldValue::Duration(Duration::from_nanos(nanos)))
+            .boxed(),
+        8 => any::<i128>().prop_map(FieldValue::Int128).boxed(),
+        _ => (any::<bool>(), vec(any::<u8>(), 0..=bounds.string_len))
+            .prop_map(|(negative, magnitude)| FieldValue::BigInt(bigint(negative, &magnitude)))
+            .boxed(),
+    }
+}
+
+/// Value of any type other than the containers
+#[cfg(feature = "proptest")]
+pub(crate) fn scalar(bounds: Bounds) -> BoxedStrategy<FieldValue> {
+    prop_oneof![
+        (0..MAP_KINDS).prop_flat_map(move |kind| typed_scalar(kind, bounds)),
+        Just(FieldValue::Null),
+    ]
+    .boxed()
+}
+
+#[cfg(feature = "proptest")]
+pub(crate) fn field_value(bounds: Bounds) -> BoxedStrategy<FieldValue> {
+    let size = (bounds.width as u32).pow(bounds.depth).max(1);
+    scalar(bounds)
+        .prop_recursive(bounds.depth, size, bounds.width as u32, move |inner| {
+            prop_oneof![
+                list_of(inner.clone(), bounds).prop_map(FieldValue::List),
+                object_of(inner.clone(), bounds).prop_map(FieldValue::Object),
+                map(bounds).prop_map(FieldValue::Map),
+                array(bounds).prop_map(FieldValue::Array),
+                (
+                    any::<u16>(),
+                    option::of(
+                        inner.prop_filter("null payload", |value| { *value != FieldValue::Null })
+                    ),
+                )
+                    .prop_map(|(discriminant, payload)| {
+                        FieldValue::Enum(EnumValue {
+                            discriminant,
+                            payload: payload.map(Box::new),
+                        })
+                    }),
+            ]
+        })
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn list_of(values: BoxedStrategy<FieldValue>, bounds: Bounds) -> BoxedStrategy<List> {
+    let width = 0..=bounds.width;
+    prop_oneof![
+        vec(any::<i64>(), width.clone()).prop_map(List::Integers),
+        vec(string(bounds).prop_map(StringValue), width.clone()).prop_map(List::Strings),
+        vec(float(), width.clone()).prop_map(List::Floats),
+        vec(any::<bool>(), width.clone()).prop_map(List::Bools),
+        vec(object_of(values.clone(), bounds), width.clone()).prop_map(List::Objects),
+        vec(values.clone(), width.clone()).prop_map(|values| {
+            List::Lists(
+                values
+                    .into_iter()
+                    .filter_map(|value| match value {
+                        FieldValue::List(list) => Some(list),
+                        _ => None,
+                    })
+                    .collect(),
+            )
+        }),
+        vec(values, width).prop_map(List::Mixed),
+    ]
+    .boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn object_of(values: BoxedStrategy<FieldValue>, bounds: Bounds) -> BoxedStrategy<Object> {
+    vec((name(), values), 0..=bounds.width)
+        .prop_map(|fields| Object(fields.into_iter().collect()))
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn map(bounds: Bounds) -> BoxedStrategy<Map> {
+    (0..MAP_KEY_KINDS, 0..MAP_KINDS)
+        .prop_flat_map(move |(key, value)| {
+            vec(
+                (typed_scalar(key, bounds), typed_scalar(value, bounds)),
+                0..=bounds.width,
+            )
+        })
+        .prop_map(Map)
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+fn array(bounds: Bounds) -> BoxedStrategy<Array> {
+    select(&ARRAY_KINDS[..])
+        .prop_flat_map(move |kind| vec(typed_scalar(kind, bounds), 0..=bounds.width))
+        .prop_map(Array)
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+pub(crate) fn list(bounds: Bounds) -> BoxedStrategy<List> {
+    list_of(field_value(bounds), bounds)
+}
+
+#[cfg(feature = "proptest")]
+pub(crate) fn object(bounds: Bounds) -> BoxedStrategy<Object> {
+    object_of(field_value(bounds), bounds)
+}
+
+/// Message of any version, type and sequence number, built so its header
+/// matches its fields
+#[cfg(feature = "proptest")]
+pub(crate) fn message(bounds: Bounds) -> BoxedStrategy<Message> {
+    (
+        1..=3u8,
+        any::<u16>(),
+        any::<u64>(),
+        option::of(any::<[u8; FINGERPRINT_SIZE]>()),
+        vec((name(), field_value(bounds)), 0..=bounds.width),
+    )
+        .prop_filter_map(
+            "fields don't fit",
+            |(version, message_type, sequence, fingerprint, fields)| {
+                let mut builder = MessageBuilder::new()
+                    .with_version(version)
+                    .with_type(MessageType(message_type))
+                    .with_sequence(sequence);
+                if let Some(fingerprint) = fingerprint {
+                    builder = builder.with_fingerprint(fingerprint);
+                }
+                fields
+                    .into_iter()
+                    .fold(builder, |builder, (name, value)| {
+                        builder.field(name.as_str(), value)
+                    })
+                    .build()
+                    .ok()
+            },
+        )
+        .boxed()
+}
+
+#[cfg(feature = "proptest")]
+impl PropArbitrary for FieldValue {
+    type Parameters = Bounds;
+    type Strategy = BoxedStrategy<FieldValue>;
+
+    fn arbitrary_with(bounds: Bounds) -> Self::Strategy {
+        field_value(bounds)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl PropArbitrary for List {
+    type Parameters = Bounds;
+    type Strategy = BoxedStrategy<List>;
+
+    fn arbitrary_with(bounds: Bounds) -> Self::Strategy {
+        list(bounds)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl PropArbitrary for Object {
+    type Parameters = Bounds;
+    type Strategy = BoxedStrategy<Object>;
+
+    fn arbitrary_with(bounds: Bounds) -> Self::Strategy {
+        object(bounds)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl PropArbitrary for Message {
+    type Parameters = Bounds;
+    type Strategy = BoxedStrategy<Message>;
+
+    fn arbitrary_with(bounds: Bounds) -> Self::Strategy {
+        message(bounds)
+    }
+}
+
+/// Values out of fuzzer input, within the [`Bounds::default`]
+#[cfg(feature = "arbitrary")]
+struct Generator<'u, 'a> {
+    u: &'u mut Unstructured<'a>,
+    bounds: Bounds,
+}
+
+#[cfg(feature = "arbitrary")]
+impl Generator<'_, '_> {
+    fn len(&mut self) -> arbitrary::Result<usize> {
+        self.u.int_in_range(0..=self.bounds.width)
+    }
+
+    fn string(&mut self) -> arbitrary::Result<String> {
+        let len = self.u.int_in_range(0..=self.bounds.string_len)?;
+        (0..len).map(|_| self.u.arbitrary::<char>()).collect()
+    }
+
+    fn name(&mut self) -> arbitrary::Result<FieldName> {
+        let mut name = self.string()?;
+        while name.len() > u8::MAX as usize {
+            name.pop();
+        }
+        Ok(FieldName::from(name))
+    }
+
+    fn float(&mut self) -> arbitrary::Result<f64> {
+        let float: f64 = self.u.arbitrary()?;
+        Ok(if float.is_nan() { 0.0 } else { float })
+    }
+
+    fn typed_scalar(&mut self, kind: usize) -> arbitrary::Result<FieldValue> {
+        Ok(match kind {
+            0 => FieldValue::Integer(self.u.arbitrary()?),
+            1 => FieldValue::String(StringValue(self.string()?)),
+            2 => FieldValue::Uuid(self.u.arbitrary()?),
+            3 => FieldValue::Decimal(Decimal::new(self.u.arbitrary()?, self.u.arbitrary()?)),
+            4 => FieldValue::Float(self.float()?),
+            5 => FieldValue::Bool(self.u.arbitrary()?),
+            6 => FieldValue::Date(Date::from_days(self.u.arbitrary()?)),
+            7 => FieldValue::Duration(Duration::from_nanos(self.u.arbitrary()?)),
+            8 => FieldValue::Int128(self.u.arbitrary()?),
+            _ => {
+                let negative = self.u.arbitrary()?;
+                let len = self.u.int_in_range(0..=self.bounds.string_len)?;
+                FieldValue::BigInt(bigint(negative, self.u.bytes(len)?))
+            }
+        })
+    }
+
+    fn value(&mut self, depth: u32) -> arbitrary::Result<FieldValue> {
+        let kinds = if depth < self.bounds.depth { 16 } else { 11 };
+        Ok(match self.u.choose_index(kinds)? {
+            kind @ 0..MAP_KINDS => self.typed_scalar(kind)?,
+            10 => FieldValue::Null,
+            11 => FieldValue::List(self.list(depth + 1)?),
+            12 => FieldValue::Object(self.object(depth + 1)?),
+            13 => {
+                let key = self.u.choose_index(MAP_KEY_KINDS)?;
+                let value = self.u.choose_index(MAP_KINDS)?;
+                let len = self.len()?;
+                let entries = (0..len)
+                    .map(|_| Ok((self.typed_scalar(key)?, self.typed_scalar(value)?)))
+                    .collect::<arbitrary::Result<_>>()?;
+                FieldValue::Map(Map(entries))
+            }
+            14 => {
+                let kind = *self.u.choose(&ARRAY_KINDS)?;
+                let len = self.len()?;
+                let elements = (0..len)
+                    .map(|_| self.typed_scalar(kind))
+                    .collect::<arbitrary::Result<_>>()?;
+                FieldValue::Array(Array(elements))
+            }
+            _ => {
+                let discriminant = self.u.arbitrary()?;
+                let payload = match self.value(depth + 1)? {
+                    FieldValue::Null => None,
+                    payload => Some(Box::new(payload)),
+                };
+                FieldValue::Enum(EnumValue {
+                    discriminant,
+                    payload,
+                })
+            }
+        })
+    }
+
+    fn values(&mut self, depth: u32) -> arbitrary::Result<Vec<FieldValue>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.value(depth)).collect()
+    }
+
+    fn list(&mut self, depth: u32) -> arbitrary::Result<List> {
+        let len = self.len()?;
+        Ok(match self.u.choose_index(7)? {
+            0 => List::Integers(
+                (0..len)
+                    .map(|_| self.u.arbitrary())
+                    .collect::<Result<_, _>>()?,
+            ),
+            1 => List::Strings(
+                (0..len)
+                    .map(|_| self.string().map(StringValue))
+                    .collect::<Result<_, _>>()?,
+            ),
+            2 => List::Floats((0..len).map(|_| self.float()).collect::<Result<_, _>>()?),
+            3 => List::Bools(
+                (0..len)
+                    .map(|_| self.u.arbitrary())
+                    .collect::<Result<_, _>>()?,
+            ),
+            4 => List::Objects(
+                (0..len)
+                    .map(|_| self.object(depth))
+                    .collect::<Result<_, _>>()?,
+            ),
+            5 if depth < self.bounds.depth => List::Lists(
+                (0..len)
+                    .map(|_| self.list(depth + 1))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => List::Mixed(self.values(depth)?),
+        })
+    }
+
+    fn object(&mut self, depth: u32) -> arbitrary::Result<Object> {
+        let len = self.len()?;
+        let fields = (0..len)
+            .map(|_| Ok((self.name()?, self.value(depth)?)))
+            .collect::<arbitrary::Result<_>>()?;
+        Ok(Object(fields))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+fn generator<'u, 'a>(u: &'u mut Unstructured<'a>) -> Generator<'u, 'a> {
+    Generator {
+        u,
+        bounds: Bounds::default(),
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for FieldValue {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        generator(u).value(0)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for List {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        generator(u).list(0)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Object {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        generator(u).object(0)
+    }
+}
+
+/// Message as [`message`] generates it, fuzzer input which makes fields that
+/// don't fit is rejected with [`arbitrary::Error::IncorrectFormat`]
+#[cfg(feature = "arbitrary")]
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let version = u.int_in_range(1..=3)?;
+        let message_type = MessageType(u.arbitrary()?);
+        let sequence = u.arbitrary()?;
+        let fingerprint: Option<[u8; FINGERPRINT_SIZE]> = u.arbitrary()?;
+        let Object(fields) = generator(u).object(0)?;
+        let mut builder = MessageBuilder::new()
+            .with_version(version)
+            .with_type(message_type)
+            .with_sequence(sequence);
+        if let Some(fingerprint) = fingerprint {
+            builder = builder.with_fingerprint(fingerprint);
+        }
+        fields
+            .into_iter()
+            .fold(builder, |builder, (name, value)| {
+                builder.field(name.as_str(), value)
+            })
+            .build()
+            .map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{Deserializable, Serializable};
+    #[cfg(feature = "proptest")]
+    use crate::galacticbuf::{Encoding, explain};
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn messages_round_trip(message in message(Bounds::default())) {
+            let bytes = message.serialize().unwrap();
+            let (read, rest) = Message::deserialize(&bytes, None).unwrap();
+            proptest::prop_assert!(rest.is_empty());
+            proptest::prop_assert_eq!(&read, &message);
+
+            let encoding = Encoding {
+                version: message.header.version,
+                name_table: true,
+                ..Encoding::default()
+            };
+            let bytes = message.serialize_with(encoding).unwrap();
+            let (read, _) = Message::deserialize(&bytes, None).unwrap();
+            proptest::prop_assert_eq!(read.body, message.body);
+        }
+
+        #[test]
+        fn text_round_trips(message in message(Bounds::default())) {
+            // Text keeps a decimal's value but not always its scale, so the
+            // length in the header can differ
+            let read = Message::from_text(&message.to_text()).unwrap();
+            proptest::prop_assert_eq!(read.body, message.body);
+        }
+
+        #[test]
+        fn corrupt_bytes_fail_cleanly(
+            message in message(Bounds::default()),
+            flips in vec((any::<proptest::sample::Index>(), 1..=u8::MAX), 1..4),
+        ) {
+            let mut bytes = message.serialize().unwrap();
+            for (index, mask) in flips {
+                let index = index.index(bytes.len());
+                bytes[index] ^= mask;
+            }
+            let _ = Message::deserialize(&bytes, None);
+            if let Ok(fields) = Message::fields(&bytes) {
+                fields.for_each(drop);
+            }
+            explain(&bytes);
+        }
+
+        #[test]
+        fn random_bytes_fail_cleanly(bytes in vec(any::<u8>(), 0..256)) {
+            let _ = Message::deserialize(&bytes, None);
+            explain(&bytes);
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_messages_round_trip() {
+        // Deterministic input standing in for a fuzzer's
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let input: Vec<u8> = (0..1 << 16)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut u = Unstructured::new(&input);
+        let mut messages = 0;
+        while !u.is_empty() {
+            let Ok(message) = u.arbitrary::<Message>() else {
+                continue;
+            };
+            let bytes = message.serialize().unwrap();
+            let (read, _) = Message::deserialize(&bytes, None).unwrap();
+            assert_eq!(read, message);
+            messages += 1;
+        }
+        assert!(messages > 10);
+    }
+}
@@ -0,0 +1,244 @@
+//! Messages as JSON, for debugging tools and HTTP endpoints taking either
+//! representation
+//!
+//! The fields of a message become the members of a JSON object, the header
+//! is left out and the members are sorted by name like all of serde_json's
+//! objects. Values map as follows:
+//!
+//! | galacticbuf       | JSON                                                  |
+//! |-------------------|-------------------------------------------------------|
+//! | integer           | number                                                |
+//! | float             | number, `null` for NaN and infinities                 |
+//! | string            | string                                                |
+//! | bool              | `true` or `false`                                     |
+//! | null              | `null`                                                |
+//! | list of any type  | array                                                 |
+//! | array             | array                                                 |
+//! | object            | object                                                |
+//! | UUID              | string, e.g. `"67e55044-10b1-426f-9247-bb680e5fe0c8"` |
+//! | decimal           | string, e.g. `"12.50"`                                |
+//! | date              | string, e.g. `"2024-03-01"`                           |
+//! | duration          | number of nanoseconds                                 |
+//! | int128, bigint    | number, beyond 64 bits a string of its digits         |
+//! | map               | array of `[key, value]` pairs                         |
+//! | enum              | `{"discriminant": 1, "payload": ...}`                 |
+//! | unknown type      | `null`                                                |
+//!
+//! Going the other way a number is an integer when it fits into an `i64` and
+//! a float when it has a fraction or exponent, larger integers are rejected.
+//! Arrays become typed lists when all their elements share a type and mixed
+//! lists otherwise. Strings stay strings and numbers numbers, so UUIDs,
+//! decimals, dates, durations, large integers, arrays, maps and enums don't
+//! come back as they were.
+
+use num_bigint::BigInt;
+use serde_json::{Map as JsonMap, Number, Value};
+
+use super::{
+    Array, ConvertError, EnumValue, FieldName, FieldValue, Fields, List, Map, Message,
+    MessageBuilder, Object, StringValue, format_uuid,
+};
+
+impl Message {
+    /// Fields of the message as a JSON object, see the module docs
+    pub(crate) fn to_json(&self) -> Value {
+        fields_to_json(&self.body)
+    }
+
+    /// Untyped message of the members of a JSON object, see the module docs
+    pub(crate) fn from_json(json: &Value) -> Result<Message, JsonError> {
+        let Value::Object(members) = json else {
+            return Err(JsonError::unsupported("message must be a JSON object"));
+        };
+        let mut builder = MessageBuilder::new();
+        for (name, value) in members {
+            let value = from_json(value).map_err(|e| e.within(name))?;
+            builder = builder.field(name, value);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+impl FieldValue {
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            FieldValue::Integer(integer) => Value::from(*integer),
+            FieldValue::Float(float) => Number::from_f64(*float).map_or(Value::Null, Value::Number),
+            FieldValue::String(StringValue(string)) => Value::from(string.as_str()),
+            FieldValue::Bool(boolean) => Value::Bool(*boolean),
+            FieldValue::Null | FieldValue::Unknown(..) => Value::Null,
+            FieldValue::List(list) => Value::Array(
+                list.clone()
+                    .into_values()
+                    .iter()
+                    .map(Self::to_json)
+                    .collect(),
+            ),
+            FieldValue::Array(Array(elements)) => {
+                Value::Array(elements.iter().map(Self::to_json).collect())
+            }
+            FieldValue::Object(Object(fields)) => fields_to_json(fields),
+            FieldValue::Uuid(uuid) => Value::from(format_uuid(uuid)),
+            FieldValue::Decimal(decimal) => Value::from(decimal.to_string()),
+            FieldValue::Date(date) => Value::from(date.to_string()),
+            FieldValue::Duration(duration) => {
+                Value::from(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+            }
+            FieldValue::Int128(integer) => integer_to_json(&BigInt::from(*integer)),
+            FieldValue::BigInt(integer) => integer_to_json(integer),
+            FieldValue::Map(Map(entries)) => Value::Array(
+                entries
+                    .iter()
+                    .map(|(key, value)| Value::Array(vec![key.to_json(), value.to_json()]))
+                    .collect(),
+            ),
+            FieldValue::Enum(EnumValue {
+                discriminant,
+                payload,
+            }) => {
+                let mut members = JsonMap::new();
+                members.insert(String::from("discriminant"), Value::from(*discriminant));
+                let payload = payload.as_deref().map_or(Value::Null, Self::to_json);
+                members.insert(String::from("payload"), payload);
+                Value::Object(members)
+            }
+        }
+    }
+}
+
+/// Number where it fits 64 bits, a string of its digits otherwise
+fn integer_to_json(integer: &BigInt) -> Value {
+    if let Ok(integer) = i64::try_from(integer) {
+        Value::from(integer)
+    } else if let Ok(integer) = u64::try_from(integer) {
+        Value::from(integer)
+    } else {
+        Value::from(integer.to_string())
+    }
+}
+
+fn fields_to_json(fields: &Fields) -> Value {
+    Value::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (String::from(name.as_str()), value.to_json()))
+            .collect(),
+    )
+}
+
+fn from_json(json: &Value) -> Result<FieldValue, JsonError> {
+    let value = match json {
+        Value::Null => FieldValue::Null,
+        Value::Bool(boolean) => FieldValue::Bool(*boolean),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(integer), _) => FieldValue::Integer(integer),
+            (None, Some(_)) => {
+                let reason = format!("integer {} beyond i64", number);
+                return Err(JsonError::unsupported(reason));
+            }
+            (None, None) => FieldValue::Float(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(string) => FieldValue::String(StringValue(string.clone())),
+        Value::Array(elements) => {
+            let values = elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| from_json(element).map_err(|e| e.within(format!("[{}]", i))))
+                .collect::<Result<_, _>>()?;
+            FieldValue::List(List::from_values(values))
+        }
+        Value::Object(members) => {
+            let fields = members
+                .iter()
+                .map(|(name, value)| {
+                    let value = from_json(value).map_err(|e| e.within(name))?;
+                    Ok((FieldName::from(name.as_str()), value))
+                })
+                .collect::<Result<_, JsonError>>()?;
+            FieldValue::Object(Object(fields))
+        }
+    };
+    Ok(value)
+}
+
+/// JSON without a counterpart in a message
+pub(crate) type JsonError = ConvertError;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::decimal::Decimal;
+
+    #[test]
+    fn json_round_trip() {
+        let json = json!({
+            "id": 7,
+            "price": 101.5,
+            "symbol": "XAU",
+            "open": true,
+            "expires": null,
+            "sizes": [1, 2, 3],
+            "mixed": [1, "two", 3.5],
+            "fills": [{"quantity": 3}, {"quantity": 4}],
+        });
+        let message = Message::from_json(&json).unwrap();
+        assert_eq!(message.get("id"), Some(&FieldValue::Integer(7)));
+        assert_eq!(message.get("price"), Some(&FieldValue::Float(101.5)));
+        assert_eq!(
+            message.get("sizes"),
+            Some(&FieldValue::List(List::Integers(vec![1, 2, 3])))
+        );
+        assert!(matches!(
+            message.get("mixed"),
+            Some(FieldValue::List(List::Mixed(_)))
+        ));
+        assert!(matches!(
+            message.get("fills"),
+            Some(FieldValue::List(List::Objects(_)))
+        ));
+        assert_eq!(message.to_json(), json);
+    }
+
+    #[test]
+    fn json_mapping() {
+        let message = Message::new([
+            ("id", FieldValue::Uuid([0xAB; 16])),
+            ("price", FieldValue::Decimal(Decimal::new(1250, -2))),
+            ("ratio", FieldValue::Float(f64::NAN)),
+            (
+                "limits",
+                FieldValue::Map(Map(vec![(FieldValue::Integer(1), FieldValue::Bool(true))])),
+            ),
+            (
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 1,
+                    payload: None,
+                }),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            message.to_json(),
+            json!({
+                "id": "abababab-abab-abab-abab-abababababab",
+                "price": "12.50",
+                "ratio": null,
+                "limits": [[1, true]],
+                "side": {"discriminant": 1, "payload": null},
+            })
+        );
+
+        let error = Message::from_json(&json!({"fills": [{"id": 1}, {"id": u64::MAX}]}));
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "fills[1].id: integer 18446744073709551615 beyond i64"
+        );
+        let error = Message::from_json(&json!([1, 2])).unwrap_err();
+        assert_eq!(error.to_string(), "message must be a JSON object");
+        let error = Message::from_json(&json!({"x".repeat(256): 1})).unwrap_err();
+        assert!(matches!(error, JsonError::Serialize(_)));
+    }
+}
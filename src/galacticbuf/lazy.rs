@@ -0,0 +1,487 @@
+//! Fields of a serialized message read one at a time, for consumers which only
+//! need a few of them out of a large message
+//!
+//! Values are skipped over rather than decoded, a [`FieldValueRef`] points at
+//! the bytes of its value and decodes them only when asked. Compressed and
+//! sealed bodies have to be decoded as a whole with [`Message::deserialize`].
+//! A name table is read up front, the names of nested objects are looked up
+//! in it again when their value is decoded.
+//!
+//! [`Message::deserialize`]: super::Deserializable::deserialize
+
+use indexmap::IndexSet;
+
+use super::{
+    ARRAY_T, BIGINT_T, BOOL_T, COMPACT_INTEGER_T, COMPRESSED_FLAG, DATE_T, DECIMAL_T, DURATION_T,
+    Deserializable, DeserializeError, DeserializeErrorKind, ENCRYPTED_FLAG, ENUM_T, Encoding,
+    FLOAT_T, FieldName, FieldValue, Frame, INT128_T, INTEGER_T, LIST_T, MAP_T, MIXED_T, Message,
+    NAME_TABLE_FLAG, NULL_T, OBJECT_T, Protection, STRING_T, UUID_T, VALUE_TYPES, VERSION3,
+    deserialize_compact_integer, deserialize_field_count, deserialize_length,
+    deserialize_name_table, deserialize_width, eof, fixed_size, from_utf8, rename_value,
+    table_name,
+};
+
+impl Message {
+    /// Fields of the serialized message in wire order, each read when the
+    /// iterator gets to it
+    pub(crate) fn fields(bytes: &[u8]) -> Result<LazyFields<'_>, DeserializeError> {
+        Message::fields_with(bytes, Encoding::default())
+    }
+
+    pub(crate) fn fields_with(
+        bytes: &[u8],
+        encoding: Encoding,
+    ) -> Result<LazyFields<'_>, DeserializeError> {
+        let Frame {
+            header,
+            encoding,
+            header_bytes,
+            body,
+            ..
+        } = Message::split_frame(bytes, encoding, Protection::default())?;
+        let header_size = header_bytes.len();
+        if header.flags & ENCRYPTED_FLAG != 0 {
+            return Err(DeserializeError::at(DeserializeErrorKind::Encrypted, 0));
+        }
+        if header.flags & COMPRESSED_FLAG != 0 {
+            let kind = DeserializeErrorKind::Invalid(String::from(
+                "compressed body can't be read field by field",
+            ));
+            return Err(DeserializeError::at(kind, header_size));
+        }
+        let (names, table, bytes) = match header.flags & NAME_TABLE_FLAG {
+            0 => (None, None, body),
+            _ => {
+                let (names, bytes) = deserialize_name_table(body, encoding)
+                    .map_err(|e| e.resolve(body.len(), header_size))?;
+                let table = &body[..body.len() - bytes.len()];
+                (Some(names), Some(table), bytes)
+            }
+        };
+        Ok(LazyFields {
+            bytes,
+            remaining: header.field_count as usize,
+            encoding,
+            names,
+            table,
+            body_length: body.len(),
+            header_size,
+            done: false,
+        })
+    }
+
+    /// Values of the named fields in the order of `names`, `None` for those the
+    /// message doesn't have
+    ///
+    /// Reading stops at the last of them, the fields after it are neither
+    /// decoded nor checked. A name repeated within the message gives its first
+    /// value.
+    pub(crate) fn extract<'a>(
+        bytes: &'a [u8],
+        names: &[&str],
+    ) -> Result<Vec<Option<FieldValueRef<'a>>>, DeserializeError> {
+        let mut found = vec![None; names.len()];
+        let mut missing = names.len();
+        let mut fields = Message::fields(bytes)?;
+        while missing > 0 {
+            let Some(field) = fields.next() else {
+                break;
+            };
+            let (name, value) = field?;
+            for (wanted, slot) in names.iter().zip(&mut found) {
+                if slot.is_none() && *wanted == name.as_str() {
+                    *slot = Some(value);
+                    missing -= 1;
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Iterator over the fields of a serialized message, ends after the first
+/// error
+pub(crate) struct LazyFields<'a> {
+    /// Body from the next field on
+    bytes: &'a [u8],
+    /// Fields the header announces which haven't been read yet
+    remaining: usize,
+    encoding: Encoding,
+    /// Name table of the body, fields are named by their index in it
+    names: Option<IndexSet<FieldName>>,
+    /// Name table as written, for the values to look their names up in
+    table: Option<&'a [u8]>,
+    body_length: usize,
+    header_size: usize,
+    done: bool,
+}
+
+impl<'a> LazyFields<'a> {
+    fn next_field(&mut self) -> Result<(FieldName, FieldValueRef<'a>), DeserializeError> {
+        let (mut name, bytes) = FieldName::deserialize_with(self.bytes, None, self.encoding)?;
+        if let Some(names) = &self.names {
+            name =
+                table_name(names, &name).map_err(|kind| DeserializeError::new(kind, self.bytes))?;
+        }
+        let (mut value, bytes) = split_value(bytes, self.encoding).map_err(|e| e.within(&name))?;
+        value.table = self.table;
+        self.bytes = bytes;
+        Ok((name, value))
+    }
+}
+
+impl<'a> Iterator for LazyFields<'a> {
+    type Item = Result<(FieldName, FieldValueRef<'a>), DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.remaining == 0 {
+            self.done = true;
+            if self.bytes.is_empty() {
+                return None;
+            }
+            let length = self.header_size + self.body_length;
+            let kind = DeserializeErrorKind::LengthMismatch {
+                length,
+                actual: length - self.bytes.len(),
+            };
+            let error = DeserializeError::new(kind, self.bytes);
+            return Some(Err(error.resolve(self.body_length, self.header_size)));
+        }
+        self.remaining -= 1;
+        let field = self
+            .next_field()
+            .map_err(|e| e.resolve(self.body_length, self.header_size));
+        self.done = field.is_err();
+        Some(field)
+    }
+}
+
+/// Value of a field as it is written, decoded on demand
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FieldValueRef<'a> {
+    type_indicator: u8,
+    /// Value without its type indicator and length
+    bytes: &'a [u8],
+    encoding: Encoding,
+    /// Name table of the message, see [`LazyFields`]
+    table: Option<&'a [u8]>,
+}
+
+impl<'a> FieldValueRef<'a> {
+    pub(crate) fn type_indicator(&self) -> u8 {
+        self.type_indicator
+    }
+
+    /// Decodes the value, with the limits of the message it was read from
+    pub(crate) fn decode(&self) -> Result<FieldValue, DeserializeError> {
+        if self.encoding.version >= VERSION3 && !VALUE_TYPES.contains(&self.type_indicator) {
+            return Ok(FieldValue::Unknown(
+                self.type_indicator,
+                self.bytes.to_vec(),
+            ));
+        }
+        let (mut value, _) =
+            FieldValue::deserialize_value(self.type_indicator, self.bytes, self.encoding)?;
+        if let Some(table) = self.table {
+            let (names, _) = deserialize_name_table(table, self.encoding)?;
+            rename_value(&mut value, &mut |name| table_name(&names, name))
+                .map_err(|kind| DeserializeError::new(kind, self.bytes))?;
+        }
+        Ok(value)
+    }
+
+    /// Integer value, `None` for values of other types
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match self.type_indicator {
+            INTEGER_T => i64::deserialize_with(self.bytes, None, self.encoding).ok(),
+            COMPACT_INTEGER_T => deserialize_width(self.bytes)
+                .and_then(|(width, bytes)| deserialize_compact_integer(bytes, width))
+                .ok(),
+            _ => None,
+        }
+        .map(|(integer, _)| integer)
+    }
+
+    /// Float value, `None` for values of other types
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self.type_indicator {
+            FLOAT_T => f64::deserialize_with(self.bytes, None, self.encoding)
+                .ok()
+                .map(|(float, _)| float),
+            _ => None,
+        }
+    }
+
+    /// Bool value, `None` for values of other types or invalid bools
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self.type_indicator {
+            BOOL_T => bool::deserialize_with(self.bytes, None, self.encoding)
+                .ok()
+                .map(|(boolean, _)| boolean),
+            _ => None,
+        }
+    }
+
+    /// String value borrowed from the message, `None` for values of other
+    /// types or invalid UTF-8
+    pub(crate) fn as_str(&self) -> Option<&'a str> {
+        if self.type_indicator != STRING_T {
+            return None;
+        }
+        let (length, bytes) = deserialize_length(self.bytes, "length").ok()?;
+        from_utf8(bytes.get(..length)?).ok()
+    }
+}
+
+/// [Type (1 byte)][Value] or since version 3 [Type (1 byte)][Length][Value]
+fn split_value(
+    bytes: &[u8],
+    encoding: Encoding,
+) -> Result<(FieldValueRef<'_>, &[u8]), DeserializeError> {
+    let (type_indicator, bytes) = bytes
+        .split_first()
+        .ok_or_else(|| eof("u8 (type indicator)", bytes))?;
+    let (value_bytes, bytes) = if encoding.version >= VERSION3 {
+        let (length, bytes) = deserialize_length(bytes, "value length")?;
+        if length > bytes.len() {
+            return Err(eof(format!("value of {} bytes", length), bytes));
+        }
+        bytes.split_at(length)
+    } else {
+        let rest = skip_value(*type_indicator, bytes, encoding)?;
+        bytes.split_at(bytes.len() - rest.len())
+    };
+    let value = FieldValueRef {
+        type_indicator: *type_indicator,
+        bytes: value_bytes,
+        encoding,
+        table: None,
+    };
+    Ok((value, bytes))
+}
+
+/// Bytes after the value of the given type, which are checked for being
+/// complete but not decoded
+fn skip_value(
+    type_indicator: u8,
+    bytes: &[u8],
+    encoding: Encoding,
+) -> Result<&[u8], DeserializeError> {
+    match type_indicator {
+        INTEGER_T => Ok(i64::deserialize_with(bytes, None, encoding)?.1),
+        STRING_T => {
+            let (length, bytes) = deserialize_length(bytes, "length")?;
+            skip(bytes, length, "string")
+        }
+        LIST_T => {
+            let encoding = encoding.nested(bytes)?;
+            let (&element_type, bytes) = bytes
+                .split_first()
+                .ok_or_else(|| eof("u8 (element type)", bytes))?;
+            if element_type == COMPACT_INTEGER_T {
+                let (width, bytes) = deserialize_width(bytes)?;
+                let (count, bytes) = deserialize_length(bytes, "count")?;
+                return skip(
+                    bytes,
+                    count.saturating_mul(width as usize),
+                    "compact integers",
+                );
+            }
+            let (count, mut bytes) = deserialize_length(bytes, "count")?;
+            for i in 0..count {
+                bytes = match element_type {
+                    MIXED_T => split_value(bytes, encoding).map(|(_, bytes)| bytes),
+                    _ => skip_value(element_type, bytes, encoding),
+                }
+                .map_err(|e| e.within(format!("[{}]", i)))?;
+            }
+            Ok(bytes)
+        }
+        OBJECT_T => {
+            let encoding = encoding.nested(bytes)?;
+            let (count, mut bytes) = deserialize_field_count(bytes, encoding)?;
+            for _ in 0..count {
+                let (name, next_bytes) = FieldName::deserialize_with(bytes, None, encoding)?;
+                bytes = split_value(next_bytes, encoding)
+                    .map_err(|e| e.within(&name))?
+                    .1;
+            }
+            Ok(bytes)
+        }
+        FLOAT_T => skip(bytes, std::mem::size_of::<f64>(), "f64"),
+        BOOL_T => skip(bytes, std::mem::size_of::<u8>(), "bool"),
+        NULL_T => Ok(bytes),
+        UUID_T => skip(bytes, 16, "16 byte uuid"),
+        DECIMAL_T => {
+            let (_, bytes) = i64::deserialize_with(bytes, None, encoding)?;
+            skip(bytes, std::mem::size_of::<i8>(), "i8 (exponent)")
+        }
+        DATE_T => skip(bytes, std::mem::size_of::<i32>(), "i32 (days)"),
+        DURATION_T => skip(bytes, std::mem::size_of::<u64>(), "u64 (nanoseconds)"),
+        INT128_T => skip(bytes, std::mem::size_of::<i128>(), "i128"),
+        ARRAY_T => {
+            let Some(&[element_type, count_high, count_low]) = bytes.get(..3) else {
+                return Err(eof("u8 (element type) and u16 (count)", bytes));
+            };
+            let Some(size) = fixed_size(element_type) else {
+                let kind = DeserializeErrorKind::UnsupportedArrayElement {
+                    type_indicator: element_type,
+                };
+                return Err(DeserializeError::new(kind, bytes));
+            };
+            let count = u16::from_be_bytes([count_high, count_low]) as usize;
+            skip(&bytes[3..], count * size, "array")
+        }
+        BIGINT_T => {
+            let bytes = skip(bytes, std::mem::size_of::<u8>(), "bool (negative)")?;
+            let (length, bytes) = deserialize_length(bytes, "bigint length")?;
+            skip(bytes, length, "bigint")
+        }
+        MAP_T => {
+            let encoding = encoding.nested(bytes)?;
+            let Some(&[key_type, value_type]) = bytes.get(..2) else {
+                return Err(eof("u8 (key type) and u8 (value type)", bytes));
+            };
+            let count = bytes
+                .get(2..4)
+                .map(|count| u16::from_be_bytes([count[0], count[1]]))
+                .ok_or_else(|| eof("u16 (entry count)", &bytes[2..]))?;
+            let mut bytes = &bytes[4..];
+            for i in 0..count {
+                bytes = skip_value(key_type, bytes, encoding)
+                    .and_then(|bytes| skip_value(value_type, bytes, encoding))
+                    .map_err(|e| e.within(format!("[{}]", i)))?;
+            }
+            Ok(bytes)
+        }
+        ENUM_T => {
+            let encoding = encoding.nested(bytes)?;
+            let bytes = skip(bytes, std::mem::size_of::<u16>(), "u16 (discriminant)")?;
+            Ok(split_value(bytes, encoding)?.1)
+        }
+        COMPACT_INTEGER_T => {
+            let (width, bytes) = deserialize_width(bytes)?;
+            skip(bytes, width as usize, "compact integer")
+        }
+        t => Err(DeserializeError::new(
+            DeserializeErrorKind::UnknownType { type_indicator: t },
+            bytes,
+        )),
+    }
+}
+
+fn skip<'a>(bytes: &'a [u8], length: usize, what: &str) -> Result<&'a [u8], DeserializeError> {
+    bytes
+        .get(length..)
+        .ok_or_else(|| eof(format!("{} of {} bytes", what, length), bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{
+        EnumValue, List, Map, MessageBuilder, Object, Serializable, StringValue, VERSION1,
+    };
+
+    fn message(version: u8) -> Message {
+        let fill = Object([(FieldName::from("quantity"), FieldValue::Integer(3))].into());
+        MessageBuilder::new()
+            .with_version(version)
+            .field("fills", FieldValue::List(List::Objects(vec![fill])))
+            .field("sizes", FieldValue::List(List::Integers(vec![1, -300])))
+            .field(
+                "limits",
+                FieldValue::Map(Map(vec![(
+                    FieldValue::String(StringValue(String::from("ask"))),
+                    FieldValue::Integer(6),
+                )])),
+            )
+            .field(
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 1,
+                    payload: Some(Box::new(FieldValue::Bool(true))),
+                }),
+            )
+            .field(
+                "symbol",
+                FieldValue::String(StringValue(String::from("XAU"))),
+            )
+            .field("price", FieldValue::Integer(-42))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn fields_match_the_message() {
+        for (version, name_table) in [VERSION1, 2, VERSION3]
+            .into_iter()
+            .flat_map(|version| [(version, false), (version, true)])
+        {
+            let encoding = Encoding {
+                compact_integers: true,
+                name_table,
+                ..Encoding::default()
+            };
+            let message = message(version);
+            let bytes = message.serialize_with(encoding).unwrap();
+            let fields: Vec<_> = Message::fields(&bytes)
+                .unwrap()
+                .map(|field| {
+                    let (name, value) = field.unwrap();
+                    (name, value.decode().unwrap())
+                })
+                .collect();
+            assert_eq!(fields, message.body.into_iter().collect::<Vec<_>>());
+        }
+
+        let bytes = message(2).serialize().unwrap();
+        let [symbol, price, missing] =
+            &Message::extract(&bytes, &["symbol", "price", "qty"]).unwrap()[..]
+        else {
+            panic!("expected a value per name");
+        };
+        assert_eq!(symbol.unwrap().as_str(), Some("XAU"));
+        assert_eq!(price.unwrap().as_i64(), Some(-42));
+        assert_eq!(price.unwrap().as_str(), None);
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn extract_stops_early() {
+        let mut bytes = message(2).serialize().unwrap();
+        // Corrupt the type of the last field, "price"
+        let at = bytes.len() - 2;
+        assert_eq!(bytes[at], INTEGER_T);
+        bytes[at] = 0xEE;
+
+        let error = Message::fields(&bytes)
+            .unwrap()
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::UnknownType {
+                type_indicator: 0xEE
+            }
+        );
+        assert_eq!(error.path, "price");
+        assert_eq!(error.offset, at + 1);
+
+        let [symbol] = &Message::extract(&bytes, &["symbol"]).unwrap()[..] else {
+            panic!("expected a value per name");
+        };
+        assert_eq!(symbol.unwrap().as_str(), Some("XAU"));
+
+        let compressed = message(2)
+            .serialize_with(Encoding {
+                compression_threshold: Some(0),
+                ..Encoding::default()
+            })
+            .unwrap();
+        assert!(Message::fields(&compressed).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Merging the fields of one object into another, to apply a partial update
+//! onto the state it updates
+
+use super::{FieldValue, Fields, List, Object};
+
+/// What happens to a field both objects have
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MergeStrategy {
+    /// The other object's value replaces the value
+    Replace,
+    /// Objects on both sides are merged field by field, lists as the
+    /// [`ListMerge`] says and all other values replaced
+    DeepMerge(ListMerge),
+    /// The value stays, the other object only adds fields
+    KeepExisting,
+}
+
+/// What [`MergeStrategy::DeepMerge`] does with lists on both sides
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ListMerge {
+    /// The other object's list replaces the list
+    Replace,
+    /// The other object's elements are appended
+    Concatenate,
+    /// The other object's elements which aren't in the list yet are appended
+    ConcatenateUnique,
+}
+
+impl Object {
+    /// Merges the fields of `other` into the object, fields only `other` has
+    /// are appended in its order
+    pub(crate) fn merge(&mut self, other: &Object, strategy: MergeStrategy) {
+        merge_fields(&mut self.0, &other.0, strategy);
+    }
+}
+
+fn merge_fields(fields: &mut Fields, other: &Fields, strategy: MergeStrategy) {
+    for (name, value) in other {
+        let Some(existing) = fields.get_mut(name) else {
+            fields.insert(name.clone(), value.clone());
+            continue;
+        };
+        match (strategy, existing, value) {
+            (MergeStrategy::KeepExisting, _, _) => {}
+            (
+                MergeStrategy::DeepMerge(_),
+                FieldValue::Object(Object(existing)),
+                FieldValue::Object(Object(other)),
+            ) => merge_fields(existing, other, strategy),
+            (
+                MergeStrategy::DeepMerge(
+                    lists @ (ListMerge::Concatenate | ListMerge::ConcatenateUnique),
+                ),
+                FieldValue::List(existing),
+                FieldValue::List(other),
+            ) => {
+                // Nothing to append, the list keeps its type even when empty
+                if other.is_empty() {
+                    continue;
+                }
+                let mut values = std::mem::replace(existing, List::Mixed(vec![])).into_values();
+                for value in other.clone().into_values() {
+                    if lists == ListMerge::Concatenate || !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                *existing = List::from_values(values);
+            }
+            (_, existing, value) => *existing = value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::FieldName;
+
+    fn object(fields: impl IntoIterator<Item = (&'static str, FieldValue)>) -> Object {
+        Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (FieldName::from(name), value))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn merges_objects() {
+        let account = object([
+            ("balance", FieldValue::Integer(100)),
+            ("orders", FieldValue::List(List::Integers(vec![1, 2]))),
+            (
+                "limits",
+                FieldValue::Object(object([
+                    ("daily", FieldValue::Integer(10)),
+                    ("single", FieldValue::Integer(5)),
+                ])),
+            ),
+        ]);
+        let update = object([
+            ("balance", FieldValue::Integer(90)),
+            ("orders", FieldValue::List(List::Integers(vec![2, 3]))),
+            (
+                "limits",
+                FieldValue::Object(object([("daily", FieldValue::Integer(20))])),
+            ),
+            ("frozen", FieldValue::Bool(false)),
+        ]);
+
+        let mut replaced = account.clone();
+        replaced.merge(&update, MergeStrategy::Replace);
+        assert_eq!(replaced.get("balance"), Some(&FieldValue::Integer(90)));
+        assert_eq!(
+            replaced.get("limits"),
+            Some(&FieldValue::Object(object([(
+                "daily",
+                FieldValue::Integer(20)
+            )])))
+        );
+        assert_eq!(replaced.get("frozen"), Some(&FieldValue::Bool(false)));
+
+        let mut kept = account.clone();
+        kept.merge(&update, MergeStrategy::KeepExisting);
+        assert_eq!(kept.get("balance"), Some(&FieldValue::Integer(100)));
+        assert_eq!(kept.get("frozen"), Some(&FieldValue::Bool(false)));
+
+        let mut merged = account.clone();
+        merged.merge(&update, MergeStrategy::DeepMerge(ListMerge::Concatenate));
+        let limits = object([
+            ("daily", FieldValue::Integer(20)),
+            ("single", FieldValue::Integer(5)),
+        ]);
+        assert_eq!(merged.get("limits"), Some(&FieldValue::Object(limits)));
+        assert_eq!(
+            merged.get("orders"),
+            Some(&FieldValue::List(List::Integers(vec![1, 2, 2, 3])))
+        );
+
+        let mut merged = account.clone();
+        merged.merge(
+            &update,
+            MergeStrategy::DeepMerge(ListMerge::ConcatenateUnique),
+        );
+        assert_eq!(
+            merged.get("orders"),
+            Some(&FieldValue::List(List::Integers(vec![1, 2, 3])))
+        );
+
+        let mut merged = account;
+        merged.merge(&update, MergeStrategy::DeepMerge(ListMerge::Replace));
+        assert_eq!(
+            merged.get("orders"),
+            Some(&FieldValue::List(List::Integers(vec![2, 3])))
+        );
+        let names: Vec<_> = merged.0.keys().map(FieldName::as_str).collect();
+        assert_eq!(names, ["balance", "orders", "limits", "frozen"]);
+    }
+}
@@ -0,0 +1,175 @@
+//! Reads large lists of objects on the rayon thread pool, behind the
+//! `parallel` feature
+//!
+//! Since version 3 every field value carries its length, so the objects of a
+//! list can be told apart by skipping from field to field without reading a
+//! single value. The objects are then read on the pool and collected in their
+//! order, the error of the first object which doesn't read wins as it would
+//! reading them one by one.
+
+use rayon::prelude::*;
+
+use super::{
+    Deserializable, DeserializeError, Encoding, Object, VERSION3, deserialize_field_count,
+    deserialize_length, eof,
+};
+
+/// Lists of fewer objects are read on the calling thread, below that splitting
+/// them costs more than it saves
+const MIN_PARALLEL_OBJECTS: usize = 4096;
+
+/// Whether a list of `count` objects is read in parallel, which takes a
+/// version whose encoding tells where the objects end
+pub(crate) fn splits(count: usize, encoding: Encoding) -> bool {
+    encoding.version >= VERSION3 && count >= MIN_PARALLEL_OBJECTS
+}
+
+/// The `count` objects of a list, see [`splits`]
+pub(crate) fn deserialize_objects(
+    bytes: &[u8],
+    count: usize,
+    encoding: Encoding,
+) -> Result<(Vec<Object>, &[u8]), DeserializeError> {
+    let encoding = encoding.reserve(count, std::mem::size_of::<Object>(), bytes)?;
+
+    let mut ends = Vec::with_capacity(count);
+    let mut end = 0;
+    while ends.len() < count {
+        let Ok(length) = object_length(&bytes[end..], encoding) else {
+            break;
+        };
+        end += length;
+        ends.push(end);
+    }
+
+    let results: Vec<_> = ends
+        .par_iter()
+        .enumerate()
+        .map(|(i, &end)| {
+            let start = if i == 0 { 0 } else { ends[i - 1] };
+            Object::deserialize_with(&bytes[start..end], None, encoding)
+                .map(|(object, _)| object)
+                .map_err(|e| e.followed_by(&bytes[end..]).within(format!("[{}]", i)))
+        })
+        .collect();
+    let mut objects: Vec<_> = results.into_iter().collect::<Result<_, _>>()?;
+
+    // From an object whose length can't be told on, the objects are read one by
+    // one, so it fails as it would otherwise
+    let mut bytes = &bytes[end..];
+    for i in objects.len()..count {
+        let (object, next_bytes) = Object::deserialize_with(bytes, None, encoding)
+            .map_err(|e| e.within(format!("[{}]", i)))?;
+        objects.push(object);
+        bytes = next_bytes;
+    }
+    Ok((objects, bytes))
+}
+
+/// Bytes of the object at the start of `bytes`, from the lengths of its field
+/// names and values
+///
+/// [Field Count (2 bytes)][Name][Type (1 byte)][Length][Value]...
+fn object_length(bytes: &[u8], encoding: Encoding) -> Result<usize, DeserializeError> {
+    let (count, mut rest) = deserialize_field_count(bytes, encoding)?;
+    for _ in 0..count {
+        let name_length = match encoding.field_ids {
+            true => std::mem::size_of::<u16>(),
+            false => {
+                let length = *rest.first().ok_or_else(|| eof("u8 (name length)", rest))?;
+                1 + length as usize
+            }
+        };
+        let value = rest
+            .get(name_length + 1..)
+            .ok_or_else(|| eof("field name and type", rest))?;
+        let (length, value) = deserialize_length(value, "value length")?;
+        rest = value
+            .get(length..)
+            .ok_or_else(|| eof(format!("value of {} bytes", length), value))?;
+    }
+    Ok(bytes.len() - rest.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{FieldName, FieldValue, List, Serializable};
+
+    fn objects(count: i64) -> Vec<Object> {
+        (0..count)
+            .map(|i| {
+                Object(
+                    [
+                        (FieldName::from("id"), FieldValue::Integer(i)),
+                        (FieldName::from("venue"), "XGAL".into()),
+                    ]
+                    .into(),
+                )
+            })
+            .collect()
+    }
+
+    /// The objects and the same bytes read one by one
+    fn read_both(
+        bytes: &[u8],
+        count: usize,
+    ) -> (
+        Result<Vec<Object>, DeserializeError>,
+        Result<Vec<Object>, DeserializeError>,
+    ) {
+        let encoding = Encoding {
+            version: VERSION3,
+            ..Encoding::default()
+        };
+        let resolve = |e: DeserializeError| e.resolve(bytes.len(), 0);
+        let parallel = deserialize_objects(bytes, count, encoding)
+            .map(|(objects, _)| objects)
+            .map_err(resolve);
+        let sequential = Vec::<Object>::deserialize_with(bytes, Some(count), encoding)
+            .map(|(objects, _)| objects)
+            .map_err(resolve);
+        (parallel, sequential)
+    }
+
+    #[test]
+    fn reads_objects_in_order() {
+        let encoding = Encoding {
+            version: VERSION3,
+            ..Encoding::default()
+        };
+        let list = List::Objects(objects(5_000));
+        let bytes = list.serialize_with(encoding).unwrap();
+        assert!(splits(5_000, encoding));
+        let (deserialized_list, rest) = List::deserialize_with(&bytes, None, encoding).unwrap();
+        assert_eq!(deserialized_list, list);
+        assert!(rest.is_empty());
+
+        // [Element Type (1 byte)][Element Count (2 bytes)]
+        let objects = &bytes[3..];
+        let (parallel, sequential) = read_both(objects, 5_000);
+        assert_eq!(parallel.unwrap(), sequential.unwrap());
+
+        // The first object which doesn't read wins, though a later one can't
+        // even be told apart
+        let mut damaged = objects.to_vec();
+        let venues: Vec<_> = damaged
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == b"XGAL")
+            .map(|(i, _)| i)
+            .collect();
+        damaged[venues[3_000]] = 0xFF;
+        damaged.truncate(venues[4_000]);
+        let (parallel, sequential) = read_both(&damaged, 5_000);
+        let error = parallel.unwrap_err();
+        assert_eq!(error, sequential.unwrap_err());
+        assert_eq!(error.path, "[3000].venue");
+
+        damaged[venues[3_000]] = b'X';
+        let (parallel, sequential) = read_both(&damaged, 5_000);
+        let error = parallel.unwrap_err();
+        assert_eq!(error, sequential.unwrap_err());
+        assert_eq!(error.path, "[4000].venue");
+    }
+}
@@ -0,0 +1,375 @@
+//! Python extension module `galacticbuf`, built with `maturin build` which
+//! enables the `python` feature
+//!
+//! `dumps(dict) -> bytes` serializes the items of a dict as the fields of an
+//! untyped message and `loads(bytes) -> dict` reads one back. Values map to
+//! Python's own types:
+//!
+//! | galacticbuf       | Python                                   |
+//! |-------------------|------------------------------------------|
+//! | integer           | `int`, 64 bits                           |
+//! | float             | `float`                                  |
+//! | string            | `str`                                    |
+//! | bool              | `bool`                                   |
+//! | null              | `None`                                   |
+//! | list of any type  | `list`                                   |
+//! | array             | `list`, read back as a list              |
+//! | object            | `dict` with `str` keys                   |
+//! | UUID              | `uuid.UUID`                              |
+//! | decimal           | `decimal.Decimal`                        |
+//! | date              | `datetime.date`, years 1 to 9999         |
+//! | duration          | `datetime.timedelta`, in microseconds    |
+//! | int128            | `int`, 65 to 128 bits                    |
+//! | bigint            | `int`, beyond 128 bits                   |
+//! | map               | `dict` with other keys, e.g. `int`       |
+//! | enum              | `(discriminant, payload)` tuple          |
+//! | unknown type      | `None`                                   |
+//!
+//! An empty dict is an object. Values without a mapping, or ones which don't
+//! fit, e.g. a negative `timedelta`, raise `ValueError` with the path to them.
+
+use std::time::Duration;
+
+use pyo3::{
+    IntoPyObjectExt,
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple},
+};
+
+use super::{
+    Array, ConvertError, Deserializable, EnumValue, FieldName, FieldValue, Fields, List, Map,
+    Message, MessageBuilder, Object, Serializable, StringValue,
+};
+use crate::{date::Date, decimal::Decimal};
+
+/// `datetime.date.toordinal()` of 1970-01-01
+const EPOCH_ORDINAL: i64 = 719_163;
+
+#[pymodule]
+fn galacticbuf(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(dumps, module)?)?;
+    module.add_function(wrap_pyfunction!(loads, module)?)?;
+    Ok(())
+}
+
+/// Serializes the items of the dict as an untyped message
+#[pyfunction]
+fn dumps<'py>(fields: &Bound<'py, PyDict>) -> PyResult<Bound<'py, PyBytes>> {
+    let mut builder = MessageBuilder::new();
+    for (name, value) in fields.iter() {
+        let name = name
+            .extract::<String>()
+            .map_err(|_| ConvertError::unsupported("field names must be strings"))?;
+        let value = from_python(&value).map_err(|e| e.within(&name))?;
+        builder = builder.field(&name, value);
+    }
+    let bytes = builder
+        .build()
+        .and_then(|message| message.serialize())
+        .map_err(ConvertError::from)?;
+    Ok(PyBytes::new(fields.py(), &bytes))
+}
+
+/// Fields of the one message the bytes hold
+#[pyfunction]
+fn loads<'py>(py: Python<'py>, bytes: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    let (message, rest) =
+        Message::deserialize(bytes, None).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if !rest.is_empty() {
+        return Err(PyValueError::new_err(format!(
+            "{} bytes following the message",
+            rest.len()
+        )));
+    }
+    fields_to_python(py, &message.body)
+}
+
+fn fields_to_python<'py>(py: Python<'py>, fields: &Fields) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, value) in fields {
+        dict.set_item(name.as_str(), to_python(py, value)?)?;
+    }
+    Ok(dict)
+}
+
+fn to_python<'py>(py: Python<'py>, value: &FieldValue) -> PyResult<Bound<'py, PyAny>> {
+    match value {
+        FieldValue::Integer(integer) => integer.into_bound_py_any(py),
+        FieldValue::Float(float) => float.into_bound_py_any(py),
+        FieldValue::String(StringValue(string)) => string.into_bound_py_any(py),
+        FieldValue::Bool(boolean) => boolean.into_bound_py_any(py),
+        FieldValue::Null | FieldValue::Unknown(..) => Ok(py.None().into_bound(py)),
+        FieldValue::List(list) => {
+            let values = list
+                .clone()
+                .into_values()
+                .iter()
+                .map(|value| to_python(py, value))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, values)?.into_bound_py_any(py)
+        }
+        FieldValue::Array(Array(elements)) => {
+            let values = elements
+                .iter()
+                .map(|value| to_python(py, value))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, values)?.into_bound_py_any(py)
+        }
+        FieldValue::Object(Object(fields)) => fields_to_python(py, fields)?.into_bound_py_any(py),
+        FieldValue::Uuid(uuid) => {
+            let arguments = PyDict::new(py);
+            arguments.set_item("bytes", PyBytes::new(py, uuid))?;
+            py.import("uuid")?
+                .getattr("UUID")?
+                .call((), Some(&arguments))
+        }
+        FieldValue::Decimal(decimal) => py
+            .import("decimal")?
+            .getattr("Decimal")?
+            .call1((decimal.to_string(),)),
+        FieldValue::Date(date) => py
+            .import("datetime")?
+            .getattr("date")?
+            .call_method1("fromordinal", (date.days() as i64 + EPOCH_ORDINAL,)),
+        FieldValue::Duration(duration) => duration.into_bound_py_any(py),
+        FieldValue::Int128(integer) => integer.into_bound_py_any(py),
+        FieldValue::BigInt(integer) => integer.into_bound_py_any(py),
+        FieldValue::Map(Map(entries)) => {
+            let dict = PyDict::new(py);
+            for (key, value) in entries {
+                dict.set_item(to_python(py, key)?, to_python(py, value)?)?;
+            }
+            dict.into_bound_py_any(py)
+        }
+        FieldValue::Enum(EnumValue {
+            discriminant,
+            payload,
+        }) => {
+            let payload = match payload {
+                Some(payload) => to_python(py, payload)?,
+                None => py.None().into_bound(py),
+            };
+            PyTuple::new(py, [discriminant.into_bound_py_any(py)?, payload])?.into_bound_py_any(py)
+        }
+    }
+}
+
+fn from_python(value: &Bound<'_, PyAny>) -> Result<FieldValue, ConvertError> {
+    let py = value.py();
+    if value.is_none() {
+        return Ok(FieldValue::Null);
+    }
+    // bool is a subclass of int
+    if value.is_instance_of::<PyBool>() {
+        return Ok(FieldValue::Bool(value.extract()?));
+    }
+    if value.is_instance_of::<PyInt>() {
+        if let Ok(integer) = value.extract() {
+            return Ok(FieldValue::Integer(integer));
+        }
+        if let Ok(integer) = value.extract() {
+            return Ok(FieldValue::Int128(integer));
+        }
+        return Ok(FieldValue::BigInt(value.extract()?));
+    }
+    if value.is_instance_of::<PyFloat>() {
+        return Ok(FieldValue::Float(value.extract()?));
+    }
+    if value.is_instance_of::<PyString>() {
+        return Ok(FieldValue::String(StringValue(value.extract()?)));
+    }
+    if value.is_instance(&py.import("uuid")?.getattr("UUID")?)? {
+        let uuid = value.getattr("bytes")?.extract()?;
+        return Ok(FieldValue::Uuid(uuid));
+    }
+    if value.is_instance(&py.import("decimal")?.getattr("Decimal")?)? {
+        return Ok(FieldValue::Decimal(decimal_from_python(value)?));
+    }
+    let datetime = py.import("datetime")?;
+    // datetime is a subclass of date
+    if value.is_instance(&datetime.getattr("datetime")?)? {
+        return Err(ConvertError::unsupported(
+            "datetime has no galacticbuf type, only its date does",
+        ));
+    }
+    if value.is_instance(&datetime.getattr("date")?)? {
+        let ordinal = value.call_method0("toordinal")?.extract::<i64>()?;
+        let days = i32::try_from(ordinal - EPOCH_ORDINAL).expect("years 1 to 9999 fit");
+        return Ok(FieldValue::Date(Date::from_days(days)));
+    }
+    if value.is_instance(&datetime.getattr("timedelta")?)? {
+        let duration = value
+            .extract::<Duration>()
+            .map_err(|_| ConvertError::unsupported("durations can't be negative"))?;
+        return Ok(FieldValue::Duration(duration));
+    }
+    if let Ok(dict) = value.cast::<PyDict>() {
+        return dict_from_python(dict);
+    }
+    if let Ok(list) = value.cast::<PyList>() {
+        let values = list
+            .iter()
+            .enumerate()
+            .map(|(i, value)| from_python(&value).map_err(|e| e.within(format!("[{}]", i))))
+            .collect::<Result<_, _>>()?;
+        return Ok(FieldValue::List(List::from_values(values)));
+    }
+    if let Ok(tuple) = value.cast::<PyTuple>() {
+        let Ok((discriminant, payload)) = tuple.extract::<(u16, Bound<'_, PyAny>)>() else {
+            return Err(ConvertError::unsupported(
+                "tuples are enums of a 16 bit discriminant and a payload",
+            ));
+        };
+        let payload = match from_python(&payload).map_err(|e| e.within("payload"))? {
+            FieldValue::Null => None,
+            payload => Some(Box::new(payload)),
+        };
+        return Ok(FieldValue::Enum(EnumValue {
+            discriminant,
+            payload,
+        }));
+    }
+    Err(ConvertError::unsupported(format!(
+        "{} has no galacticbuf type",
+        value.get_type().name()?
+    )))
+}
+
+/// Object when all keys are strings, map otherwise
+fn dict_from_python(dict: &Bound<'_, PyDict>) -> Result<FieldValue, ConvertError> {
+    if dict
+        .keys()
+        .iter()
+        .all(|key| key.is_instance_of::<PyString>())
+    {
+        let mut fields = Fields::new();
+        for (name, value) in dict.iter() {
+            let name = name.extract::<String>()?;
+            let value = from_python(&value).map_err(|e| e.within(&name))?;
+            fields.insert(FieldName::from(name.as_str()), value);
+        }
+        return Ok(FieldValue::Object(Object(fields)));
+    }
+    let mut entries = vec![];
+    for (key, value) in dict.iter() {
+        let key = from_python(&key)?;
+        let value = from_python(&value).map_err(|e| e.within(format!("[{}]", key.type_name())))?;
+        entries.push((key, value));
+    }
+    Ok(FieldValue::Map(Map(entries)))
+}
+
+/// Exact value of the `decimal.Decimal` out of its sign, digits and exponent
+fn decimal_from_python(value: &Bound<'_, PyAny>) -> Result<Decimal, ConvertError> {
+    let (sign, digits, exponent) =
+        value
+            .call_method0("as_tuple")?
+            .extract::<(u8, Vec<u8>, Bound<'_, PyAny>)>()?;
+    let exponent = exponent
+        .extract::<i8>()
+        .map_err(|_| ConvertError::unsupported("decimal exponent must fit into 8 bits"))?;
+    let magnitude = digits
+        .iter()
+        .try_fold(0i64, |magnitude, &digit| {
+            magnitude.checked_mul(10)?.checked_add(digit as i64)
+        })
+        .ok_or_else(|| ConvertError::unsupported("decimal has too many digits"))?;
+    let mantissa = if sign == 1 { -magnitude } else { magnitude };
+    Ok(Decimal::new(mantissa, exponent))
+}
+
+/// Failed attribute lookups and extractions, e.g. of a `uuid.UUID` subclass
+/// without its bytes
+impl From<PyErr> for ConvertError {
+    fn from(error: PyErr) -> Self {
+        ConvertError::unsupported(error.to_string())
+    }
+}
+
+/// Python values which aren't galacticbuf values are raised as `ValueError`
+impl From<ConvertError> for PyErr {
+    fn from(error: ConvertError) -> Self {
+        PyValueError::new_err(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn eval<'py>(py: Python<'py>, code: &str) -> Bound<'py, PyAny> {
+        let code = CString::new(code).unwrap();
+        let globals = PyDict::new(py);
+        for module in ["datetime", "decimal", "uuid"] {
+            globals
+                .set_item(module, py.import(module).unwrap())
+                .unwrap();
+        }
+        py.eval(&code, Some(&globals), None).unwrap()
+    }
+
+    #[test]
+    fn python_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let fields = eval(
+                py,
+                "{'id': 7, 'price': decimal.Decimal('12.50'), 'ratio': 0.5, 'active': True, \
+                 'note': None, 'tags': ['ioc', 'post'], 'account': {'user': 'ada'}, \
+                 'order_id': uuid.UUID('67e55044-10b1-426f-9247-bb680e5fe0c8'), \
+                 'depth': {100: 3, 101: 5}, 'side': (1, None), 'fill': (2, {'qty': 4}), \
+                 'settles': datetime.date(2024, 3, 5), 'ttl': datetime.timedelta(seconds=90), \
+                 'volume': 2**64, 'supply': -2**200}",
+            );
+            let fields = fields.cast::<PyDict>().unwrap();
+            let bytes = dumps(fields).unwrap();
+            let loaded = loads(py, bytes.as_bytes()).unwrap();
+            assert!(loaded.eq(fields).unwrap(), "{}", loaded);
+            assert_eq!(
+                loaded
+                    .get_item("price")
+                    .unwrap()
+                    .unwrap()
+                    .str()
+                    .unwrap()
+                    .to_string(),
+                "12.50"
+            );
+        });
+    }
+
+    #[test]
+    fn python_errors() {
+        Python::initialize();
+        Python::attach(|py| {
+            for (code, error) in [
+                (
+                    "{'at': {'when': object()}}",
+                    "at.when: object has no galacticbuf type",
+                ),
+                ("{'side': (1, 2, 3)}", "side: tuples are enums"),
+                ("{1: 2}", "field names must be strings"),
+                (
+                    "{'ttl': -datetime.timedelta(1)}",
+                    "ttl: durations can't be negative",
+                ),
+                (
+                    "{'at': datetime.datetime(2024, 3, 5)}",
+                    "at: datetime has no galacticbuf type",
+                ),
+            ] {
+                let fields = eval(py, code);
+                let message = dumps(fields.cast::<PyDict>().unwrap())
+                    .unwrap_err()
+                    .value(py)
+                    .to_string();
+                assert!(message.starts_with(error), "{}", message);
+            }
+            let message = loads(py, &[9]).unwrap_err().value(py).to_string();
+            assert!(message.contains("expected version"), "{}", message);
+        });
+    }
+}
@@ -0,0 +1,353 @@
+//! Messages read one after the other from a stream, e.g. a capture file or a
+//! TCP connection
+//!
+//! The reader takes the header first and then exactly the bytes its length
+//! announces, so it never reads past the message it returns. Where the bytes
+//! arrive in chunks of their own, e.g. from a non-blocking socket, the
+//! [`Decoder`] takes them as they come instead.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Read},
+};
+
+use super::{Deserializable, DeserializeError, DeserializeErrorKind, Encoding, Header, Message};
+
+/// Iterator over the messages of a stream, ends at the end of the stream
+/// between two messages or after the first error
+pub(crate) struct MessageReader<R> {
+    reader: R,
+    encoding: Encoding,
+    /// Bytes of the current message, kept to read the next one into
+    frame: Vec<u8>,
+    failed: bool,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        MessageReader::with_encoding(reader, Encoding::default())
+    }
+
+    /// Reader applying the limits and policies of `encoding` to every message
+    pub(crate) fn with_encoding(reader: R, encoding: Encoding) -> Self {
+        MessageReader {
+            reader,
+            encoding,
+            frame: vec![],
+            failed: false,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Bytes of the next message without decoding its body, e.g. to decrypt
+    /// it, `None` at the end of the stream
+    pub(crate) fn read_frame(&mut self) -> Result<Option<&[u8]>, ReadError> {
+        self.frame.clear();
+        let mut version = [0u8];
+        if !read_start(&mut self.reader, &mut version)? {
+            return Ok(None);
+        }
+
+        let header_size = Header::size(version[0]);
+        self.frame.resize(header_size, 0);
+        self.frame[0] = version[0];
+        self.reader.read_exact(&mut self.frame[1..])?;
+        let length = frame_length(&self.frame, self.encoding)?;
+        self.frame.resize(length, 0);
+        self.reader.read_exact(&mut self.frame[header_size..])?;
+        Ok(Some(&self.frame))
+    }
+
+    /// Next message, `None` at the end of the stream
+    pub(crate) fn read_message(&mut self) -> Result<Option<Message>, ReadError> {
+        let encoding = self.encoding;
+        let Some(frame) = self.read_frame()? else {
+            return Ok(None);
+        };
+        let (message, _) = Message::deserialize_with(frame, None, encoding)
+            .map_err(|e| e.resolve(frame.len(), 0))?;
+        Ok(Some(message))
+    }
+}
+
+/// Fills `bytes` from the reader, `false` when the stream ended before the
+/// first of them and [`io::ErrorKind::UnexpectedEof`] when it ended later
+pub(super) fn read_start<R: Read>(reader: &mut R, bytes: &mut [u8]) -> io::Result<bool> {
+    loop {
+        match reader.read(bytes) {
+            Ok(0) => return Ok(bytes.is_empty()),
+            Ok(read) => {
+                reader.read_exact(&mut bytes[read..])?;
+                return Ok(true);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Bytes of the message starting with `header`, checked against the size limit
+/// before any of them are read
+///
+/// A length short of the header itself is left for the deserializer to report.
+pub(super) fn frame_length(header: &[u8], encoding: Encoding) -> Result<usize, DeserializeError> {
+    let (parsed, _) = Header::deserialize_with(header, None, encoding)?;
+    let length = parsed.length as usize;
+    let max = encoding.limits.max_message_size;
+    if length > max {
+        let kind = DeserializeErrorKind::LimitExceeded {
+            limit: "message size",
+            value: length,
+            max,
+        };
+        return Err(DeserializeError::at(kind, 0));
+    }
+    Ok(length.max(Header::size(parsed.version)))
+}
+
+/// Push parser for bytes arriving in chunks of any size, keeps what it has of
+/// the next message until the rest arrives
+///
+/// After an error the stream is out of step, the decoder has to be dropped
+/// along with the connection.
+pub(crate) struct Decoder {
+    encoding: Encoding,
+    buffer: Vec<u8>,
+    state: DecoderState,
+}
+
+enum DecoderState {
+    /// Waiting for the header of the next message
+    Header,
+    /// Header read, waiting for the rest of a message of this many bytes
+    Body { length: usize },
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Decoder::with_encoding(Encoding::default())
+    }
+
+    /// Decoder applying the limits and policies of `encoding` to every message
+    pub(crate) fn with_encoding(encoding: Encoding) -> Self {
+        Decoder {
+            encoding,
+            buffer: vec![],
+            state: DecoderState::Header,
+        }
+    }
+
+    /// Takes the next chunk of the stream, returns the messages it completes
+    pub(crate) fn feed(&mut self, bytes: &[u8]) -> Result<Vec<Message>, DeserializeError> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = vec![];
+        let mut start = 0;
+        loop {
+            let available = &self.buffer[start..];
+            match self.state {
+                DecoderState::Header => {
+                    let Some(version) = available.first() else {
+                        break;
+                    };
+                    if available.len() < Header::size(*version) {
+                        break;
+                    }
+                    let length = frame_length(available, self.encoding)?;
+                    self.state = DecoderState::Body { length };
+                }
+                DecoderState::Body { length } => {
+                    let Some(frame) = available.get(..length) else {
+                        break;
+                    };
+                    let (message, _) = Message::deserialize_with(frame, None, self.encoding)
+                        .map_err(|e| e.resolve(frame.len(), 0))?;
+                    messages.push(message);
+                    start += length;
+                    self.state = DecoderState::Header;
+                }
+            }
+        }
+        self.buffer.drain(..start);
+        Ok(messages)
+    }
+
+    /// Bytes of an incomplete message held back, 0 between messages
+    pub(crate) fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+impl<R: Read> Iterator for MessageReader<R> {
+    type Item = Result<Message, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let message = self.read_message();
+        self.failed = message.is_err();
+        message.transpose()
+    }
+}
+
+/// Stream failing, or a message which doesn't deserialize, with offsets from
+/// the start of the message
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    Io(io::Error),
+    Deserialize(DeserializeError),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{}", error),
+            ReadError::Deserialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
+    }
+}
+
+impl From<DeserializeError> for ReadError {
+    fn from(error: DeserializeError) -> Self {
+        ReadError::Deserialize(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{
+        DeserializeLimits, FieldValue, MessageBuilder, MessageType, Serializable,
+    };
+
+    /// Reader handing out one byte per call, like a slow connection
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            match buf.first_mut() {
+                Some(byte) => *byte = *first,
+                None => return Ok(0),
+            }
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::new([("price", FieldValue::Integer(5))]).unwrap(),
+            MessageBuilder::new()
+                .with_type(MessageType(7))
+                .with_sequence(2)
+                .field("side", FieldValue::Bool(true))
+                .build()
+                .unwrap(),
+            Message::new([]).unwrap(),
+        ]
+    }
+
+    fn stream(messages: &[Message]) -> Vec<u8> {
+        messages
+            .iter()
+            .flat_map(|message| message.serialize().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reads_messages_in_order() {
+        let stream = stream(&messages());
+        let read: Vec<Message> = MessageReader::new(&stream[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, messages());
+
+        let read: Vec<Message> = MessageReader::new(Trickle(&stream))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, messages());
+
+        assert!(MessageReader::new(&[][..]).next().is_none());
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let mut stream = stream(&messages());
+        stream.truncate(stream.len() - 1);
+        let mut reader = MessageReader::new(&stream[..]);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        let Some(Err(ReadError::Io(error))) = reader.next() else {
+            panic!("expected the truncated message to fail");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+
+        // The announced length is checked before anything is read for it
+        let encoding = Encoding {
+            limits: DeserializeLimits {
+                max_message_size: 8,
+                ..DeserializeLimits::default()
+            },
+            ..Encoding::default()
+        };
+        let stream = self::stream(&messages()[1..]);
+        let mut reader = MessageReader::with_encoding(&stream[..], encoding);
+        let Some(Err(ReadError::Deserialize(error))) = reader.next() else {
+            panic!("expected the message to exceed the limit");
+        };
+        assert!(matches!(
+            error.kind,
+            DeserializeErrorKind::LimitExceeded {
+                limit: "message size",
+                ..
+            }
+        ));
+        assert_eq!(reader.into_inner().len(), stream.len() - 18);
+    }
+
+    #[test]
+    fn decoder_takes_any_chunks() {
+        let stream = stream(&messages());
+        for chunk_size in [1, 3, 18, stream.len()] {
+            let mut decoder = Decoder::new();
+            let mut decoded = vec![];
+            for chunk in stream.chunks(chunk_size) {
+                decoded.extend(decoder.feed(chunk).unwrap());
+            }
+            assert_eq!(decoded, messages());
+            assert_eq!(decoder.buffered(), 0);
+        }
+
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(&stream[..20]).unwrap(), messages()[..1]);
+        assert!(decoder.buffered() > 0);
+        assert_eq!(decoder.feed(&stream[20..]).unwrap(), messages()[1..]);
+
+        let error = Decoder::new().feed(&[0x07; 18]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::VersionMismatch { found: 0x07 }
+        );
+    }
+}
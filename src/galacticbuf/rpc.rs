@@ -0,0 +1,358 @@
+//! Request/response calls over messages, matched up by a correlation ID
+//!
+//! A request is a message with a `method`, a `correlation_id` and the fields of
+//! its payload in a `payload` object. The response repeats the correlation ID
+//! and adds a `status`, error responses explain themselves in an `error`
+//! string. The type and sequence number of the payload travel in the header of
+//! the envelope.
+
+use std::{collections::HashMap, fmt::Display};
+
+use super::{
+    Deserializable, DeserializeError, Encoding, FieldAccess, FieldValue, Header, Message,
+    MessageBuilder, Object, Serializable, SerializeError, StringValue,
+};
+
+/// Outcome of a call, with the meaning of the HTTP status of the same number
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct RpcStatus(pub(crate) u16);
+
+impl RpcStatus {
+    pub(crate) const OK: RpcStatus = RpcStatus(200);
+    /// Request is malformed or its payload doesn't fit the method
+    pub(crate) const BAD_REQUEST: RpcStatus = RpcStatus(400);
+    pub(crate) const UNAUTHORIZED: RpcStatus = RpcStatus(401);
+    /// No method of that name
+    pub(crate) const NOT_FOUND: RpcStatus = RpcStatus(404);
+    pub(crate) const INTERNAL_ERROR: RpcStatus = RpcStatus(500);
+    pub(crate) const UNAVAILABLE: RpcStatus = RpcStatus(503);
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct RpcRequest {
+    pub(crate) method: String,
+    pub(crate) correlation_id: i64,
+    pub(crate) payload: Message,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct RpcResponse {
+    /// Correlation ID of the request answered
+    pub(crate) correlation_id: i64,
+    pub(crate) status: RpcStatus,
+    /// What went wrong, `None` for successful calls
+    pub(crate) error: Option<String>,
+    pub(crate) payload: Message,
+}
+
+impl RpcRequest {
+    pub(crate) fn new(method: &str, correlation_id: i64, payload: Message) -> Self {
+        RpcRequest {
+            method: String::from(method),
+            correlation_id,
+            payload,
+        }
+    }
+
+    /// Successful response to the request
+    pub(crate) fn reply(&self, payload: Message) -> RpcResponse {
+        RpcResponse {
+            correlation_id: self.correlation_id,
+            status: RpcStatus::OK,
+            error: None,
+            payload,
+        }
+    }
+
+    /// Error response to the request, with an empty payload
+    pub(crate) fn reply_error(&self, status: RpcStatus, error: impl Display) -> RpcResponse {
+        RpcResponse::error(self.correlation_id, status, error)
+    }
+}
+
+impl RpcResponse {
+    /// Error response with an empty payload
+    pub(crate) fn error(correlation_id: i64, status: RpcStatus, error: impl Display) -> Self {
+        RpcResponse {
+            correlation_id,
+            status,
+            error: Some(error.to_string()),
+            payload: Message::new([]).expect("empty message fits into the wire format"),
+        }
+    }
+
+    /// [`RpcStatus::BAD_REQUEST`] response to a message which isn't a valid
+    /// request, answering its correlation ID when it has one and 0 otherwise
+    pub(crate) fn rejecting(message: &Message, error: &DeserializeError) -> Self {
+        let correlation_id = message.get_i64("correlation_id").unwrap_or(0);
+        RpcResponse::error(correlation_id, RpcStatus::BAD_REQUEST, error)
+    }
+
+    pub(crate) fn is_ok(&self) -> bool {
+        self.status == RpcStatus::OK
+    }
+}
+
+/// Envelope carrying the payload's fields in a `payload` object, with the
+/// payload's header and `fields` ahead of it
+fn envelope(payload: &Message, fields: Vec<(&str, FieldValue)>) -> Result<Message, SerializeError> {
+    fields
+        .into_iter()
+        .fold(builder(payload.header), |builder, (name, value)| {
+            builder.field(name, value)
+        })
+        .field("payload", FieldValue::Object(Object(payload.body.clone())))
+        .build()
+}
+
+/// Payload taken out of an envelope, see [`envelope`]
+fn payload(envelope: &Message) -> Result<Message, DeserializeError> {
+    let Object(fields) = envelope.get_object("payload")?;
+    fields
+        .iter()
+        .fold(builder(envelope.header), |builder, (name, value)| {
+            builder.field(name.as_str(), value.clone())
+        })
+        .build()
+        .map_err(|e| DeserializeError::invalid(e.to_string()).within("payload"))
+}
+
+/// Builder of a message with the version, type and sequence number of `header`
+fn builder(header: Header) -> MessageBuilder {
+    MessageBuilder::new()
+        .with_version(header.version)
+        .with_type(header.message_type)
+        .with_sequence(header.sequence)
+}
+
+impl TryFrom<&RpcRequest> for Message {
+    type Error = SerializeError;
+
+    fn try_from(request: &RpcRequest) -> Result<Self, Self::Error> {
+        envelope(
+            &request.payload,
+            vec![
+                ("method", request.method.as_str().into()),
+                ("correlation_id", request.correlation_id.into()),
+            ],
+        )
+    }
+}
+
+impl TryFrom<&Message> for RpcRequest {
+    type Error = DeserializeError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        Ok(RpcRequest {
+            method: String::from(message.get_str("method")?),
+            correlation_id: message.get_i64("correlation_id")?,
+            payload: payload(message)?,
+        })
+    }
+}
+
+impl TryFrom<&RpcResponse> for Message {
+    type Error = SerializeError;
+
+    fn try_from(response: &RpcResponse) -> Result<Self, Self::Error> {
+        let mut fields = vec![
+            ("correlation_id", response.correlation_id.into()),
+            ("status", i64::from(response.status.0).into()),
+        ];
+        if let Some(error) = &response.error {
+            fields.push(("error", FieldValue::String(StringValue(error.clone()))));
+        }
+        envelope(&response.payload, fields)
+    }
+}
+
+impl TryFrom<&Message> for RpcResponse {
+    type Error = DeserializeError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let status = message.get_i64("status")?;
+        let status = u16::try_from(status).map_err(|_| {
+            DeserializeError::invalid(format!("status {} is out of range", status)).within("status")
+        })?;
+        let error = match message.get("error") {
+            Some(_) => Some(String::from(message.get_str("error")?)),
+            None => None,
+        };
+        Ok(RpcResponse {
+            correlation_id: message.get_i64("correlation_id")?,
+            status: RpcStatus(status),
+            error,
+            payload: payload(message)?,
+        })
+    }
+}
+
+/// [Message with `method`, `correlation_id` and `payload` fields]
+impl Serializable for RpcRequest {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        Message::try_from(self)?.serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message with `method`, `correlation_id` and `payload` fields]
+impl Deserializable for RpcRequest {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        Ok((RpcRequest::try_from(&message)?, bytes))
+    }
+}
+
+/// [Message with `correlation_id`, `status`, `error` and `payload` fields]
+impl Serializable for RpcResponse {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        Message::try_from(self)?.serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message with `correlation_id`, `status`, `error` and `payload` fields]
+impl Deserializable for RpcResponse {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        Ok((RpcResponse::try_from(&message)?, bytes))
+    }
+}
+
+/// Calls sent and not answered yet, hands out correlation IDs and matches
+/// responses back to whatever the caller keeps for each call
+#[derive(Debug)]
+pub(crate) struct PendingCalls<T> {
+    next_id: i64,
+    pending: HashMap<i64, T>,
+}
+
+impl<T> PendingCalls<T> {
+    pub(crate) fn new() -> Self {
+        PendingCalls {
+            next_id: 1,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Request with the next correlation ID, `context` is handed back with
+    /// its response
+    pub(crate) fn call(&mut self, method: &str, payload: Message, context: T) -> RpcRequest {
+        let correlation_id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(correlation_id, context);
+        RpcRequest::new(method, correlation_id, payload)
+    }
+
+    /// Context of the call the response answers, `None` for responses to
+    /// calls which were never made or already answered
+    pub(crate) fn complete(&mut self, response: &RpcResponse) -> Option<T> {
+        self.pending.remove(&response.correlation_id)
+    }
+
+    /// Gives up on a call, e.g. when it timed out
+    pub(crate) fn cancel(&mut self, correlation_id: i64) -> Option<T> {
+        self.pending.remove(&correlation_id)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl<T> Default for PendingCalls<T> {
+    fn default() -> Self {
+        PendingCalls::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{DeserializeErrorKind, Fields, MessageType};
+
+    fn order() -> Message {
+        MessageBuilder::new()
+            .with_type(MessageType(4))
+            .with_sequence(17)
+            .field("price", FieldValue::Integer(105))
+            .field("side", FieldValue::String(StringValue(String::from("buy"))))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn calls_round_trip() {
+        let mut calls = PendingCalls::new();
+        let request = calls.call("place_order", order(), "first");
+        let bytes = request.serialize().unwrap();
+        let (read, rest) = RpcRequest::deserialize(&bytes, None).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(read, request);
+        assert_eq!(read.payload.message_type(), MessageType(4));
+        assert_eq!(read.payload.header.sequence, 17);
+
+        let accepted = Message::new([("id", FieldValue::Integer(9))]).unwrap();
+        let bytes = read.reply(accepted).serialize().unwrap();
+        let (response, _) = RpcResponse::deserialize(&bytes, None).unwrap();
+        assert!(response.is_ok());
+        assert_eq!(response.error, None);
+        assert_eq!(response.payload.get("id"), Some(&FieldValue::Integer(9)));
+
+        assert_eq!(calls.complete(&response), Some("first"));
+        // A response repeated or made up matches no call
+        assert_eq!(calls.complete(&response), None);
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn error_responses() {
+        let request = RpcRequest::new("cancel_order", 3, order());
+        let response = request.reply_error(RpcStatus::NOT_FOUND, "no order 12");
+        let bytes = response.serialize().unwrap();
+        let (read, _) = RpcResponse::deserialize(&bytes, None).unwrap();
+        assert_eq!(read, response);
+        assert!(!read.is_ok());
+        assert_eq!(read.error.as_deref(), Some("no order 12"));
+
+        // A request without a method is answered under its correlation ID
+        let message = Message::new([
+            ("correlation_id", FieldValue::Integer(8)),
+            ("payload", FieldValue::Object(Object(Fields::new()))),
+        ])
+        .unwrap();
+        let error = RpcRequest::try_from(&message).unwrap_err();
+        assert_eq!(error.to_string(), "method: missing field at byte 0");
+        let response = RpcResponse::rejecting(&message, &error);
+        assert_eq!(response.correlation_id, 8);
+        assert_eq!(response.status, RpcStatus::BAD_REQUEST);
+
+        let message = Message::new([
+            ("correlation_id", FieldValue::Integer(8)),
+            ("status", FieldValue::Integer(70_000)),
+            ("payload", FieldValue::Object(Object(Fields::new()))),
+        ])
+        .unwrap();
+        let error = RpcResponse::try_from(&message).unwrap_err();
+        assert!(matches!(error.kind, DeserializeErrorKind::Invalid(_)));
+        assert_eq!(error.path, "status");
+    }
+}
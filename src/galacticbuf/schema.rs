@@ -0,0 +1,1604 @@
+//! Schemas declaring the fields of messages and objects
+//!
+//! A schema is written in a small text format, by convention in `.gbs` files:
+//!
+//! ```text
+//! # Sides of the book
+//! enum Side {
+//!     buy = 0
+//!     sell = 1
+//! }
+//!
+//! message Order = 1 {
+//!     id: uuid
+//!     side: Side
+//!     price: decimal?
+//!     fills: list<Fill>
+//!     tags: map<string, string>?
+//! }
+//!
+//! object Fill {
+//!     quantity: integer
+//! }
+//! ```
+//!
+//! Field types are `integer`, `string`, `float`, `bool`, `uuid`, `decimal`,
+//! `date`, `duration`, `int128`, `bigint`, `any`, `list<T>`, `array<T, N>` of
+//! `N` elements of a type of a fixed size, `map<K, V>` with integer, string, uuid or decimal keys and
+//! the names of declared objects, messages and enums. Fields are required unless their type ends with `?`, an optional field
+//! may be missing or null. Fields the schema doesn't declare are allowed, so
+//! newer senders can add fields older readers don't know yet.
+//!
+//! A field may be given an ID after its type, e.g. `price: decimal? = 3`, so
+//! messages can be written with 2 byte field IDs in place of their names, see
+//! [`Schema::serialize_with_ids`]. Such messages can only be read by name with
+//! the schema at hand.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{
+    Array, Deserializable, DeserializeError, DeserializeErrorKind, Encoding, EnumType, EnumValue,
+    FIELD_IDS_FLAG, FINGERPRINT_SIZE, FieldName, FieldValue, Fields, Header, List, Map, Message,
+    MessageType, Object, Serializable, SerializeError, join_path, map_key,
+};
+
+/// Type of a field, see the module docs for its notation
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum FieldType {
+    Integer,
+    String,
+    Float,
+    Bool,
+    Uuid,
+    Decimal,
+    Date,
+    Duration,
+    Int128,
+    BigInt,
+    /// Any value, null included
+    Any,
+    List(Box<FieldType>),
+    /// Elements of a type of a fixed size, exactly as many as the length
+    Array(Box<FieldType>, u16),
+    Map(Box<FieldType>, Box<FieldType>),
+    /// Object with the fields of the named object or message
+    Object(String),
+    /// Variant of the named enum
+    Enum(String),
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Integer => write!(f, "integer"),
+            FieldType::String => write!(f, "string"),
+            FieldType::Float => write!(f, "float"),
+            FieldType::Bool => write!(f, "bool"),
+            FieldType::Uuid => write!(f, "uuid"),
+            FieldType::Decimal => write!(f, "decimal"),
+            FieldType::Date => write!(f, "date"),
+            FieldType::Duration => write!(f, "duration"),
+            FieldType::Int128 => write!(f, "int128"),
+            FieldType::BigInt => write!(f, "bigint"),
+            FieldType::Any => write!(f, "any"),
+            FieldType::List(element) => write!(f, "list<{}>", element),
+            FieldType::Array(element, length) => write!(f, "array<{}, {}>", element, length),
+            FieldType::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            FieldType::Object(name) | FieldType::Enum(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FieldSchema {
+    pub(crate) name: String,
+    pub(crate) field_type: FieldType,
+    /// Field has to be present and not null
+    pub(crate) required: bool,
+    /// Written in place of the name with field IDs, unique within the object
+    pub(crate) id: Option<u16>,
+}
+
+/// Fields of a message or of an object nested in one
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ObjectSchema {
+    pub(crate) name: String,
+    /// Type in the header of messages, `None` for objects
+    pub(crate) message_type: Option<MessageType>,
+    pub(crate) fields: Vec<FieldSchema>,
+}
+
+impl ObjectSchema {
+    pub(crate) fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+/// Messages, objects and enums of a schema file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Schema {
+    pub(crate) objects: Vec<ObjectSchema>,
+    pub(crate) enums: Vec<EnumType>,
+}
+
+/// Direction of renaming fields between their names and field IDs
+#[derive(Clone, Copy)]
+enum Naming {
+    Ids,
+    Names,
+}
+
+/// Schema file that doesn't parse or refers to undeclared types
+#[derive(Debug, PartialEq)]
+pub(crate) struct SchemaError {
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Field of a message not matching its schema
+#[derive(Debug, PartialEq)]
+pub(crate) struct Violation {
+    /// Path of the field, e.g. `fills[2].quantity`, empty for the message itself
+    pub(crate) path: String,
+    pub(crate) kind: ViolationKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ViolationKind {
+    Missing,
+    /// Required field is present, but null
+    Null,
+    WrongType {
+        expected: String,
+        found: String,
+    },
+    UnknownVariant {
+        enum_name: String,
+        discriminant: u16,
+    },
+    /// Array of another length than its type declares
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    /// Message type the schema declares no message for
+    UnknownMessageType(MessageType),
+}
+
+impl Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationKind::Missing => write!(f, "missing required field"),
+            ViolationKind::Null => write!(f, "required field is null"),
+            ViolationKind::WrongType { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ViolationKind::UnknownVariant {
+                enum_name,
+                discriminant,
+            } => write!(f, "unknown variant {} of {}", discriminant, enum_name),
+            ViolationKind::WrongLength { expected, found } => {
+                write!(f, "expected {} elements, found {}", expected, found)
+            }
+            ViolationKind::UnknownMessageType(message_type) => {
+                write!(f, "unknown message type {}", message_type.0)
+            }
+        }
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.path, self.kind)
+        }
+    }
+}
+
+/// Difference between two versions of a schema which can break a peer still
+/// on the other version
+#[derive(Debug, PartialEq)]
+pub(crate) struct BreakingChange {
+    /// Type or field changed, e.g. `Order` or `Order.price`
+    pub(crate) path: String,
+    pub(crate) kind: BreakingChangeKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum BreakingChangeKind {
+    /// Message, object or enum of the old schema missing from the new one
+    RemovedType,
+    ChangedMessageType {
+        old: Option<MessageType>,
+        new: Option<MessageType>,
+    },
+    RemovedRequiredField,
+    /// Field new readers require, which old writers don't send
+    AddedRequiredField,
+    /// Optional field made required
+    BecameRequired,
+    ChangedType {
+        old: FieldType,
+        new: FieldType,
+    },
+    ChangedFieldId {
+        old: u16,
+        new: Option<u16>,
+    },
+    RemovedVariant {
+        name: String,
+        discriminant: u16,
+    },
+}
+
+impl Display for BreakingChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message_type = |message_type: &Option<MessageType>| match message_type {
+            Some(message_type) => message_type.0.to_string(),
+            None => String::from("none"),
+        };
+        match self {
+            BreakingChangeKind::RemovedType => write!(f, "type removed"),
+            BreakingChangeKind::ChangedMessageType { old, new } => write!(
+                f,
+                "message type changed from {} to {}",
+                message_type(old),
+                message_type(new)
+            ),
+            BreakingChangeKind::RemovedRequiredField => write!(f, "required field removed"),
+            BreakingChangeKind::AddedRequiredField => write!(f, "required field added"),
+            BreakingChangeKind::BecameRequired => write!(f, "optional field made required"),
+            BreakingChangeKind::ChangedType { old, new } => {
+                write!(f, "type changed from {} to {}", old, new)
+            }
+            BreakingChangeKind::ChangedFieldId { old, new } => match new {
+                Some(new) => write!(f, "field ID changed from {} to {}", old, new),
+                None => write!(f, "field ID {} removed", old),
+            },
+            BreakingChangeKind::RemovedVariant { name, discriminant } => {
+                write!(f, "variant {} = {} removed", name, discriminant)
+            }
+        }
+    }
+}
+
+impl Display for BreakingChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+/// Canonical form of the schema, the one [`Schema::fingerprint`] hashes
+///
+/// Enums and objects are sorted by name and variants by discriminant, fields
+/// keep their order. Comments and formatting are dropped, so schemas which
+/// declare the same types come out the same.
+impl Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut enums: Vec<&EnumType> = self.enums.iter().collect();
+        enums.sort_by_key(|enum_type| enum_type.name());
+        for enum_type in enums {
+            writeln!(f, "enum {} {{", enum_type.name())?;
+            let mut variants: Vec<&(u16, String)> = enum_type.variants.iter().collect();
+            variants.sort_by_key(|(discriminant, _)| *discriminant);
+            for (discriminant, name) in variants {
+                writeln!(f, "    {} = {}", name, discriminant)?;
+            }
+            writeln!(f, "}}")?;
+        }
+        let mut objects: Vec<&ObjectSchema> = self.objects.iter().collect();
+        objects.sort_by_key(|object| object.name.as_str());
+        for object in objects {
+            match object.message_type {
+                Some(message_type) => {
+                    writeln!(f, "message {} = {} {{", object.name, message_type.0)?
+                }
+                None => writeln!(f, "object {} {{", object.name)?,
+            }
+            for field in &object.fields {
+                write!(f, "    {}: {}", field.name, field.field_type)?;
+                if !field.required {
+                    write!(f, "?")?;
+                }
+                if let Some(id) = field.id {
+                    write!(f, " = {}", id)?;
+                }
+                writeln!(f)?;
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Schema {
+    pub(crate) fn parse(source: &str) -> Result<Schema, SchemaError> {
+        Parser::new(source)?.schema()
+    }
+
+    /// Object or message by its name
+    pub(crate) fn object(&self, name: &str) -> Option<&ObjectSchema> {
+        self.objects.iter().find(|object| object.name == name)
+    }
+
+    pub(crate) fn message(&self, message_type: MessageType) -> Option<&ObjectSchema> {
+        self.objects
+            .iter()
+            .find(|object| object.message_type == Some(message_type))
+    }
+
+    pub(crate) fn enum_type(&self, name: &str) -> Option<&EnumType> {
+        self.enums.iter().find(|enum_type| enum_type.name() == name)
+    }
+
+    /// First 8 bytes of the SHA-256 of the schema's canonical form, see its
+    /// [`Display`], which messages carry in their header with
+    /// [`MessageBuilder::with_fingerprint`](super::MessageBuilder::with_fingerprint)
+    pub(crate) fn fingerprint(&self) -> [u8; FINGERPRINT_SIZE] {
+        let digest = Sha256::digest(self.to_string());
+        digest[..FINGERPRINT_SIZE]
+            .try_into()
+            .expect("SHA-256 is longer than a fingerprint")
+    }
+
+    /// Checks the message against the schema of its header's message type,
+    /// returns every violation rather than stopping at the first
+    pub(crate) fn validate(&self, message: &Message) -> Result<(), Vec<Violation>> {
+        let message_type = message.header.message_type;
+        match self.message(message_type) {
+            Some(schema) => self.validate_fields(schema, &message.body),
+            None => Err(vec![Violation {
+                path: String::new(),
+                kind: ViolationKind::UnknownMessageType(message_type),
+            }]),
+        }
+    }
+
+    /// Checks fields against the schema of the named object or message, e.g.
+    /// the body of an untyped message
+    ///
+    /// # Panics
+    ///
+    /// When the schema declares no object or message of that name.
+    pub(crate) fn validate_as(&self, name: &str, fields: &Fields) -> Result<(), Vec<Violation>> {
+        let schema = self
+            .object(name)
+            .unwrap_or_else(|| panic!("Schema declares no object {}", name));
+        self.validate_fields(schema, fields)
+    }
+
+    /// Whether messages written with the old schema can still be read with the
+    /// new one and the other way around, see [`Schema::breaking_changes`]
+    pub(crate) fn is_backward_compatible(old: &Schema, new: &Schema) -> bool {
+        Schema::breaking_changes(old, new).is_empty()
+    }
+
+    /// Changes from the old schema to the new one which break peers still on
+    /// the other version
+    ///
+    /// Types may be added and fields may be added as optional or dropped when
+    /// they were optional, any other change to a declared field, message type,
+    /// field ID or enum variant is breaking.
+    pub(crate) fn breaking_changes(old: &Schema, new: &Schema) -> Vec<BreakingChange> {
+        let mut changes = vec![];
+        let mut change = |path: String, kind| changes.push(BreakingChange { path, kind });
+        for old_object in &old.objects {
+            let Some(new_object) = new.object(&old_object.name) else {
+                change(old_object.name.clone(), BreakingChangeKind::RemovedType);
+                continue;
+            };
+            if old_object.message_type != new_object.message_type {
+                let kind = BreakingChangeKind::ChangedMessageType {
+                    old: old_object.message_type,
+                    new: new_object.message_type,
+                };
+                change(old_object.name.clone(), kind);
+            }
+            for old_field in &old_object.fields {
+                let path = format!("{}.{}", old_object.name, old_field.name);
+                let Some(new_field) = new_object.field(&old_field.name) else {
+                    if old_field.required {
+                        change(path, BreakingChangeKind::RemovedRequiredField);
+                    }
+                    continue;
+                };
+                if !old_field.required && new_field.required {
+                    change(path.clone(), BreakingChangeKind::BecameRequired);
+                }
+                if old_field.field_type != new_field.field_type {
+                    let kind = BreakingChangeKind::ChangedType {
+                        old: old_field.field_type.clone(),
+                        new: new_field.field_type.clone(),
+                    };
+                    change(path.clone(), kind);
+                }
+                if let Some(old_id) = old_field.id
+                    && new_field.id != Some(old_id)
+                {
+                    let kind = BreakingChangeKind::ChangedFieldId {
+                        old: old_id,
+                        new: new_field.id,
+                    };
+                    change(path, kind);
+                }
+            }
+            for new_field in &new_object.fields {
+                if new_field.required && old_object.field(&new_field.name).is_none() {
+                    let path = format!("{}.{}", new_object.name, new_field.name);
+                    change(path, BreakingChangeKind::AddedRequiredField);
+                }
+            }
+        }
+        for old_enum in &old.enums {
+            let Some(new_enum) = new.enum_type(old_enum.name()) else {
+                change(
+                    String::from(old_enum.name()),
+                    BreakingChangeKind::RemovedType,
+                );
+                continue;
+            };
+            for (discriminant, name) in &old_enum.variants {
+                if new_enum.variant_name(*discriminant).is_none() {
+                    let kind = BreakingChangeKind::RemovedVariant {
+                        name: name.clone(),
+                        discriminant: *discriminant,
+                    };
+                    change(String::from(old_enum.name()), kind);
+                }
+            }
+        }
+        changes
+    }
+
+    /// Serializes the message with the field IDs of its message type's schema
+    /// in place of their names, nested objects included
+    ///
+    /// Fails with [`SerializeError::MissingFieldId`] for a field without an ID,
+    /// fields the schema doesn't declare can only be written when they are
+    /// already named by an ID, e.g. `#7`.
+    pub(crate) fn serialize_with_ids(
+        &self,
+        message: &Message,
+        encoding: Encoding,
+    ) -> Result<Vec<u8>, SerializeError> {
+        let body = match self.message(message.header.message_type) {
+            Some(schema) => self.rename_fields(schema, &message.body, Naming::Ids),
+            None => message.body.clone(),
+        };
+        let message = Message {
+            header: Header { ..message.header },
+            body,
+        };
+        message.serialize_with(Encoding {
+            field_ids: true,
+            ..encoding
+        })
+    }
+
+    /// Deserializes a message, naming the fields of one written with field IDs
+    /// by its message type's schema
+    ///
+    /// IDs the schema doesn't declare are kept as names like `#7`, so the
+    /// message still writes back the same with [`Schema::serialize_with_ids`].
+    /// A message with the fingerprint of another schema fails with
+    /// [`DeserializeErrorKind::SchemaMismatch`] before its body is read.
+    pub(crate) fn deserialize<'a>(
+        &self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(Message, &'a [u8]), DeserializeError> {
+        if let Ok((
+            Header {
+                fingerprint: Some(found),
+                ..
+            },
+            _,
+        )) = Header::deserialize(bytes, None)
+        {
+            let expected = self.fingerprint();
+            if found != expected {
+                let kind = DeserializeErrorKind::SchemaMismatch { expected, found };
+                return Err(DeserializeError::at(kind, 0));
+            }
+        }
+        let (mut message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        if message.header.flags & FIELD_IDS_FLAG != 0
+            && let Some(schema) = self.message(message.header.message_type)
+        {
+            message.body = self.rename_fields(schema, &message.body, Naming::Names);
+        }
+        Ok((message, bytes))
+    }
+
+    fn rename_fields(&self, schema: &ObjectSchema, fields: &Fields, naming: Naming) -> Fields {
+        fields
+            .iter()
+            .map(|(name, value)| {
+                let field = match naming {
+                    Naming::Ids => schema.field(&name.0),
+                    Naming::Names => name
+                        .id()
+                        .and_then(|id| schema.fields.iter().find(|field| field.id == Some(id))),
+                };
+                let Some(field) = field else {
+                    return (name.clone(), value.clone());
+                };
+                let renamed = match (naming, field.id) {
+                    (Naming::Ids, Some(id)) => FieldName::from_id(id),
+                    _ => FieldName::from(field.name.as_str()),
+                };
+                (renamed, self.rename_value(&field.field_type, value, naming))
+            })
+            .collect()
+    }
+
+    fn rename_value(
+        &self,
+        field_type: &FieldType,
+        value: &FieldValue,
+        naming: Naming,
+    ) -> FieldValue {
+        match (field_type, value) {
+            (FieldType::Object(name), FieldValue::Object(Object(fields))) => {
+                FieldValue::Object(Object(self.rename_object(name, fields, naming)))
+            }
+            (FieldType::List(element_type), FieldValue::List(list)) => {
+                FieldValue::List(self.rename_list(element_type, list, naming))
+            }
+            (FieldType::Map(_, value_type), FieldValue::Map(Map(entries))) => {
+                FieldValue::Map(Map(entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.rename_value(value_type, value, naming)))
+                    .collect()))
+            }
+            _ => value.clone(),
+        }
+    }
+
+    fn rename_list(&self, element_type: &FieldType, list: &List, naming: Naming) -> List {
+        match (element_type, list) {
+            (FieldType::Object(name), List::Objects(objects)) => List::Objects(
+                objects
+                    .iter()
+                    .map(|Object(fields)| Object(self.rename_object(name, fields, naming)))
+                    .collect(),
+            ),
+            (FieldType::List(nested_type), List::Lists(lists)) => List::Lists(
+                lists
+                    .iter()
+                    .map(|list| self.rename_list(nested_type, list, naming))
+                    .collect(),
+            ),
+            (_, List::Mixed(values)) => List::Mixed(
+                values
+                    .iter()
+                    .map(|value| self.rename_value(element_type, value, naming))
+                    .collect(),
+            ),
+            _ => list.clone(),
+        }
+    }
+
+    fn rename_object(&self, name: &str, fields: &Fields, naming: Naming) -> Fields {
+        let schema = self
+            .object(name)
+            .expect("parsed schemas declare every object they refer to");
+        self.rename_fields(schema, fields, naming)
+    }
+
+    fn validate_fields(
+        &self,
+        schema: &ObjectSchema,
+        fields: &Fields,
+    ) -> Result<(), Vec<Violation>> {
+        let mut violations = vec![];
+        self.check_fields(schema, fields, "", &mut violations);
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations),
+        }
+    }
+
+    fn check_fields(
+        &self,
+        schema: &ObjectSchema,
+        fields: &Fields,
+        prefix: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        for field in &schema.fields {
+            let path = join_path(prefix, &field.name);
+            let kind = match fields.get(&FieldName::from(field.name.as_str())) {
+                None if field.required => ViolationKind::Missing,
+                Some(FieldValue::Null) if field.required && field.field_type != FieldType::Any => {
+                    ViolationKind::Null
+                }
+                None | Some(FieldValue::Null) => continue,
+                Some(value) => {
+                    self.check(&field.field_type, value, &path, violations);
+                    continue;
+                }
+            };
+            violations.push(Violation { path, kind });
+        }
+    }
+
+    fn check(
+        &self,
+        field_type: &FieldType,
+        value: &FieldValue,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        match (field_type, value) {
+            (FieldType::Any, _)
+            | (FieldType::Integer, FieldValue::Integer(_))
+            | (FieldType::String, FieldValue::String(_))
+            | (FieldType::Float, FieldValue::Float(_))
+            | (FieldType::Bool, FieldValue::Bool(_))
+            | (FieldType::Uuid, FieldValue::Uuid(_))
+            | (FieldType::Decimal, FieldValue::Decimal(_))
+            | (FieldType::Date, FieldValue::Date(_))
+            | (FieldType::Duration, FieldValue::Duration(_))
+            | (FieldType::Int128, FieldValue::Int128(_))
+            | (FieldType::BigInt, FieldValue::BigInt(_)) => {}
+            (FieldType::List(element), FieldValue::List(list)) => {
+                self.check_list(element, list, path, violations)
+            }
+            (FieldType::Map(key_type, value_type), FieldValue::Map(Map(entries))) => {
+                for (key, value) in entries {
+                    let path = format!("{}[{}]", path, map_key(key));
+                    self.check(key_type, key, &path, violations);
+                    self.check(value_type, value, &path, violations);
+                }
+            }
+            (FieldType::Array(element, length), FieldValue::Array(Array(elements))) => {
+                if elements.len() != *length as usize {
+                    violations.push(Violation {
+                        path: String::from(path),
+                        kind: ViolationKind::WrongLength {
+                            expected: *length as usize,
+                            found: elements.len(),
+                        },
+                    });
+                }
+                for (index, value) in elements.iter().enumerate() {
+                    let path = format!("{}[{}]", path, index);
+                    self.check(element, value, &path, violations);
+                }
+            }
+            (FieldType::Object(name), FieldValue::Object(Object(fields))) => {
+                let schema = self.object(name).expect("parsing resolves object types");
+                self.check_fields(schema, fields, path, violations);
+            }
+            (FieldType::Enum(name), FieldValue::Enum(EnumValue { discriminant, .. })) => {
+                let enum_type = self.enum_type(name).expect("parsing resolves enum types");
+                if enum_type.variant_name(*discriminant).is_none() {
+                    violations.push(Violation {
+                        path: String::from(path),
+                        kind: ViolationKind::UnknownVariant {
+                            enum_name: name.clone(),
+                            discriminant: *discriminant,
+                        },
+                    });
+                }
+            }
+            (expected, value) => violations.push(Violation {
+                path: String::from(path),
+                kind: ViolationKind::WrongType {
+                    expected: expected.to_string(),
+                    found: String::from(value.type_name()),
+                },
+            }),
+        }
+    }
+
+    /// Lists of a single scalar type are checked as a whole, a mismatch is one
+    /// violation rather than one per element
+    fn check_list(
+        &self,
+        element: &FieldType,
+        list: &List,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let element_path = |index: usize| format!("{}[{}]", path, index);
+        let found = match (element, list) {
+            (_, list) if list.is_empty() => return,
+            (FieldType::Any, _)
+            | (FieldType::Integer, List::Integers(_))
+            | (FieldType::String, List::Strings(_))
+            | (FieldType::Float, List::Floats(_))
+            | (FieldType::Bool, List::Bools(_)) => return,
+            (FieldType::Object(name), List::Objects(objects)) => {
+                let schema = self.object(name).expect("parsing resolves object types");
+                for (index, Object(fields)) in objects.iter().enumerate() {
+                    self.check_fields(schema, fields, &element_path(index), violations);
+                }
+                return;
+            }
+            (FieldType::List(nested), List::Lists(lists)) => {
+                for (index, list) in lists.iter().enumerate() {
+                    self.check_list(nested, list, &element_path(index), violations);
+                }
+                return;
+            }
+            (_, List::Mixed(values)) => {
+                for (index, value) in values.iter().enumerate() {
+                    self.check(element, value, &element_path(index), violations);
+                }
+                return;
+            }
+            (_, List::Integers(_)) => "integer",
+            (_, List::Strings(_)) => "string",
+            (_, List::Floats(_)) => "float",
+            (_, List::Bools(_)) => "bool",
+            (_, List::Objects(_)) => "object",
+            (_, List::Lists(_)) => "list",
+        };
+        violations.push(Violation {
+            path: String::from(path),
+            kind: ViolationKind::WrongType {
+                expected: format!("list<{}>", element),
+                found: format!("list<{}>", found),
+            },
+        });
+    }
+}
+
+/// Schemas known to the application, looked up by the fingerprint messages
+/// written with them carry in their header
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SchemaRegistry(HashMap<[u8; FINGERPRINT_SIZE], Schema>);
+
+impl SchemaRegistry {
+    /// Registers the schema under its fingerprint, which is returned
+    pub(crate) fn register(&mut self, schema: Schema) -> [u8; FINGERPRINT_SIZE] {
+        let fingerprint = schema.fingerprint();
+        self.0.insert(fingerprint, schema);
+        fingerprint
+    }
+
+    pub(crate) fn get(&self, fingerprint: [u8; FINGERPRINT_SIZE]) -> Option<&Schema> {
+        self.0.get(&fingerprint)
+    }
+
+    /// Deserializes a message with the schema of its fingerprint, see
+    /// [`Schema::deserialize`]
+    ///
+    /// A message without a fingerprint is read as it is, one with a fingerprint
+    /// of no registered schema fails with [`DeserializeErrorKind::UnknownSchema`].
+    pub(crate) fn deserialize<'a>(
+        &self,
+        bytes: &'a [u8],
+        encoding: Encoding,
+    ) -> Result<(Message, &'a [u8]), DeserializeError> {
+        let fingerprint = match Header::deserialize(bytes, None) {
+            Ok((
+                Header {
+                    fingerprint: Some(fingerprint),
+                    ..
+                },
+                _,
+            )) => fingerprint,
+            _ => return Message::deserialize_with(bytes, None, encoding),
+        };
+        match self.get(fingerprint) {
+            Some(schema) => schema.deserialize(bytes, encoding),
+            None => Err(DeserializeError::at(
+                DeserializeErrorKind::UnknownSchema { fingerprint },
+                0,
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Number(u64),
+    Symbol(char),
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{}`", ident),
+            Token::Number(number) => write!(f, "`{}`", number),
+            Token::Symbol(symbol) => write!(f, "`{}`", symbol),
+        }
+    }
+}
+
+/// Recursive descent parser over the tokens of a schema file, each token with
+/// its line
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    position: usize,
+    /// Named field types with their line, checked once all types are declared
+    references: Vec<(usize, String)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Result<Self, SchemaError> {
+        let mut tokens = vec![];
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split_once('#').map_or(line, |(code, _)| code);
+            let mut chars = line.char_indices().peekable();
+            while let Some((start, c)) = chars.next() {
+                let mut end = start + c.len_utf8();
+                let token = if c.is_whitespace() {
+                    continue;
+                } else if c.is_ascii_alphabetic() || c == '_' {
+                    while let Some((i, c)) =
+                        chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                    {
+                        end = i + c.len_utf8();
+                    }
+                    Token::Ident(&line[start..end])
+                } else if c.is_ascii_digit() {
+                    while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        end = i + c.len_utf8();
+                    }
+                    let number = line[start..end].parse().map_err(|_| SchemaError {
+                        line: line_number,
+                        message: format!("number `{}` too large", &line[start..end]),
+                    })?;
+                    Token::Number(number)
+                } else if "{}<>,:?=".contains(c) {
+                    Token::Symbol(c)
+                } else {
+                    return Err(SchemaError {
+                        line: line_number,
+                        message: format!("unexpected character `{}`", c),
+                    });
+                };
+                tokens.push((line_number, token));
+            }
+        }
+        Ok(Parser {
+            tokens,
+            position: 0,
+            references: vec![],
+        })
+    }
+
+    fn error(&self, message: String) -> SchemaError {
+        let line = match self.tokens.get(self.position).or(self.tokens.last()) {
+            Some((line, _)) => *line,
+            None => 1,
+        };
+        SchemaError { line, message }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).map(|(_, token)| *token)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token<'a>, SchemaError> {
+        match self.peek() {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => Err(self.error(format!("expected {}, found end of file", expected))),
+        }
+    }
+
+    fn unexpected<T>(&mut self, expected: &str, token: Token<'a>) -> Result<T, SchemaError> {
+        self.position -= 1;
+        Err(self.error(format!("expected {}, found {}", expected, token)))
+    }
+
+    /// Consumes the symbol when it comes next
+    fn eat(&mut self, symbol: char) -> bool {
+        let next = self.peek() == Some(Token::Symbol(symbol));
+        if next {
+            self.position += 1;
+        }
+        next
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<(), SchemaError> {
+        let expected = format!("`{}`", symbol);
+        match self.next(&expected)? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => self.unexpected(&expected, token),
+        }
+    }
+
+    fn ident(&mut self, expected: &str) -> Result<&'a str, SchemaError> {
+        match self.next(expected)? {
+            Token::Ident(ident) => Ok(ident),
+            token => self.unexpected(expected, token),
+        }
+    }
+
+    fn number(&mut self, expected: &str) -> Result<u16, SchemaError> {
+        match self.next(expected)? {
+            Token::Number(number) => u16::try_from(number).map_err(|_| {
+                self.position -= 1;
+                self.error(format!("{} {} beyond {}", expected, number, u16::MAX))
+            }),
+            token => self.unexpected(expected, token),
+        }
+    }
+
+    fn schema(mut self) -> Result<Schema, SchemaError> {
+        let mut schema = Schema::default();
+        while self.peek().is_some() {
+            let keyword = self.ident("`message`, `object` or `enum`")?;
+            let name_position = self.position;
+            let name = self.ident("type name")?;
+            if schema.object(name).is_some() || schema.enum_type(name).is_some() {
+                self.position = name_position;
+                return Err(self.error(format!("type `{}` is already declared", name)));
+            }
+            match keyword {
+                "message" | "object" => {
+                    let message_type = if keyword == "message" && self.eat('=') {
+                        let message_type = MessageType(self.number("message type")?);
+                        self.check_message_type(&schema, message_type)?;
+                        Some(message_type)
+                    } else {
+                        None
+                    };
+                    let fields = self.fields()?;
+                    schema.objects.push(ObjectSchema {
+                        name: String::from(name),
+                        message_type,
+                        fields,
+                    });
+                }
+                "enum" => {
+                    let enum_type = self.enum_type(name)?;
+                    schema.enums.push(enum_type);
+                }
+                _ => {
+                    self.position = name_position - 1;
+                    return Err(self.error(format!(
+                        "expected `message`, `object` or `enum`, found `{}`",
+                        keyword
+                    )));
+                }
+            }
+        }
+        for (line, name) in &self.references {
+            if schema.object(name).is_none() && schema.enum_type(name).is_none() {
+                return Err(SchemaError {
+                    line: *line,
+                    message: format!("unknown type `{}`", name),
+                });
+            }
+        }
+        let enums: Vec<String> = schema
+            .enums
+            .iter()
+            .map(|e| String::from(e.name()))
+            .collect();
+        for object in &mut schema.objects {
+            for field in &mut object.fields {
+                resolve_enums(&mut field.field_type, &enums);
+            }
+        }
+        Ok(schema)
+    }
+
+    fn check_message_type(
+        &mut self,
+        schema: &Schema,
+        message_type: MessageType,
+    ) -> Result<(), SchemaError> {
+        let message = if message_type == MessageType::UNTYPED {
+            format!(
+                "message type {} is reserved for untyped messages",
+                message_type.0
+            )
+        } else if let Some(other) = schema.message(message_type) {
+            format!(
+                "message type {} is already used by `{}`",
+                message_type.0, other.name
+            )
+        } else {
+            return Ok(());
+        };
+        self.position -= 1;
+        Err(self.error(message))
+    }
+
+    fn fields(&mut self) -> Result<Vec<FieldSchema>, SchemaError> {
+        self.symbol('{')?;
+        let mut fields: Vec<FieldSchema> = vec![];
+        while !self.eat('}') {
+            let name = self.ident("field name or `}`")?;
+            if fields.iter().any(|field| field.name == name) {
+                self.position -= 1;
+                return Err(self.error(format!("field `{}` is already declared", name)));
+            }
+            self.symbol(':')?;
+            let field_type = self.field_type()?;
+            let required = !self.eat('?');
+            let id = match self.eat('=') {
+                true => Some(self.field_id(&fields)?),
+                false => None,
+            };
+            self.eat(',');
+            fields.push(FieldSchema {
+                name: String::from(name),
+                field_type,
+                required,
+                id,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn field_id(&mut self, fields: &[FieldSchema]) -> Result<u16, SchemaError> {
+        let id = self.number("field ID")?;
+        match fields.iter().find(|field| field.id == Some(id)) {
+            Some(other) => {
+                self.position -= 1;
+                Err(self.error(format!(
+                    "field ID {} is already used by `{}`",
+                    id, other.name
+                )))
+            }
+            None => Ok(id),
+        }
+    }
+
+    fn field_type(&mut self) -> Result<FieldType, SchemaError> {
+        let field_type = match self.ident("field type")? {
+            "integer" => FieldType::Integer,
+            "string" => FieldType::String,
+            "float" => FieldType::Float,
+            "bool" => FieldType::Bool,
+            "uuid" => FieldType::Uuid,
+            "decimal" => FieldType::Decimal,
+            "date" => FieldType::Date,
+            "duration" => FieldType::Duration,
+            "int128" => FieldType::Int128,
+            "bigint" => FieldType::BigInt,
+            "any" => FieldType::Any,
+            "list" => {
+                self.symbol('<')?;
+                let element = self.field_type()?;
+                self.symbol('>')?;
+                FieldType::List(Box::new(element))
+            }
+            "map" => {
+                self.symbol('<')?;
+                let key_position = self.position;
+                let key = self.field_type()?;
+                if !matches!(
+                    key,
+                    FieldType::Integer | FieldType::String | FieldType::Uuid | FieldType::Decimal
+                ) {
+                    self.position = key_position;
+                    return Err(self.error(format!(
+                        "map keys are integer, string, uuid or decimal, found {}",
+                        key
+                    )));
+                }
+                self.symbol(',')?;
+                let value = self.field_type()?;
+                self.symbol('>')?;
+                FieldType::Map(Box::new(key), Box::new(value))
+            }
+            "array" => {
+                self.symbol('<')?;
+                let element_position = self.position;
+                let element = self.field_type()?;
+                if !matches!(
+                    element,
+                    FieldType::Integer
+                        | FieldType::Float
+                        | FieldType::Bool
+                        | FieldType::Uuid
+                        | FieldType::Decimal
+                        | FieldType::Date
+                        | FieldType::Duration
+                        | FieldType::Int128
+                ) {
+                    self.position = element_position;
+                    return Err(self.error(format!(
+                        "array elements are of a type of a fixed size, found {}",
+                        element
+                    )));
+                }
+                self.symbol(',')?;
+                let length = self.number("array length")?;
+                self.symbol('>')?;
+                FieldType::Array(Box::new(element), length)
+            }
+            name => {
+                let (line, _) = self.tokens[self.position - 1];
+                self.references.push((line, String::from(name)));
+                FieldType::Object(String::from(name))
+            }
+        };
+        Ok(field_type)
+    }
+
+    fn enum_type(&mut self, name: &str) -> Result<EnumType, SchemaError> {
+        let mut enum_type = EnumType::new(name);
+        self.symbol('{')?;
+        while !self.eat('}') {
+            let variant = self.ident("variant name or `}`")?;
+            self.symbol('=')?;
+            let discriminant = self.number("discriminant")?;
+            if enum_type.discriminant(variant).is_some()
+                || enum_type.variant_name(discriminant).is_some()
+            {
+                self.position -= 3;
+                return Err(self.error(format!(
+                    "variant {} = {} clashes with an earlier one",
+                    variant, discriminant
+                )));
+            }
+            self.eat(',');
+            enum_type = enum_type.variant(discriminant, variant);
+        }
+        Ok(enum_type)
+    }
+}
+
+/// Named types are parsed as objects, turns those naming enums into enums
+fn resolve_enums(field_type: &mut FieldType, enums: &[String]) {
+    match field_type {
+        FieldType::Object(name) if enums.contains(name) => {
+            *field_type = FieldType::Enum(name.clone());
+        }
+        FieldType::List(element) => resolve_enums(element, enums),
+        FieldType::Map(key, value) => {
+            resolve_enums(key, enums);
+            resolve_enums(value, enums);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decimal::Decimal,
+        galacticbuf::{FINGERPRINT_FLAG, MessageBuilder, StringValue, format_fingerprint},
+    };
+
+    const SCHEMA: &str = "
+        # Sides of the book
+        enum Side {
+            buy = 0
+            sell = 1
+        }
+
+        message Order = 1 {
+            id: uuid
+            side: Side
+            price: decimal?   # market orders have none
+            fills: list<Fill>
+            tags: map<string, string>?
+            brackets: array<decimal, 2>?
+        }
+
+        object Fill {
+            quantity: integer, maker: bool
+        }
+    ";
+
+    fn order(fields: Vec<(&str, FieldValue)>) -> Message {
+        fields
+            .into_iter()
+            .fold(
+                MessageBuilder::new().with_type(MessageType(1)),
+                |builder, (name, value)| builder.field(name, value),
+            )
+            .build()
+            .unwrap()
+    }
+
+    fn fill(quantity: FieldValue) -> Object {
+        Object(
+            [
+                (FieldName::from("quantity"), quantity),
+                (FieldName::from("maker"), FieldValue::Bool(true)),
+            ]
+            .into(),
+        )
+    }
+
+    #[test]
+    fn parse() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let order = schema.message(MessageType(1)).unwrap();
+        assert_eq!(order.name, "Order");
+        assert_eq!(
+            order.fields[1],
+            FieldSchema {
+                name: String::from("side"),
+                field_type: FieldType::Enum(String::from("Side")),
+                required: true,
+                id: None,
+            }
+        );
+        assert!(!order.fields[2].required);
+        assert_eq!(order.fields[3].field_type.to_string(), "list<Fill>");
+        assert_eq!(
+            order.fields[4].field_type.to_string(),
+            "map<string, string>"
+        );
+        assert_eq!(
+            order.fields[5].field_type,
+            FieldType::Array(Box::new(FieldType::Decimal), 2)
+        );
+        assert_eq!(schema.object("Fill").unwrap().message_type, None);
+        assert_eq!(
+            schema.enum_type("Side").unwrap().discriminant("sell"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |source: &str| Schema::parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("message A {\n  a: Price\n}"),
+            "line 2: unknown type `Price`"
+        );
+        assert_eq!(
+            error("message A {\n  a: integer\n  a: string\n}"),
+            "line 3: field `a` is already declared"
+        );
+        assert_eq!(
+            error("message A = 1 {}\nmessage B = 1 {}"),
+            "line 2: message type 1 is already used by `A`"
+        );
+        assert_eq!(
+            error("message A = 0 {}"),
+            "line 1: message type 0 is reserved for untyped messages"
+        );
+        assert_eq!(
+            error("enum E { a = 0, b = 0 }"),
+            "line 1: variant b = 0 clashes with an earlier one"
+        );
+        assert_eq!(
+            error("object A {\n  a: bool = 1\n  b: bool? = 1\n}"),
+            "line 3: field ID 1 is already used by `a`"
+        );
+        assert_eq!(
+            error("object A { a: map<float, bool> }"),
+            "line 1: map keys are integer, string, uuid or decimal, found float"
+        );
+        assert_eq!(
+            error("object A { a: array<string, 2> }"),
+            "line 1: array elements are of a type of a fixed size, found string"
+        );
+        assert_eq!(
+            error("object A { a: list<integer }"),
+            "line 1: expected `>`, found `}`"
+        );
+        assert_eq!(
+            error("object A {\n a: bool"),
+            "line 2: expected field name or `}`, found end of file"
+        );
+        assert_eq!(
+            error("struct A {}"),
+            "line 1: expected `message`, `object` or `enum`, found `struct`"
+        );
+        assert_eq!(
+            error("object A { a: bool; }"),
+            "line 1: unexpected character `;`"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let valid = order(vec![
+            ("id", FieldValue::Uuid([7; 16])),
+            (
+                "side",
+                EnumValue {
+                    discriminant: 1,
+                    payload: None,
+                }
+                .into(),
+            ),
+            ("price", FieldValue::Null),
+            (
+                "fills",
+                List::Objects(vec![fill(FieldValue::Integer(5))]).into(),
+            ),
+            ("venue", "XGAL".into()),
+        ]);
+        assert_eq!(schema.validate(&valid), Ok(()));
+
+        let invalid = order(vec![
+            (
+                "side",
+                EnumValue {
+                    discriminant: 9,
+                    payload: None,
+                }
+                .into(),
+            ),
+            ("price", FieldValue::Float(1.5)),
+            (
+                "fills",
+                List::Objects(vec![
+                    fill(FieldValue::Integer(5)),
+                    fill(FieldValue::Decimal(Decimal::new(5, 0))),
+                ])
+                .into(),
+            ),
+            (
+                "tags",
+                FieldValue::Map(Map(vec![("desk".into(), 4.into())])),
+            ),
+            (
+                "brackets",
+                FieldValue::Array(Array(vec![FieldValue::Integer(12)])),
+            ),
+        ]);
+        let violations: Vec<String> = schema
+            .validate(&invalid)
+            .unwrap_err()
+            .iter()
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "id: missing required field",
+                "side: unknown variant 9 of Side",
+                "price: expected decimal, found float",
+                "fills[1].quantity: expected integer, found decimal",
+                "tags[\"desk\"]: expected string, found integer",
+                "brackets: expected 2 elements, found 1",
+                "brackets[0]: expected decimal, found integer",
+            ]
+        );
+
+        let nulls = order(vec![
+            ("id", FieldValue::Null),
+            ("side", "sell".into()),
+            ("fills", List::Integers(vec![1, 2]).into()),
+        ]);
+        let violations: Vec<String> = schema
+            .validate(&nulls)
+            .unwrap_err()
+            .iter()
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "id: required field is null",
+                "side: expected Side, found string",
+                "fills: expected list<Fill>, found list<integer>",
+            ]
+        );
+
+        let untyped = Message::new([("quantity", FieldValue::Integer(1))]).unwrap();
+        assert_eq!(
+            schema.validate(&untyped),
+            Err(vec![Violation {
+                path: String::new(),
+                kind: ViolationKind::UnknownMessageType(MessageType::UNTYPED),
+            }])
+        );
+        assert_eq!(
+            schema.validate_as("Fill", &untyped.body),
+            Err(vec![Violation {
+                path: String::from("maker"),
+                kind: ViolationKind::Missing,
+            }])
+        );
+    }
+
+    #[test]
+    fn field_ids() {
+        let schema = Schema::parse(
+            "
+            message Order = 1 {
+                id: uuid = 1
+                fills: list<Fill> = 2
+                note: string?
+            }
+
+            object Fill { quantity: integer = 1, maker: bool = 2 }
+            ",
+        )
+        .unwrap();
+        let valid = order(vec![
+            ("id", FieldValue::Uuid([7; 16])),
+            (
+                "fills",
+                FieldValue::List(List::Objects(vec![fill(FieldValue::Integer(5))])),
+            ),
+            ("#9", FieldValue::Bool(true)),
+        ]);
+        let binary_message = schema
+            .serialize_with_ids(&valid, Encoding::default())
+            .unwrap();
+        assert!(binary_message.len() < valid.serialize().unwrap().len());
+
+        // Without the schema fields are named by their IDs
+        let (message, _) = Message::deserialize(&binary_message, None).unwrap();
+        assert_eq!(message.get("#1"), Some(&FieldValue::Uuid([7; 16])));
+        let Some(FieldValue::List(List::Objects(fills))) = message.get("#2") else {
+            panic!("expected a list of objects");
+        };
+        assert_eq!(fills[0].get("#1"), Some(&FieldValue::Integer(5)));
+
+        let (message, bytes) = schema
+            .deserialize(&binary_message, Encoding::default())
+            .unwrap();
+        assert_eq!(bytes.len(), 0);
+        assert_eq!(message.body, valid.body);
+        assert_eq!(
+            schema
+                .serialize_with_ids(&message, Encoding::default())
+                .unwrap(),
+            binary_message
+        );
+
+        let noted = order(vec![(
+            "note",
+            FieldValue::String(StringValue(String::from("rush"))),
+        )]);
+        assert_eq!(
+            schema
+                .serialize_with_ids(&noted, Encoding::default())
+                .unwrap_err(),
+            SerializeError::MissingFieldId {
+                name: String::from("note")
+            }
+        );
+    }
+
+    #[test]
+    fn backward_compatibility() {
+        let old = Schema::parse(SCHEMA).unwrap();
+        let compatible = Schema::parse(&format!(
+            "{}\nobject Cancel {{ reason: string }}",
+            SCHEMA.replace("tags: map<string, string>?", "venue: string?")
+        ))
+        .unwrap();
+        assert!(Schema::is_backward_compatible(&old, &compatible));
+        assert!(Schema::is_backward_compatible(&old, &old));
+
+        let new = Schema::parse(
+            "
+            enum Side { buy = 0 }
+
+            message Order = 2 {
+                id: string
+                price: decimal
+                fills: list<integer>
+                tags: map<string, string>?
+                account: string
+            }
+            ",
+        )
+        .unwrap();
+        let changes: Vec<String> = Schema::breaking_changes(&old, &new)
+            .iter()
+            .map(BreakingChange::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "Order: message type changed from 1 to 2",
+                "Order.id: type changed from uuid to string",
+                "Order.side: required field removed",
+                "Order.price: optional field made required",
+                "Order.fills: type changed from list<Fill> to list<integer>",
+                "Order.account: required field added",
+                "Fill: type removed",
+                "Side: variant sell = 1 removed",
+            ]
+        );
+        assert!(!Schema::is_backward_compatible(&old, &new));
+
+        let with_ids = |price_id| {
+            Schema::parse(&format!(
+                "message Order = 1 {{ price: decimal? = {} }}",
+                price_id
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            Schema::breaking_changes(&with_ids(1), &with_ids(2)),
+            [BreakingChange {
+                path: String::from("Order.price"),
+                kind: BreakingChangeKind::ChangedFieldId {
+                    old: 1,
+                    new: Some(2)
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn fingerprints() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let reordered = Schema::parse(
+            "object Fill { quantity: integer, maker: bool }
+            message Order = 1 {
+                id: uuid, side: Side, price: decimal?, fills: list<Fill>
+                tags: map<string, string>?, brackets: array<decimal, 2>?
+            }
+            enum Side { sell = 1, buy = 0 }",
+        )
+        .unwrap();
+        assert_eq!(reordered.fingerprint(), schema.fingerprint());
+        assert_eq!(
+            Schema::parse(&schema.to_string()).unwrap().to_string(),
+            schema.to_string()
+        );
+        let changed = Schema::parse(&SCHEMA.replace("maker: bool", "maker: bool?")).unwrap();
+        assert_ne!(changed.fingerprint(), schema.fingerprint());
+
+        let fingerprint = schema.fingerprint();
+        let message = MessageBuilder::new()
+            .with_type(MessageType(1))
+            .with_fingerprint(fingerprint)
+            .field("id", FieldValue::Uuid([7; 16]))
+            .build()
+            .unwrap();
+        assert_eq!(message.header.version, 2);
+        let bytes = message.serialize().unwrap();
+        assert_eq!(bytes[1], FINGERPRINT_FLAG);
+        assert_eq!(bytes[18..26], fingerprint);
+        assert_eq!(
+            schema.deserialize(&bytes, Encoding::default()).unwrap().0,
+            message
+        );
+        let error = changed
+            .deserialize(&bytes, Encoding::default())
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::SchemaMismatch {
+                expected: changed.fingerprint(),
+                found: fingerprint
+            }
+        );
+
+        let mut registry = SchemaRegistry::default();
+        let error = registry
+            .deserialize(&bytes, Encoding::default())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "unknown schema of fingerprint {} at byte 0",
+                format_fingerprint(&fingerprint)
+            )
+        );
+        assert_eq!(registry.register(schema), fingerprint);
+        registry.register(changed);
+        assert_eq!(
+            registry.deserialize(&bytes, Encoding::default()).unwrap().0,
+            message
+        );
+    }
+}
@@ -0,0 +1,907 @@
+//! Serde data model on top of galacticbuf values
+//!
+//! Structs and maps with string keys become objects, sequences become lists
+//! (typed where all elements share a type, mixed otherwise), enums become
+//! [`EnumValue`]s carrying the variant index, options become the value or null.
+//! The top level value has to serialize as an object, its fields are the
+//! fields of the message. `i128` and `u128` become int128s, or bigints beyond
+//! `i128`. Dates read as their ISO 8601 string, durations as their
+//! nanoseconds, bigints as their digits and arrays as sequences.
+
+use std::fmt::{self, Display};
+
+use ::serde::{
+    Deserialize, Serialize,
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess,
+        Visitor,
+        value::{MapDeserializer, SeqDeserializer},
+    },
+    ser,
+};
+use num_bigint::BigInt;
+
+use super::{
+    Array, Deserializable, DeserializeError, EnumValue, FieldName, FieldValue, Fields, List, Map,
+    Message, Object, Serializable, SerializeError, StringValue,
+};
+
+/// Serializes any `Serialize` type as a message, it has to serialize as a
+/// struct or a map with string keys
+pub(crate) fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    let FieldValue::Object(Object(fields)) = value.serialize(ValueSerializer)? else {
+        return Err(Error::Message(String::from(
+            "message must serialize as a struct or a map with string keys",
+        )));
+    };
+    let message = Message::new(
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone())),
+    )?;
+    Ok(message.serialize()?)
+}
+
+/// Deserializes any `Deserialize` type from the fields of a message
+pub(crate) fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    let (message, _) = Message::deserialize(bytes, None)?;
+    T::deserialize(FieldValue::Object(Object(message.body)))
+}
+
+/// Serde failure, or a value the wire format can't carry
+#[derive(Debug, PartialEq)]
+pub(crate) enum Error {
+    Message(String),
+    Serialize(SerializeError),
+    Deserialize(DeserializeError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Message(message) => write!(f, "{}", message),
+            Error::Serialize(error) => write!(f, "{}", error),
+            Error::Deserialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        Error::Message(message.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        Error::Message(message.to_string())
+    }
+}
+
+impl From<SerializeError> for Error {
+    fn from(error: SerializeError) -> Self {
+        Error::Serialize(error)
+    }
+}
+
+impl From<DeserializeError> for Error {
+    fn from(error: DeserializeError) -> Self {
+        Error::Deserialize(error)
+    }
+}
+
+fn variant(variant_index: u32, payload: Option<FieldValue>) -> Result<FieldValue, Error> {
+    let discriminant = u16::try_from(variant_index)
+        .map_err(|_| Error::Message(format!("variant index {} beyond u16", variant_index)))?;
+    Ok(FieldValue::Enum(EnumValue {
+        discriminant,
+        payload: payload.map(Box::new),
+    }))
+}
+
+/// Serializes a Rust value into a [`FieldValue`]
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = FieldValue;
+    type Error = Error;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Integer(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Int128(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<FieldValue, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<FieldValue, Error> {
+        let v =
+            i64::try_from(v).map_err(|_| Error::Message(format!("integer {} beyond i64", v)))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<FieldValue, Error> {
+        Ok(match i128::try_from(v) {
+            Ok(v) => FieldValue::Int128(v),
+            Err(_) => FieldValue::BigInt(BigInt::from(v)),
+        })
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<FieldValue, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<FieldValue, Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<FieldValue, Error> {
+        Ok(FieldValue::String(StringValue(String::from(v))))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<FieldValue, Error> {
+        let bytes = v.iter().map(|byte| *byte as i64).collect();
+        Ok(FieldValue::List(List::Integers(bytes)))
+    }
+
+    fn serialize_none(self) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<FieldValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<FieldValue, Error> {
+        Ok(FieldValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+    ) -> Result<FieldValue, Error> {
+        variant(variant_index, None)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<FieldValue, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<FieldValue, Error> {
+        variant(variant_index, Some(value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+        Ok(SerializeList {
+            variant_index: None,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        len: usize,
+    ) -> Result<SerializeList, Error> {
+        Ok(SerializeList {
+            variant_index: Some(variant_index),
+            values: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap {
+            entries: vec![],
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<SerializeObject, Error> {
+        Ok(SerializeObject {
+            variant_index: None,
+            fields: Fields::new(),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<SerializeObject, Error> {
+        Ok(SerializeObject {
+            variant_index: Some(variant_index),
+            fields: Fields::new(),
+        })
+    }
+}
+
+/// Elements of a sequence, tuple or tuple variant
+struct SerializeList {
+    variant_index: Option<u32>,
+    values: Vec<FieldValue>,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<FieldValue, Error> {
+        let list = FieldValue::List(List::from_values(self.values));
+        match self.variant_index {
+            Some(variant_index) => variant(variant_index, Some(list)),
+            None => Ok(list),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+/// Entries of a map, an object when all keys are strings
+struct SerializeMap {
+    entries: Vec<(FieldValue, FieldValue)>,
+    key: Option<FieldValue>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Message(String::from("map value without a key")))?;
+        self.entries.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        let string_keys = self
+            .entries
+            .iter()
+            .all(|(key, _)| matches!(key, FieldValue::String(_)));
+        if !string_keys {
+            return Ok(FieldValue::Map(Map(self.entries)));
+        }
+        let fields = self
+            .entries
+            .into_iter()
+            .map(|(key, value)| match key {
+                FieldValue::String(StringValue(name)) => (FieldName::from(name), value),
+                _ => unreachable!(),
+            })
+            .collect();
+        Ok(FieldValue::Object(Object(fields)))
+    }
+}
+
+/// Fields of a struct or struct variant
+struct SerializeObject {
+    variant_index: Option<u32>,
+    fields: Fields,
+}
+
+impl SerializeObject {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let value = value.serialize(ValueSerializer)?;
+        self.fields.insert(FieldName::from(key), value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<FieldValue, Error> {
+        let object = FieldValue::Object(Object(self.fields));
+        match self.variant_index {
+            Some(variant_index) => variant(variant_index, Some(object)),
+            None => Ok(object),
+        }
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = FieldValue;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<FieldValue, Error> {
+        self.finish()
+    }
+}
+
+/// Serializes the value with serde's data model, see the module docs
+impl Serialize for FieldValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::{SerializeMap, SerializeSeq};
+
+        match self {
+            FieldValue::Integer(integer) => serializer.serialize_i64(*integer),
+            FieldValue::String(StringValue(string)) => serializer.serialize_str(string),
+            FieldValue::List(list) => {
+                let values = list.clone().into_values();
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in &values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            FieldValue::Array(Array(elements)) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            FieldValue::Object(Object(fields)) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name.as_str(), value)?;
+                }
+                map.end()
+            }
+            FieldValue::Float(float) => serializer.serialize_f64(*float),
+            FieldValue::Bool(boolean) => serializer.serialize_bool(*boolean),
+            FieldValue::Null => serializer.serialize_unit(),
+            FieldValue::Uuid(uuid) => serializer.serialize_bytes(uuid),
+            FieldValue::Decimal(decimal) => serializer.serialize_str(&decimal.to_string()),
+            FieldValue::Date(date) => serializer.serialize_str(&date.to_string()),
+            FieldValue::Duration(duration) => {
+                serializer.serialize_u64(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+            }
+            FieldValue::Int128(integer) => serializer.serialize_i128(*integer),
+            FieldValue::BigInt(integer) => serializer.serialize_str(&integer.to_string()),
+            FieldValue::Map(Map(entries)) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            FieldValue::Enum(EnumValue {
+                discriminant,
+                payload,
+            }) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(discriminant, payload)?;
+                map.end()
+            }
+            FieldValue::Unknown(type_indicator, _) => Err(ser::Error::custom(format!(
+                "value of unknown type {}",
+                type_indicator
+            ))),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for FieldValue {
+    type Deserializer = FieldValue;
+
+    fn into_deserializer(self) -> FieldValue {
+        self
+    }
+}
+
+/// Deserializes a Rust value out of the value, see the module docs
+impl<'de> de::Deserializer<'de> for FieldValue {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            FieldValue::Integer(integer) => visitor.visit_i64(integer),
+            FieldValue::String(StringValue(string)) => visitor.visit_string(string),
+            FieldValue::List(list) => {
+                visitor.visit_seq(SeqDeserializer::new(list.into_values().into_iter()))
+            }
+            FieldValue::Array(Array(elements)) => {
+                visitor.visit_seq(SeqDeserializer::new(elements.into_iter()))
+            }
+            FieldValue::Object(Object(fields)) => visitor.visit_map(MapDeserializer::new(
+                fields.into_iter().map(|(name, value)| {
+                    (
+                        FieldValue::String(StringValue(String::from(name.as_str()))),
+                        value,
+                    )
+                }),
+            )),
+            FieldValue::Float(float) => visitor.visit_f64(float),
+            FieldValue::Bool(boolean) => visitor.visit_bool(boolean),
+            FieldValue::Null => visitor.visit_unit(),
+            FieldValue::Uuid(uuid) => visitor.visit_byte_buf(uuid.to_vec()),
+            FieldValue::Decimal(decimal) => visitor.visit_string(decimal.to_string()),
+            FieldValue::Date(date) => visitor.visit_string(date.to_string()),
+            FieldValue::Duration(duration) => {
+                visitor.visit_u64(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+            }
+            FieldValue::Int128(integer) => visitor.visit_i128(integer),
+            FieldValue::BigInt(integer) => visitor.visit_string(integer.to_string()),
+            FieldValue::Map(Map(entries)) => {
+                visitor.visit_map(MapDeserializer::new(entries.into_iter()))
+            }
+            FieldValue::Enum(value) => visitor.visit_enum(value),
+            FieldValue::Unknown(type_indicator, _) => Err(de::Error::custom(format!(
+                "value of unknown type {}",
+                type_indicator
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            FieldValue::Null => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            FieldValue::Enum(value) => visitor.visit_enum(value),
+            FieldValue::String(StringValue(name)) => visitor.visit_enum(name.into_deserializer()),
+            value => Err(de::Error::custom(format!(
+                "expected enum, found {}",
+                value.type_name()
+            ))),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            FieldValue::List(List::Integers(integers)) => {
+                let bytes = integers
+                    .into_iter()
+                    .map(|integer| {
+                        u8::try_from(integer)
+                            .map_err(|_| Error::Message(format!("byte {} beyond u8", integer)))
+                    })
+                    .collect::<Result<_, _>>()?;
+                visitor.visit_byte_buf(bytes)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> EnumAccess<'de> for EnumValue {
+    type Error = Error;
+    type Variant = Payload;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let discriminant: de::value::U32Deserializer<Error> =
+            (self.discriminant as u32).into_deserializer();
+        Ok((seed.deserialize(discriminant)?, Payload(self.payload)))
+    }
+}
+
+/// Payload of an enum variant, null when there is none
+pub(crate) struct Payload(Option<Box<FieldValue>>);
+
+impl Payload {
+    fn value(self) -> FieldValue {
+        self.0.map_or(FieldValue::Null, |payload| *payload)
+    }
+}
+
+impl<'de> VariantAccess<'de> for Payload {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.value())
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.value(), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.value(), visitor)
+    }
+}
+
+/// Deserializes a value from serde's data model, see the module docs
+impl<'de> Deserialize<'de> for FieldValue {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldValueVisitor;
+
+        impl<'de> Visitor<'de> for FieldValueVisitor {
+            type Value = FieldValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a galacticbuf value")
+            }
+
+            fn visit_bool<E>(self, v: bool) -> Result<FieldValue, E> {
+                Ok(FieldValue::Bool(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<FieldValue, E> {
+                Ok(FieldValue::Integer(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<FieldValue, E> {
+                i64::try_from(v)
+                    .map(FieldValue::Integer)
+                    .map_err(|_| E::custom(format!("integer {} beyond i64", v)))
+            }
+
+            fn visit_i128<E>(self, v: i128) -> Result<FieldValue, E> {
+                Ok(FieldValue::Int128(v))
+            }
+
+            fn visit_u128<E>(self, v: u128) -> Result<FieldValue, E> {
+                Ok(match i128::try_from(v) {
+                    Ok(v) => FieldValue::Int128(v),
+                    Err(_) => FieldValue::BigInt(BigInt::from(v)),
+                })
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<FieldValue, E> {
+                Ok(FieldValue::Float(v))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<FieldValue, E> {
+                Ok(FieldValue::String(StringValue(String::from(v))))
+            }
+
+            fn visit_unit<E>(self) -> Result<FieldValue, E> {
+                Ok(FieldValue::Null)
+            }
+
+            fn visit_none<E>(self) -> Result<FieldValue, E> {
+                Ok(FieldValue::Null)
+            }
+
+            fn visit_some<D: de::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<FieldValue, D::Error> {
+                <FieldValue as Deserialize>::deserialize(deserializer)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<FieldValue, A::Error> {
+                let mut values = vec![];
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(FieldValue::List(List::from_values(values)))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<FieldValue, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry::<FieldValue, FieldValue>()? {
+                    entries.push(entry);
+                }
+                let map = SerializeMap { entries, key: None };
+                ser::SerializeMap::end(map).map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(FieldValueVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ::serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::galacticbuf::FieldAccess;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Side {
+        Buy,
+        Sell,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Kind {
+        Market,
+        Limit { price: u32 },
+        Stop(i64),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Fill {
+        quantity: u64,
+        maker: bool,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        symbol: String,
+        side: Side,
+        kind: Kind,
+        fills: Vec<Fill>,
+        tags: BTreeMap<String, i32>,
+        expires: Option<i64>,
+        ratio: f64,
+        levels: Vec<(i64, String)>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let order = Order {
+            symbol: String::from("BTC"),
+            side: Side::Sell,
+            kind: Kind::Limit { price: 100 },
+            fills: vec![
+                Fill {
+                    quantity: 5,
+                    maker: true,
+                },
+                Fill {
+                    quantity: 7,
+                    maker: false,
+                },
+            ],
+            tags: [(String::from("desk"), 4)].into(),
+            expires: None,
+            ratio: 0.5,
+            levels: vec![(1, String::from("a"))],
+        };
+        let bytes = to_vec(&order).unwrap();
+        let (message, _) = Message::deserialize(&bytes, None).unwrap();
+        assert_eq!(message.get_str("symbol"), Ok("BTC"));
+        assert_eq!(
+            message.get("side"),
+            Some(&FieldValue::Enum(EnumValue {
+                discriminant: 1,
+                payload: None
+            }))
+        );
+        assert!(matches!(
+            message.get_list("fills"),
+            Ok(List::Objects(fills)) if fills.len() == 2
+        ));
+        assert_eq!(message.get("expires"), Some(&FieldValue::Null));
+
+        assert_eq!(from_slice::<Order>(&bytes).unwrap(), order);
+
+        for kind in [Kind::Market, Kind::Stop(-3)] {
+            let order = Order {
+                kind,
+                ..order_with_defaults()
+            };
+            assert_eq!(
+                from_slice::<Order>(&to_vec(&order).unwrap()).unwrap(),
+                order
+            );
+        }
+    }
+
+    fn order_with_defaults() -> Order {
+        Order {
+            symbol: String::new(),
+            side: Side::Buy,
+            kind: Kind::Market,
+            fills: vec![],
+            tags: BTreeMap::new(),
+            expires: Some(9),
+            ratio: 0.0,
+            levels: vec![],
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(to_vec(&42), Err(Error::Message(_))));
+        assert!(matches!(
+            to_vec(&[("big", u64::MAX)].into_iter().collect::<BTreeMap<_, _>>()),
+            Err(Error::Message(_))
+        ));
+        let bytes = to_vec(&Fill {
+            quantity: 1,
+            maker: true,
+        })
+        .unwrap();
+        assert!(matches!(
+            from_slice::<Order>(&bytes),
+            Err(Error::Message(_))
+        ));
+        assert!(matches!(
+            from_slice::<Fill>(&bytes[..3]),
+            Err(Error::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn field_value() {
+        let value = FieldValue::Object(Object(
+            [
+                (FieldName::from("a"), FieldValue::Integer(1)),
+                (
+                    FieldName::from("b"),
+                    FieldValue::List(List::Strings(vec![StringValue(String::from("x"))])),
+                ),
+            ]
+            .into(),
+        ));
+        let serialized = Serialize::serialize(&value, ValueSerializer).unwrap();
+        assert_eq!(serialized, value);
+        assert_eq!(
+            <FieldValue as Deserialize>::deserialize(value.clone()).unwrap(),
+            value
+        );
+    }
+}
@@ -0,0 +1,360 @@
+// Generated from a galacticbuf schema, do not edit
+
+#[allow(unused_imports)]
+use std::time::Duration;
+
+#[allow(unused_imports)]
+use indexmap::IndexMap;
+#[allow(unused_imports)]
+use num_bigint::BigInt;
+
+#[allow(unused_imports)]
+use crate::{
+    date::Date,
+    decimal::Decimal,
+    galacticbuf::{
+        Deserializable, DeserializeError, Encoding, EnumValue, FieldError, FieldName, FieldValue,
+        Fields, GalacticDeserialize, GalacticSerialize, Message, MessageBuilder, MessageType,
+        Object, Serializable, SerializeError,
+        codegen::{SchemaValue, field, optional_field},
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub(crate) fn discriminant(self) -> u16 {
+        match self {
+            Side::Buy => 0,
+            Side::Sell => 1,
+        }
+    }
+
+    pub(crate) fn from_discriminant(discriminant: u16) -> Option<Self> {
+        match discriminant {
+            0 => Some(Side::Buy),
+            1 => Some(Side::Sell),
+            _ => None,
+        }
+    }
+}
+
+impl SchemaValue for Side {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::Enum(EnumValue {
+            discriminant: self.discriminant(),
+            payload: None,
+        })
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        let variant = match value {
+            FieldValue::Enum(EnumValue { discriminant, .. }) => {
+                Side::from_discriminant(*discriminant)
+            }
+            _ => None,
+        };
+        variant.ok_or_else(|| FieldError::wrong_type(name, "Side", value))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Order {
+    pub(crate) id: [u8; 16],
+    pub(crate) side: Side,
+    pub(crate) price: Option<Decimal>,
+    pub(crate) quantity: i64,
+    pub(crate) fills: Vec<Fill>,
+    pub(crate) tags: Option<IndexMap<String, String>>,
+    pub(crate) matrix: Vec<Vec<f64>>,
+    pub(crate) client_order_id: Option<String>,
+    pub(crate) settles: Date,
+    pub(crate) expires_in: Option<Duration>,
+    pub(crate) volume: i128,
+    pub(crate) notional: Option<BigInt>,
+    pub(crate) brackets: Option<[Decimal; 2]>,
+    pub(crate) extra: FieldValue,
+}
+
+impl Order {
+    pub(crate) fn id(&self) -> [u8; 16] {
+        self.id
+    }
+
+    pub(crate) fn side(&self) -> Side {
+        self.side
+    }
+
+    pub(crate) fn price(&self) -> Option<Decimal> {
+        self.price
+    }
+
+    pub(crate) fn quantity(&self) -> i64 {
+        self.quantity
+    }
+
+    pub(crate) fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub(crate) fn tags(&self) -> Option<&IndexMap<String, String>> {
+        self.tags.as_ref()
+    }
+
+    pub(crate) fn matrix(&self) -> &[Vec<f64>] {
+        &self.matrix
+    }
+
+    pub(crate) fn client_order_id(&self) -> Option<&str> {
+        self.client_order_id.as_deref()
+    }
+
+    pub(crate) fn settles(&self) -> Date {
+        self.settles
+    }
+
+    pub(crate) fn expires_in(&self) -> Option<Duration> {
+        self.expires_in
+    }
+
+    pub(crate) fn volume(&self) -> i128 {
+        self.volume
+    }
+
+    pub(crate) fn notional(&self) -> Option<&BigInt> {
+        self.notional.as_ref()
+    }
+
+    pub(crate) fn brackets(&self) -> Option<[Decimal; 2]> {
+        self.brackets
+    }
+
+    pub(crate) fn extra(&self) -> &FieldValue {
+        &self.extra
+    }
+}
+
+impl GalacticSerialize for Order {
+    fn to_object(&self) -> Object {
+        let mut fields = Fields::new();
+        fields.insert(FieldName::from("id"), self.id.to_value());
+        fields.insert(FieldName::from("side"), self.side.to_value());
+        if let Some(price) = &self.price {
+            fields.insert(FieldName::from("price"), price.to_value());
+        }
+        fields.insert(FieldName::from("quantity"), self.quantity.to_value());
+        fields.insert(FieldName::from("fills"), self.fills.to_value());
+        if let Some(tags) = &self.tags {
+            fields.insert(FieldName::from("tags"), tags.to_value());
+        }
+        fields.insert(FieldName::from("matrix"), self.matrix.to_value());
+        if let Some(client_order_id) = &self.client_order_id {
+            fields.insert(FieldName::from("clientOrderId"), client_order_id.to_value());
+        }
+        fields.insert(FieldName::from("settles"), self.settles.to_value());
+        if let Some(expires_in) = &self.expires_in {
+            fields.insert(FieldName::from("expiresIn"), expires_in.to_value());
+        }
+        fields.insert(FieldName::from("volume"), self.volume.to_value());
+        if let Some(notional) = &self.notional {
+            fields.insert(FieldName::from("notional"), notional.to_value());
+        }
+        if let Some(brackets) = &self.brackets {
+            fields.insert(FieldName::from("brackets"), brackets.to_value());
+        }
+        fields.insert(FieldName::from("extra"), self.extra.to_value());
+        Object(fields)
+    }
+}
+
+impl GalacticDeserialize for Order {
+    fn from_object(object: &Object) -> Result<Self, FieldError> {
+        Ok(Order {
+            id: field(object, "id")?,
+            side: field(object, "side")?,
+            price: optional_field(object, "price")?,
+            quantity: field(object, "quantity")?,
+            fills: field(object, "fills")?,
+            tags: optional_field(object, "tags")?,
+            matrix: field(object, "matrix")?,
+            client_order_id: optional_field(object, "clientOrderId")?,
+            settles: field(object, "settles")?,
+            expires_in: optional_field(object, "expiresIn")?,
+            volume: field(object, "volume")?,
+            notional: optional_field(object, "notional")?,
+            brackets: optional_field(object, "brackets")?,
+            extra: field(object, "extra")?,
+        })
+    }
+}
+
+impl SchemaValue for Order {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::Object(self.to_object())
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Object(object) => Order::from_object(object),
+            value => Err(FieldError::wrong_type(name, "Order", value)),
+        }
+    }
+}
+
+/// [Message of type 1]
+impl Serializable for Order {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let Object(fields) = self.to_object();
+        let mut builder = MessageBuilder::new().with_type(MessageType(1));
+        for (FieldName(name), value) in fields {
+            builder = builder.field(&name, value);
+        }
+        builder.build()?.serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message of type 1]
+impl Deserializable for Order {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        if message.header.message_type != MessageType(1) {
+            return Err(DeserializeError::invalid(format!(
+                "expected message type 1, found {}",
+                message.header.message_type.0
+            )));
+        }
+        let value = Order::from_object(&Object(message.body))?;
+        Ok((value, bytes))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Fill {
+    pub(crate) quantity: i64,
+    pub(crate) maker: bool,
+    pub(crate) r#type: Option<Vec<Side>>,
+}
+
+impl Fill {
+    pub(crate) fn quantity(&self) -> i64 {
+        self.quantity
+    }
+
+    pub(crate) fn maker(&self) -> bool {
+        self.maker
+    }
+
+    pub(crate) fn r#type(&self) -> Option<&[Side]> {
+        self.r#type.as_deref()
+    }
+}
+
+impl GalacticSerialize for Fill {
+    fn to_object(&self) -> Object {
+        let mut fields = Fields::new();
+        fields.insert(FieldName::from("quantity"), self.quantity.to_value());
+        fields.insert(FieldName::from("maker"), self.maker.to_value());
+        if let Some(r#type) = &self.r#type {
+            fields.insert(FieldName::from("type"), r#type.to_value());
+        }
+        Object(fields)
+    }
+}
+
+impl GalacticDeserialize for Fill {
+    fn from_object(object: &Object) -> Result<Self, FieldError> {
+        Ok(Fill {
+            quantity: field(object, "quantity")?,
+            maker: field(object, "maker")?,
+            r#type: optional_field(object, "type")?,
+        })
+    }
+}
+
+impl SchemaValue for Fill {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::Object(self.to_object())
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Object(object) => Fill::from_object(object),
+            value => Err(FieldError::wrong_type(name, "Fill", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Heartbeat {}
+
+impl GalacticSerialize for Heartbeat {
+    fn to_object(&self) -> Object {
+        Object(Fields::new())
+    }
+}
+
+impl GalacticDeserialize for Heartbeat {
+    fn from_object(_: &Object) -> Result<Self, FieldError> {
+        Ok(Heartbeat {})
+    }
+}
+
+impl SchemaValue for Heartbeat {
+    fn to_value(&self) -> FieldValue {
+        FieldValue::Object(self.to_object())
+    }
+
+    fn from_value(name: &str, value: &FieldValue) -> Result<Self, FieldError> {
+        match value {
+            FieldValue::Object(object) => Heartbeat::from_object(object),
+            value => Err(FieldError::wrong_type(name, "Heartbeat", value)),
+        }
+    }
+}
+
+/// [Message of type 2]
+impl Serializable for Heartbeat {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        let Object(fields) = self.to_object();
+        let mut builder = MessageBuilder::new().with_type(MessageType(2));
+        for (FieldName(name), value) in fields {
+            builder = builder.field(&name, value);
+        }
+        builder.build()?.serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message of type 2]
+impl Deserializable for Heartbeat {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        if message.header.message_type != MessageType(2) {
+            return Err(DeserializeError::invalid(format!(
+                "expected message type 2, found {}",
+                message.header.message_type.0
+            )));
+        }
+        let value = Heartbeat::from_object(&Object(message.body))?;
+        Ok((value, bytes))
+    }
+}
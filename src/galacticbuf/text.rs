@@ -0,0 +1,720 @@
+//! Messages as indented text, to read what a message holds without decoding
+//! its bytes by hand and to write test fixtures
+//!
+//! ```text
+//! message version=2 type=4 sequence=17  # 85 bytes
+//!   id: integer = 7  # 5 bytes
+//!   symbol: string = "XAU"  # 13 bytes
+//!   fills: list<object>  # 23 bytes
+//!     [0]: object
+//!       quantity: integer = 3  # 11 bytes
+//!   limits: map  # 16 bytes
+//!     [integer 1]: decimal = 12.50
+//!   side: enum = 2  # 10 bytes
+//!     payload: integer = 7
+//! ```
+//!
+//! Each line is a field, element or map entry with its type and its value,
+//! containers list what they hold one level deeper. Strings are quoted and
+//! escaped like Rust string literals, so are field names which aren't plain
+//! words. Dates are written as `2024-03-01` and durations in nanoseconds, as
+//! `1500000000ns`. The header names a schema fingerprint as `fingerprint=`
+//! and its 16 hex digits. Everything after a `#` is a comment, the sizes of
+//! the message and its fields are written as one, in the encoding of the
+//! message's version.
+//! Reading, the header line is optional and comments are ignored.
+
+use std::{
+    fmt::{self, Display, Write as _},
+    time::Duration,
+};
+
+use num_bigint::BigInt;
+
+use super::{
+    Array, CHECKSUM_FLAG, Encoding, EnumValue, FieldName, FieldValue, Fields, List, Map, Message,
+    MessageBuilder, MessageType, Object, Serializable, SerializeError, StringValue,
+    format_fingerprint, format_uuid, parse_fingerprint, parse_uuid,
+};
+use crate::{date::Date, decimal::Decimal};
+
+/// Spaces each level of nesting is indented by
+const INDENT: usize = 2;
+
+impl Message {
+    /// Message as text, see the module docs
+    pub(crate) fn to_text(&self) -> String {
+        let encoding = Encoding {
+            version: self.header.version,
+            ..Encoding::default()
+        };
+        let mut text = format!(
+            "message version={} type={} sequence={}",
+            self.header.version, self.header.message_type.0, self.header.sequence
+        );
+        if self.header.flags & CHECKSUM_FLAG != 0 {
+            text.push_str(" checksum");
+        }
+        if let Some(fingerprint) = self.header.fingerprint {
+            write!(text, " fingerprint={}", format_fingerprint(&fingerprint)).unwrap();
+        }
+        writeln!(text, "  # {} bytes", self.serialized_len_with(encoding)).unwrap();
+        write_fields(&mut text, INDENT, &self.body, encoding);
+        text
+    }
+
+    /// Message of the text, see the module docs
+    pub(crate) fn from_text(text: &str) -> Result<Message, TextError> {
+        let mut parser = Parser::new(text);
+        let mut builder = MessageBuilder::new();
+        let mut indent = 0;
+        if let Some(line) = parser.peek()
+            && (line.text == "message" || line.text.starts_with("message "))
+        {
+            builder = header(builder, line)?;
+            indent = line.indent + INDENT;
+            parser.next += 1;
+        }
+        for entry in parser.entries(indent)? {
+            let Key::Name(name) = entry.key else {
+                return Err(TextError::syntax(entry.line, "expected a field name"));
+            };
+            builder = builder.field(&name, entry.value);
+        }
+        if let Some(line) = parser.peek() {
+            return Err(TextError::syntax(line.number, "unexpected indentation"));
+        }
+        Ok(builder.build()?)
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text())
+    }
+}
+
+/// Text the message couldn't be read from
+#[derive(Debug, PartialEq)]
+pub(crate) enum TextError {
+    /// Line which doesn't follow the format, numbered from 1
+    Syntax { line: usize, reason: String },
+    /// Fields which don't fit into a message, e.g. a name of over 255 bytes
+    Serialize(SerializeError),
+}
+
+impl TextError {
+    fn syntax(line: usize, reason: impl Into<String>) -> Self {
+        TextError::Syntax {
+            line,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Syntax { line, reason } => write!(f, "line {}: {}", line, reason),
+            TextError::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl From<SerializeError> for TextError {
+    fn from(error: SerializeError) -> Self {
+        TextError::Serialize(error)
+    }
+}
+
+fn write_fields(text: &mut String, indent: usize, fields: &Fields, encoding: Encoding) {
+    for (name, value) in fields {
+        let size = name.serialized_len_with(encoding) + value.serialized_len_with(encoding);
+        let key = match is_word(name.as_str()) {
+            true => name.to_string(),
+            false => format!("{:?}", name.as_str()),
+        };
+        write_value(text, indent, &key, value, Some(size), encoding);
+    }
+}
+
+/// Line of the value, with the size of the field it is the value of, and the
+/// lines of what it holds
+fn write_value(
+    text: &mut String,
+    indent: usize,
+    key: &str,
+    value: &FieldValue,
+    size: Option<usize>,
+    encoding: Encoding,
+) {
+    write!(text, "{:indent$}{}: {}", "", key, type_label(value)).unwrap();
+    if let Some(scalar) = scalar(value) {
+        write!(text, " = {}", scalar).unwrap();
+    }
+    if let Some(size) = size {
+        write!(text, "  # {} bytes", size).unwrap();
+    }
+    text.push('\n');
+
+    let indent = indent + INDENT;
+    match value {
+        FieldValue::List(list) => {
+            for (i, element) in list.clone().into_values().iter().enumerate() {
+                write_value(text, indent, &format!("[{}]", i), element, None, encoding);
+            }
+        }
+        FieldValue::Array(Array(elements)) => {
+            for (i, element) in elements.iter().enumerate() {
+                write_value(text, indent, &format!("[{}]", i), element, None, encoding);
+            }
+        }
+        FieldValue::Object(Object(fields)) => write_fields(text, indent, fields, encoding),
+        FieldValue::Map(Map(entries)) => {
+            for (key, value) in entries {
+                let key = format!("[{} {}]", key.type_name(), scalar(key).unwrap_or_default());
+                write_value(text, indent, &key, value, None, encoding);
+            }
+        }
+        FieldValue::Enum(EnumValue {
+            payload: Some(payload),
+            ..
+        }) => write_value(text, indent, "payload", payload, None, encoding),
+        _ => {}
+    }
+}
+
+/// Type of the value, lists with the type of their elements, e.g. `list<integer>`
+pub(super) fn type_label(value: &FieldValue) -> String {
+    match value {
+        FieldValue::List(list) => {
+            let elements = match list {
+                List::Integers(_) => "integer",
+                List::Strings(_) => "string",
+                List::Objects(_) => "object",
+                List::Floats(_) => "float",
+                List::Bools(_) => "bool",
+                List::Lists(_) => "list",
+                List::Mixed(_) => "mixed",
+            };
+            format!("list<{}>", elements)
+        }
+        FieldValue::Unknown(type_indicator, _) => format!("unknown 0x{:02X}", type_indicator),
+        value => String::from(value.type_name()),
+    }
+}
+
+/// Value written after the type, `None` for null and containers
+pub(super) fn scalar(value: &FieldValue) -> Option<String> {
+    let scalar = match value {
+        FieldValue::Integer(integer) => integer.to_string(),
+        FieldValue::Float(float) => format!("{:?}", float),
+        FieldValue::String(StringValue(string)) => format!("{:?}", string),
+        FieldValue::Bool(boolean) => boolean.to_string(),
+        FieldValue::Uuid(uuid) => format_uuid(uuid),
+        FieldValue::Decimal(decimal) => decimal.to_string(),
+        FieldValue::Date(date) => date.to_string(),
+        FieldValue::Duration(duration) => format!("{}ns", duration.as_nanos()),
+        FieldValue::Int128(integer) => integer.to_string(),
+        FieldValue::BigInt(integer) => integer.to_string(),
+        FieldValue::Enum(EnumValue { discriminant, .. }) => discriminant.to_string(),
+        FieldValue::Unknown(_, bytes) if !bytes.is_empty() => {
+            bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+        }
+        _ => return None,
+    };
+    Some(scalar)
+}
+
+/// Name written without quotes, one which can't be mistaken for the rest of
+/// the line
+fn is_word(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('[')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_#.-".contains(c))
+}
+
+fn header(mut builder: MessageBuilder, line: &Line) -> Result<MessageBuilder, TextError> {
+    let error = |reason: String| TextError::syntax(line.number, reason);
+    let text = line.text.split('#').next().unwrap_or_default();
+    for attribute in text.split_whitespace().skip(1) {
+        builder = match attribute.split_once('=') {
+            None if attribute == "checksum" => builder.with_checksum(),
+            Some(("version", version)) => builder.with_version(
+                version
+                    .parse()
+                    .map_err(|_| error(format!("invalid version `{}`", version)))?,
+            ),
+            Some(("type", message_type)) => builder.with_type(MessageType(
+                message_type
+                    .parse()
+                    .map_err(|_| error(format!("invalid type `{}`", message_type)))?,
+            )),
+            Some(("sequence", sequence)) => builder.with_sequence(
+                sequence
+                    .parse()
+                    .map_err(|_| error(format!("invalid sequence `{}`", sequence)))?,
+            ),
+            Some(("fingerprint", fingerprint)) => builder.with_fingerprint(
+                parse_fingerprint(fingerprint)
+                    .ok_or_else(|| error(format!("invalid fingerprint `{}`", fingerprint)))?,
+            ),
+            _ => return Err(error(format!("unknown header attribute `{}`", attribute))),
+        };
+    }
+    Ok(builder)
+}
+
+/// Line with its indentation taken off
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// What a line is the value of
+enum Key {
+    Name(String),
+    Index(usize),
+    Map(FieldValue),
+}
+
+struct Entry {
+    line: usize,
+    key: Key,
+    value: FieldValue,
+}
+
+struct Parser<'a> {
+    /// Lines which aren't blank or comments
+    lines: Vec<Line<'a>>,
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        let lines = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let text = line.trim_start();
+                (!text.is_empty() && !text.starts_with('#')).then(|| Line {
+                    number: i + 1,
+                    indent: line.len() - text.len(),
+                    text: text.trim_end(),
+                })
+            })
+            .collect();
+        Parser { lines, next: 0 }
+    }
+
+    fn peek(&self) -> Option<&Line<'a>> {
+        self.lines.get(self.next)
+    }
+
+    /// Entries indented by exactly `indent`, up to the first line indented less
+    fn entries(&mut self, indent: usize) -> Result<Vec<Entry>, TextError> {
+        let mut entries = vec![];
+        while let Some(line) = self.peek() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(TextError::syntax(line.number, "unexpected indentation"));
+            }
+            entries.push(self.entry()?);
+        }
+        Ok(entries)
+    }
+
+    /// Entry of the next line and the lines nested under it
+    fn entry(&mut self) -> Result<Entry, TextError> {
+        let line = &self.lines[self.next];
+        let (number, indent) = (line.number, line.indent);
+        let error = |reason: String| TextError::syntax(number, reason);
+        let (key, rest) = key(line.text).map_err(error)?;
+        let rest = rest
+            .strip_prefix(':')
+            .ok_or_else(|| error(String::from("expected `:` after the key")))?
+            .trim_start();
+        let (label, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        let (label, rest) = match label {
+            "unknown" => {
+                let (type_indicator, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+                let type_indicator = type_indicator
+                    .strip_prefix("0x")
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| error(format!("invalid type indicator `{}`", type_indicator)))?;
+                (Label::Unknown(type_indicator), rest)
+            }
+            label => (Label::Type(label), rest),
+        };
+        let rest = rest.trim_start();
+        let (scalar, rest) = match rest.strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                let (scalar, rest) = match value.starts_with('"') {
+                    true => parse_string(value)
+                        .map(|(string, rest)| (Scalar::String(string), rest))
+                        .ok_or_else(|| error(String::from("unterminated string")))?,
+                    false => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                        (Scalar::Token(&value[..end]), &value[end..])
+                    }
+                };
+                (Some(scalar), rest.trim_start())
+            }
+            None => (None, rest),
+        };
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(error(format!("unexpected `{}`", rest)));
+        }
+        self.next += 1;
+
+        let value = match label {
+            Label::Unknown(type_indicator) => {
+                let bytes = match scalar {
+                    Some(Scalar::Token(hex)) => {
+                        parse_hex(hex).ok_or_else(|| error(format!("invalid bytes `{}`", hex)))?
+                    }
+                    Some(Scalar::String(_)) => return Err(error(String::from("expected bytes"))),
+                    None => vec![],
+                };
+                FieldValue::Unknown(type_indicator, bytes)
+            }
+            Label::Type(label) => match scalar {
+                Some(scalar) => parse_scalar(label, scalar).map_err(error)?,
+                None => self.container(label, number, indent + INDENT)?,
+            },
+        };
+        let value = match value {
+            FieldValue::Enum(EnumValue { discriminant, .. }) => FieldValue::Enum(EnumValue {
+                discriminant,
+                payload: self.payload(indent + INDENT)?,
+            }),
+            value => value,
+        };
+        Ok(Entry {
+            line: number,
+            key,
+            value,
+        })
+    }
+
+    /// Payload of an enum, on the line nested under it if there is one
+    fn payload(&mut self, indent: usize) -> Result<Option<Box<FieldValue>>, TextError> {
+        let mut entries = self.entries(indent)?.into_iter();
+        let payload = match entries.next() {
+            Some(Entry {
+                key: Key::Name(name),
+                value,
+                ..
+            }) if name == "payload" => Some(Box::new(value)),
+            Some(entry) => return Err(TextError::syntax(entry.line, "expected the payload")),
+            None => None,
+        };
+        if let Some(entry) = entries.next() {
+            return Err(TextError::syntax(entry.line, "enum has a single payload"));
+        }
+        Ok(payload)
+    }
+
+    /// Value of a type without a value on its own line, made of the lines
+    /// nested under it
+    fn container(
+        &mut self,
+        label: &str,
+        number: usize,
+        indent: usize,
+    ) -> Result<FieldValue, TextError> {
+        let error = |reason: String| TextError::syntax(number, reason);
+        if label == "null" {
+            return Ok(FieldValue::Null);
+        }
+        let entries = self.entries(indent)?;
+        let value = match label {
+            "object" => FieldValue::Object(Object(names(entries)?)),
+            "map" => {
+                let entries = entries
+                    .into_iter()
+                    .map(|entry| match entry.key {
+                        Key::Map(key) => Ok((key, entry.value)),
+                        _ => Err(TextError::syntax(entry.line, "expected a map key")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                FieldValue::Map(Map(entries))
+            }
+            "array" => FieldValue::Array(Array(elements(entries).map_err(error)?)),
+            label => {
+                let elements = label
+                    .strip_prefix("list<")
+                    .and_then(|label| label.strip_suffix('>'))
+                    .ok_or_else(|| error(format!("expected a value of type `{}`", label)))?;
+                FieldValue::List(list(elements, entries).map_err(error)?)
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Type of a line, unknown types with their type indicator
+enum Label<'a> {
+    Type(&'a str),
+    Unknown(u8),
+}
+
+/// Value after the `=`
+enum Scalar<'a> {
+    String(String),
+    Token(&'a str),
+}
+
+/// Key at the start of the line and what follows it
+fn key(text: &str) -> Result<(Key, &str), String> {
+    if text.starts_with('"') {
+        let (name, rest) = parse_string(text).ok_or("unterminated string")?;
+        return Ok((Key::Name(name), rest));
+    }
+    let Some(inner) = text.strip_prefix('[') else {
+        let end = text.find(':').ok_or("expected `:` after the key")?;
+        return Ok((Key::Name(String::from(&text[..end])), &text[end..]));
+    };
+    if inner.starts_with(|c: char| c.is_ascii_digit()) {
+        let end = inner.find(']').ok_or("unterminated `[`")?;
+        let index = inner[..end]
+            .parse()
+            .map_err(|_| format!("invalid index `{}`", &inner[..end]))?;
+        return Ok((Key::Index(index), &inner[end + 1..]));
+    }
+    let (label, value) = inner.split_once(' ').ok_or("expected `[type value]`")?;
+    let (scalar, rest) = match value.starts_with('"') {
+        true => {
+            let (string, rest) = parse_string(value).ok_or("unterminated string")?;
+            (Scalar::String(string), rest)
+        }
+        false => {
+            let end = value.find(']').ok_or("unterminated `[`")?;
+            (Scalar::Token(&value[..end]), &value[end..])
+        }
+    };
+    let rest = rest.strip_prefix(']').ok_or("unterminated `[`")?;
+    Ok((Key::Map(parse_scalar(label, scalar)?), rest))
+}
+
+fn parse_scalar(label: &str, scalar: Scalar) -> Result<FieldValue, String> {
+    let token = match scalar {
+        Scalar::String(string) if label == "string" => {
+            return Ok(FieldValue::String(StringValue(string)));
+        }
+        Scalar::String(_) => return Err(format!("expected a value of type `{}`", label)),
+        Scalar::Token(token) => token,
+    };
+    let invalid = || format!("invalid {} `{}`", label, token);
+    let value = match label {
+        "integer" => FieldValue::Integer(token.parse().map_err(|_| invalid())?),
+        "float" => FieldValue::Float(token.parse().map_err(|_| invalid())?),
+        "bool" => FieldValue::Bool(token.parse().map_err(|_| invalid())?),
+        "uuid" => FieldValue::Uuid(parse_uuid(token).ok_or_else(invalid)?),
+        "decimal" => FieldValue::Decimal(token.parse::<Decimal>().map_err(|_| invalid())?),
+        "date" => FieldValue::Date(token.parse::<Date>().map_err(|_| invalid())?),
+        "duration" => FieldValue::Duration(Duration::from_nanos(
+            token
+                .strip_suffix("ns")
+                .and_then(|nanos| nanos.parse().ok())
+                .ok_or_else(invalid)?,
+        )),
+        "int128" => FieldValue::Int128(token.parse().map_err(|_| invalid())?),
+        "bigint" => FieldValue::BigInt(token.parse::<BigInt>().map_err(|_| invalid())?),
+        "enum" => FieldValue::Enum(EnumValue {
+            discriminant: token.parse().map_err(|_| invalid())?,
+            payload: None,
+        }),
+        "string" => return Err(String::from("expected a quoted string")),
+        label => return Err(format!("unknown type `{}`", label)),
+    };
+    Ok(value)
+}
+
+fn names(entries: Vec<Entry>) -> Result<Fields, TextError> {
+    entries
+        .into_iter()
+        .map(|entry| match entry.key {
+            Key::Name(name) => Ok((FieldName::from(name), entry.value)),
+            _ => Err(TextError::syntax(entry.line, "expected a field name")),
+        })
+        .collect()
+}
+
+/// Values of entries indexed from 0 in order
+fn elements(entries: Vec<Entry>) -> Result<Vec<FieldValue>, String> {
+    let mut values = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        match entry.key {
+            Key::Index(index) if index == i => values.push(entry.value),
+            _ => return Err(format!("expected element [{}]", i)),
+        }
+    }
+    Ok(values)
+}
+
+/// List of the `elements` type, whose entries are indexed from 0 in order
+fn list(elements: &str, entries: Vec<Entry>) -> Result<List, String> {
+    let values = self::elements(entries)?;
+    macro_rules! typed {
+        ($variant:ident, $list:ident) => {
+            List::$list(
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        FieldValue::$variant(value) => Ok(value),
+                        value => Err(format!("{} in a list of {}s", value.type_name(), elements)),
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        };
+    }
+    let list = match elements {
+        "integer" => typed!(Integer, Integers),
+        "string" => typed!(String, Strings),
+        "object" => typed!(Object, Objects),
+        "float" => typed!(Float, Floats),
+        "bool" => typed!(Bool, Bools),
+        "list" => typed!(List, Lists),
+        "mixed" => List::Mixed(values),
+        elements => return Err(format!("unknown element type `{}`", elements)),
+    };
+    Ok(list)
+}
+
+/// String literal at the start of `text`, escaped the way `{:?}` escapes
+/// strings, and what follows it
+fn parse_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut string = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((string, &text[i + 2..])),
+            '\\' => string.push(match chars.next()?.1 {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                '\\' => '\\',
+                '"' => '"',
+                '\'' => '\'',
+                'u' => {
+                    let rest = chars.as_str().strip_prefix('{')?;
+                    let end = rest.find('}')?;
+                    let c = char::from_u32(u32::from_str_radix(&rest[..end], 16).ok()?)?;
+                    chars.nth(end + 1)?;
+                    c
+                }
+                _ => return None,
+            }),
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let fill = Object([(FieldName::from("quantity"), FieldValue::Integer(3))].into());
+        let message = MessageBuilder::new()
+            .with_type(MessageType(4))
+            .with_sequence(17)
+            .with_fingerprint([0, 1, 2, 3, 0xFC, 0xFD, 0xFE, 0xFF])
+            .field("id", FieldValue::Uuid([0xAB; 16]))
+            .field("price", FieldValue::Decimal(Decimal::new(1250, -2)))
+            .field("ratio", FieldValue::Float(f64::INFINITY))
+            .field(
+                "note",
+                FieldValue::String(StringValue(String::from("say \"hi\"\n\u{1}  # no comment"))),
+            )
+            .field("open", FieldValue::Bool(true))
+            .field("expires", FieldValue::Null)
+            .field("settles", FieldValue::Date(Date::from_days(19_787)))
+            .field("ttl", FieldValue::Duration(Duration::from_millis(1500)))
+            .field("volume", FieldValue::Int128(i128::MIN))
+            .field("supply", FieldValue::BigInt(BigInt::from(u128::MAX) * 1000))
+            .field(
+                "position",
+                FieldValue::Array(Array(vec![FieldValue::Float(1.5), FieldValue::Float(-2.0)])),
+            )
+            .field("sizes", FieldValue::List(List::Integers(vec![])))
+            .field("fills", FieldValue::List(List::Objects(vec![fill])))
+            .field(
+                "mixed",
+                FieldValue::List(List::Mixed(vec![FieldValue::Integer(1), FieldValue::Null])),
+            )
+            .field(
+                "limits",
+                FieldValue::Map(Map(vec![(
+                    FieldValue::String(StringValue(String::from("a]b"))),
+                    FieldValue::Integer(-5),
+                )])),
+            )
+            .field(
+                "side",
+                FieldValue::Enum(EnumValue {
+                    discriminant: 2,
+                    payload: Some(Box::new(FieldValue::Integer(7))),
+                }),
+            )
+            .field("odd: name", FieldValue::Integer(0))
+            .build()
+            .unwrap();
+        let text = message.to_text();
+        assert!(
+            text.starts_with("message version=2 type=4 sequence=17 fingerprint=00010203fcfdfeff")
+        );
+        assert_eq!(message.to_string(), text);
+        assert_eq!(Message::from_text(&text).unwrap(), message);
+    }
+
+    #[test]
+    fn text_format() {
+        let message = Message::new([
+            ("id", FieldValue::Integer(7)),
+            ("sizes", FieldValue::List(List::Integers(vec![1, 2]))),
+        ])
+        .unwrap();
+        assert_eq!(
+            message.to_text(),
+            "message version=1 type=0 sequence=0  # 42 bytes\n\
+             \x20 id: integer = 7  # 12 bytes\n\
+             \x20 sizes: list<integer>  # 26 bytes\n\
+             \x20   [0]: integer = 1\n\
+             \x20   [1]: integer = 2\n"
+        );
+
+        // Fixtures can leave out the header and comment on what they hold
+        let fixture = "# an order\nid: integer = 7\nsizes: list<integer>\n  [0]: integer = 1\n  [1]: integer = 2\n";
+        assert_eq!(Message::from_text(fixture).unwrap(), message);
+
+        let error = Message::from_text("id: integer = 7\n    price: float = 1.5").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unexpected indentation");
+        let error = Message::from_text("sizes: list<integer>\n  [0]: string = \"x\"").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: string in a list of integers");
+        let error = Message::from_text("id: integer = seven").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: invalid integer `seven`");
+    }
+}
@@ -0,0 +1,34 @@
+//! JavaScript bindings for the browser, built with
+//! `wasm-pack build --target web -- --features wasm`
+//!
+//! Messages cross over as plain objects in the JSON representation of
+//! [`Message::to_json`], so the same mapping applies. JavaScript numbers are
+//! doubles, integers beyond ±2^53 lose precision on the way.
+
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+
+use super::{Deserializable, Message, Serializable};
+
+/// Serializes the fields of a plain object into an untyped message
+#[wasm_bindgen]
+pub fn encode(message: JsValue) -> Result<Vec<u8>, JsError> {
+    let json = JSON::stringify(&message)
+        .map_err(|_| JsError::new("message can't be converted to JSON"))?;
+    let json = serde_json::from_str(&String::from(json))?;
+    Ok(Message::from_json(&json)?.serialize()?)
+}
+
+/// Reads one message, the bytes must hold nothing else
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8]) -> Result<JsValue, JsError> {
+    let (message, rest) = Message::deserialize(bytes, None)?;
+    if !rest.is_empty() {
+        return Err(JsError::new(&format!(
+            "{} bytes following the message",
+            rest.len()
+        )));
+    }
+    JSON::parse(&message.to_json().to_string())
+        .map_err(|_| JsError::new("message can't be converted from JSON"))
+}
@@ -1 +1,29 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod accounts;
+#[cfg(not(target_arch = "wasm32"))]
+mod candles;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+mod date;
+mod decimal;
+#[cfg(not(target_arch = "wasm32"))]
+mod engine;
 mod galacticbuf;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logging;
+#[cfg(not(target_arch = "wasm32"))]
+mod markets;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod schemas;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+mod session;
+#[cfg(not(target_arch = "wasm32"))]
+mod ticker;
+#[cfg(not(target_arch = "wasm32"))]
+mod trades;
+
+#[cfg(feature = "benchmarking")]
+pub use galacticbuf::bench;
+#[cfg(feature = "fuzzing")]
+pub use galacticbuf::fuzz;
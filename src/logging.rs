@@ -0,0 +1,20 @@
+//! Logs of the server, tracing events written to stdout as the config says
+//!
+//! `RUST_LOG` takes env-filter directives which override the log level of the
+//! config, e.g. `RUST_LOG=galactic_exchange=debug,warn`.
+
+use tracing_subscriber::{EnvFilter, fmt};
+
+use crate::config::{Config, LogFormat};
+
+/// Installs the subscriber of the process, a second call leaves the first one
+/// in place
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(config.log_level.as_str()));
+    let builder = fmt().with_env_filter(filter);
+    let _ = match config.log_format {
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+}
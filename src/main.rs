@@ -1,16 +1,19 @@
-#[macro_use]
-extern crate rouille;
+use std::{path::PathBuf, process};
 
-fn main() {
-    println!("Hello, galaxy!!");
-    println!("Now listening on 0.0.0.0:8080");
+use galactic_exchange::{config::Config, logging, server::Server};
 
-    rouille::start_server("0.0.0.0:8080", move |request| {
-        router!(request,
-            (GET) (/health) => {
-                rouille::Response::text("").with_status_code(200)
-            },
-            _ => rouille::Response::empty_404()
-        )
+/// Usage: `galactic-exchange [CONFIG]`, see `config` for the settings of the
+/// TOML file and the environment
+fn main() {
+    let path = std::env::args_os().nth(1).map(PathBuf::from);
+    let config = Config::load(path.as_deref()).unwrap_or_else(|e| {
+        eprintln!("invalid configuration: {}", e);
+        process::exit(2);
     });
+    logging::init(&config);
+    let server = Server::new(config).expect("data directory can't be opened");
+    if let Err(e) = server.run() {
+        tracing::error!(error = %e, "server failed");
+        process::exit(1);
+    }
 }
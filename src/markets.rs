@@ -0,0 +1,278 @@
+//! Markets the exchange lists, the symbols orders can be placed on and the
+//! steps their prices and quantities move in
+//!
+//! Orders are priced in whole ticks and sized in whole lots of their market,
+//! and the price times the quantity of a limit order must reach the market's
+//! minimum notional. A halted market takes no new orders nor amends, the
+//! orders in its book can still be cancelled.
+//!
+//! Markets are listed in the `[[markets]]` tables of the config, see
+//! [`config`](crate::config), or through the admin API. Those listed through
+//! the admin API are written to a file in the data directory and win over
+//! the config's when the server restarts.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{decimal::Decimal, engine::validate_amount};
+
+/// Longest symbol
+const MAX_SYMBOL_LENGTH: usize = 16;
+
+/// Longest name of an asset
+const MAX_ASSET_LENGTH: usize = 12;
+
+/// Market of a symbol, see the module docs
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Market {
+    pub(crate) symbol: String,
+    /// Asset bought and sold, e.g. `XGAL`
+    pub(crate) base: String,
+    /// Asset prices are in, e.g. `USD`
+    pub(crate) quote: String,
+    /// Step of prices
+    pub(crate) tick_size: Decimal,
+    /// Step of quantities
+    pub(crate) lot_size: Decimal,
+    /// Least price times quantity of a limit order, none unless given
+    #[serde(default = "zero")]
+    pub(crate) min_notional: Decimal,
+    #[serde(default)]
+    pub(crate) status: MarketStatus,
+}
+
+fn zero() -> Decimal {
+    Decimal::ZERO
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MarketStatus {
+    #[default]
+    Trading,
+    /// Takes no new orders, see the module docs
+    Halted,
+}
+
+impl MarketStatus {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            MarketStatus::Trading => "trading",
+            MarketStatus::Halted => "halted",
+        }
+    }
+}
+
+/// Market which can't be listed, with the field at fault
+#[derive(Debug, PartialEq)]
+pub(crate) struct MarketError {
+    pub(crate) field: &'static str,
+    pub(crate) reason: String,
+}
+
+impl Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+impl Market {
+    pub(crate) fn validate(&self) -> Result<(), MarketError> {
+        let invalid = |field, reason: String| Err(MarketError { field, reason });
+        let symbol_chars = |c: char| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-';
+        if self.symbol.is_empty()
+            || self.symbol.len() > MAX_SYMBOL_LENGTH
+            || !self.symbol.chars().all(symbol_chars)
+        {
+            let reason = format!(
+                "must be 1 to {} uppercase letters, digits or `-`",
+                MAX_SYMBOL_LENGTH
+            );
+            return invalid("symbol", reason);
+        }
+        let asset_chars = |c: char| c.is_ascii_uppercase() || c.is_ascii_digit();
+        for (field, asset) in [("base", &self.base), ("quote", &self.quote)] {
+            if asset.is_empty() || asset.len() > MAX_ASSET_LENGTH || !asset.chars().all(asset_chars)
+            {
+                let reason = format!(
+                    "must be 1 to {} uppercase letters or digits",
+                    MAX_ASSET_LENGTH
+                );
+                return invalid(field, reason);
+            }
+        }
+        if self.base == self.quote {
+            return invalid("quote", String::from("must not be the base asset"));
+        }
+        for (field, step) in [("tick_size", self.tick_size), ("lot_size", self.lot_size)] {
+            validate_amount(field, step).or_else(|e| invalid(field, e.reason))?;
+        }
+        if !self.min_notional.is_zero() {
+            validate_amount("min_notional", self.min_notional)
+                .or_else(|e| invalid("min_notional", e.reason))?;
+        }
+        Ok(())
+    }
+}
+
+/// Listed markets, in memory and those listed through the admin API on disk
+#[derive(Debug)]
+pub(crate) struct MarketStore {
+    path: PathBuf,
+    markets: RwLock<BTreeMap<String, Market>>,
+    /// Markets listed through the admin API, as the file has them, held while
+    /// one is listed so the file and the engine see listings in one order
+    stored: Mutex<BTreeMap<String, Market>>,
+}
+
+impl MarketStore {
+    /// Markets of the config, overridden by those in the file
+    pub(crate) fn open(path: impl Into<PathBuf>, configured: &[Market]) -> io::Result<MarketStore> {
+        let path = path.into();
+        let stored: Vec<Market> = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                let message = format!("{}: {}", path.display(), e);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let stored: BTreeMap<_, _> = stored
+            .into_iter()
+            .map(|market| (market.symbol.clone(), market))
+            .collect();
+        let mut markets: BTreeMap<_, _> = configured
+            .iter()
+            .map(|market| (market.symbol.clone(), market.clone()))
+            .collect();
+        markets.extend(stored.clone());
+        Ok(MarketStore {
+            path,
+            markets: RwLock::new(markets),
+            stored: Mutex::new(stored),
+        })
+    }
+
+    /// Every market, by symbol
+    pub(crate) fn list(&self) -> Vec<Market> {
+        self.markets.read().unwrap().values().cloned().collect()
+    }
+
+    pub(crate) fn get(&self, symbol: &str) -> Option<Market> {
+        self.markets.read().unwrap().get(symbol).cloned()
+    }
+
+    /// Lists the market once `list` has listed it elsewhere, e.g. in the
+    /// engine, and writes it to the file
+    pub(crate) fn set<E: From<io::Error>>(
+        &self,
+        market: Market,
+        list: impl FnOnce(&Market) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut stored = self.stored.lock().unwrap();
+        list(&market)?;
+        let mut updated = stored.clone();
+        updated.insert(market.symbol.clone(), market.clone());
+        let markets: Vec<_> = updated.values().collect();
+        let partial = self.path.with_extension("partial");
+        fs::write(
+            &partial,
+            serde_json::to_vec_pretty(&markets).map_err(io::Error::from)?,
+        )?;
+        fs::rename(&partial, &self.path)?;
+        *stored = updated;
+        self.markets
+            .write()
+            .unwrap()
+            .insert(market.symbol.clone(), market);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(symbol: &str) -> Market {
+        let (base, quote) = symbol.split_once('-').unwrap();
+        Market {
+            symbol: symbol.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            tick_size: "0.01".parse().unwrap(),
+            lot_size: "0.001".parse().unwrap(),
+            min_notional: Decimal::ZERO,
+            status: MarketStatus::Trading,
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(market("XGAL-USD").validate(), Ok(()));
+        let field = |market: Market| market.validate().unwrap_err().field;
+        assert_eq!(field(market("xgal-usd")), "symbol");
+        let same_assets = Market {
+            quote: String::from("XGAL"),
+            ..market("XGAL-USD")
+        };
+        assert_eq!(field(same_assets), "quote");
+        let no_tick = Market {
+            tick_size: Decimal::ZERO,
+            ..market("XGAL-USD")
+        };
+        assert_eq!(field(no_tick), "tick_size");
+        let fine_lots = Market {
+            lot_size: "0.000000001".parse().unwrap(),
+            ..market("XGAL-USD")
+        };
+        assert_eq!(
+            fine_lots.validate().unwrap_err().to_string(),
+            "`lot_size`: must have at most 8 decimal places"
+        );
+
+        let read: Market = serde_json::from_str(
+            r#"{"symbol": "XGAL-USD", "base": "XGAL", "quote": "USD",
+                "tick_size": "0.01", "lot_size": 0.001}"#,
+        )
+        .unwrap();
+        assert_eq!(read, market("XGAL-USD"));
+    }
+
+    #[test]
+    fn store() {
+        let path = std::env::temp_dir().join(format!("markets-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let configured = [market("XGAL-USD"), market("XORB-USD")];
+        let store = MarketStore::open(&path, &configured).unwrap();
+        assert_eq!(store.list(), configured);
+
+        let halted = Market {
+            status: MarketStatus::Halted,
+            ..market("XGAL-USD")
+        };
+        store
+            .set(halted.clone(), |_| Ok::<_, io::Error>(()))
+            .unwrap();
+        let failed = store.set(market("XNEB-USD"), |_| {
+            Err(io::Error::other("engine stopped"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(store.get("XGAL-USD"), Some(halted.clone()));
+        assert_eq!(store.get("XNEB-USD"), None);
+
+        // Listings through the admin API win over the config after a restart
+        let store = MarketStore::open(&path, &configured).unwrap();
+        assert_eq!(store.list(), [halted, market("XORB-USD")]);
+        fs::remove_file(&path).unwrap();
+    }
+}
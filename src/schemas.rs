@@ -0,0 +1,295 @@
+//! Schema registry of the server, which lets clients send messages that name
+//! their schema by its fingerprint rather than carrying their field names
+//!
+//! `POST /v1/schemas` registers the schema source in the request body and
+//! answers with its fingerprint, `GET /v1/schemas/{fingerprint}` answers with
+//! the canonical form of a registered schema. `POST /v1/decode` reads a
+//! galacticbuf message and answers with its fields as JSON, fields written
+//! with field IDs named by the schema of the message's fingerprint.
+//!
+//! Schemas are kept in memory and written to a directory, one
+//! `{fingerprint}.gbs` file each, so they survive a restart.
+
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::PathBuf,
+    sync::RwLock,
+};
+
+use rouille::Request;
+use serde_json::json;
+
+use crate::config::Limits;
+use crate::galacticbuf::{
+    DeserializeError, Encoding, FINGERPRINT_SIZE, Message, format_fingerprint, parse_fingerprint,
+    schema::{Schema, SchemaError, SchemaRegistry},
+};
+use crate::server::{ApiError, ApiResponse, read_body};
+
+/// Registered schemas, in memory and on disk
+#[derive(Debug)]
+pub struct SchemaStore {
+    dir: PathBuf,
+    registry: RwLock<SchemaRegistry>,
+    /// Bounds on request bodies and the messages in them
+    limits: Limits,
+}
+
+/// Schema which couldn't be registered
+#[derive(Debug)]
+pub(crate) enum StoreError {
+    Schema(SchemaError),
+    Io(io::Error),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Schema(e) => write!(f, "invalid schema: {}", e),
+            StoreError::Io(e) => write!(f, "schema not stored: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl SchemaStore {
+    /// Store of the schemas in `dir`, which is created when it is missing
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<SchemaStore> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut registry = SchemaRegistry::default();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "gbs") {
+                continue;
+            }
+            let source = fs::read_to_string(&path)?;
+            let schema = Schema::parse(&source).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?;
+            registry.register(schema);
+        }
+        Ok(SchemaStore {
+            dir,
+            registry: RwLock::new(registry),
+            limits: Limits::default(),
+        })
+    }
+
+    /// Store reading request bodies and messages within the limits
+    pub fn with_limits(mut self, limits: Limits) -> SchemaStore {
+        self.limits = limits;
+        self
+    }
+
+    /// Registers the schema of the source, returns its fingerprint and whether
+    /// it is new
+    ///
+    /// The schema is on disk before it is registered, a schema registered
+    /// again is left as it is.
+    pub(crate) fn register(
+        &self,
+        source: &str,
+    ) -> Result<([u8; FINGERPRINT_SIZE], bool), StoreError> {
+        let schema = Schema::parse(source).map_err(StoreError::Schema)?;
+        let fingerprint = schema.fingerprint();
+        let mut registry = self
+            .registry
+            .write()
+            .expect("schema store lock is poisoned");
+        if registry.get(fingerprint).is_some() {
+            return Ok((fingerprint, false));
+        }
+        // Renamed into place so a crash can't leave half a schema behind
+        let path = self
+            .dir
+            .join(format!("{}.gbs", format_fingerprint(&fingerprint)));
+        let partial = path.with_extension("gbs.partial");
+        fs::write(&partial, schema.to_string())?;
+        fs::rename(&partial, &path)?;
+        registry.register(schema);
+        Ok((fingerprint, true))
+    }
+
+    /// Whether schemas can still be written, by writing and removing a file
+    /// in the directory
+    pub(crate) fn check_writable(&self) -> io::Result<()> {
+        let probe = self.dir.join(".probe.partial");
+        fs::write(&probe, b"")?;
+        fs::remove_file(&probe)
+    }
+
+    pub(crate) fn get(&self, fingerprint: [u8; FINGERPRINT_SIZE]) -> Option<Schema> {
+        let registry = self.registry.read().expect("schema store lock is poisoned");
+        registry.get(fingerprint).cloned()
+    }
+
+    /// Reads one message with the schema of its fingerprint, see
+    /// [`SchemaRegistry::deserialize`], the bytes must hold nothing else
+    pub(crate) fn decode(&self, bytes: &[u8]) -> Result<Message, DeserializeError> {
+        let registry = self.registry.read().expect("schema store lock is poisoned");
+        let mut encoding = Encoding::default();
+        encoding.limits = self.limits.message_limits();
+        let (message, rest) = registry.deserialize(bytes, encoding)?;
+        if !rest.is_empty() {
+            let reason = format!("{} bytes following the message", rest.len());
+            return Err(DeserializeError::invalid(reason));
+        }
+        Ok(message)
+    }
+
+    /// `POST /v1/schemas`, 201 with the fingerprint of a new schema, 200 with the
+    /// one of a schema registered before
+    pub fn register_request(&self, request: &Request) -> Result<ApiResponse, ApiError> {
+        let source = String::from_utf8(read_body(request, self.limits.max_body_size)?)
+            .map_err(|_| ApiError::bad_request("invalid_schema", "schema is not UTF-8"))?;
+        let (fingerprint, new) = self.register(&source)?;
+        let fingerprint = json!({ "fingerprint": format_fingerprint(&fingerprint) });
+        let status = if new { 201 } else { 200 };
+        Ok(ApiResponse::new(fingerprint).with_status(status))
+    }
+
+    /// `GET /v1/schemas/{fingerprint}`, the canonical form of the schema
+    pub fn schema_response(&self, fingerprint: &str) -> Result<ApiResponse, ApiError> {
+        let parsed = parse_fingerprint(fingerprint).ok_or_else(|| {
+            ApiError::bad_request("invalid_fingerprint", "fingerprint is not 16 hex digits")
+        })?;
+        let schema = self
+            .get(parsed)
+            .ok_or_else(|| ApiError::not_found(format_args!("schema {}", fingerprint)))?;
+        Ok(ApiResponse::text(schema.to_string()))
+    }
+
+    /// `POST /v1/decode`, the fields of the message as JSON
+    pub fn decode_request(&self, request: &Request) -> Result<ApiResponse, ApiError> {
+        let bytes = read_body(request, self.limits.max_body_size)?;
+        let message = self.decode(&bytes)?;
+        Ok(ApiResponse::new(message.to_json()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::{FieldValue, MessageBuilder, MessageType, Serializable};
+
+    const SCHEMA: &str = "message Order = 1 { price: integer = 1, side: string = 2 }";
+
+    /// Store in a directory of its own, removed again when the test is done
+    struct TempStore(SchemaStore);
+
+    impl TempStore {
+        fn new(name: &str) -> TempStore {
+            let dir = std::env::temp_dir().join(format!("schemas-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempStore(SchemaStore::open(dir).unwrap())
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.dir);
+        }
+    }
+
+    fn request(method: &str, url: &str, body: &[u8]) -> Request {
+        Request::fake_http(method, url, vec![], body.to_vec())
+    }
+
+    #[test]
+    fn schemas_survive_a_restart() {
+        let store = TempStore::new("restart");
+        let (fingerprint, new) = store.0.register(SCHEMA).unwrap();
+        assert!(new);
+        assert_eq!(store.0.register(SCHEMA).unwrap(), (fingerprint, false));
+        assert!(matches!(
+            store.0.register("message Order {"),
+            Err(StoreError::Schema(_))
+        ));
+
+        let reopened = SchemaStore::open(&store.0.dir).unwrap();
+        let schema = reopened.get(fingerprint).unwrap();
+        assert_eq!(schema, Schema::parse(SCHEMA).unwrap());
+    }
+
+    #[test]
+    fn endpoints() {
+        let store = TempStore::new("endpoints");
+        let response = store
+            .0
+            .register_request(&request("POST", "/schemas", SCHEMA.as_bytes()))
+            .unwrap();
+        let fingerprint = Schema::parse(SCHEMA).unwrap().fingerprint();
+        let hex = format_fingerprint(&fingerprint);
+        assert_eq!(
+            response,
+            ApiResponse::new(json!({ "fingerprint": hex })).with_status(201)
+        );
+        let error = store
+            .0
+            .register_request(&request("POST", "/schemas", b"enum {"))
+            .unwrap_err();
+        assert_eq!((error.status, error.code), (400, "invalid_schema"));
+
+        assert_eq!(
+            store.0.schema_response(&hex).unwrap(),
+            ApiResponse::text(
+                "message Order = 1 {\n    price: integer = 1\n    side: string = 2\n}\n"
+            )
+        );
+        let error = store.0.schema_response("00000000000000ff").unwrap_err();
+        assert_eq!(error.status, 404);
+        assert_eq!(error.message, "schema 00000000000000ff not found");
+        let error = store.0.schema_response("order").unwrap_err();
+        assert_eq!((error.status, error.code), (400, "invalid_fingerprint"));
+
+        // Written with field IDs, named again by the registered schema
+        let schema = store.0.get(fingerprint).unwrap();
+        let message = MessageBuilder::new()
+            .with_type(MessageType(1))
+            .with_fingerprint(fingerprint)
+            .field("price", FieldValue::Integer(12))
+            .field("side", "buy".into())
+            .build()
+            .unwrap();
+        let bytes = schema
+            .serialize_with_ids(&message, Encoding::default())
+            .unwrap();
+        let response = store
+            .0
+            .decode_request(&request("POST", "/decode", &bytes))
+            .unwrap();
+        assert_eq!(
+            response,
+            ApiResponse::new(json!({ "price": 12, "side": "buy" }))
+        );
+
+        let unknown = MessageBuilder::new()
+            .with_fingerprint([1; FINGERPRINT_SIZE])
+            .build()
+            .unwrap()
+            .serialize()
+            .unwrap();
+        let error = store
+            .0
+            .decode_request(&request("POST", "/decode", &unknown))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ApiError::bad_request(
+                "invalid_message",
+                "unknown schema of fingerprint 0101010101010101 at byte 0"
+            )
+            .with_details(json!({ "offset": 0 }))
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! HTTP server of the exchange, routing requests to their handlers
+//!
+//! `GET /health` answers as long as the process does, `GET /ready` only while
+//! the components requests need work, with 503 and the failing ones
+//! otherwise. `GET /version` tells which build is running. `POST /v1/accounts`
+//! registers an account with an API key, `POST /v1/orders` submits an order of
+//! the key's account to the matching engine, once however often it is retried
+//! with the same `Idempotency-Key` header. `GET /v1/markets` lists the markets
+//! orders are taken for, `GET /v1/markets/{symbol}/trades` the trades made in
+//! one, `GET /v1/markets/{symbol}/ticker` its last 24 hours and `GET
+//! /v1/markets/{symbol}/candles` its prices over time.
+//!
+//! [`Server::handle`] answers a single request without a socket, so tests can
+//! call the routes in-process, [`Server::run`] serves them on the listen
+//! address of the config. Each request is handled in a `request` span with
+//! its ID, method, path, status and duration in milliseconds.
+//!
+//! The ID of a request is the one its `X-Request-Id` header gives, or a new
+//! random UUID when it has none that fits in a header. Every response echoes
+//! it in its `X-Request-Id` header, error responses name it in their body too,
+//! see [`ApiError`].
+//!
+//! Endpoints answer in JSON or galacticbuf, as the `Accept` header of the
+//! request prefers, see [`ApiResponse`].
+
+use std::{
+    error::Error,
+    io::{self, Read},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rouille::{Request, Response};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::field::Empty;
+
+use crate::{
+    accounts::{AccountId, AccountStore},
+    candles::CandleStore,
+    config::Config,
+    engine::{Engine, EngineHandle},
+    galacticbuf::{VERSIONS, format_uuid},
+    markets::MarketStore,
+    schemas::SchemaStore,
+    ticker::{self, TickerStats},
+    trades::TradeHistory,
+};
+
+mod accounts;
+mod content;
+mod error;
+mod idempotency;
+mod markets;
+mod orders;
+mod routes;
+
+use self::{content::Codec, idempotency::IdempotencyCache};
+
+pub use self::{content::ApiResponse, error::ApiError};
+
+/// Header carrying the ID of a request and of its response
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request ID taken from a client
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Routes and the state their handlers share
+#[derive(Debug)]
+pub struct Server {
+    config: Config,
+    schemas: SchemaStore,
+    markets: MarketStore,
+    accounts: AccountStore,
+    trades: Arc<TradeHistory>,
+    tickers: Arc<TickerStats>,
+    candles: Arc<CandleStore>,
+    engine: EngineHandle,
+    idempotency: IdempotencyCache,
+}
+
+impl Server {
+    /// Server of the settings, with the schemas, markets, accounts and trades
+    /// of earlier runs loaded from the data directory and the matching engine
+    /// started
+    pub fn new(config: Config) -> io::Result<Server> {
+        let schemas =
+            SchemaStore::open(config.data_dir.join("schemas"))?.with_limits(config.limits);
+        let markets = MarketStore::open(config.data_dir.join("markets.json"), &config.markets)?;
+        let accounts = AccountStore::open(config.data_dir.join("accounts.json"))?;
+        let trades = Arc::new(TradeHistory::open(config.data_dir.join("trades"))?);
+        let mut engine = Engine::new(markets.list());
+        engine.continue_trades_after(trades.last_id());
+        let tickers = Arc::new(TickerStats::default());
+        trades.replay(SystemTime::now() - ticker::WINDOW, tickers.as_ref())?;
+        let candles = Arc::new(CandleStore::default());
+        trades.replay(SystemTime::UNIX_EPOCH, candles.as_ref())?;
+        engine.subscribe(trades.clone());
+        engine.subscribe(tickers.clone());
+        engine.subscribe(candles.clone());
+        let engine = EngineHandle::spawn(engine)?;
+        let idempotency = IdempotencyCache::new(Duration::from_secs(config.idempotency_ttl_secs));
+        Ok(Server {
+            config,
+            schemas,
+            markets,
+            accounts,
+            trades,
+            tickers,
+            candles,
+            engine,
+            idempotency,
+        })
+    }
+
+    /// Response to the request
+    pub fn handle(&self, request: &Request) -> Response {
+        let request_id = request_id(request);
+        let span = tracing::info_span!(
+            "request",
+            request_id,
+            method = request.method(),
+            path = request.url(),
+            status = Empty,
+            duration_ms = Empty,
+        );
+        let _entered = span.enter();
+        let start = Instant::now();
+        let response = routes::route(self, request).unwrap_or_else(|error| {
+            let codec = Codec::accepted(request.header("Accept")).unwrap_or(Codec::Json);
+            error.into_response(&request_id, codec)
+        });
+        let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+        span.record("status", response.status_code);
+        span.record("duration_ms", duration_ms);
+        tracing::info!(
+            status = response.status_code,
+            duration_ms,
+            "request handled"
+        );
+        response.with_unique_header(REQUEST_ID_HEADER, request_id)
+    }
+
+    /// Account of the API key the request carries, 401 when it carries none
+    /// or one which is unknown or revoked
+    fn authenticate(&self, request: &Request) -> Result<AccountId, ApiError> {
+        let Some(key) = request
+            .header("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+        else {
+            let message = "API key missing, send it as `Authorization: Bearer {key}`";
+            return Err(ApiError::new(401, "unauthorized", message));
+        };
+        self.accounts
+            .authenticate(key)
+            .ok_or_else(|| ApiError::new(401, "unauthorized", "API key unknown or revoked"))
+    }
+
+    /// Checks the request carries the admin token of the config, 401 when it
+    /// doesn't, 403 when the config has none
+    fn authorize_admin(&self, request: &Request) -> Result<(), ApiError> {
+        let Some(token) = &self.config.admin_token else {
+            let message = "admin API is disabled, the config has no `admin_token`";
+            return Err(ApiError::new(403, "admin_disabled", message));
+        };
+        let given = request
+            .header("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+        // Compared as digests, so how long it takes tells nothing of the token
+        match given.is_some_and(|given| Sha256::digest(given) == Sha256::digest(token)) {
+            true => Ok(()),
+            false => Err(ApiError::new(
+                401,
+                "unauthorized",
+                "admin token missing or wrong",
+            )),
+        }
+    }
+
+    /// Each component requests depend on, with the reason it fails if it does
+    fn readiness(&self) -> Vec<(&'static str, Result<(), String>)> {
+        vec![
+            (
+                "persistence",
+                self.schemas.check_writable().map_err(|e| e.to_string()),
+            ),
+            ("matching_engine", self.engine.check()),
+        ]
+    }
+
+    /// `GET /ready`, 200 when every component works, 503 naming the failing
+    /// ones otherwise
+    fn ready_response(&self) -> Result<ApiResponse, ApiError> {
+        let failing: Vec<_> = self
+            .readiness()
+            .into_iter()
+            .filter_map(|(component, check)| {
+                let reason = check.err()?;
+                tracing::warn!(component, reason, "not ready");
+                Some(json!({ "component": component, "reason": reason }))
+            })
+            .collect();
+        if !failing.is_empty() {
+            let error = ApiError::new(503, "not_ready", "components are failing");
+            return Err(error.with_details(json!({ "failing": failing })));
+        }
+        Ok(ApiResponse::new(json!({ "status": "ready" })))
+    }
+
+    /// Serves requests on the listen address until the process ends, fails
+    /// when the address can't be bound
+    pub fn run(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let listen = self.config.listen;
+        let workers = self.config.workers;
+        tracing::info!(%listen, workers, "listening");
+        rouille::Server::new(listen, move |request| self.handle(request))?
+            .pool_size(workers)
+            .run();
+        Ok(())
+    }
+}
+
+/// `GET /version`, the crate version, the git commit and time of the build,
+/// and the galacticbuf protocol versions spoken, from `build.rs`
+fn version_response() -> ApiResponse {
+    ApiResponse::new(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": option_env!("GALACTIC_GIT_SHA").unwrap_or("unknown"),
+        "build_timestamp": option_env!("GALACTIC_BUILD_TIMESTAMP").unwrap_or("unknown"),
+        "protocol_versions": VERSIONS,
+    }))
+}
+
+/// ID of the request, see the module docs
+fn request_id(request: &Request) -> String {
+    let given = request.header(REQUEST_ID_HEADER).filter(|id| {
+        !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LENGTH
+            && id.bytes().all(|b| b.is_ascii_graphic())
+    });
+    if let Some(id) = given {
+        return id.to_string();
+    }
+    // Version 4 UUID
+    let mut uuid = [0; 16];
+    getrandom::fill(&mut uuid).expect("the OS has random bytes");
+    uuid[6] = uuid[6] & 0x0F | 0x40;
+    uuid[8] = uuid[8] & 0x3F | 0x80;
+    format_uuid(&uuid)
+}
+
+/// Body of the request, 413 when it is larger than `max` bytes
+pub(crate) fn read_body(request: &Request, max: u64) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = vec![];
+    if let Some(data) = request.data() {
+        data.take(max + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|_| ApiError::bad_request("unreadable_body", "request body can't be read"))?;
+    }
+    if bytes.len() as u64 > max {
+        return Err(ApiError::too_large(max));
+    }
+    Ok(bytes)
+}
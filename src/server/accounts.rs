@@ -0,0 +1,170 @@
+//! Account endpoints, which register accounts and manage their API keys
+//!
+//! `POST /v1/accounts` registers an account, with an optional `name`, e.g.
+//! `{"name": "Orbital Traders"}`, and answers 201 with it and its first API
+//! key, the secret in `api_key.key`:
+//!
+//! ```json
+//! {"id": 7, "name": "Orbital Traders", "created_at": "...",
+//!  "api_key": {"id": "9f86d081884c7d65", "label": null, "created_at": "...",
+//!              "revoked_at": null, "key": "gx_9f86d081884c7d65_..."}}
+//! ```
+//!
+//! The key is only ever shown then, as `POST /v1/accounts/{id}/keys` shows the
+//! ones it issues, with an optional `label`. `GET /v1/accounts/{id}/keys`
+//! lists the account's keys without their secrets, revoked ones included, and
+//! `DELETE /v1/accounts/{id}/keys/{key_id}` revokes one, which is refused from
+//! then on. `GET /v1/accounts/{id}` answers with the account.
+//!
+//! Every endpoint but the registration needs a key of the account, as
+//! `Authorization: Bearer {key}`, 401 without one and 403 with one of another
+//! account.
+
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{ApiError, ApiResponse, Server, content::ApiRequest};
+use crate::{
+    accounts::{Account, AccountId, ApiKey, IssuedKey, validate_name},
+    date::format_timestamp,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewAccount {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewKey {
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// `POST /v1/accounts`, see the module docs
+pub(super) fn create_request(server: &Server, api: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let new: NewAccount = parse(api.body(server, "NewAccount")?)?;
+    if let Some(name) = &new.name {
+        validate_name("name", name)?;
+    }
+    let (account, issued) = server.accounts.create(new.name)?;
+    tracing::info!(account_id = account.id, "account registered");
+    let mut body = account_json(&account);
+    body["api_key"] = issued_json(&issued);
+    Ok(ApiResponse::new(body).with_status(201))
+}
+
+/// `GET /v1/accounts/{id}`
+pub(super) fn account_response(
+    server: &Server,
+    api: &ApiRequest,
+    id: &str,
+) -> Result<ApiResponse, ApiError> {
+    let id = authorize_account(server, api, id)?;
+    let account = server
+        .accounts
+        .account(id)
+        .ok_or_else(|| ApiError::not_found(format_args!("account {}", id)))?;
+    Ok(ApiResponse::new(account_json(&account)))
+}
+
+/// `POST /v1/accounts/{id}/keys`, see the module docs
+pub(super) fn issue_key_request(
+    server: &Server,
+    api: &ApiRequest,
+    id: &str,
+) -> Result<ApiResponse, ApiError> {
+    let id = authorize_account(server, api, id)?;
+    let new: NewKey = parse(api.body(server, "NewKey")?)?;
+    if let Some(label) = &new.label {
+        validate_name("label", label)?;
+    }
+    let issued = server
+        .accounts
+        .issue_key(id, new.label)?
+        .ok_or_else(|| ApiError::not_found(format_args!("account {}", id)))?;
+    tracing::info!(account_id = id, key_id = issued.key.id, "API key issued");
+    Ok(ApiResponse::new(issued_json(&issued)).with_status(201))
+}
+
+/// `GET /v1/accounts/{id}/keys`
+pub(super) fn keys_response(
+    server: &Server,
+    api: &ApiRequest,
+    id: &str,
+) -> Result<ApiResponse, ApiError> {
+    let id = authorize_account(server, api, id)?;
+    let account = server
+        .accounts
+        .account(id)
+        .ok_or_else(|| ApiError::not_found(format_args!("account {}", id)))?;
+    let keys: Vec<_> = account.keys.iter().map(key_json).collect();
+    Ok(ApiResponse::new(json!({ "keys": keys })))
+}
+
+/// `DELETE /v1/accounts/{id}/keys/{key_id}`, see the module docs
+pub(super) fn revoke_key_request(
+    server: &Server,
+    api: &ApiRequest,
+    id: &str,
+    key_id: &str,
+) -> Result<ApiResponse, ApiError> {
+    let id = authorize_account(server, api, id)?;
+    let key = server
+        .accounts
+        .revoke_key(id, key_id)?
+        .ok_or_else(|| ApiError::not_found(format_args!("API key {}", key_id)))?;
+    tracing::info!(account_id = id, key_id, "API key revoked");
+    Ok(ApiResponse::new(key_json(&key)))
+}
+
+/// Account of the path, once the request's API key is found to be one of it
+fn authorize_account(server: &Server, api: &ApiRequest, id: &str) -> Result<AccountId, ApiError> {
+    let id: AccountId = id
+        .parse()
+        .map_err(|_| ApiError::not_found(format_args!("account {}", id)))?;
+    if server.authenticate(api.http)? != id {
+        let message = format!("API key is not one of account {}", id);
+        return Err(ApiError::new(403, "forbidden", message));
+    }
+    Ok(id)
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(body: Value) -> Result<T, ApiError> {
+    serde_json::from_value(body)
+        .map_err(|e| ApiError::bad_request("invalid_account", e.to_string()))
+}
+
+/// Account as the API shows it, without its keys
+fn account_json(account: &Account) -> Value {
+    json!({
+        "id": account.id,
+        "name": account.name,
+        "created_at": timestamp(account.created_at),
+    })
+}
+
+/// Key as the API shows it, without its secret
+fn key_json(key: &ApiKey) -> Value {
+    json!({
+        "id": key.id,
+        "label": key.label,
+        "created_at": timestamp(key.created_at),
+        "revoked_at": key.revoked_at.map(timestamp),
+    })
+}
+
+/// Key as it is issued, with its secret
+fn issued_json(issued: &IssuedKey) -> Value {
+    let mut body = key_json(&issued.key);
+    body["key"] = json!(issued.secret);
+    body
+}
+
+fn timestamp(millis: u64) -> String {
+    format_timestamp(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+}
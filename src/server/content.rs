@@ -0,0 +1,413 @@
+//! Content negotiation, so every API endpoint speaks JSON and galacticbuf
+//!
+//! [`ApiRequest::negotiate`] picks the [`Codec`] of the response from the
+//! `Accept` header before the request is routed, JSON unless galacticbuf is
+//! preferred, 406 when neither is acceptable. Handlers read the body with
+//! [`ApiRequest::body`] in the codec its `Content-Type` names, 415 for other
+//! types, and answer with an [`ApiResponse`] of a JSON value, which is then
+//! written in the negotiated codec. Errors are written in it too.
+//!
+//! A galacticbuf body is read like `POST /v1/decode` reads one and checked
+//! against the message of `api.gbs` declaring the shape of the endpoint's
+//! requests, its fields are then handled as the members of a JSON body, see
+//! `galacticbuf::json`. A galacticbuf response is the untyped message of the
+//! members of the JSON value.
+
+use std::sync::LazyLock;
+
+use rouille::{Request, Response};
+use serde_json::{Value, json};
+
+use super::{ApiError, Server, read_body};
+use crate::galacticbuf::{Message, Serializable, schema::Schema};
+
+/// Shapes of galacticbuf request bodies, by the name of their message
+static API_SCHEMA: LazyLock<Schema> =
+    LazyLock::new(|| Schema::parse(include_str!("api.gbs")).expect("api.gbs is a valid schema"));
+
+/// Format of a body
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Codec {
+    Json,
+    Galacticbuf,
+}
+
+impl Codec {
+    /// Every codec, the one answering when the client likes them alike first
+    const ALL: [Codec; 2] = [Codec::Json, Codec::Galacticbuf];
+
+    pub(crate) fn media_type(self) -> &'static str {
+        match self {
+            Codec::Json => "application/json",
+            Codec::Galacticbuf => "application/x-galacticbuf",
+        }
+    }
+
+    /// Codec the `Accept` header prefers, JSON when there is none, `None` when
+    /// it accepts neither
+    pub(crate) fn accepted(accept: Option<&str>) -> Option<Codec> {
+        let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+            return Some(Codec::Json);
+        };
+        let mut best = None;
+        for codec in Codec::ALL {
+            let quality = codec.quality(accept);
+            if quality > best.map_or(0.0, |(_, best)| best) {
+                best = Some((codec, quality));
+            }
+        }
+        best.map(|(codec, _)| codec)
+    }
+
+    /// Quality the `Accept` header gives the codec, that of the most specific
+    /// media range matching it, 0 when none does
+    fn quality(self, accept: &str) -> f32 {
+        let (kind, _) = self.media_type().split_once('/').unwrap();
+        let mut best = (0, 0.0);
+        for range in accept.split(',') {
+            let mut parameters = range.split(';');
+            let media_range = parameters.next().unwrap_or_default().trim();
+            let specificity = if media_range.eq_ignore_ascii_case(self.media_type()) {
+                3
+            } else if media_range
+                .strip_suffix("/*")
+                .is_some_and(|range| range.eq_ignore_ascii_case(kind))
+            {
+                2
+            } else if media_range == "*/*" {
+                1
+            } else {
+                continue;
+            };
+            let quality = parameters
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .map_or(1.0, |quality| quality.trim().parse().unwrap_or(0.0));
+            if specificity > best.0 {
+                best = (specificity, quality);
+            }
+        }
+        best.1
+    }
+
+    /// Codec of the `Content-Type` header, JSON when there is none, 415 for
+    /// other types
+    fn of_content_type(content_type: Option<&str>) -> Result<Codec, ApiError> {
+        let Some(content_type) = content_type else {
+            return Ok(Codec::Json);
+        };
+        let (essence, _) = content_type.split_once(';').unwrap_or((content_type, ""));
+        Codec::ALL
+            .into_iter()
+            .find(|codec| essence.trim().eq_ignore_ascii_case(codec.media_type()))
+            .ok_or_else(|| {
+                let message = format!(
+                    "request body must be {} or {}",
+                    Codec::Json.media_type(),
+                    Codec::Galacticbuf.media_type()
+                );
+                ApiError::new(415, "unsupported_media_type", message)
+            })
+    }
+
+    /// Response of the value in the codec, 500 when galacticbuf can't carry it
+    pub(crate) fn response(self, status: u16, body: &Value) -> Result<Response, ApiError> {
+        let response = match self {
+            Codec::Json => Response::json(body),
+            Codec::Galacticbuf => {
+                let encoding_failed =
+                    |reason: String| ApiError::new(500, "encoding_failed", reason);
+                let message =
+                    Message::from_json(body).map_err(|e| encoding_failed(e.to_string()))?;
+                let bytes = message
+                    .serialize()
+                    .map_err(|e| encoding_failed(e.to_string()))?;
+                Response::from_data(self.media_type(), bytes)
+            }
+        };
+        Ok(response.with_status_code(status))
+    }
+}
+
+/// Request as API handlers see it, see the module docs
+#[derive(Clone, Copy)]
+pub(crate) struct ApiRequest<'a> {
+    pub(crate) http: &'a Request,
+    /// Codec of the response
+    accept: Codec,
+}
+
+impl<'a> ApiRequest<'a> {
+    /// Request answered in the codec its `Accept` header prefers, 406 when it
+    /// accepts none
+    pub(crate) fn negotiate(request: &'a Request) -> Result<ApiRequest<'a>, ApiError> {
+        let accept = Codec::accepted(request.header("Accept")).ok_or_else(|| {
+            let message = format!(
+                "responses are {} or {}",
+                Codec::Json.media_type(),
+                Codec::Galacticbuf.media_type()
+            );
+            ApiError::new(406, "not_acceptable", message)
+        })?;
+        Ok(ApiRequest {
+            http: request,
+            accept,
+        })
+    }
+
+    /// Body of the request as JSON, a galacticbuf body must match the message
+    /// `shape` of the API schema
+    pub(crate) fn body(&self, server: &Server, shape: &str) -> Result<Value, ApiError> {
+        let codec = Codec::of_content_type(self.http.header("Content-Type"))?;
+        let body = read_body(self.http, server.config.limits.max_body_size)?;
+        if codec == Codec::Json {
+            return serde_json::from_slice(&body)
+                .map_err(|e| ApiError::bad_request("invalid_json", e.to_string()));
+        }
+        let message = server.schemas.decode(&body)?;
+        API_SCHEMA
+            .validate_as(shape, &message.body)
+            .map_err(|violations| {
+                let message = violations
+                    .iter()
+                    .map(|violation| violation.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let violations: Vec<_> = violations
+                    .iter()
+                    .map(|violation| {
+                        let reason = violation.kind.to_string();
+                        json!({ "path": violation.path, "reason": reason })
+                    })
+                    .collect();
+                ApiError::bad_request("invalid_message", message)
+                    .with_details(json!({ "violations": violations }))
+            })?;
+        Ok(message.to_json())
+    }
+
+    /// Response in the negotiated codec
+    pub(crate) fn respond(&self, response: ApiResponse) -> Result<Response, ApiError> {
+        let mut http = match response.body {
+            Body::Value(body) => self.accept.response(response.status, &body)?,
+            Body::Text(text) => Response::text(text).with_status_code(response.status),
+        };
+        for (name, value) in response.headers {
+            http = http.with_unique_header(name, value);
+        }
+        Ok(http)
+    }
+}
+
+/// Answer of an API handler, written in the codec the client accepts
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiResponse {
+    status: u16,
+    pub(super) body: Body,
+    headers: Vec<(&'static str, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Body {
+    Value(Value),
+    /// Plain text whatever the client accepts, e.g. the source of a schema
+    Text(String),
+}
+
+impl ApiResponse {
+    /// 200 with the value
+    pub fn new(body: Value) -> ApiResponse {
+        ApiResponse {
+            status: 200,
+            body: Body::Value(body),
+            headers: vec![],
+        }
+    }
+
+    /// 200 with the text
+    pub fn text(text: impl Into<String>) -> ApiResponse {
+        ApiResponse {
+            status: 200,
+            body: Body::Text(text.into()),
+            headers: vec![],
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> ApiResponse {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> ApiResponse {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use super::*;
+    use crate::{
+        config::Config,
+        decimal::Decimal,
+        galacticbuf::{Deserializable, FieldValue, List, MessageBuilder, Object},
+    };
+
+    #[test]
+    fn api_schema() {
+        for shape in ["NewOrder", "AmendOrder", "Batch", "Market"] {
+            assert!(API_SCHEMA.object(shape).is_some(), "{}", shape);
+        }
+    }
+
+    #[test]
+    fn negotiation() {
+        let accepted = |accept| Codec::accepted(Some(accept));
+        assert_eq!(Codec::accepted(None), Some(Codec::Json));
+        assert_eq!(accepted(""), Some(Codec::Json));
+        assert_eq!(accepted("*/*"), Some(Codec::Json));
+        assert_eq!(
+            accepted("application/x-galacticbuf"),
+            Some(Codec::Galacticbuf)
+        );
+        assert_eq!(
+            accepted("application/json;q=0.5, application/x-galacticbuf"),
+            Some(Codec::Galacticbuf)
+        );
+        assert_eq!(
+            accepted("application/*;q=0.2, application/json;q=0.9, */*;q=0"),
+            Some(Codec::Json)
+        );
+        assert_eq!(
+            accepted("Application/X-Galacticbuf; q=0.8, application/json; q=0.1"),
+            Some(Codec::Galacticbuf)
+        );
+        assert_eq!(accepted("text/html"), None);
+        assert_eq!(accepted("*/*;q=0"), None);
+
+        assert_eq!(Codec::of_content_type(None), Ok(Codec::Json));
+        assert_eq!(
+            Codec::of_content_type(Some("application/json; charset=utf-8")),
+            Ok(Codec::Json)
+        );
+        assert_eq!(
+            Codec::of_content_type(Some("text/plain"))
+                .unwrap_err()
+                .status,
+            415
+        );
+    }
+
+    #[test]
+    fn galacticbuf_orders() {
+        let data_dir = std::env::temp_dir().join(format!("content-{}", std::process::id()));
+        let markets = r#"
+            [[markets]]
+            symbol = "XGAL-USD"
+            base = "XGAL"
+            quote = "USD"
+            tick_size = "0.01"
+            lot_size = "1"
+        "#;
+        let config = Config {
+            data_dir: data_dir.clone(),
+            ..Config::from_toml(markets).unwrap()
+        };
+        let server = Server::new(config).unwrap();
+        let (_, api_key) = server.accounts.create(None).unwrap();
+        let send = |url: &str, message: Message, headers: &[(&str, &str)]| {
+            let authorization = format!("Bearer {}", api_key.secret);
+            let headers = [("Authorization", authorization.as_str())]
+                .iter()
+                .chain(headers)
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            let body = message.serialize().unwrap();
+            let request = Request::fake_http("POST", url, headers, body);
+            let response = server.handle(&request);
+            let (mut reader, _) = response.data.into_reader_and_size();
+            let mut body = vec![];
+            reader.read_to_end(&mut body).unwrap();
+            (response.status_code, body)
+        };
+        let post = |message, headers: &[_]| send("/v1/orders", message, headers);
+        let order = |quantity: FieldValue| {
+            MessageBuilder::new()
+                .field("symbol", "XGAL-USD".into())
+                .field("side", "buy".into())
+                .field("type", "limit".into())
+                .field("price", FieldValue::Decimal(Decimal::new(10125, -2)))
+                .field("quantity", quantity)
+                .build()
+                .unwrap()
+        };
+        let content_type = ("Content-Type", Codec::Galacticbuf.media_type());
+        let accept = ("Accept", Codec::Galacticbuf.media_type());
+
+        let (status, body) = post(
+            order(FieldValue::Decimal(Decimal::from(3))),
+            &[content_type, accept],
+        );
+        assert_eq!(status, 201);
+        let (accepted, _) = Message::deserialize(&body, None).unwrap();
+        assert_eq!(accepted.get("status"), Some(&FieldValue::from("open")));
+        assert_eq!(accepted.get("price"), Some(&FieldValue::from("101.25")));
+
+        // Answered in JSON unless asked otherwise
+        let (status, body) = post(
+            order(FieldValue::Decimal(Decimal::from(3))),
+            &[content_type],
+        );
+        assert_eq!(status, 201);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], 2);
+
+        // Errors in the codec accepted too
+        let (status, body) = post(order(FieldValue::Integer(3)), &[content_type, accept]);
+        assert_eq!(status, 400);
+        let (error, _) = Message::deserialize(&body, None).unwrap();
+        assert_eq!(
+            error.get("code"),
+            Some(&FieldValue::from("invalid_message"))
+        );
+        let error = error.to_json();
+        assert_eq!(error["details"]["violations"][0]["path"], "quantity");
+
+        // Without its content type the message isn't JSON
+        let (status, _) = post(order(FieldValue::Decimal(Decimal::from(3))), &[]);
+        assert_eq!(status, 400);
+
+        let orders = [
+            FieldValue::Decimal(Decimal::from(1)),
+            FieldValue::Integer(1),
+        ]
+        .map(|quantity| FieldValue::Object(Object(order(quantity).body)));
+        let batch = MessageBuilder::new()
+            .field(
+                "orders",
+                FieldValue::List(List::from_values(orders[..1].to_vec())),
+            )
+            .build()
+            .unwrap();
+        let (status, body) = send("/v1/orders/batch", batch, &[content_type]);
+        assert_eq!(status, 200);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["results"][0]["status"], 201);
+        let batch = MessageBuilder::new()
+            .field(
+                "orders",
+                FieldValue::List(List::from_values(orders.to_vec())),
+            )
+            .build()
+            .unwrap();
+        let (status, body) = send("/v1/orders/batch", batch, &[content_type]);
+        assert_eq!(status, 400);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["details"]["violations"][0]["path"],
+            "orders[1].quantity"
+        );
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,190 @@
+//! Failed requests as JSON, the same shape from every endpoint
+//!
+//! ```json
+//! {"code": "invalid_message", "message": "invalid utf-8 string at byte 36",
+//!  "details": {"offset": 36, "path": "venue"}, "request_id": "..."}
+//! ```
+//!
+//! `code` is stable for clients to match on, `message` is for people,
+//! `details` is left out when there are none. A client accepting galacticbuf
+//! gets the same fields as a galacticbuf message.
+
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+use rouille::Response;
+use serde_json::{Value, json};
+
+use super::content::Codec;
+use crate::{
+    accounts::AccountError,
+    engine::{OrderError, SubmitError, UpdateError},
+    galacticbuf::DeserializeError,
+    markets::MarketError,
+    schemas::StoreError,
+};
+
+/// Error a handler answers with, see the module docs
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiError {
+    pub status: u16,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: u16, code: &'static str, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> ApiError {
+        self.details = Some(details);
+        self
+    }
+
+    /// 400, the request can't be served as it is
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> ApiError {
+        ApiError::new(400, code, message)
+    }
+
+    /// 400 of a query parameter
+    pub fn invalid_query(parameter: &'static str, reason: &str) -> ApiError {
+        ApiError::bad_request("invalid_query", format!("`{}`: {}", parameter, reason))
+            .with_details(json!({ "parameter": parameter }))
+    }
+
+    /// 404 of a path no route serves
+    pub fn no_route() -> ApiError {
+        ApiError::new(404, "not_found", "no such endpoint")
+    }
+
+    /// 404 of a resource which doesn't exist
+    pub fn not_found(what: impl Display) -> ApiError {
+        ApiError::new(404, "not_found", format!("{} not found", what))
+    }
+
+    /// 413 of a body beyond `max` bytes
+    pub fn too_large(max: u64) -> ApiError {
+        ApiError::new(
+            413,
+            "body_too_large",
+            format!("request body is larger than {} bytes", max),
+        )
+    }
+
+    /// 503 of a request the matching engine can't take
+    pub fn engine_stopped() -> ApiError {
+        ApiError::new(503, "engine_unavailable", "matching engine has stopped")
+    }
+
+    /// Code, message and details of the error
+    pub fn to_json(&self) -> Value {
+        let mut body = json!({ "code": self.code, "message": self.message });
+        if let Some(details) = &self.details {
+            body["details"] = details.clone();
+        }
+        body
+    }
+
+    /// Response naming the request the error answers, in the codec or in
+    /// JSON when the codec can't carry it
+    pub(crate) fn into_response(self, request_id: &str, codec: Codec) -> Response {
+        let mut body = self.to_json();
+        body["request_id"] = json!(request_id);
+        codec
+            .response(self.status, &body)
+            .unwrap_or_else(|_| Response::json(&body).with_status_code(self.status))
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.status, self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<DeserializeError> for ApiError {
+    fn from(e: DeserializeError) -> Self {
+        let mut details = json!({ "offset": e.offset });
+        if !e.path.is_empty() {
+            details["path"] = json!(e.path);
+        }
+        ApiError::bad_request("invalid_message", e.to_string()).with_details(details)
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Schema(_) => ApiError::bad_request("invalid_schema", e.to_string()),
+            StoreError::Io(_) => ApiError::new(500, "storage_failed", e.to_string()),
+        }
+    }
+}
+
+impl From<AccountError> for ApiError {
+    fn from(e: AccountError) -> Self {
+        let field = e.field;
+        ApiError::bad_request("invalid_account", e.to_string())
+            .with_details(json!({ "field": field }))
+    }
+}
+
+impl From<MarketError> for ApiError {
+    fn from(e: MarketError) -> Self {
+        let field = e.field;
+        ApiError::bad_request("invalid_market", e.to_string())
+            .with_details(json!({ "field": field }))
+    }
+}
+
+/// 500 of state which can't be written
+impl From<io::Error> for ApiError {
+    fn from(e: io::Error) -> Self {
+        ApiError::new(500, "storage_failed", e.to_string())
+    }
+}
+
+impl From<OrderError> for ApiError {
+    fn from(e: OrderError) -> Self {
+        let field = e.field;
+        ApiError::bad_request("invalid_order", e.to_string())
+            .with_details(json!({ "field": field }))
+    }
+}
+
+impl From<SubmitError> for ApiError {
+    fn from(e: SubmitError) -> Self {
+        match e {
+            SubmitError::Invalid(e) => e.into(),
+            SubmitError::DuplicateClientOrderId { order_id, .. } => {
+                ApiError::new(409, "duplicate_client_order_id", e.to_string())
+                    .with_details(json!({ "order_id": order_id }))
+            }
+        }
+    }
+}
+
+impl From<UpdateError> for ApiError {
+    fn from(e: UpdateError) -> Self {
+        match e {
+            UpdateError::NotFound(key) => ApiError::not_found(key),
+            // Filled or cancelled between the client's last look and the update
+            UpdateError::Closed(ref order) => {
+                let details = json!({ "status": order.status, "filled_quantity": order.filled });
+                ApiError::new(409, "too_late", e.to_string()).with_details(details)
+            }
+            UpdateError::Invalid(e) => e.into(),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Idempotency keys, so a client can retry a submission without placing the
+//! order twice
+//!
+//! A request with an `Idempotency-Key` header is handled once, the answer is
+//! kept for the retention window of the config and retries with the key by the
+//! same account get it again, with an `Idempotent-Replayed: true` header,
+//! instead of being handled again. A retry arriving while the first request is
+//! still being handled is answered with 409 `request_in_flight`, one with the
+//! key of a request to another endpoint or with another body with 422
+//! `idempotency_key_reused`.
+//!
+//! Server errors aren't kept, the request didn't happen and a retry may
+//! succeed, neither are the answers of requests failing before they are
+//! handled, e.g. with a body which can't be read.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rouille::Request;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::{ApiError, ApiResponse};
+use crate::accounts::AccountId;
+
+/// Header carrying the key of a request
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header marking an answer as that of an earlier request
+const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Longest key taken from a client
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Answers of the requests with a key, see the module docs
+#[derive(Debug)]
+pub(crate) struct IdempotencyCache {
+    /// How long an answer is kept
+    ttl: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Entries by account and key, accounts pick their keys on their own
+    entries: HashMap<(AccountId, String), Entry>,
+    /// Keys of the kept answers, oldest first, to drop them when they expire
+    expiries: VecDeque<(Instant, (AccountId, String))>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Digest of the method, path and body of the request
+    fingerprint: [u8; 32],
+    /// `None` while the request is being handled
+    answer: Option<(Instant, Result<ApiResponse, ApiError>)>,
+}
+
+impl IdempotencyCache {
+    pub(crate) fn new(ttl: Duration) -> IdempotencyCache {
+        IdempotencyCache {
+            ttl,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Answer of `handler` to the account's request with the body, or the
+    /// kept answer of the account's earlier request with its key
+    pub(crate) fn handle(
+        &self,
+        account_id: AccountId,
+        request: &Request,
+        body: &Value,
+        handler: impl FnOnce() -> Result<ApiResponse, ApiError>,
+    ) -> Result<ApiResponse, ApiError> {
+        let Some(key) = request.header(IDEMPOTENCY_KEY_HEADER) else {
+            return handler();
+        };
+        if key.is_empty()
+            || key.len() > MAX_KEY_LENGTH
+            || !key.bytes().all(|byte| byte.is_ascii_graphic())
+        {
+            let reason = format!(
+                "`{}` must be 1 to {} visible ASCII characters",
+                IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH
+            );
+            return Err(ApiError::bad_request("invalid_idempotency_key", reason));
+        }
+        let fingerprint = fingerprint(request, body);
+        let entry_key = (account_id, key.to_string());
+        {
+            let mut state = self.state.lock().unwrap();
+            state.expire(Instant::now());
+            if let Some(entry) = state.entries.get(&entry_key) {
+                if entry.fingerprint != fingerprint {
+                    return Err(ApiError::new(
+                        422,
+                        "idempotency_key_reused",
+                        format!("idempotency key `{}` was sent with another request", key),
+                    ));
+                }
+                return match &entry.answer {
+                    None => Err(ApiError::new(
+                        409,
+                        "request_in_flight",
+                        format!("request with idempotency key `{}` is in progress", key),
+                    )),
+                    Some((_, answer)) => {
+                        tracing::info!(idempotency_key = key, "answer replayed");
+                        answer
+                            .clone()
+                            .map(|response| response.with_header(REPLAYED_HEADER, "true"))
+                    }
+                };
+            }
+            let entry = Entry {
+                fingerprint,
+                answer: None,
+            };
+            state.entries.insert(entry_key.clone(), entry);
+        }
+        let mut in_flight = InFlight {
+            cache: self,
+            key: Some(entry_key),
+        };
+        let answer = handler();
+        in_flight.finish(&answer);
+        answer
+    }
+}
+
+impl State {
+    /// Drops the answers kept beyond their expiry
+    fn expire(&mut self, now: Instant) {
+        while let Some((expiry, _)) = self.expiries.front() {
+            if *expiry > now {
+                break;
+            }
+            let (expiry, key) = self.expiries.pop_front().unwrap();
+            // The key may have been taken again since, by a request which is
+            // still in flight
+            let expired = self.entries.get(&key).is_some_and(|entry| {
+                entry
+                    .answer
+                    .as_ref()
+                    .is_some_and(|(kept_until, _)| *kept_until == expiry)
+            });
+            if expired {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+/// Key of a request being handled, which is freed again if the handler
+/// panics
+struct InFlight<'a> {
+    cache: &'a IdempotencyCache,
+    key: Option<(AccountId, String)>,
+}
+
+impl InFlight<'_> {
+    /// Keeps the answer, unless it is a server error
+    fn finish(&mut self, answer: &Result<ApiResponse, ApiError>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut state = self.cache.state.lock().unwrap();
+        if matches!(answer, Err(error) if error.status >= 500) {
+            state.entries.remove(&key);
+            return;
+        }
+        let expiry = Instant::now() + self.cache.ttl;
+        if let Some(entry) = state.entries.get_mut(&key) {
+            entry.answer = Some((expiry, answer.clone()));
+            state.expiries.push_back((expiry, key));
+        }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key
+            && let Ok(mut state) = self.cache.state.lock()
+        {
+            state.entries.remove(key);
+        }
+    }
+}
+
+/// Digest of the method, path and body of the request
+fn fingerprint(request: &Request, body: &Value) -> [u8; 32] {
+    let mut digest = Sha256::new();
+    digest.update(request.method());
+    digest.update([0]);
+    digest.update(request.url());
+    digest.update([0]);
+    digest.update(body.to_string());
+    digest.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, panic};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::content::Body;
+
+    fn request(key: &str, url: &str) -> Request {
+        let headers = vec![(IDEMPOTENCY_KEY_HEADER.to_string(), key.to_string())];
+        Request::fake_http("POST", url, headers, vec![])
+    }
+
+    #[test]
+    fn replays() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let handled = Cell::new(0);
+        let handler = || {
+            handled.set(handled.get() + 1);
+            Ok(ApiResponse::new(json!({ "id": handled.get() })).with_status(201))
+        };
+        let body = json!({ "quantity": "1" });
+
+        let first = cache
+            .handle(1, &request("a", "/orders"), &body, handler)
+            .unwrap();
+        assert_eq!(first, ApiResponse::new(json!({ "id": 1 })).with_status(201));
+        let replayed = cache.handle(1, &request("a", "/orders"), &body, handler);
+        assert_eq!(replayed, Ok(first.with_header(REPLAYED_HEADER, "true")));
+        assert_eq!(handled.get(), 1);
+
+        let other = cache.handle(1, &request("b", "/orders"), &body, handler);
+        assert_eq!(other.unwrap().body, Body::Value(json!({ "id": 2 })));
+        let unkeyed = Request::fake_http("POST", "/orders", vec![], vec![]);
+        cache.handle(1, &unkeyed, &body, handler).unwrap();
+        cache.handle(1, &unkeyed, &body, handler).unwrap();
+        assert_eq!(handled.get(), 4);
+        // Another account's key, though the same
+        let of_other_account = cache.handle(2, &request("a", "/orders"), &body, handler);
+        assert_eq!(
+            of_other_account.unwrap().body,
+            Body::Value(json!({ "id": 5 }))
+        );
+
+        let code = |answer: Result<ApiResponse, ApiError>| answer.unwrap_err().code;
+        let reused = cache.handle(1, &request("a", "/orders"), &json!({}), handler);
+        assert_eq!(code(reused), "idempotency_key_reused");
+        let reused = cache.handle(1, &request("a", "/orders/batch"), &body, handler);
+        assert_eq!(code(reused), "idempotency_key_reused");
+        let invalid = cache.handle(1, &request("", "/orders"), &body, handler);
+        assert_eq!(code(invalid), "invalid_idempotency_key");
+        let invalid = cache.handle(1, &request("a b", "/orders"), &body, handler);
+        assert_eq!(code(invalid), "invalid_idempotency_key");
+        assert_eq!(handled.get(), 5);
+    }
+
+    #[test]
+    fn errors() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let body = json!({});
+        let rejected = || Err(ApiError::bad_request("invalid_order", "no"));
+        let stopped = || Err(ApiError::engine_stopped());
+        let accepted = || Ok(ApiResponse::new(json!({})));
+
+        assert_eq!(
+            cache.handle(1, &request("a", "/orders"), &body, rejected),
+            rejected()
+        );
+        assert_eq!(
+            cache.handle(1, &request("a", "/orders"), &body, accepted),
+            rejected()
+        );
+
+        assert_eq!(
+            cache.handle(1, &request("b", "/orders"), &body, stopped),
+            stopped()
+        );
+        assert_eq!(
+            cache.handle(1, &request("b", "/orders"), &body, accepted),
+            accepted()
+        );
+    }
+
+    #[test]
+    fn in_flight() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let body = json!({});
+        let answer = cache.handle(1, &request("a", "/orders"), &body, || {
+            let retry = cache.handle(1, &request("a", "/orders"), &body, || unreachable!());
+            assert_eq!(retry.unwrap_err().code, "request_in_flight");
+            Ok(ApiResponse::new(json!({})))
+        });
+        assert!(answer.is_ok());
+
+        let panicked = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            cache.handle(1, &request("b", "/orders"), &body, || {
+                panic!("handler failed")
+            })
+        }));
+        assert!(panicked.is_err());
+        let retry = cache.handle(1, &request("b", "/orders"), &body, || {
+            Ok(ApiResponse::new(json!({})))
+        });
+        assert_eq!(retry, Ok(ApiResponse::new(json!({}))));
+    }
+
+    #[test]
+    fn expiry() {
+        let cache = IdempotencyCache::new(Duration::ZERO);
+        let handled = Cell::new(0);
+        let handler = || {
+            handled.set(handled.get() + 1);
+            Ok(ApiResponse::new(json!({})))
+        };
+        cache
+            .handle(1, &request("a", "/orders"), &json!({}), handler)
+            .unwrap();
+        cache
+            .handle(1, &request("a", "/orders"), &json!({}), handler)
+            .unwrap();
+        assert_eq!(handled.get(), 2);
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.entries.len(), 1);
+        assert_eq!(state.expiries.len(), 1);
+    }
+}
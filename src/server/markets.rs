@@ -0,0 +1,354 @@
+//! Market endpoints, which answer from the registry of
+//! [`markets`](crate::markets)
+//!
+//! `GET /v1/markets` answers with every market by symbol, in `markets`, and
+//! `GET /v1/markets/{symbol}` with one, e.g.
+//!
+//! ```json
+//! {"symbol": "XGAL-USD", "base": "XGAL", "quote": "USD", "tick_size": "0.01",
+//!  "lot_size": "0.001", "min_notional": "10", "status": "trading"}
+//! ```
+//!
+//! `GET /v1/markets/{symbol}/depth` answers with the price levels of the
+//! market's book, best first, and the quantity left at each:
+//!
+//! ```json
+//! {"symbol": "XGAL-USD", "sequence": 1042,
+//!  "bids": [{"price": "101.50", "quantity": "3"}],
+//!  "asks": [{"price": "101.55", "quantity": "0.5"}]}
+//! ```
+//!
+//! - `levels`, levels of each side, 50 unless given, 500 at most
+//! - `aggregation`, a multiple of the tick size, to sum the levels of each of
+//!   its multiples instead, bids rounded down and asks up
+//!
+//! `sequence` counts the changes the book went through, so two answers with
+//! the same one show the same book and a higher one a later book.
+//!
+//! `GET /v1/markets/{symbol}/trades` answers with the market's trades, newest
+//! first, the side being that of the taker order:
+//!
+//! ```json
+//! {"trades": [{"id": 87, "price": "101.50", "quantity": "2", "side": "buy",
+//!              "time": "2025-03-14T09:26:53.589Z"}],
+//!  "next_cursor": "87"}
+//! ```
+//!
+//! - `limit`, trades on a page, 100 unless given, 1000 at most
+//! - `before`, the `next_cursor` of the previous page, for the older trades,
+//!   which is null on the last one
+//!
+//! The last trades of each market are answered from memory, older ones from
+//! the trade history on disk, see [`trades`](crate::trades).
+//!
+//! `GET /v1/markets/{symbol}/ticker` answers with the market's last price,
+//! the open, high, low and close prices and the volumes of its trades over
+//! the last 24 hours, and the best prices of its book:
+//!
+//! ```json
+//! {"symbol": "XGAL-USD", "last_price": "101.50", "open": "99.80",
+//!  "high": "102.00", "low": "99.10", "close": "101.50", "volume": "1250.5",
+//!  "quote_volume": "126312.25", "best_bid": "101.45", "best_ask": "101.55"}
+//! ```
+//!
+//! `volume` is in the base asset and `quote_volume` in the quote asset. The
+//! prices are null when there were no trades, the best prices when that side
+//! of the book is empty. The statistics are kept as trades happen, see
+//! [`ticker`](crate::ticker).
+//!
+//! `GET /v1/markets/{symbol}/candles` answers with the market's candles,
+//! oldest first, each an array of its start and its open, high, low and close
+//! prices and volume:
+//!
+//! ```json
+//! {"symbol": "XGAL-USD", "interval": "1h",
+//!  "candles": [["2025-03-14T09:00:00.000Z", "99.80", "102.00", "99.10",
+//!               "101.50", "1250.5"]]}
+//! ```
+//!
+//! - `interval`, `1m`, `5m`, `1h` or `1d`
+//! - `start` and `end`, RFC 3339 UTC timestamps, the candles starting at or
+//!   after `start` and before `end`, all those kept and up to now unless
+//!   given
+//! - `limit`, candles in the answer, the last of the range, 500 unless given,
+//!   1000 at most
+//!
+//! Intervals without trades have no candle, see [`candles`](crate::candles).
+//!
+//! `PUT /v1/admin/markets/{symbol}` lists a market or changes a listed one,
+//! e.g. halts it with `"status": "halted"`, and answers with it. The body is
+//! the whole market, its `symbol` may be left out. Admin endpoints need the
+//! `admin_token` of the config, as `Authorization: Bearer {admin_token}`.
+
+use std::time::SystemTime;
+
+use serde_json::{Value, json};
+
+use super::{ApiError, ApiResponse, Server, content::ApiRequest};
+use crate::{
+    candles::Interval,
+    date::{format_timestamp, parse_timestamp},
+    decimal::Decimal,
+    engine::validate_amount,
+    markets::Market,
+};
+
+/// Levels of each side of `GET /v1/markets/{symbol}/depth` without `levels`
+const DEFAULT_DEPTH: usize = 50;
+
+/// Most levels of each side of `GET /v1/markets/{symbol}/depth`
+const MAX_DEPTH: usize = 500;
+
+/// Trades on a page of `GET /v1/markets/{symbol}/trades` without `limit`
+const DEFAULT_TRADES: usize = 100;
+
+/// Most trades on a page of `GET /v1/markets/{symbol}/trades`
+const MAX_TRADES: usize = 1000;
+
+/// Candles of `GET /v1/markets/{symbol}/candles` without `limit`
+const DEFAULT_CANDLES: usize = 500;
+
+/// Most candles of `GET /v1/markets/{symbol}/candles`
+const MAX_CANDLES: usize = 1000;
+
+/// `GET /v1/markets`
+pub(super) fn list_response(server: &Server) -> Result<ApiResponse, ApiError> {
+    Ok(ApiResponse::new(
+        json!({ "markets": server.markets.list() }),
+    ))
+}
+
+/// `GET /v1/markets/{symbol}`
+pub(super) fn market_response(server: &Server, symbol: &str) -> Result<ApiResponse, ApiError> {
+    let market = server
+        .markets
+        .get(symbol)
+        .ok_or_else(|| ApiError::not_found(format_args!("market {}", symbol)))?;
+    Ok(ApiResponse::new(json!(market)))
+}
+
+/// `GET /v1/markets/{symbol}/depth`, see the module docs
+pub(super) fn depth_response(
+    server: &Server,
+    api: &ApiRequest,
+    symbol: &str,
+) -> Result<ApiResponse, ApiError> {
+    let market = server
+        .markets
+        .get(symbol)
+        .ok_or_else(|| ApiError::not_found(format_args!("market {}", symbol)))?;
+    let request = api.http;
+    let levels = match request.get_param("levels") {
+        None => DEFAULT_DEPTH,
+        Some(levels) => levels
+            .parse()
+            .ok()
+            .filter(|levels| (1..=MAX_DEPTH).contains(levels))
+            .ok_or_else(|| {
+                ApiError::invalid_query("levels", &format!("must be 1 to {}", MAX_DEPTH))
+            })?,
+    };
+    let aggregation = request
+        .get_param("aggregation")
+        .map(|aggregation| {
+            let invalid = |reason: &str| ApiError::invalid_query("aggregation", reason);
+            let step: Decimal = aggregation
+                .parse()
+                .map_err(|_| invalid("must be a decimal"))?;
+            validate_amount("aggregation", step).map_err(|e| invalid(&e.reason))?;
+            if !step.is_multiple_of(market.tick_size) {
+                let reason = format!("must be a multiple of the tick size {}", market.tick_size);
+                return Err(invalid(&reason));
+            }
+            Ok(step)
+        })
+        .transpose()?;
+    let book = market.symbol.clone();
+    let depth = server
+        .engine
+        .call(move |engine| engine.depth(&book, levels, aggregation))
+        .ok_or_else(ApiError::engine_stopped)?;
+    let levels_json = |levels: Vec<(Decimal, Decimal)>| {
+        levels
+            .into_iter()
+            .map(|(price, quantity)| json!({ "price": price, "quantity": quantity }))
+            .collect::<Vec<_>>()
+    };
+    Ok(ApiResponse::new(json!({
+        "symbol": market.symbol,
+        "sequence": depth.sequence,
+        "bids": levels_json(depth.bids),
+        "asks": levels_json(depth.asks),
+    })))
+}
+
+/// `GET /v1/markets/{symbol}/trades`, see the module docs
+pub(super) fn trades_response(
+    server: &Server,
+    api: &ApiRequest,
+    symbol: &str,
+) -> Result<ApiResponse, ApiError> {
+    let market = server
+        .markets
+        .get(symbol)
+        .ok_or_else(|| ApiError::not_found(format_args!("market {}", symbol)))?;
+    let request = api.http;
+    let before = request
+        .get_param("before")
+        .map(|before| {
+            before
+                .parse()
+                .map_err(|_| ApiError::invalid_query("before", "is not a cursor of this endpoint"))
+        })
+        .transpose()?;
+    let limit = match request.get_param("limit") {
+        None => DEFAULT_TRADES,
+        Some(limit) => limit
+            .parse()
+            .ok()
+            .filter(|limit| (1..=MAX_TRADES).contains(limit))
+            .ok_or_else(|| {
+                ApiError::invalid_query("limit", &format!("must be 1 to {}", MAX_TRADES))
+            })?,
+    };
+    let page = server.trades.page(&market.symbol, before, limit)?;
+    let trades: Vec<_> = page
+        .trades
+        .iter()
+        .map(|trade| {
+            json!({
+                "id": trade.id,
+                "price": trade.price,
+                "quantity": trade.quantity,
+                "side": trade.taker_side,
+                "time": format_timestamp(trade.time),
+            })
+        })
+        .collect();
+    Ok(ApiResponse::new(json!({
+        "trades": trades,
+        "next_cursor": page.next.map(|id| id.to_string()),
+    })))
+}
+
+/// `GET /v1/markets/{symbol}/ticker`, see the module docs
+pub(super) fn ticker_response(server: &Server, symbol: &str) -> Result<ApiResponse, ApiError> {
+    let market = server
+        .markets
+        .get(symbol)
+        .ok_or_else(|| ApiError::not_found(format_args!("market {}", symbol)))?;
+    let book = market.symbol.clone();
+    let depth = server
+        .engine
+        .call(move |engine| engine.depth(&book, 1, None))
+        .ok_or_else(ApiError::engine_stopped)?;
+    let ticker = server.tickers.ticker(&market.symbol, SystemTime::now());
+    let best = |levels: &[(Decimal, Decimal)]| levels.first().map(|&(price, _)| price);
+    Ok(ApiResponse::new(json!({
+        "symbol": market.symbol,
+        "last_price": ticker.last_price,
+        "open": ticker.open,
+        "high": ticker.high,
+        "low": ticker.low,
+        "close": ticker.close,
+        "volume": ticker.volume,
+        "quote_volume": ticker.quote_volume,
+        "best_bid": best(&depth.bids),
+        "best_ask": best(&depth.asks),
+    })))
+}
+
+/// `GET /v1/markets/{symbol}/candles`, see the module docs
+pub(super) fn candles_response(
+    server: &Server,
+    api: &ApiRequest,
+    symbol: &str,
+) -> Result<ApiResponse, ApiError> {
+    let market = server
+        .markets
+        .get(symbol)
+        .ok_or_else(|| ApiError::not_found(format_args!("market {}", symbol)))?;
+    let request = api.http;
+    let interval = request
+        .get_param("interval")
+        .and_then(|interval| Interval::parse(&interval))
+        .ok_or_else(|| ApiError::invalid_query("interval", "must be `1m`, `5m`, `1h` or `1d`"))?;
+    let time = |parameter| {
+        request
+            .get_param(parameter)
+            .map(|time| {
+                parse_timestamp(&time).ok_or_else(|| {
+                    ApiError::invalid_query(parameter, "must be an RFC 3339 UTC timestamp")
+                })
+            })
+            .transpose()
+    };
+    let start = time("start")?.unwrap_or(SystemTime::UNIX_EPOCH);
+    let end = time("end")?.unwrap_or_else(SystemTime::now);
+    let limit = match request.get_param("limit") {
+        None => DEFAULT_CANDLES,
+        Some(limit) => limit
+            .parse()
+            .ok()
+            .filter(|limit| (1..=MAX_CANDLES).contains(limit))
+            .ok_or_else(|| {
+                ApiError::invalid_query("limit", &format!("must be 1 to {}", MAX_CANDLES))
+            })?,
+    };
+    let candles: Vec<_> = server
+        .candles
+        .candles(&market.symbol, interval, start, end, limit)
+        .iter()
+        .map(|candle| {
+            json!([
+                format_timestamp(candle.start),
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume(),
+            ])
+        })
+        .collect();
+    Ok(ApiResponse::new(json!({
+        "symbol": market.symbol,
+        "interval": interval.as_str(),
+        "candles": candles,
+    })))
+}
+
+/// `PUT /v1/admin/markets/{symbol}`, see the module docs
+pub(super) fn set_request(
+    server: &Server,
+    api: &ApiRequest,
+    symbol: &str,
+) -> Result<ApiResponse, ApiError> {
+    server.authorize_admin(api.http)?;
+    let mut body = api.body(server, "Market")?;
+    let Value::Object(fields) = &mut body else {
+        return Err(invalid_market("market must be an object"));
+    };
+    match fields.get("symbol") {
+        None => {
+            fields.insert(String::from("symbol"), json!(symbol));
+        }
+        Some(given) if given == symbol => {}
+        Some(_) => return Err(invalid_market("`symbol`: must be the symbol of the path")),
+    }
+    let market: Market =
+        serde_json::from_value(body).map_err(|e| invalid_market(&e.to_string()))?;
+    market.validate()?;
+    server.markets.set(market.clone(), |market| {
+        let market = market.clone();
+        server
+            .engine
+            .call(move |engine| engine.list(market))
+            .ok_or_else(ApiError::engine_stopped)
+    })?;
+    tracing::info!(symbol, status = market.status.as_str(), "market listed");
+    Ok(ApiResponse::new(json!(market)))
+}
+
+fn invalid_market(reason: &str) -> ApiError {
+    ApiError::bad_request("invalid_market", reason)
+}
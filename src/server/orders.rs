@@ -0,0 +1,344 @@
+//! Order endpoints, which hand orders to the matching engine
+//!
+//! `POST /v1/orders` submits an order, e.g.
+//!
+//! ```json
+//! {"symbol": "XGAL-USD", "side": "buy", "type": "limit", "price": "101.25",
+//!  "quantity": "3", "time_in_force": "gtc"}
+//! ```
+//!
+//! and answers 201 with the order as the engine accepted it, its status after
+//! matching and the fills it made on arrival. Prices and quantities are
+//! decimal strings both ways, numbers are read too. Orders may be sent and
+//! answered in galacticbuf as well.
+//!
+//! Every endpoint here needs an API key, as `Authorization: Bearer {key}`, and
+//! acts for the key's account, see `accounts`, 401 `unauthorized` without
+//! one. Orders are placed for the account, the orders of other accounts are
+//! unknown to it.
+//!
+//! A submission or batch with an `Idempotency-Key` header is answered the same
+//! however often the account retries it with the key, the orders are only
+//! placed once, see `idempotency`.
+//!
+//! An order may carry a `client_order_id` of the client's choosing, 1 to 64
+//! letters, digits, `-` or `_`, which no other order of the account in the
+//! book may have, 409 `duplicate_client_order_id` otherwise. The endpoints
+//! below taking an order ID take it as `/v1/orders/client/{client_order_id}`
+//! too, which names the account's newest order with it.
+//!
+//! `DELETE /v1/orders/{id}` cancels an order in the book and `PATCH
+//! /v1/orders/{id}` changes its price, its quantity or both, e.g.
+//! `{"quantity": "5"}`, see the engine for which amends lose the order's
+//! place in the queue. Both answer 200 with the order as it is after the
+//! update, an amend with the fills it made too. An order which filled, expired
+//! or was cancelled before the update reached the engine is answered with 409
+//! `too_late` naming its status, an unknown one with 404.
+//!
+//! `POST /v1/orders/batch` submits up to 100 orders at once, a JSON array of
+//! them or an object with them in `orders` like a galacticbuf `Batch` is. The
+//! engine takes them one after the other with nothing in between, each
+//! accepted or rejected on its own, and the answer is 200 with a result for
+//! each in `results`: its `index` in the batch and `status`, the order as
+//! `POST /v1/orders` answers with it when 201, the error when not.
+//!
+//! `DELETE /v1/orders` cancels every order of the account in the book at once,
+//! those of the `symbol` and `side` parameters when given, and answers with
+//! how many it cancelled and their IDs, e.g. `{"count": 2, "order_ids": [3,
+//! 7]}`.
+//!
+//! `GET /v1/orders/{id}` answers with an order, `GET /v1/orders` with a page of
+//! the account's orders, oldest first:
+//!
+//! - `status`, `open` for the orders in the book, `closed` for the others
+//! - `symbol`, the orders of one symbol
+//! - `limit`, orders on a page, 100 unless given, 500 at most
+//! - `cursor`, the `next_cursor` of the previous page, which is null on the
+//!   last one
+//!
+//! Orders which left the book long ago are forgotten by the engine, see its
+//! history, and are answered with 404 like unknown ones.
+
+use serde_json::{Value, json};
+
+use super::{ApiError, ApiResponse, Server, content::ApiRequest};
+use crate::{
+    accounts::AccountId,
+    date::format_timestamp,
+    engine::{Amend, NewOrder, Order, OrderFilter, OrderKey, Side, Trade},
+};
+
+/// Orders on a page of `GET /v1/orders` without `limit`
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Most orders on a page of `GET /v1/orders`
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Most orders in a batch
+const MAX_BATCH_SIZE: usize = 100;
+
+/// `POST /v1/orders`, see the module docs
+pub(super) fn submit_request(server: &Server, api: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let body = api.body(server, "NewOrder")?;
+    server.idempotency.handle(account_id, api.http, &body, || {
+        let order = parse_order(body.clone(), account_id)?;
+        let (order, trades) = server
+            .engine
+            .call(move |engine| engine.submit(order))
+            .ok_or_else(ApiError::engine_stopped)??;
+        Ok(ApiResponse::new(accepted_json(&order, &trades)).with_status(201))
+    })
+}
+
+/// `POST /v1/orders/batch`, see the module docs
+pub(super) fn batch_request(server: &Server, api: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let body = api.body(server, "Batch")?;
+    server.idempotency.handle(account_id, api.http, &body, || {
+        submit_batch(server, body.clone(), account_id)
+    })
+}
+
+/// Answer to the account's batch of a `POST /v1/orders/batch`
+fn submit_batch(
+    server: &Server,
+    body: Value,
+    account_id: AccountId,
+) -> Result<ApiResponse, ApiError> {
+    let orders = match body {
+        Value::Array(orders) => orders,
+        Value::Object(mut batch) => match batch.remove("orders") {
+            Some(Value::Array(orders)) => orders,
+            _ => return Err(invalid_batch("`orders` must be a list of orders")),
+        },
+        _ => return Err(invalid_batch("batch must be a list of orders")),
+    };
+    if orders.is_empty() || orders.len() > MAX_BATCH_SIZE {
+        let reason = format!("batch must have 1 to {} orders", MAX_BATCH_SIZE);
+        return Err(invalid_batch(&reason));
+    }
+    let orders: Vec<_> = orders
+        .into_iter()
+        .map(|order| parse_order(order, account_id))
+        .collect();
+    let results = server
+        .engine
+        .call(move |engine| {
+            orders
+                .into_iter()
+                .map(|order| order.and_then(|order| Ok(engine.submit(order)?)))
+                .collect::<Vec<_>>()
+        })
+        .ok_or_else(ApiError::engine_stopped)?;
+    let results: Vec<_> = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| match result {
+            Ok((order, trades)) => json!({
+                "index": index,
+                "status": 201,
+                "order": accepted_json(&order, &trades),
+            }),
+            Err(error) => json!({
+                "index": index,
+                "status": error.status,
+                "error": error.to_json(),
+            }),
+        })
+        .collect();
+    Ok(ApiResponse::new(json!({ "results": results })))
+}
+
+fn invalid_batch(reason: &str) -> ApiError {
+    ApiError::bad_request("invalid_batch", reason)
+}
+
+/// Order of the JSON of a submission by the account
+fn parse_order(order: Value, account_id: AccountId) -> Result<NewOrder, ApiError> {
+    let order: NewOrder = serde_json::from_value(order)
+        .map_err(|e| ApiError::bad_request("invalid_order", e.to_string()))?;
+    Ok(NewOrder {
+        account_id,
+        ..order
+    })
+}
+
+/// Order as submitting it answers, with its fills
+fn accepted_json(order: &Order, trades: &[Trade]) -> Value {
+    tracing::info!(
+        order_id = order.id,
+        symbol = order.symbol,
+        status = ?order.status,
+        trades = trades.len(),
+        "order accepted"
+    );
+    let mut body = order_json(order);
+    body["fills"] = trades.iter().map(fill_json).collect();
+    body
+}
+
+/// `DELETE /v1/orders/{id}`, see the module docs
+pub(super) fn cancel_request(
+    server: &Server,
+    api: &ApiRequest,
+    key: OrderKey,
+) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let order = server
+        .engine
+        .call(move |engine| engine.cancel(engine.find(account_id, &key)?))
+        .ok_or_else(ApiError::engine_stopped)??;
+    tracing::info!(order_id = order.id, "order cancelled");
+    Ok(ApiResponse::new(order_json(&order)))
+}
+
+/// `DELETE /v1/orders`, see the module docs
+pub(super) fn cancel_all_request(
+    server: &Server,
+    api: &ApiRequest,
+) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let symbol = api.http.get_param("symbol");
+    let side = match api.http.get_param("side").as_deref() {
+        None => None,
+        Some("buy") => Some(Side::Buy),
+        Some("sell") => Some(Side::Sell),
+        Some(_) => return Err(ApiError::invalid_query("side", "must be `buy` or `sell`")),
+    };
+    let ids = server
+        .engine
+        .call(move |engine| engine.cancel_all(account_id, symbol.as_deref(), side))
+        .ok_or_else(ApiError::engine_stopped)?;
+    tracing::info!(count = ids.len(), "orders cancelled");
+    Ok(ApiResponse::new(
+        json!({ "count": ids.len(), "order_ids": ids }),
+    ))
+}
+
+/// `PATCH /v1/orders/{id}`, see the module docs
+pub(super) fn amend_request(
+    server: &Server,
+    api: &ApiRequest,
+    key: OrderKey,
+) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let amend: Amend = serde_json::from_value(api.body(server, "AmendOrder")?)
+        .map_err(|e| ApiError::bad_request("invalid_order", e.to_string()))?;
+    if amend.price.is_none() && amend.quantity.is_none() {
+        return Err(ApiError::bad_request(
+            "invalid_order",
+            "an amend changes the price, the quantity or both",
+        ));
+    }
+    let (order, trades) = server
+        .engine
+        .call(move |engine| engine.amend(engine.find(account_id, &key)?, amend))
+        .ok_or_else(ApiError::engine_stopped)??;
+    tracing::info!(order_id = order.id, trades = trades.len(), "order amended");
+    let mut body = order_json(&order);
+    body["fills"] = trades.iter().map(fill_json).collect();
+    Ok(ApiResponse::new(body))
+}
+
+/// `GET /v1/orders/{id}`
+pub(super) fn order_response(
+    server: &Server,
+    api: &ApiRequest,
+    key: OrderKey,
+) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let order = server
+        .engine
+        .call(move |engine| engine.order(account_id, &key).cloned())
+        .ok_or_else(ApiError::engine_stopped)??;
+    Ok(ApiResponse::new(order_json(&order)))
+}
+
+/// `GET /v1/orders`, see the module docs
+pub(super) fn list_response(server: &Server, api: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let account_id = server.authenticate(api.http)?;
+    let request = api.http;
+    let open = match request.get_param("status").as_deref() {
+        None => None,
+        Some("open") => Some(true),
+        Some("closed") => Some(false),
+        Some(_) => {
+            return Err(ApiError::invalid_query(
+                "status",
+                "must be `open` or `closed`",
+            ));
+        }
+    };
+    let after = request
+        .get_param("cursor")
+        .map(|cursor| {
+            cursor
+                .parse()
+                .map_err(|_| ApiError::invalid_query("cursor", "is not a cursor of this endpoint"))
+        })
+        .transpose()?;
+    let limit = match request.get_param("limit") {
+        None => DEFAULT_PAGE_SIZE,
+        Some(limit) => limit
+            .parse()
+            .ok()
+            .filter(|limit| (1..=MAX_PAGE_SIZE).contains(limit))
+            .ok_or_else(|| {
+                ApiError::invalid_query("limit", &format!("must be 1 to {}", MAX_PAGE_SIZE))
+            })?,
+    };
+    let filter = OrderFilter {
+        account_id,
+        symbol: request.get_param("symbol"),
+        open,
+        after,
+        limit,
+    };
+    let page = server
+        .engine
+        .call(move |engine| engine.orders(&filter))
+        .ok_or_else(ApiError::engine_stopped)?;
+    let orders: Vec<_> = page.orders.iter().map(order_json).collect();
+    Ok(ApiResponse::new(json!({
+        "orders": orders,
+        "next_cursor": page.next.map(|id| id.to_string()),
+    })))
+}
+
+/// Order ID of a path, 404 when it can't be one
+pub(super) fn parse_id(id: &str) -> Result<OrderKey, ApiError> {
+    id.parse()
+        .map(OrderKey::Id)
+        .map_err(|_| ApiError::not_found(format_args!("order {}", id)))
+}
+
+/// Order as the API shows it
+fn order_json(order: &Order) -> Value {
+    json!({
+        "id": order.id,
+        "symbol": order.symbol,
+        "side": order.side,
+        "type": order.order_type,
+        "price": order.price,
+        "quantity": order.quantity,
+        "filled_quantity": order.filled,
+        "remaining_quantity": order.remaining(),
+        "time_in_force": order.time_in_force,
+        "status": order.status,
+        "client_order_id": order.client_order_id,
+        "account_id": order.account_id,
+        "created_at": format_timestamp(order.created_at),
+        "updated_at": format_timestamp(order.updated_at),
+    })
+}
+
+/// Trade as the taker order sees it
+fn fill_json(trade: &Trade) -> Value {
+    json!({
+        "trade_id": trade.id,
+        "price": trade.price,
+        "quantity": trade.quantity,
+        "maker_order_id": trade.maker_order_id,
+    })
+}
@@ -0,0 +1,177 @@
+//! Routes of the server, the operational endpoints at the root and the API
+//! under the prefix of each of its versions
+//!
+//! `/health`, `/ready` and `/version` stay unversioned, probes and deploy
+//! tooling call them. The API is mounted once per version in [`API_VERSIONS`],
+//! so a `/v2` tree can be served next to `/v1` while clients move over. A
+//! path without a version which the newest API serves is redirected there with
+//! 301, other paths are 404.
+//!
+//! Every route but `/health` answers through an [`ApiRequest`], so in the
+//! codec the client accepts.
+
+use rouille::{Request, Response, router};
+
+use super::{
+    ApiError, ApiResponse, Server, accounts, content::ApiRequest, markets, orders, version_response,
+};
+use crate::engine::OrderKey;
+
+/// Tree of routes under one version prefix
+struct ApiVersion {
+    /// e.g. `/v1`
+    prefix: &'static str,
+    /// First segments of the paths it serves, to redirect unversioned paths
+    /// without handling them
+    resources: &'static [&'static str],
+    routes: fn(&Server, &ApiRequest) -> Result<ApiResponse, ApiError>,
+}
+
+/// Versions of the API, oldest first
+const API_VERSIONS: &[ApiVersion] = &[ApiVersion {
+    prefix: "/v1",
+    resources: &[
+        "schemas", "decode", "accounts", "orders", "markets", "admin",
+    ],
+    routes: v1,
+}];
+
+/// Response of the route of the request
+pub(super) fn route(server: &Server, request: &Request) -> Result<Response, ApiError> {
+    match (request.method(), request.url().as_str()) {
+        ("GET", "/health") => return Ok(Response::text("")),
+        ("GET", "/ready") => return respond(request, |_| server.ready_response()),
+        ("GET", "/version") => return respond(request, |_| Ok(version_response())),
+        _ => {}
+    }
+    for version in API_VERSIONS {
+        if let Some(request) = without_prefix(request, version.prefix) {
+            return respond(&request, |api| (version.routes)(server, api));
+        }
+    }
+    redirect_unversioned(request)
+}
+
+/// Answer of the handler in the codec the request accepts
+fn respond(
+    request: &Request,
+    handler: impl FnOnce(&ApiRequest) -> Result<ApiResponse, ApiError>,
+) -> Result<Response, ApiError> {
+    let api = ApiRequest::negotiate(request)?;
+    api.respond(handler(&api)?)
+}
+
+/// `/v1` and everything under it
+fn v1(server: &Server, api: &ApiRequest) -> Result<ApiResponse, ApiError> {
+    let request = api.http;
+    router!(request,
+        (POST) (/schemas) => {
+            server.schemas.register_request(request)
+        },
+        (GET) (/schemas/{fingerprint: String}) => {
+            server.schemas.schema_response(&fingerprint)
+        },
+        (POST) (/decode) => {
+            server.schemas.decode_request(request)
+        },
+        (POST) (/accounts) => {
+            accounts::create_request(server, api)
+        },
+        (GET) (/accounts/{id: String}) => {
+            accounts::account_response(server, api, &id)
+        },
+        (POST) (/accounts/{id: String}/keys) => {
+            accounts::issue_key_request(server, api, &id)
+        },
+        (GET) (/accounts/{id: String}/keys) => {
+            accounts::keys_response(server, api, &id)
+        },
+        (DELETE) (/accounts/{id: String}/keys/{key_id: String}) => {
+            accounts::revoke_key_request(server, api, &id, &key_id)
+        },
+        (POST) (/orders) => {
+            orders::submit_request(server, api)
+        },
+        (POST) (/orders/batch) => {
+            orders::batch_request(server, api)
+        },
+        (GET) (/orders) => {
+            orders::list_response(server, api)
+        },
+        (DELETE) (/orders) => {
+            orders::cancel_all_request(server, api)
+        },
+        (GET) (/orders/{id: String}) => {
+            orders::order_response(server, api, orders::parse_id(&id)?)
+        },
+        (DELETE) (/orders/{id: String}) => {
+            orders::cancel_request(server, api, orders::parse_id(&id)?)
+        },
+        (PATCH) (/orders/{id: String}) => {
+            orders::amend_request(server, api, orders::parse_id(&id)?)
+        },
+        (GET) (/orders/client/{client_id: String}) => {
+            orders::order_response(server, api, OrderKey::Client(client_id))
+        },
+        (DELETE) (/orders/client/{client_id: String}) => {
+            orders::cancel_request(server, api, OrderKey::Client(client_id))
+        },
+        (PATCH) (/orders/client/{client_id: String}) => {
+            orders::amend_request(server, api, OrderKey::Client(client_id))
+        },
+        (GET) (/markets) => {
+            markets::list_response(server)
+        },
+        (GET) (/markets/{symbol: String}) => {
+            markets::market_response(server, &symbol)
+        },
+        (GET) (/markets/{symbol: String}/depth) => {
+            markets::depth_response(server, api, &symbol)
+        },
+        (GET) (/markets/{symbol: String}/trades) => {
+            markets::trades_response(server, api, &symbol)
+        },
+        (GET) (/markets/{symbol: String}/ticker) => {
+            markets::ticker_response(server, &symbol)
+        },
+        (GET) (/markets/{symbol: String}/candles) => {
+            markets::candles_response(server, api, &symbol)
+        },
+        (PUT) (/admin/markets/{symbol: String}) => {
+            markets::set_request(server, api, &symbol)
+        },
+        _ => Err(ApiError::no_route())
+    )
+}
+
+/// Request of the path under `prefix`, `None` unless the path is the prefix
+/// or under it, `/v10` is not under `/v1`
+fn without_prefix(request: &Request, prefix: &str) -> Option<Request> {
+    let rest = request.raw_url().strip_prefix(prefix)?;
+    match rest.is_empty() || rest.starts_with(['/', '?']) {
+        true => request.remove_prefix(prefix),
+        false => None,
+    }
+}
+
+/// 301 to the newest version serving the path, 404 when none does
+fn redirect_unversioned(request: &Request) -> Result<Response, ApiError> {
+    let resource = request.url();
+    let resource = resource
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    let newest = API_VERSIONS
+        .iter()
+        .rev()
+        .find(|version| version.resources.contains(&resource));
+    match newest {
+        Some(version) => Ok(Response::redirect_301(format!(
+            "{}{}",
+            version.prefix,
+            request.raw_url()
+        ))),
+        None => Err(ApiError::no_route()),
+    }
+}
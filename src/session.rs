@@ -0,0 +1,356 @@
+#![allow(dead_code)]
+
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::galacticbuf::{
+    Deserializable, DeserializeError, Encoding, FieldAccess, FieldValue, List, Message,
+    Serializable, SerializeError,
+};
+
+/// Session protocol versions the server speaks, most preferred first
+const SUPPORTED_VERSIONS: [i64; 1] = [1];
+
+static NEXT_SESSION_ID: AtomicI64 = AtomicI64::new(1);
+
+/// Control messages of the raw TCP protocol, sent as GalacticBuf messages
+/// with a `type` field telling them apart
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ControlMessage {
+    /// Client opens the session with the versions it speaks and its credentials
+    Hello { versions: Vec<i64>, auth: String },
+    /// Server accepts the session with the negotiated version
+    HelloAck { version: i64, session_id: i64 },
+    /// Sent by either side to keep a quiet session alive
+    Heartbeat,
+    /// Sent by either side before closing the session
+    Goodbye { reason: String },
+}
+
+fn message(fields: Vec<(&str, FieldValue)>) -> Message {
+    Message::new(fields).expect("control messages fit into the wire format")
+}
+
+fn get_integers(message: &Message, name: &str) -> Result<Vec<i64>, DeserializeError> {
+    match message.get_list(name)? {
+        List::Integers(integers) => Ok(integers.clone()),
+        list => Err(DeserializeError::invalid(format!(
+            "expected list of integers, found {:?}",
+            list
+        ))
+        .within(name)),
+    }
+}
+
+impl From<&ControlMessage> for Message {
+    fn from(control: &ControlMessage) -> Self {
+        match control {
+            ControlMessage::Hello { versions, auth } => message(vec![
+                ("type", "hello".into()),
+                ("versions", versions.clone().into()),
+                ("auth", auth.as_str().into()),
+            ]),
+            ControlMessage::HelloAck {
+                version,
+                session_id,
+            } => message(vec![
+                ("type", "hello_ack".into()),
+                ("version", (*version).into()),
+                ("session_id", (*session_id).into()),
+            ]),
+            ControlMessage::Heartbeat => message(vec![("type", "heartbeat".into())]),
+            ControlMessage::Goodbye { reason } => message(vec![
+                ("type", "goodbye".into()),
+                ("reason", reason.as_str().into()),
+            ]),
+        }
+    }
+}
+
+impl TryFrom<&Message> for ControlMessage {
+    type Error = DeserializeError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let control = match message.get_str("type")? {
+            "hello" => ControlMessage::Hello {
+                versions: get_integers(message, "versions")?,
+                auth: String::from(message.get_str("auth")?),
+            },
+            "hello_ack" => ControlMessage::HelloAck {
+                version: message.get_i64("version")?,
+                session_id: message.get_i64("session_id")?,
+            },
+            "heartbeat" => ControlMessage::Heartbeat,
+            "goodbye" => ControlMessage::Goodbye {
+                reason: String::from(message.get_str("reason")?),
+            },
+            t => {
+                let reason = format!("unknown control message type `{}`", t);
+                return Err(DeserializeError::invalid(reason).within("type"));
+            }
+        };
+        Ok(control)
+    }
+}
+
+/// [Message with `type` field]
+impl Serializable for ControlMessage {
+    fn serialize_into_with(
+        &self,
+        bytes: &mut Vec<u8>,
+        encoding: Encoding,
+    ) -> Result<(), SerializeError> {
+        Message::from(self).serialize_into_with(bytes, encoding)
+    }
+}
+
+/// [Message with `type` field]
+impl Deserializable for ControlMessage {
+    fn deserialize_with(
+        bytes: &[u8],
+        _: Option<usize>,
+        encoding: Encoding,
+    ) -> Result<(Self, &[u8]), DeserializeError> {
+        let (message, bytes) = Message::deserialize_with(bytes, None, encoding)?;
+        let control = ControlMessage::try_from(&message)?;
+        Ok((control, bytes))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SessionState {
+    /// Connection is open, the client has not introduced itself yet
+    AwaitingHello,
+    Established {
+        session_id: i64,
+        version: i64,
+    },
+    Closed,
+}
+
+/// Server side of a single TCP session
+///
+/// The session does no I/O itself, the connection handler feeds it received
+/// control messages and the current time, and sends back whatever it returns.
+#[derive(Debug)]
+pub(crate) struct Session {
+    state: SessionState,
+    heartbeat_interval: Duration,
+    idle_timeout: Duration,
+    last_received: Instant,
+    last_sent: Instant,
+}
+
+impl Session {
+    pub(crate) fn new(heartbeat_interval: Duration, idle_timeout: Duration, now: Instant) -> Self {
+        Session {
+            state: SessionState::AwaitingHello,
+            heartbeat_interval,
+            idle_timeout,
+            last_received: now,
+            last_sent: now,
+        }
+    }
+
+    pub(crate) fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    /// Handles a control message from the client, returns the reply to send back
+    pub(crate) fn receive(
+        &mut self,
+        message: ControlMessage,
+        now: Instant,
+    ) -> Option<ControlMessage> {
+        self.last_received = now;
+        let reply = match (&self.state, message) {
+            (SessionState::Closed, _) => None,
+            (_, ControlMessage::Goodbye { .. }) => {
+                self.state = SessionState::Closed;
+                None
+            }
+            (SessionState::AwaitingHello, ControlMessage::Hello { versions, auth }) => {
+                Some(self.hello(&versions, &auth))
+            }
+            (SessionState::AwaitingHello, message) => {
+                Some(self.close(format!("expected hello, got {:?}", message)))
+            }
+            (SessionState::Established { .. }, ControlMessage::Heartbeat) => None,
+            (SessionState::Established { .. }, message) => {
+                Some(self.close(format!("unexpected {:?}", message)))
+            }
+        };
+        if reply.is_some() {
+            self.last_sent = now;
+        }
+        reply
+    }
+
+    /// Checks the session timers, returns a heartbeat when the server has been
+    /// quiet for too long, or closes a session whose client went silent
+    pub(crate) fn poll(&mut self, now: Instant) -> Option<ControlMessage> {
+        if self.state == SessionState::Closed {
+            return None;
+        }
+        let reply = if now.duration_since(self.last_received) > self.idle_timeout {
+            self.close(String::from("idle timeout"))
+        } else if matches!(self.state, SessionState::Established { .. })
+            && now.duration_since(self.last_sent) >= self.heartbeat_interval
+        {
+            ControlMessage::Heartbeat
+        } else {
+            return None;
+        };
+        self.last_sent = now;
+        Some(reply)
+    }
+
+    fn hello(&mut self, versions: &[i64], auth: &str) -> ControlMessage {
+        if auth.is_empty() {
+            return self.close(String::from("missing credentials"));
+        }
+        let Some(version) = SUPPORTED_VERSIONS
+            .into_iter()
+            .find(|version| versions.contains(version))
+        else {
+            return self.close(format!(
+                "no common version, server supports {:?}",
+                SUPPORTED_VERSIONS
+            ));
+        };
+        let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+        self.state = SessionState::Established {
+            session_id,
+            version,
+        };
+        ControlMessage::HelloAck {
+            version,
+            session_id,
+        }
+    }
+
+    fn close(&mut self, reason: String) -> ControlMessage {
+        self.state = SessionState::Closed;
+        ControlMessage::Goodbye { reason }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::galacticbuf::DeserializeErrorKind;
+
+    const HEARTBEAT: Duration = Duration::from_secs(5);
+    const IDLE: Duration = Duration::from_secs(15);
+
+    fn hello(versions: Vec<i64>) -> ControlMessage {
+        ControlMessage::Hello {
+            versions,
+            auth: String::from("secret"),
+        }
+    }
+
+    #[test]
+    fn control_messages_round_trip() {
+        let messages = [
+            hello(vec![1, 2]),
+            ControlMessage::HelloAck {
+                version: 1,
+                session_id: 42,
+            },
+            ControlMessage::Heartbeat,
+            ControlMessage::Goodbye {
+                reason: String::from("bye"),
+            },
+        ];
+        for control in messages {
+            let bytes = control.serialize().unwrap();
+            let (deserialized, bytes) = ControlMessage::deserialize(&bytes, None).unwrap();
+            assert_eq!(bytes.len(), 0);
+            assert_eq!(control, deserialized);
+        }
+    }
+
+    #[test]
+    fn unknown_control_message() {
+        let bytes = message(vec![("type", "launch".into())])
+            .serialize()
+            .unwrap();
+        let error = ControlMessage::deserialize(&bytes, None).unwrap_err();
+        assert_eq!(
+            error.kind,
+            DeserializeErrorKind::Invalid(String::from("unknown control message type `launch`"))
+        );
+        assert_eq!(error.path, "type");
+    }
+
+    #[test]
+    fn handshake() {
+        let now = Instant::now();
+        let mut session = Session::new(HEARTBEAT, IDLE, now);
+
+        let Some(ControlMessage::HelloAck {
+            version,
+            session_id,
+        }) = session.receive(hello(vec![2, 1]), now)
+        else {
+            panic!("expected hello ack");
+        };
+        assert_eq!(version, 1);
+        assert_eq!(
+            session.state(),
+            &SessionState::Established {
+                session_id,
+                version
+            }
+        );
+
+        let reply = session.receive(ControlMessage::Heartbeat, now);
+        assert_eq!(reply, None);
+        let reply = session.receive(
+            ControlMessage::Goodbye {
+                reason: String::from("done"),
+            },
+            now,
+        );
+        assert_eq!(reply, None);
+        assert_eq!(session.state(), &SessionState::Closed);
+    }
+
+    #[test]
+    fn handshake_rejected() {
+        let now = Instant::now();
+
+        let mut session = Session::new(HEARTBEAT, IDLE, now);
+        let reply = session.receive(hello(vec![7]), now);
+        assert!(matches!(reply, Some(ControlMessage::Goodbye { .. })));
+        assert_eq!(session.state(), &SessionState::Closed);
+
+        let mut session = Session::new(HEARTBEAT, IDLE, now);
+        let reply = session.receive(ControlMessage::Heartbeat, now);
+        assert!(matches!(reply, Some(ControlMessage::Goodbye { .. })));
+        assert_eq!(session.state(), &SessionState::Closed);
+    }
+
+    #[test]
+    fn heartbeat_and_idle_timeout() {
+        let start = Instant::now();
+        let mut session = Session::new(HEARTBEAT, IDLE, start);
+        session.receive(hello(vec![1]), start);
+
+        assert_eq!(session.poll(start + Duration::from_secs(1)), None);
+        assert_eq!(
+            session.poll(start + HEARTBEAT),
+            Some(ControlMessage::Heartbeat)
+        );
+        assert_eq!(session.poll(start + HEARTBEAT), None);
+
+        session.receive(ControlMessage::Heartbeat, start + HEARTBEAT);
+        let reply = session.poll(start + HEARTBEAT + IDLE + Duration::from_secs(1));
+        assert!(matches!(reply, Some(ControlMessage::Goodbye { .. })));
+        assert_eq!(session.state(), &SessionState::Closed);
+        assert_eq!(session.poll(start + IDLE * 10), None);
+    }
+}
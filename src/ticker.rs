@@ -0,0 +1,214 @@
+//! Ticker of each market, its last price and its statistics over the last 24
+//! hours, kept up to date trade by trade
+//!
+//! The [`TickerStats`] listen to the engine, see
+//! [`TradeListener`](crate::engine::TradeListener). Each market keeps the
+//! trades of its window, with their volumes summed as they enter and leave it
+//! and the highest and lowest prices in monotonic queues, so a ticker is read
+//! without going over the trades. The window moves on as trades arrive and as
+//! tickers are read.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    decimal::Decimal,
+    engine::{MAX_DECIMAL_PLACES, Trade, TradeListener},
+};
+
+/// Span of the statistics of a ticker
+pub(crate) const WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Statistics of every market, see the module docs
+#[derive(Debug, Default)]
+pub(crate) struct TickerStats {
+    markets: Mutex<HashMap<String, MarketStats>>,
+}
+
+#[derive(Debug, Default)]
+struct MarketStats {
+    /// Price of the last trade, in the window or not
+    last_price: Option<Decimal>,
+    /// Trades in the window, oldest first
+    window: VecDeque<WindowTrade>,
+    /// Trades in the window which no later one is priced above, oldest first,
+    /// so the first is the highest
+    highs: VecDeque<(u64, Decimal)>,
+    /// Trades in the window which no later one is priced below, the first is
+    /// the lowest
+    lows: VecDeque<(u64, Decimal)>,
+    /// Quantity traded in the window, in units of 10^-8
+    volume: i128,
+    /// Price times quantity traded in the window, in units of 10^-16
+    quote_volume: i128,
+}
+
+#[derive(Debug)]
+struct WindowTrade {
+    id: u64,
+    time: SystemTime,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+/// Last price of a market and its statistics over the last 24 hours, the
+/// prices are `None` without trades
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Ticker {
+    pub(crate) last_price: Option<Decimal>,
+    pub(crate) open: Option<Decimal>,
+    pub(crate) high: Option<Decimal>,
+    pub(crate) low: Option<Decimal>,
+    pub(crate) close: Option<Decimal>,
+    pub(crate) volume: Decimal,
+    pub(crate) quote_volume: Decimal,
+}
+
+impl TickerStats {
+    /// Ticker of the market at `now`
+    pub(crate) fn ticker(&self, symbol: &str, now: SystemTime) -> Ticker {
+        let mut markets = self.markets.lock().unwrap();
+        let Some(stats) = markets.get_mut(symbol) else {
+            return Ticker::default();
+        };
+        stats.expire(now);
+        let price = |trade: Option<&WindowTrade>| trade.map(|trade| trade.price);
+        Ticker {
+            last_price: stats.last_price,
+            open: price(stats.window.front()),
+            high: stats.highs.front().map(|&(_, price)| price),
+            low: stats.lows.front().map(|&(_, price)| price),
+            close: price(stats.window.back()),
+            volume: Decimal::from_units(stats.volume, MAX_DECIMAL_PLACES),
+            quote_volume: Decimal::from_units(stats.quote_volume, 2 * MAX_DECIMAL_PLACES),
+        }
+    }
+}
+
+impl TradeListener for TickerStats {
+    fn trade(&self, trade: &Trade) {
+        let mut markets = self.markets.lock().unwrap();
+        let stats = markets.entry(trade.symbol.clone()).or_default();
+        stats.last_price = Some(trade.price);
+        while stats
+            .highs
+            .back()
+            .is_some_and(|&(_, high)| high <= trade.price)
+        {
+            stats.highs.pop_back();
+        }
+        stats.highs.push_back((trade.id, trade.price));
+        while stats
+            .lows
+            .back()
+            .is_some_and(|&(_, low)| low >= trade.price)
+        {
+            stats.lows.pop_back();
+        }
+        stats.lows.push_back((trade.id, trade.price));
+        let (price, quantity) = (units(trade.price), units(trade.quantity));
+        stats.volume = stats.volume.saturating_add(quantity);
+        stats.quote_volume = stats.quote_volume.saturating_add(price * quantity);
+        stats.window.push_back(WindowTrade {
+            id: trade.id,
+            time: trade.time,
+            price: trade.price,
+            quantity: trade.quantity,
+        });
+        stats.expire(trade.time);
+    }
+}
+
+impl MarketStats {
+    /// Drops the trades 24 hours or more before `now` from the window
+    fn expire(&mut self, now: SystemTime) {
+        let Some(start) = now.checked_sub(WINDOW) else {
+            return;
+        };
+        while let Some(trade) = self.window.front() {
+            if trade.time > start {
+                break;
+            }
+            let (price, quantity) = (units(trade.price), units(trade.quantity));
+            self.volume = self.volume.saturating_sub(quantity);
+            self.quote_volume = self.quote_volume.saturating_sub(price * quantity);
+            if self.highs.front().is_some_and(|&(id, _)| id == trade.id) {
+                self.highs.pop_front();
+            }
+            if self.lows.front().is_some_and(|&(id, _)| id == trade.id) {
+                self.lows.pop_front();
+            }
+            self.window.pop_front();
+        }
+    }
+}
+
+/// Units of 10^-8 of a price or quantity, which has at most 8 decimal places
+fn units(amount: Decimal) -> i128 {
+    amount.to_units(MAX_DECIMAL_PLACES).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Side;
+
+    use super::*;
+
+    fn trade(id: u64, price: &str, quantity: &str, minutes: u64) -> Trade {
+        Trade {
+            id,
+            symbol: String::from("XGAL-USD"),
+            price: price.parse().unwrap(),
+            quantity: quantity.parse().unwrap(),
+            maker_order_id: 1,
+            taker_order_id: 2,
+            taker_side: Side::Buy,
+            time: at(minutes),
+        }
+    }
+
+    fn at(minutes: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + minutes * 60)
+    }
+
+    fn decimal(amount: &str) -> Option<Decimal> {
+        Some(amount.parse().unwrap())
+    }
+
+    #[test]
+    fn window() {
+        let stats = TickerStats::default();
+        assert_eq!(stats.ticker("XGAL-USD", at(0)), Ticker::default());
+        stats.trade(&trade(1, "10", "2", 0));
+        stats.trade(&trade(2, "12.5", "1", 60));
+        stats.trade(&trade(3, "9", "0.5", 120));
+        stats.trade(&trade(4, "11", "1", 180));
+        assert_eq!(
+            stats.ticker("XGAL-USD", at(180)),
+            Ticker {
+                last_price: decimal("11"),
+                open: decimal("10"),
+                high: decimal("12.5"),
+                low: decimal("9"),
+                close: decimal("11"),
+                volume: "4.5".parse().unwrap(),
+                quote_volume: "48".parse().unwrap(),
+            }
+        );
+
+        // The first two trades leave the window, the highest with them
+        let ticker = stats.ticker("XGAL-USD", at(24 * 60 + 60));
+        assert_eq!(ticker.open, decimal("9"));
+        assert_eq!((ticker.high, ticker.low), (decimal("11"), decimal("9")));
+        assert_eq!(ticker.volume, "1.5".parse().unwrap());
+        assert_eq!(ticker.quote_volume, "15.5".parse().unwrap());
+
+        let ticker = stats.ticker("XGAL-USD", at(48 * 60));
+        assert_eq!(ticker.last_price, decimal("11"));
+        assert_eq!((ticker.open, ticker.high, ticker.close), (None, None, None));
+        assert_eq!(ticker.volume, Decimal::ZERO);
+    }
+}
@@ -0,0 +1,464 @@
+//! Trade history of each market, the recent trades in memory and every trade
+//! on disk
+//!
+//! The history listens to the engine, see
+//! [`TradeListener`](crate::engine::TradeListener). Each market keeps its
+//! last trades in a ring buffer, pages of them are answered from memory, of
+//! older ones from the market's file, `{symbol}.jsonl` in the history's
+//! directory with a line of JSON for each trade. A trade which can't be
+//! written is still served while it is in memory. The files are read once at
+//! start, so trade IDs carry on after a restart where they left off.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decimal::Decimal,
+    engine::{OrderId, Side, Trade, TradeListener},
+};
+
+/// Trades of each market kept in memory
+const RECENT_TRADES: usize = 1000;
+
+/// Trades of every market, see the module docs
+#[derive(Debug)]
+pub(crate) struct TradeHistory {
+    dir: PathBuf,
+    /// Trades kept in memory for each market
+    capacity: usize,
+    markets: RwLock<HashMap<String, MarketTrades>>,
+}
+
+#[derive(Debug, Default)]
+struct MarketTrades {
+    /// Last trades, oldest first
+    recent: VecDeque<Trade>,
+    /// Whether there are trades older than those in memory, which only the
+    /// file has
+    evicted: bool,
+    /// File trades are appended to, opened with the first
+    file: Option<File>,
+}
+
+/// Trades of a market, newest first, with the ID of the last of them when
+/// there are older ones
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TradePage {
+    pub(crate) trades: Vec<Trade>,
+    pub(crate) next: Option<u64>,
+}
+
+/// Trade as a line of a file has it
+#[derive(Serialize, Deserialize)]
+struct Record {
+    id: u64,
+    price: Decimal,
+    quantity: Decimal,
+    maker_order_id: OrderId,
+    taker_order_id: OrderId,
+    taker_side: Side,
+    /// Milliseconds since the Unix epoch
+    time: u64,
+}
+
+impl TradeHistory {
+    /// History of the files in the directory, which is created if need be
+    pub(crate) fn open(dir: impl Into<PathBuf>) -> io::Result<TradeHistory> {
+        TradeHistory::with_capacity(dir, RECENT_TRADES)
+    }
+
+    fn with_capacity(dir: impl Into<PathBuf>, capacity: usize) -> io::Result<TradeHistory> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut markets = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "jsonl")
+            {
+                continue;
+            }
+            let Some(symbol) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let mut trades = MarketTrades::default();
+            let whole = for_each_stored(&path, symbol, |trade| trades.push(trade, capacity))?;
+            // Drops a line torn by a crash, trades appended after it would
+            // be unreadable
+            let file = File::options().write(true).open(&path)?;
+            if file.metadata()?.len() > whole {
+                tracing::warn!(path = %path.display(), "partial trade dropped");
+                file.set_len(whole)?;
+            }
+            markets.insert(symbol.to_string(), trades);
+        }
+        Ok(TradeHistory {
+            dir,
+            capacity,
+            markets: RwLock::new(markets),
+        })
+    }
+
+    /// ID of the last trade of any market, 0 when there are none
+    pub(crate) fn last_id(&self) -> u64 {
+        let markets = self.markets.read().unwrap();
+        let last = markets.values().filter_map(|trades| trades.recent.back());
+        last.map(|trade| trade.id).max().unwrap_or(0)
+    }
+
+    /// Page of the trades of the market, the newest before the trade
+    /// `before` when given, see [`TradePage`]
+    pub(crate) fn page(
+        &self,
+        symbol: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> io::Result<TradePage> {
+        let before = before.unwrap_or(u64::MAX);
+        let (mut trades, oldest) = {
+            let markets = self.markets.read().unwrap();
+            let Some(market) = markets.get(symbol) else {
+                return Ok(TradePage::default());
+            };
+            // One more than the page to tell whether there are older ones
+            let trades: Vec<_> = market
+                .recent
+                .iter()
+                .rev()
+                .filter(|trade| trade.id < before)
+                .take(limit + 1)
+                .cloned()
+                .collect();
+            let complete = !market.evicted || trades.len() > limit;
+            let oldest = market.recent.front().map(|trade| trade.id);
+            (trades, oldest.filter(|_| !complete))
+        };
+        // Older than memory goes back, read without holding the lock so
+        // trades can be added meanwhile, those are newer than the page. Only
+        // the trades older than memory are taken from the file, which misses
+        // those that couldn't be written
+        if let Some(oldest) = oldest {
+            let mut older = VecDeque::with_capacity(limit + 1);
+            for_each_stored(&self.path(symbol), symbol, |trade| {
+                if trade.id < before.min(oldest) {
+                    if older.len() == limit + 1 {
+                        older.pop_front();
+                    }
+                    older.push_back(trade);
+                }
+            })?;
+            trades.extend(older.into_iter().rev());
+        }
+        let next = match trades.len() > limit {
+            true => {
+                trades.truncate(limit);
+                trades.last().map(|trade| trade.id)
+            }
+            false => None,
+        };
+        Ok(TradePage { trades, next })
+    }
+
+    /// Hands the listener the trades of each market made since `since`,
+    /// oldest first, and those before it which are in memory
+    pub(crate) fn replay(&self, since: SystemTime, listener: &dyn TradeListener) -> io::Result<()> {
+        let markets = self.markets.read().unwrap();
+        for (symbol, market) in markets.iter() {
+            if market.evicted
+                && let Some(oldest) = market.recent.front()
+                && oldest.time >= since
+            {
+                for_each_stored(&self.path(symbol), symbol, |trade| {
+                    if trade.time >= since && trade.id < oldest.id {
+                        listener.trade(&trade);
+                    }
+                })?;
+            }
+            market.recent.iter().for_each(|trade| listener.trade(trade));
+        }
+        Ok(())
+    }
+
+    fn path(&self, symbol: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", symbol))
+    }
+}
+
+impl TradeListener for TradeHistory {
+    /// Keeps the trade in memory and appends it to the file of its market,
+    /// a trade which can't be written is only logged, the engine goes on
+    fn trade(&self, trade: &Trade) {
+        let path = self.path(&trade.symbol);
+        let mut markets = self.markets.write().unwrap();
+        let market = markets.entry(trade.symbol.clone()).or_default();
+        if let Err(e) = market.append(&path, trade) {
+            tracing::error!(
+                trade_id = trade.id,
+                symbol = trade.symbol,
+                error = %e,
+                "trade not written"
+            );
+        }
+        market.push(trade.clone(), self.capacity);
+    }
+}
+
+impl MarketTrades {
+    /// Keeps the trade in memory, in place of the oldest one when it is full
+    fn push(&mut self, trade: Trade, capacity: usize) {
+        if self.recent.len() == capacity {
+            self.recent.pop_front();
+            self.evicted = true;
+        }
+        self.recent.push_back(trade);
+    }
+
+    fn append(&mut self, path: &Path, trade: &Trade) -> io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self
+                .file
+                .insert(File::options().create(true).append(true).open(path)?),
+        };
+        let time = trade
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let record = Record {
+            id: trade.id,
+            price: trade.price,
+            quantity: trade.quantity,
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+            taker_side: trade.taker_side,
+            time: time.as_millis() as u64,
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        // One write, so a reader sees whole lines or a partial last one
+        let length = file.metadata()?.len();
+        file.write_all(&line).inspect_err(|_| {
+            // The next trade would be appended to what was written of this one
+            if let Err(e) = file.set_len(length) {
+                tracing::error!(path = %path.display(), error = %e, "partial trade left");
+            }
+        })
+    }
+}
+
+/// Calls `f` with each trade of the file, oldest first, none when there is
+/// no file, stops at a partial last line being written, returns the bytes of
+/// the whole lines read
+///
+/// A whole line which isn't a trade is skipped with a warning, the trades
+/// after it are still read.
+fn for_each_stored(path: &Path, symbol: &str, mut f: impl FnMut(Trade)) -> io::Result<u64> {
+    let mut file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut read = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let length = file.read_line(&mut line)?;
+        if !line.ends_with('\n') {
+            return Ok(read);
+        }
+        let offset = read;
+        read += length as u64;
+        let record = match serde_json::from_str::<Record>(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), offset, error = %e, "corrupt trade skipped");
+                continue;
+            }
+        };
+        f(Trade {
+            id: record.id,
+            symbol: symbol.to_string(),
+            price: record.price,
+            quantity: record.quantity,
+            maker_order_id: record.maker_order_id,
+            taker_order_id: record.taker_order_id,
+            taker_side: record.taker_side,
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(record.time),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    struct TempHistory(PathBuf);
+
+    impl TempHistory {
+        fn new(name: &str) -> TempHistory {
+            let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempHistory(dir)
+        }
+    }
+
+    impl Drop for TempHistory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn trade(id: u64, symbol: &str) -> Trade {
+        Trade {
+            id,
+            symbol: symbol.to_string(),
+            price: Decimal::from(10),
+            quantity: Decimal::from(1),
+            maker_order_id: 1,
+            taker_order_id: 2,
+            taker_side: Side::Buy,
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+        }
+    }
+
+    fn ids(page: &TradePage) -> Vec<u64> {
+        page.trades.iter().map(|trade| trade.id).collect()
+    }
+
+    #[test]
+    fn pages() {
+        let dir = TempHistory::new("trades-pages");
+        let history = TradeHistory::with_capacity(&dir.0, 3).unwrap();
+        for id in 1..=5 {
+            history.trade(&trade(id, "XGAL-USD"));
+        }
+        history.trade(&trade(6, "XORB-USD"));
+        assert_eq!(history.last_id(), 6);
+
+        // From memory, then the older ones from the file
+        let page = history.page("XGAL-USD", None, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![5, 4], Some(4)));
+        let page = history.page("XGAL-USD", page.next, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![3, 2], Some(2)));
+        let page = history.page("XGAL-USD", page.next, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![1], None));
+        assert_eq!(page.trades[0], trade(1, "XGAL-USD"));
+
+        let page = history.page("XORB-USD", None, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![6], None));
+        assert_eq!(
+            history.page("XNEB-USD", None, 2).unwrap(),
+            TradePage::default()
+        );
+    }
+
+    #[test]
+    fn unwritten() {
+        let dir = TempHistory::new("trades-unwritten");
+        let history = TradeHistory::with_capacity(&dir.0, 3).unwrap();
+        let file = |file: Option<File>| {
+            let mut markets = history.markets.write().unwrap();
+            markets.get_mut("XGAL-USD").unwrap().file = file;
+        };
+        for id in 1..=6 {
+            if id == 4 {
+                // Read only, so appending the trade fails
+                file(Some(File::open(dir.0.join("XGAL-USD.jsonl")).unwrap()));
+            }
+            history.trade(&trade(id, "XGAL-USD"));
+            file(None);
+        }
+        let stored = fs::read_to_string(dir.0.join("XGAL-USD.jsonl")).unwrap();
+        assert_eq!(stored.lines().count(), 5);
+
+        // Trade 4 is only in memory, the file has the older ones
+        let page = history.page("XGAL-USD", None, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![6, 5], Some(5)));
+        let page = history.page("XGAL-USD", page.next, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![4, 3], Some(3)));
+        let page = history.page("XGAL-USD", page.next, 2).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![2, 1], None));
+    }
+
+    #[test]
+    fn replay() {
+        #[derive(Debug, Default)]
+        struct Recorder(Mutex<Vec<u64>>);
+
+        impl TradeListener for Recorder {
+            fn trade(&self, trade: &Trade) {
+                self.0.lock().unwrap().push(trade.id);
+            }
+        }
+
+        let dir = TempHistory::new("trades-replay");
+        let history = TradeHistory::with_capacity(&dir.0, 2).unwrap();
+        for id in 1..=4 {
+            history.trade(&Trade {
+                time: SystemTime::UNIX_EPOCH + Duration::from_secs(id),
+                ..trade(id, "XGAL-USD")
+            });
+        }
+        let replayed = |since: u64| {
+            let recorder = Recorder::default();
+            let since = SystemTime::UNIX_EPOCH + Duration::from_secs(since);
+            history.replay(since, &recorder).unwrap();
+            recorder.0.into_inner().unwrap()
+        };
+        // From the file when memory doesn't go back far enough
+        assert_eq!(replayed(2), [2, 3, 4]);
+        assert_eq!(replayed(4), [3, 4]);
+    }
+
+    #[test]
+    fn restart() {
+        let dir = TempHistory::new("trades-restart");
+        let history = TradeHistory::with_capacity(&dir.0, 2).unwrap();
+        for id in 1..=3 {
+            history.trade(&trade(id, "XGAL-USD"));
+        }
+        // Torn by a crash while it was written
+        let mut file = File::options()
+            .append(true)
+            .open(dir.0.join("XGAL-USD.jsonl"))
+            .unwrap();
+        file.write_all(br#"{"id": 4, "pri"#).unwrap();
+
+        let history = TradeHistory::with_capacity(&dir.0, 2).unwrap();
+        assert_eq!(history.last_id(), 3);
+        history.trade(&trade(4, "XGAL-USD"));
+        let page = history.page("XGAL-USD", None, 10).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![4, 3, 2, 1], None));
+    }
+
+    #[test]
+    fn corrupt_line() {
+        let dir = TempHistory::new("trades-corrupt");
+        let history = TradeHistory::with_capacity(&dir.0, 2).unwrap();
+        history.trade(&trade(1, "XGAL-USD"));
+        // A partial line which a later trade was appended to
+        let path = dir.0.join("XGAL-USD.jsonl");
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(br#"{"id": 2, "pri"#).unwrap();
+        history.trade(&trade(3, "XGAL-USD"));
+        history.trade(&trade(4, "XGAL-USD"));
+
+        // Skipped, the trades after it are kept
+        let history = TradeHistory::with_capacity(&dir.0, 2).unwrap();
+        assert_eq!(history.last_id(), 4);
+        let page = history.page("XGAL-USD", None, 10).unwrap();
+        assert_eq!((ids(&page), page.next), (vec![4, 1], None));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+}
@@ -0,0 +1,1039 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use galactic_exchange::{config::Config, server::Server};
+use rouille::{Request, Response};
+
+/// Markets of the tests, in the config of their servers
+const MARKETS: &str = r#"
+admin_token = "admin-secret"
+
+[[markets]]
+symbol = "XGAL-USD"
+base = "XGAL"
+quote = "USD"
+tick_size = "0.01"
+lot_size = "1"
+
+[[markets]]
+symbol = "XORB-USD"
+base = "XORB"
+quote = "USD"
+tick_size = "0.01"
+lot_size = "1"
+"#;
+
+/// Server keeping its state in a directory of its own, removed again when the
+/// test is done, with an account its requests are made for
+struct TempServer {
+    server: Server,
+    data_dir: PathBuf,
+    /// API key of the account
+    api_key: String,
+}
+
+impl TempServer {
+    fn new(name: &str) -> TempServer {
+        let data_dir = std::env::temp_dir().join(format!("server-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        let config = Config {
+            data_dir: data_dir.clone(),
+            ..Config::from_toml(MARKETS).unwrap()
+        };
+        let server = Server::new(config).unwrap();
+        let request = Request::fake_http("POST", "/v1/accounts", vec![], b"{}".to_vec());
+        let (status, body) = response(server.handle(&request));
+        assert_eq!(status, 201, "{}", body);
+        let account: serde_json::Value = serde_json::from_str(&body).unwrap();
+        TempServer {
+            server,
+            data_dir,
+            api_key: account["api_key"]["key"].as_str().unwrap().to_string(),
+        }
+    }
+
+    fn request(&self, method: &str, url: &str, body: &[u8]) -> (u16, String) {
+        let request = Request::fake_http(method, url, self.headers(), body.to_vec());
+        response(self.server.handle(&request))
+    }
+
+    /// Headers authenticating a request as the account
+    fn headers(&self) -> Vec<(String, String)> {
+        vec![authorization(&self.api_key)]
+    }
+}
+
+impl Drop for TempServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+fn authorization(api_key: &str) -> (String, String) {
+    (String::from("Authorization"), format!("Bearer {}", api_key))
+}
+
+fn response(response: Response) -> (u16, String) {
+    let status = response.status_code;
+    let (mut reader, _) = response.data.into_reader_and_size();
+    let mut body = String::new();
+    reader.read_to_string(&mut body).unwrap();
+    (status, body)
+}
+
+#[test]
+fn health() {
+    let server = TempServer::new("health");
+    assert_eq!(server.request("GET", "/health", b""), (200, String::new()));
+    assert_eq!(server.request("POST", "/health", b"").0, 404);
+    assert_eq!(server.request("GET", "/quotes", b"").0, 404);
+}
+
+#[test]
+fn ready() {
+    let server = TempServer::new("ready");
+    assert_eq!(
+        server.request("GET", "/ready", b""),
+        (200, String::from(r#"{"status":"ready"}"#))
+    );
+
+    fs::remove_dir_all(&server.data_dir).unwrap();
+    let (status, body) = server.request("GET", "/ready", b"");
+    assert_eq!(status, 503);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["code"], "not_ready");
+    assert_eq!(body["details"]["failing"][0]["component"], "persistence");
+    assert!(body["details"]["failing"][0]["reason"].is_string());
+    assert_eq!(server.request("GET", "/health", b"").0, 200);
+}
+
+#[test]
+fn version() {
+    let server = TempServer::new("version");
+    let (status, body) = server.request("GET", "/version", b"");
+    assert_eq!(status, 200);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["git_sha"].is_string());
+    let timestamp = body["build_timestamp"].as_str().unwrap();
+    assert_eq!(
+        (timestamp.len(), &timestamp[10..11]),
+        (20, "T"),
+        "{}",
+        timestamp
+    );
+    assert_eq!(body["protocol_versions"], serde_json::json!([1, 2, 3]));
+}
+
+#[test]
+fn errors() {
+    let server = TempServer::new("errors");
+    let (status, body) = server.request("GET", "/v1/quotes", b"");
+    assert_eq!(status, 404);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["message"], "no such endpoint");
+    assert!(body["request_id"].is_string());
+    assert!(body.get("details").is_none());
+
+    // Limited by the config, 1 MiB by default
+    let (status, body) = server.request("POST", "/v1/decode", &vec![0; 2 << 20]);
+    assert_eq!(status, 413);
+    assert!(body.contains(r#""code":"body_too_large""#), "{}", body);
+
+    let (status, body) = server.request("POST", "/v1/decode", b"\x02\x00");
+    assert_eq!(status, 400);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["code"], "invalid_message");
+    assert_eq!(body["details"]["offset"], 0);
+}
+
+#[test]
+fn content_negotiation() {
+    let server = TempServer::new("negotiation");
+    let request = |url: &str, headers: &[(&str, &str)], body: &[u8]| {
+        let mut all = server.headers();
+        all.extend(
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        let method = if body.is_empty() { "GET" } else { "POST" };
+        let response = server
+            .server
+            .handle(&Request::fake_http(method, url, all, body.to_vec()));
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(name, _)| name == "Content-Type")
+            .map(|(_, value)| value.to_string());
+        (response.status_code, content_type)
+    };
+
+    let galacticbuf = Some(String::from("application/x-galacticbuf"));
+    let (status, content_type) =
+        request("/version", &[("Accept", "application/x-galacticbuf")], b"");
+    assert_eq!((status, content_type), (200, galacticbuf.clone()));
+    let (status, content_type) = request("/version", &[("Accept", "*/*")], b"");
+    assert_eq!(status, 200);
+    assert!(content_type.unwrap().starts_with("application/json"));
+    assert_eq!(request("/version", &[("Accept", "text/html")], b"").0, 406);
+
+    let order = br#"{"symbol": "XGAL-USD", "side": "buy", "type": "market", "quantity": "1"}"#;
+    assert_eq!(
+        request("/v1/orders", &[("Content-Type", "text/csv")], order).0,
+        415
+    );
+    let json = [
+        ("Content-Type", "application/json"),
+        ("Accept", "application/x-galacticbuf"),
+    ];
+    assert_eq!(
+        request("/v1/orders", &json, order),
+        (201, galacticbuf.clone())
+    );
+    // Errors in the accepted codec too
+    assert_eq!(request("/v1/orders", &json, b"{"), (400, galacticbuf));
+}
+
+#[test]
+fn versioned_paths() {
+    let server = TempServer::new("versioned");
+    let redirect = |url: &str| {
+        let request = Request::fake_http("GET", url, vec![], vec![]);
+        let response = server.server.handle(&request);
+        let location = response
+            .headers
+            .iter()
+            .find(|(name, _)| name == "Location")
+            .map(|(_, value)| value.to_string());
+        (response.status_code, location)
+    };
+    assert_eq!(
+        redirect("/schemas/00010203fcfdfeff?pretty"),
+        (
+            301,
+            Some(String::from("/v1/schemas/00010203fcfdfeff?pretty"))
+        )
+    );
+    assert_eq!(redirect("/decode"), (301, Some(String::from("/v1/decode"))));
+    assert_eq!(redirect("/v1/schemas/00010203fcfdfeff").0, 404);
+    assert_eq!(redirect("/v1/health"), (404, None));
+    assert_eq!(redirect("/v10/schemas"), (404, None));
+    assert_eq!(redirect("/quotes"), (404, None));
+}
+
+#[test]
+fn schemas() {
+    let server = TempServer::new("schemas");
+    let schema = "message Order = 1 { price: integer = 1 }";
+    let (status, body) = server.request("POST", "/v1/schemas", schema.as_bytes());
+    assert_eq!(status, 201);
+    let fingerprint = body
+        .strip_prefix("{\"fingerprint\":\"")
+        .and_then(|rest| rest.strip_suffix("\"}"))
+        .unwrap();
+
+    let url = format!("/v1/schemas/{}", fingerprint);
+    assert_eq!(
+        server.request("GET", &url, b""),
+        (
+            200,
+            String::from("message Order = 1 {\n    price: integer = 1\n}\n")
+        )
+    );
+    assert_eq!(server.request("POST", "/v1/decode", b"\x07").0, 400);
+
+    // Registered schemas are loaded again by the next server
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::default()
+    };
+    let restarted = Server::new(config).unwrap();
+    let request = Request::fake_http("GET", url, vec![], vec![]);
+    assert_eq!(response(restarted.handle(&request)).0, 200);
+}
+
+#[test]
+fn orders() {
+    let server = TempServer::new("orders");
+    let submit = |order: serde_json::Value| {
+        let (status, body) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    let (status, sell) = submit(serde_json::json!({
+        "symbol": "XGAL-USD", "side": "sell", "type": "limit", "price": "101.50", "quantity": 5
+    }));
+    assert_eq!(status, 201);
+    assert_eq!(sell["status"], "open");
+    assert_eq!(sell["price"], "101.50");
+    assert_eq!(sell["time_in_force"], "gtc");
+    assert_eq!(sell["remaining_quantity"], "5");
+    assert_eq!(sell["fills"], serde_json::json!([]));
+    assert!(sell["created_at"].as_str().unwrap().ends_with('Z'));
+
+    let (status, buy) = submit(serde_json::json!({
+        "symbol": "XGAL-USD", "side": "buy", "type": "market", "quantity": "2"
+    }));
+    assert_eq!(status, 201);
+    assert_ne!(buy["id"], sell["id"]);
+    assert_eq!(buy["status"], "filled");
+    assert_eq!(buy["time_in_force"], "ioc");
+    assert_eq!(buy["filled_quantity"], "2");
+    assert_eq!(buy["fills"][0]["price"], "101.50");
+    assert_eq!(buy["fills"][0]["maker_order_id"], sell["id"]);
+
+    let (status, error) = submit(serde_json::json!({
+        "symbol": "XGAL-USD", "side": "buy", "type": "limit", "quantity": "1"
+    }));
+    assert_eq!(status, 400);
+    assert_eq!(error["code"], "invalid_order");
+    assert_eq!(error["details"]["field"], "price");
+    let (status, error) = submit(serde_json::json!({
+        "symbol": "XGAL-USD", "side": "bid", "type": "limit", "price": "1", "quantity": "1"
+    }));
+    assert_eq!(
+        (status, &error["code"]),
+        (400, &serde_json::json!("invalid_order"))
+    );
+    assert_eq!(server.request("GET", "/orders", b"").0, 301);
+}
+
+#[test]
+fn batches() {
+    let server = TempServer::new("batches");
+    let order = |side: &str, price: &str| {
+        serde_json::json!({
+            "symbol": "XGAL-USD", "side": side, "type": "limit", "price": price, "quantity": "1"
+        })
+    };
+    let batch = serde_json::json!([order("sell", "10"), order("sell", "-1"), order("buy", "10")]);
+    let (status, body) = server.request("POST", "/v1/orders/batch", batch.to_string().as_bytes());
+    assert_eq!(status, 200);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        (&results[0]["index"], &results[0]["status"]),
+        (&0.into(), &201.into())
+    );
+    assert_eq!(results[1]["status"], 400);
+    assert_eq!(results[1]["error"]["details"]["field"], "price");
+    assert_eq!(results[2]["order"]["status"], "filled");
+    assert_eq!(
+        results[2]["order"]["fills"][0]["maker_order_id"],
+        results[0]["order"]["id"]
+    );
+
+    let batch = serde_json::json!({ "orders": [order("buy", "9"), {"side": "buy"}] });
+    let (_, body) = server.request("POST", "/v1/orders/batch", batch.to_string().as_bytes());
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["results"][0]["status"], 201);
+    assert_eq!(body["results"][1]["error"]["code"], "invalid_order");
+
+    for batch in [
+        serde_json::json!([]),
+        serde_json::json!(vec![order("buy", "1"); 101]),
+    ] {
+        let (status, body) =
+            server.request("POST", "/v1/orders/batch", batch.to_string().as_bytes());
+        assert_eq!(status, 400);
+        assert!(body.contains("invalid_batch"), "{}", body);
+    }
+}
+
+#[test]
+fn idempotency_keys() {
+    let server = TempServer::new("idempotency");
+    let submit = |key: &str, order: &serde_json::Value| {
+        let mut headers = server.headers();
+        headers.push((String::from("Idempotency-Key"), key.to_string()));
+        let body = order.to_string().into_bytes();
+        let response =
+            server
+                .server
+                .handle(&Request::fake_http("POST", "/v1/orders", headers, body));
+        let replayed = response
+            .headers
+            .iter()
+            .any(|(name, value)| name == "Idempotent-Replayed" && value == "true");
+        let (status, body) = self::response(response);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        (status, body, replayed)
+    };
+    let order = serde_json::json!({
+        "symbol": "XGAL-USD", "side": "buy", "type": "limit", "price": "10", "quantity": "1"
+    });
+
+    let (status, first, replayed) = submit("order-1", &order);
+    assert_eq!((status, replayed), (201, false));
+    let (status, retry, replayed) = submit("order-1", &order);
+    assert_eq!((status, replayed), (201, true));
+    assert_eq!(retry, first);
+    let (_, other, _) = submit("order-2", &order);
+    assert_ne!(other["id"], first["id"]);
+
+    let (_, body) = server.request("GET", "/v1/orders", b"");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["orders"].as_array().unwrap().len(), 2);
+
+    let mut changed = order.clone();
+    changed["quantity"] = "2".into();
+    let (status, error, _) = submit("order-1", &changed);
+    assert_eq!(
+        (status, &error["code"]),
+        (422, &"idempotency_key_reused".into())
+    );
+}
+
+#[test]
+fn cancel_and_amend() {
+    let server = TempServer::new("cancel");
+    let send = |method: &str, url: &str, body: serde_json::Value| {
+        let (status, body) = server.request(method, url, body.to_string().as_bytes());
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    let (_, sell) = send(
+        "POST",
+        "/v1/orders",
+        serde_json::json!({
+            "symbol": "XGAL-USD", "side": "sell", "type": "limit", "price": "10", "quantity": "4"
+        }),
+    );
+    let url = format!("/v1/orders/{}", sell["id"]);
+
+    let (status, amended) = send("PATCH", &url, serde_json::json!({ "quantity": "3" }));
+    assert_eq!(status, 200);
+    assert_eq!(
+        (&amended["quantity"], &amended["status"]),
+        (&"3".into(), &"open".into())
+    );
+    assert_eq!(amended["fills"], serde_json::json!([]));
+    let (status, error) = send("PATCH", &url, serde_json::json!({}));
+    assert_eq!((status, &error["code"]), (400, &"invalid_order".into()));
+
+    let (status, cancelled) = send("DELETE", &url, serde_json::Value::Null);
+    assert_eq!((status, &cancelled["status"]), (200, &"cancelled".into()));
+    let (status, error) = send("DELETE", &url, serde_json::Value::Null);
+    assert_eq!(status, 409);
+    assert_eq!(error["code"], "too_late");
+    assert_eq!(error["details"]["status"], "cancelled");
+    let (status, _) = send("PATCH", &url, serde_json::json!({ "price": "11" }));
+    assert_eq!(status, 409);
+
+    assert_eq!(
+        send("DELETE", "/v1/orders/404", serde_json::Value::Null).0,
+        404
+    );
+    let (status, error) = send("DELETE", "/v1/orders/first", serde_json::Value::Null);
+    assert_eq!(
+        (status, &error["message"]),
+        (404, &"order first not found".into())
+    );
+
+    for (symbol, side) in [
+        ("XGAL-USD", "buy"),
+        ("XGAL-USD", "sell"),
+        ("XORB-USD", "buy"),
+    ] {
+        let price = if side == "buy" { "9" } else { "10" };
+        let order = serde_json::json!({
+            "symbol": symbol, "side": side, "type": "limit", "price": price, "quantity": "1"
+        });
+        assert_eq!(send("POST", "/v1/orders", order).0, 201);
+    }
+    let (status, cancelled) = send(
+        "DELETE",
+        "/v1/orders?symbol=XGAL-USD&side=buy",
+        serde_json::Value::Null,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(
+        cancelled,
+        serde_json::json!({ "count": 1, "order_ids": [2] })
+    );
+    let (_, cancelled) = send("DELETE", "/v1/orders", serde_json::Value::Null);
+    assert_eq!(
+        cancelled,
+        serde_json::json!({ "count": 2, "order_ids": [3, 4] })
+    );
+    assert_eq!(
+        send("DELETE", "/v1/orders?side=bid", serde_json::Value::Null).0,
+        400
+    );
+}
+
+#[test]
+fn client_order_ids() {
+    let server = TempServer::new("client-ids");
+    let send = |method: &str, url: &str, body: serde_json::Value| {
+        let (status, body) = server.request(method, url, body.to_string().as_bytes());
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    let order = serde_json::json!({
+        "symbol": "XGAL-USD", "side": "sell", "type": "limit", "price": "10", "quantity": "4",
+        "client_order_id": "grid-7"
+    });
+    let (status, first) = send("POST", "/v1/orders", order.clone());
+    assert_eq!((status, &first["client_order_id"]), (201, &"grid-7".into()));
+    let (status, error) = send("POST", "/v1/orders", order.clone());
+    assert_eq!(status, 409);
+    assert_eq!(error["code"], "duplicate_client_order_id");
+    assert_eq!(error["details"]["order_id"], first["id"]);
+
+    let url = "/v1/orders/client/grid-7";
+    let (status, found) = send("GET", url, serde_json::Value::Null);
+    assert_eq!((status, &found["id"]), (200, &first["id"]));
+    let (_, amended) = send("PATCH", url, serde_json::json!({ "price": "11" }));
+    assert_eq!(
+        (&amended["id"], &amended["price"]),
+        (&first["id"], &"11".into())
+    );
+    let (status, cancelled) = send("DELETE", url, serde_json::Value::Null);
+    assert_eq!((status, &cancelled["status"]), (200, &"cancelled".into()));
+
+    // Free again once the order left the book
+    let (status, second) = send("POST", "/v1/orders", order);
+    assert_eq!(status, 201);
+    assert_eq!(
+        send("GET", url, serde_json::Value::Null).1["id"],
+        second["id"]
+    );
+    let (status, error) = send("GET", "/v1/orders/client/grid-8", serde_json::Value::Null);
+    assert_eq!(status, 404);
+    assert_eq!(error["message"], "order with client ID `grid-8` not found");
+}
+
+#[test]
+fn accounts() {
+    let server = TempServer::new("accounts");
+    let request = |method: &str, url: &str, api_key: Option<&str>, body: &str| {
+        let headers = api_key.map(authorization).into_iter().collect();
+        let request = Request::fake_http(method, url, headers, body.as_bytes().to_vec());
+        let (status, body) = response(server.server.handle(&request));
+        let body = if body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_str(&body).unwrap()
+        };
+        (status, body)
+    };
+    let order =
+        r#"{"symbol": "XGAL-USD", "side": "buy", "type": "limit", "price": "9", "quantity": 1}"#;
+
+    let (status, error) = request("POST", "/v1/orders", None, order);
+    assert_eq!((status, &error["code"]), (401, &"unauthorized".into()));
+    let (status, _) = request("POST", "/v1/orders", Some("gx_nokey_secret"), order);
+    assert_eq!(status, 401);
+
+    let (status, account) = request("POST", "/v1/accounts", None, r#"{"name": "Orbital"}"#);
+    assert_eq!(status, 201);
+    assert_eq!(
+        (&account["id"], &account["name"]),
+        (&2.into(), &"Orbital".into())
+    );
+    let first = account["api_key"]["key"].as_str().unwrap();
+    let (status, error) = request("POST", "/v1/accounts", None, r#"{"name": ""}"#);
+    assert_eq!((status, &error["details"]["field"]), (400, &"name".into()));
+
+    let (status, key) = request(
+        "POST",
+        "/v1/accounts/2/keys",
+        Some(first),
+        r#"{"label": "bot"}"#,
+    );
+    assert_eq!((status, &key["label"]), (201, &"bot".into()));
+    let second = key["key"].as_str().unwrap();
+    let (_, keys) = request("GET", "/v1/accounts/2/keys", Some(second), "");
+    assert_eq!(keys["keys"].as_array().unwrap().len(), 2);
+    assert_eq!(keys["keys"][1]["key"], serde_json::Value::Null);
+    assert_eq!(
+        request("GET", "/v1/accounts/2", Some(&server.api_key), "").0,
+        403
+    );
+    assert_eq!(request("GET", "/v1/accounts/9", Some(first), "").0, 403);
+    assert_eq!(request("GET", "/v1/accounts/me", Some(first), "").0, 404);
+
+    // Orders belong to the account of the key they are placed with
+    let (status, placed) = request("POST", "/v1/orders", Some(first), order);
+    assert_eq!((status, &placed["account_id"]), (201, &2.into()));
+    let url = format!("/v1/orders/{}", placed["id"]);
+    assert_eq!(request("GET", &url, Some(second), "").0, 200);
+    assert_eq!(request("GET", &url, Some(&server.api_key), "").0, 404);
+    assert_eq!(request("DELETE", &url, Some(&server.api_key), "").0, 404);
+    let (_, listed) = request("GET", "/v1/orders", Some(&server.api_key), "");
+    assert_eq!(listed["orders"], serde_json::json!([]));
+
+    let first_id = account["api_key"]["id"].as_str().unwrap();
+    let url = format!("/v1/accounts/2/keys/{}", first_id);
+    let (status, revoked) = request("DELETE", &url, Some(second), "");
+    assert_eq!(status, 200);
+    assert!(revoked["revoked_at"].is_string());
+    assert_eq!(request("GET", "/v1/accounts/2", Some(first), "").0, 401);
+    assert_eq!(
+        request("DELETE", "/v1/accounts/2/keys/none", Some(second), "").0,
+        404
+    );
+
+    // Keys are read back by the next server
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::from_toml(MARKETS).unwrap()
+    };
+    let restarted = Server::new(config).unwrap();
+    for (api_key, status) in [(first, 401), (second, 200)] {
+        let headers = vec![authorization(api_key)];
+        let request = Request::fake_http("GET", "/v1/accounts/2", headers, vec![]);
+        assert_eq!(response(restarted.handle(&request)).0, status);
+    }
+}
+
+#[test]
+fn order_queries() {
+    let server = TempServer::new("queries");
+    let get = |url: &str| {
+        let (status, body) = server.request("GET", url, b"");
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    for (symbol, quantity) in [("XGAL-USD", "3"), ("XGAL-USD", "4"), ("XORB-USD", "5")] {
+        let order = serde_json::json!({
+            "symbol": symbol, "side": "sell", "type": "limit", "price": "10", "quantity": quantity
+        });
+        assert_eq!(
+            server
+                .request("POST", "/v1/orders", order.to_string().as_bytes())
+                .0,
+            201
+        );
+    }
+    let buy = br#"{"symbol": "XGAL-USD", "side": "buy", "type": "market", "quantity": "1"}"#;
+    server.request("POST", "/v1/orders", buy);
+
+    let (status, order) = get("/v1/orders/1");
+    assert_eq!(status, 200);
+    assert_eq!(order["status"], "partially_filled");
+    assert_eq!(
+        (&order["filled_quantity"], &order["remaining_quantity"]),
+        (&"1".into(), &"2".into())
+    );
+    assert!(order["created_at"].is_string() && order["updated_at"].is_string());
+    assert_eq!(get("/v1/orders/9").0, 404);
+
+    let (status, page) = get("/v1/orders?status=open&symbol=XGAL-USD&limit=1");
+    assert_eq!(status, 200);
+    assert_eq!(page["orders"][0]["id"], 1);
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (_, page) = get(&format!(
+        "/v1/orders?status=open&symbol=XGAL-USD&limit=1&cursor={}",
+        cursor
+    ));
+    assert_eq!(page["orders"][0]["id"], 2);
+    assert_eq!(page["next_cursor"], serde_json::Value::Null);
+    let (_, page) = get("/v1/orders?status=closed");
+    assert_eq!(page["orders"].as_array().unwrap().len(), 1);
+    assert_eq!(page["orders"][0]["status"], "filled");
+
+    let (status, error) = get("/v1/orders?status=pending");
+    assert_eq!(
+        (status, &error["details"]["parameter"]),
+        (400, &"status".into())
+    );
+    assert_eq!(get("/v1/orders?limit=0").0, 400);
+    assert_eq!(get("/v1/orders?cursor=x").0, 400);
+}
+
+/// Log lines written by the tests' subscriber
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Logs {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn markets() {
+    let server = TempServer::new("markets");
+    let (status, body) = server.request("GET", "/v1/markets", b"");
+    assert_eq!(status, 200);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let symbols: Vec<_> = body["markets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|market| market["symbol"].as_str().unwrap())
+        .collect();
+    assert_eq!(symbols, ["XGAL-USD", "XORB-USD"]);
+    let (status, body) = server.request("GET", "/v1/markets/XGAL-USD", b"");
+    assert_eq!(status, 200);
+    let market: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        market,
+        serde_json::json!({
+            "symbol": "XGAL-USD", "base": "XGAL", "quote": "USD", "tick_size": "0.01",
+            "lot_size": "1", "min_notional": "0", "status": "trading"
+        })
+    );
+    assert_eq!(server.request("GET", "/v1/markets/XNEB-USD", b"").0, 404);
+
+    let admin = |token: &str, symbol: &str, market: serde_json::Value| {
+        let headers = vec![(String::from("Authorization"), format!("Bearer {}", token))];
+        let url = format!("/v1/admin/markets/{}", symbol);
+        let body = market.to_string().into_bytes();
+        let (status, body) = response(
+            server
+                .server
+                .handle(&Request::fake_http("PUT", &url, headers, body)),
+        );
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+    let neb = serde_json::json!({
+        "base": "XNEB", "quote": "USD", "tick_size": "0.5", "lot_size": "0.1"
+    });
+    let (status, error) = admin("guess", "XNEB-USD", neb.clone());
+    assert_eq!((status, &error["code"]), (401, &"unauthorized".into()));
+    let (status, listed) = admin("admin-secret", "XNEB-USD", neb);
+    assert_eq!((status, &listed["symbol"]), (200, &"XNEB-USD".into()));
+    let (status, error) = admin(
+        "admin-secret",
+        "XNEB-USD",
+        serde_json::json!({ "symbol": "XGAL-USD" }),
+    );
+    assert_eq!((status, &error["code"]), (400, &"invalid_market".into()));
+
+    let order = |price: &str| {
+        let order = serde_json::json!({
+            "symbol": "XNEB-USD", "side": "buy", "type": "limit", "price": price, "quantity": "1"
+        });
+        let (status, body) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        (status, body["details"]["field"].clone())
+    };
+    assert_eq!(order("10.5").0, 201);
+    assert_eq!(order("10.25"), (400, "price".into()));
+
+    let mut halted = listed.clone();
+    halted["status"] = "halted".into();
+    assert_eq!(admin("admin-secret", "XNEB-USD", halted).0, 200);
+    assert_eq!(order("10.5"), (400, "symbol".into()));
+
+    // Listings through the admin API survive a restart
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::from_toml(MARKETS).unwrap()
+    };
+    let restarted = Server::new(config).unwrap();
+    let request = Request::fake_http("GET", "/v1/markets/XNEB-USD", vec![], vec![]);
+    let (_, body) = response(restarted.handle(&request));
+    assert!(body.contains(r#""status":"halted""#), "{}", body);
+}
+
+#[test]
+fn depth() {
+    let server = TempServer::new("depth");
+    for (side, price, quantity) in [("buy", "9.90", 1), ("buy", "9.70", 2), ("sell", "10.10", 3)] {
+        let order = serde_json::json!({
+            "symbol": "XGAL-USD", "side": side, "type": "limit", "price": price,
+            "quantity": quantity
+        });
+        let (status, _) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        assert_eq!(status, 201);
+    }
+    let depth = |query: &str| {
+        let url = format!("/v1/markets/XGAL-USD/depth{}", query);
+        let (status, body) = server.request("GET", &url, b"");
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    let (status, book) = depth("");
+    assert_eq!(status, 200);
+    assert_eq!(book["symbol"], "XGAL-USD");
+    assert_eq!(
+        book["bids"],
+        serde_json::json!([
+            {"price": "9.90", "quantity": "1"},
+            {"price": "9.70", "quantity": "2"},
+        ])
+    );
+    assert_eq!(book["asks"][0]["price"], "10.10");
+    let sequence = book["sequence"].as_u64().unwrap();
+    let (_, aggregated) = depth("?levels=1&aggregation=0.5");
+    assert_eq!(
+        aggregated["bids"],
+        serde_json::json!([{"price": "9.5", "quantity": "3"}])
+    );
+    assert_eq!(aggregated["asks"][0]["price"], "10.5");
+    assert_eq!(aggregated["sequence"], sequence);
+
+    let (status, error) = depth("?aggregation=0.005");
+    assert_eq!(status, 400);
+    assert_eq!(error["details"]["parameter"], "aggregation");
+    assert_eq!(depth("?levels=0").0, 400);
+    let (status, _) = server.request("GET", "/v1/markets/XNEB-USD/depth", b"");
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn trades() {
+    let server = TempServer::new("trades");
+    for (side, price, quantity) in [
+        ("sell", "10.10", 1),
+        ("sell", "10.20", 1),
+        ("buy", "10.20", 2),
+        ("buy", "9.90", 1),
+        ("sell", "9.90", 1),
+    ] {
+        let order = serde_json::json!({
+            "symbol": "XGAL-USD", "side": side, "type": "limit", "price": price,
+            "quantity": quantity
+        });
+        let (status, _) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        assert_eq!(status, 201);
+    }
+    let trades = |server: &Server, query: &str| {
+        let url = format!("/v1/markets/XGAL-USD/trades{}", query);
+        let request = Request::fake_http("GET", url, vec![], vec![]);
+        let (status, body) = response(server.handle(&request));
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    let (status, page) = trades(&server.server, "?limit=2");
+    assert_eq!(status, 200);
+    assert_eq!(page["trades"][0]["id"], 3);
+    assert_eq!(page["trades"][0]["price"], "9.90");
+    assert_eq!(page["trades"][0]["side"], "sell");
+    assert_eq!(page["trades"][1]["price"], "10.20");
+    assert_eq!(page["trades"][1]["side"], "buy");
+    assert!(page["trades"][1]["time"].is_string());
+    assert_eq!(page["next_cursor"], "2");
+    let (_, last) = trades(&server.server, "?limit=2&before=2");
+    assert_eq!(last["trades"][0]["price"], "10.10");
+    assert_eq!(last["next_cursor"], serde_json::Value::Null);
+
+    assert_eq!(trades(&server.server, "?limit=0").0, 400);
+    assert_eq!(trades(&server.server, "?before=latest").0, 400);
+    let (status, _) = server.request("GET", "/v1/markets/XNEB-USD/trades", b"");
+    assert_eq!(status, 404);
+
+    // Trades are read back by the next server, which numbers new ones after
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::from_toml(MARKETS).unwrap()
+    };
+    let restarted = Server::new(config).unwrap();
+    assert_eq!(
+        trades(&restarted, "").1["trades"].as_array().unwrap().len(),
+        3
+    );
+    for order in [
+        r#"{"symbol": "XGAL-USD", "side": "sell", "type": "limit", "price": "10", "quantity": 1}"#,
+        r#"{"symbol": "XGAL-USD", "side": "buy", "type": "market", "quantity": 1}"#,
+    ] {
+        let request = Request::fake_http(
+            "POST",
+            "/v1/orders",
+            server.headers(),
+            order.as_bytes().to_vec(),
+        );
+        assert_eq!(response(restarted.handle(&request)).0, 201);
+    }
+    assert_eq!(trades(&restarted, "?limit=1").1["trades"][0]["id"], 4);
+}
+
+#[test]
+fn ticker() {
+    let server = TempServer::new("ticker");
+    let ticker = |server: &Server| {
+        let request = Request::fake_http("GET", "/v1/markets/XGAL-USD/ticker", vec![], vec![]);
+        let (status, body) = response(server.handle(&request));
+        assert_eq!(status, 200);
+        serde_json::from_str::<serde_json::Value>(&body).unwrap()
+    };
+    let quiet = ticker(&server.server);
+    assert_eq!(quiet["last_price"], serde_json::Value::Null);
+    assert_eq!(quiet["volume"], "0");
+
+    for (side, price, quantity) in [
+        ("sell", "10.10", 1),
+        ("sell", "10.20", 3),
+        ("buy", "10.20", 2),
+        ("buy", "9.90", 1),
+        ("sell", "9.90", 1),
+        ("buy", "9.80", 1),
+    ] {
+        let order = serde_json::json!({
+            "symbol": "XGAL-USD", "side": side, "type": "limit", "price": price,
+            "quantity": quantity
+        });
+        let (status, _) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        assert_eq!(status, 201);
+    }
+    let expected = serde_json::json!({
+        "symbol": "XGAL-USD", "last_price": "9.90", "open": "10.10", "high": "10.20",
+        "low": "9.90", "close": "9.90", "volume": "3", "quote_volume": "30.2",
+        "best_bid": "9.80", "best_ask": "10.20"
+    });
+    assert_eq!(ticker(&server.server), expected);
+    let (status, _) = server.request("GET", "/v1/markets/XNEB-USD/ticker", b"");
+    assert_eq!(status, 404);
+
+    // The statistics are read back from the trades by the next server, whose
+    // book starts empty
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::from_toml(MARKETS).unwrap()
+    };
+    let restarted = ticker(&Server::new(config).unwrap());
+    assert_eq!(restarted["volume"], "3");
+    assert_eq!(restarted["high"], "10.20");
+    assert_eq!(restarted["best_bid"], serde_json::Value::Null);
+}
+
+#[test]
+fn candles() {
+    let server = TempServer::new("candles");
+    for (side, price, quantity) in [
+        ("sell", "10.10", 1),
+        ("sell", "10.20", 3),
+        ("buy", "10.20", 2),
+        ("buy", "9.90", 1),
+        ("sell", "9.90", 1),
+    ] {
+        let order = serde_json::json!({
+            "symbol": "XGAL-USD", "side": side, "type": "limit", "price": price,
+            "quantity": quantity
+        });
+        let (status, _) = server.request("POST", "/v1/orders", order.to_string().as_bytes());
+        assert_eq!(status, 201);
+    }
+    let candles = |server: &Server, query: &str| {
+        let url = format!("/v1/markets/XGAL-USD/candles{}", query);
+        let request = Request::fake_http("GET", url, vec![], vec![]);
+        let (status, body) = response(server.handle(&request));
+        (
+            status,
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+        )
+    };
+
+    let (status, day) = candles(&server.server, "?interval=1d");
+    assert_eq!(status, 200);
+    assert_eq!(day["interval"], "1d");
+    let candle = &day["candles"][0];
+    assert!(candle[0].as_str().unwrap().ends_with("T00:00:00.000Z"));
+    assert_eq!(
+        candle.as_array().unwrap()[1..],
+        ["10.10", "10.20", "9.90", "9.90", "3"]
+    );
+    let (_, early) = candles(&server.server, "?interval=1m&end=2000-01-01T00:00:00Z");
+    assert_eq!(early["candles"], serde_json::json!([]));
+
+    assert_eq!(candles(&server.server, "").0, 400);
+    assert_eq!(candles(&server.server, "?interval=1w").0, 400);
+    let (status, error) = candles(&server.server, "?interval=1m&start=yesterday");
+    assert_eq!(status, 400);
+    assert_eq!(error["details"]["parameter"], "start");
+    assert_eq!(candles(&server.server, "?interval=1m&limit=0").0, 400);
+    let (status, _) = server.request("GET", "/v1/markets/XNEB-USD/candles?interval=1m", b"");
+    assert_eq!(status, 404);
+
+    // The candles are filled in again from the trades by the next server
+    let config = Config {
+        data_dir: server.data_dir.clone(),
+        ..Config::from_toml(MARKETS).unwrap()
+    };
+    let restarted = Server::new(config).unwrap();
+    assert_eq!(candles(&restarted, "?interval=1d").1["candles"][0], *candle);
+}
+
+#[test]
+fn request_logs() {
+    let server = TempServer::new("logs");
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        server.request("GET", "/missing", b"");
+    });
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let line: serde_json::Value = serde_json::from_str(logs.lines().next().unwrap()).unwrap();
+    assert_eq!(line["fields"]["message"], "request handled");
+    assert_eq!(line["fields"]["status"], 404);
+    assert!(line["fields"]["duration_ms"].is_f64());
+    assert_eq!(line["span"]["name"], "request");
+    assert_eq!(line["span"]["method"], "GET");
+    assert_eq!(line["span"]["path"], "/missing");
+    assert!(line["span"]["request_id"].is_string());
+}
+
+fn request_id(response: &Response) -> Option<&str> {
+    response
+        .headers
+        .iter()
+        .find(|(name, _)| name == "X-Request-Id")
+        .map(|(_, value)| &**value)
+}
+
+#[test]
+fn request_ids() {
+    let server = TempServer::new("request-ids");
+
+    let health = Request::fake_http("GET", "/health", vec![], vec![]);
+    let first = server.server.handle(&health);
+    let second = server.server.handle(&health);
+    let id = request_id(&first).unwrap();
+    assert_eq!(id.len(), 36);
+    assert_eq!(&id[14..15], "4");
+    assert_ne!(request_id(&second), Some(id));
+
+    let given = |id: &str| {
+        let headers = vec![(String::from("X-Request-Id"), id.to_string())];
+        Request::fake_http("POST", "/v1/decode", headers, b"\x07".to_vec())
+    };
+    let decoded = server.server.handle(&given("client-42"));
+    assert_eq!(request_id(&decoded), Some("client-42"));
+    let (status, body) = response(decoded);
+    assert_eq!(status, 400);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["request_id"], "client-42");
+
+    // Not something a header can carry back
+    let decoded = server.server.handle(&given(&"x".repeat(200)));
+    assert_eq!(request_id(&decoded).unwrap().len(), 36);
+}